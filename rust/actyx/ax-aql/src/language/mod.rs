@@ -47,6 +47,7 @@ pub enum Operation {
     Select(NonEmptyVec<SpreadExpr>),
     Aggregate(SimpleExpr),
     Limit(NonZeroU64),
+    Offset(u64),
     Binding(String, SimpleExpr),
 }
 
@@ -376,6 +377,7 @@ impl SimpleExpr {
                             }
                             Operation::Aggregate(e) => e.traverse(f),
                             Operation::Limit(_) => {}
+                            Operation::Offset(_) => {}
                             Operation::Binding(_, e) => e.traverse(f),
                         }
                     }
@@ -777,10 +779,10 @@ mod for_tests {
             fn Binding(g: &mut Gen) -> Operation {
                 Operation::Binding(Var::arbitrary(g).0, SimpleExpr::arbitrary(g))
             }
-            arb!(Operation: g => Filter Select Aggregate{ Context::Aggregate { now: Timestamp::now() } } Limit,,,, Binding)
+            arb!(Operation: g => Filter Select Aggregate{ Context::Aggregate { now: Timestamp::now() } } Limit Offset,,,, Binding)
         }
         fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
-            shrink!(Operation: self => Filter Select Aggregate Limit,,,
+            shrink!(Operation: self => Filter Select Aggregate Limit Offset,,,
                 Operation::Binding(n, e) => {
                     let n = n.clone();
                     Box::new(e.shrink().map(move |e| Operation::Binding(n.clone(), e)))
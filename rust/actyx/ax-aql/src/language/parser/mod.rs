@@ -605,6 +605,7 @@ fn r_query<'a>(pragmas: Vec<(&'a str, &'a str)>, features: Vec<String>, p: P, ct
                 .ops
                 .push(Operation::Aggregate(r_simple_expr(o.single()?, ctx.aggregate())?)),
             Rule::limit => q.ops.push(Operation::Limit(o.single()?.natural()?.try_into()?)),
+            Rule::offset => q.ops.push(Operation::Offset(o.single()?.natural()?)),
             Rule::binding => {
                 let mut p = o.inner()?;
                 let ident = p.string()?;
@@ -933,7 +934,7 @@ mod tests {
             parser: Aql,
             input: "FROM 'x' ELECT 'x'",
             rule: Rule::main_query,
-            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::limit, Rule::binding, Rule::and, Rule::or],
+            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::limit, Rule::offset, Rule::binding, Rule::and, Rule::or],
             negatives: vec![],
             pos: 9
         };
@@ -941,7 +942,7 @@ mod tests {
             parser: Aql,
             input: "FROM 'x' FITTER 'x'",
             rule: Rule::main_query,
-            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::limit, Rule::binding, Rule::and, Rule::or],
+            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::limit, Rule::offset, Rule::binding, Rule::and, Rule::or],
             negatives: vec![],
             pos: 9
         };
@@ -1088,6 +1089,20 @@ mod tests {
         assert_eq!(&q.ops[0], &Operation::Limit(10.try_into().unwrap()));
     }
 
+    #[test]
+    fn offset() {
+        let q = Query::parse("FROM 'x' OFFSET 0 LIMIT 10 OFFSET 5").unwrap();
+        assert_eq!(
+            q.ops,
+            vec![
+                Operation::Offset(0),
+                Operation::Limit(10.try_into().unwrap()),
+                Operation::Offset(5)
+            ]
+        );
+        assert!(Query::parse("FROM 'x' OFFSET -1").is_err());
+    }
+
     #[test]
     fn func_call() {
         let p = |s: &str, e: Option<&str>| {
@@ -199,6 +199,9 @@ fn render_operation(w: &mut impl Write, e: &Operation) -> Result {
         Operation::Limit(l) => {
             write!(w, "LIMIT {}", l)
         }
+        Operation::Offset(o) => {
+            write!(w, "OFFSET {}", o)
+        }
         Operation::Binding(n, e) => {
             write!(w, "LET {} := ", n)?;
             render_simple_expr(w, e)
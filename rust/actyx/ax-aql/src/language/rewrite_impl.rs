@@ -85,6 +85,7 @@ impl Operation {
             }
             Operation::Aggregate(x) => map(x.rewrite(surfer), Operation::Aggregate),
             Operation::Limit(x) => (Operation::Limit(*x), false),
+            Operation::Offset(x) => (Operation::Offset(*x), false),
             Operation::Binding(x, y) => map(y.rewrite(surfer), |y| Operation::Binding(x.clone(), y)),
         }
     }
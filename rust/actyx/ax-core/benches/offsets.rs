@@ -0,0 +1,48 @@
+use ax_core::swarm::VersionedOffsets;
+use ax_types::{NodeId, Offset, OffsetMap, StreamId};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+const STREAMS: u64 = 10_000;
+
+fn stream(n: u64) -> StreamId {
+    NodeId::from_bytes(&[(n % 251) as u8; 32]).unwrap().stream(n.into())
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let map = (0..STREAMS)
+        .map(|n| (stream(n), Offset::from(1)))
+        .collect::<OffsetMap>();
+    let offsets = VersionedOffsets::from(&map);
+
+    c.bench_function("OffsetMap clone and update 1 of 10k", |b| {
+        b.iter(|| {
+            let mut map = map.clone();
+            map.update(stream(black_box(4711)), Offset::from(2));
+            map
+        })
+    });
+    c.bench_function("VersionedOffsets clone and update 1 of 10k", |b| {
+        b.iter(|| {
+            let mut offsets = offsets.clone();
+            offsets.update(stream(black_box(4711)), Offset::from(2));
+            offsets
+        })
+    });
+    c.bench_function("VersionedOffsets delta of 10 in 10k", |b| {
+        b.iter_batched(
+            || {
+                let mut offsets = offsets.clone();
+                let version = offsets.version();
+                for n in 0..10 {
+                    offsets.update(stream(n * 997), Offset::from(2));
+                }
+                (offsets, version)
+            },
+            |(offsets, version)| offsets.delta_since(version),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
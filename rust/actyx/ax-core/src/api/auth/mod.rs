@@ -7,9 +7,14 @@ use warp::{body, post, reply, Filter, Rejection, Reply};
 
 use crate::{
     api::{
-        bearer_token::BearerToken, filters::accept_json, licensing::Licensing, reject, rejections::ApiError, AppMode,
-        NodeInfo, Token,
+        bearer_token::BearerToken,
+        filters::accept_json,
+        licensing::{LicenseWarning, Licensing},
+        reject,
+        rejections::ApiError,
+        AppMode, NodeInfo, Token,
     },
+    certs::LicenseTolerance,
     crypto::{PublicKey, SignedMessage},
 };
 
@@ -74,14 +79,19 @@ pub(crate) fn verify_token(node_info: NodeInfo, token: Token) -> Result<BearerTo
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct TokenResponse {
     token: String,
+    /// Licenses that have expired and are only accepted within their grace period
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    license_warnings: Vec<LicenseWarning>,
 }
 
 impl TokenResponse {
-    fn new(token: Token) -> Self {
+    fn new(token: Token, license_warnings: Vec<LicenseWarning>) -> Self {
         Self {
             token: token.to_string(),
+            license_warnings,
         }
     }
 }
@@ -90,20 +100,45 @@ fn validate_manifest(
     manifest: &AppManifest,
     ax_public_key: &PublicKey,
     licensing: &Licensing,
-) -> Result<(AppMode, AppId, String), ApiError> {
+    license_tolerance: &LicenseTolerance,
+) -> Result<(AppMode, AppId, String, Vec<LicenseWarning>), ApiError> {
     if manifest.is_signed() {
-        validate_signed_manifest(manifest, ax_public_key, licensing)
-            .map(|_| (AppMode::Signed, manifest.app_id(), manifest.version().to_owned()))
+        validate_signed_manifest(manifest, ax_public_key, licensing, license_tolerance).map(|warnings| {
+            (
+                AppMode::Signed,
+                manifest.app_id(),
+                manifest.version().to_owned(),
+                warnings,
+            )
+        })
     } else {
-        Ok((AppMode::Trial, manifest.app_id(), manifest.version().to_owned()))
+        Ok((AppMode::Trial, manifest.app_id(), manifest.version().to_owned(), vec![]))
     }
 }
 
 async fn handle_auth(node_info: NodeInfo, manifest: AppManifest) -> Result<impl Reply, Rejection> {
-    match validate_manifest(&manifest, &node_info.ax_public_key, &node_info.licensing) {
-        Ok((is_trial, app_id, version)) => create_token(node_info, app_id, version, is_trial)
-            .map(|token| reply::json(&TokenResponse::new(token)))
-            .map_err(reject),
+    match validate_manifest(
+        &manifest,
+        &node_info.ax_public_key,
+        &node_info.licensing,
+        &node_info.license_tolerance,
+    ) {
+        Ok((is_trial, app_id, version, warnings)) => {
+            let now = Utc::now();
+            for warning in &warnings {
+                tracing::warn!(
+                    target: "AUTH",
+                    "License for {} expired at {}, it is accepted for another {} seconds until {}. Please renew it.",
+                    warning.app_id,
+                    warning.expired_at,
+                    warning.remaining(now).num_seconds(),
+                    warning.grace_until
+                );
+            }
+            create_token(node_info, app_id, version, is_trial)
+                .map(|token| reply::json(&TokenResponse::new(token, warnings)))
+                .map_err(reject)
+        }
         Err(x) => Err(warp::reject::custom(x)),
     }
 }
@@ -139,6 +174,7 @@ mod tests {
             token_validity: 300,
             ax_public_key: PrivateKey::generate().into(),
             licensing: Licensing::default(),
+            license_tolerance: Default::default(),
             started_at: Utc::now(),
         };
         route(auth_args)
@@ -181,6 +217,7 @@ mod tests {
             token_validity: 300,
             ax_public_key: PrivateKey::generate().into(),
             licensing: Licensing::default(),
+            license_tolerance: Default::default(),
             started_at: Utc::now(),
         };
 
@@ -194,6 +231,7 @@ mod tests {
         assert_eq!(resp.headers()["content-type"], "application/json");
 
         let token: TokenResponse = serde_json::from_slice(resp.body()).unwrap();
+        assert!(token.license_warnings.is_empty());
         assert!(verify_token(auth_args, token.token.into()).is_ok())
     }
 
@@ -221,13 +259,20 @@ mod tests {
     #[test]
     fn validate_manifest_should_succeed_for_trial() {
         let x = setup();
-        let result = validate_manifest(&x.trial_manifest, &x.ax_public_key, &Licensing::default()).unwrap();
+        let result = validate_manifest(
+            &x.trial_manifest,
+            &x.ax_public_key,
+            &Licensing::default(),
+            &Default::default(),
+        )
+        .unwrap();
         assert_eq!(
             result,
             (
                 AppMode::Trial,
                 x.trial_manifest.app_id(),
-                x.trial_manifest.version().to_owned()
+                x.trial_manifest.version().to_owned(),
+                vec![]
             )
         );
     }
@@ -1,23 +1,25 @@
 use crate::{
-    certs::{app_manifest_signer, AppLicenseType, Expiring, SignedAppLicense},
+    certs::{app_manifest_signer, AppLicenseType, Expiring, LicenseTolerance, SignedAppLicense},
     crypto::PublicKey,
 };
 
 use crate::api::{
-    licensing::Licensing,
+    licensing::{check_license, LicenseWarning, Licensing, NodeLicense},
     rejections::{ApiError, UnauthorizedReason},
 };
 use ax_types::AppManifest;
-use chrono::Utc;
 
+/// Validate a signed manifest and the licenses it needs, returning warnings about licenses that
+/// are only accepted within their grace period.
 pub fn validate_signed_manifest(
     manifest: &AppManifest,
     ax_public_key: &PublicKey,
     licensing: &Licensing,
-) -> Result<(), ApiError> {
-    app_manifest_signer::validate(manifest, ax_public_key)
+    tolerance: &LicenseTolerance,
+) -> Result<Vec<LicenseWarning>, ApiError> {
+    app_manifest_signer::verify_manifest(manifest, ax_public_key)
         .map_err(|x| ApiError::InvalidManifest { msg: x.to_string() })?;
-    if licensing.is_node_licensed(ax_public_key)? {
+    if let NodeLicense::Licensed(node_warning) = licensing.node_license(ax_public_key, tolerance)? {
         let app_id = manifest.app_id();
         let license = licensing
             .app_id_license(&app_id)
@@ -33,31 +35,17 @@ pub fn validate_signed_manifest(
                         reason: UnauthorizedReason::MalformedLicense,
                     })
             })?;
-
-        license.validate(ax_public_key).map_err(|_| ApiError::AppUnauthorized {
-            app_id,
-            reason: UnauthorizedReason::InvalidSignature,
+        let app_warning = check_license(&license, ax_public_key, &app_id, tolerance).map_err(|reason| {
+            let app_id = match (&reason, &license.license.license_type) {
+                // name the subject the license was issued for
+                (UnauthorizedReason::WrongSubject, AppLicenseType::Expiring(Expiring { app_id, .. })) => app_id.clone(),
+                _ => app_id.clone(),
+            };
+            ApiError::AppUnauthorized { app_id, reason }
         })?;
-
-        match license.license.license_type {
-            AppLicenseType::Expiring(Expiring { expires_at, app_id }) => {
-                if app_id != manifest.app_id() {
-                    Err(ApiError::AppUnauthorized {
-                        app_id,
-                        reason: UnauthorizedReason::WrongSubject,
-                    })
-                } else if expires_at < Utc::now() {
-                    Err(ApiError::AppUnauthorized {
-                        app_id,
-                        reason: UnauthorizedReason::Expired,
-                    })
-                } else {
-                    Ok(())
-                }
-            }
-        }
+        Ok(node_warning.into_iter().chain(app_warning).collect())
     } else {
-        Ok(())
+        Ok(vec![])
     }
 }
 
@@ -106,7 +94,13 @@ mod tests {
     #[test]
     fn should_succeed_when_node_in_dev_mode() {
         let x = setup();
-        validate_signed_manifest(&x.signed_manifest, &x.ax_public_key, &Licensing::default()).unwrap();
+        validate_signed_manifest(
+            &x.signed_manifest,
+            &x.ax_public_key,
+            &Licensing::default(),
+            &LicenseTolerance::default(),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -118,6 +112,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(x.node_license, apps),
+            &LicenseTolerance::default(),
         )
         .unwrap();
     }
@@ -129,6 +124,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(x.node_license, BTreeMap::default()),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -149,6 +145,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(x.node_license, apps),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -169,6 +166,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(x.node_license, apps),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -189,6 +187,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(x.node_license, apps),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -200,6 +199,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_succeed_when_node_in_prod_mode_with_app_license_in_grace_period() {
+        let x = setup();
+        let mut apps = BTreeMap::new();
+        apps.insert(x.app_id.clone(), x.expired_app_license);
+        let tolerance = LicenseTolerance {
+            grace_period: chrono::Duration::days(365 * 100),
+            ..Default::default()
+        };
+        let warnings = validate_signed_manifest(
+            &x.signed_manifest,
+            &x.ax_public_key,
+            &Licensing::new(x.node_license, apps),
+            &tolerance,
+        )
+        .unwrap();
+        let expired_at = "2020-01-01T00:00:00Z".parse().unwrap();
+        assert_eq!(
+            warnings,
+            vec![LicenseWarning {
+                app_id: x.app_id,
+                expired_at,
+                grace_until: expired_at + tolerance.grace_period,
+            }]
+        );
+    }
+
     #[test]
     fn should_fail_when_node_in_prod_mode_with_falsified_node_license() {
         let x = setup();
@@ -218,6 +244,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(node_license, apps),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -237,6 +264,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new("malformed".into(), apps),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -256,6 +284,7 @@ mod tests {
             &x.signed_manifest,
             &x.ax_public_key,
             &Licensing::new(x.expired_node_license, apps),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert_eq!(
@@ -273,6 +302,7 @@ mod tests {
             &x.signed_manifest,
             &PrivateKey::generate().into(),
             &Licensing::default(),
+            &LicenseTolerance::default(),
         )
         .unwrap_err();
         assert!(
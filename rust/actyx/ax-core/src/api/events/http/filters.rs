@@ -29,6 +29,33 @@ pub fn offsets(
         .and_then(handlers::offsets)
 }
 
+pub fn schemas(
+    node_info: NodeInfo,
+    event_service: EventService,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    path("schemas")
+        .and(path::end())
+        .and(get())
+        .and(authorize(node_info))
+        .and(accept_json())
+        .and(with_service(event_service))
+        .and_then(handlers::schemas)
+}
+
+pub fn schema(
+    node_info: NodeInfo,
+    event_service: EventService,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    path("schemas")
+        .and(path::param::<String>())
+        .and(path::end())
+        .and(get())
+        .and(authorize(node_info))
+        .and(accept_json())
+        .and(with_service(event_service))
+        .and_then(handlers::schema)
+}
+
 pub fn publish(
     node_info: NodeInfo,
     event_service: EventService,
@@ -7,8 +7,9 @@ use crate::{
 };
 use ax_types::{
     service::{PublishRequest, QueryRequest, SubscribeMonotonicRequest, SubscribeRequest},
-    AppId,
+    AppId, Tag,
 };
+use percent_encoding::percent_decode_str;
 use warp::{reply, Rejection, Reply};
 
 pub async fn offsets(_app_id: AppId, event_service: EventService) -> Result<impl Reply> {
@@ -20,6 +21,26 @@ pub async fn offsets(_app_id: AppId, event_service: EventService) -> Result<impl
         .map_err(reject)
 }
 
+pub async fn schemas(_app_id: AppId, event_service: EventService) -> Result<impl Reply> {
+    Ok(reply::json(&event_service.payload_schemas()))
+}
+
+pub async fn schema(tag: String, _app_id: AppId, event_service: EventService) -> Result<impl Reply> {
+    let tag = percent_decode_str(&tag)
+        .decode_utf8()
+        .ok()
+        .and_then(|tag| tag.parse::<Tag>().ok())
+        .ok_or_else(|| {
+            warp::reject::custom(ApiError::BadRequest {
+                cause: format!("invalid tag `{}`", tag),
+            })
+        })?;
+    event_service
+        .payload_schema(&tag)
+        .map(|schema| reply::json(&schema))
+        .ok_or_else(|| warp::reject::custom(ApiError::NotFound))
+}
+
 pub async fn publish(app_id: AppId, request: PublishRequest, event_service: EventService) -> Result<impl Reply> {
     event_service
         .publish(app_id, request)
@@ -62,8 +83,10 @@ fn reject(err: anyhow::Error) -> Rejection {
         return match e {
             event_store_ref::Error::Aborted => warp::reject::custom(ApiError::Shutdown { cause }),
             event_store_ref::Error::Overload => warp::reject::custom(ApiError::Overloaded { cause }),
+            event_store_ref::Error::MailboxFull { .. } => warp::reject::custom(ApiError::TooManyRequests { cause }),
             event_store_ref::Error::InvalidUpperBounds => warp::reject::custom(ApiError::BadRequest { cause }),
             event_store_ref::Error::TagExprError(_) => warp::reject::custom(ApiError::BadRequest { cause }),
+            event_store_ref::Error::InlinePayload(_) => warp::reject::custom(ApiError::Internal),
         };
     }
     let err = match err.downcast::<ApiError>() {
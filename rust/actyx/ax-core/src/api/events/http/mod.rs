@@ -15,6 +15,8 @@ pub(crate) fn routes(
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     balanced_or!(
         filters::offsets(node_info.clone(), event_service.clone()),
+        filters::schemas(node_info.clone(), event_service.clone()),
+        filters::schema(node_info.clone(), event_service.clone()),
         filters::publish(node_info.clone(), event_service.clone()),
         filters::query(node_info.clone(), event_service.clone()),
         filters::subscribe(node_info.clone(), event_service.clone()),
@@ -1,4 +1,5 @@
 mod http;
+pub mod schemas;
 pub mod service;
 mod ws;
 
@@ -0,0 +1,341 @@
+//! JSON schemas describing the payloads of events with a given tag.
+//!
+//! Clients can fetch the registered schemas, e.g. to render forms. Queries whose `FROM` clause only
+//! selects tags with registered schemas are checked for field references that none of these
+//! schemas defines; this only produces warnings since schemas may well be incomplete.
+//!
+//! Only `properties`, `items` and local `$ref`s are followed when looking up a field, any other
+//! construct (like `allOf` or `additionalProperties`) makes all fields below it acceptable.
+use ax_aql::{is_ident, Ind, Index, Operation, Query, SimpleExpr, Source, TagAtom, TagExpr, Traverse};
+use ax_types::{
+    service::{Diagnostic, PayloadSchema, PayloadSchemasResponse},
+    Tag,
+};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayloadSchemas(Arc<BTreeMap<Tag, Value>>);
+
+impl PayloadSchemas {
+    pub fn new(schemas: BTreeMap<Tag, Value>) -> Self {
+        Self(Arc::new(schemas))
+    }
+
+    pub fn get(&self, tag: &Tag) -> Option<PayloadSchema> {
+        self.0.get(tag).map(|schema| PayloadSchema {
+            tag: tag.clone(),
+            schema: schema.clone(),
+        })
+    }
+
+    pub fn list(&self) -> PayloadSchemasResponse {
+        PayloadSchemasResponse {
+            schemas: self
+                .0
+                .iter()
+                .map(|(tag, schema)| PayloadSchema {
+                    tag: tag.clone(),
+                    schema: schema.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Warnings for payload fields referenced by the query that the registered schemas don’t define.
+    ///
+    /// Nothing is checked unless every alternative of the `FROM` clause requires at least one tag
+    /// with a registered schema.
+    pub fn lint(&self, query: &Query) -> Vec<Diagnostic> {
+        let from = match &query.source {
+            Source::Events { from, .. } => from,
+            Source::Array(_) => return vec![],
+        };
+        let mut schemas = BTreeMap::new();
+        for term in terms(from) {
+            let pinned = term
+                .into_iter()
+                .filter_map(|atom| atom.tag())
+                .filter_map(|tag| self.0.get_key_value(tag))
+                .collect::<Vec<_>>();
+            if pinned.is_empty() {
+                return vec![];
+            }
+            schemas.extend(pinned);
+        }
+
+        // `_` refers to the event payload until the first SELECT or AGGREGATE
+        let mut paths = BTreeSet::new();
+        for op in &query.ops {
+            match op {
+                Operation::Filter(e) | Operation::Binding(_, e) => payload_paths(e, &mut paths),
+                Operation::Select(es) => {
+                    es.iter().for_each(|e| payload_paths(e, &mut paths));
+                    break;
+                }
+                Operation::Aggregate(e) => {
+                    payload_paths(e, &mut paths);
+                    break;
+                }
+                Operation::Limit(_) | Operation::Offset(_) => {}
+            }
+        }
+
+        let tags = schemas
+            .iter()
+            .map(|(tag, _)| format!("'{}'", tag))
+            .collect::<Vec<_>>()
+            .join(", ");
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                // closest match for the unknown field in any of the schemas, with its distance
+                let mut suggestion = None::<(usize, &str)>;
+                for schema in schemas.values() {
+                    match lookup(schema, &path) {
+                        Lookup::Known => return None,
+                        Lookup::Unknown(name, candidates) => {
+                            for candidate in candidates {
+                                let distance = edit_distance(name, candidate);
+                                if suggestion.map(|(d, _)| distance < d).unwrap_or(true) {
+                                    suggestion = Some((distance, candidate));
+                                }
+                            }
+                        }
+                    }
+                }
+                let mut message = format!(
+                    "field `{}` is not defined by the payload schemas of {}",
+                    render(&path),
+                    tags
+                );
+                if let Some((_, closest)) = suggestion {
+                    write!(message, ", did you mean `{}`?", closest).unwrap();
+                }
+                Some(Diagnostic::warn(message))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Segment {
+    Field(String),
+    Element(u64),
+}
+
+/// The alternatives of the tag expression in disjunctive normal form.
+fn terms(expr: &TagExpr) -> Vec<Vec<&TagAtom>> {
+    match expr {
+        TagExpr::Or(or) => {
+            let mut left = terms(&or.0);
+            left.extend(terms(&or.1));
+            left
+        }
+        TagExpr::And(and) => {
+            let right = terms(&and.1);
+            terms(&and.0)
+                .into_iter()
+                .flat_map(|l| {
+                    right.iter().map(move |r| {
+                        let mut term = l.clone();
+                        term.extend(r.iter().copied());
+                        term
+                    })
+                })
+                .collect()
+        }
+        TagExpr::Atom(atom) => vec![vec![atom]],
+    }
+}
+
+/// Collects the statically known paths into the event payload `_` referenced by the expression.
+fn payload_paths(expr: &SimpleExpr, paths: &mut BTreeSet<Vec<Segment>>) {
+    expr.traverse(&mut |e| match e {
+        // sub-queries bind `_` to their own events
+        SimpleExpr::SubQuery(_) => Traverse::Stop,
+        SimpleExpr::Indexing(Ind { head, tail }) => {
+            if matches!(&**head, SimpleExpr::Variable(v) if &**v == "_") {
+                let path = tail
+                    .iter()
+                    .map_while(|index| match index {
+                        Index::String(s) => Some(Segment::Field(s.clone())),
+                        Index::Number(n) => Some(Segment::Element(*n)),
+                        Index::Expr(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                if !path.is_empty() {
+                    paths.insert(path);
+                }
+            }
+            Traverse::Descend
+        }
+        _ => Traverse::Descend,
+    })
+}
+
+enum Lookup<'a> {
+    Known,
+    /// The path leaves the schema at the given field, which is not among the listed properties
+    Unknown(&'a str, Vec<&'a str>),
+}
+
+fn lookup<'a>(root: &'a Value, path: &'a [Segment]) -> Lookup<'a> {
+    let mut node = root;
+    for segment in path {
+        node = resolve(root, node);
+        match segment {
+            Segment::Field(name) => {
+                let properties = match node.get("properties").and_then(Value::as_object) {
+                    Some(p) if !is_open(node) => p,
+                    _ => return Lookup::Known,
+                };
+                match properties.get(name) {
+                    Some(property) => node = property,
+                    None => return Lookup::Unknown(name, properties.keys().map(String::as_str).collect()),
+                }
+            }
+            Segment::Element(_) => match node.get("items") {
+                Some(items) if items.is_object() => node = items,
+                _ => return Lookup::Known,
+            },
+        }
+    }
+    Lookup::Known
+}
+
+/// Follows local `$ref` pointers.
+fn resolve<'a>(root: &'a Value, mut node: &'a Value) -> &'a Value {
+    for _ in 0..32 {
+        match node
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|r| r.strip_prefix('#'))
+            .and_then(|ptr| root.pointer(ptr))
+        {
+            Some(target) => node = target,
+            None => break,
+        }
+    }
+    node
+}
+
+/// Whether the schema admits fields beyond those listed in `properties`.
+fn is_open(node: &Value) -> bool {
+    matches!(
+        node.get("additionalProperties"),
+        Some(Value::Bool(true) | Value::Object(_))
+    ) || ["patternProperties", "allOf", "anyOf", "oneOf"]
+        .iter()
+        .any(|key| node.get(key).is_some())
+}
+
+fn render(path: &[Segment]) -> String {
+    let mut s = String::from("_");
+    for segment in path {
+        match segment {
+            Segment::Field(name) if is_ident(name) => write!(s, ".{}", name),
+            Segment::Field(name) => write!(s, "['{}']", name.replace('\'', "''")),
+            Segment::Element(n) => write!(s, "[{}]", n),
+        }
+        .unwrap();
+    }
+    s
+}
+
+/// Levenshtein distance between the two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+    use serde_json::json;
+
+    fn schemas() -> PayloadSchemas {
+        PayloadSchemas::new(btreemap! {
+            Tag::try_from("order").unwrap() => json!({
+                "type": "object",
+                "properties": {
+                    "customer": { "$ref": "#/definitions/Customer" },
+                    "items": { "type": "array", "items": { "type": "object", "properties": { "sku": {} } } },
+                    "extra": { "type": "object" }
+                },
+                "definitions": {
+                    "Customer": { "type": "object", "properties": { "name": {}, "address": {} } }
+                }
+            }),
+            Tag::try_from("open").unwrap() => json!({
+                "type": "object",
+                "properties": { "a": {} },
+                "additionalProperties": true
+            }),
+        })
+    }
+
+    fn lint(query: &str) -> Vec<String> {
+        schemas()
+            .lint(&Query::parse(query).unwrap())
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn unknown_fields() {
+        assert_eq!(
+            lint("FROM 'order' FILTER _.customer.nmae = 'x' SELECT _.itmes[0].sku"),
+            vec![
+                "field `_.customer.nmae` is not defined by the payload schemas of 'order', did you mean `name`?",
+                "field `_.itmes[0].sku` is not defined by the payload schemas of 'order', did you mean `items`?",
+            ]
+        );
+        assert_eq!(
+            lint("FROM 'order' & 'x' SELECT _.items[1].skus"),
+            vec!["field `_.items[1].skus` is not defined by the payload schemas of 'order', did you mean `sku`?"]
+        );
+    }
+
+    #[test]
+    fn known_fields() {
+        assert!(lint("FROM 'order' FILTER _.customer.address.street SELECT _.items[0].sku").is_empty());
+        // no properties listed below `extra`, additional properties allowed in 'open'
+        assert!(lint("FROM 'order' SELECT _.extra.anything").is_empty());
+        assert!(lint("FROM 'open' SELECT _.b").is_empty());
+        // `_` is no longer the payload after SELECT
+        assert!(lint("FROM 'order' SELECT _.customer FILTER _.name").is_empty());
+        // a field known to any of the queried schemas is accepted
+        assert!(lint("FROM 'order' | 'open' SELECT _.b").is_empty());
+    }
+
+    #[test]
+    fn unpinned_tags() {
+        assert!(lint("FROM 'other' SELECT _.whatever").is_empty());
+        assert!(lint("FROM 'order' | 'other' SELECT _.whatever").is_empty());
+        assert!(lint("FROM allEvents SELECT _.whatever").is_empty());
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("name", "nmae"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    api::rejections::ApiError,
+    api::{events::schemas::PayloadSchemas, rejections::ApiError},
     ax_futures_util::{stream::AxStreamExt, ReceiverExt},
     runtime::{
         error::{RuntimeError, RuntimeFailure},
@@ -10,18 +10,19 @@ use crate::{
     },
     swarm::{
         event_store_ref::{EventStoreHandler, EventStoreRef},
-        BanyanStore,
+        BanyanStore, PayloadRef,
     },
 };
 use ax_aql::{Arr, SimpleExpr, SpreadExpr};
 use ax_types::{
     app_id,
     service::{
-        Diagnostic, OffsetMapResponse, OffsetsResponse, Order, PublishEvent, PublishRequest, PublishResponse,
-        PublishResponseKey, QueryRequest, QueryResponse, Severity, SubscribeMonotonicRequest,
+        Diagnostic, EventMeta, OffsetMapResponse, OffsetsResponse, Order, PayloadSchema, PayloadSchemasResponse,
+        PublishEvent, PublishRequest, PublishResponse, PublishResponseKey, QueryChecksumBuilder, QueryProgress,
+        QueryProgressRequest, QueryRequest, QueryResponse, QueryTimeout, Severity, SubscribeMonotonicRequest,
         SubscribeMonotonicResponse, SubscribeRequest, SubscribeResponse,
     },
-    AppId, Event, EventKey, NodeId, OffsetMap, OffsetOrMin, Payload, TagSet, Timestamp,
+    AppId, Event, EventKey, LamportTimestamp, NodeId, Offset, OffsetMap, Payload, StreamId, Tag, TagSet, Timestamp,
 };
 use futures::{
     future::{poll_fn, ready},
@@ -32,39 +33,91 @@ use futures::{
 use genawaiter::sync::{Co, Gen};
 use serde::Deserialize;
 use std::{
+    collections::BTreeMap,
     convert::{From, TryFrom},
+    future::Future,
     num::NonZeroU64,
     ops::Deref,
     task::{self, Poll},
+    time::Duration,
 };
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, time::Instant};
+
+/// Execution timeouts for bounded queries.
+///
+/// The default is to not limit the execution time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryTimeouts {
+    /// Timeout for queries that don’t request one
+    pub default: Option<Duration>,
+    /// Upper limit for all timeouts, including those requested by clients
+    pub max: Option<Duration>,
+}
+
+impl QueryTimeouts {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// The timeout to apply to a query requesting the given timeout.
+    pub fn effective(&self, requested_millis: Option<u64>) -> Option<Duration> {
+        let timeout = requested_millis.map(Duration::from_millis).or(self.default);
+        match (timeout, self.max) {
+            (Some(timeout), Some(max)) => Some(timeout.min(max)),
+            (timeout, max) => timeout.or(max),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct EventService {
     store: EventStoreRef,
     node_id: NodeId,
+    query_timeouts: QueryTimeouts,
+    payload_schemas: PayloadSchemas,
 }
 
 impl EventService {
     pub fn new(store: EventStoreRef, node_id: NodeId) -> EventService {
-        EventService { store, node_id }
+        EventService {
+            store,
+            node_id,
+            query_timeouts: QueryTimeouts::unlimited(),
+            payload_schemas: PayloadSchemas::default(),
+        }
+    }
+
+    pub fn with_query_timeouts(mut self, query_timeouts: QueryTimeouts) -> Self {
+        self.query_timeouts = query_timeouts;
+        self
+    }
+
+    pub fn with_payload_schemas(mut self, payload_schemas: PayloadSchemas) -> Self {
+        self.payload_schemas = payload_schemas;
+        self
     }
 }
 
 impl EventService {
     pub async fn offsets(&self) -> anyhow::Result<OffsetsResponse> {
         let offsets = self.store.offsets().await?;
-        let present = offsets.present();
         let to_replicate = offsets
-            .replication_target()
-            .stream_iter()
-            .filter_map(|(stream, target)| {
-                let actual = present.offset(stream);
-                let diff = OffsetOrMin::from(target) - actual;
-                u64::try_from(diff).ok().and_then(NonZeroU64::new).map(|o| (stream, o))
-            })
+            .replication_lag()
+            .into_iter()
+            .filter_map(|(stream, lag)| NonZeroU64::new(lag).map(|lag| (stream, lag)))
             .collect();
-        Ok(OffsetsResponse { present, to_replicate })
+        Ok(OffsetsResponse {
+            present: offsets.present(),
+            to_replicate,
+        })
+    }
+
+    pub fn payload_schemas(&self) -> PayloadSchemasResponse {
+        self.payload_schemas.list()
+    }
+
+    pub fn payload_schema(&self, tag: &Tag) -> Option<PayloadSchema> {
+        self.payload_schemas.get(tag)
     }
 
     pub async fn publish(&self, app_id: AppId, request: PublishRequest) -> anyhow::Result<PublishResponse> {
@@ -97,16 +150,34 @@ impl EventService {
             cause: format!("{:#}", e),
         })?;
 
+        let lints = self.payload_schemas.lint(&query);
+
+        let timeout = self.query_timeouts.effective(request.timeout_millis);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
         let (query, pragmas) = Query::from(query, app_id);
         let features = Features::from_query(&query);
         let enabled = query.enabled_features(&pragmas);
         features.validate(&enabled, Endpoint::Query)?;
-        let mut feeder = query.make_feeder();
-
-        async fn y(co: &Co<QueryResponse>, vs: Vec<anyhow::Result<Value>>) {
+        // a leading OFFSET and LIMIT are applied while reading the events
+        let window = query.source_window();
+        let mut feeder = query.make_feeder_after(&window);
+        let limit = window.events();
+
+        async fn y(
+            co: &Co<QueryResponse>,
+            checksum: &mut Option<QueryChecksumBuilder>,
+            vs: Vec<anyhow::Result<Value>>,
+        ) {
             for v in vs {
                 co.yield_(match v {
-                    Ok(v) => QueryResponse::Event(v.into()),
+                    Ok(v) => {
+                        let event = v.into();
+                        if let Some(checksum) = checksum.as_mut() {
+                            checksum.add(&event);
+                        }
+                        QueryResponse::Event(event)
+                    }
                     Err(e) => QueryResponse::Diagnostic(to_diagnostic(e)),
                 })
                 .await;
@@ -128,7 +199,13 @@ impl EventService {
         let lower_bound = request.lower_bound.unwrap_or_default();
 
         let request_order = request.order;
+        let request_progress = request.progress;
+        let inline_blobs = request.inline_blobs.unwrap_or_default();
+        let mut checksum = request.checksum.unwrap_or_default().then(QueryChecksumBuilder::new);
         let gen = Gen::new(move |co: Co<QueryResponse>| async move {
+            for lint in lints {
+                co.yield_(QueryResponse::Diagnostic(lint)).await;
+            }
             let cx = Context::root(
                 Order::StreamAsc,
                 store.clone(),
@@ -136,7 +213,8 @@ impl EventService {
                 upper_bound.clone(),
             );
             let mut cx = cx.child();
-            let mut stream = match &query.source {
+            let mut progress = None;
+            let stream = match &query.source {
                 ax_aql::Source::Events { from, order } => {
                     let order = order.or_else(|| feeder.preferred_order()).unwrap_or(request_order);
                     cx.order = order;
@@ -148,16 +226,29 @@ impl EventService {
                                 .await
                         }
                     };
+                    // positions are also needed for resuming after a timeout
+                    if request_progress.is_some() || timeout.is_some() {
+                        progress = Some(ProgressTracker::new(
+                            request_progress,
+                            order,
+                            &lower_bound,
+                            &upper_bound,
+                        ));
+                    }
                     let stream = match order {
                         Order::Asc => {
                             store
-                                .bounded_forward(tag_expr, lower_bound, upper_bound.clone(), false)
+                                .bounded_forward(tag_expr, lower_bound, upper_bound.clone(), false, limit)
+                                .await
+                        }
+                        Order::Desc => {
+                            store
+                                .bounded_backward(tag_expr, lower_bound, upper_bound.clone(), limit)
                                 .await
                         }
-                        Order::Desc => store.bounded_backward(tag_expr, lower_bound, upper_bound.clone()).await,
                         Order::StreamAsc => {
                             store
-                                .bounded_forward(tag_expr, lower_bound, upper_bound.clone(), true)
+                                .bounded_forward(tag_expr, lower_bound, upper_bound.clone(), true, limit)
                                 .await
                         }
                     };
@@ -169,8 +260,21 @@ impl EventService {
                                 .await
                         }
                     };
+                    let events = store.0.clone();
                     stream
                         .stop_on_error()
+                        .then(move |ev| {
+                            let events = events.clone();
+                            async move {
+                                match ev {
+                                    Ok(mut ev) if inline_blobs => {
+                                        ev.payload = inline_payload(&events, ev.payload).await;
+                                        Ok(ev)
+                                    }
+                                    ev => ev,
+                                }
+                            }
+                        })
                         .map(|ev| match ev {
                             Ok(ev) => Ok(Value::from(ev)),
                             Err(e) => Err(e.into()),
@@ -211,27 +315,63 @@ impl EventService {
                     })
                     .right_stream(),
             };
-
-            while let Some(ev) = stream.next().await {
+            let mut stream = window.apply(stream);
+
+            loop {
+                let ev = match before(deadline, stream.next()).await {
+                    Some(Some(ev)) => ev,
+                    Some(None) => break,
+                    None => {
+                        // dropping the stream stops the work in the store
+                        drop(stream);
+                        return co.yield_(timed_out(timeout, progress)).await;
+                    }
+                };
                 let ev = match ev {
                     Ok(ev) => ev,
                     Err(e) => {
                         tracing::error!("aborting query due to {:#}", e);
-                        y(&co, vec![Err(e)]).await;
+                        y(&co, &mut checksum, vec![Err(e)]).await;
                         return;
                     }
                 };
-                let vs = feeder.feed(Some(ev), &cx).await;
-                y(&co, vs).await;
+                let key = match ev.meta() {
+                    EventMeta::Event { key, .. } => Some(*key),
+                    _ => None,
+                };
+                let vs = match before(deadline, feeder.feed(Some(ev), &cx)).await {
+                    Some(vs) => vs,
+                    None => {
+                        drop(stream);
+                        return co.yield_(timed_out(timeout, progress)).await;
+                    }
+                };
+                y(&co, &mut checksum, vs).await;
+                // only count the event as done once its results have been delivered
+                if let (Some(progress), Some(key)) = (progress.as_mut(), key) {
+                    progress.observe(&key);
+                }
                 if feeder.is_done() {
                     break;
                 }
+                if let Some(p) = progress.as_mut().and_then(|p| p.progress()) {
+                    co.yield_(QueryResponse::Progress(p)).await;
+                }
             }
             drop(stream);
 
-            let vs = feeder.feed(None, &cx).await;
-            y(&co, vs).await;
+            let vs = match before(deadline, feeder.feed(None, &cx)).await {
+                Some(vs) => vs,
+                None => return co.yield_(timed_out(timeout, progress)).await,
+            };
+            y(&co, &mut checksum, vs).await;
 
+            if let Some(progress) = progress.filter(|p| p.reports()) {
+                co.yield_(QueryResponse::Progress(progress.complete())).await;
+            }
+            if let Some(checksum) = checksum {
+                co.yield_(QueryResponse::Checksum(checksum.checksum())).await;
+            }
             co.yield_(QueryResponse::Offsets(OffsetMapResponse { offsets: upper_bound }))
                 .await;
         })
@@ -289,7 +429,7 @@ impl EventService {
 
         let mut bounded = self
             .store
-            .bounded_forward(tag_expr.clone(), lower_bound.clone(), present.clone(), false)
+            .bounded_forward(tag_expr.clone(), lower_bound.clone(), present.clone(), false, None)
             .await?
             .stop_on_error();
         lower_bound.union_with(&present);
@@ -418,7 +558,7 @@ impl EventService {
 
         let mut bounded = self
             .store
-            .bounded_forward(tag_expr.clone(), lower_bound.clone(), present.clone(), false)
+            .bounded_forward(tag_expr.clone(), lower_bound.clone(), present.clone(), false, None)
             .await?
             .stop_on_error();
         lower_bound.union_with(&present);
@@ -427,9 +567,11 @@ impl EventService {
             .unbounded_forward(tag_expr.clone(), lower_bound)
             .await?
             .stop_on_error();
+        let mut heartbeats = self.store.stream_heartbeats().await?;
+        let store = self.store.clone();
         let mut latest = self
             .store
-            .bounded_backward(tag_expr, OffsetMap::default(), request.lower_bound.clone())
+            .bounded_backward(tag_expr, OffsetMap::default(), request.lower_bound.clone(), Some(1))
             .await?
             .recv()
             .await
@@ -484,6 +626,33 @@ impl EventService {
             }
         }
 
+        /// The next event of `unbounded`, passing on the heartbeats of idle streams while waiting
+        /// for it. A heartbeat is only passed on once the events of its stream up to its offset
+        /// are present, and thereby have been fed into `unbounded`.
+        async fn next_event<T>(
+            co: &Co<SubscribeMonotonicResponse>,
+            unbounded: &mut (impl futures::Stream<Item = T> + Unpin),
+            heartbeats: &mut BoxStream<'static, (StreamId, LamportTimestamp, Offset)>,
+            store: &EventStoreRef,
+        ) -> Option<T> {
+            loop {
+                tokio::select! {
+                    biased;
+                    event = unbounded.next() => return event,
+                    Some((stream, lamport, offset)) = heartbeats.next() => {
+                        let present = match store.offsets().await {
+                            Ok(offsets) => offsets.present().offset(stream),
+                            Err(_) => continue,
+                        };
+                        if present >= offset.into() {
+                            co.yield_(SubscribeMonotonicResponse::Watermark { stream, lamport, offset })
+                                .await;
+                        }
+                    }
+                }
+            }
+        }
+
         let gen = Gen::new(move |co: Co<SubscribeMonotonicResponse>| async move {
             let cx = cx.child();
             while let Some(ev) = bounded.next().await {
@@ -506,7 +675,7 @@ impl EventService {
             }))
             .await;
 
-            let mut event = unbounded.next().await;
+            let mut event = next_event(&co, &mut unbounded, &mut heartbeats, &store).await;
             while let Some(ev) = event {
                 let next = poll_fn(|cx| Poll::Ready(unbounded.next().poll_unpin(cx))).await;
                 let ev = match ev {
@@ -523,7 +692,7 @@ impl EventService {
                 }
                 match next {
                     Poll::Ready(x) => event = x,
-                    Poll::Pending => event = unbounded.next().await,
+                    Poll::Pending => event = next_event(&co, &mut unbounded, &mut heartbeats, &store).await,
                 }
             }
         })
@@ -541,6 +710,145 @@ impl EventService {
     }
 }
 
+/// Computes the progress of a bounded query from the keys of the events it delivers.
+///
+/// The denominator is the number of offsets between lower and upper bound, the numerator
+/// the number of offsets passed so far; the latter only advances with delivered events,
+/// no additional tree reads are performed.
+struct ProgressTracker {
+    /// throttling of progress messages, `None` if no progress messages were requested
+    report: Option<(Duration, u64)>,
+    order: Order,
+    lower_bound: OffsetMap,
+    upper_bound: OffsetMap,
+    total: u64,
+    /// highest offsets delivered so far (ascending orders)
+    reached: OffsetMap,
+    /// offsets yet to be delivered (descending order)
+    remaining: OffsetMap,
+    /// lowest offsets delivered so far (descending order)
+    lowest: BTreeMap<StreamId, Offset>,
+    since_last: u64,
+    last: Instant,
+}
+
+impl ProgressTracker {
+    fn new(
+        config: Option<QueryProgressRequest>,
+        order: Order,
+        lower_bound: &OffsetMap,
+        upper_bound: &OffsetMap,
+    ) -> Self {
+        Self {
+            report: config.map(|config| (Duration::from_millis(config.interval_millis), config.events.max(1))),
+            order,
+            lower_bound: lower_bound.clone(),
+            upper_bound: upper_bound.clone(),
+            total: upper_bound - lower_bound,
+            reached: lower_bound.clone(),
+            remaining: upper_bound.clone(),
+            lowest: BTreeMap::new(),
+            since_last: 0,
+            last: Instant::now(),
+        }
+    }
+
+    fn observe(&mut self, key: &EventKey) {
+        match self.order {
+            Order::Asc | Order::StreamAsc => self.reached += key,
+            Order::Desc => {
+                self.remaining -= key;
+                self.lowest.insert(key.stream, key.offset);
+            }
+        }
+        self.since_last += 1;
+    }
+
+    fn done(&self) -> u64 {
+        match self.order {
+            Order::Asc | Order::StreamAsc => &self.reached.intersection(&self.upper_bound) - &self.lower_bound,
+            Order::Desc => self.total - (&self.remaining - &self.lower_bound),
+        }
+    }
+
+    fn positions(&self) -> OffsetMap {
+        match self.order {
+            Order::Asc | Order::StreamAsc => self.reached.clone(),
+            Order::Desc => self.lowest.clone().into(),
+        }
+    }
+
+    /// Whether progress messages were requested.
+    fn reports(&self) -> bool {
+        self.report.is_some()
+    }
+
+    /// Returns a progress message if one is due according to the configured throttling.
+    ///
+    /// Only [`complete`](Self::complete) reports the full total, so that 1.0 is only
+    /// ever reached once the query has delivered all its events.
+    fn progress(&mut self) -> Option<QueryProgress> {
+        let (interval, events) = self.report?;
+        if self.since_last < events && self.last.elapsed() < interval {
+            return None;
+        }
+        let current = self.current();
+        if current.done >= self.total {
+            return None;
+        }
+        self.since_last = 0;
+        self.last = Instant::now();
+        Some(current)
+    }
+
+    fn current(&self) -> QueryProgress {
+        QueryProgress {
+            done: self.done(),
+            total: self.total,
+            positions: self.positions(),
+        }
+    }
+
+    /// Lower and upper bound covering the offsets not yet delivered.
+    fn remaining_bounds(&self) -> (OffsetMap, OffsetMap) {
+        match self.order {
+            Order::Asc | Order::StreamAsc => (self.reached.clone(), self.upper_bound.clone()),
+            Order::Desc => (self.lower_bound.clone(), self.remaining.clone()),
+        }
+    }
+
+    fn complete(self) -> QueryProgress {
+        QueryProgress {
+            done: self.total,
+            total: self.total,
+            positions: self.positions(),
+        }
+    }
+}
+
+/// Runs `fut` to completion unless `deadline` passes first, in which case `None` is returned.
+async fn before<T>(deadline: Option<Instant>, fut: impl Future<Output = T>) -> Option<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, fut).await.ok(),
+        None => Some(fut.await),
+    }
+}
+
+fn timed_out(timeout: Option<Duration>, progress: Option<ProgressTracker>) -> QueryResponse {
+    let timeout = timeout.unwrap_or_default();
+    tracing::debug!("aborting query after timeout of {:?}", timeout);
+    let (lower_bound, upper_bound) = match progress.as_ref().map(|p| p.remaining_bounds()) {
+        Some((lower, upper)) => (Some(lower), Some(upper)),
+        None => (None, None),
+    };
+    QueryResponse::Timeout(QueryTimeout {
+        timeout_millis: timeout.as_millis().try_into().unwrap_or(u64::MAX),
+        progress: progress.map(|p| p.current()),
+        lower_bound,
+        upper_bound,
+    })
+}
+
 fn to_diagnostic(err: anyhow::Error) -> Diagnostic {
     if let Some(err) = err.downcast_ref::<RuntimeFailure>() {
         Diagnostic {
@@ -555,6 +863,20 @@ fn to_diagnostic(err: anyhow::Error) -> Diagnostic {
     }
 }
 
+/// Replace a blob reference by the original payload, keeping the reference if that fails.
+async fn inline_payload(events: &EventStoreRef, payload: Payload) -> Payload {
+    if PayloadRef::from_payload(&payload).is_none() {
+        return payload;
+    }
+    match events.inline_payload(payload.clone()).await {
+        Ok(inlined) => inlined,
+        Err(e) => {
+            tracing::warn!("delivering payload as reference: {:#}", e);
+            payload
+        }
+    }
+}
+
 struct EphemeralStore(EventStoreRef, Option<BanyanStore>);
 impl Drop for EphemeralStore {
     fn drop(&mut self) {
@@ -619,13 +941,14 @@ async fn store_line(store: &BanyanStore, line: &str) -> anyhow::Result<()> {
 mod tests {
     use super::*;
     use crate::swarm::{
-        event_store_ref::{self, EventStoreHandler},
-        BanyanStore, EventRoute,
+        event_store::{self, EventStore},
+        event_store_ref::{self, EventStoreHandler, EventStoreRequest},
+        BanyanStore, EventRoute, SwarmConfig,
     };
     use ax_aql::TagExpr;
     use ax_types::{
         app_id,
-        service::{EventMeta, EventResponse, SessionId},
+        service::{EventMeta, EventResponse, QueryChecksum, SessionId},
         tags, Metadata, TagSet,
     };
     use chrono::{DateTime, SecondsFormat};
@@ -634,7 +957,19 @@ mod tests {
     use lazy_static::lazy_static;
     use maplit::btreemap;
     use regex::Regex;
-    use std::{collections::BTreeMap, convert::TryInto, pin::Pin, str::FromStr, time::Duration};
+    use serde_json::json;
+    use sha2::Digest;
+    use std::{
+        collections::BTreeMap,
+        convert::TryInto,
+        pin::Pin,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
     use tokio::{
         runtime::{Handle, Runtime},
         sync::mpsc,
@@ -762,6 +1097,10 @@ mod tests {
                     upper_bound: None,
                     query: q.to_owned(),
                     order: Order::StreamAsc,
+                    progress: None,
+                    inline_blobs: None,
+                    checksum: None,
+                    timeout_millis: None,
                 },
             )
             .await
@@ -770,6 +1109,9 @@ mod tests {
                 QueryResponse::Event(e) => e.payload.json_string(),
                 QueryResponse::Offsets(_) => "offsets".to_owned(),
                 QueryResponse::Diagnostic(d) => d.message,
+                QueryResponse::Progress(_) => "progress".to_owned(),
+                QueryResponse::Checksum(_) => "checksum".to_owned(),
+                QueryResponse::Timeout(_) => "timeout".to_owned(),
                 QueryResponse::FutureCompat => unreachable!(),
             })
             .collect()
@@ -815,6 +1157,7 @@ mod tests {
                 SubscribeMonotonicResponse::Offsets(_) => "offsets".to_owned(),
                 SubscribeMonotonicResponse::TimeTravel { .. } => "timeTravel".to_owned(),
                 SubscribeMonotonicResponse::Diagnostic(d) => d.message,
+                SubscribeMonotonicResponse::Watermark { .. } => "watermark".to_owned(),
                 SubscribeMonotonicResponse::FutureCompat => unreachable!(),
             })
             .collect()
@@ -829,6 +1172,10 @@ mod tests {
                     upper_bound: None,
                     query: q.to_owned(),
                     order: Order::StreamAsc,
+                    progress: None,
+                    inline_blobs: None,
+                    checksum: None,
+                    timeout_millis: None,
                 },
             )
             .await
@@ -1038,6 +1385,412 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn offset() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("offset").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    for n in 1..=5 {
+                        publish(&service, tags!("a"), n).await;
+                    }
+
+                    // skipped before taking, whichever comes first
+                    assert_eq!(
+                        query(&service, "FEATURES(offset) FROM appId(me) OFFSET 1 LIMIT 2").await,
+                        vec!["2", "3", "offsets"]
+                    );
+                    assert_eq!(
+                        query(&service, "FEATURES(offset) FROM appId(me) LIMIT 4 OFFSET 3").await,
+                        vec!["4", "offsets"]
+                    );
+                    assert_eq!(
+                        query(&service, "FEATURES(offset) FROM appId(me) ORDER DESC OFFSET 1 LIMIT 2").await,
+                        vec!["4", "3", "offsets"]
+                    );
+                    assert_eq!(
+                        query(&service, "FEATURES(offset) FROM appId(me) LIMIT 2 OFFSET 3").await,
+                        vec!["offsets"]
+                    );
+                    // later stages see only the events after the offset
+                    assert_eq!(
+                        query(&service, "FEATURES(offset) FROM appId(me) OFFSET 3 FILTER _ < 5").await,
+                        vec!["4", "offsets"]
+                    );
+                    assert_eq!(
+                        query(&service, "FEATURES(offset) FROM appId(me) FILTER _ > 1 OFFSET 3").await,
+                        vec!["5", "offsets"]
+                    );
+                    assert_eq!(
+                        query(&service, "FEATURES(offset fromArray) FROM [1, 2, 3] OFFSET 1").await,
+                        vec!["2", "3", "offsets"]
+                    );
+                    assert_eq!(
+                        query(
+                            &service,
+                            "FEATURES(offset subQuery) FROM appId(me) LIMIT 1 SELECT FROM appId(me) OFFSET 3 LIMIT 1"
+                        )
+                        .await,
+                        vec!["[4]", "offsets"]
+                    );
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn progress() {
+        let rt = Runtime::new().unwrap();
+        let _guard = rt.enter();
+        rt.block_on(timeout(TIMEOUT, async {
+            let store = BanyanStore::test_with_routing(
+                "progress",
+                vec![
+                    EventRoute::new(TagExpr::from_str("'a'").unwrap(), "stream_a".to_string()),
+                    EventRoute::new(TagExpr::from_str("'b'").unwrap(), "stream_b".to_string()),
+                ],
+            )
+            .await
+            .unwrap();
+            let (_node_id, service) = setup(&store);
+
+            for i in 0..5 {
+                publish(&service, tags!("a"), i).await;
+                publish(&service, tags!("b"), i).await;
+                publish(&service, tags!("c"), i).await;
+            }
+
+            for order in [Order::Asc, Order::Desc, Order::StreamAsc] {
+                let responses = service
+                    .query(
+                        app_id!("test"),
+                        QueryRequest {
+                            lower_bound: None,
+                            upper_bound: None,
+                            query: "FROM allEvents".to_owned(),
+                            order,
+                            progress: Some(QueryProgressRequest {
+                                interval_millis: 60_000,
+                                events: 1,
+                            }),
+                            inline_blobs: None,
+                            checksum: None,
+                            timeout_millis: None,
+                        },
+                    )
+                    .await
+                    .unwrap()
+                    .collect::<Vec<_>>()
+                    .await;
+                let progress = responses
+                    .iter()
+                    .filter_map(|r| match r {
+                        QueryResponse::Progress(p) => Some(p.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                assert!(progress.len() > 1, "{:?}", responses);
+                assert!(progress.windows(2).all(|w| w[0].done <= w[1].done), "{:?}", progress);
+                assert_eq!(progress.iter().filter(|p| p.done == p.total).count(), 1);
+                assert!(matches!(
+                    &responses[responses.len() - 2],
+                    QueryResponse::Progress(p) if p.done == p.total
+                ));
+                assert!(matches!(responses.last(), Some(QueryResponse::Offsets(_))));
+            }
+
+            assert!(!query(&service, "FROM allEvents").await.contains(&"progress".to_owned()));
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn query_timeouts() {
+        let secs = |s| Some(Duration::from_secs(s));
+        let timeouts = QueryTimeouts {
+            default: secs(10),
+            max: secs(60),
+        };
+        assert_eq!(timeouts.effective(None), secs(10));
+        assert_eq!(timeouts.effective(Some(5_000)), secs(5));
+        assert_eq!(timeouts.effective(Some(120_000)), secs(60));
+        let uncapped = QueryTimeouts { max: None, ..timeouts };
+        assert_eq!(uncapped.effective(Some(120_000)), secs(120));
+        let no_default = QueryTimeouts {
+            default: None,
+            ..timeouts
+        };
+        assert_eq!(no_default.effective(None), secs(60));
+        assert_eq!(QueryTimeouts::unlimited().effective(None), None);
+        assert_eq!(QueryTimeouts::unlimited().effective(Some(1_000)), secs(1));
+    }
+
+    /// Event service on a store that takes 50ms to decode each event of a `StreamAsc` query.
+    fn setup_slow(store: &BanyanStore, decoded: Arc<AtomicUsize>) -> EventService {
+        let event_store = {
+            let store2 = store.clone();
+            let (tx, mut rx) = mpsc::channel(100);
+            store.spawn_task(
+                "slow handler".to_owned(),
+                async move {
+                    let events = EventStore::new(store2.clone());
+                    let mut handler = EventStoreHandler::new(store2);
+                    let runtime = Handle::current();
+                    while let Some(request) = rx.recv().await {
+                        match request {
+                            EventStoreRequest::BoundedForward {
+                                tag_expr,
+                                from_offsets_excluding,
+                                to_offsets_including,
+                                per_stream: true,
+                                limit,
+                                reply,
+                            } => {
+                                let events = events.clone();
+                                let decoded = decoded.clone();
+                                handler.stream(reply, &runtime, move || async move {
+                                    let stream = events
+                                        .bounded_forward_per_stream(
+                                            &tag_expr,
+                                            from_offsets_excluding,
+                                            to_offsets_including,
+                                            limit,
+                                        )
+                                        .await?;
+                                    Ok::<_, event_store::Error>(
+                                        stream
+                                            .then(move |ev| {
+                                                let decoded = decoded.clone();
+                                                async move {
+                                                    tokio::time::sleep(Duration::from_millis(50)).await;
+                                                    decoded.fetch_add(1, Ordering::SeqCst);
+                                                    ev
+                                                }
+                                            })
+                                            .boxed(),
+                                    )
+                                });
+                            }
+                            request => handler.handle(request, &runtime),
+                        }
+                    }
+                }
+                .boxed(),
+            );
+            EventStoreRef::new(Box::new(move |e| tx.try_send(e).map_err(event_store_ref::Error::from)))
+        };
+        EventService::new(event_store, store.node_id())
+    }
+
+    #[test]
+    fn timeout_with_resume_position() {
+        let rt = Runtime::new().unwrap();
+        let _guard = rt.enter();
+        rt.block_on(timeout(TIMEOUT, async {
+            let store = BanyanStore::test("timeout").await.unwrap();
+            let (_node_id, service) = setup(&store);
+            for i in 0..20 {
+                publish(&service, tags!("a"), i).await;
+            }
+            let decoded = Arc::new(AtomicUsize::new(0));
+            let slow = setup_slow(&store, decoded.clone());
+
+            let request = |lower_bound, upper_bound, timeout_millis| QueryRequest {
+                lower_bound,
+                upper_bound,
+                query: "FROM 'a'".to_owned(),
+                order: Order::StreamAsc,
+                progress: None,
+                inline_blobs: None,
+                checksum: None,
+                timeout_millis,
+            };
+            let payloads = |responses: &[QueryResponse]| {
+                responses
+                    .iter()
+                    .filter_map(|r| match r {
+                        QueryResponse::Event(e) => Some(e.payload.json_string()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let responses = slow
+                .query(app_id!("test"), request(None, None, Some(300)))
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await;
+            let delivered = payloads(&responses);
+            assert!(!delivered.is_empty() && delivered.len() < 20, "{:?}", responses);
+            let (lower_bound, upper_bound) = match responses.last() {
+                Some(QueryResponse::Timeout(t)) => {
+                    assert_eq!(t.timeout_millis, 300);
+                    assert_eq!(t.progress.as_ref().map(|p| p.total), Some(20));
+                    (t.lower_bound.clone(), t.upper_bound.clone())
+                }
+                x => panic!("unexpected: {:?}", x),
+            };
+            assert!(lower_bound.is_some() && upper_bound.is_some());
+
+            // the store stops decoding events once the query has been aborted
+            let stopped = decoded.load(Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            assert!(decoded.load(Ordering::SeqCst) <= stopped + 1);
+            assert!(stopped < 20);
+
+            // resuming delivers exactly the remaining events
+            let responses = service
+                .query(app_id!("test"), request(lower_bound, upper_bound, None))
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await;
+            assert!(matches!(responses.last(), Some(QueryResponse::Offsets(_))));
+            let all = delivered.into_iter().chain(payloads(&responses)).collect::<Vec<_>>();
+            assert_eq!(all, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn checksum() {
+        let rt = Runtime::new().unwrap();
+        let _guard = rt.enter();
+        rt.block_on(timeout(TIMEOUT, async {
+            let store = BanyanStore::test("checksum").await.unwrap();
+            let (_node_id, service) = setup(&store);
+            for i in 0..5 {
+                publish(&service, tags!("a"), i).await;
+            }
+
+            let responses = service
+                .query(
+                    app_id!("test"),
+                    QueryRequest {
+                        lower_bound: None,
+                        upper_bound: None,
+                        query: "FROM 'a'".to_owned(),
+                        order: Order::Asc,
+                        progress: None,
+                        inline_blobs: None,
+                        checksum: Some(true),
+                        timeout_millis: None,
+                    },
+                )
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await;
+            let events = responses
+                .iter()
+                .filter_map(|r| match r {
+                    QueryResponse::Event(e) => Some(e.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(events.len(), 5);
+            let expected = match &responses[responses.len() - 2] {
+                QueryResponse::Checksum(c) => c.clone(),
+                x => panic!("unexpected: {:?}", x),
+            };
+            assert!(matches!(responses.last(), Some(QueryResponse::Offsets(_))));
+
+            // recompute following the documented record format
+            let recompute = |events: &[EventResponse<Payload>]| {
+                let mut records = Vec::new();
+                for e in events {
+                    let key = match &e.meta {
+                        EventMeta::Event { key, .. } => key,
+                        x => panic!("unexpected: {:?}", x),
+                    };
+                    records.extend(serde_json::to_vec(&json!({ "key": key, "payload": e.payload })).unwrap());
+                    records.push(b'\n');
+                }
+                QueryChecksum {
+                    events: events.len() as u64,
+                    sha256: hex::encode(sha2::Sha256::digest(&records)),
+                }
+            };
+            assert_eq!(recompute(&events), expected);
+            // a response missing an event no longer matches
+            assert_ne!(recompute(&events[1..]), expected);
+
+            assert!(!query(&service, "FROM 'a'").await.contains(&"checksum".to_owned()));
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn inline_blobs() {
+        let rt = Runtime::new().unwrap();
+        let _guard = rt.enter();
+        rt.block_on(timeout(TIMEOUT, async {
+            let cfg = SwarmConfig {
+                payload_blob_threshold: Some(1024),
+                ..SwarmConfig::test("inline_blobs")
+            };
+            let store = BanyanStore::new(cfg, acto::ActoRef::blackhole()).await.unwrap();
+            let (_node_id, service) = setup(&store);
+            let large = Payload::compact(&"x".repeat(4096)).unwrap();
+            service
+                .publish(
+                    app_id!("test"),
+                    PublishRequest {
+                        data: vec![PublishEvent {
+                            tags: tags!("a"),
+                            payload: large.clone(),
+                        }],
+                    },
+                )
+                .await
+                .unwrap();
+
+            let payloads = |inline_blobs| {
+                let service = service.clone();
+                async move {
+                    service
+                        .query(
+                            app_id!("test"),
+                            QueryRequest {
+                                lower_bound: None,
+                                upper_bound: None,
+                                query: "FROM 'a'".to_owned(),
+                                order: Order::Asc,
+                                progress: None,
+                                inline_blobs,
+                                checksum: None,
+                                timeout_millis: None,
+                            },
+                        )
+                        .await
+                        .unwrap()
+                        .filter_map(|r| {
+                            ready(if let QueryResponse::Event(e) = r {
+                                Some(e.payload)
+                            } else {
+                                None
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                        .await
+                }
+            };
+
+            let references = payloads(None).await;
+            assert_eq!(references.len(), 1);
+            let reference = PayloadRef::from_payload(&references[0]).unwrap();
+            assert_eq!(reference.size, large.as_slice().len() as u64);
+            assert_eq!(payloads(Some(false)).await, references);
+            assert_eq!(payloads(Some(true)).await, vec![large]);
+        }))
+        .unwrap();
+    }
+
     #[test]
     fn order() {
         Runtime::new()
@@ -0,0 +1,34 @@
+use ax_types::service::compact::{CompactEncoder, CompactResponse, Compactable};
+use futures::stream::{self, BoxStream, StreamExt};
+use wsrpc::Service;
+
+/// Serves the responses of the wrapped service in compact encoding, with one stream ID
+/// dictionary per request.
+pub struct Compact<S>(S);
+
+impl<S> Service for Compact<S>
+where
+    S: Service,
+    S::Resp: Compactable + Send,
+    S::Error: Send,
+{
+    type Req = S::Req;
+    type Resp = CompactResponse<S::Resp>;
+    type Error = S::Error;
+    type Ctx = S::Ctx;
+
+    fn serve(&self, ctx: Self::Ctx, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
+        let mut encoder = CompactEncoder::default();
+        self.0
+            .serve(ctx, req)
+            .flat_map(move |response| match response {
+                Ok(response) => stream::iter(encoder.encode(response).into_iter().map(Ok).collect::<Vec<_>>()),
+                Err(e) => stream::iter(vec![Err(e)]),
+            })
+            .boxed()
+    }
+}
+
+pub fn service<S>(inner: S) -> Compact<S> {
+    Compact(inner)
+}
@@ -10,6 +10,7 @@ use crate::api::{
     NodeInfo,
 };
 
+mod compact;
 mod offsets;
 mod publish;
 mod query;
@@ -27,6 +28,8 @@ pub(crate) fn routes(
       "offsets"             => offsets::service(event_service.clone()).boxed(),
       "query"               => query::service(event_service.clone()).boxed(),
       "subscribe"           => subscribe::service(event_service.clone()).boxed(),
+      "query_compact"       => compact::service(query::service(event_service.clone())).boxed(),
+      "subscribe_compact"   => compact::service(subscribe::service(event_service.clone())).boxed(),
       "subscribe_monotonic" => subscribe_monotonic::service(event_service.clone()).boxed(),
       "publish"             => publish::service(event_service).boxed(),
     });
@@ -1,11 +1,11 @@
 use crate::swarm::BanyanStore;
 use anyhow::{Context, Result};
 use ax_types::AppId;
-use futures::{Stream, StreamExt};
+use futures::{stream, Stream, StreamExt};
 use http::header::CONTENT_DISPOSITION;
 use libipld::cid::Cid;
 use percent_encoding::percent_decode_str;
-use std::{collections::VecDeque, path::Path, str::FromStr};
+use std::{collections::VecDeque, ops::Range, path::Path, str::FromStr, time::Duration};
 use warp::{
     host::Authority,
     http::header::{HeaderValue, CONTENT_TYPE},
@@ -55,37 +55,66 @@ pub fn content_type_from_content(chunk: &[u8]) -> Option<&'static str> {
     Some(mime)
 }
 
-pub async fn get_file(store: BanyanStore, cid: Cid) -> anyhow::Result<impl Stream<Item = anyhow::Result<Vec<u8>>>> {
+/// How long to wait for the swarm to deliver a block of a file before giving up
+pub(crate) const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, derive_more::Display, derive_more::Error)]
+#[display(fmt = "No block received within {:?}.", FETCH_TIMEOUT)]
+pub(crate) struct FetchTimeout;
+
+pub async fn get_file(
+    store: BanyanStore,
+    cid: Cid,
+    range: Range<u64>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<Vec<u8>>>> {
     let mut tmp = store.ipfs().create_temp_pin()?;
     store.ipfs().temp_pin(&mut tmp, &cid)?;
 
-    Ok(store.cat(cid, false))
+    Ok(store.cat_range(cid, range, false))
 }
 
-pub(crate) async fn get_file_raw(store: BanyanStore, cid: Cid, name: &str) -> anyhow::Result<Response<Body>> {
-    let s = get_file(store, cid).await?;
-    let mut response = if let Some(ct) = content_type_from_ext(name) {
-        let mut r = Response::new(Body::wrap_stream(s));
-        r.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_str(&ct)?);
-        r
-    } else {
-        let mut s = Box::pin(s.peekable());
-        let buf = s
-            .as_mut()
-            .peek()
-            .await
-            .context("empty stream")?
-            .as_ref()
-            .map_err(|e| anyhow::anyhow!("{:#}", e))?;
-        tracing::debug!(%cid, %name, size=buf.len(), "Detecting content-type from content");
-
-        let ct = content_type_from_content(&buf[..buf.len().min(1024)]);
-        let mut r = Response::new(Body::wrap_stream(s));
-        if let Some(ct) = ct {
-            r.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_str(ct)?);
+/// Ends the stream with a [`FetchTimeout`] once a chunk takes longer than [`FETCH_TIMEOUT`].
+fn with_fetch_timeout(
+    chunks: impl Stream<Item = anyhow::Result<Vec<u8>>> + Send + 'static,
+) -> impl Stream<Item = anyhow::Result<Vec<u8>>> + Send + 'static {
+    stream::unfold(Some(chunks.boxed()), |chunks| async move {
+        let mut chunks = chunks?;
+        match tokio::time::timeout(FETCH_TIMEOUT, chunks.next()).await {
+            Ok(Some(Ok(chunk))) => Some((Ok(chunk), Some(chunks))),
+            Ok(Some(Err(e))) => Some((Err(e), None)),
+            Ok(None) => None,
+            Err(_) => Some((Err(FetchTimeout.into()), None)),
         }
-        r
+    })
+}
+
+/// Serve the bytes within `range` of a file.
+///
+/// The first block is fetched before responding, so that a file the swarm cannot deliver is
+/// reported as an error instead of an empty response.
+pub(crate) async fn get_file_raw(
+    store: BanyanStore,
+    cid: Cid,
+    name: &str,
+    range: Range<u64>,
+) -> anyhow::Result<Response<Body>> {
+    // only the beginning of a file tells its type
+    let sniff = range.start == 0;
+    let mut s = with_fetch_timeout(get_file(store, cid, range).await?).boxed();
+    // empty files yield no chunks at all
+    let first = s.next().await.transpose()?;
+    let ct = match content_type_from_ext(name) {
+        Some(ct) => Some(ct),
+        None if sniff => first.as_ref().and_then(|buf| {
+            tracing::debug!(%cid, %name, size=buf.len(), "Detecting content-type from content");
+            content_type_from_content(&buf[..buf.len().min(1024)]).map(String::from)
+        }),
+        None => None,
     };
+    let mut response = Response::new(Body::wrap_stream(stream::iter(first.map(Ok)).chain(s)));
+    if let Some(ct) = ct {
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_str(&ct)?);
+    }
 
     if !name.is_empty() {
         response.headers_mut().insert(
@@ -1,7 +1,7 @@
 mod ipfs;
 mod pinner;
 
-use self::ipfs::{extract_query_from_host, extract_query_from_path, IpfsQuery};
+use self::ipfs::{extract_query_from_host, extract_query_from_path, FetchTimeout, IpfsQuery, FETCH_TIMEOUT};
 use crate::{
     api::{
         ans::{ActyxName, ActyxNamingService, PersistenceLevel},
@@ -10,18 +10,26 @@ use crate::{
         NodeInfo,
     },
     balanced_or,
-    swarm::{BanyanStore, Block, BufferingTreeBuilder, TreeOptions},
+    swarm::{BanyanStore, Block, BufferingTreeBuilder, FileNode, TreeOptions, UnixfsPathError},
 };
 use anyhow::Context;
 use ax_sdk::files::{DirectoryChild, FilesGetResponse, PrefetchRequest};
-use ax_types::{app_id, tags, AppId, Payload};
+use ax_types::{app_id, tags, AppId, Payload, Timestamp};
 use bytes::{BufMut, Bytes};
+use chrono::{DateTime, Utc};
 use futures::prelude::*;
-use http::{header::CACHE_CONTROL, Uri};
-use libipld::Cid;
+use http::{
+    header::{
+        HeaderValue, ACCEPT, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_NONE_MATCH,
+        LAST_MODIFIED, RANGE,
+    },
+    StatusCode, Uri,
+};
+use libipld::{error::BlockNotFound, Cid};
 use serde::Serialize;
-use std::{fmt::Write, path::Path, str::FromStr, time::Duration};
+use std::{collections::VecDeque, fmt::Write, ops::Range, path::Path, str::FromStr, time::Duration};
 use warp::{
+    hyper::{Body, Response},
     path::{self, FullPath},
     Buf, Filter, Rejection, Reply,
 };
@@ -36,7 +44,7 @@ pub fn root_serve(
     store: BanyanStore,
     node_info: NodeInfo,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    warp::header::optional(http::header::ACCEPT.as_str())
+    file_request_headers()
         .and(extract_query_from_host(
             node_info,
             ActyxNamingService::new(store.clone()),
@@ -44,20 +52,11 @@ pub fn root_serve(
         .and(warp::path::full())
         .and(query_raw_opt())
         .and_then(
-            move |accept_header: Option<String>,
+            move |headers: FileRequestHeaders,
                   (query, maybe_name): (IpfsQuery, Option<ActyxName>),
                   uri_path: FullPath,
                   raw_query: Option<String>| {
-                serve_unixfs_node(
-                    store.clone(),
-                    query,
-                    uri_path,
-                    raw_query,
-                    accept_header,
-                    true,
-                    maybe_name,
-                )
-                .map_err(crate::api::reject)
+                serve_unixfs_node(store.clone(), query, uri_path, raw_query, headers, true, maybe_name).map_err(reject)
             },
         )
 }
@@ -69,31 +68,199 @@ fn query_raw_opt() -> impl Filter<Extract = (Option<String>,), Error = Rejection
         .unify()
 }
 
+/// The request headers that determine how a unixfs node is served
+#[derive(Debug, Clone, Default)]
+struct FileRequestHeaders {
+    accept: Option<String>,
+    range: Option<String>,
+    if_none_match: Option<String>,
+}
+
+impl FileRequestHeaders {
+    fn accepts_html(&self) -> bool {
+        self.accept
+            .as_deref()
+            .map(|x| x.to_lowercase().contains("text/html"))
+            .unwrap_or_default()
+    }
+
+    /// Whether the client already has the content of `cid`, which never changes.
+    fn has_cached(&self, cid: &Cid) -> bool {
+        let etag = etag(cid);
+        self.if_none_match
+            .as_deref()
+            .map(|tags| {
+                tags.split(',')
+                    .map(|tag| tag.trim())
+                    .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn file_request_headers() -> impl Filter<Extract = (FileRequestHeaders,), Error = Rejection> + Clone {
+    warp::header::optional(ACCEPT.as_str())
+        .and(warp::header::optional(RANGE.as_str()))
+        .and(warp::header::optional(IF_NONE_MATCH.as_str()))
+        .map(|accept, range, if_none_match| FileRequestHeaders {
+            accept,
+            range,
+            if_none_match,
+        })
+}
+
+/// Surface missing paths and content the swarm did not deliver with their own status codes.
+fn reject(err: anyhow::Error) -> Rejection {
+    if let Some(e) = err.downcast_ref::<UnixfsPathError>() {
+        return warp::reject::custom(ApiError::PathNotFound {
+            segment: e.segment().to_owned(),
+            cause: e.to_string(),
+        });
+    }
+    if err.is::<FetchTimeout>() || err.is::<BlockNotFound>() {
+        return warp::reject::custom(ApiError::GatewayTimeout {
+            cause: format!("{:#}", err),
+        });
+    }
+    match err.downcast::<ApiError>() {
+        Ok(e) => warp::reject::custom(e),
+        Err(err) => crate::api::reject(err),
+    }
+}
+
+/// Contents are addressed by their [`Cid`], so it makes for a strong entity tag.
+fn etag(cid: &Cid) -> String {
+    format!("\"{}\"", cid)
+}
+
+fn with_etag(mut response: Response<Body>, cid: &Cid) -> anyhow::Result<Response<Body>> {
+    response.headers_mut().insert(ETAG, HeaderValue::from_str(&etag(cid))?);
+    Ok(response)
+}
+
+/// The bytes requested by a `Range` header of a file with `size` bytes.
+///
+/// Only a single range of bytes is supported, other range requests are ignored and answered with
+/// the whole file as permitted by RFC 7233.
+fn parse_range(header: &str, size: u64) -> Result<Option<Range<u64>>, ApiError> {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Ok(None),
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return Ok(None),
+    };
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // the last `n` bytes
+        (Err(_), Ok(n)) if start.is_empty() => {
+            if n == 0 {
+                return Err(ApiError::RangeNotSatisfiable { size });
+            }
+            size.saturating_sub(n)..size
+        }
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        (Ok(start), Ok(end)) if start <= end => start..size.min(end.saturating_add(1)),
+        _ => return Ok(None),
+    };
+    if range.start >= size {
+        return Err(ApiError::RangeNotSatisfiable { size });
+    }
+    Ok(Some(range))
+}
+
+/// Resolve the path within `root`, giving up after [`FETCH_TIMEOUT`].
+async fn resolve(store: &BanyanStore, root: Cid, path: VecDeque<String>) -> anyhow::Result<FileNode> {
+    tokio::time::timeout(FETCH_TIMEOUT, store.unixfs_resolve_path(root, path))
+        .await
+        .map_err(|_| FetchTimeout)?
+}
+
+async fn serve_file(
+    store: BanyanStore,
+    cid: Cid,
+    name: &str,
+    size: u64,
+    mtime: Option<Timestamp>,
+    headers: &FileRequestHeaders,
+) -> anyhow::Result<Response<Body>> {
+    if headers.has_cached(&cid) {
+        return not_modified(&cid);
+    }
+    let range = match headers.range.as_deref() {
+        Some(header) => parse_range(header, size)?,
+        None => None,
+    };
+    let mut response = match range {
+        Some(range) => {
+            // blocks outside of the range are not fetched
+            let mut response = ipfs::get_file_raw(store, cid, name, range.clone()).await?;
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            response.headers_mut().insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", range.start, range.end - 1, size))?,
+            );
+            response
+                .headers_mut()
+                .insert(CONTENT_LENGTH, (range.end - range.start).into());
+            response
+        }
+        None => {
+            let mut response = ipfs::get_file_raw(store, cid, name, 0..u64::MAX).await?;
+            response.headers_mut().insert(CONTENT_LENGTH, size.into());
+            response
+        }
+    };
+    response
+        .headers_mut()
+        .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(date) = mtime.and_then(http_date) {
+        response
+            .headers_mut()
+            .insert(LAST_MODIFIED, HeaderValue::from_str(&date)?);
+    }
+    with_etag(response, &cid)
+}
+
+/// Formats `timestamp` as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, `None` if it is out
+/// of range.
+fn http_date(timestamp: Timestamp) -> Option<String> {
+    let date = DateTime::<Utc>::try_from(timestamp).ok()?;
+    Some(date.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+fn not_modified(cid: &Cid) -> anyhow::Result<Response<Body>> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    with_etag(response, cid)
+}
+
 async fn serve_unixfs_node(
     store: BanyanStore,
     query: IpfsQuery,
     uri_path: FullPath,
     raw_query: Option<String>,
-    accept_headers: Option<String>,
+    headers: FileRequestHeaders,
     auto_serve_index_html: bool,
     ans_name: Option<ActyxName>,
 ) -> anyhow::Result<impl Reply> {
-    let mut response = match store.unixfs_resolve_path(query.root, query.path).await? {
-        crate::swarm::FileNode::Directory {
+    let mut response = match resolve(&store, query.root, query.path).await? {
+        FileNode::Directory {
             children,
             name,
             own_cid,
         } => {
-            if accept_headers
-                .as_deref()
-                .map(|x| x.to_lowercase().contains("text/html"))
-                .unwrap_or_default()
-            {
+            if headers.accepts_html() {
                 if let Some(index_html) = auto_serve_index_html
                     .then(|| children.iter().find(|x| &*x.name == "index.html"))
                     .flatten()
                 {
-                    ipfs::get_file_raw(store, index_html.cid, &index_html.name).await?
+                    match resolve(&store, index_html.cid, VecDeque::new()).await? {
+                        FileNode::File { cid, size, mtime, .. } => {
+                            serve_file(store, cid, &index_html.name, size, mtime, &headers).await?
+                        }
+                        FileNode::Directory { .. } => anyhow::bail!("{} is not a file", index_html.name),
+                    }
                 } else if !uri_path.as_str().ends_with('/') {
                     // Add trailing slash so the links in the directory listings
                     // work as intended.
@@ -103,10 +270,14 @@ async fn serve_unixfs_node(
                         raw_query.map(|q| format!("?{}", q)).unwrap_or_default(),
                     );
                     warp::redirect(Uri::from_str(&uri)?).into_response()
+                } else if headers.has_cached(&own_cid) {
+                    not_modified(&own_cid)?
                 } else {
                     let body = render_directory_listing(name, own_cid, children, raw_query)?;
-                    warp::reply::html(body).into_response()
+                    with_etag(warp::reply::html(body).into_response(), &own_cid)?
                 }
+            } else if headers.has_cached(&own_cid) {
+                not_modified(&own_cid)?
             } else {
                 let r = FilesGetResponse::Directory {
                     name,
@@ -120,10 +291,10 @@ async fn serve_unixfs_node(
                         })
                         .collect(),
                 };
-                warp::reply::json(&r).into_response()
+                with_etag(warp::reply::json(&r).into_response(), &own_cid)?
             }
         }
-        crate::swarm::FileNode::File { cid, name } => ipfs::get_file_raw(store, cid, &name).await?,
+        FileNode::File { cid, name, size, mtime } => serve_file(store, cid, &name, size, mtime, &headers).await?,
     };
     if ans_name.is_some() {
         response
@@ -222,25 +393,16 @@ fn render_directory_listing(
 fn get(store: BanyanStore, node_info: NodeInfo) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     warp::get()
         .and(authorize(node_info).map(|_| ()).untuple_one())
-        .and(warp::header::optional(http::header::ACCEPT.as_str()))
+        .and(file_request_headers())
         .and(extract_query_from_path(ActyxNamingService::new(store.clone())))
         .and(warp::path::full())
         .and(query_raw_opt())
         .and_then(
-            move |accept_header: Option<String>,
+            move |headers: FileRequestHeaders,
                   (query, maybe_name): (IpfsQuery, Option<ActyxName>),
                   uri_path: FullPath,
                   raw_query: Option<String>| {
-                serve_unixfs_node(
-                    store.clone(),
-                    query,
-                    uri_path,
-                    raw_query,
-                    accept_header,
-                    false,
-                    maybe_name,
-                )
-                .map_err(crate::api::reject)
+                serve_unixfs_node(store.clone(), query, uri_path, raw_query, headers, false, maybe_name).map_err(reject)
             },
         )
 }
@@ -427,3 +589,17 @@ fn mime(name: impl AsRef<Path>) -> String {
         .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM)
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_modified_is_an_http_date() {
+        assert_eq!(
+            http_date(Timestamp::new(784_111_777_123_456)).as_deref(),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+        assert_eq!(http_date(Timestamp::new(i64::MAX as u64)), None);
+    }
+}
@@ -213,6 +213,10 @@ async fn evaluate(event_svc: &EventService, ipfs: &Ipfs, app_id: &AppId, query:
                 upper_bound: None,
                 query,
                 order: Order::Desc,
+                progress: None,
+                inline_blobs: None,
+                checksum: None,
+                timeout_millis: None,
             },
         )
         .await?
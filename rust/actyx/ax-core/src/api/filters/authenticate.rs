@@ -156,6 +156,7 @@ mod tests {
             token_validity: 300,
             ax_public_key: PrivateKey::generate().into(),
             licensing: Licensing::default(),
+            license_tolerance: Default::default(),
             started_at: Utc::now(),
         };
 
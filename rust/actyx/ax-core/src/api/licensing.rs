@@ -1,28 +1,77 @@
 use crate::{
     api::rejections::{ApiError, UnauthorizedReason},
-    certs::{AppLicenseType, Expiring, SignedAppLicense},
+    certs::{InvalidLicense, LicenseCheckOutcome, LicenseTolerance, SignedAppLicense},
     crypto::PublicKey,
 };
-use ax_types::AppId;
-use chrono::Utc;
+use ax_types::{app_id, AppId};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Licensing {
+    #[serde(with = "crate::settings::sensitive")]
     node: String,
+    #[serde(with = "crate::settings::sensitive_values")]
     pub apps: BTreeMap<AppId, String>,
+    /// Seconds for which expired licenses are still accepted, none if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grace_period: Option<u64>,
+    /// Seconds by which the creation of a license may lie in the future, unchecked if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_skew: Option<u64>,
+}
+
+/// A license that has expired but is still accepted within its grace period.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseWarning {
+    pub app_id: AppId,
+    pub expired_at: DateTime<Utc>,
+    pub grace_until: DateTime<Utc>,
+}
+
+impl LicenseWarning {
+    pub fn remaining(&self, now: DateTime<Utc>) -> Duration {
+        (self.grace_until - now).max(Duration::zero())
+    }
+}
+
+/// The node license as checked by [`Licensing::node_license`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum NodeLicense {
+    Development,
+    Licensed(Option<LicenseWarning>),
 }
 
 impl Licensing {
     pub fn new(node: String, apps: BTreeMap<AppId, String>) -> Self {
-        Self { node, apps }
+        Self {
+            node,
+            apps,
+            grace_period: None,
+            clock_skew: None,
+        }
     }
 
-    pub fn is_node_licensed(&self, ax_public_key: &PublicKey) -> Result<bool, ApiError> {
+    /// The tolerance for checking licenses as configured in the settings.
+    pub fn tolerance(&self) -> LicenseTolerance {
+        // capped so that adding it to a timestamp cannot overflow
+        let secs = |secs: u64| Duration::seconds(secs.min(i32::MAX as u64) as i64);
+        LicenseTolerance {
+            grace_period: self.grace_period.map(secs).unwrap_or_else(Duration::zero),
+            clock_skew: self.clock_skew.map(secs),
+        }
+    }
+
+    pub fn node_license(
+        &self,
+        ax_public_key: &PublicKey,
+        tolerance: &LicenseTolerance,
+    ) -> Result<NodeLicense, ApiError> {
         if self.node == "development" {
-            return Ok(false);
+            return Ok(NodeLicense::Development);
         }
         let license = self
             .node
@@ -30,26 +79,14 @@ impl Licensing {
             .map_err(|_| ApiError::NodeUnauthorized {
                 reason: UnauthorizedReason::MalformedLicense,
             })?;
-        license
-            .validate(ax_public_key)
-            .map_err(|_| ApiError::NodeUnauthorized {
-                reason: UnauthorizedReason::InvalidSignature,
-            })?;
-        match license.license.license_type {
-            AppLicenseType::Expiring(Expiring { app_id, expires_at }) => {
-                if app_id.as_str() != "com.actyx.node" {
-                    Err(ApiError::NodeUnauthorized {
-                        reason: UnauthorizedReason::WrongSubject,
-                    })
-                } else if expires_at < Utc::now() {
-                    Err(ApiError::NodeUnauthorized {
-                        reason: UnauthorizedReason::Expired,
-                    })
-                } else {
-                    Ok(true)
-                }
-            }
-        }
+        let node_app_id = app_id!("com.actyx.node");
+        check_license(&license, ax_public_key, &node_app_id, tolerance)
+            .map(NodeLicense::Licensed)
+            .map_err(|reason| ApiError::NodeUnauthorized { reason })
+    }
+
+    pub fn is_node_licensed(&self, ax_public_key: &PublicKey, tolerance: &LicenseTolerance) -> Result<bool, ApiError> {
+        Ok(self.node_license(ax_public_key, tolerance)? != NodeLicense::Development)
     }
 
     pub fn app_id_license(&self, app_id: &AppId) -> Option<&String> {
@@ -57,12 +94,33 @@ impl Licensing {
     }
 }
 
+/// Check a license for `subject` now, returning a warning while it is only accepted by grace.
+pub(crate) fn check_license(
+    license: &SignedAppLicense,
+    ax_public_key: &PublicKey,
+    subject: &AppId,
+    tolerance: &LicenseTolerance,
+) -> Result<Option<LicenseWarning>, UnauthorizedReason> {
+    match license.check(ax_public_key, subject, tolerance, Utc::now()) {
+        LicenseCheckOutcome::Valid => Ok(None),
+        LicenseCheckOutcome::GracePeriod {
+            expired_at,
+            grace_until,
+        } => Ok(Some(LicenseWarning {
+            app_id: subject.clone(),
+            expired_at,
+            grace_until,
+        })),
+        LicenseCheckOutcome::Invalid(InvalidLicense::InvalidSignature) => Err(UnauthorizedReason::InvalidSignature),
+        LicenseCheckOutcome::Invalid(InvalidLicense::WrongSubject(_)) => Err(UnauthorizedReason::WrongSubject),
+        LicenseCheckOutcome::Invalid(InvalidLicense::Expired(_)) => Err(UnauthorizedReason::Expired),
+        LicenseCheckOutcome::Invalid(InvalidLicense::NotYetValid(_)) => Err(UnauthorizedReason::NotYetValid),
+    }
+}
+
 impl Default for Licensing {
     fn default() -> Self {
-        Licensing {
-            node: "development".into(),
-            apps: BTreeMap::default(),
-        }
+        Licensing::new("development".into(), BTreeMap::default())
     }
 }
 
@@ -77,20 +135,40 @@ mod tests {
         let licensing = Licensing::default();
         assert_eq!(licensing.node, "development");
         assert!(licensing.apps.is_empty());
+        assert_eq!(licensing.tolerance(), LicenseTolerance::default());
+    }
+
+    #[test]
+    fn tolerance_from_settings() {
+        let licensing: Licensing = serde_json::from_value(serde_json::json!({
+            "node": "development",
+            "apps": {},
+            "gracePeriod": 86400,
+            "clockSkew": 3600,
+        }))
+        .unwrap();
+        assert_eq!(
+            licensing.tolerance(),
+            LicenseTolerance {
+                grace_period: Duration::days(1),
+                clock_skew: Some(Duration::hours(1)),
+            }
+        );
     }
 
     #[test]
     fn is_node_licensed() {
         let licensing = Licensing::default();
         let ax_key = PublicKey::ax_public_key();
-        assert!(!licensing.is_node_licensed(&ax_key).unwrap());
+        assert!(!licensing
+            .is_node_licensed(&ax_key, &LicenseTolerance::default())
+            .unwrap());
 
-        let licensing = Licensing {
-            node: "licensed".into(),
-            apps: BTreeMap::default(),
-        };
+        let licensing = Licensing::new("licensed".into(), BTreeMap::default());
         assert_eq!(
-            licensing.is_node_licensed(&ax_key).unwrap_err(),
+            licensing
+                .is_node_licensed(&ax_key, &LicenseTolerance::default())
+                .unwrap_err(),
             ApiError::NodeUnauthorized {
                 reason: UnauthorizedReason::MalformedLicense
             }
@@ -0,0 +1,22 @@
+use crate::{
+    api::{node::with_store, reject, Result},
+    swarm::BanyanStore,
+};
+use warp::{get, path, reply, Filter, Rejection, Reply};
+
+/// Prometheus scrape endpoint, only served if enabled in the swarm config.
+pub(crate) fn route(store: BanyanStore) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    path("metrics")
+        .and(path::end())
+        .and(get())
+        .and(with_store(store))
+        .and_then(handle_metrics)
+}
+
+async fn handle_metrics(store: BanyanStore) -> Result<impl Reply> {
+    if !store.prometheus_enabled() {
+        return Err(warp::reject::not_found());
+    }
+    let (content_type, body) = store.prometheus_text().map_err(reject)?;
+    Ok(reply::with_header(body, http::header::CONTENT_TYPE, content_type))
+}
@@ -8,15 +8,20 @@ mod filters;
 mod hyper_serve;
 pub mod licensing;
 pub(crate) mod macros;
+mod metrics;
 mod node;
 mod rejections;
 #[cfg(test)]
 mod tests;
 
-pub use crate::api::events::service::EventService;
+pub use crate::api::events::{
+    schemas::PayloadSchemas,
+    service::{EventService, QueryTimeouts},
+};
 use crate::{
     api::{files::FilePinner, hyper_serve::serve_it, licensing::Licensing},
     ax_panic, balanced_or,
+    certs::LicenseTolerance,
     crypto::{KeyStoreRef, PublicKey},
     swarm::{blob_store::BlobStore, event_store_ref::EventStoreRef, BanyanStore},
     util::{
@@ -43,6 +48,7 @@ pub struct NodeInfo {
     pub cycles: NodeCycleCount,
     pub ax_public_key: PublicKey,
     pub licensing: Licensing,
+    pub license_tolerance: LicenseTolerance,
     pub started_at: DateTime<Utc>,
 }
 
@@ -60,6 +66,7 @@ impl NodeInfo {
             cycles,
             token_validity: 86400,
             ax_public_key: PublicKey::ax_public_key(),
+            license_tolerance: licensing.tolerance(),
             licensing,
             started_at,
         }
@@ -93,6 +100,7 @@ pub enum AppMode {
     Signed,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     node_info: NodeInfo,
     store: BanyanStore,
@@ -101,8 +109,15 @@ pub async fn run(
     bind_to: Arc<Mutex<SocketAddrHelper>>,
     snd: Sender<anyhow::Result<()>>,
     swarm_state: Reader<SwarmState>,
+    query_timeouts: QueryTimeouts,
+    payload_schemas: PayloadSchemas,
 ) {
-    let event_service = events::service::EventService::new(event_store, node_info.node_id);
+    if let Err(e) = store.register_event_store(event_store.clone()) {
+        tracing::warn!("cannot export the event store mailbox metrics: {}", e);
+    }
+    let event_service = EventService::new(event_store, node_info.node_id)
+        .with_query_timeouts(query_timeouts)
+        .with_payload_schemas(payload_schemas);
     let pinner = FilePinner::new(event_service.clone(), store.ipfs().clone());
     let api = routes(node_info, store, event_service, pinner, blobs, swarm_state);
     #[allow(clippy::needless_collect)]
@@ -174,6 +189,7 @@ fn routes(
         );
     });
     balanced_or!(
+        metrics::route(store.clone()),
         files::root_serve(store, node_info),
         api_path.and(balanced_or!(
             path("events").and(events),
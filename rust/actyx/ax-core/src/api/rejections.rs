@@ -16,6 +16,8 @@ pub enum UnauthorizedReason {
     WrongSubject,
     #[display(fmt = "license expired")]
     Expired,
+    #[display(fmt = "license not yet valid")]
+    NotYetValid,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
@@ -23,6 +25,15 @@ pub enum ApiError {
     #[display(fmt = "The requested resource could not be found.")]
     NotFound,
 
+    #[display(fmt = "Path segment '{}' could not be found. {}", segment, cause)]
+    PathNotFound { segment: String, cause: String },
+
+    #[display(fmt = "Requested range is not within the {} bytes of the file.", size)]
+    RangeNotSatisfiable { size: u64 },
+
+    #[display(fmt = "Timed out fetching content from the swarm. {}", cause)]
+    GatewayTimeout { cause: String },
+
     #[display(fmt = "Method not supported.")]
     MethodNotAllowed,
 
@@ -82,6 +93,9 @@ pub enum ApiError {
     #[display(fmt = "Service overloaded. {}", cause)]
     Overloaded { cause: String },
 
+    #[display(fmt = "Too many requests. {}", cause)]
+    TooManyRequests { cause: String },
+
     #[display(fmt = "Service shutting down. {}", cause)]
     Shutdown { cause: String },
 
@@ -134,7 +148,11 @@ impl From<ApiError> for ApiErrorResponse {
             ApiError::MissingTokenParameter => (StatusCode::UNAUTHORIZED, "ERR_MISSING_TOKEN_PARAM"),
             ApiError::NotAcceptable { .. } => (StatusCode::NOT_ACCEPTABLE, "ERR_NOT_ACCEPTABLE"),
             ApiError::NotFound => (StatusCode::NOT_FOUND, "ERR_NOT_FOUND"),
+            ApiError::PathNotFound { .. } => (StatusCode::NOT_FOUND, "ERR_NOT_FOUND"),
+            ApiError::RangeNotSatisfiable { .. } => (StatusCode::RANGE_NOT_SATISFIABLE, "ERR_RANGE_NOT_SATISFIABLE"),
+            ApiError::GatewayTimeout { .. } => (StatusCode::GATEWAY_TIMEOUT, "ERR_GATEWAY_TIMEOUT"),
             ApiError::Overloaded { .. } => (StatusCode::SERVICE_UNAVAILABLE, "ERR_SERVICE_OVERLOADED"),
+            ApiError::TooManyRequests { .. } => (StatusCode::TOO_MANY_REQUESTS, "ERR_TOO_MANY_REQUESTS"),
             ApiError::Shutdown { .. } => (StatusCode::SERVICE_UNAVAILABLE, "ERR_SHUTTING_DOWN"),
             ApiError::TokenExpired => (StatusCode::UNAUTHORIZED, "ERR_TOKEN_EXPIRED"),
             ApiError::TokenInvalid { .. } => (StatusCode::BAD_REQUEST, "ERR_TOKEN_INVALID"),
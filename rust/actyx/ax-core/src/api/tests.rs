@@ -1,22 +1,27 @@
 use crate::{
-    api::{auth::create_token, files::FilePinner, licensing::Licensing, rejections, AppMode, EventService, NodeInfo},
+    api::{
+        auth::create_token, files::FilePinner, licensing::Licensing, rejections, AppMode, EventService, NodeInfo,
+        PayloadSchemas,
+    },
     crypto::{KeyStore, KeyStoreRef, PrivateKey, PublicKey},
     swarm::{
         blob_store::BlobStore,
         event_store_ref::{self, EventStoreHandler, EventStoreRef},
-        BanyanStore, DbPath,
+        BanyanStore, DbPath, SwarmConfig,
     },
     util::variable::Writer,
 };
+use acto::ActoRef;
 use ax_types::{
     app_id,
     service::{AuthenticationResponse, SwarmState},
-    NodeId,
+    tag, tags, NodeId, Payload, StreamNr, Timestamp,
 };
 use bytes::Bytes;
 use chrono::Utc;
 use futures::FutureExt;
 use hyper::Response;
+use maplit::btreemap;
 use parking_lot::lock_api::RwLock;
 use serde_json::json;
 use tokio::{runtime::Handle, sync::mpsc};
@@ -54,6 +59,7 @@ async fn test_routes() -> (
         token_validity: 300,
         ax_public_key: PrivateKey::generate().into(),
         licensing: Licensing::default(),
+        license_tolerance: Default::default(),
         started_at: Utc::now(),
     };
     let event_store = {
@@ -72,7 +78,13 @@ async fn test_routes() -> (
         );
         EventStoreRef::new(move |e| tx.try_send(e).map_err(event_store_ref::Error::from))
     };
-    let event_service = EventService::new(event_store, auth_args.node_id);
+    let payload_schemas = PayloadSchemas::new(btreemap! {
+        tag!("order") => json!({
+            "type": "object",
+            "properties": { "customer": { "type": "string" }, "amount": { "type": "number" } }
+        })
+    });
+    let event_service = EventService::new(event_store, auth_args.node_id).with_payload_schemas(payload_schemas);
     let pinner = FilePinner::new(event_service.clone(), store.ipfs().clone());
     let blobs = BlobStore::new(DbPath::Memory).unwrap();
     let swarm_state = Writer::new(SwarmState::default()).reader();
@@ -410,6 +422,72 @@ async fn unauthorized_invalid() {
     );
 }
 
+#[tokio::test]
+async fn payload_schemas() {
+    let (route, token, ..) = test_routes().await;
+    let get = |path: &str| {
+        test::request()
+            .path(path)
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&route)
+    };
+    let schema = json!({
+        "tag": "order",
+        "schema": {
+            "type": "object",
+            "properties": { "customer": { "type": "string" }, "amount": { "type": "number" } }
+        }
+    });
+
+    let resp = get("/api/v2/events/schemas").await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+        json!({ "schemas": [schema] })
+    );
+    let resp = get("/api/v2/events/schemas/order").await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap(),
+        schema
+    );
+    let resp = get("/api/v2/events/schemas/other%20tag").await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn payload_schema_lint() {
+    let (route, token, ..) = test_routes().await;
+    let query = |query: &str| {
+        test::request()
+            .path("/api/v2/events/query")
+            .method("POST")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "offsets": {}, "upperBound": {}, "query": query, "order": "asc" }))
+            .reply(&route)
+    };
+    let diagnostics = |resp: Response<Bytes>| {
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        std::str::from_utf8(resp.body())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|v| v["type"] == "diagnostic")
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        diagnostics(query("FROM 'order' FILTER _.amount > 10 SELECT _.custmer").await),
+        vec![json!({
+            "type": "diagnostic",
+            "severity": "warning",
+            "message": "field `_.custmer` is not defined by the payload schemas of 'order', did you mean `customer`?"
+        })]
+    );
+    assert!(diagnostics(query("FROM 'order' SELECT _.customer").await).is_empty());
+    assert!(diagnostics(query("FROM 'unregistered' SELECT _.custmer").await).is_empty());
+}
+
 #[tokio::test]
 async fn not_found() {
     let (route, ..) = test_routes().await;
@@ -605,13 +683,14 @@ async fn bad_request_aql_feature() {
     );
 }
 
+fn to_json(m: warp::ws::Message) -> anyhow::Result<serde_json::Value> {
+    Ok(m.to_str()
+        .map_err(|_| anyhow::anyhow!("binary"))?
+        .parse::<serde_json::Value>()?)
+}
+
 #[tokio::test]
 async fn ws_aql_feature() -> anyhow::Result<()> {
-    fn to_json(m: warp::ws::Message) -> anyhow::Result<serde_json::Value> {
-        Ok(m.to_str()
-            .map_err(|_| anyhow::anyhow!("binary"))?
-            .parse::<serde_json::Value>()?)
-    }
     async fn assert_complete(ws: &mut test::WsClient, id: u32) {
         assert_eq!(
             to_json(ws.recv().await.unwrap()).unwrap(),
@@ -722,6 +801,128 @@ async fn ws_aql_feature() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn ws_compact_subscribe() -> anyhow::Result<()> {
+    use ax_types::service::{
+        compact::{CompactDecoder, CompactResponse},
+        SubscribeResponse,
+    };
+
+    /// Collects the responses up to the first offsets message and the number of bytes received.
+    async fn collect(
+        ws: &mut test::WsClient,
+        service: &str,
+        id: u32,
+    ) -> anyhow::Result<(Vec<serde_json::Value>, usize)> {
+        ws.send_text(
+            json!({
+                "type": "request",
+                "serviceId": service,
+                "requestId": id,
+                "payload": { "query": "FROM 'compact'" }
+            })
+            .to_string(),
+        )
+        .await;
+        let (mut responses, mut bytes) = (vec![], 0);
+        loop {
+            let msg = ws.recv().await?;
+            let text = msg.to_str().map_err(|_| anyhow::anyhow!("binary"))?;
+            bytes += text.len();
+            let msg = text.parse::<serde_json::Value>()?;
+            anyhow::ensure!(msg["type"] == "next", "unexpected message {}", msg);
+            for response in msg["payload"].as_array().unwrap() {
+                responses.push(response.clone());
+                if response["type"] == "offsets" || response["type"] == "o" {
+                    return Ok((responses, bytes));
+                }
+            }
+        }
+    }
+
+    let (route, token, ..) = test_routes().await;
+    let mut ws = test::ws()
+        .path(&format!("/api/v2/events?{}", token))
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .handshake(route)
+        .await?;
+
+    let events = (0..200)
+        .map(|n| json!({ "tags": ["compact", "test"], "payload": { "n": n } }))
+        .collect::<Vec<_>>();
+    ws.send_text(
+        json!({
+            "type": "request",
+            "serviceId": "publish",
+            "requestId": 1,
+            "payload": { "data": events }
+        })
+        .to_string(),
+    )
+    .await;
+    while to_json(ws.recv().await?)?["type"] != "complete" {}
+
+    let (regular, regular_bytes) = collect(&mut ws, "subscribe", 2).await?;
+    let regular = regular
+        .into_iter()
+        .map(serde_json::from_value::<SubscribeResponse>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (compact, compact_bytes) = collect(&mut ws, "subscribe_compact", 3).await?;
+    let mut decoder = CompactDecoder::default();
+    let mut decoded = vec![];
+    for response in compact {
+        let response = serde_json::from_value::<CompactResponse<SubscribeResponse>>(response)?;
+        decoded.extend(decoder.decode(response)?);
+    }
+
+    assert_eq!(regular.len(), 201);
+    assert_eq!(decoded, regular);
+    assert!(
+        compact_bytes * 4 < regular_bytes * 3,
+        "compact {} bytes, regular {} bytes",
+        compact_bytes,
+        regular_bytes
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn prometheus_metrics() -> anyhow::Result<()> {
+    let cfg = SwarmConfig {
+        enable_prometheus: true,
+        ..SwarmConfig::test("prometheus")
+    };
+    let store = BanyanStore::new(cfg, ActoRef::blackhole()).await?;
+    let events = vec![(tags!("a"), Payload::null()); 3];
+    store
+        .append0(StreamNr::from(7), app_id!("test"), Timestamp::now(), events)
+        .await?;
+    let resp = test::request()
+        .path("/metrics")
+        .reply(&super::metrics::route(store))
+        .await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body = String::from_utf8(resp.body().to_vec())?;
+    assert!(
+        body.contains(r#"ax_swarm_appended_events_total{stream="7"} 3"#),
+        "{}",
+        body
+    );
+
+    // disabled by default
+    let store = BanyanStore::test("no_prometheus").await?;
+    let resp = test::request()
+        .path("/metrics")
+        .reply(&super::metrics::route(store))
+        .await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    Ok(())
+}
+
 mod files {
     use std::{collections::BTreeMap, time::Duration};
 
@@ -1036,6 +1237,129 @@ mod files {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn serving_files_with_etags_and_ranges() -> anyhow::Result<()> {
+        let (route, token, ..) = test_routes().await;
+        // more than one block of the default chunker
+        let data = (0..300_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let body = create_mutlipart(btreemap! {
+            "folder/data.bin" => data.clone(),
+            "folder/hello.txt" => b"Hello World!\n".to_vec(),
+        });
+        let resp = test::request()
+            .path("/api/v2/files")
+            .method("POST")
+            .header("Authorization", format!("Bearer {}", token))
+            .header(
+                "Content-Type",
+                r#"multipart/form-data; charset=utf-8; boundary="boundary""#,
+            )
+            .body(body)
+            .reply(&route)
+            .await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let root = String::from_utf8(resp.body().to_vec())?;
+        let get = |path: String, headers: Vec<(&'static str, String)>| {
+            let mut request = test::request()
+                .path(&path)
+                .method("GET")
+                .header("Authorization", format!("Bearer {}", token));
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.reply(&route)
+        };
+
+        // directory listing, with its cid as entity tag
+        let resp = get(format!("/api/v2/files/{}/folder", root), vec![]).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let (folder, children) = match serde_json::from_slice::<ax_sdk::files::FilesGetResponse>(resp.body())? {
+            ax_sdk::files::FilesGetResponse::Directory { cid, children, .. } => (cid, children),
+            other => panic!("expected a directory, got {:?}", other),
+        };
+        assert_eq!(resp.headers().get("ETag").unwrap().to_str()?, format!("\"{}\"", folder));
+        let file = children.iter().find(|c| c.name == "data.bin").unwrap().cid;
+        let etag = format!("\"{}\"", file);
+        let resp = get(
+            format!("/api/v2/files/{}/folder", root),
+            vec![("If-None-Match", format!("\"{}\"", folder))],
+        )
+        .await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+
+        // the whole file
+        let path = format!("/api/v2/files/{}/folder/data.bin", root);
+        let resp = get(path.clone(), vec![]).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(resp.headers().get("ETag").unwrap().to_str()?, etag);
+        assert_eq!(resp.headers().get("Accept-Ranges").unwrap().to_str()?, "bytes");
+        assert_eq!(resp.headers().get("Content-Length").unwrap().to_str()?, "300000");
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap().to_str()?,
+            "application/octet-stream"
+        );
+        assert_eq!(resp.body().to_vec(), data);
+
+        let resp = get(path.clone(), vec![("If-None-Match", format!("W/{}, \"other\"", etag))]).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get("ETag").unwrap().to_str()?, etag);
+        assert!(resp.body().is_empty());
+
+        // ranges, the first one across a block boundary
+        for (range, expected, content_range) in [
+            ("bytes=262140-262150", 262140..262151, "bytes 262140-262150/300000"),
+            ("bytes=299990-", 299990..300000, "bytes 299990-299999/300000"),
+            ("bytes=-5", 299995..300000, "bytes 299995-299999/300000"),
+            ("bytes=10-1000000", 10..300000, "bytes 10-299999/300000"),
+        ] {
+            let resp = get(path.clone(), vec![("Range", range.to_owned())]).await;
+            assert_eq!(resp.status(), http::StatusCode::PARTIAL_CONTENT, "{}", range);
+            assert_eq!(resp.headers().get("Content-Range").unwrap().to_str()?, content_range);
+            assert_eq!(
+                resp.headers().get("Content-Length").unwrap().to_str()?,
+                expected.len().to_string()
+            );
+            assert_eq!(resp.body().to_vec(), data[expected].to_vec(), "{}", range);
+        }
+
+        // several ranges are not supported and answered with the whole file
+        let resp = get(path.clone(), vec![("Range", "bytes=0-1,5-6".to_owned())]).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert_eq!(resp.body().len(), data.len());
+
+        let resp = get(path, vec![("Range", "bytes=300000-".to_owned())]).await;
+        assert_err_response(
+            resp,
+            http::StatusCode::RANGE_NOT_SATISFIABLE,
+            json!({
+                "code": "ERR_RANGE_NOT_SATISFIABLE",
+                "message": "Requested range is not within the 300000 bytes of the file."
+            }),
+        );
+
+        // paths that do not exist name the failing segment
+        let resp = get(format!("/api/v2/files/{}/folder/nope/data.bin", root), vec![]).await;
+        assert_err_response(
+            resp,
+            http::StatusCode::NOT_FOUND,
+            json!({
+                "code": "ERR_NOT_FOUND",
+                "message": format!("Path segment 'nope' could not be found. Path nope not found inside {}", folder)
+            }),
+        );
+        let resp = get(format!("/api/v2/files/{}/folder/data.bin/deeper", root), vec![]).await;
+        assert_err_response(
+            resp,
+            http::StatusCode::NOT_FOUND,
+            json!({
+                "code": "ERR_NOT_FOUND",
+                "message": "Path segment 'deeper' could not be found. \
+                            Found file data.bin while looking for directory deeper"
+            }),
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn should_return_404_in_root() -> anyhow::Result<()> {
         let (route, ..) = test_routes().await;
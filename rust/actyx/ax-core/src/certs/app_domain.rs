@@ -20,6 +20,18 @@ impl AppDomain {
             .zip(app_id.split('.').chain(repeat("")))
             .all(|(me, them)| (me == "*" && !them.is_empty()) || me == them)
     }
+
+    /// Explains why `app_id` is not allowed in this domain, `None` if it is.
+    pub(crate) fn mismatch(&self, app_id: &AppId) -> Option<String> {
+        if self.is_app_id_allowed(app_id) {
+            return None;
+        }
+        let prefix = self.0.trim_end_matches('*');
+        Some(format!(
+            "'{}' requires the app id to start with '{}' followed by a name",
+            self, prefix
+        ))
+    }
 }
 
 impl FromStr for AppDomain {
@@ -91,6 +103,11 @@ mod tests {
         vec!["com.actyx", "com", "xxx.xxx"].into_iter().for_each(|x| {
             let app_id = AppId::from_str(x).unwrap();
             assert!(!app_domain.is_app_id_allowed(&app_id));
+            assert_eq!(
+                app_domain.mismatch(&app_id).unwrap(),
+                "'com.actyx.*' requires the app id to start with 'com.actyx.' followed by a name"
+            );
         });
+        assert_eq!(app_domain.mismatch(&AppId::from_str("com.actyx.test").unwrap()), None);
     }
 }
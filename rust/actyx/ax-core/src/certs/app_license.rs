@@ -3,7 +3,7 @@ use std::str::FromStr;
 use crate::crypto::{PrivateKey, PublicKey};
 use anyhow::Context;
 use ax_types::AppId;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::certs::signature::Signature;
@@ -46,6 +46,50 @@ pub struct SignedAppLicense {
     pub requester: RequesterInfo,
 }
 
+/// Leeway granted when checking the validity period of a license.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LicenseTolerance {
+    /// How long an expired license is still accepted, with a warning
+    pub grace_period: Duration,
+    /// How far the node clock may lag behind the clock that created the license, licenses
+    /// created in the future are accepted if this is `None`
+    pub clock_skew: Option<Duration>,
+}
+
+impl Default for LicenseTolerance {
+    fn default() -> Self {
+        // devices with a skewed or reset RTC must keep accepting their licenses, so a license is
+        // only rejected for being created in the future if asked for
+        Self {
+            grace_period: Duration::zero(),
+            clock_skew: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum InvalidLicense {
+    #[display(fmt = "invalid signature")]
+    InvalidSignature,
+    #[display(fmt = "license is for {}", _0)]
+    WrongSubject(AppId),
+    #[display(fmt = "license expired at {}", _0)]
+    Expired(DateTime<Utc>),
+    #[display(fmt = "license is not valid before {}", _0)]
+    NotYetValid(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseCheckOutcome {
+    Valid,
+    /// The license has expired but is still accepted, this should be surfaced as a warning.
+    GracePeriod {
+        expired_at: DateTime<Utc>,
+        grace_until: DateTime<Utc>,
+    },
+    Invalid(InvalidLicense),
+}
+
 impl SignedAppLicense {
     pub fn new(
         ax_private_key: PrivateKey,
@@ -71,6 +115,43 @@ impl SignedAppLicense {
         self.signature.verify(&self.license, ax_public_key)
     }
 
+    /// Check signature, subject and validity period of the license at the given time.
+    ///
+    /// The license is valid from its creation up to and including its expiry.
+    pub fn check(
+        &self,
+        ax_public_key: &PublicKey,
+        subject: &AppId,
+        tolerance: &LicenseTolerance,
+        now: DateTime<Utc>,
+    ) -> LicenseCheckOutcome {
+        if self.validate(ax_public_key).is_err() {
+            return LicenseCheckOutcome::Invalid(InvalidLicense::InvalidSignature);
+        }
+        match &self.license.license_type {
+            AppLicenseType::Expiring(Expiring { app_id, expires_at }) => {
+                let grace_until = *expires_at + tolerance.grace_period;
+                if app_id != subject {
+                    LicenseCheckOutcome::Invalid(InvalidLicense::WrongSubject(app_id.clone()))
+                } else if tolerance
+                    .clock_skew
+                    .map_or(false, |skew| now + skew < self.license.created_at)
+                {
+                    LicenseCheckOutcome::Invalid(InvalidLicense::NotYetValid(self.license.created_at))
+                } else if now <= *expires_at {
+                    LicenseCheckOutcome::Valid
+                } else if now <= grace_until {
+                    LicenseCheckOutcome::GracePeriod {
+                        expired_at: *expires_at,
+                        grace_until,
+                    }
+                } else {
+                    LicenseCheckOutcome::Invalid(InvalidLicense::Expired(*expires_at))
+                }
+            }
+        }
+    }
+
     pub fn to_base64(&self) -> anyhow::Result<String> {
         let bytes = serde_cbor::to_vec(&self)?;
         Ok(base64::encode(bytes))
@@ -90,9 +171,12 @@ impl FromStr for SignedAppLicense {
 mod tests {
     use crate::crypto::{PrivateKey, PublicKey};
     use ax_types::{app_id, AppId};
-    use chrono::{DateTime, TimeZone, Utc};
+    use chrono::{DateTime, Duration, TimeZone, Utc};
 
-    use crate::certs::{app_license::SignedAppLicense, signature::InvalidSignature};
+    use crate::certs::{
+        app_license::{InvalidLicense, LicenseCheckOutcome, LicenseTolerance, SignedAppLicense},
+        signature::InvalidSignature,
+    };
 
     struct TestFixture {
         ax_private_key: PrivateKey,
@@ -183,4 +267,104 @@ mod tests {
         let deserialized: SignedAppLicense = expected.parse().unwrap();
         assert_eq!(deserialized, license);
     }
+
+    #[test]
+    fn check_at_expiry_boundaries() {
+        let x = setup();
+        let license = SignedAppLicense::new(
+            x.ax_private_key,
+            x.email,
+            x.app_id.clone(),
+            x.expires_at,
+            Some(x.created_at),
+        )
+        .unwrap();
+        let second = Duration::seconds(1);
+        let check = |tolerance: &LicenseTolerance, now| license.check(&x.ax_public_key, &x.app_id, tolerance, now);
+
+        let strict = LicenseTolerance::default();
+        assert_eq!(check(&strict, x.expires_at), LicenseCheckOutcome::Valid);
+        assert_eq!(
+            check(&strict, x.expires_at + second),
+            LicenseCheckOutcome::Invalid(InvalidLicense::Expired(x.expires_at))
+        );
+
+        let lenient = LicenseTolerance {
+            grace_period: Duration::days(3),
+            ..Default::default()
+        };
+        let grace_until = x.expires_at + Duration::days(3);
+        assert_eq!(check(&lenient, x.expires_at), LicenseCheckOutcome::Valid);
+        for now in [x.expires_at + second, grace_until] {
+            assert_eq!(
+                check(&lenient, now),
+                LicenseCheckOutcome::GracePeriod {
+                    expired_at: x.expires_at,
+                    grace_until
+                }
+            );
+        }
+        assert_eq!(
+            check(&lenient, grace_until + second),
+            LicenseCheckOutcome::Invalid(InvalidLicense::Expired(x.expires_at))
+        );
+    }
+
+    #[test]
+    fn check_clock_skew_boundaries() {
+        let x = setup();
+        let license = SignedAppLicense::new(
+            x.ax_private_key,
+            x.email,
+            x.app_id.clone(),
+            x.expires_at,
+            Some(x.created_at),
+        )
+        .unwrap();
+        assert_eq!(
+            license.check(
+                &x.ax_public_key,
+                &x.app_id,
+                &LicenseTolerance::default(),
+                x.created_at - Duration::days(365)
+            ),
+            LicenseCheckOutcome::Valid
+        );
+
+        let tolerance = LicenseTolerance {
+            clock_skew: Some(Duration::hours(2)),
+            ..Default::default()
+        };
+        let earliest = x.created_at - Duration::hours(2);
+        assert_eq!(
+            license.check(&x.ax_public_key, &x.app_id, &tolerance, earliest),
+            LicenseCheckOutcome::Valid
+        );
+        assert_eq!(
+            license.check(&x.ax_public_key, &x.app_id, &tolerance, earliest - Duration::seconds(1)),
+            LicenseCheckOutcome::Invalid(InvalidLicense::NotYetValid(x.created_at))
+        );
+    }
+
+    #[test]
+    fn check_signature_and_subject() {
+        let x = setup();
+        let license = SignedAppLicense::new(
+            x.ax_private_key,
+            x.email,
+            x.app_id.clone(),
+            x.expires_at,
+            Some(x.created_at),
+        )
+        .unwrap();
+        let tolerance = LicenseTolerance::default();
+        assert_eq!(
+            license.check(&PrivateKey::generate().into(), &x.app_id, &tolerance, x.created_at),
+            LicenseCheckOutcome::Invalid(InvalidLicense::InvalidSignature)
+        );
+        assert_eq!(
+            license.check(&x.ax_public_key, &app_id!("com.actyx.other"), &tolerance, x.created_at),
+            LicenseCheckOutcome::Invalid(InvalidLicense::WrongSubject(x.app_id))
+        );
+    }
 }
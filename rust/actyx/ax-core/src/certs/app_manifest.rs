@@ -13,6 +13,66 @@ pub struct AppManifestSignatureProps {
     pub version: String,
 }
 
+/// An app manifest to be signed with
+/// [`sign_manifest`](app_manifest_signer::sign_manifest).
+///
+/// Unlike a trial [`AppManifest`] it may use any app id; a signature present in its JSON form is
+/// ignored, so that an already signed manifest can be signed again.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrialAppManifest {
+    pub app_id: AppId,
+    pub display_name: String,
+    pub version: String,
+}
+
+impl TrialAppManifest {
+    pub fn new(app_id: AppId, display_name: String, version: String) -> Self {
+        Self {
+            app_id,
+            display_name,
+            version,
+        }
+    }
+}
+
+/// An [`AppManifest`] carrying a signature, as returned by
+/// [`sign_manifest`](app_manifest_signer::sign_manifest).
+///
+/// The signature has not necessarily been verified, see
+/// [`verify_manifest`](app_manifest_signer::verify_manifest).
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(into = "AppManifest", try_from = "AppManifest")]
+pub struct SignedAppManifest(AppManifest);
+
+impl SignedAppManifest {
+    pub fn manifest(&self) -> &AppManifest {
+        &self.0
+    }
+
+    pub fn signature(&self) -> &str {
+        self.0.signature().as_deref().unwrap_or_default()
+    }
+}
+
+impl From<SignedAppManifest> for AppManifest {
+    fn from(value: SignedAppManifest) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<AppManifest> for SignedAppManifest {
+    type Error = String;
+
+    fn try_from(value: AppManifest) -> Result<Self, Self::Error> {
+        if value.is_signed() {
+            Ok(Self(value))
+        } else {
+            Err(format!("App manifest for '{}' is not signed.", value.app_id()))
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct AppManifestSignature {
     // this is zero (for now)
@@ -55,7 +115,7 @@ pub mod app_manifest_signer {
     use std::{fs, path::PathBuf};
 
     use crate::{
-        certs::DeveloperCertificate,
+        certs::{DeveloperCertificate, SignedRevocationList},
         private_key::AxPrivateKey,
         util::formats::{ActyxOSCode, ActyxOSResult, ActyxOSResultExt},
     };
@@ -78,12 +138,12 @@ pub mod app_manifest_signer {
         })?;
         let app_manifest =
             fs::read_to_string(&manifest_path).ax_err_ctx(ActyxOSCode::ERR_IO, "Failed to read app manifest")?;
-        let app_manifest: AppManifest = serde_json::from_str(&app_manifest)
+        let app_manifest: TrialAppManifest = serde_json::from_str(&app_manifest)
             .ax_err_ctx(ActyxOSCode::ERR_INVALID_INPUT, "Failed to deserialize app manifest")?;
 
-        let signed_manifest =
-            app_manifest_signer::make_signed(&app_manifest, dev_privkey, dev_cert.manifest_dev_cert())
-                .ax_err_ctx(ActyxOSCode::ERR_INVALID_INPUT, "Failed to create signed manifest")?;
+        let signed_manifest: AppManifest = sign_manifest(app_manifest, dev_privkey, dev_cert.manifest_dev_cert())
+            .ax_err_ctx(ActyxOSCode::ERR_INVALID_INPUT, "Failed to create signed manifest")?
+            .into();
         let serialized = serde_json::to_string(&signed_manifest)
             .ax_err_ctx(ActyxOSCode::ERR_IO, "Failed to serialize signed app manifest")?;
         fs::write(&manifest_path, serialized).ax_err_ctx(ActyxOSCode::ERR_IO, "Failed to overwrite app manifest")?;
@@ -91,30 +151,45 @@ pub mod app_manifest_signer {
         Ok(signed_manifest)
     }
 
-    pub fn make_signed(
-        manifest: &AppManifest,
+    /// Sign `manifest` as the developer certified by `dev_cert`.
+    ///
+    /// Fails if `dev_privkey` does not belong to the certified developer or if the app id is not
+    /// within the certificate's app domains. The certificate's own signature is not checked here,
+    /// that is up to the node verifying the manifest with [`verify_manifest`].
+    pub fn sign_manifest(
+        manifest: TrialAppManifest,
         dev_privkey: PrivateKey,
         dev_cert: ManifestDeveloperCertificate,
-    ) -> anyhow::Result<AppManifest> {
-        dev_cert.validate_app_id(&manifest.app_id())?;
+    ) -> anyhow::Result<SignedAppManifest> {
+        if PublicKey::from(dev_privkey) != dev_cert.dev_public_key() {
+            anyhow::bail!(
+                "Developer private key does not match the public key '{}' of the developer certificate.",
+                dev_cert.dev_public_key()
+            );
+        }
+        dev_cert.validate_app_id(&manifest.app_id)?;
         let hash_input = AppManifestSignatureProps {
-            app_id: manifest.app_id(),
-            display_name: manifest.display_name().into(),
-            version: manifest.version().into(),
+            app_id: manifest.app_id,
+            display_name: manifest.display_name,
+            version: manifest.version,
         };
         let dev_signature = Signature::new(&hash_input, dev_privkey)?;
         let manifest_signature = AppManifestSignature::new(dev_signature, dev_cert);
         let manifest_signature_string: String = manifest_signature.try_into()?;
-        let manifest: AppManifest = AppManifest::signed(
-            manifest.app_id(),
-            manifest.display_name().into(),
-            manifest.version().into(),
+        Ok(SignedAppManifest(AppManifest::signed(
+            hash_input.app_id,
+            hash_input.display_name,
+            hash_input.version,
             manifest_signature_string,
-        );
-        Ok(manifest)
+        )))
     }
 
-    pub fn validate(manifest: &AppManifest, ax_public_key: &PublicKey) -> anyhow::Result<()> {
+    /// Check that a signed manifest carries a developer certificate issued by Actyx, that its app
+    /// id is within the certificate's app domains and that the developer signed exactly this app
+    /// id, display name and version.
+    ///
+    /// Trial manifests carry no signature and are accepted as they are.
+    pub fn verify_manifest(manifest: &AppManifest, ax_public_key: &PublicKey) -> anyhow::Result<()> {
         if let Some(signature) = manifest.signature() {
             let signature = AppManifestSignature::from_str(signature)?;
             // Check signature on the dev cert
@@ -138,6 +213,27 @@ pub mod app_manifest_signer {
         }
         Ok(())
     }
+
+    /// Like [`verify_manifest`], but additionally rejects manifests whose developer certificate or app id
+    /// has been revoked.
+    ///
+    /// Trial manifests are not signed by a developer, so only their app id is checked.
+    pub fn validate_with_revocations(
+        manifest: &AppManifest,
+        ax_public_key: &PublicKey,
+        revocations: &SignedRevocationList,
+    ) -> anyhow::Result<()> {
+        revocations
+            .validate(ax_public_key)
+            .map_err(|x| anyhow::Error::msg(format!("Failed to validate revocation list. {}", x)))?;
+        verify_manifest(manifest, ax_public_key)?;
+        let dev_public_key = match manifest.signature() {
+            Some(signature) => Some(AppManifestSignature::from_str(signature)?.dev_cert.dev_public_key()),
+            None => None,
+        };
+        revocations.check(dev_public_key.as_ref(), &manifest.app_id())?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -151,16 +247,39 @@ mod tests {
         signature::Signature,
     };
 
-    use super::{app_manifest_signer, AppManifest, AppManifestSignature};
+    use super::{app_manifest_signer, AppManifest, AppManifestSignature, SignedAppManifest, TrialAppManifest};
+    use crate::certs::{DeveloperCertificate, RevocationError, SignedRevocationList};
+    use ax_types::{app_id, AppId};
+    use chrono::{Duration, Utc};
 
     struct TestFixture {
+        ax_private_key: PrivateKey,
         ax_public_key: PublicKey,
         serialized_manifest: serde_json::Value,
     }
 
+    // the developer key that signed the fixture manifest
+    fn dev_private_key() -> PrivateKey {
+        PrivateKey::from_str("0BKoAIaJ1z3AM+hqJiquSoPvEMbnIeznncmZxo24j5SY=").unwrap()
+    }
+
+    fn dev_cert(x: &TestFixture) -> DeveloperCertificate {
+        DeveloperCertificate::create(
+            dev_private_key().into(),
+            vec!["com.actyx.*".parse().unwrap()],
+            x.ax_private_key,
+        )
+        .unwrap()
+    }
+
+    fn trial_manifest(app_id: AppId) -> TrialAppManifest {
+        TrialAppManifest::new(app_id, "display name".into(), "version 0".into())
+    }
+
     fn setup() -> TestFixture {
         let ax_private_key = PrivateKey::from_str("0WBFFicIHbivRZXAlO7tPs7rCX6s7u2OIMJ2mx9nwg0w=").unwrap();
         TestFixture {
+            ax_private_key,
             ax_public_key: ax_private_key.into(),
             serialized_manifest: serde_json::json!({
                 "appId":"com.actyx.test-app",
@@ -175,7 +294,7 @@ mod tests {
     fn validate() {
         let x = setup();
         let manifest = serde_json::from_value::<AppManifest>(x.serialized_manifest).unwrap();
-        let result = app_manifest_signer::validate(&manifest, &x.ax_public_key);
+        let result = app_manifest_signer::verify_manifest(&manifest, &x.ax_public_key);
         assert!(matches!(result, Ok(())), "valid signature");
     }
 
@@ -183,7 +302,7 @@ mod tests {
     fn should_fail_validation_when_using_wrong_ax_public_key() {
         let x = setup();
         let manifest = serde_json::from_value::<AppManifest>(x.serialized_manifest).unwrap();
-        let result = app_manifest_signer::validate(&manifest, &PrivateKey::generate().into()).unwrap_err();
+        let result = app_manifest_signer::verify_manifest(&manifest, &PrivateKey::generate().into()).unwrap_err();
         assert_eq!(
             result.to_string(),
             "Failed to validate developer certificate. Invalid signature for provided input."
@@ -202,7 +321,7 @@ mod tests {
         .for_each(|(from, to)| {
             let manifest: AppManifest =
                 serde_json::from_str(&x.serialized_manifest.to_string().replace(from, to)).unwrap();
-            let result = app_manifest_signer::validate(&manifest, &PrivateKey::generate().into()).unwrap_err();
+            let result = app_manifest_signer::verify_manifest(&manifest, &PrivateKey::generate().into()).unwrap_err();
             assert_eq!(
                 result.to_string(),
                 "Failed to validate developer certificate. Invalid signature for provided input."
@@ -210,6 +329,195 @@ mod tests {
         });
     }
 
+    #[test]
+    fn sign_and_verify() {
+        let x = setup();
+        let signed = app_manifest_signer::sign_manifest(
+            trial_manifest(app_id!("com.actyx.test-app")),
+            dev_private_key(),
+            dev_cert(&x).manifest_dev_cert(),
+        )
+        .unwrap();
+        let manifest: AppManifest = signed.into();
+        assert_eq!(manifest.app_id(), app_id!("com.actyx.test-app"));
+        assert_eq!(manifest.display_name(), "display name");
+        assert_eq!(manifest.version(), "version 0");
+        app_manifest_signer::verify_manifest(&manifest, &x.ax_public_key).unwrap();
+        // signing is deterministic, so the result matches what earlier versions produced
+        assert_eq!(serde_json::to_value(&manifest).unwrap(), x.serialized_manifest);
+    }
+
+    #[test]
+    fn signature_string_is_stable() {
+        let x = setup();
+        let signed = app_manifest_signer::sign_manifest(
+            trial_manifest(app_id!("com.actyx.test-app")),
+            dev_private_key(),
+            dev_cert(&x).manifest_dev_cert(),
+        )
+        .unwrap();
+        assert_eq!(signed.signature(), x.serialized_manifest["signature"].as_str().unwrap());
+
+        let serialized = serde_json::to_value(&signed).unwrap();
+        assert_eq!(serialized, x.serialized_manifest);
+        let deserialized: SignedAppManifest = serde_json::from_value(serialized).unwrap();
+        assert_eq!(deserialized, signed);
+        let signature = AppManifestSignature::from_str(deserialized.signature()).unwrap();
+        let reencoded: String = signature.try_into().unwrap();
+        assert_eq!(reencoded, signed.signature());
+    }
+
+    #[test]
+    fn signed_manifest_requires_signature() {
+        let json = serde_json::json!({
+            "appId": "com.example.test-app",
+            "displayName": "display name",
+            "version": "version 0"
+        });
+        let err = serde_json::from_value::<SignedAppManifest>(json).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "App manifest for 'com.example.test-app' is not signed."
+        );
+    }
+
+    #[test]
+    fn trial_manifest_ignores_previous_signature() {
+        let x = setup();
+        let manifest: TrialAppManifest = serde_json::from_value(x.serialized_manifest).unwrap();
+        assert_eq!(manifest, trial_manifest(app_id!("com.actyx.test-app")));
+    }
+
+    #[test]
+    fn should_fail_signing_outside_app_domains() {
+        let x = setup();
+        let err = app_manifest_signer::sign_manifest(
+            trial_manifest(app_id!("io.actyx.test-app")),
+            dev_private_key(),
+            dev_cert(&x).manifest_dev_cert(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AppId 'io.actyx.test-app' is not allowed in the app domains of the developer certificate: \
+             'com.actyx.*' requires the app id to start with 'com.actyx.' followed by a name"
+        );
+    }
+
+    #[test]
+    fn should_fail_signing_with_other_dev_key() {
+        let x = setup();
+        let err = app_manifest_signer::sign_manifest(
+            trial_manifest(app_id!("com.actyx.test-app")),
+            PrivateKey::generate(),
+            dev_cert(&x).manifest_dev_cert(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Developer private key does not match the public key '{}' of the developer certificate.",
+                PublicKey::from(dev_private_key())
+            )
+        );
+    }
+
+    #[test]
+    fn should_fail_verification_for_tampered_display_name() {
+        let x = setup();
+        let signed: AppManifest = app_manifest_signer::sign_manifest(
+            trial_manifest(app_id!("com.actyx.test-app")),
+            dev_private_key(),
+            dev_cert(&x).manifest_dev_cert(),
+        )
+        .unwrap()
+        .into();
+        let tampered = AppManifest::signed(
+            signed.app_id(),
+            "another name".into(),
+            signed.version().into(),
+            signed.signature().clone().unwrap(),
+        );
+        let err = app_manifest_signer::verify_manifest(&tampered, &x.ax_public_key).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to validate app manifest. Invalid signature for provided input."
+        );
+    }
+
+    fn revocations(x: &TestFixture, dev_keys: Vec<PublicKey>, app_ids: Vec<AppId>) -> SignedRevocationList {
+        SignedRevocationList::new(
+            x.ax_private_key,
+            dev_keys,
+            app_ids,
+            Utc::now() + Duration::days(1),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_with_revocations() {
+        let x = setup();
+        let manifest = serde_json::from_value::<AppManifest>(x.serialized_manifest.clone()).unwrap();
+        let dev_key = AppManifestSignature::from_str(manifest.signature().unwrap())
+            .unwrap()
+            .dev_cert
+            .dev_public_key();
+
+        let list = revocations(
+            &x,
+            vec![PrivateKey::generate().into()],
+            vec![app_id!("com.actyx.other-app")],
+        );
+        app_manifest_signer::validate_with_revocations(&manifest, &x.ax_public_key, &list).unwrap();
+
+        let list = revocations(&x, vec![dev_key], vec![]);
+        let err = app_manifest_signer::validate_with_revocations(&manifest, &x.ax_public_key, &list).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<RevocationError>(), Some(RevocationError::DevKeyRevoked(k)) if *k == dev_key),
+            "{}",
+            err
+        );
+
+        let list = revocations(&x, vec![], vec![manifest.app_id()]);
+        let err = app_manifest_signer::validate_with_revocations(&manifest, &x.ax_public_key, &list).unwrap_err();
+        assert_eq!(err.to_string(), "AppId 'com.actyx.test-app' has been revoked.");
+
+        // a trial manifest can only be revoked by its app id
+        let trial = AppManifest::trial(app_id!("com.example.test-app"), "trial".into(), "1".into()).unwrap();
+        let list = revocations(&x, vec![dev_key], vec![]);
+        app_manifest_signer::validate_with_revocations(&trial, &x.ax_public_key, &list).unwrap();
+        let list = revocations(&x, vec![], vec![trial.app_id()]);
+        app_manifest_signer::validate_with_revocations(&trial, &x.ax_public_key, &list).unwrap_err();
+    }
+
+    #[test]
+    fn validate_with_revocations_requires_valid_list() {
+        let x = setup();
+        let manifest = serde_json::from_value::<AppManifest>(x.serialized_manifest).unwrap();
+        let list = SignedRevocationList::new(
+            PrivateKey::generate(),
+            vec![],
+            vec![],
+            Utc::now() + Duration::days(1),
+            None,
+        )
+        .unwrap();
+        let err = app_manifest_signer::validate_with_revocations(&manifest, &x.ax_public_key, &list).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to validate revocation list. Invalid signature for provided input."
+        );
+
+        let list =
+            SignedRevocationList::new(x.ax_private_key, vec![], vec![], Utc::now() - Duration::days(1), None).unwrap();
+        let err = app_manifest_signer::validate_with_revocations(&manifest, &x.ax_public_key, &list).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Failed to validate revocation list. Revocation list expired at"));
+    }
+
     #[test]
     fn test_app_manifest_signature_version_is_0() {
         let private = PrivateKey::generate();
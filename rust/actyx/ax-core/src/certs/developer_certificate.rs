@@ -4,8 +4,7 @@ use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::certs::{app_domain::AppDomain, signature::Signature};
 
-#[derive(Debug, derive_more::Display, derive_more::Error)]
-#[display(fmt = "AppId '{}' is not allowed in app_domains '{:?}'", app_id, app_domains)]
+#[derive(Debug, derive_more::Error)]
 pub struct InvalidAppId {
     app_id: AppId,
     app_domains: Vec<AppDomain>,
@@ -17,6 +16,25 @@ impl InvalidAppId {
     }
 }
 
+impl std::fmt::Display for InvalidAppId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AppId '{}' is not allowed in the app domains of the developer certificate: ",
+            self.app_id
+        )?;
+        if self.app_domains.is_empty() {
+            return write!(f, "it has no app domains");
+        }
+        let reasons = self
+            .app_domains
+            .iter()
+            .filter_map(|domain| domain.mismatch(&self.app_id))
+            .collect::<Vec<_>>();
+        write!(f, "{}", reasons.join("; "))
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct DeveloperCertificateInput {
@@ -90,6 +108,21 @@ pub struct DeveloperCertificate {
 }
 
 impl DeveloperCertificate {
+    /// Certify the developer owning `dev_pubkey` for the given app domains, signed with the Actyx
+    /// private key.
+    ///
+    /// The certificate does not contain the developer's private key, it has to be passed
+    /// separately when signing a manifest with
+    /// [`sign_manifest`](crate::certs::app_manifest_signer::sign_manifest).
+    pub fn create(dev_pubkey: PublicKey, app_domains: Vec<AppDomain>, ax_privkey: PrivateKey) -> anyhow::Result<Self> {
+        let input = DeveloperCertificateInput::new(dev_pubkey, app_domains);
+        let manifest_dev_cert = ManifestDeveloperCertificate::new(input, ax_privkey)?;
+        Ok(Self {
+            dev_privkey: None,
+            manifest_dev_cert,
+        })
+    }
+
     pub fn new(dev_privkey: PrivateKey, app_domains: Vec<AppDomain>, ax_privkey: PrivateKey) -> anyhow::Result<Self> {
         let input = DeveloperCertificateInput::new(dev_privkey.into(), app_domains);
         let manifest_dev_cert = ManifestDeveloperCertificate::new(input, ax_privkey)?;
@@ -199,7 +232,34 @@ mod tests {
             .unwrap_or_else(|| panic!("Found wrong error: {}", err));
         assert_eq!(
             err.to_string(),
-            "AppId \'com.example.test-app\' is not allowed in app_domains \'[AppDomain(\"com.actyx.*\")]\'"
+            "AppId 'com.example.test-app' is not allowed in the app domains of the developer certificate: \
+             'com.actyx.*' requires the app id to start with 'com.actyx.' followed by a name"
+        );
+    }
+
+    #[test]
+    fn validate_app_id_failure_names_every_domain() {
+        let x = setup();
+        let input = DeveloperCertificateInput::new(
+            x.dev_public_key,
+            vec!["com.actyx.*".parse().unwrap(), "io.actyx.*".parse().unwrap()],
+        );
+        let dev_cert = ManifestDeveloperCertificate::new(input, x.ax_private_key).unwrap();
+        let err = dev_cert.validate_app_id(&app_id!("com.actyx")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AppId 'com.actyx' is not allowed in the app domains of the developer certificate: \
+             'com.actyx.*' requires the app id to start with 'com.actyx.' followed by a name; \
+             'io.actyx.*' requires the app id to start with 'io.actyx.' followed by a name"
+        );
+
+        let input = DeveloperCertificateInput::new(x.dev_public_key, vec![]);
+        let dev_cert = ManifestDeveloperCertificate::new(input, x.ax_private_key).unwrap();
+        let err = dev_cert.validate_app_id(&app_id!("com.actyx.test-app")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AppId 'com.actyx.test-app' is not allowed in the app domains of the developer certificate: \
+             it has no app domains"
         );
     }
 
@@ -244,6 +304,16 @@ mod tests {
         assert_eq!(dev_cert, expected_dev_cert);
     }
 
+    #[test]
+    fn create_from_public_key() {
+        let x = setup();
+        let dev_cert = DeveloperCertificate::create(x.dev_public_key, x.app_domains, x.ax_private_key).unwrap();
+        assert_eq!(dev_cert.private_key(), None);
+        let manifest_dev_cert = dev_cert.manifest_dev_cert();
+        manifest_dev_cert.validate(&x.ax_public_key).unwrap();
+        assert_eq!(serde_json::to_value(manifest_dev_cert).unwrap(), x.manifest_dev_cert);
+    }
+
     #[test]
     fn deserialize_developer_certificate_to_manifest_developer_cert() {
         let x = setup();
@@ -2,12 +2,19 @@ mod app_domain;
 mod app_license;
 mod app_manifest;
 mod developer_certificate;
+mod revocation_list;
 mod signature;
 
 pub use app_domain::AppDomain;
-pub use app_license::{AppLicense, AppLicenseType, Expiring, RequesterInfo, SignedAppLicense};
-pub use app_manifest::{app_manifest_signer, AppManifestSignature, AppManifestSignatureProps};
+pub use app_license::{
+    AppLicense, AppLicenseType, Expiring, InvalidLicense, LicenseCheckOutcome, LicenseTolerance, RequesterInfo,
+    SignedAppLicense,
+};
+pub use app_manifest::{
+    app_manifest_signer, AppManifestSignature, AppManifestSignatureProps, SignedAppManifest, TrialAppManifest,
+};
 pub use developer_certificate::{DeveloperCertificate, DeveloperCertificateInput, ManifestDeveloperCertificate};
+pub use revocation_list::{RevocationError, RevocationList, SignedRevocationList};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,226 @@
+use std::str::FromStr;
+
+use crate::crypto::{PrivateKey, PublicKey};
+use anyhow::Context;
+use ax_types::AppId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::certs::signature::Signature;
+
+/// The newest list version this node understands.
+///
+/// Fields may only be added together with a new version, since the signature covers the fields
+/// known to the signer, which an older node could not reproduce. Optional fields that are left out
+/// when empty are the exception, lists without them serialize as before.
+const REVOCATION_LIST_VERSION: u8 = 0;
+
+#[derive(Debug, Clone, derive_more::Display, derive_more::Error)]
+pub enum RevocationError {
+    #[display(fmt = "Developer certificate for key '{}' has been revoked.", _0)]
+    DevKeyRevoked(#[error(ignore)] PublicKey),
+    #[display(fmt = "AppId '{}' has been revoked.", _0)]
+    AppIdRevoked(#[error(ignore)] AppId),
+    #[display(fmt = "Revocation list expired at {}.", _0)]
+    Expired(#[error(ignore)] DateTime<Utc>),
+    #[display(fmt = "Revocation list version {} is not supported.", _0)]
+    UnsupportedVersion(#[error(ignore)] u8),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RevocationList {
+    list_version: u8,
+    /// developer keys whose certificates must no longer be accepted, for any app id
+    pub revoked_dev_keys: Vec<PublicKey>,
+    /// app ids that must no longer be accepted, whichever developer signed them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revoked_app_ids: Vec<AppId>,
+    /// when it was created
+    pub created_at: DateTime<Utc>,
+    /// after this the list must be replaced by a newer one, so that it cannot be replayed to
+    /// hide later revocations
+    pub valid_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedRevocationList {
+    #[serde(flatten)]
+    pub list: RevocationList,
+    signature: Signature,
+}
+
+impl SignedRevocationList {
+    pub fn new(
+        ax_private_key: PrivateKey,
+        revoked_dev_keys: Vec<PublicKey>,
+        revoked_app_ids: Vec<AppId>,
+        valid_until: DateTime<Utc>,
+        created_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Self> {
+        let list = RevocationList {
+            list_version: REVOCATION_LIST_VERSION,
+            revoked_dev_keys,
+            revoked_app_ids,
+            created_at: created_at.unwrap_or_else(Utc::now),
+            valid_until,
+        };
+        let signature = Signature::new(&list, ax_private_key)?;
+        Ok(Self { list, signature })
+    }
+
+    /// Check that the list was signed with the ax key and is still valid.
+    pub fn validate(&self, ax_public_key: &PublicKey) -> anyhow::Result<()> {
+        if self.list.list_version > REVOCATION_LIST_VERSION {
+            return Err(RevocationError::UnsupportedVersion(self.list.list_version).into());
+        }
+        self.signature.verify(&self.list, ax_public_key)?;
+        if self.list.valid_until < Utc::now() {
+            return Err(RevocationError::Expired(self.list.valid_until).into());
+        }
+        Ok(())
+    }
+
+    /// Check a developer key and app id against the list, without validating the list itself.
+    ///
+    /// Trial manifests have no developer key, so only their app id is checked.
+    pub fn check(&self, dev_public_key: Option<&PublicKey>, app_id: &AppId) -> Result<(), RevocationError> {
+        if let Some(key) = dev_public_key.filter(|key| self.list.revoked_dev_keys.contains(key)) {
+            Err(RevocationError::DevKeyRevoked(*key))
+        } else if self.list.revoked_app_ids.contains(app_id) {
+            Err(RevocationError::AppIdRevoked(app_id.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn to_base64(&self) -> anyhow::Result<String> {
+        let bytes = serde_cbor::to_vec(&self)?;
+        Ok(base64::encode(bytes))
+    }
+}
+
+impl FromStr for SignedRevocationList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = base64::decode(s).context("Failed to base64 decode revocation list")?;
+        serde_cbor::from_slice::<SignedRevocationList>(&data).context("Failed to deserialize to revocation list")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::{PrivateKey, PublicKey};
+    use chrono::{TimeZone, Utc};
+
+    use crate::certs::{
+        revocation_list::{RevocationError, SignedRevocationList},
+        signature::InvalidSignature,
+    };
+
+    #[test]
+    fn roundtrip_and_validate() {
+        let ax_private_key = PrivateKey::generate();
+        let dev_key: PublicKey = PrivateKey::generate().into();
+        let list = SignedRevocationList::new(
+            ax_private_key,
+            vec![dev_key],
+            vec![ax_types::app_id!("com.actyx.revoked")],
+            Utc::now() + chrono::Duration::days(1),
+            None,
+        )
+        .unwrap();
+
+        let json: SignedRevocationList = serde_json::from_value(serde_json::to_value(&list).unwrap()).unwrap();
+        assert_eq!(json, list);
+        let cbor: SignedRevocationList = list.to_base64().unwrap().parse().unwrap();
+        assert_eq!(cbor, list);
+        cbor.validate(&ax_private_key.into()).unwrap();
+    }
+
+    #[test]
+    fn lists_without_app_ids_can_be_read() {
+        let ax_private_key = PrivateKey::generate();
+        let list = SignedRevocationList::new(
+            ax_private_key,
+            vec![],
+            vec![],
+            Utc::now() + chrono::Duration::days(1),
+            None,
+        )
+        .unwrap();
+        let json = serde_json::to_value(&list).unwrap();
+        assert_eq!(json["listVersion"], 0);
+        // written by a signer that does not know the field
+        assert_eq!(json.get("revokedAppIds"), None);
+        let parsed: SignedRevocationList = serde_json::from_value(json).unwrap();
+        parsed.validate(&ax_private_key.into()).unwrap();
+        let cbor: SignedRevocationList = parsed.to_base64().unwrap().parse().unwrap();
+        cbor.validate(&ax_private_key.into()).unwrap();
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        let ax_private_key = PrivateKey::generate();
+        let list = SignedRevocationList::new(
+            ax_private_key,
+            vec![],
+            vec![],
+            Utc::now() + chrono::Duration::days(1),
+            None,
+        )
+        .unwrap();
+        let mut json = serde_json::to_value(&list).unwrap();
+        json["listVersion"] = serde_json::json!(1);
+        let newer: SignedRevocationList = serde_json::from_value(json).unwrap();
+        let err = newer.validate(&ax_private_key.into()).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<RevocationError>(),
+                Some(RevocationError::UnsupportedVersion(1))
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn reject_expired_list() {
+        let ax_private_key = PrivateKey::generate();
+        let valid_until = Utc.with_ymd_and_hms(1971, 1, 1, 0, 0, 0).single().unwrap();
+        let list = SignedRevocationList::new(ax_private_key, vec![], vec![], valid_until, None).unwrap();
+        let err = list.validate(&ax_private_key.into()).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<RevocationError>(), Some(RevocationError::Expired(t)) if *t == valid_until),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn reject_tampered_list() {
+        let ax_private_key = PrivateKey::generate();
+        let dev_key: PublicKey = PrivateKey::generate().into();
+        let list = SignedRevocationList::new(
+            ax_private_key,
+            vec![dev_key],
+            vec![],
+            Utc::now() + chrono::Duration::days(1),
+            None,
+        )
+        .unwrap();
+        let mut json = serde_json::to_value(&list).unwrap();
+        json["revokedDevKeys"] = serde_json::json!([]);
+        let tampered: SignedRevocationList = serde_json::from_value(json).unwrap();
+        let err = tampered.validate(&ax_private_key.into()).unwrap_err();
+        err.downcast_ref::<InvalidSignature>()
+            .unwrap_or_else(|| panic!("Found wrong error: {}", err));
+
+        let other_key = PrivateKey::generate();
+        let err = list.validate(&other_key.into()).unwrap_err();
+        err.downcast_ref::<InvalidSignature>()
+            .unwrap_or_else(|| panic!("Found wrong error: {}", err));
+    }
+}
@@ -1,14 +1,15 @@
 use super::{
+    inflight::{Inflight, InflightGuard},
     protocol::{RequestId, StreamingResponseConfig, StreamingResponseMessage},
     protocol_v2::{self, upgrade_inbound, upgrade_outbound, ProtocolError},
     upgrade::{from_fn, FromFnUpgrade},
-    Codec, SequenceNo,
+    Codec, ProtocolVersion, SequenceNo, ViolationHandler,
 };
 use futures::{
     channel::{mpsc, oneshot},
     future::{ready, select, BoxFuture, Either, Ready},
     stream::FuturesUnordered,
-    AsyncWriteExt, FutureExt, SinkExt, StreamExt,
+    AsyncWriteExt, FutureExt, SinkExt, StreamExt, TryFutureExt,
 };
 use libp2p::{
     core::{ConnectedPoint, Endpoint, UpgradeError},
@@ -36,6 +37,19 @@ pub enum Response<T> {
     Msg(T),
     Error(ProtocolError),
     Finished,
+    /// The request was given up on by this side, the substream has been closed
+    Cancelled(CancellationReason),
+}
+
+/// Why a request was cancelled locally before the response stream was finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum CancellationReason {
+    /// No response frame arrived within the configured response timeout
+    #[display(fmt = "no response received within the response timeout")]
+    Timeout,
+    /// The request was not sent because the configured number of requests is already in flight
+    #[display(fmt = "too many requests in flight to this peer")]
+    TooManyRequests,
 }
 
 impl<T> Response<T> {
@@ -44,6 +58,10 @@ impl<T> Response<T> {
             Response::Msg(msg) => Ok(msg),
             Response::Error(e) => Err(e),
             Response::Finished => Err(ProtocolError::Io(ErrorKind::UnexpectedEof.into())),
+            Response::Cancelled(CancellationReason::Timeout) => Err(ProtocolError::Io(ErrorKind::TimedOut.into())),
+            Response::Cancelled(CancellationReason::TooManyRequests) => {
+                Err(ProtocolError::Io(ErrorKind::WouldBlock.into()))
+            }
         }
     }
 }
@@ -51,6 +69,7 @@ impl<T> Response<T> {
 pub struct Request<T: Codec> {
     request: ManuallyDrop<T::Request>,
     channel: ManuallyDrop<mpsc::Sender<Response<T::Response>>>,
+    guard: Option<InflightGuard>,
 }
 
 impl<T: Codec> Drop for Request<T> {
@@ -68,18 +87,24 @@ impl<T: Codec> Drop for Request<T> {
 }
 
 impl<T: Codec> Request<T> {
-    pub fn new(request: T::Request, channel: mpsc::Sender<Response<T::Response>>) -> Self {
+    pub fn new(
+        request: T::Request,
+        channel: mpsc::Sender<Response<T::Response>>,
+        guard: Option<InflightGuard>,
+    ) -> Self {
         Self {
             request: ManuallyDrop::new(request),
             channel: ManuallyDrop::new(channel),
+            guard,
         }
     }
 
-    pub fn into_inner(mut self) -> (T::Request, mpsc::Sender<Response<T::Response>>) {
+    pub fn into_inner(mut self) -> (T::Request, mpsc::Sender<Response<T::Response>>, Option<InflightGuard>) {
         let ret = unsafe {
             (
                 ManuallyDrop::take(&mut self.request),
                 ManuallyDrop::take(&mut self.channel),
+                self.guard.take(),
             )
         };
         forget(self);
@@ -96,21 +121,43 @@ impl<T: Codec> Debug for Request<T> {
 pub struct RequestReceived<T: Codec> {
     pub(crate) request: T::Request,
     pub(crate) channel: mpsc::Sender<T::Response>,
+    pub(crate) protocol: ProtocolVersion,
 }
 
 impl<T: Codec> Debug for RequestReceived<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RequestReceived")
             .field("request", &self.request)
+            .field("protocol", &self.protocol)
             .finish()
     }
 }
 
+pub enum HandlerEvent<T: Codec> {
+    RequestReceived(RequestReceived<T>),
+    /// A response frame of `size` bytes was not sent and the response stream ended with an error
+    ResponseTooLarge {
+        size: usize,
+    },
+}
+
+impl<T: Codec> Debug for HandlerEvent<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RequestReceived(r) => f.debug_tuple("RequestReceived").field(r).finish(),
+            Self::ResponseTooLarge { size } => f.debug_struct("ResponseTooLarge").field("size", size).finish(),
+        }
+    }
+}
+
 pub struct IntoHandler<T> {
     max_message_size: u32,
     request_timeout: Duration,
+    response_timeout: Option<Duration>,
     response_send_buffer_size: usize,
     keep_alive: bool,
+    on_violation: Option<ViolationHandler>,
+    inbound_limit: Option<(Inflight, usize)>,
     _ph: PhantomData<T>,
 }
 
@@ -118,14 +165,20 @@ impl<T> IntoHandler<T> {
     pub fn new(
         max_message_size: u32,
         request_timeout: Duration,
+        response_timeout: Option<Duration>,
         response_send_buffer_size: usize,
         keep_alive: bool,
+        on_violation: Option<ViolationHandler>,
+        inbound_limit: Option<(Inflight, usize)>,
     ) -> Self {
         Self {
             max_message_size,
             request_timeout,
+            response_timeout,
             response_send_buffer_size,
             keep_alive,
+            on_violation,
+            inbound_limit,
             _ph: PhantomData,
         }
     }
@@ -134,13 +187,19 @@ impl<T> IntoHandler<T> {
 impl<T: Codec + Send + 'static> IntoConnectionHandler for IntoHandler<T> {
     type Handler = Handler<T>;
 
-    fn into_handler(self, _remote_peer_id: &PeerId, _connected_point: &ConnectedPoint) -> Self::Handler {
-        Handler::new(
+    fn into_handler(self, remote_peer_id: &PeerId, _connected_point: &ConnectedPoint) -> Self::Handler {
+        let mut handler = Handler::new(
             self.max_message_size,
             self.request_timeout,
+            self.response_timeout,
             self.response_send_buffer_size,
             self.keep_alive,
-        )
+        );
+        handler.on_violation = self.on_violation.map(|f| (*remote_peer_id, f));
+        handler.inbound_limit = self
+            .inbound_limit
+            .map(|(inflight, max)| (*remote_peer_id, inflight, max));
+        handler
     }
 
     fn inbound_protocol(&self) -> <Self::Handler as ConnectionHandler>::InboundProtocol {
@@ -167,7 +226,7 @@ type Upgrade = FromFnUpgrade<
 type ProtocolEvent<T> = ConnectionHandlerEvent<
     Upgrade,
     <Handler<T> as ConnectionHandler>::OutboundOpenInfo,
-    RequestReceived<T>,
+    HandlerEvent<T>,
     ProtocolError,
 >;
 pub type ResponseFuture = BoxFuture<'static, Result<(), ProtocolError>>;
@@ -175,10 +234,10 @@ pub type ResponseFuture = BoxFuture<'static, Result<(), ProtocolError>>;
 pub struct Handler<T: Codec + Send + 'static> {
     events: VecDeque<ProtocolEvent<T>>,
     streams: FuturesUnordered<ResponseFuture>,
-    inbound_v2: FuturesUnordered<BoxFuture<'static, Result<(T::Request, NegotiatedSubstream), ProtocolError>>>,
+    inbound_v2: FuturesUnordered<BoxFuture<'static, Result<InboundV2<T>, ProtocolError>>>,
     inbound_v1: FuturesUnordered<<StreamingResponseConfig<T> as InboundUpgradeSend>::Future>,
     outbound_v1: FuturesUnordered<BoxFuture<'static, (RequestId, Result<(), ProtocolError>)>>,
-    responses_v1: BTreeMap<RequestId, mpsc::Sender<Response<T::Response>>>,
+    responses_v1: BTreeMap<RequestId, (mpsc::Sender<Response<T::Response>>, Option<InflightGuard>)>,
     // cancellations coming from the peer, so NOT OUR REQUEST_IDs!
     cancel_v1: BTreeMap<RequestId, oneshot::Sender<()>>,
     v1_tx: mpsc::Sender<ProtocolEvent<T>>,
@@ -186,12 +245,17 @@ pub struct Handler<T: Codec + Send + 'static> {
     req_id: RequestId,
     max_message_size: u32,
     request_timeout: Duration,
+    response_timeout: Option<Duration>,
     response_send_buffer_size: usize,
     keep_alive: bool,
     v1_dialling: HashSet<RequestId>,
     v1_queue: Vec<(Upgrade, StreamingResponseMessage<T>)>,
+    on_violation: Option<(PeerId, ViolationHandler)>,
+    inbound_limit: Option<(PeerId, Inflight, usize)>,
 }
 
+type InboundV2<T> = (<T as Codec>::Request, NegotiatedSubstream, Option<InflightGuard>);
+
 impl<T: Codec + Send + 'static> Debug for Handler<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Handler")
@@ -202,9 +266,19 @@ impl<T: Codec + Send + 'static> Debug for Handler<T> {
 }
 
 impl<T: Codec + Send + 'static> Handler<T> {
+    /// The channel handed out with a received request, bounded to the configured size.
+    ///
+    /// The send loops only take a response out once the previous one has been accepted by the
+    /// substream, so a slow peer makes `send` on the channel wait.
+    fn response_channel(&self) -> (mpsc::Sender<T::Response>, mpsc::Receiver<T::Response>) {
+        // each sender has a guaranteed slot on top of the buffer
+        mpsc::channel(self.response_send_buffer_size.saturating_sub(1))
+    }
+
     pub fn new(
         max_message_size: u32,
         request_timeout: Duration,
+        response_timeout: Option<Duration>,
         response_send_buffer_size: usize,
         keep_alive: bool,
     ) -> Self {
@@ -222,22 +296,25 @@ impl<T: Codec + Send + 'static> Handler<T> {
             req_id: RequestId::default(),
             max_message_size,
             request_timeout,
+            response_timeout,
             response_send_buffer_size,
             keep_alive,
             v1_dialling: HashSet::new(),
             v1_queue: vec![],
+            on_violation: None,
+            inbound_limit: None,
         }
     }
 }
 
 pub enum OutboundInfo<T: Codec> {
     V1(StreamingResponseMessage<T>),
-    V2(T::Request, mpsc::Sender<Response<T::Response>>),
+    V2(T::Request, mpsc::Sender<Response<T::Response>>, Option<InflightGuard>),
 }
 
 impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
     type InEvent = Request<T>;
-    type OutEvent = RequestReceived<T>;
+    type OutEvent = HandlerEvent<T>;
     type Error = ProtocolError;
     type InboundProtocol = Upgrade;
     type OutboundProtocol = Upgrade;
@@ -256,9 +333,22 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
         let (stream, proto) = protocol;
         tracing::trace!("handler received request for protocol {}", proto);
         if T::info_v2().contains(&proto) {
+            let guard = match &self.inbound_limit {
+                Some((peer_id, inflight, max)) => match inflight.acquire(*peer_id, *max) {
+                    Some(guard) => Some(guard),
+                    None => {
+                        tracing::debug!("{} requests in flight from {}, dropping substream", max, peer_id);
+                        return;
+                    }
+                },
+                None => None,
+            };
             // use the new stream-based approach
-            self.inbound_v2
-                .push(upgrade_inbound::<T>(self.max_message_size, stream, proto).boxed());
+            self.inbound_v2.push(
+                upgrade_inbound::<T>(self.max_message_size, stream, proto)
+                    .map_ok(move |(request, stream)| (request, stream, guard))
+                    .boxed(),
+            );
         } else if proto == T::info_v1() {
             // fall back to OneShot-based approach
             self.inbound_v1
@@ -296,10 +386,13 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                     .boxed(),
                 );
             }
-            OutboundInfo::V2(request, mut tx) if T::info_v2().contains(&proto) => {
+            OutboundInfo::V2(request, mut tx, guard) if T::info_v2().contains(&proto) => {
                 let max_message_size = self.max_message_size;
+                let response_timeout = self.response_timeout;
                 self.streams.push(
                     async move {
+                        // released before the final response so that the next request can go out right away
+                        let mut guard = guard;
                         let result = upgrade_outbound::<T>(max_message_size, request, stream, proto).await;
                         let mut stream = match result {
                             Ok(stream) => stream,
@@ -312,34 +405,49 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                         tracing::trace!("starting receive loop for protocol `{}`", proto);
                         let mut buffer = Vec::new();
                         loop {
-                            match protocol_v2::read_msg(&mut stream, max_message_size, &mut buffer)
-                                .await
-                                .unwrap_or_else(Response::Error)
-                            {
+                            // the timer is restarted for every frame, so only a silent peer is cut off
+                            let next = protocol_v2::read_msg(&mut stream, max_message_size, &mut buffer);
+                            let next = match response_timeout {
+                                Some(timeout) => match tokio::time::timeout(timeout, next).await {
+                                    Ok(next) => next,
+                                    Err(_) => {
+                                        tracing::debug!("no response frame within {:?}, closing substream", timeout);
+                                        stream.close().await.ok();
+                                        drop(guard.take());
+                                        tx.feed(Response::Cancelled(CancellationReason::Timeout)).await?;
+                                        return Ok(());
+                                    }
+                                },
+                                None => next.await,
+                            };
+                            match next.unwrap_or_else(Response::Error) {
                                 Response::Msg(msg) => {
                                     tx.feed(Response::Msg(msg)).await?;
                                     tracing::trace!("response sent to client code");
                                 }
                                 Response::Error(e) => {
                                     tracing::debug!("sending substream error {}", e);
+                                    drop(guard.take());
                                     tx.feed(Response::Error(e)).await?;
                                     return Ok(());
                                 }
                                 Response::Finished => {
                                     tracing::trace!("finishing substream");
+                                    drop(guard.take());
                                     tx.feed(Response::Finished).await?;
                                     return Ok(());
                                 }
+                                Response::Cancelled(_) => unreachable!("read_msg never cancels"),
                             }
                         }
                     }
                     .boxed(),
                 );
             }
-            OutboundInfo::V2(request, tx) if proto == T::info_v1() => {
+            OutboundInfo::V2(request, tx, guard) if proto == T::info_v1() => {
                 let request_id = self.req_id;
                 self.req_id.increment();
-                self.responses_v1.insert(request_id, tx);
+                self.responses_v1.insert(request_id, (tx, guard));
                 self.outbound_v1.push(
                     StreamingResponseMessage::<T>::Request {
                         id: request_id,
@@ -350,7 +458,7 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                     .boxed(),
                 )
             }
-            OutboundInfo::V2(_, _) => {
+            OutboundInfo::V2(..) => {
                 tracing::error!(
                     "inbound negotiation result `{}` is not among supported protocols [{:?}, {}], dropping stream",
                     proto,
@@ -362,10 +470,10 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
     }
 
     fn inject_event(&mut self, command: Self::InEvent) {
-        let (request, channel) = command.into_inner();
+        let (request, channel, guard) = command.into_inner();
         tracing::trace!("requesting {:?}", request);
         self.events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
-            protocol: SubstreamProtocol::new(upgrade::<T>(false), OutboundInfo::V2(request, channel))
+            protocol: SubstreamProtocol::new(upgrade::<T>(false), OutboundInfo::V2(request, channel, guard))
                 .with_timeout(self.request_timeout),
         })
     }
@@ -385,7 +493,7 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
             ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(e)) => e.into(),
         };
         tracing::debug!("dial upgrade error: {}", error);
-        if let OutboundInfo::V2(_, mut tx) = info {
+        if let OutboundInfo::V2(_, mut tx, _) = info {
             if let Err(Response::Error(e)) = tx.try_send(Response::Error(error)).map_err(|e| e.into_inner()) {
                 tracing::warn!("cannot send upgrade error to requester: {}", e);
             }
@@ -406,8 +514,8 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                 break;
             };
             match result {
-                Ok((request, mut stream)) => {
-                    let (channel, mut rx) = mpsc::channel(self.response_send_buffer_size);
+                Ok((request, mut stream, guard)) => {
+                    let (channel, mut rx) = self.response_channel();
                     let max_message_size = self.max_message_size;
                     self.streams.push(
                         async move {
@@ -429,6 +537,8 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                                 };
                                 protocol_v2::write_msg(&mut stream, response, max_message_size, &mut buffer).await?;
                             }
+                            // free the slot before the requester can learn that this request is done
+                            drop(guard);
                             tracing::trace!("flushing and closing substream");
                             protocol_v2::write_finish(&mut stream).await?;
                             Ok(())
@@ -436,9 +546,20 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                         .boxed(),
                     );
                     self.events
-                        .push_back(ConnectionHandlerEvent::Custom(RequestReceived { request, channel }));
+                        .push_back(ConnectionHandlerEvent::Custom(HandlerEvent::RequestReceived(
+                            RequestReceived {
+                                request,
+                                channel,
+                                protocol: ProtocolVersion::V2,
+                            },
+                        )));
+                }
+                Err(err) => {
+                    tracing::debug!("inbound upgrade error for protocol `{:?}`: {}", T::info_v2(), err);
+                    if let Some((peer_id, on_violation)) = self.on_violation.as_ref().filter(|_| err.is_violation()) {
+                        on_violation(*peer_id, &err);
+                    }
                 }
-                Err(err) => tracing::debug!("inbound upgrade error for protocol `{:?}`: {}", T::info_v2(), err),
             }
         }
 
@@ -450,7 +571,7 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                 Ok(request) => match request {
                     StreamingResponseMessage::Request { id, payload } => {
                         let mut tx = self.v1_tx.clone();
-                        let (channel, mut rx) = mpsc::channel(self.response_send_buffer_size);
+                        let (channel, mut rx) = self.response_channel();
                         let (cancel_tx, mut cancel_rx) = oneshot::channel();
                         self.cancel_v1.insert(id, cancel_tx);
                         self.streams.push(
@@ -482,10 +603,14 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                             }
                             .boxed(),
                         );
-                        self.events.push_back(ConnectionHandlerEvent::Custom(RequestReceived {
-                            request: payload,
-                            channel,
-                        }));
+                        self.events
+                            .push_back(ConnectionHandlerEvent::Custom(HandlerEvent::RequestReceived(
+                                RequestReceived {
+                                    request: payload,
+                                    channel,
+                                    protocol: ProtocolVersion::V1,
+                                },
+                            )));
                     }
                     StreamingResponseMessage::CancelRequest { id } => {
                         if let Some(tx) = self.cancel_v1.remove(&id) {
@@ -495,7 +620,7 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                         }
                     }
                     StreamingResponseMessage::Response { id, seq_no: _, payload } => {
-                        if let Some(tx) = self.responses_v1.get_mut(&id) {
+                        if let Some((tx, _)) = self.responses_v1.get_mut(&id) {
                             if let Err(err) = tx.try_send(Response::Msg(payload)) {
                                 if err.is_disconnected() {
                                     self.events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
@@ -513,7 +638,7 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                         }
                     }
                     StreamingResponseMessage::ResponseEnd { id, seq_no: _ } => {
-                        if let Some(mut tx) = self.responses_v1.remove(&id) {
+                        if let Some((mut tx, _)) = self.responses_v1.remove(&id) {
                             if let Err(err) = tx.try_send(Response::Finished) {
                                 tracing::warn!("`{}` dropping response end: {}", T::info_v1(), err);
                             }
@@ -526,7 +651,12 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
             }
         }
 
-        while let Poll::Ready(Some(msg)) = self.v1_rx.poll_next_unpin(cx) {
+        // v1 responses wait here for the previous substream of their request, so only take on more
+        // while there is room; otherwise the send loops block and with them the response channels
+        while self.v1_queue.len() < self.response_send_buffer_size {
+            let Poll::Ready(Some(msg)) = self.v1_rx.poll_next_unpin(cx) else {
+                break;
+            };
             self.events.push_back(msg);
         }
 
@@ -572,7 +702,7 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
             };
             if let Err(e) = result {
                 tracing::debug!("error in v1 substream task: {}", e);
-                if let Some(mut tx) = self.responses_v1.remove(&request_id) {
+                if let Some((mut tx, _)) = self.responses_v1.remove(&request_id) {
                     tx.try_send(Response::Error(e)).ok();
                 }
             }
@@ -584,13 +714,23 @@ impl<T: Codec + Send + 'static> ConnectionHandler for Handler<T> {
                 break;
             };
             some_finished = true;
-            if let Err(e) = result {
-                tracing::debug!("error in substream task: {}", e);
+            match result {
+                Err(ProtocolError::MessageTooLargeSent(size)) => {
+                    tracing::debug!("response of {} bytes too large, substream closed", size);
+                    self.events
+                        .push_back(ConnectionHandlerEvent::Custom(HandlerEvent::ResponseTooLarge { size }));
+                }
+                Err(e) => tracing::debug!("error in substream task: {}", e),
+                Ok(()) => {}
             }
         }
         if some_finished {
             self.cancel_v1.retain(|_k, v| !v.is_canceled());
         }
+        if !self.events.is_empty() {
+            // events produced above are emitted on the next round
+            cx.waker().wake_by_ref();
+        }
 
         Poll::Pending
     }
@@ -0,0 +1,76 @@
+//! Bookkeeping for the per-peer cap on concurrent requests.
+//!
+//! A slot is held by an [`InflightGuard`] for as long as the request is being served, i.e. until
+//! its response stream is finished or the guard is dropped together with the connection handler.
+use fnv::FnvHashMap;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Number of requests in flight per peer, shared between the behaviour and its handlers
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Inflight(Arc<Mutex<FnvHashMap<PeerId, usize>>>);
+
+impl Inflight {
+    /// Take a slot for the peer unless `max` slots are already taken
+    pub fn acquire(&self, peer: PeerId, max: usize) -> Option<InflightGuard> {
+        let mut counts = self.0.lock();
+        let count = counts.entry(peer).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(InflightGuard {
+            inflight: self.clone(),
+            peer,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, peer: &PeerId) -> usize {
+        self.0.lock().get(peer).copied().unwrap_or_default()
+    }
+}
+
+/// Releases its slot when dropped
+#[derive(Debug)]
+pub(crate) struct InflightGuard {
+    inflight: Inflight,
+    peer: PeerId,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        let mut counts = self.inflight.0.lock();
+        if let Some(count) = counts.get_mut(&self.peer) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.peer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_per_peer() {
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let inflight = Inflight::default();
+        let a1 = inflight.acquire(a, 2).unwrap();
+        let a2 = inflight.acquire(a, 2).unwrap();
+        assert!(inflight.acquire(a, 2).is_none());
+        let _b1 = inflight.acquire(b, 2).unwrap();
+        assert_eq!(inflight.get(&a), 2);
+
+        drop(a1);
+        assert_eq!(inflight.get(&a), 1);
+        let _a3 = inflight.acquire(a, 2).unwrap();
+        drop(a2);
+        assert_eq!(inflight.get(&a), 1);
+        assert_eq!(inflight.get(&b), 1);
+    }
+}
@@ -15,14 +15,25 @@
 //! responses received as [`Response`].
 //!
 //! Individual responses are sent using [`RequestReceived::channel`]
-//! upon receiving a [`RequestReceived`]. The response stream can
+//! upon receiving a [`StreamingResponseEvent::RequestReceived`]. The response stream can
 //! be finalized by calling [`Response::Finished`],
 //! which will result in the emission of an
 //! [`Response::Finished`] on the requester's side. After
 //! that, the response channel can't be used anymore.
 //!
+//! The response channel is bounded to
+//! [`StreamingResponseConfig::with_response_send_buffer_size`] and only drained as fast as the
+//! substream accepts the frames, so a slow requester makes sending on the channel wait. A frame
+//! exceeding the maximum message size ends the response stream with an error for the requester and
+//! is reported as [`StreamingResponseEvent::ResponseTooLarge`].
+//!
 //! An ongoing request is cancelled if either the peer disconnects.
 //!
+//! The number of concurrent requests per peer can be capped with
+//! [`StreamingResponseConfig::with_max_inflight_per_peer`]: requests beyond the cap are
+//! cancelled on the requester's side with [`CancellationReason::TooManyRequests`], and
+//! inbound substreams beyond the cap are dropped by the responder.
+//!
 //! ## Protocol Families
 //!
 //! A single [`StreamingResponse`] instance can be used with an entire
@@ -33,11 +44,13 @@
 //!
 //! The ergonomics of this behaviour are inspired by the
 //! `libp2p::request_response` implementation. However, it enables the exchange
-//! of multiple response frames per request. Currently, it does neither support
-//! timeouts nor signalling of successful commits of outbound messages to the
-//! underlying transport mechanism. Sending requests and/or responses is a
-//! fire-and-forget action. Only if the remote peer is disconnected, consumer
-//! code will be notified through [`Response::Error`].
+//! of multiple response frames per request. Currently, it does not support
+//! signalling of successful commits of outbound messages to the underlying
+//! transport mechanism. Sending requests and/or responses is a fire-and-forget
+//! action. Only if the remote peer is disconnected, consumer code will be
+//! notified through [`Response::Error`]. A requester can bound the time it waits
+//! for each response frame with [`StreamingResponseConfig::with_response_timeout`],
+//! in which case a silent peer results in [`Response::Cancelled`].
 //! Another notable difference is that this behaviour won't initiate any dialing
 //! attempts, thus this behaviour needs to be wrapped inside another behaviour
 //! providing dialing functionality.
@@ -51,9 +64,10 @@
 //! the recipient earlier than bigger ones. Each response frame includes a
 //! monotonic sequence number, which can be used for ordering purposes.
 
-use crate::libp2p_streaming_response::handler::IntoHandler;
+use crate::libp2p_streaming_response::{handler::IntoHandler, inflight::Inflight};
 use derive_more::{Add, Deref, Display, Sub};
 use futures::channel::mpsc;
+use handler::HandlerEvent;
 use handler::Request;
 use libp2p::{
     core::connection::ConnectionId,
@@ -65,11 +79,13 @@ use std::{
     collections::VecDeque,
     fmt::Debug,
     marker::PhantomData,
+    sync::Arc,
     task::{Context, Poll},
     time::Duration,
 };
 
 mod handler;
+mod inflight;
 mod protocol;
 mod protocol_v2;
 mod upgrade;
@@ -77,7 +93,7 @@ mod upgrade;
 #[cfg(test)]
 mod tests;
 
-pub use handler::Response;
+pub use handler::{CancellationReason, Response};
 pub use protocol_v2::ProtocolError;
 
 /// A [`Codec`] defines the request and response types for a [`StreamingResponse`]
@@ -104,9 +120,21 @@ impl SequenceNo {
     }
 }
 
+/// The protocol version negotiated for the substream a request arrived on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    /// one substream per message, named by [`Codec::info_v1`]
+    V1,
+    /// one substream per request, named by [`Codec::info_v2`]
+    V2,
+}
+
 pub struct RequestReceived<T: Codec> {
     pub peer_id: PeerId,
     pub connection: ConnectionId,
+    pub protocol: ProtocolVersion,
+    /// The agent version announced by the peer, if the configured [`AgentLookup`] knows it
+    pub agent_version: Option<String>,
     pub request: T::Request,
     pub channel: mpsc::Sender<T::Response>,
 }
@@ -116,16 +144,62 @@ impl<T: Codec> Debug for RequestReceived<T> {
         f.debug_struct("RequestReceived")
             .field("peer_id", &self.peer_id)
             .field("connection", &self.connection)
+            .field("protocol", &self.protocol)
+            .field("agent_version", &self.agent_version)
             .field("request", &self.request)
             .finish()
     }
 }
 
+/// A response frame was too large to be sent, the response stream has been ended with an error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseTooLarge {
+    pub peer_id: PeerId,
+    pub connection: ConnectionId,
+    /// encoded size of the frame in bytes
+    pub size: usize,
+    pub max_message_size: u32,
+}
+
+impl std::fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "response of {} bytes to {} exceeds the maximum message size of {}",
+            self.size, self.peer_id, self.max_message_size
+        )
+    }
+}
+
+pub enum StreamingResponseEvent<T: Codec> {
+    RequestReceived(RequestReceived<T>),
+    ResponseTooLarge(ResponseTooLarge),
+}
+
+impl<T: Codec> Debug for StreamingResponseEvent<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RequestReceived(r) => f.debug_tuple("RequestReceived").field(r).finish(),
+            Self::ResponseTooLarge(r) => f.debug_tuple("ResponseTooLarge").field(r).finish(),
+        }
+    }
+}
+
+/// Callback invoked when a peer sends a request that violates the protocol
+pub type ViolationHandler = Arc<dyn Fn(PeerId, &ProtocolError) + Send + Sync>;
+
+/// Callback resolving the agent version of a peer, e.g. from the identify information held by the swarm
+pub type AgentLookup = Arc<dyn Fn(&PeerId) -> Option<String> + Send + Sync>;
+
 pub struct StreamingResponseConfig {
     request_timeout: Duration,
+    response_timeout: Option<Duration>,
+    max_inflight_per_peer: Option<usize>,
     max_message_size: u32,
     response_send_buffer_size: usize,
     keep_alive: bool,
+    on_violation: Option<ViolationHandler>,
+    agent_lookup: Option<AgentLookup>,
 }
 
 impl StreamingResponseConfig {
@@ -136,6 +210,31 @@ impl StreamingResponseConfig {
             ..self
         }
     }
+    /// Maximum time to wait for the next response frame of an outbound request, default is no limit
+    ///
+    /// The timer starts when the request has been sent and is restarted whenever a frame arrives,
+    /// so a slow but steady response stream is not affected. On expiry the substream is closed and
+    /// the requester receives [`Response::Cancelled`] with [`CancellationReason::Timeout`]. This only
+    /// applies to peers speaking the v2 protocol.
+    pub fn with_response_timeout(self, response_timeout: Duration) -> Self {
+        Self {
+            response_timeout: Some(response_timeout),
+            ..self
+        }
+    }
+    /// Maximum number of requests in flight per peer and direction, default is no limit
+    ///
+    /// A request occupies its slot until its response stream is finished or the peer disconnects.
+    /// Requests beyond the cap are not sent, the requester receives [`Response::Cancelled`] with
+    /// [`CancellationReason::TooManyRequests`] instead. On the responding side, v2 substreams beyond
+    /// the cap are dropped without reading the request. Peers speaking the v1 protocol are only
+    /// limited on the requesting side.
+    pub fn with_max_inflight_per_peer(self, max_inflight_per_peer: usize) -> Self {
+        Self {
+            max_inflight_per_peer: Some(max_inflight_per_peer),
+            ..self
+        }
+    }
     /// Maximum message size permitted for requests and responses (limited to 0xfeffffff !)
     ///
     /// The maximum is slightly below 4GiB, the default 1MB. Sending huge messages requires corresponding
@@ -155,7 +254,9 @@ impl StreamingResponseConfig {
     /// Set the queue size in messages for the channel created for incoming requests
     ///
     /// All channels are bounded in size and use back-pressure. This channel size allows some
-    /// decoupling between response generation and network transmission. Default is 128.
+    /// decoupling between response generation and network transmission. Sending on the channel
+    /// waits while it is full; each clone of the sender adds room for one more message. Default
+    /// is 128.
     pub fn with_response_send_buffer_size(self, response_send_buffer_size: usize) -> Self {
         Self {
             response_send_buffer_size,
@@ -169,23 +270,48 @@ impl StreamingResponseConfig {
     pub fn with_keep_alive(self, keep_alive: bool) -> Self {
         Self { keep_alive, ..self }
     }
+    /// Get notified of inbound requests that cannot be decoded or exceed the maximum message size
+    ///
+    /// This allows tracking misbehaving peers; the offending substream is dropped in any case.
+    pub fn with_violation_handler(self, on_violation: impl Fn(PeerId, &ProtocolError) + Send + Sync + 'static) -> Self {
+        Self {
+            on_violation: Some(Arc::new(on_violation)),
+            ..self
+        }
+    }
+    /// Fill in [`RequestReceived::agent_version`] from the given lookup
+    ///
+    /// This behaviour does not run the identify protocol itself; without a lookup the agent version
+    /// is always `None`.
+    pub fn with_agent_lookup(self, agent_lookup: impl Fn(&PeerId) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self {
+            agent_lookup: Some(Arc::new(agent_lookup)),
+            ..self
+        }
+    }
 }
 
 impl Default for StreamingResponseConfig {
     fn default() -> Self {
         Self {
             request_timeout: Duration::from_secs(10),
+            response_timeout: None,
+            max_inflight_per_peer: None,
             max_message_size: 1_000_000,
             response_send_buffer_size: 128,
             keep_alive: false,
+            on_violation: None,
+            agent_lookup: None,
         }
     }
 }
 
 pub struct StreamingResponse<T: Codec + Send + 'static> {
     config: StreamingResponseConfig,
-    events: VecDeque<RequestReceived<T>>,
-    requests: VecDeque<NetworkBehaviourAction<RequestReceived<T>, IntoHandler<T>>>,
+    events: VecDeque<StreamingResponseEvent<T>>,
+    requests: VecDeque<NetworkBehaviourAction<StreamingResponseEvent<T>, IntoHandler<T>>>,
+    outbound: Inflight,
+    inbound: Inflight,
     _ph: PhantomData<T>,
 }
 
@@ -195,29 +321,47 @@ impl<T: Codec + Send + 'static> StreamingResponse<T> {
             config,
             events: VecDeque::default(),
             requests: VecDeque::default(),
+            outbound: Inflight::default(),
+            inbound: Inflight::default(),
             _ph: PhantomData,
         }
     }
 
-    pub fn request(&mut self, peer_id: PeerId, request: T::Request, channel: mpsc::Sender<Response<T::Response>>) {
+    pub fn request(&mut self, peer_id: PeerId, request: T::Request, mut channel: mpsc::Sender<Response<T::Response>>) {
+        let guard = match self.config.max_inflight_per_peer {
+            Some(max) => match self.outbound.acquire(peer_id, max) {
+                Some(guard) => Some(guard),
+                None => {
+                    tracing::debug!("{} requests in flight to {}, rejecting request", max, peer_id);
+                    channel
+                        .try_send(Response::Cancelled(CancellationReason::TooManyRequests))
+                        .ok();
+                    return;
+                }
+            },
+            None => None,
+        };
         self.requests.push_back(NetworkBehaviourAction::NotifyHandler {
             peer_id,
             handler: NotifyHandler::Any,
-            event: Request::new(request, channel),
+            event: Request::new(request, channel, guard),
         })
     }
 }
 
 impl<T: Codec + Send + 'static> NetworkBehaviour for StreamingResponse<T> {
     type ConnectionHandler = IntoHandler<T>;
-    type OutEvent = RequestReceived<T>;
+    type OutEvent = StreamingResponseEvent<T>;
 
     fn new_handler(&mut self) -> Self::ConnectionHandler {
         IntoHandler::new(
             self.config.max_message_size,
             self.config.request_timeout,
+            self.config.response_timeout,
             self.config.response_send_buffer_size,
             self.config.keep_alive,
+            self.config.on_violation.clone(),
+            self.config.max_inflight_per_peer.map(|max| (self.inbound.clone(), max)),
         )
     }
 
@@ -227,14 +371,31 @@ impl<T: Codec + Send + 'static> NetworkBehaviour for StreamingResponse<T> {
         connection: ConnectionId,
         event: <<Self::ConnectionHandler as libp2p::swarm::IntoConnectionHandler>::Handler as libp2p::swarm::ConnectionHandler>::OutEvent,
     ) {
-        let handler::RequestReceived { request, channel } = event;
-        tracing::trace!("request received by behaviour: {:?}", request);
-        self.events.push_back(RequestReceived {
-            peer_id,
-            connection,
-            request,
-            channel,
-        });
+        let event = match event {
+            HandlerEvent::RequestReceived(handler::RequestReceived {
+                request,
+                channel,
+                protocol,
+            }) => {
+                tracing::trace!("request received by behaviour: {:?}", request);
+                let agent_version = self.config.agent_lookup.as_ref().and_then(|lookup| lookup(&peer_id));
+                StreamingResponseEvent::RequestReceived(RequestReceived {
+                    peer_id,
+                    connection,
+                    protocol,
+                    agent_version,
+                    request,
+                    channel,
+                })
+            }
+            HandlerEvent::ResponseTooLarge { size } => StreamingResponseEvent::ResponseTooLarge(ResponseTooLarge {
+                peer_id,
+                connection,
+                size,
+                max_message_size: self.config.max_message_size,
+            }),
+        };
+        self.events.push_back(event);
     }
 
     fn poll(
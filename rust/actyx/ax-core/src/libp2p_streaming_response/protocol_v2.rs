@@ -45,6 +45,10 @@ impl PartialEq for ProtocolError {
 }
 
 impl ProtocolError {
+    /// Whether the error is caused by the peer sending data that does not follow the protocol
+    pub fn is_violation(&self) -> bool {
+        matches!(self, ProtocolError::MessageTooLargeRecv(_) | ProtocolError::Serde(_))
+    }
     pub fn as_code(&self) -> u8 {
         match self {
             ProtocolError::Timeout => 1,
@@ -1,8 +1,10 @@
 use crate::libp2p_streaming_response::{
-    Codec, ProtocolError, RequestReceived, Response, StreamingResponse, StreamingResponseConfig,
+    CancellationReason, Codec, ProtocolError, ProtocolVersion, RequestReceived, Response, StreamingResponse,
+    StreamingResponseConfig, StreamingResponseEvent,
 };
 use futures::{
     channel::mpsc::{self, Receiver, Sender},
+    future::{select, Either},
     Future, SinkExt, StreamExt,
 };
 use libp2p::{
@@ -14,6 +16,13 @@ use libp2p::{
     yamux::YamuxConfig,
     Multiaddr, PeerId, Swarm, Transport,
 };
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio::runtime::Runtime;
 
 mod proto;
@@ -22,6 +31,13 @@ const PROTO: &str = "/my/test";
 const PROTO_V2: &str = "/my/test/2";
 
 fn test_swarm() -> Swarm<StreamingResponse<Proto>> {
+    let config = StreamingResponseConfig::default()
+        .with_keep_alive(true)
+        .with_max_message_size(100);
+    swarm(config)
+}
+
+fn swarm<T: Codec + Send + 'static>(config: StreamingResponseConfig) -> Swarm<StreamingResponse<T>> {
     let local_key = Keypair::generate_ed25519();
     let local_public_key = local_key.public();
     let local_peer_id = local_public_key.clone().into();
@@ -30,9 +46,6 @@ fn test_swarm() -> Swarm<StreamingResponse<Proto>> {
         .authenticate(PlainText2Config { local_public_key })
         .multiplex(YamuxConfig::default())
         .boxed();
-    let config = StreamingResponseConfig::default()
-        .with_keep_alive(true)
-        .with_max_message_size(100);
     let behaviour = StreamingResponse::new(config);
     SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build()
 }
@@ -64,6 +77,21 @@ impl Codec for Proto {
     }
 }
 
+/// A client that only speaks the v1 protocol
+struct ProtoV1;
+impl Codec for ProtoV1 {
+    type Request = String;
+    type Response = String;
+
+    fn info_v1() -> &'static str {
+        PROTO
+    }
+
+    fn info_v2() -> &'static [&'static str] {
+        &[]
+    }
+}
+
 macro_rules! wait4 {
     ($s:ident, $p:pat => $e:expr) => {
         loop {
@@ -115,7 +143,7 @@ fn smoke() {
 
         responder.dial(addr).unwrap();
         task!(responder,
-            SwarmEvent::Behaviour(RequestReceived { request, peer_id, mut channel, .. }) => {
+            SwarmEvent::Behaviour(StreamingResponseEvent::RequestReceived(RequestReceived { request, peer_id, mut channel, .. })) => {
                 tokio::spawn(async move {
                     channel.feed(request).await.unwrap();
                     channel.feed(peer_id.to_string()).await.unwrap();
@@ -137,6 +165,7 @@ fn smoke() {
                 Response::Msg(m) => Some(m),
                 Response::Error(e) => panic!("got error: {:#}", e),
                 Response::Finished => None,
+                Response::Cancelled(r) => panic!("got cancelled: {}", r),
             })
             .collect::<Vec<_>>()
             .await;
@@ -148,6 +177,15 @@ fn smoke() {
 }
 
 fn test_setup<F, Fut, L>(request: String, logic: L, f: F)
+where
+    F: FnOnce(Receiver<Response<String>>) -> Fut + Send + 'static,
+    Fut: Future,
+    L: Fn(String, PeerId, Sender<String>) + Send + 'static,
+{
+    test_setup_with(test_swarm(), request, logic, f)
+}
+
+fn test_setup_with<F, Fut, L>(mut asker: Swarm<StreamingResponse<Proto>>, request: String, logic: L, f: F)
 where
     F: FnOnce(Receiver<Response<String>>) -> Fut + Send + 'static,
     Fut: Future,
@@ -155,7 +193,6 @@ where
 {
     crate::util::setup_logger();
     let rt = Runtime::new().unwrap();
-    let mut asker = test_swarm();
     let mut responder = test_swarm();
 
     rt.block_on(async move {
@@ -163,7 +200,7 @@ where
             .listen_on(Multiaddr::empty().with(Protocol::Memory(0)))
             .unwrap();
         let addr = wait4!(responder, SwarmEvent::NewListenAddr{ address, .. } => address);
-        task!(responder, SwarmEvent::Behaviour(RequestReceived { request, peer_id, channel, .. }) => logic(request, peer_id, channel));
+        task!(responder, SwarmEvent::Behaviour(StreamingResponseEvent::RequestReceived(RequestReceived { request, peer_id, channel, .. })) => logic(request, peer_id, channel));
         asker.dial(addr).unwrap();
         let peer_id = wait4!(asker, SwarmEvent::ConnectionEstablished { peer_id, .. } => peer_id);
         let (tx, rx) = mpsc::channel(10);
@@ -280,3 +317,305 @@ fn err_request_size() {
         },
     );
 }
+
+fn impatient_swarm() -> Swarm<StreamingResponse<Proto>> {
+    swarm(
+        StreamingResponseConfig::default()
+            .with_keep_alive(true)
+            .with_response_timeout(Duration::from_millis(500)),
+    )
+}
+
+#[test]
+fn response_timeout_silent() {
+    test_setup_with(
+        impatient_swarm(),
+        "request".to_owned(),
+        |_request, _peer_id, channel| {
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                drop(channel);
+            });
+        },
+        |mut rx| async move {
+            let started = Instant::now();
+            assert_eq!(rx.next().await, Some(Response::Cancelled(CancellationReason::Timeout)));
+            assert!(started.elapsed() >= Duration::from_millis(400));
+            assert_eq!(rx.next().await, None);
+        },
+    );
+}
+
+#[test]
+fn response_timeout_slow_but_alive() {
+    test_setup_with(
+        impatient_swarm(),
+        "request".to_owned(),
+        |request, _peer_id, mut channel| {
+            tokio::spawn(async move {
+                for i in 0..4 {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    channel.feed(format!("{}{}", request, i)).await.unwrap();
+                    channel.flush().await.unwrap();
+                }
+                channel.close().await.unwrap();
+            });
+        },
+        |rx| async move {
+            let response = rx.collect::<Vec<_>>().await;
+            assert_eq!(
+                response,
+                vec![
+                    Response::Msg("request0".to_owned()),
+                    Response::Msg("request1".to_owned()),
+                    Response::Msg("request2".to_owned()),
+                    Response::Msg("request3".to_owned()),
+                    Response::Finished,
+                ]
+            );
+        },
+    );
+}
+
+type Requests = Sender<(String, Sender<Response<String>>)>;
+
+/// Runs an asker that takes requests from a channel and a responder that hands out the response channels
+fn inflight_setup<F, Fut>(asker: StreamingResponseConfig, responder: StreamingResponseConfig, f: F)
+where
+    F: FnOnce(Requests, Receiver<Sender<String>>) -> Fut + Send + 'static,
+    Fut: Future,
+{
+    crate::util::setup_logger();
+    let rt = Runtime::new().unwrap();
+    let mut asker = swarm::<Proto>(asker);
+    let mut responder = swarm::<Proto>(responder);
+
+    rt.block_on(async move {
+        responder
+            .listen_on(Multiaddr::empty().with(Protocol::Memory(0)))
+            .unwrap();
+        let addr = wait4!(responder, SwarmEvent::NewListenAddr{ address, .. } => address);
+        let (channels_tx, channels) = mpsc::channel(10);
+        task!(responder, SwarmEvent::Behaviour(StreamingResponseEvent::RequestReceived(RequestReceived { channel, .. })) => channels_tx.clone().try_send(channel).unwrap());
+        asker.dial(addr).unwrap();
+        let peer_id = wait4!(asker, SwarmEvent::ConnectionEstablished { peer_id, .. } => peer_id);
+        let (requests, mut requests_rx) = mpsc::channel::<(String, Sender<Response<String>>)>(10);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    ev = asker.next() => if ev.is_none() { break },
+                    Some((request, tx)) = requests_rx.next() => asker.behaviour_mut().request(peer_id, request, tx),
+                }
+            }
+        });
+        f(requests, channels).await;
+    });
+}
+
+fn send_request(requests: &mut Requests, request: &str) -> Receiver<Response<String>> {
+    let (tx, rx) = mpsc::channel(10);
+    requests.try_send((request.to_owned(), tx)).unwrap();
+    rx
+}
+
+async fn serve_one(channels: &mut Receiver<Sender<String>>) {
+    let mut channel = channels.next().await.unwrap();
+    channel.feed("done".to_owned()).await.unwrap();
+    channel.close().await.unwrap();
+}
+
+async fn assert_done(rx: Receiver<Response<String>>) {
+    assert_eq!(
+        rx.collect::<Vec<_>>().await,
+        vec![Response::Msg("done".to_owned()), Response::Finished]
+    );
+}
+
+#[test]
+fn max_inflight_requester() {
+    inflight_setup(
+        StreamingResponseConfig::default()
+            .with_keep_alive(true)
+            .with_max_inflight_per_peer(2),
+        StreamingResponseConfig::default().with_keep_alive(true),
+        |mut requests, mut channels| async move {
+            let rx1 = send_request(&mut requests, "1");
+            let rx2 = send_request(&mut requests, "2");
+            let mut rx3 = send_request(&mut requests, "3");
+            assert_eq!(
+                rx3.next().await,
+                Some(Response::Cancelled(CancellationReason::TooManyRequests))
+            );
+            assert_eq!(rx3.next().await, None);
+
+            serve_one(&mut channels).await;
+            serve_one(&mut channels).await;
+            assert_done(rx1).await;
+            assert_done(rx2).await;
+
+            // both slots are free again
+            let rx4 = send_request(&mut requests, "4");
+            let rx5 = send_request(&mut requests, "5");
+            serve_one(&mut channels).await;
+            serve_one(&mut channels).await;
+            assert_done(rx4).await;
+            assert_done(rx5).await;
+        },
+    );
+}
+
+#[test]
+fn max_inflight_responder() {
+    inflight_setup(
+        StreamingResponseConfig::default().with_keep_alive(true),
+        StreamingResponseConfig::default()
+            .with_keep_alive(true)
+            .with_max_inflight_per_peer(1),
+        |mut requests, mut channels| async move {
+            let mut rx1 = send_request(&mut requests, "1");
+            let mut rx2 = send_request(&mut requests, "2");
+            // the accepted request stays silent until served, so the first response is the rejection
+            let (rejected, first_rejected) = match select(rx1.next(), rx2.next()).await {
+                Either::Left((r, _)) => (r, true),
+                Either::Right((r, _)) => (r, false),
+            };
+            assert!(matches!(rejected, Some(Response::Error(_))), "{:?}", rejected);
+            let accepted = if first_rejected { rx2 } else { rx1 };
+
+            serve_one(&mut channels).await;
+            assert_done(accepted).await;
+
+            let rx3 = send_request(&mut requests, "3");
+            serve_one(&mut channels).await;
+            assert_done(rx3).await;
+        },
+    );
+}
+
+/// Sends a request from a client using codec `T` and returns what the responder learned about it
+fn negotiated<T>() -> (ProtocolVersion, Option<String>)
+where
+    T: Codec<Request = String, Response = String> + Send + 'static,
+{
+    crate::util::setup_logger();
+    let rt = Runtime::new().unwrap();
+    let mut asker = swarm::<T>(StreamingResponseConfig::default().with_keep_alive(true));
+    let asker_id = *asker.local_peer_id();
+    let mut responder = swarm::<Proto>(
+        StreamingResponseConfig::default()
+            .with_keep_alive(true)
+            .with_agent_lookup(move |peer_id| (*peer_id == asker_id).then(|| "ax/2.18.0".to_owned())),
+    );
+
+    rt.block_on(async move {
+        responder
+            .listen_on(Multiaddr::empty().with(Protocol::Memory(0)))
+            .unwrap();
+        let addr = wait4!(responder, SwarmEvent::NewListenAddr{ address, .. } => address);
+        asker.dial(addr).unwrap();
+        let peer_id = wait4!(asker, SwarmEvent::ConnectionEstablished { peer_id, .. } => peer_id);
+        let (tx, _rx) = mpsc::channel(10);
+        asker.behaviour_mut().request(peer_id, "request".to_owned(), tx);
+        task!(asker);
+        wait4!(responder, SwarmEvent::Behaviour(StreamingResponseEvent::RequestReceived(RequestReceived { protocol, agent_version, peer_id, .. })) => {
+            assert_eq!(peer_id, asker_id);
+            (protocol, agent_version)
+        })
+    })
+}
+
+#[test]
+fn protocol_version_v2() {
+    assert_eq!(
+        negotiated::<Proto>(),
+        (ProtocolVersion::V2, Some("ax/2.18.0".to_owned()))
+    );
+}
+
+#[test]
+fn protocol_version_v1() {
+    assert_eq!(
+        negotiated::<ProtoV1>(),
+        (ProtocolVersion::V1, Some("ax/2.18.0".to_owned()))
+    );
+}
+
+#[test]
+fn slow_requester_holds_back_responder() {
+    const FRAMES: usize = 100;
+    inflight_setup(
+        StreamingResponseConfig::default().with_keep_alive(true),
+        StreamingResponseConfig::default()
+            .with_keep_alive(true)
+            .with_response_send_buffer_size(4),
+        |mut requests, mut channels| async move {
+            // nothing is taken out of this channel for now
+            let (tx, mut rx) = mpsc::channel(1);
+            requests.try_send(("request".to_owned(), tx)).unwrap();
+            let mut channel = channels.next().await.unwrap();
+            let sent = Arc::new(AtomicUsize::new(0));
+            let producer = tokio::spawn({
+                let sent = sent.clone();
+                async move {
+                    for i in 0..FRAMES {
+                        channel.send(format!("{:04}{}", i, "x".repeat(65536))).await.unwrap();
+                        sent.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+
+            let mut stalled = sent.load(Ordering::SeqCst);
+            loop {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                let now = sent.load(Ordering::SeqCst);
+                if now == stalled {
+                    break;
+                }
+                stalled = now;
+            }
+            assert!(stalled < FRAMES, "producer was not held back");
+
+            for i in 0..FRAMES {
+                match rx.next().await {
+                    Some(Response::Msg(msg)) => assert!(msg.starts_with(&format!("{:04}", i)), "frame {}", i),
+                    other => panic!("expected frame {}, got {:?}", i, other.map(dbg)),
+                }
+            }
+            assert_eq!(rx.next().await, Some(Response::Finished));
+            producer.await.unwrap();
+            assert_eq!(sent.load(Ordering::SeqCst), FRAMES);
+        },
+    );
+}
+
+#[test]
+fn response_too_large_event() {
+    crate::util::setup_logger();
+    let rt = Runtime::new().unwrap();
+    let mut asker = test_swarm();
+    let asker_id = *asker.local_peer_id();
+    let mut responder = test_swarm();
+
+    rt.block_on(async move {
+        responder
+            .listen_on(Multiaddr::empty().with(Protocol::Memory(0)))
+            .unwrap();
+        let addr = wait4!(responder, SwarmEvent::NewListenAddr{ address, .. } => address);
+        asker.dial(addr).unwrap();
+        let peer_id = wait4!(asker, SwarmEvent::ConnectionEstablished { peer_id, .. } => peer_id);
+        let (tx, mut rx) = mpsc::channel(10);
+        asker.behaviour_mut().request(peer_id, "request".to_owned(), tx);
+        task!(asker);
+
+        let mut channel = wait4!(responder, SwarmEvent::Behaviour(StreamingResponseEvent::RequestReceived(RequestReceived { channel, .. })) => channel);
+        channel.try_send("x".repeat(200)).unwrap();
+        let too_large = wait4!(responder, SwarmEvent::Behaviour(StreamingResponseEvent::ResponseTooLarge(e)) => e);
+        assert_eq!(too_large.peer_id, asker_id);
+        assert_eq!(too_large.max_message_size, 100);
+        assert!(too_large.size > 200, "{}", too_large);
+        assert_eq!(
+            rx.next().await,
+            Some(Response::Error(ProtocolError::MessageTooLargeSent(0)))
+        );
+    });
+}
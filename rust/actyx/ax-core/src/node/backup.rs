@@ -0,0 +1,502 @@
+//! Backup of a node's working directory into a single archive, and restoring it.
+//!
+//! The archive is a zstd compressed tar file starting with a [`BackupManifest`], followed by the
+//! files of the working directory: the settings and node databases with the key store, and the
+//! index and block stores of all topics. The SQLite databases are copied with the online backup
+//! API, which yields a consistent copy of each database while the node keeps appending.
+use crate::{swarm::BanyanStore, util::version::NodeVersion};
+use anyhow::{bail, ensure, Context, Result};
+use ax_types::{NodeId, OffsetMap, Timestamp};
+use rusqlite::{backup::Backup, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+/// Version of the archive layout, only this one can be restored
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// First entry of a backup archive, describing the node and the files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub node_id: NodeId,
+    pub topic: String,
+    /// version of the node that created the backup
+    pub node_version: NodeVersion,
+    pub created: Timestamp,
+    /// present offsets when the backup started, the archive contains at least these events
+    pub offsets: OffsetMap,
+    /// the other entries of the archive, by path relative to the working directory
+    pub files: BTreeMap<String, BackupFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupFile {
+    pub size: u64,
+    /// hex encoded SHA-256 of the contents
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BackupStage {
+    /// copying the files next to the archive
+    Snapshot,
+    /// writing the archive
+    Archive,
+    /// checking the archive against its manifest, before anything is restored
+    Verify,
+    /// writing the files into the working directory
+    Unpack,
+}
+
+/// Reported after each file of a backup or restore
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupProgress {
+    pub stage: BackupStage,
+    pub path: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Write a backup of the node with the given `working_dir` and running `store` to `archive`.
+///
+/// The files are copied to a directory next to `archive`, which is removed afterwards. Appends
+/// are paused only to capture the offsets before the copy starts.
+pub fn create(
+    store: &BanyanStore,
+    working_dir: &Path,
+    archive: &Path,
+    mut progress: impl FnMut(BackupProgress),
+) -> Result<BackupManifest> {
+    ensure!(
+        !archive.exists(),
+        "backup archive `{}` already exists",
+        archive.display()
+    );
+    let name = archive
+        .file_name()
+        .with_context(|| format!("invalid backup archive path `{}`", archive.display()))?;
+    let staging = archive.with_file_name(format!(".{}.partial", name.to_string_lossy()));
+    ensure!(
+        !staging.exists(),
+        "`{}` exists, is another backup running?",
+        staging.display()
+    );
+    let files = working_dir_files(working_dir)?;
+    fs::create_dir_all(staging.join("files")).with_context(|| format!("creating `{}`", staging.display()))?;
+    let result = create_from(store, working_dir, &files, &staging, archive, &mut progress);
+    fs::remove_dir_all(&staging).ok();
+    result
+}
+
+fn create_from(
+    store: &BanyanStore,
+    working_dir: &Path,
+    files: &[PathBuf],
+    staging: &Path,
+    archive: &Path,
+    progress: &mut impl FnMut(BackupProgress),
+) -> Result<BackupManifest> {
+    let copies = staging.join("files");
+    let offsets = store.with_appends_paused(|offsets| offsets.clone());
+    let mut copied = vec![];
+    for (done, path) in files.iter().enumerate() {
+        let target = copies.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // files may be removed after they have been listed, like the databases of a deleted topic
+        if copy_file(&working_dir.join(path), &target).with_context(|| format!("copying `{}`", path.display()))? {
+            copied.push(path);
+        }
+        progress(BackupProgress {
+            stage: BackupStage::Snapshot,
+            path: archive_path(path)?,
+            done: done + 1,
+            total: files.len(),
+        });
+    }
+
+    let mut manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        node_id: store.node_id(),
+        topic: store.topic().to_owned(),
+        node_version: NodeVersion::get().clone(),
+        created: Timestamp::now(),
+        offsets,
+        files: BTreeMap::new(),
+    };
+    for path in &copied {
+        let file = checksum(File::open(copies.join(path))?)?;
+        manifest.files.insert(archive_path(path)?, file);
+    }
+
+    let partial = staging.join("archive");
+    let mut tar = tar::Builder::new(zstd::stream::write::Encoder::new(File::create(&partial)?, 0)?);
+    let json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, MANIFEST, json.as_slice())?;
+    for (done, path) in copied.iter().enumerate() {
+        let name = archive_path(path)?;
+        tar.append_path_with_name(copies.join(path), &name)?;
+        progress(BackupProgress {
+            stage: BackupStage::Archive,
+            path: name,
+            done: done + 1,
+            total: copied.len(),
+        });
+    }
+    tar.into_inner()?.finish()?.sync_all()?;
+    fs::rename(&partial, archive).with_context(|| format!("writing `{}`", archive.display()))?;
+    Ok(manifest)
+}
+
+/// Restore the backup in `archive` into `working_dir`, which must be empty or not exist.
+///
+/// The whole archive is checked against its manifest before the first file is written.
+pub fn restore(archive: &Path, working_dir: &Path, mut progress: impl FnMut(BackupProgress)) -> Result<BackupManifest> {
+    if working_dir.exists() {
+        let mut entries = fs::read_dir(working_dir).with_context(|| format!("reading `{}`", working_dir.display()))?;
+        ensure!(
+            entries.next().is_none(),
+            "cannot restore into `{}`, it is not empty",
+            working_dir.display()
+        );
+    }
+    let manifest = verify(archive, &mut progress)?;
+
+    let mut tar = open_archive(archive)?;
+    let mut done = 0;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry_name(&entry)?;
+        if name == MANIFEST {
+            continue;
+        }
+        let target = working_dir.join(relative_path(&name)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&target).with_context(|| format!("creating `{}`", target.display()))?;
+        io::copy(&mut entry, &mut file)?;
+        file.sync_all()?;
+        done += 1;
+        progress(BackupProgress {
+            stage: BackupStage::Unpack,
+            path: name,
+            done,
+            total: manifest.files.len(),
+        });
+    }
+    Ok(manifest)
+}
+
+/// Check the version and the checksums of all files in the archive, returning its manifest.
+fn verify(archive: &Path, progress: &mut impl FnMut(BackupProgress)) -> Result<BackupManifest> {
+    let mut tar = open_archive(archive)?;
+    let mut entries = tar.entries()?;
+    let mut first = entries.next().context("backup archive is empty")??;
+    ensure!(
+        entry_name(&first)? == MANIFEST,
+        "backup archive does not start with a manifest"
+    );
+    let manifest: BackupManifest = serde_json::from_reader(&mut first).context("reading the backup manifest")?;
+    check_compatibility(&manifest)?;
+
+    let mut missing = manifest.files.keys().cloned().collect::<BTreeSet<_>>();
+    for entry in entries {
+        let mut entry = entry?;
+        let name = entry_name(&entry)?;
+        relative_path(&name)?;
+        let expected = manifest
+            .files
+            .get(&name)
+            .with_context(|| format!("`{}` is not listed in the backup manifest", name))?;
+        ensure!(
+            missing.remove(&name),
+            "`{}` is contained twice in the backup archive",
+            name
+        );
+        ensure!(
+            checksum(&mut entry)? == *expected,
+            "`{}` does not match the checksum in the backup manifest",
+            name
+        );
+        progress(BackupProgress {
+            stage: BackupStage::Verify,
+            path: name,
+            done: manifest.files.len() - missing.len(),
+            total: manifest.files.len(),
+        });
+    }
+    ensure!(missing.is_empty(), "backup archive is missing {:?}", missing);
+    Ok(manifest)
+}
+
+/// Backups can be restored by the same or newer nodes.
+fn check_compatibility(manifest: &BackupManifest) -> Result<()> {
+    ensure!(
+        manifest.format_version == BACKUP_FORMAT_VERSION,
+        "backup format version {} is not supported, only {}",
+        manifest.format_version,
+        BACKUP_FORMAT_VERSION
+    );
+    let current = NodeVersion::get();
+    match (manifest.node_version.version(), current.version()) {
+        (Some(backup), Some(this)) => ensure!(
+            backup <= this,
+            "the backup was created by node version {}, which is newer than this node's version {}",
+            manifest.node_version,
+            current
+        ),
+        _ => bail!(
+            "cannot compare the backup's node version {} with this node's version {}",
+            manifest.node_version,
+            current
+        ),
+    }
+    Ok(())
+}
+
+fn open_archive(archive: &Path) -> Result<tar::Archive<impl Read>> {
+    let file = File::open(archive).with_context(|| format!("opening `{}`", archive.display()))?;
+    Ok(tar::Archive::new(zstd::stream::read::Decoder::new(file)?))
+}
+
+/// The files of the working directory to back up, as relative paths. The lock file and the
+/// temporary files of SQLite are left out.
+fn working_dir_files(working_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(working_dir.join(&dir)).with_context(|| format!("reading `{}`", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() && !is_transient(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_transient(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    name == "lockfile" || name == MANIFEST || ["-wal", "-shm", "-journal"].iter().any(|s| name.ends_with(s))
+}
+
+/// Copy a file, using the SQLite backup API for databases. Returns `false` if it does not exist.
+fn copy_file(from: &Path, to: &Path) -> Result<bool> {
+    let mut magic = [0u8; 16];
+    let is_sqlite = match File::open(from) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && magic == *SQLITE_MAGIC,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    if is_sqlite {
+        let src = Connection::open_with_flags(from, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut dst = Connection::open(to)?;
+        Backup::new(&src, &mut dst)?.run_to_completion(-1, Duration::from_millis(10), None)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+    Ok(true)
+}
+
+fn checksum(mut reader: impl Read) -> Result<BackupFile> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut reader, &mut hasher)?;
+    Ok(BackupFile {
+        size,
+        sha256: hex::encode(hasher.finalize()),
+    })
+}
+
+/// The name of a file in the archive, with `/` as separator on all platforms
+fn archive_path(path: &Path) -> Result<String> {
+    let parts = path
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .with_context(|| format!("`{}` is not valid UTF-8", path.display()))?;
+    Ok(parts.join("/"))
+}
+
+fn entry_name<R: Read>(entry: &tar::Entry<R>) -> Result<String> {
+    let path = entry.path()?;
+    Ok(path.to_str().context("invalid path in backup archive")?.to_owned())
+}
+
+/// The path for a name in the archive, refusing anything that would end up outside of the
+/// working directory.
+fn relative_path(name: &str) -> Result<PathBuf> {
+    let path = name.split('/').collect::<PathBuf>();
+    ensure!(
+        !name.is_empty() && path.components().all(|c| matches!(c, Component::Normal(_))),
+        "invalid path `{}` in backup archive",
+        name
+    );
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        node::{node_storage::NodeStorage, util::make_keystore},
+        swarm::{BanyanStore, SwarmConfig},
+    };
+    use acto::ActoRef;
+    use ax_types::{app_id, tags, Offset, OffsetOrMin, Payload, StreamId};
+    use banyan::query::AllQuery;
+    use futures::TryStreamExt;
+
+    fn store_config(working_dir: &Path, storage: NodeStorage) -> Result<SwarmConfig> {
+        let keystore = make_keystore(storage.clone())?;
+        let node_id = match storage.get_node_key()? {
+            Some(node_id) => node_id,
+            None => {
+                let node_id = keystore.write().generate_key_pair()?.into();
+                storage.set_node_id(node_id)?;
+                node_id
+            }
+        };
+        let store_dir = working_dir.join("store");
+        fs::create_dir_all(&store_dir)?;
+        let keypair = keystore.read().get_pair(node_id.into());
+        Ok(SwarmConfig {
+            keypair,
+            db_path: Some(store_dir.join("topic.sqlite")),
+            index_store: Some(store_dir.join("topic-index")),
+            ..SwarmConfig::test("backup")
+        })
+    }
+
+    async fn events(store: &BanyanStore, stream_id: StreamId) -> Result<Vec<(u64, Payload)>> {
+        let snapshot = store.snapshot();
+        let chunks = store
+            .stream_filtered_chunked_snapshot(&snapshot, stream_id, 0..=u64::MAX, AllQuery)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.data)
+            .map(|(offset, _, payload)| (offset, payload))
+            .collect())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backup_and_restore() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let working_dir = dir.path().join("node");
+        fs::create_dir_all(&working_dir)?;
+        let storage = NodeStorage::new(working_dir.join("node.sqlite"))?;
+        let store = BanyanStore::new(store_config(&working_dir, storage)?, ActoRef::blackhole()).await?;
+        let node_id = store.node_id();
+        let payloads = (0..10)
+            .map(|i| (tags!("backup"), Payload::from_json_str(&i.to_string()).unwrap()))
+            .collect();
+        let stream_id = node_id.stream(store.append(app_id!("test"), payloads).await?[0].2);
+        let expected = events(&store, stream_id).await?;
+        let (last, _) = expected.last().unwrap();
+
+        let archive = dir.path().join("backup.tar.zst");
+        let mut reported = vec![];
+        let manifest = create(&store, &working_dir, &archive, |p| reported.push(p))?;
+        assert_eq!(manifest.node_id, node_id);
+        let last = OffsetOrMin::from(Offset::try_from(*last)?);
+        assert_eq!(manifest.offsets.offset(stream_id), last);
+        assert!(manifest.files.contains_key("node.sqlite"));
+        assert!(manifest.files.contains_key("store/topic.sqlite"));
+        assert!(reported
+            .iter()
+            .any(|p| p.stage == BackupStage::Archive && p.done == p.total));
+        assert!(
+            create(&store, &working_dir, &archive, |_| {}).is_err(),
+            "archive overwritten"
+        );
+        drop(store);
+
+        // only into an empty directory
+        let err = restore(&archive, &working_dir, |_| {}).unwrap_err();
+        assert!(err.to_string().contains("not empty"), "{}", err);
+
+        let restored_dir = dir.path().join("restored");
+        let restored = restore(&archive, &restored_dir, |_| {})?;
+        assert_eq!(restored, manifest);
+        let storage = NodeStorage::new(restored_dir.join("node.sqlite"))?;
+        assert_eq!(storage.get_node_key()?, Some(node_id));
+        let store = BanyanStore::new(store_config(&restored_dir, storage)?, ActoRef::blackhole()).await?;
+        assert_eq!(store.node_id(), node_id);
+        assert_eq!(events(&store, stream_id).await?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn tampered_archive_is_not_restored() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = BackupManifest {
+            format_version: BACKUP_FORMAT_VERSION,
+            node_id: NodeId::from_bytes(&[1; 32])?,
+            topic: "topic".to_owned(),
+            node_version: NodeVersion::get().clone(),
+            created: Timestamp::now(),
+            offsets: OffsetMap::empty(),
+            files: maplit::btreemap! {
+                "node.sqlite".to_owned() => checksum(&b"original"[..])?,
+            },
+        };
+        let write = |name: &str, manifest: &BackupManifest, contents: &[u8]| -> Result<PathBuf> {
+            let path = dir.path().join(name);
+            let mut tar = tar::Builder::new(zstd::stream::write::Encoder::new(File::create(&path)?, 0)?);
+            for (name, data) in [
+                (MANIFEST, serde_json::to_vec(manifest)?),
+                ("node.sqlite", contents.to_vec()),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                tar.append_data(&mut header, name, data.as_slice())?;
+            }
+            tar.into_inner()?.finish()?;
+            Ok(path)
+        };
+
+        let tampered = write("tampered.tar.zst", &manifest, b"modified")?;
+        let err = restore(&tampered, &dir.path().join("a"), |_| {}).unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{}", err);
+        assert!(!dir.path().join("a").exists());
+
+        let newer = BackupManifest {
+            format_version: BACKUP_FORMAT_VERSION + 1,
+            ..manifest.clone()
+        };
+        let newer = write("newer.tar.zst", &newer, b"original")?;
+        let err = restore(&newer, &dir.path().join("b"), |_| {}).unwrap_err();
+        assert!(err.to_string().contains("not supported"), "{}", err);
+
+        let valid = write("valid.tar.zst", &manifest, b"original")?;
+        restore(&valid, &dir.path().join("c"), |_| {})?;
+        assert_eq!(fs::read(dir.path().join("c/node.sqlite"))?, b"original");
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 use super::{Component, ComponentState};
-use crate::node::{
-    components::ComponentRequest,
-    formats::{ExternalEvent, ShutdownReason},
-    node_settings::Settings,
+use crate::{
+    node::{
+        components::ComponentRequest,
+        formats::{ExternalEvent, ShutdownReason},
+        node_settings::Settings,
+    },
+    util::formats::ComponentStatus,
 };
 use anyhow::Result;
 use crossbeam::channel::{self, Receiver, Sender};
@@ -48,6 +51,9 @@ impl From<ShutdownReason> for FfiMessage {
                 crate::node::NodeError::ServicesStartup { err, .. } | crate::node::NodeError::InternalError(err) => {
                     (ffi_codes::NODE_STOPPED_BY_NODE, format!("{:#}", err))
                 }
+                err @ crate::node::NodeError::DatabaseFromFuture { .. } => {
+                    (ffi_codes::NODE_STOPPED_BY_NODE, err.to_string())
+                }
                 crate::node::NodeError::PortCollision { component, addr } => (
                     ffi_codes::ERR_PORT_COLLISION,
                     format!(
@@ -129,6 +135,9 @@ impl Component<(), ()> for Android {
                     snd.send((Self::get_type().into(), ComponentState::Started))?;
                     supervisor.replace(snd);
                 }
+                ComponentRequest::HealthCheck(tx) => {
+                    let _ = tx.send(ComponentStatus::Running);
+                }
                 _ => {}
             }
         }
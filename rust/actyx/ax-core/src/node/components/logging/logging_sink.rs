@@ -1,6 +1,13 @@
 use tracing::Subscriber;
-use tracing_subscriber::{fmt::format::FmtSpan, layer::Layer, reload, reload::Handle, EnvFilter};
+use tracing_subscriber::{
+    fmt::format::FmtSpan,
+    layer::{Layer, SubscriberExt},
+    reload,
+    reload::Handle,
+    EnvFilter,
+};
 
+use super::RecentLogs;
 use crate::util::formats::{ActyxOSResult, LogSeverity};
 
 // Wrapper trait to contain the types
@@ -26,7 +33,7 @@ pub struct LoggingSink {
 }
 
 impl LoggingSink {
-    pub fn new(level: LogSeverity, log_no_color: bool, log_as_json: bool) -> Self {
+    pub fn new(level: LogSeverity, log_no_color: bool, log_as_json: bool, recent_logs: RecentLogs) -> Self {
         // If the `RUST_LOG` env var is set, the filter is statically set to
         // said value. This supports the common RUST_LOG syntax, see
         // https://docs.rs/tracing-subscriber/0.2.17/tracing_subscriber/fmt/index.html#filtering-events-with-environment-variables
@@ -56,7 +63,8 @@ impl LoggingSink {
                 .with_writer(std::io::stderr)
                 .with_filter_reloading();
             let filter_handle = Box::new(builder.reload_handle());
-            let subscriber = builder.finish();
+            // the filter applies to the recent logs as well
+            let subscriber = builder.finish().with(recent_logs);
             #[cfg(target_os = "android")]
             let subscriber = tracing_android::layer("com.actyx").unwrap().with_subscriber(subscriber);
             let sub = Box::new(subscriber);
@@ -68,7 +76,7 @@ impl LoggingSink {
                 .with_writer(std::io::stderr)
                 .with_filter_reloading();
             let filter_handle = Box::new(builder.reload_handle());
-            let subscriber = builder.finish();
+            let subscriber = builder.finish().with(recent_logs);
             #[cfg(target_os = "android")]
             let subscriber = tracing_android::layer("com.actyx").unwrap().with_subscriber(subscriber);
             let sub = Box::new(subscriber);
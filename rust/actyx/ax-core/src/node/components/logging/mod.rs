@@ -1,18 +1,26 @@
 use self::logging_sink::LoggingSink;
 use super::{Component, ComponentRequest};
-use crate::{node::node_settings::Settings, util::formats::LogSeverity};
+use crate::{
+    node::node_settings::{LogBuffer, Settings},
+    util::formats::LogSeverity,
+};
 use anyhow::Result;
 use crossbeam::channel::{Receiver, Sender};
 use parking_lot::Mutex;
 use std::sync::Arc;
 
 mod logging_sink;
+mod recent_logs;
+
+pub use recent_logs::RecentLogs;
+
 pub struct Logging {
     rx: Receiver<ComponentRequest<()>>,
     logging_sink: Arc<Mutex<LoggingSink>>,
+    recent_logs: RecentLogs,
 }
 
-impl Component<(), LogSeverity> for Logging {
+impl Component<(), (LogSeverity, LogBuffer)> for Logging {
     fn get_type() -> &'static str {
         "logging"
     }
@@ -22,13 +30,14 @@ impl Component<(), LogSeverity> for Logging {
     fn handle_request(&mut self, _: ()) -> Result<()> {
         Ok(())
     }
-    fn extract_settings(&self, settings: Settings) -> Result<LogSeverity> {
-        Ok(settings.admin.log_levels.node)
+    fn extract_settings(&self, settings: Settings) -> Result<(LogSeverity, LogBuffer)> {
+        Ok((settings.admin.log_levels.node, settings.admin.log_buffer))
     }
-    fn set_up(&mut self, settings: LogSeverity) -> bool {
-        if let Err(e) = self.logging_sink.lock().set_level(settings) {
+    fn set_up(&mut self, (level, log_buffer): (LogSeverity, LogBuffer)) -> bool {
+        if let Err(e) = self.logging_sink.lock().set_level(level) {
             eprintln!("Error setting new log level: {}", e);
         }
+        self.recent_logs.set_limits(log_buffer);
         false
     }
     fn start(&mut self, snd: Sender<anyhow::Result<()>>) -> Result<()> {
@@ -40,12 +49,30 @@ impl Component<(), LogSeverity> for Logging {
     }
 }
 impl Logging {
-    pub fn new(rx: Receiver<ComponentRequest<()>>, level: LogSeverity, log_no_color: bool, log_as_json: bool) -> Self {
-        let logging_sink = Arc::new(Mutex::new(LoggingSink::new(level, log_no_color, log_as_json)));
-        Self { rx, logging_sink }
+    pub fn new(
+        rx: Receiver<ComponentRequest<()>>,
+        level: LogSeverity,
+        log_no_color: bool,
+        log_as_json: bool,
+        recent_logs: RecentLogs,
+    ) -> Self {
+        let logging_sink = Arc::new(Mutex::new(LoggingSink::new(
+            level,
+            log_no_color,
+            log_as_json,
+            recent_logs.clone(),
+        )));
+        Self {
+            rx,
+            logging_sink,
+            recent_logs,
+        }
     }
     pub fn set_log_level(&self, level: LogSeverity) -> anyhow::Result<()> {
         self.logging_sink.lock().set_level(level)?;
         Ok(())
     }
+    pub fn set_log_buffer(&self, log_buffer: LogBuffer) {
+        self.recent_logs.set_limits(log_buffer);
+    }
 }
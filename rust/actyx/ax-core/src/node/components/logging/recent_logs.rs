@@ -0,0 +1,215 @@
+use crate::{
+    node::node_settings::LogBuffer,
+    util::formats::{LogEntry, LogSeverity},
+};
+use ax_types::Timestamp;
+use parking_lot::Mutex;
+use std::{collections::VecDeque, fmt, sync::Arc};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Appended to messages and field values that were cut short
+const TRUNCATED: &str = "…";
+
+/// Ring buffer of the most recent log records, installed as a layer of the global subscriber.
+///
+/// It only sees the records enabled by the configured log level, changing the level doesn't touch
+/// the records already kept.
+#[derive(Clone)]
+pub struct RecentLogs(Arc<Mutex<RecentLogsInner>>);
+
+struct RecentLogsInner {
+    entries: VecDeque<LogEntry>,
+    limits: LogBuffer,
+}
+
+impl Default for RecentLogs {
+    fn default() -> Self {
+        Self::new(LogBuffer::default())
+    }
+}
+
+impl RecentLogs {
+    pub fn new(limits: LogBuffer) -> Self {
+        Self(Arc::new(Mutex::new(RecentLogsInner {
+            entries: VecDeque::new(),
+            limits,
+        })))
+    }
+
+    /// Applies new limits, dropping the oldest records if fewer are to be kept.
+    pub fn set_limits(&self, limits: LogBuffer) {
+        let mut inner = self.0.lock();
+        while inner.entries.len() > limits.records as usize {
+            inner.entries.pop_front();
+        }
+        inner.limits = limits;
+    }
+
+    fn push(&self, mut entry: LogEntry) {
+        let mut inner = self.0.lock();
+        let max_len = inner.limits.max_message_length as usize;
+        truncate(&mut entry.message, max_len);
+        for value in entry.fields.values_mut() {
+            truncate(value, max_len);
+        }
+        if inner.limits.records == 0 {
+            return;
+        }
+        while inner.entries.len() >= inner.limits.records as usize {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(entry);
+    }
+
+    /// The most recent `count` records matching the filters, oldest first.
+    ///
+    /// `target_filter` matches targets by prefix, as in `RUST_LOG`.
+    pub fn tail(&self, count: usize, min_level: Option<&LogSeverity>, target_filter: Option<&str>) -> Vec<LogEntry> {
+        let min_rank = min_level.map(rank).unwrap_or_default();
+        let inner = self.0.lock();
+        let mut entries = inner
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| rank(&entry.level) >= min_rank)
+            .filter(|entry| target_filter.map_or(true, |target| entry.target.starts_with(target)))
+            .take(count)
+            .cloned()
+            .collect::<Vec<_>>();
+        entries.reverse();
+        entries
+    }
+}
+
+/// Orders the plain severities, a `RUST_LOG` directive is no severity and admits everything.
+fn rank(level: &LogSeverity) -> u8 {
+    match level {
+        LogSeverity::Trace | LogSeverity::RustLog(_) => 0,
+        LogSeverity::Debug => 1,
+        LogSeverity::Info => 2,
+        LogSeverity::Warn => 3,
+        LogSeverity::Error => 4,
+    }
+}
+
+fn truncate(s: &mut String, max_len: usize) {
+    if s.len() > max_len {
+        let mut end = max_len;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s.push_str(TRUNCATED);
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RecentLogs {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // records from the `log` crate carry their real target and level in fields
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let mut entry = LogEntry {
+            timestamp: Timestamp::now(),
+            level: metadata.level().into(),
+            target: metadata.target().to_owned(),
+            message: String::new(),
+            fields: Default::default(),
+        };
+        event.record(&mut EntryVisitor(&mut entry));
+        self.push(entry);
+    }
+}
+
+struct EntryVisitor<'a>(&'a mut LogEntry);
+
+impl<'a> Visit for EntryVisitor<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{:?}", value));
+    }
+}
+
+impl<'a> EntryVisitor<'a> {
+    fn record(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => self.0.message = value,
+            name if name.starts_with("log.") => {}
+            name => {
+                self.0.fields.insert(name.to_owned(), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+    fn limits(records: u64, max_message_length: u64) -> LogBuffer {
+        LogBuffer {
+            records,
+            max_message_length,
+        }
+    }
+
+    fn messages(entries: &[LogEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.message.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_and_orders_records() {
+        let logs = RecentLogs::new(limits(100, 1000));
+        tracing::subscriber::with_default(Registry::default().with(logs.clone()), || {
+            tracing::debug!(target: "ax::swarm", "one");
+            tracing::warn!(target: "ax::swarm::gossip", peer = "a", "two");
+            tracing::info!(target: "ax::api", "three");
+            tracing::error!(target: "ax::swarm", "four");
+        });
+
+        assert_eq!(messages(&logs.tail(10, None, None)), ["one", "two", "three", "four"]);
+        assert_eq!(messages(&logs.tail(2, None, None)), ["three", "four"]);
+        assert_eq!(
+            messages(&logs.tail(10, Some(&LogSeverity::Info), None)),
+            ["two", "three", "four"]
+        );
+        assert_eq!(
+            messages(&logs.tail(10, Some(&LogSeverity::Warn), Some("ax::swarm"))),
+            ["two", "four"]
+        );
+        assert_eq!(messages(&logs.tail(1, None, Some("ax::swarm"))), ["four"]);
+
+        let two = &logs.tail(10, None, Some("ax::swarm::gossip"))[0];
+        assert_eq!(two.level, LogSeverity::Warn);
+        assert_eq!(two.fields.get("peer").map(String::as_str), Some("a"));
+    }
+
+    #[test]
+    fn bounds_records_and_lengths() {
+        let logs = RecentLogs::new(limits(3, 5));
+        tracing::subscriber::with_default(Registry::default().with(logs.clone()), || {
+            for i in 0..5 {
+                tracing::info!("{}", i);
+            }
+            tracing::info!(detail = "abcdefgh", "äöüäöü");
+        });
+
+        let tail = logs.tail(10, None, None);
+        assert_eq!(messages(&tail), ["3", "4", "äö…"]);
+        assert_eq!(tail[2].fields.get("detail").map(String::as_str), Some("abcde…"));
+
+        // keeping fewer records drops the oldest, keeping more loses nothing
+        logs.set_limits(limits(2, 5));
+        assert_eq!(messages(&logs.tail(10, None, None)), ["4", "äö…"]);
+        logs.set_limits(limits(10, 5));
+        assert_eq!(messages(&logs.tail(10, None, None)), ["4", "äö…"]);
+    }
+}
@@ -1,4 +1,5 @@
 use super::{formats::ShutdownReason, node_settings::Settings, util::spawn_with_name};
+use crate::util::formats::ComponentStatus;
 use anyhow::Result;
 use crossbeam::{channel, select};
 use std::thread::JoinHandle;
@@ -18,6 +19,8 @@ pub enum ComponentRequest<A> {
     RegisterSupervisor(channel::Sender<(ComponentType, ComponentState)>),
     /// Global Settings have changed
     SettingsChanged(Box<Settings>),
+    /// Report the component's status, answered right away from its thread
+    HealthCheck(channel::Sender<ComponentStatus>),
     /// Trigger a stop and restart
     Restart,
     /// Trigger graceful shutdown
@@ -121,6 +124,13 @@ where
     /// method.
     fn stop(&mut self) -> Result<()>;
 
+    /// Answer a health check. `lifecycle` is the status as tracked by
+    /// `Component::loop_on_rx`; components that can detect being degraded
+    /// while running should report that here.
+    fn health(&self, lifecycle: ComponentStatus) -> ComponentStatus {
+        lifecycle
+    }
+
     /// Convenience implementation managing the lifecycle of a `Component` as
     /// driven by `ComponentRequest`s: New settings are converted to component
     /// specific ones; if they have been changed (as determined by Eq), the
@@ -131,11 +141,16 @@ where
         let mut supervisor: Option<channel::Sender<(ComponentType, ComponentState)>> = None;
         let (err_tx, err_rx) = channel::bounded::<anyhow::Result<()>>(8);
         let mut has_started = false;
+        let mut status = ComponentStatus::Stopped;
         loop {
             select! {
                 recv(err_rx) -> result => {
                     tracing::debug!("Component \"{}\": started", Self::get_type());
                     let result = result.expect("We keep another Sender around, thus channel can't be disconnected");
+                    status = match &result {
+                        Ok(()) => ComponentStatus::Running,
+                        Err(e) => ComponentStatus::Degraded { reason: format!("{:#}", e) },
+                    };
                     state_change!(
                         supervisor,
                         Self::get_type(),
@@ -169,6 +184,7 @@ where
                                             state_change!(supervisor, Self::get_type(), ComponentState::Stopped, self.stop());
                                         }
                                         has_started = true;
+                                        status = ComponentStatus::Starting;
                                         state_change!(
                                             supervisor,
                                             Self::get_type(),
@@ -183,6 +199,7 @@ where
                                     state_change!(supervisor, Self::get_type(), ComponentState::Stopped, self.stop());
                                 }
                                 has_started = true;
+                                status = ComponentStatus::Starting;
                                 state_change!(
                                     supervisor,
                                     Self::get_type(),
@@ -190,6 +207,9 @@ where
                                     self.start(err_tx.clone())
                                 );
                             }
+                            ComponentRequest::HealthCheck(tx) => {
+                                let _ = tx.send(self.health(status.clone()));
+                            }
                             ComponentRequest::<RequestType>::Shutdown(_) => break,
                         }
 
@@ -344,6 +364,36 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn health_check_follows_lifecycle() -> Result<()> {
+        let (tx, rx) = channel::bounded(42);
+        let c = SimpleComponent::new(rx, Default::default());
+        let h = c.spawn()?;
+        let (tx_supervisor, rx_supervisor) = channel::bounded(42);
+        tx.send(ComponentRequest::RegisterSupervisor(tx_supervisor))?;
+        let health = || -> Result<ComponentStatus> {
+            let (health_tx, health_rx) = channel::bounded(1);
+            tx.send(ComponentRequest::HealthCheck(health_tx))?;
+            Ok(health_rx.recv_timeout(_3SEC)?)
+        };
+        assert_eq!(health()?, ComponentStatus::Stopped);
+
+        tx.send(ComponentRequest::SettingsChanged(Box::new(Settings::sample())))?;
+        assert_eq!(
+            rx_supervisor.recv_timeout(_3SEC)?,
+            ("test".into(), ComponentState::Starting)
+        );
+        assert_eq!(
+            rx_supervisor.recv_timeout(_3SEC)?,
+            ("test".into(), ComponentState::Started)
+        );
+        assert_eq!(health()?, ComponentStatus::Running);
+
+        tx.send(ComponentRequest::Shutdown(ShutdownReason::TriggeredByHost))?;
+        h.join().unwrap();
+        Ok(())
+    }
+
     #[test]
     fn setup_start_runtime_error() -> Result<()> {
         let (tx, rx) = channel::bounded(42);
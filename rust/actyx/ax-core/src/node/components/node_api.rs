@@ -1,14 +1,16 @@
-use super::store::StoreTx;
+use super::store::{payload_schemas, query_timeouts, StoreTx};
 use crate::{
+    api::{PayloadSchemas, QueryTimeouts},
     node::{
-        components::{Component, ComponentRequest},
+        components::{logging::RecentLogs, Component, ComponentRequest},
         formats::ExternalEvent,
         node_settings::Settings,
     },
-    util::SocketAddrHelper,
+    swarm::PeerQuarantine,
+    util::{variable::Reader, SocketAddrHelper},
 };
 use anyhow::Result;
-use ax_types::NodeId;
+use ax_types::{service::SwarmState, NodeId};
 use crossbeam::channel::{Receiver, Sender};
 use libp2p::PeerId;
 use parking_lot::Mutex;
@@ -30,6 +32,9 @@ impl NodeApi {
         rx: Receiver<ComponentRequest<()>>,
         store_dir: PathBuf,
         store: StoreTx,
+        quarantine: PeerQuarantine,
+        swarm_state: Reader<SwarmState>,
+        recent_logs: RecentLogs,
     ) -> Self {
         Self {
             node_id,
@@ -41,6 +46,9 @@ impl NodeApi {
             settings: Default::default(),
             store_dir,
             store,
+            quarantine,
+            swarm_state,
+            recent_logs,
         }
     }
 }
@@ -55,10 +63,19 @@ pub struct NodeApi {
     settings: Arc<Mutex<NodeApiSettings>>,
     store_dir: PathBuf,
     store: StoreTx,
+    quarantine: PeerQuarantine,
+    swarm_state: Reader<SwarmState>,
+    recent_logs: RecentLogs,
 }
 #[derive(Default, PartialEq, Eq, Clone)]
 pub struct NodeApiSettings {
     pub authorized_keys: Vec<PeerId>,
+    /// Keys allowed to read sensitive settings values
+    pub read_secrets_keys: Vec<PeerId>,
+    /// Timeouts for queries received via the admin port
+    pub query_timeouts: QueryTimeouts,
+    /// Payload schemas for linting queries received via the admin port
+    pub payload_schemas: PayloadSchemas,
 }
 impl Component<(), NodeApiSettings> for NodeApi {
     fn get_type() -> &'static str {
@@ -98,6 +115,9 @@ impl Component<(), NodeApiSettings> for NodeApi {
             self.store_dir.clone(),
             self.store.clone(),
             self.settings.clone(),
+            self.quarantine.clone(),
+            self.swarm_state.clone(),
+            self.recent_logs.clone(),
         ))?;
 
         // mk_swarm has bound the listen sockets, so declare victory
@@ -115,20 +135,29 @@ impl Component<(), NodeApiSettings> for NodeApi {
 }
 
 fn extract_settings_into_node_settings(s: Settings) -> Result<NodeApiSettings> {
-    let authorized_keys: Vec<PeerId> = s
-        .admin
-        .authorized_users
-        .iter()
+    Ok(NodeApiSettings {
+        authorized_keys: parse_keys(&s.admin.authorized_users, "authorizedUsers"),
+        read_secrets_keys: parse_keys(&s.admin.read_secrets, "readSecrets"),
+        query_timeouts: if s.api.events.admin_queries_unlimited {
+            QueryTimeouts::unlimited()
+        } else {
+            query_timeouts(&s.api.events)
+        },
+        payload_schemas: payload_schemas(&s.api.events),
+    })
+}
+
+fn parse_keys(keys: &[String], name: &str) -> Vec<PeerId> {
+    keys.iter()
         .enumerate()
         .filter_map(|(i, pk)| match crate::crypto::PublicKey::from_str(pk) {
             Ok(pk) => Some(PeerId::from(pk)),
             Err(_) => {
-                tracing::warn!("Found invalid entry in config/admin/authorizedUsers at index: {}", i);
+                tracing::warn!("Found invalid entry in config/admin/{} at index: {}", name, i);
                 None
             }
         })
-        .collect();
-    Ok(NodeApiSettings { authorized_keys })
+        .collect()
 }
 
 #[cfg(test)]
@@ -1,15 +1,27 @@
-use super::{Component, ComponentRequest};
+use super::{
+    swarm_observer::{self, SwarmObserver},
+    Component, ComponentRequest,
+};
 use crate::{
-    api::{licensing::Licensing, NodeInfo},
+    api::{licensing::Licensing, NodeInfo, PayloadSchemas, QueryTimeouts},
     crypto::KeyStoreRef,
-    node::{node_settings::Settings, BindTo},
+    node::{
+        backup::{self, BackupManifest, BackupProgress},
+        node_settings::{Events, Settings},
+        BindTo,
+    },
     swarm::{
         blob_store::BlobStore,
         event_store_ref::{EventStoreHandler, EventStoreRef, EventStoreRequest},
-        BanyanStore, DbPath, EphemeralEventsConfig, EventRoute, GossipMessage, Ipfs, SwarmConfig,
+        BanyanStore, DbPath, DecisionRecord, EphemeralEventsConfig, EventRoute, EventRoutes, Ipfs, LamportConfig,
+        MaintenanceSchedule, OffsetsComparison, PeerQuarantine, QuarantineConfig, RootMapCadence, RuntimeSwarmSettings,
+        SwarmConfig,
     },
     util::{
-        formats::{Connection, Failure, NodeCycleCount, Peer, PeerInfo, PingStats},
+        formats::{
+            Connection, Failure, MaintenanceStatus, NodeCycleCount, Peer, PeerInfo, PingStats, PreviousTopicTraffic,
+            QuarantinedPeer,
+        },
         variable::Reader,
         SocketAddrHelper,
     },
@@ -25,20 +37,30 @@ use libp2p::{multiaddr::Protocol, Multiaddr};
 use parking_lot::Mutex;
 use std::{
     convert::TryInto,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 use tracing::*;
 
 pub(crate) enum StoreRequest {
     NodesInspect(oneshot::Sender<Result<InspectResponse>>),
     EventsV2(EventStoreRequest),
     ActiveTopic(oneshot::Sender<String>),
+    SwarmAddListenAddr(Multiaddr, oneshot::Sender<Result<Multiaddr>>),
+    SwarmRemoveListenAddr(Multiaddr, oneshot::Sender<Result<()>>),
+    CompareOffsets(PeerId, oneshot::Sender<Result<OffsetsComparison>>),
+    DecisionLogTail(usize, oneshot::Sender<Result<Vec<DecisionRecord>>>),
+    /// Back up the node into the archive at the given path, see [`backup::create`]
+    BackupCreate(
+        PathBuf,
+        mpsc::UnboundedSender<BackupProgress>,
+        oneshot::Sender<Result<BackupManifest>>,
+    ),
 }
 
 impl std::fmt::Debug for StoreRequest {
@@ -50,6 +72,11 @@ impl std::fmt::Debug for StoreRequest {
                 f.debug_tuple("EventsV2").field(&req.as_str()).finish()
             }
             Self::ActiveTopic(_) => f.debug_tuple("ActiveTopic").finish(),
+            Self::SwarmAddListenAddr(addr, _) => f.debug_tuple("SwarmAddListenAddr").field(addr).finish(),
+            Self::SwarmRemoveListenAddr(addr, _) => f.debug_tuple("SwarmRemoveListenAddr").field(addr).finish(),
+            Self::CompareOffsets(peer, _) => f.debug_tuple("CompareOffsets").field(peer).finish(),
+            Self::DecisionLogTail(count, _) => f.debug_tuple("DecisionLogTail").field(count).finish(),
+            Self::BackupCreate(path, ..) => f.debug_tuple("BackupCreate").field(path).finish(),
         }
     }
 }
@@ -61,6 +88,11 @@ pub(crate) struct InspectResponse {
     pub announce_addrs: Vec<String>,
     pub connections: Vec<Connection>,
     pub known_peers: Vec<Peer>,
+    pub gossip_interval: Duration,
+    pub quarantined_peers: Vec<QuarantinedPeer>,
+    pub previous_topics: Vec<PreviousTopicTraffic>,
+    pub maintenance: Option<MaintenanceStatus>,
+    pub lamport_warnings: Vec<String>,
 }
 
 pub(crate) type StoreTx = Sender<ComponentRequest<StoreRequest>>;
@@ -70,6 +102,34 @@ pub(crate) type StoreTx = Sender<ComponentRequest<StoreRequest>>;
 pub(crate) struct StoreConfig {
     swarm_config: SwarmConfig,
     licensing: Licensing,
+    query_timeouts: QueryTimeouts,
+    payload_schemas: PayloadSchemas,
+}
+
+/// Query timeouts for the events API as configured in the settings, where 0 means unlimited.
+pub(crate) fn query_timeouts(events: &Events) -> QueryTimeouts {
+    let secs = |secs| (secs > 0).then(|| Duration::from_secs(secs));
+    QueryTimeouts {
+        default: secs(events.query_timeout),
+        max: secs(events.max_query_timeout),
+    }
+}
+
+/// Payload schemas for the events API as configured in the settings.
+pub(crate) fn payload_schemas(events: &Events) -> PayloadSchemas {
+    PayloadSchemas::new(
+        events
+            .payload_schemas
+            .iter()
+            .filter_map(|(tag, schema)| match tag.parse() {
+                Ok(tag) => Some((tag, schema.clone())),
+                Err(e) => {
+                    warn!("ignoring payload schema for invalid tag `{}`: {}", tag, e);
+                    None
+                }
+            })
+            .collect(),
+    )
 }
 
 fn without_peer(addr: &Multiaddr) -> String {
@@ -153,6 +213,62 @@ fn known_peers(ipfs: &Ipfs) -> Vec<Peer> {
         .collect()
 }
 
+fn quarantined_peers(quarantine: &PeerQuarantine) -> Vec<QuarantinedPeer> {
+    quarantine
+        .quarantined()
+        .into_iter()
+        .map(|q| QuarantinedPeer {
+            peer_id: q.peer_id.to_string(),
+            reason: q.reason,
+            remaining_secs: q.remaining.as_secs(),
+        })
+        .collect()
+}
+
+fn previous_topics(store: &BanyanStore) -> Vec<PreviousTopicTraffic> {
+    store
+        .previous_topics()
+        .get()
+        .into_iter()
+        .map(|t| PreviousTopicTraffic {
+            topic: t.topic,
+            messages: t.messages,
+            last_received: t.last_received.map(|ts| {
+                DateTime::<Utc>::try_from(ts)
+                    .map(|dt| dt.to_rfc3339_opts(Millis, true))
+                    .unwrap_or_else(|_| ts.to_string())
+            }),
+            remaining_secs: t.remaining.as_secs(),
+        })
+        .collect()
+}
+
+fn maintenance_status(schedule: &MaintenanceSchedule) -> Option<MaintenanceStatus> {
+    let state = schedule.state();
+    state.windows.then(|| MaintenanceStatus {
+        in_window: state.in_window(),
+        window_end: state.window_end.map(|t| t.to_rfc3339_opts(Millis, true)),
+        next_window: state.next_window.map(|t| t.to_rfc3339_opts(Millis, true)),
+    })
+}
+
+/// The name of the local storage files for the given topic configuration.
+///
+/// The event store is named after the topic, so after renaming the topic the store of the first
+/// previous topic is used until one for the new topic exists.
+fn storage_topic(working_dir: &Path, topic: &str, previous_topics: &[String]) -> String {
+    let file_name = |topic: &str| topic.replace('/', "_");
+    let current = file_name(topic);
+    if working_dir.join(format!("{}.sqlite", current)).exists() {
+        return current;
+    }
+    previous_topics
+        .iter()
+        .map(|t| file_name(t))
+        .find(|t| working_dir.join(format!("{}.sqlite", t)).exists())
+        .unwrap_or(current)
+}
+
 impl Component<StoreRequest, StoreConfig> for Store {
     fn get_type() -> &'static str {
         "Swarm"
@@ -173,6 +289,11 @@ impl Component<StoreRequest, StoreConfig> for Store {
                         announce_addrs: announce_addrs(ipfs),
                         connections: connections(ipfs),
                         known_peers: known_peers(ipfs),
+                        gossip_interval: store.root_map_interval(),
+                        quarantined_peers: quarantined_peers(store.quarantine()),
+                        previous_topics: previous_topics(store),
+                        maintenance: maintenance_status(store.maintenance()),
+                        lamport_warnings: store.lamport_warnings(),
                     }));
                 } else {
                     let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
@@ -187,12 +308,81 @@ impl Component<StoreRequest, StoreConfig> for Store {
                 let state = self.state.as_ref().expect("Internal store state should be valid.");
                 let _ = tx.send(state.store.get_topic());
             }
+            StoreRequest::SwarmAddListenAddr(addr, tx) => {
+                if let Some(InternalStoreState { rt, store, .. }) = self.state.as_ref() {
+                    let store = store.clone();
+                    rt.spawn(async move {
+                        let _ = tx.send(store.listen_on(addr).await);
+                    });
+                } else {
+                    let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
+                }
+            }
+            StoreRequest::SwarmRemoveListenAddr(addr, tx) => {
+                if let Some(InternalStoreState { store, .. }) = self.state.as_ref() {
+                    let _ = tx.send(store.stop_listening_on(addr));
+                } else {
+                    let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
+                }
+            }
+            StoreRequest::CompareOffsets(peer, tx) => {
+                if let Some(InternalStoreState { rt, store, .. }) = self.state.as_ref() {
+                    let store = store.clone();
+                    rt.spawn(async move {
+                        let _ = tx.send(store.compare_offsets(peer).await);
+                    });
+                } else {
+                    let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
+                }
+            }
+            StoreRequest::DecisionLogTail(count, tx) => {
+                if let Some(InternalStoreState { store, .. }) = self.state.as_ref() {
+                    let _ = tx.send(Ok(store.decision_log_tail(count)));
+                } else {
+                    let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
+                }
+            }
+            StoreRequest::BackupCreate(path, progress, tx) => {
+                if let Some(InternalStoreState { rt, store, .. }) = self.state.as_ref() {
+                    let store = store.clone();
+                    // the store directory is within the node's working directory
+                    let working_dir = self.working_dir.parent().unwrap_or(&self.working_dir).to_owned();
+                    rt.spawn_blocking(move || {
+                        let result = backup::create(&store, &working_dir, &path, |p| {
+                            let _ = progress.send(p);
+                        });
+                        // ends the progress before the result arrives
+                        drop(progress);
+                        let _ = tx.send(result);
+                    });
+                } else {
+                    let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
+                }
+            }
         }
         Ok(())
     }
     fn set_up(&mut self, settings: StoreConfig) -> bool {
+        // maintenance windows are applied through the shared schedule, without restarting
+        self.maintenance.set_config(settings.swarm_config.maintenance.clone());
+        let needs_restart = match &self.store_config {
+            Some(current) => {
+                let mut current = current.clone();
+                current.swarm_config.maintenance = settings.swarm_config.maintenance.clone();
+                // likewise the settings of the periodic tasks, as long as the store accepts them
+                let runtime = RuntimeSwarmSettings::from(&settings.swarm_config);
+                current.swarm_config.set_runtime_settings(runtime.clone());
+                // and the event routes
+                let routes_changed = current.swarm_config.event_routes != settings.swarm_config.event_routes;
+                current.swarm_config.event_routes = settings.swarm_config.event_routes.clone();
+                current != settings
+                    || !self.update_runtime_settings(runtime)
+                    || (routes_changed && !self.update_event_routes(settings.swarm_config.event_routes.clone()))
+            }
+            None => true,
+        };
         self.store_config = Some(settings);
-        true
+        needs_restart
     }
     fn start(&mut self, snd: Sender<anyhow::Result<()>>) -> Result<()> {
         debug_assert!(self.state.is_none());
@@ -220,6 +410,8 @@ impl Component<StoreRequest, StoreConfig> for Store {
             let swarm_config = cfg.swarm_config;
             let swarm_observer = self.swarm_observer.clone();
             let swarm_state = self.swarm_state.clone();
+            let query_timeouts = cfg.query_timeouts;
+            let payload_schemas = cfg.payload_schemas;
             let store = rt.block_on(async move {
                 let blobs = BlobStore::new(
                     swarm_config
@@ -228,10 +420,26 @@ impl Component<StoreRequest, StoreConfig> for Store {
                         .map(DbPath::File)
                         .unwrap_or(DbPath::Memory),
                 )?;
-                let store = BanyanStore::new(swarm_config, swarm_observer).await?;
+                let store =
+                    BanyanStore::new(swarm_config, swarm_observer.clone().contramap(SwarmObserver::from)).await?;
+                store.spawn_task(
+                    "swarm_observer_peers".to_owned(),
+                    swarm_observer::observe_peers(store.clone(), swarm_observer).boxed(),
+                );
                 store.spawn_task(
                     "api".to_owned(),
-                    crate::api::run(node_info, store.clone(), event_store, blobs, bind_api, snd, swarm_state).boxed(),
+                    crate::api::run(
+                        node_info,
+                        store.clone(),
+                        event_store,
+                        blobs,
+                        bind_api,
+                        snd,
+                        swarm_state,
+                        query_timeouts,
+                        payload_schemas,
+                    )
+                    .boxed(),
                 );
                 Ok::<BanyanStore, anyhow::Error>(store)
             })?;
@@ -259,22 +467,35 @@ impl Component<StoreRequest, StoreConfig> for Store {
         let psk: [u8; 32] = base64::decode(&s.swarm.swarm_key)?
             .try_into()
             .map_err(|_| anyhow::anyhow!("invalid psk"))?;
-        let topic = s.swarm.topic.replace('/', "_");
+        let topic = storage_topic(&self.working_dir, &s.swarm.topic, &s.swarm.previous_topics);
         let db_path = self.working_dir.join(format!("{}.sqlite", topic));
         let index_store = Some(self.working_dir.join(format!("{}-index", topic)));
         let blob_store = Some(self.working_dir.join(format!("{}-blobs", topic)));
+        let decision_log_path = s
+            .swarm
+            .decision_log
+            .then(|| self.working_dir.join(format!("{}-decisions.cbor", topic)));
         let read_only = s.api.events.read_only;
+        let query_timeouts = query_timeouts(&s.api.events);
+        let payload_schemas = payload_schemas(&s.api.events);
 
         let event_routes = s
             .event_routing
             .routes
             .into_iter()
             .map(|e| EventRoute::new(e.from, e.into))
-            .collect();
+            .collect::<Vec<_>>();
+        if let Err(errors) = EventRoutes(event_routes.clone()).validate() {
+            let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            anyhow::bail!("invalid event routes: {}", errors.join("; "));
+        }
         let ephemeral_event_config = EphemeralEventsConfig::from(s.event_routing.streams);
 
         let swarm_config = SwarmConfig {
-            topic,
+            // the gossip topic has always been the file name of the store
+            topic: s.swarm.topic.replace('/', "_"),
+            previous_topics: s.swarm.previous_topics.iter().map(|t| t.replace('/', "_")).collect(),
+            previous_topics_grace: Duration::from_secs(s.swarm.previous_topics_grace_period),
             index_store,
             blob_store,
             keypair: Some(keypair),
@@ -302,18 +523,47 @@ impl Component<StoreRequest, StoreConfig> for Store {
             block_cache_size: s.swarm.block_cache_size,
             block_gc_interval: Duration::from_secs(s.swarm.block_gc_interval),
             enable_metrics: s.swarm.metrics_interval > 0,
-            metrics_interval: Duration::from_secs(s.swarm.metrics_interval),
+            // zero disables the metrics, the interval is unused then but must still be valid
+            metrics_interval: Duration::from_secs(s.swarm.metrics_interval.max(1)),
             ping_timeout: Duration::from_secs(s.swarm.ping_timeout),
             bitswap_timeout: Duration::from_secs(s.swarm.bitswap_timeout),
             branch_cache_size: s.swarm.branch_cache_size,
-            cadence_root_map: Duration::from_secs(s.swarm.gossip_interval),
+            cadence_root_map: RootMapCadence::adaptive(
+                Duration::from_secs(s.swarm.gossip_interval),
+                Duration::from_secs(s.swarm.gossip_max_interval),
+            ),
+            cadence_fast_path_batch: Duration::from_millis(s.swarm.fast_path_batch),
             event_routes,
             ephemeral_event_config,
+            quarantine: QuarantineConfig {
+                threshold: s.swarm.quarantine_threshold,
+                window: Duration::from_secs(s.swarm.quarantine_window),
+                duration: Duration::from_secs(s.swarm.quarantine_duration),
+            },
+            peer_quarantine: self.quarantine.clone(),
+            maintenance: s.swarm.maintenance,
+            lamport: LamportConfig {
+                max_jump: s.swarm.max_lamport_jump,
+                ..Default::default()
+            },
+            maintenance_schedule: self.maintenance.clone(),
+            lazy_stream_loading: s.swarm.lazy_stream_loading,
+            known_peers_max_age: Duration::from_secs(s.swarm.known_peers_max_age),
+            enable_prometheus: s.swarm.prometheus,
+            decision_log_path,
+            max_connections: s.swarm.max_connections,
+            max_connections_per_peer: s.swarm.max_connections_per_peer,
+            payload_blob_threshold: s
+                .swarm
+                .payload_blob_threshold
+                .map(|threshold| usize::try_from(threshold).unwrap_or(usize::MAX)),
             ..SwarmConfig::basic()
         };
         Ok(StoreConfig {
             swarm_config,
             licensing: s.licensing,
+            query_timeouts,
+            payload_schemas,
         })
     }
 }
@@ -336,11 +586,43 @@ pub(crate) struct Store {
     number_of_threads: Option<usize>,
     node_cycle_count: NodeCycleCount,
     started_at: DateTime<Utc>,
-    swarm_observer: ActoRef<(PeerId, GossipMessage)>,
+    swarm_observer: ActoRef<SwarmObserver>,
     swarm_state: Reader<SwarmState>,
+    quarantine: PeerQuarantine,
+    maintenance: MaintenanceSchedule,
 }
 
 impl Store {
+    /// Returns whether the settings could be applied without restarting the store
+    fn update_runtime_settings(&self, settings: RuntimeSwarmSettings) -> bool {
+        let Some(InternalStoreState { rt, store, .. }) = self.state.as_ref() else {
+            return true;
+        };
+        // changes may spawn or abort store tasks
+        let _rt = rt.enter();
+        match store.update_settings(settings) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("restarting the store to apply the swarm settings: {:#}", err);
+                false
+            }
+        }
+    }
+
+    /// Returns whether the event routes could be applied without restarting the store
+    fn update_event_routes(&self, routes: Vec<EventRoute>) -> bool {
+        let Some(InternalStoreState { rt, store, .. }) = self.state.as_ref() else {
+            return true;
+        };
+        match rt.block_on(store.update_event_routes(routes)) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("restarting the store to apply the event routes: {:#}", err);
+                false
+            }
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         rx: Receiver<ComponentRequest<StoreRequest>>,
@@ -350,8 +632,9 @@ impl Store {
         keystore: KeyStoreRef,
         node_id: NodeId,
         node_cycle_count: NodeCycleCount,
-        swarm_observer: ActoRef<(PeerId, GossipMessage)>,
+        swarm_observer: ActoRef<SwarmObserver>,
         swarm_state: Reader<SwarmState>,
+        quarantine: PeerQuarantine,
     ) -> anyhow::Result<Self> {
         std::fs::create_dir_all(working_dir.clone())?;
         Ok(Self {
@@ -369,6 +652,8 @@ impl Store {
             started_at: Utc::now(),
             swarm_observer,
             swarm_state,
+            quarantine,
+            maintenance: MaintenanceSchedule::default(),
         })
     }
 }
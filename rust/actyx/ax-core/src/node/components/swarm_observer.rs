@@ -1,21 +1,30 @@
 use crate::{
+    crypto::peer_id_to_node_id,
     node::{actors::ComponentCommand, node_settings::Settings},
-    swarm::{GossipMessage, RootMap, RootUpdate},
+    swarm::{BanyanStore, DisconnectReason, GossipMessage, PeerEvent, RootMap, RootUpdate},
     util::variable::Writer,
 };
-use acto::{ActoCell, ActoInput, ActoRuntime};
+use acto::{ActoCell, ActoInput, ActoRef, ActoRuntime};
 use ax_types::{
-    service::{PeerStatus, SwarmState},
+    service::{PeerQuality, PeerStatus, SwarmState},
     NodeId, Offset, OffsetMap, StreamId, Timestamp,
 };
+use futures::StreamExt;
 use im::OrdMap;
 use ipfs_embed::PeerId;
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
+
+/// Weight of a new ping result in the average round-trip time
+const RTT_WEIGHT: f64 = 0.25;
 
 pub enum SwarmObserver {
     NewSettings(Settings),
     Gossip(PeerId, RootMap),
     StreamUpdate(PeerId, RootUpdate),
+    /// A ping to the peer was answered after the given round-trip time
+    PingResult(PeerId, Duration),
+    /// The last connection to the peer was closed
+    ConnectionClosed(PeerId, DisconnectReason),
 }
 
 impl From<ComponentCommand> for SwarmObserver {
@@ -66,6 +75,62 @@ impl HistoryEntry {
     }
 }
 
+/// Forwards the ping results and closed connections of the store to the swarm observer, runs until
+/// the store shuts down.
+pub(crate) async fn observe_peers(store: BanyanStore, observer: ActoRef<SwarmObserver>) {
+    let mut events = store.peer_events();
+    // the last ping seen per connected peer, as (current, decay_3, decay_10) round-trip times
+    let mut last_pings = HashMap::new();
+    while let Some(event) = events.next().await {
+        match event {
+            PeerEvent::NewInfo { peer } => {
+                // also sent for identify information, in which case the latest ping is repeated;
+                // a new sample moves the averages along with the current value
+                let rtt = store
+                    .ipfs()
+                    .peer_info(&peer)
+                    .and_then(|info| info.full_rtt())
+                    .filter(|rtt| rtt.failures() == 0)
+                    .map(|rtt| (rtt.current(), rtt.decay_3(), rtt.decay_10()));
+                if let Some(rtt) = rtt {
+                    if last_pings.insert(peer, rtt) != Some(rtt) {
+                        observer.send(SwarmObserver::PingResult(peer, rtt.0));
+                    }
+                }
+            }
+            PeerEvent::Disconnected { peer, reason } => {
+                last_pings.remove(&peer);
+                observer.send(SwarmObserver::ConnectionClosed(peer, reason));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn peer_quality<'a>(swarm_state: &'a mut SwarmState, peer_id: PeerId, now: Timestamp) -> Option<&'a mut PeerQuality> {
+    let node_id = match peer_id_to_node_id(peer_id) {
+        Ok(node_id) => node_id,
+        Err(err) => {
+            tracing::debug!(peer = %peer_id, "cannot track connection quality: {}", err);
+            return None;
+        }
+    };
+    let quality = swarm_state.peers_quality.entry(node_id).or_insert(PeerQuality {
+        rtt_micros: None,
+        pings: 0,
+        failures: 0,
+        last_seen: now,
+    });
+    quality.last_seen = now;
+    Some(quality)
+}
+
+fn evict_stale_peers(swarm_state: &mut SwarmState, cutoff: Timestamp) {
+    if swarm_state.peers_quality.values().any(|q| q.last_seen < cutoff) {
+        swarm_state.peers_quality.retain(|_, q| q.last_seen >= cutoff);
+    }
+}
+
 pub async fn swarm_observer(
     mut cell: ActoCell<SwarmObserver, impl ActoRuntime>,
     state: Writer<SwarmState>,
@@ -77,6 +142,7 @@ pub async fn swarm_observer(
     let mut gossip_cycle_micros = 10_000_000;
     let mut lookback_low_latency = 2 * gossip_cycle_micros;
     let mut lookback_high_latency = 5 * gossip_cycle_micros;
+    let mut peer_stale_after_micros = 600_000_000;
     while let ActoInput::Message(msg) = cell.recv().await {
         let now = Timestamp::now();
         let fresh_cutoff = now - 1_000_000;
@@ -87,6 +153,7 @@ pub async fn swarm_observer(
                     (settings.swarm.detection_cycles_low_latency * gossip_cycle_micros as f64) as u64;
                 lookback_high_latency =
                     (settings.swarm.detection_cycles_high_latency * gossip_cycle_micros as f64) as u64;
+                peer_stale_after_micros = settings.swarm.peer_stale_after.saturating_mul(1_000_000);
                 tracing::debug!(gossip = %gossip_cycle_micros, low = %lookback_low_latency, high = %lookback_high_latency, "new settings");
             }
             SwarmObserver::Gossip(peer_id, root_map) => {
@@ -120,7 +187,27 @@ pub async fn swarm_observer(
                     he.ingest(stream_id, offset);
                 }
             }
+            SwarmObserver::PingResult(peer_id, rtt) => {
+                tracing::trace!(peer = %peer_id, ?rtt, "ping result");
+                if let Some(quality) = peer_quality(&mut swarm_state, peer_id, now) {
+                    let rtt = rtt.as_micros() as u64;
+                    quality.rtt_micros = Some(match quality.rtt_micros {
+                        Some(avg) => (avg as f64 * (1.0 - RTT_WEIGHT) + rtt as f64 * RTT_WEIGHT) as u64,
+                        None => rtt,
+                    });
+                    quality.pings += 1;
+                }
+            }
+            SwarmObserver::ConnectionClosed(peer_id, reason) => {
+                tracing::debug!(peer = %peer_id, ?reason, "connection closed");
+                if let Some(quality) = peer_quality(&mut swarm_state, peer_id, now) {
+                    if reason == DisconnectReason::PingFailure {
+                        quality.failures += 1;
+                    }
+                }
+            }
         }
+        evict_stale_peers(&mut swarm_state, now - peer_stale_after_micros);
         let low_latency = now - lookback_low_latency;
         let high_latency = now - lookback_high_latency;
         prune_history(&mut history, high_latency);
@@ -215,3 +302,74 @@ fn get_history(history: &[HistoryEntry], at: Timestamp) -> Option<&HistoryEntry>
         Some(&history[idx - 1])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::KeyPair, util::variable::Reader};
+    use acto::AcTokio;
+    use std::time::Instant;
+
+    fn peer() -> (PeerId, NodeId) {
+        let key = KeyPair::generate().pub_key();
+        (key.into(), key.into())
+    }
+
+    fn wait_for(state: &Reader<SwarmState>, f: impl Fn(&SwarmState) -> bool) -> SwarmState {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let current = state.get_cloned();
+            if f(&current) {
+                return current;
+            }
+            assert!(Instant::now() < deadline, "timed out, state is {:?}", current);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn ping_results_and_failures() {
+        let rt = AcTokio::new("test", 1).unwrap();
+        let writer = Writer::new(SwarmState::default());
+        let state = writer.reader();
+        let observer = rt.spawn_actor("swarm_observer", |cell| swarm_observer(cell, writer)).me;
+        let (peer_a, node_a) = peer();
+        let (peer_b, node_b) = peer();
+
+        observer.send(SwarmObserver::PingResult(peer_a, Duration::from_millis(100)));
+        observer.send(SwarmObserver::PingResult(peer_a, Duration::from_millis(200)));
+        observer.send(SwarmObserver::ConnectionClosed(peer_a, DisconnectReason::PingFailure));
+        observer.send(SwarmObserver::ConnectionClosed(peer_b, DisconnectReason::Closed));
+
+        let state = wait_for(&state, |s| s.peers_quality.contains_key(&node_b));
+        let a = state.peers_quality[&node_a];
+        assert_eq!(a.rtt_micros, Some(125_000));
+        assert_eq!((a.pings, a.failures), (2, 1));
+        let b = state.peers_quality[&node_b];
+        assert_eq!(b.rtt_micros, None);
+        assert_eq!((b.pings, b.failures), (0, 0));
+        assert!(state.peers_status.is_empty());
+    }
+
+    #[test]
+    fn stale_peers_are_evicted() {
+        let rt = AcTokio::new("test", 1).unwrap();
+        let writer = Writer::new(SwarmState::default());
+        let state = writer.reader();
+        let observer = rt.spawn_actor("swarm_observer", |cell| swarm_observer(cell, writer)).me;
+        let mut settings = Settings::sample();
+        settings.swarm.peer_stale_after = 1;
+        observer.send(SwarmObserver::NewSettings(settings));
+        let (peer_a, node_a) = peer();
+        let (peer_b, node_b) = peer();
+
+        observer.send(SwarmObserver::PingResult(peer_a, Duration::from_millis(10)));
+        wait_for(&state, |s| s.peers_quality.contains_key(&node_a));
+        std::thread::sleep(Duration::from_millis(1100));
+
+        // eviction happens while processing the next message
+        observer.send(SwarmObserver::PingResult(peer_b, Duration::from_millis(10)));
+        let state = wait_for(&state, |s| s.peers_quality.contains_key(&node_b));
+        assert!(!state.peers_quality.contains_key(&node_a));
+    }
+}
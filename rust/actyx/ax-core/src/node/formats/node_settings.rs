@@ -1,4 +1,9 @@
-use crate::{api::licensing::Licensing, util::formats::LogSeverity};
+use crate::{
+    api::licensing::Licensing,
+    node::settings::system_scope,
+    settings::{mark_sensitive, Validator},
+    util::formats::LogSeverity,
+};
 use ax_aql::TagExpr;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -13,6 +18,11 @@ use std::collections::{BTreeMap, BTreeSet};
 #[serde(rename_all = "camelCase")]
 pub struct Events {
     pub read_only: bool,
+    pub query_timeout: u64,
+    pub max_query_timeout: u64,
+    pub admin_queries_unlimited: bool,
+    /// JSON schemas of event payloads by tag
+    pub payload_schemas: BTreeMap<String, serde_json::Value>,
     #[serde(rename = "_internal")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub internal: Option<serde_json::Value>,
@@ -20,11 +30,14 @@ pub struct Events {
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Swarm {
+    #[serde(with = "crate::settings::sensitive")]
     pub swarm_key: String,
     // TODO: use multiaddr
     pub initial_peers: BTreeSet<String>,
     pub announce_addresses: BTreeSet<String>,
     pub topic: String,
+    pub previous_topics: Vec<String>,
+    pub previous_topics_grace_period: u64,
     pub block_cache_size: u64,
     pub block_cache_count: u64,
     pub block_gc_interval: u64,
@@ -34,15 +47,35 @@ pub struct Swarm {
     pub mdns: bool,
     pub branch_cache_size: u64,
     pub gossip_interval: u64,
+    pub gossip_max_interval: u64,
+    pub fast_path_batch: u64,
     pub detection_cycles_low_latency: f64,
     pub detection_cycles_high_latency: f64,
+    pub peer_stale_after: u64,
+    pub quarantine_threshold: u32,
+    pub quarantine_window: u64,
+    pub quarantine_duration: u64,
+    pub max_lamport_jump: u64,
+    pub lazy_stream_loading: bool,
+    pub known_peers_max_age: u64,
+    pub prometheus: bool,
+    pub decision_log: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_peer: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_blob_threshold: Option<u64>,
+    pub maintenance: crate::swarm::MaintenanceConfig,
 }
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Admin {
     pub display_name: String,
     pub authorized_users: Vec<String>,
+    pub read_secrets: Vec<String>,
     pub log_levels: LogLevels,
+    pub log_buffer: LogBuffer,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -57,6 +90,24 @@ pub struct LogLevels {
     pub node: LogSeverity,
 }
 
+/// Limits of the log records kept in memory for `AdminRequest::LogsTail`
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogBuffer {
+    pub records: u64,
+    /// longer messages and field values are truncated
+    pub max_message_length: u64,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self {
+            records: 10_000,
+            max_message_length: 4096,
+        }
+    }
+}
+
 mod tag_expr {
     use ax_aql::TagExpr;
     use serde::{de::Visitor, Deserializer, Serializer};
@@ -118,6 +169,23 @@ pub struct Settings {
 }
 
 impl Settings {
+    /// The JSON schema of the node settings.
+    ///
+    /// The [`SENSITIVE`](crate::settings::SENSITIVE) keyword is not written into the schema file
+    /// but added for the fields marked with [`sensitive`](crate::settings::sensitive) on these types.
+    pub fn schema() -> anyhow::Result<serde_json::Value> {
+        let mut schema: serde_json::Value = serde_json::from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/resources/json-schema/node-settings.schema.json"
+        )))?;
+        let defaults =
+            Validator::new(schema.clone())?.validate_with_defaults(Some(&serde_json::json!({})), &system_scope())?;
+        let probe = serde_json::from_value::<Settings>(defaults)?;
+        mark_sensitive(&probe, &mut schema)
+            .map_err(|path| anyhow::anyhow!("sensitive setting {} is not in the schema", path))?;
+        Ok(schema)
+    }
+
     #[cfg(test)]
     pub fn sample() -> Self {
         use maplit::btreeset;
@@ -127,6 +195,8 @@ impl Settings {
                 initial_peers: btreeset!["some bootstrap node".into()],
                 announce_addresses: btreeset![],
                 topic: "some topic".into(),
+                previous_topics: vec![],
+                previous_topics_grace_period: 604800,
                 block_cache_count: 1024 * 128,
                 block_cache_size: 1024 * 1024 * 1024,
                 block_gc_interval: 300,
@@ -136,19 +206,40 @@ impl Settings {
                 mdns: true,
                 branch_cache_size: 67108864,
                 gossip_interval: 10,
+                gossip_max_interval: 60,
+                fast_path_batch: 50,
                 detection_cycles_low_latency: 2.0,
                 detection_cycles_high_latency: 5.0,
+                peer_stale_after: 600,
+                quarantine_threshold: 20,
+                quarantine_window: 60,
+                quarantine_duration: 600,
+                max_lamport_jump: 1 << 40,
+                lazy_stream_loading: false,
+                known_peers_max_age: 604800,
+                prometheus: false,
+                decision_log: false,
+                max_connections: None,
+                max_connections_per_peer: None,
+                payload_blob_threshold: None,
+                maintenance: Default::default(),
             },
             admin: Admin {
                 display_name: "some name".into(),
                 log_levels: LogLevels::default(),
+                log_buffer: LogBuffer::default(),
                 authorized_users: vec![],
+                read_secrets: vec![],
             },
             licensing: Licensing::default(),
             api: Api {
                 events: Events {
                     internal: None,
                     read_only: true,
+                    query_timeout: 300,
+                    max_query_timeout: 3600,
+                    admin_queries_unlimited: false,
+                    payload_schemas: BTreeMap::new(),
                 },
             },
             event_routing: Default::default(),
@@ -102,11 +102,7 @@ pub(crate) fn apply_system_schema(
     settings_repo: &mut crate::settings::Repository,
 ) -> Result<(), crate::settings::RepositoryError> {
     tracing::debug!("setting current schema for com.actyx");
-    let schema: serde_json::Value = serde_json::from_slice(include_bytes!(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/resources/json-schema/node-settings.schema.json"
-    )))
-    .expect("embedded settings schema is not valid json");
+    let schema = Settings::schema().expect("embedded settings schema is not valid");
     // check that embedded schema for com.actyx is a valid schema. If not, there is no point in going on.
     crate::settings::Validator::new(schema.clone()).expect("Embedded schema for com.actyx is not a valid JSON schema.");
 
@@ -1,9 +1,13 @@
+mod schema;
+
 use rusqlite::OpenFlags;
 use std::path::{Path, PathBuf};
 
-use super::node_storage::{NodeStorage, CURRENT_VERSION};
+use super::node_storage::{NodeStorage, WrongVersionFuture, CURRENT_VERSION};
 use anyhow::Context;
 
+pub use schema::{migrate, MigrationReport, MigrationStep, StatementReport};
+
 const NODE_DB_FILENAME: &str = "node.sqlite";
 
 fn open_readonly(path: impl AsRef<Path>) -> anyhow::Result<rusqlite::Connection> {
@@ -71,6 +75,16 @@ fn find_earlier_working_dir(base: impl AsRef<Path>) -> Option<(PathBuf, PathBuf)
     }
 }
 
+/// Report what [`migrate_if_necessary`] would do to the node database in `working_dir`, without
+/// changing it. Returns `None` if there is no database yet.
+pub fn preview_migration(working_dir: impl AsRef<Path>) -> anyhow::Result<Option<MigrationReport>> {
+    let Some((_, node_db)) = find_earlier_working_dir(&working_dir) else {
+        return Ok(None);
+    };
+    let mut conn = rusqlite::Connection::open_with_flags(&node_db, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    migrate(&mut conn, CURRENT_VERSION, true).map(Some)
+}
+
 /// Migrates the AX node if necessary. If the node's version is current, this
 /// is a no-op.
 pub fn migrate_if_necessary(working_dir: impl AsRef<Path>) -> anyhow::Result<()> {
@@ -83,15 +97,24 @@ pub fn migrate_if_necessary(working_dir: impl AsRef<Path>) -> anyhow::Result<()>
             0 | 1 => {
                 anyhow::bail!("Migrating from versions 0.x and 1.x is only possible in AX versions up to 2.15.0")
             }
-            2 => {
-                tracing::info!(target:"MIGRATION", "Migrating data from an earlier version (2 to 3) ..");
+            CURRENT_VERSION => break,
+            v if v > CURRENT_VERSION => return Err(WrongVersionFuture(v).into()),
+            _ => {
+                tracing::info!(
+                    target: "MIGRATION",
+                    "Migrating data from an earlier version ({} to {}) ..",
+                    db_version,
+                    CURRENT_VERSION
+                );
                 tracing::debug!("Opening database {}", node_db.display());
                 let mut conn = rusqlite::Connection::open(&node_db)?;
-                NodeStorage::migrate(&mut conn, db_version).context("migrating from storage version 2")?;
+                let report = migrate(&mut conn, CURRENT_VERSION, false)
+                    .with_context(|| format!("migrating from storage version {}", db_version))?;
+                for step in report.steps {
+                    tracing::debug!(from = step.from, to = step.to, "{}", step.description);
+                }
                 tracing::info!(target:"MIGRATION", "Migration succeeded.");
             }
-            CURRENT_VERSION => break,
-            _ => anyhow::bail!("Detected future version {}", db_version),
         }
     }
     Ok(())
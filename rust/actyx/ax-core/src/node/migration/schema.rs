@@ -0,0 +1,209 @@
+//! Numbered migrations of the node database schema.
+//!
+//! Each migration lifts the database by exactly one version, the version itself is recorded in the
+//! `database_version` row of the `node` table. A new schema change is added by appending to
+//! [`MIGRATIONS`] and bumping [`CURRENT_VERSION`].
+use crate::node::node_storage::{NodeStorage, WrongVersionFuture, CURRENT_VERSION};
+use anyhow::Context;
+use rusqlite::Connection;
+
+pub(crate) struct Migration {
+    /// the version this migration starts from, it ends at the next one
+    pub from: u32,
+    pub description: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+pub(crate) const MIGRATIONS: &[Migration] = &[Migration {
+    from: 2,
+    description: "remove the event store tables of AX v2.8 and earlier",
+    statements: &["DROP TABLE IF EXISTS meta", "DROP TABLE IF EXISTS streams"],
+}];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementReport {
+    pub sql: &'static str,
+    /// rows changed by the statement, zero for schema changes
+    pub rows: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStep {
+    pub from: u32,
+    pub to: u32,
+    pub description: &'static str,
+    pub statements: Vec<StatementReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub dry_run: bool,
+    pub steps: Vec<MigrationStep>,
+}
+
+/// Migrate the node database to version `target` in a single transaction.
+///
+/// With `dry_run` the transaction is rolled back after all statements have run, so the report
+/// shows what the migration would do without changing the database.
+pub fn migrate(conn: &mut Connection, target: u32, dry_run: bool) -> anyhow::Result<MigrationReport> {
+    run(conn, MIGRATIONS, target, dry_run)
+}
+
+pub(crate) fn run(
+    conn: &mut Connection,
+    migrations: &[Migration],
+    target: u32,
+    dry_run: bool,
+) -> anyhow::Result<MigrationReport> {
+    let latest = migrations.last().map(|m| m.from + 1).unwrap_or(CURRENT_VERSION);
+    anyhow::ensure!(target <= latest, "cannot migrate to unknown version {}", target);
+    let from = NodeStorage::version(conn)?;
+    if from > latest {
+        return Err(WrongVersionFuture(from).into());
+    }
+    anyhow::ensure!(
+        from <= target,
+        "cannot migrate database version {} back to {}",
+        from,
+        target
+    );
+
+    let tx = conn.transaction()?;
+    let mut steps = vec![];
+    for version in from..target {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .with_context(|| format!("no migration from database version {}", version))?;
+        let mut statements = vec![];
+        for &sql in migration.statements {
+            let rows = tx
+                .execute(sql, [])
+                .with_context(|| format!("migrating from version {}: {}", version, sql))?;
+            statements.push(StatementReport { sql, rows });
+        }
+        tx.execute(
+            "UPDATE node SET value = ? WHERE name = 'database_version'",
+            [version + 1],
+        )?;
+        steps.push(MigrationStep {
+            from: version,
+            to: version + 1,
+            description: migration.description,
+            statements,
+        });
+    }
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+    Ok(MigrationReport {
+        from,
+        to: target,
+        dry_run,
+        steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_v2(conn: &Connection) {
+        conn.execute_batch(
+            "CREATE TABLE node (name TEXT PRIMARY KEY, value BLOB) WITHOUT ROWID;
+            INSERT INTO node (name, value) VALUES ('database_version', 2);
+            CREATE TABLE meta (name TEXT, value BLOB);
+            CREATE TABLE streams (stream TEXT);
+            INSERT INTO streams VALUES ('a'), ('b');",
+        )
+        .unwrap();
+    }
+
+    fn tables(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn migrations_end_at_current_version() {
+        assert_eq!(MIGRATIONS.last().unwrap().from + 1, CURRENT_VERSION);
+        for pair in MIGRATIONS.windows(2) {
+            assert_eq!(pair[0].from + 1, pair[1].from);
+        }
+    }
+
+    #[test]
+    fn migrate_from_n_minus_2() {
+        const STEPS: &[Migration] = &[
+            Migration {
+                from: 2,
+                description: "add table",
+                statements: &[
+                    "CREATE TABLE extra (x INTEGER)",
+                    "INSERT INTO extra SELECT 1 FROM streams",
+                ],
+            },
+            Migration {
+                from: 3,
+                description: "update rows",
+                statements: &["UPDATE extra SET x = x + 1"],
+            },
+        ];
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_v2(&conn);
+
+        let report = run(&mut conn, STEPS, 4, false).unwrap();
+        assert_eq!((report.from, report.to), (2, 4));
+        let rows = report
+            .steps
+            .iter()
+            .flat_map(|s| s.statements.iter().map(|s| s.rows))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![0, 2, 2]);
+        assert_eq!(NodeStorage::version(&conn).unwrap(), 4);
+        let sum: u32 = conn
+            .query_row("SELECT sum(x) FROM extra", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sum, 4);
+
+        // nothing left to do
+        assert!(run(&mut conn, STEPS, 4, false).unwrap().steps.is_empty());
+        // a newer database is refused
+        let err = run(&mut conn, &STEPS[..1], 3, false).unwrap_err();
+        assert!(err.downcast_ref::<WrongVersionFuture>().is_some(), "{}", err);
+    }
+
+    #[test]
+    fn migrate_to_current_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_v2(&conn);
+        let report = migrate(&mut conn, CURRENT_VERSION, false).unwrap();
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(tables(&conn), vec!["node"]);
+        assert_eq!(NodeStorage::version(&conn).unwrap(), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn dry_run_leaves_the_file_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.sqlite");
+        create_v2(&Connection::open(&path).unwrap());
+        let before = std::fs::read(&path).unwrap();
+
+        let mut conn = Connection::open(&path).unwrap();
+        let report = migrate(&mut conn, CURRENT_VERSION, true).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.steps[0].statements.len(), 2);
+        assert_eq!(NodeStorage::version(&conn).unwrap(), 2);
+        drop(conn);
+
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+    }
+}
@@ -1,4 +1,5 @@
 mod actors;
+pub mod backup;
 mod components;
 mod formats;
 mod host;
@@ -22,8 +23,11 @@ pub use host::lock_working_dir;
 use crate::util::formats::LogSeverity;
 
 use crate::{
-    swarm::event_store_ref::{self, EventStoreRef},
-    util::{variable::Writer, SocketAddrHelper},
+    swarm::{
+        event_store_ref::{self, EventStoreRef, MailboxStrategy},
+        PeerQuarantine,
+    },
+    util::{variable::Writer, Preference, SocketAddrHelper},
 };
 use acto::ActoRuntime;
 use actors::Actors;
@@ -31,7 +35,7 @@ use anyhow::Context;
 use ax_types::service::SwarmState;
 use components::{
     android::{Android, FfiMessage},
-    logging::Logging,
+    logging::{Logging, RecentLogs},
     node_api::NodeApi,
     store::{Store, StoreRequest},
     swarm_observer::swarm_observer,
@@ -43,7 +47,7 @@ pub use host::initialize_repository;
 use host::Host;
 use node_impl::{ComponentChannel, NodeProcessResult, NodeWrapper};
 use settings::SettingsRequest;
-use std::{net::ToSocketAddrs, path::PathBuf, str::FromStr, thread};
+use std::{path::PathBuf, str::FromStr, thread, time::Duration};
 use util::init_panic_hook;
 
 // Rust defaults to use the system allocator, which seemed to be the fastest
@@ -77,6 +81,11 @@ fn bounded_channel<T>() -> (Sender<T>, Receiver<T>) {
     bounded(256)
 }
 
+/// Pending event store requests of the API, leaving room in the store channel for the rest
+const API_MAILBOX_CAPACITY: usize = 128;
+/// How long API requests wait for room in their mailbox before being rejected
+const API_MAILBOX_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn spawn(
     working_dir: PathBuf,
     runtime: Runtime,
@@ -103,7 +112,10 @@ fn spawn(
     actors.supervise(swarm_observer.contramap(SwarmObserver::from));
 
     let tx = store_tx.clone();
-    let event_store = EventStoreRef::new(move |e| {
+    let strategy = MailboxStrategy::Block {
+        timeout: API_MAILBOX_TIMEOUT,
+    };
+    let event_store = EventStoreRef::with_mailbox(API_MAILBOX_CAPACITY, strategy, move |e| {
         tx.try_send(ComponentRequest::Individual(StoreRequest::EventsV2(e)))
             .map_err(event_store_ref::Error::from)
     });
@@ -116,7 +128,15 @@ fn spawn(
 
     // Component: Logging
     // Set up logging so tracing is set up for migration
-    let logging = Logging::new(logs_rx, LogSeverity::default(), log_no_color, log_as_json);
+    // shared between the logging component filling and the node API serving it
+    let recent_logs = RecentLogs::default();
+    let logging = Logging::new(
+        logs_rx,
+        LogSeverity::default(),
+        log_no_color,
+        log_as_json,
+        recent_logs.clone(),
+    );
     log::set_boxed_logger(Box::new(log_tracer::LogTracer::new([
         "yamux",
         "libp2p_gossipsub",
@@ -128,12 +148,15 @@ fn spawn(
     .ok();
     log::set_max_level(log::LevelFilter::max());
 
-    migration::migrate_if_necessary(&working_dir)?;
+    // turn a database from a newer version into a readable error instead of failing on its schema
+    migration::migrate_if_necessary(&working_dir).map_err(NodeError::from)?;
 
     // Host interface
     let host = Host::new(working_dir.clone()).context("creating host interface")?;
     // now set up the configured log level after initializing `Host`
-    logging.set_log_level(host.get_settings().admin.log_levels.node.clone())?;
+    let admin_settings = &host.get_settings().admin;
+    logging.set_log_level(admin_settings.log_levels.node.clone())?;
+    logging.set_log_buffer(admin_settings.log_buffer);
     join_handles.push(logging.spawn().context("spawning logger")?);
 
     let node_id = host.get_or_create_node_id().context("getting node ID")?;
@@ -153,6 +176,8 @@ fn spawn(
     let keystore = host.get_keystore();
 
     let node_cycle_count = host.get_cycle_count().context("getting cycle count")?;
+    // shared between swarm and node API, which talk to the same peers
+    let quarantine = PeerQuarantine::default();
     // THE node :-)
     let node = NodeWrapper::new((node_tx, node_rx), components, host).context("creating node core")?;
 
@@ -173,6 +198,9 @@ fn spawn(
             nodeapi_rx,
             working_dir.join("store"),
             store_tx,
+            quarantine.clone(),
+            swarm_state.clone(),
+            recent_logs,
         )
     };
     join_handles.push(node_api.spawn().context("spawning node API")?);
@@ -186,8 +214,9 @@ fn spawn(
         keystore,
         node_id,
         node_cycle_count,
-        swarm_observer_ref.contramap(SwarmObserver::from),
+        swarm_observer_ref,
         swarm_state,
+        quarantine,
     )
     .context("creating event store")?;
     join_handles.push(store.spawn().context("spawning event store")?);
@@ -216,7 +245,8 @@ impl Default for BindTo {
         Self {
             admin: SocketAddrHelper::unspecified(4458).expect("unspecified can only fail for port 0"),
             swarm: SocketAddrHelper::unspecified(4001).expect("unspecified can only fail for port 0"),
-            api: "localhost:4454".parse().unwrap(),
+            api: SocketAddrHelper::resolve_with_preference("localhost:4454", Preference::default())
+                .expect("localhost must resolve"),
         }
     }
 }
@@ -227,7 +257,7 @@ impl BindTo {
         Ok(Self {
             admin: SocketAddrHelper::unspecified(0)?,
             swarm: SocketAddrHelper::unspecified(0)?,
-            api: "localhost:0".parse()?,
+            api: SocketAddrHelper::resolve_with_preference("localhost:0", Preference::default())?,
         })
     }
 }
@@ -253,6 +283,47 @@ impl<const N: u16> FromStr for PortOrHostPort<N> {
     }
 }
 
+impl<const N: u16> PortOrHostPort<N> {
+    /// Combine the directives given for one endpoint: either a single port, which `port` turns
+    /// into addresses, or any number of host:port combinations.
+    pub fn fold(
+        port: impl FnOnce(u16) -> anyhow::Result<SocketAddrHelper>,
+        input: Vec<Self>,
+    ) -> anyhow::Result<SocketAddrHelper> {
+        if input.is_empty() {
+            anyhow::bail!("no value provided");
+        }
+        let mut found_port = None;
+        let mut host_port: Option<SocketAddrHelper> = None;
+        for i in input.into_iter() {
+            match i {
+                Self::Port(p) => {
+                    if found_port.is_some() {
+                        anyhow::bail!("Multiple single port directives not supported");
+                    } else if host_port.is_some() {
+                        anyhow::bail!("Both port directive and host:port combination not supported");
+                    } else {
+                        found_port.replace(p);
+                    }
+                }
+                Self::HostPort(addr) => {
+                    if found_port.is_some() {
+                        anyhow::bail!("Both port directive and host:port combination not supported");
+                    } else if let Some(x) = host_port.as_mut() {
+                        x.append(addr);
+                    } else {
+                        let _ = host_port.replace(addr);
+                    }
+                }
+            }
+        }
+        found_port
+            .map(port)
+            .or_else(|| host_port.map(Ok))
+            .expect("Input must not be empty")
+    }
+}
+
 fn parse_port_maybe_host<const N: u16>(src: &str) -> Result<PortOrHostPort<N>, String> {
     let port = match src.parse::<u16>() {
         Ok(p) => return Ok(PortOrHostPort::Port(p)),
@@ -266,8 +337,8 @@ fn parse_port_maybe_host<const N: u16>(src: &str) -> Result<PortOrHostPort<N>, S
         Ok(m) => return Ok(PortOrHostPort::HostPort(m)),
         Err(e) => e,
     };
-    let sock_addr = match (src, N).to_socket_addrs() {
-        Ok(i) => return Ok(PortOrHostPort::HostPort(i.collect())),
+    let sock_addr = match SocketAddrHelper::resolve_with_preference((src, N), Preference::default()) {
+        Ok(addrs) => return Ok(PortOrHostPort::HostPort(addrs)),
         Err(e) => e,
     };
     Err(format!(
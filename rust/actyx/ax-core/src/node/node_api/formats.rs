@@ -1,4 +1,4 @@
-use crate::util::formats::{ActyxOSResult, NodesLsResponse};
+use crate::util::formats::{ActyxOSResult, NodesLsResponse, NodesStatusResponse};
 use ax_types::NodeId;
 use tokio::sync::oneshot::Sender;
 
@@ -6,4 +6,6 @@ use tokio::sync::oneshot::Sender;
 pub enum NodesRequest {
     Ls(Sender<ActyxOSResult<NodesLsResponse>>),
     GetNodeId(Sender<ActyxOSResult<NodeId>>),
+    /// Component status and uptime, the peers status is left empty
+    Status(Sender<ActyxOSResult<NodesStatusResponse>>),
 }
@@ -1,5 +1,7 @@
 use super::{
+    backup::{self, BackupManifest, BackupProgress},
     components::{
+        logging::RecentLogs,
         node_api::NodeApiSettings,
         store::{Store, StoreRequest, StoreTx},
         Component, ComponentRequest,
@@ -12,9 +14,11 @@ use crate::{
     api::EventService,
     ax_futures_util::stream::variable::Variable,
     crypto::PublicKey,
-    libp2p_streaming_response::{RequestReceived, StreamingResponse, StreamingResponseConfig},
+    libp2p_streaming_response::{RequestReceived, StreamingResponse, StreamingResponseConfig, StreamingResponseEvent},
     swarm::{
-        event_store_ref::EventStoreRef, BanyanConfig, BlockWriter, StorageConfig, StorageService, StorageServiceStore,
+        event_store_ref::EventStoreRef,
+        transport::{socket_options, TcpSocketConfig},
+        BanyanConfig, BlockWriter, PeerQuarantine, PeerUnreachable, StorageConfig, StorageService, StorageServiceStore,
         StorageServiceStoreWrite, StreamAlias,
     },
     trees::{
@@ -23,26 +27,28 @@ use crate::{
     },
     util::{
         formats::{
-            admin_protocol::{AdminProtocol, AdminRequest, AdminResponse},
+            admin_protocol::{AdminProtocol, AdminRequest, AdminResponse, AX_AGENT_PREFIX},
             banyan_protocol::{
                 decode_dump_frame, decode_dump_header, BanyanProtocol, BanyanProtocolName, BanyanRequest,
                 BanyanResponse,
             },
             events_protocol::{EventsProtocol, EventsRequest, EventsResponse},
             ActyxOSCode, ActyxOSError, ActyxOSResult, ActyxOSResultExt, NodeErrorContext, NodesInspectResponse,
-            TopicDeleteResponse, TopicLsResponse,
+            PeerConnectionQuality, TopicDeleteResponse, TopicLsResponse,
         },
-        version::NodeVersion,
+        variable::Reader,
+        version::{NodeVersion, Version},
         SocketAddrHelper,
     },
 };
 use anyhow::{anyhow, bail, Context};
 use ax_types::{
     app_id,
-    service::{QueryResponse, SubscribeMonotonicResponse, SubscribeResponse},
+    service::{QueryResponse, SubscribeMonotonicResponse, SubscribeResponse, SwarmState},
     tag, LamportTimestamp, NodeId, Payload,
 };
 use cbor_data::Cbor;
+use chrono::{DateTime, SecondsFormat, Utc};
 use crossbeam::channel::Sender;
 use formats::NodesRequest;
 use futures::{
@@ -121,6 +127,11 @@ struct State {
     pending_finalise: FuturesUnordered<PendingFinalise>,
     admin_sockets: Variable<BTreeSet<Multiaddr>>,
     banyan_stores: BTreeMap<String, BanyanWriter>,
+    quarantine: PeerQuarantine,
+    /// agent versions received via identify from the currently connected peers
+    agents: Arc<Mutex<BTreeMap<PeerId, String>>>,
+    swarm_state: Reader<SwarmState>,
+    recent_logs: RecentLogs,
 }
 
 #[derive(NetworkBehaviour)]
@@ -141,6 +152,9 @@ impl ApiBehaviour {
         store: StoreTx,
         auth_info: Arc<Mutex<NodeApiSettings>>,
         local_public_key: libp2p::core::PublicKey,
+        quarantine: PeerQuarantine,
+        swarm_state: Reader<SwarmState>,
+        recent_logs: RecentLogs,
     ) -> (Self, State) {
         let tx = store.clone();
         let events = EventStoreRef::new(move |req| {
@@ -158,18 +172,31 @@ impl ApiBehaviour {
             pending_finalise: FuturesUnordered::new(),
             admin_sockets: Variable::default(),
             banyan_stores: BTreeMap::default(),
+            quarantine: quarantine.clone(),
+            agents: Arc::default(),
+            swarm_state,
+            recent_logs,
+        };
+        let streaming_response_config = || {
+            let quarantine = quarantine.clone();
+            let agents = state.agents.clone();
+            StreamingResponseConfig::default()
+                .with_violation_handler(move |peer_id, err| {
+                    quarantine.record_violation(peer_id, format_args!("invalid request: {}", err));
+                })
+                .with_agent_lookup(move |peer_id| agents.lock().get(peer_id).cloned())
         };
         let mut request_response_config = RequestResponseConfig::default();
         request_response_config.set_request_timeout(Duration::from_secs(120));
         let ret = Self {
             ping: ping::Behaviour::new(ping::Config::new()),
-            admin: StreamingResponse::new(StreamingResponseConfig::default()),
+            admin: StreamingResponse::new(streaming_response_config()),
             banyan: RequestResponse::new(
                 BanyanProtocol::default(),
                 [(BanyanProtocolName, ProtocolSupport::Inbound)],
                 request_response_config,
             ),
-            events: StreamingResponse::new(StreamingResponseConfig::default()),
+            events: StreamingResponse::new(streaming_response_config()),
             identify: identify::Behaviour::new(
                 identify::Config::new(format!("Actyx-{}", NodeVersion::get()), local_public_key)
                     .with_initial_delay(Duration::ZERO),
@@ -188,6 +215,13 @@ impl State {
         g.authorized_keys.is_empty() || g.authorized_keys.contains(peer)
     }
 
+    /// Checks whether `peer` may read sensitive settings values. As long as there
+    /// are no authorized keys the node is being bootstrapped, so any peer may.
+    fn may_read_secrets(&self, peer: &PeerId) -> bool {
+        let g = self.auth_info.lock();
+        g.authorized_keys.is_empty() || g.read_secrets_keys.contains(peer)
+    }
+
     fn maybe_add_key(&self, key_id: PublicKey, peer: PeerId) -> Option<BoxFuture<'static, ActyxOSResult<()>>> {
         let mut auth_info = self.auth_info.lock();
         if auth_info.authorized_keys.is_empty() {
@@ -203,6 +237,7 @@ impl State {
                     ignore_errors: false,
                     json: serde_json::json!([format!("{}", key_id)]),
                     response: tx,
+                    redact: false,
                 }))
                 .unwrap();
             Some(
@@ -253,8 +288,16 @@ async fn poll_swarm(mut swarm: Swarm<ApiBehaviour>, mut state: State) {
         match event {
             MyEvent::Swarm(Some(event)) => match event {
                 SwarmEvent::Behaviour(event) => match event {
-                    ApiBehaviourEvent::Admin(event) => inject_admin_event(&mut state, event),
-                    ApiBehaviourEvent::Events(event) => inject_events_event(&mut state, event),
+                    ApiBehaviourEvent::Admin(StreamingResponseEvent::RequestReceived(event)) => {
+                        inject_admin_event(&mut state, event)
+                    }
+                    ApiBehaviourEvent::Events(StreamingResponseEvent::RequestReceived(event)) => {
+                        inject_events_event(&mut state, event)
+                    }
+                    ApiBehaviourEvent::Admin(StreamingResponseEvent::ResponseTooLarge(e))
+                    | ApiBehaviourEvent::Events(StreamingResponseEvent::ResponseTooLarge(e)) => {
+                        tracing::warn!("{}", e)
+                    }
                     ApiBehaviourEvent::Banyan(event) => inject_banyan_event(&mut state, swarm.behaviour_mut(), event),
                     ApiBehaviourEvent::Ping(_x) => {}
                     ApiBehaviourEvent::Identify(event) => inject_identify_event(&mut state, event),
@@ -283,8 +326,16 @@ async fn poll_swarm(mut swarm: Swarm<ApiBehaviour>, mut state: State) {
                 SwarmEvent::ConnectionEstablished { endpoint, .. } => {
                     tracing::debug!(endpoint = ?&endpoint, "connection established");
                 }
-                SwarmEvent::ConnectionClosed { endpoint, .. } => {
+                SwarmEvent::ConnectionClosed {
+                    peer_id,
+                    endpoint,
+                    num_established,
+                    ..
+                } => {
                     tracing::debug!(endpoint = ?&endpoint, "connection closed");
+                    if num_established == 0 {
+                        state.agents.lock().remove(&peer_id);
+                    }
                 }
                 SwarmEvent::IncomingConnectionError {
                     local_addr,
@@ -309,22 +360,65 @@ async fn poll_swarm(mut swarm: Swarm<ApiBehaviour>, mut state: State) {
     }
 }
 
+/// The oldest `ax` version able to decode the response variant answering the request, if that
+/// variant is newer than the first `ax` release
+fn min_agent_version(request: &AdminRequest) -> Option<Version> {
+    match request {
+        AdminRequest::TopicLs | AdminRequest::TopicDelete { .. } => Some(Version::new(2, 16, 0)),
+        _ => None,
+    }
+}
+
+/// Checks that the requesting `ax` can decode the response to `request`.
+///
+/// Clients that don't announce an `ax` agent version predate this check and are served as before.
+fn check_agent_version(request: &AdminRequest, agent_version: Option<&str>) -> ActyxOSResult<()> {
+    let (Some(required), Some(agent)) = (
+        min_agent_version(request),
+        agent_version.and_then(|a| a.strip_prefix(AX_AGENT_PREFIX)),
+    ) else {
+        return Ok(());
+    };
+    match agent.parse::<NodeVersion>().ok().and_then(|v| v.version()) {
+        Some(version) if version >= required => Ok(()),
+        _ => Err(ActyxOSCode::ERR_UNSUPPORTED.with_message(format!(
+            "This request requires ax {} or newer, but the client is {} (node AX version: {})",
+            required,
+            agent,
+            NodeVersion::get()
+        ))),
+    }
+}
+
 fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>) {
     let RequestReceived {
         peer_id,
         connection: _,
+        protocol: _,
+        agent_version,
         request,
         mut channel,
     } = event;
     tracing::debug!("Received streaming_response admin: {:?}", request);
-    if !state.is_authorized(&peer_id) {
+    if state.quarantine.is_quarantined(&peer_id) {
+        tracing::debug!("Received request from quarantined peer {}. Rejecting.", peer_id);
+        channel
+            .try_send(Err(
+                ActyxOSCode::ERR_UNAUTHORIZED.with_message("Peer is quarantined for sending malformed data.")
+            ))
+            .ok();
+    } else if !state.is_authorized(&peer_id) {
         tracing::warn!("Received unauthorized request from {}. Rejecting.", peer_id);
         channel
             .try_send(Err(
                 ActyxOSCode::ERR_UNAUTHORIZED.with_message("Provided key is not authorized to access the API.")
             ))
             .ok();
+    } else if let Err(err) = check_agent_version(&request, agent_version.as_deref()) {
+        tracing::debug!("Received request from incompatible client {}: {}", peer_id, err);
+        channel.try_send(Err(err)).ok();
     } else {
+        let redact = !state.may_read_secrets(&peer_id);
         fn respond<T, F>(
             node_tx: Sender<ExternalEvent>,
             mut channel: mpsc::Sender<ActyxOSResult<AdminResponse>>,
@@ -345,6 +439,29 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                 channel.feed(result).await.ok();
             });
         }
+        fn respond_from_store<T, F>(
+            store: &StoreTx,
+            mut channel: mpsc::Sender<ActyxOSResult<AdminResponse>>,
+            f: F,
+            wrap: fn(T) -> AdminResponse,
+        ) where
+            F: FnOnce(oneshot::Sender<anyhow::Result<T>>) -> StoreRequest,
+            T: Send + 'static,
+        {
+            let (tx, rx) = oneshot::channel();
+            let send = store.send(ComponentRequest::Individual(f(tx)));
+            tokio::spawn(async move {
+                let result = async move {
+                    send.ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "sending to store")?;
+                    rx.await
+                        .ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "Error waiting for response")?
+                        .ax_invalid_input()
+                        .map(wrap)
+                }
+                .await;
+                channel.feed(result).await.ok();
+            });
+        }
         match request {
             AdminRequest::FutureCompat => {
                 // We try to send the response but if sending fails, it means no one is listening on the other side
@@ -363,6 +480,24 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                     .store
                     .send(ComponentRequest::Individual(StoreRequest::NodesInspect(tx)));
                 let admin_addrs = state.admin_sockets.get_cloned().iter().map(|a| a.to_string()).collect();
+                let admin_socket_options = socket_options(&TcpSocketConfig::default(), std::env::consts::OS)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                let peer_quality = state.swarm_state.project(|s| {
+                    s.peers_quality
+                        .iter()
+                        .map(|(node_id, quality)| PeerConnectionQuality {
+                            node_id: node_id.to_string(),
+                            rtt_micros: quality.rtt_micros,
+                            pings: quality.pings,
+                            failures: quality.failures,
+                            last_seen: DateTime::<Utc>::try_from(quality.last_seen)
+                                .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+                                .unwrap_or_else(|_| quality.last_seen.to_string()),
+                        })
+                        .collect()
+                });
                 let mut channel = channel;
                 tokio::spawn(
                     async move {
@@ -376,8 +511,15 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                             swarm_addrs: res.swarm_addrs,
                             announce_addrs: res.announce_addrs,
                             admin_addrs,
+                            admin_socket_options,
                             connections: res.connections,
                             known_peers: res.known_peers,
+                            gossip_interval_millis: Some(res.gossip_interval.as_millis() as u64),
+                            quarantined_peers: res.quarantined_peers,
+                            previous_topics: res.previous_topics,
+                            maintenance: res.maintenance,
+                            lamport_warnings: res.lamport_warnings,
+                            peer_quality,
                         }))
                     }
                     .then(move |res| async move {
@@ -385,6 +527,31 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                     }),
                 );
             }
+            AdminRequest::NodesStatus => {
+                let (tx, rx) = oneshot::channel();
+                state
+                    .node_tx
+                    .send(ExternalEvent::NodesRequest(NodesRequest::Status(tx)))
+                    .expect("node must keep running");
+                let swarm_state = state.swarm_state.clone();
+                let mut channel = channel;
+                tokio::spawn(async move {
+                    let result = rx
+                        .await
+                        .ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "receiving response from node")
+                        .unwrap_or_else(|e| Err(e))
+                        .map(|mut status| {
+                            status.peers_status = swarm_state.project(|s| {
+                                s.peers_status
+                                    .iter()
+                                    .map(|(node_id, status)| (node_id.to_string(), *status))
+                                    .collect()
+                            });
+                            AdminResponse::NodesStatusResponse(status)
+                        });
+                    channel.feed(result).await.ok();
+                });
+            }
             AdminRequest::NodesShutdown => trigger_shutdown(true),
             AdminRequest::SettingsGet { scope, no_defaults } => respond(
                 state.node_tx.clone(),
@@ -394,6 +561,7 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                         scope,
                         no_defaults,
                         response: tx,
+                        redact,
                     })
                 },
                 AdminResponse::SettingsGetResponse,
@@ -411,6 +579,7 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                         json,
                         ignore_errors,
                         response: tx,
+                        redact,
                     })
                 },
                 AdminResponse::SettingsSetResponse,
@@ -435,10 +604,117 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
             ),
             AdminRequest::TopicLs => handle_topic_ls(state, channel),
             AdminRequest::TopicDelete { name } => handle_topic_delete(state, channel, name),
+            AdminRequest::SwarmAddListenAddr(addr) => respond_from_store(
+                &state.store,
+                channel,
+                |tx| StoreRequest::SwarmAddListenAddr(addr, tx),
+                AdminResponse::SwarmAddListenAddrResponse,
+            ),
+            AdminRequest::SwarmRemoveListenAddr(addr) => respond_from_store(
+                &state.store,
+                channel,
+                |tx| StoreRequest::SwarmRemoveListenAddr(addr, tx),
+                |_| AdminResponse::SwarmRemoveListenAddrResponse,
+            ),
+            AdminRequest::LogsTail {
+                count,
+                min_level,
+                target_filter,
+            } => {
+                let entries = state
+                    .recent_logs
+                    .tail(count, min_level.as_ref(), target_filter.as_deref());
+                let _ = channel.try_send(Ok(AdminResponse::LogsTailResponse(entries)));
+            }
+            AdminRequest::CompareOffsets { peer } => handle_compare_offsets(state, channel, peer),
+            AdminRequest::DecisionLogTail { count } => respond_from_store(
+                &state.store,
+                channel,
+                |tx| StoreRequest::DecisionLogTail(count, tx),
+                AdminResponse::DecisionLogTailResponse,
+            ),
+            AdminRequest::BackupCreate { path } => {
+                let (progress_tx, progress) = tokio::sync::mpsc::unbounded_channel();
+                let (tx, rx) = oneshot::channel();
+                let send = state
+                    .store
+                    .send(ComponentRequest::Individual(StoreRequest::BackupCreate(
+                        path,
+                        progress_tx,
+                        tx,
+                    )));
+                if let Err(err) = send {
+                    let err = ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!("sending to store: {}", err));
+                    channel.try_send(Err(err)).ok();
+                } else {
+                    respond_with_progress(channel, progress, rx, AdminResponse::BackupCreateResponse);
+                }
+            }
+            AdminRequest::BackupRestore { path, working_dir } => {
+                let (progress_tx, progress) = tokio::sync::mpsc::unbounded_channel();
+                let (tx, rx) = oneshot::channel();
+                tokio::task::spawn_blocking(move || {
+                    let result = backup::restore(&path, &working_dir, |p| {
+                        let _ = progress_tx.send(p);
+                    });
+                    drop(progress_tx);
+                    let _ = tx.send(result);
+                });
+                respond_with_progress(channel, progress, rx, AdminResponse::BackupRestoreResponse);
+            }
         };
     }
 }
 
+/// Forward the progress of a backup or restore to the client, followed by the result once the
+/// progress has ended.
+fn respond_with_progress(
+    mut channel: mpsc::Sender<ActyxOSResult<AdminResponse>>,
+    mut progress: tokio::sync::mpsc::UnboundedReceiver<BackupProgress>,
+    result: oneshot::Receiver<anyhow::Result<BackupManifest>>,
+    wrap: fn(BackupManifest) -> AdminResponse,
+) {
+    tokio::spawn(async move {
+        while let Some(p) = progress.recv().await {
+            if channel.feed(Ok(AdminResponse::BackupProgress(p))).await.is_err() {
+                // the client is gone, the backup or restore still completes
+                return;
+            }
+        }
+        let result = match result.await {
+            Ok(Ok(manifest)) => Ok(wrap(manifest)),
+            Ok(Err(err)) => Err(ActyxOSCode::ERR_INVALID_INPUT.with_message(format!("{:#}", err))),
+            Err(_) => Err(ActyxOSCode::ERR_INTERNAL_ERROR.with_message("Error waiting for response")),
+        };
+        channel.feed(result).await.ok();
+    });
+}
+
+/// Handle the offsets comparison admin request.
+fn handle_compare_offsets(state: &mut State, mut channel: mpsc::Sender<ActyxOSResult<AdminResponse>>, peer: PeerId) {
+    let (tx, rx) = oneshot::channel();
+    let send = state
+        .store
+        .send(ComponentRequest::Individual(StoreRequest::CompareOffsets(peer, tx)));
+    tokio::spawn(async move {
+        let result = async move {
+            send.ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "sending to store")?;
+            match rx
+                .await
+                .ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "Error waiting for response")?
+            {
+                Ok(comparison) => Ok(AdminResponse::CompareOffsetsResponse(comparison)),
+                Err(err) => match err.downcast_ref::<PeerUnreachable>() {
+                    Some(unreachable) => Err(ActyxOSCode::ERR_NODE_UNREACHABLE.with_message(unreachable.to_string())),
+                    None => Err(ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!("{:#}", err))),
+                },
+            }
+        }
+        .await;
+        channel.feed(result).await.ok();
+    });
+}
+
 /// Delete all topic-related files in the provided store.
 fn delete_topic<P: AsRef<Path>>(store_dir: P, topic_name: &str) -> std::io::Result<bool> {
     let mut deleted = false;
@@ -669,11 +945,22 @@ fn inject_events_event(state: &mut State, event: RequestReceived<EventsProtocol>
     let RequestReceived {
         peer_id,
         connection: _,
+        protocol: _,
+        agent_version: _,
         request,
         mut channel,
     } = event;
     tracing::debug!("Received streaming_response event: {:?}", request);
-    if !state.is_authorized(&peer_id) {
+    if state.quarantine.is_quarantined(&peer_id) {
+        tracing::debug!("Received request from quarantined peer {}. Rejecting.", peer_id);
+        tokio::spawn(async move {
+            channel
+                .feed(EventsResponse::Error {
+                    message: "Peer is quarantined for sending malformed data.".to_owned(),
+                })
+                .await
+        });
+    } else if !state.is_authorized(&peer_id) {
         tracing::warn!("Received unauthorized request from {}. Rejecting.", peer_id);
         tokio::spawn(async move {
             channel
@@ -683,7 +970,15 @@ fn inject_events_event(state: &mut State, event: RequestReceived<EventsProtocol>
                 .await
         });
     } else {
-        let events = state.events.clone();
+        let (query_timeouts, payload_schemas) = {
+            let settings = state.auth_info.lock();
+            (settings.query_timeouts, settings.payload_schemas.clone())
+        };
+        let events = state
+            .events
+            .clone()
+            .with_query_timeouts(query_timeouts)
+            .with_payload_schemas(payload_schemas);
         tokio::spawn(async move {
             match request {
                 EventsRequest::Offsets => {
@@ -703,6 +998,9 @@ fn inject_events_event(state: &mut State, event: RequestReceived<EventsProtocol>
                                 QueryResponse::Event(ev) => EventsResponse::Event(ev),
                                 QueryResponse::Offsets(o) => EventsResponse::OffsetMap { offsets: o.offsets },
                                 QueryResponse::Diagnostic(d) => EventsResponse::Diagnostic(d),
+                                QueryResponse::Progress(p) => EventsResponse::Progress(p),
+                                QueryResponse::Checksum(c) => EventsResponse::Checksum(c),
+                                QueryResponse::Timeout(t) => EventsResponse::Timeout(t),
                                 QueryResponse::FutureCompat => continue,
                             };
                             channel.feed(item).await?;
@@ -746,6 +1044,7 @@ fn inject_events_event(state: &mut State, event: RequestReceived<EventsProtocol>
                                     SubscribeMonotonicResponse::Diagnostic(d) => EventsResponse::Diagnostic(d),
                                     SubscribeMonotonicResponse::FutureCompat => continue,
                                     SubscribeMonotonicResponse::TimeTravel { .. } => continue,
+                                    SubscribeMonotonicResponse::Watermark { .. } => continue,
                                 };
                                 channel.feed(item).await?;
                             }
@@ -970,6 +1269,7 @@ async fn switch_to_dump(
         scope: "com.actyx".parse().unwrap(),
         no_defaults: false,
         response: tx,
+        redact: false,
     });
     if node_tx.send(get_settings).is_err() {
         return (channel, BanyanResponse::Error("store closed".into()));
@@ -999,6 +1299,7 @@ async fn switch_to_dump(
             json: settings,
             ignore_errors: false,
             response: tx,
+            redact: false,
         });
         if node_tx.send(set_settings).is_err() {
             return (channel, BanyanResponse::Error("store closed".into()));
@@ -1074,6 +1375,7 @@ fn store_events(writer: &mut BanyanWriter) -> anyhow::Result<()> {
 fn inject_identify_event(state: &mut State, event: identify::Event) {
     match event {
         identify::Event::Received { peer_id, info } => {
+            state.agents.lock().insert(peer_id, info.agent_version.clone());
             PublicKey::try_from(&info.public_key)
                 .ok()
                 .and_then(|key| state.maybe_add_key(key, peer_id))
@@ -1104,12 +1406,26 @@ pub(crate) async fn mk_swarm(
     store_dir: PathBuf,
     store: StoreTx,
     auth_info: Arc<Mutex<NodeApiSettings>>,
+    quarantine: PeerQuarantine,
+    swarm_state: Reader<SwarmState>,
+    recent_logs: RecentLogs,
 ) -> anyhow::Result<PeerId> {
-    if bind_to.to_multiaddrs().next().is_none() {
+    let bind_to = bind_to.to_multiaddrs()?;
+    if bind_to.is_empty() {
         bail!("cannot start node API without any listen addresses");
     }
 
-    let (protocol, state) = ApiBehaviour::new(node_id, node_tx, store_dir, store, auth_info, keypair.public());
+    let (protocol, state) = ApiBehaviour::new(
+        node_id,
+        node_tx,
+        store_dir,
+        store,
+        auth_info,
+        keypair.public(),
+        quarantine,
+        swarm_state,
+        recent_logs,
+    );
     let (peer_id, transport) = mk_transport(keypair).await?;
 
     let mut swarm = SwarmBuilder::with_tokio_executor(transport, protocol, peer_id).build();
@@ -1120,7 +1436,7 @@ pub(crate) async fn mk_swarm(
     // rust-libp2p sets `IPV6_V6ONLY` (or the platform equivalent) [0]. This is
     // why we have to to bind to ip4 and ip6 manually.
     // [0] https://github.com/libp2p/rust-libp2p/blob/master/transports/tcp/src/lib.rs#L322
-    for addr in bind_to.to_multiaddrs() {
+    for addr in bind_to.iter().cloned() {
         tracing::debug!("Admin API trying to bind to {}", addr);
         swarm
             .listen_on(addr.clone())
@@ -1135,7 +1451,7 @@ pub(crate) async fn mk_swarm(
     // check that some addresses were bound
     let mut set = addrs.next().await.ok_or_else(|| anyhow!("address stream died"))?;
     let deadline = Instant::now() + Duration::from_secs(10);
-    for addr in bind_to.to_multiaddrs() {
+    for addr in bind_to {
         match addr.into_iter().next() {
             Some(Protocol::Ip4(ip4)) if ip4.is_loopback() || ip4.is_unspecified() => loop {
                 if set
@@ -1174,8 +1490,34 @@ type TConnErr = <<<ApiBehaviour as NetworkBehaviour>::ConnectionHandler as libp2
 
 async fn mk_transport(id_keys: identity::Keypair) -> anyhow::Result<(PeerId, Boxed<(PeerId, StreamMuxerBox)>)> {
     let peer_id = id_keys.public().to_peer_id();
-    let transport = crate::swarm::transport::build_transport(id_keys, None, Duration::from_secs(20))
-        .await
-        .context("Building libp2p transport")?;
+    let transport =
+        crate::swarm::transport::build_transport(id_keys, None, Duration::from_secs(20), TcpSocketConfig::default())
+            .await
+            .context("Building libp2p transport")?;
     Ok((peer_id, transport))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agent_version_gating() {
+        let topics = AdminRequest::TopicLs;
+        let agent = |version: &str| format!("{}{}-cargo-linux-x86_64-release", AX_AGENT_PREFIX, version);
+
+        assert!(check_agent_version(&topics, Some(&agent("2.16.0"))).is_ok());
+        assert!(check_agent_version(&topics, Some(&agent("2.18.2"))).is_ok());
+        let err = check_agent_version(&topics, Some(&agent("2.15.0"))).unwrap_err();
+        assert_eq!(err.code(), ActyxOSCode::ERR_UNSUPPORTED);
+        assert!(err.to_string().contains("requires ax 2.16.0 or newer"), "{}", err);
+        // an unparseable version cannot be trusted with the new variant
+        assert!(check_agent_version(&topics, Some(&format!("{}garbage", AX_AGENT_PREFIX))).is_err());
+
+        // old clients without an announced version, and other agents, are served as before
+        assert!(check_agent_version(&topics, None).is_ok());
+        assert!(check_agent_version(&topics, Some("rust-libp2p/0.41.0")).is_ok());
+        // variants every ax understands are never gated
+        assert!(check_agent_version(&AdminRequest::NodesLs, Some(&agent("2.0.0"))).is_ok());
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     time::{Duration, Instant},
 };
 
@@ -9,16 +9,20 @@ use super::{
     formats::{ExternalEvent, NodeDetails, NodeEvent, NodeState, ResultInspect, ShutdownReason},
     host::Host,
     node_api::formats::NodesRequest,
+    node_storage::{WrongVersionFuture, CURRENT_VERSION},
     settings::{is_system_scope, system_scope, SettingsRequest},
     spawn_with_name,
     util::trigger_shutdown,
 };
 use crate::util::{
-    formats::{ActyxOSCode, ActyxOSError, ActyxOSResult, ActyxOSResultExt, NodeErrorContext},
+    formats::{
+        ActyxOSCode, ActyxOSError, ActyxOSResult, ActyxOSResultExt, ComponentStatus, NodeErrorContext,
+        NodesStatusResponse,
+    },
     version::NodeVersion,
 };
 use acto::ActoRef;
-use chrono::SecondsFormat;
+use chrono::{SecondsFormat, Utc};
 use crossbeam::{
     channel::{bounded, Receiver, Sender},
     select,
@@ -30,6 +34,9 @@ use tracing::*;
 
 pub type ApiResult<T> = ActyxOSResult<T>;
 
+/// Components not answering a health check within this time are reported as unresponsive
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
 pub type NodeProcessResult<T> = std::result::Result<T, NodeError>;
 
 #[derive(Error, Debug, Clone)]
@@ -40,10 +47,17 @@ pub enum NodeError {
     InternalError(Arc<anyhow::Error>),
     #[error("ERR_PORT_COLLISION\nAX shut down because it could not bind to port {addr}. Please specify a different {component} port. Please refer to https://developer.actyx.com/docs/how-to/troubleshooting/installation-and-startup/#err_port_collision for more information.")]
     PortCollision { component: String, addr: Multiaddr },
+    #[error("NODE_STOPPED_BY_NODE\nAX shut down because its data directory was written by a newer version (database version {found}, this version supports up to {supported}). Please use a newer version of AX or start from a fresh data directory.")]
+    DatabaseFromFuture { found: u32, supported: u32 },
 }
 impl From<Arc<anyhow::Error>> for NodeError {
     fn from(err: Arc<anyhow::Error>) -> Self {
-        if let Some(ctx) = err.downcast_ref::<NodeErrorContext>() {
+        if let Some(WrongVersionFuture(found)) = err.downcast_ref::<WrongVersionFuture>() {
+            Self::DatabaseFromFuture {
+                found: *found,
+                supported: CURRENT_VERSION,
+            }
+        } else if let Some(ctx) = err.downcast_ref::<NodeErrorContext>() {
             match ctx {
                 NodeErrorContext::BindFailed { addr, component } => Self::PortCollision {
                     addr: addr.clone(),
@@ -148,6 +162,19 @@ impl Node {
         Ok(update)
     }
 
+    fn maybe_redact(
+        &self,
+        scope: &crate::settings::Scope,
+        settings: serde_json::Value,
+        redact: bool,
+    ) -> Result<serde_json::Value, crate::settings::RepositoryError> {
+        if redact {
+            self.settings_repo().redact(scope, &settings)
+        } else {
+            Ok(settings)
+        }
+    }
+
     fn handle_unset_settings_request(&mut self, scope: &crate::settings::Scope) -> ApiResult<()> {
         debug!("Trying to unset settings for {}", scope);
         self.settings_repo().clear_settings(scope)?;
@@ -162,9 +189,11 @@ impl Node {
                 json,
                 response,
                 ignore_errors,
+                redact,
             } => {
                 let res = self
                     .handle_set_settings_request(&scope, json, ignore_errors)
+                    .and_then(|settings| Ok(self.maybe_redact(&scope, settings, redact)?))
                     .ax_inspect_err(|e| debug!("Error handling set settings request: {}", e));
                 if res.is_ok() {
                     info!(target: "NODE_SETTINGS_CHANGED", "Node settings at scope {} were changed.", scope);
@@ -181,10 +210,12 @@ impl Node {
                 scope,
                 response,
                 no_defaults,
+                redact,
             } => {
                 let res = self
                     .settings_repo()
                     .get_settings(&scope, no_defaults)
+                    .and_then(|settings| self.maybe_redact(&scope, settings, redact))
                     .map_err(Into::into);
                 let _ = response.send(res);
             }
@@ -227,6 +258,40 @@ impl Node {
                         .map_err(|_| ActyxOSError::internal("Failed to get node id")),
                 );
             }
+            NodesRequest::Status(sender) => {
+                let pending = self
+                    .components
+                    .iter()
+                    .map(|(component, channel)| {
+                        let (tx, rx) = bounded(1);
+                        let sent = match channel {
+                            ComponentChannel::Store(s) => s.try_send(ComponentRequest::HealthCheck(tx)).is_ok(),
+                            ComponentChannel::NodeApi(s) => s.try_send(ComponentRequest::HealthCheck(tx)).is_ok(),
+                            ComponentChannel::Logging(s) => s.try_send(ComponentRequest::HealthCheck(tx)).is_ok(),
+                            ComponentChannel::Android(s) => s.try_send(ComponentRequest::HealthCheck(tx)).is_ok(),
+                            #[cfg(test)]
+                            ComponentChannel::Test(s) => s.try_send(ComponentRequest::HealthCheck(tx)).is_ok(),
+                        };
+                        if !sent {
+                            // the dropped sender makes it show up as unresponsive
+                            debug!("component `{}` is not accepting requests", component);
+                        }
+                        (component.clone(), rx)
+                    })
+                    .collect::<Vec<_>>();
+                let uptime_secs = (Utc::now() - self.state.started_at)
+                    .to_std()
+                    .unwrap_or_default()
+                    .as_secs();
+                // don't block the node while waiting for the components
+                let _ = spawn_with_name("NodeStatus", move || {
+                    let _ = sender.send(Ok(NodesStatusResponse {
+                        components: collect_component_status(pending, HEALTH_CHECK_TIMEOUT),
+                        uptime_secs,
+                        peers_status: Default::default(),
+                    }));
+                });
+            }
         }
     }
     fn handle_restart_request(&self, component: ComponentType) {
@@ -250,7 +315,7 @@ impl Node {
     }
     fn update_node_state(&mut self) -> ActyxOSResult<()> {
         let node_settings = self.settings_repo().get_settings(&system_scope(), false)?;
-        let settings = serde_json::from_value(node_settings)
+        let settings = serde_json::from_value(node_settings.clone())
             .ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "Error deserializing system settings")?;
         if settings != self.state.settings {
             let details = NodeDetails::from_settings(
@@ -259,7 +324,12 @@ impl Node {
                     .get_or_create_node_id()
                     .map_err(|_| ActyxOSError::internal("Failed to get node id"))?,
             );
-            debug!("Setting node settings to: {:?}", settings);
+            debug!(
+                "Setting node settings to: {}",
+                self.settings_repo()
+                    .redact(&system_scope(), &node_settings)
+                    .unwrap_or_else(|_| crate::settings::REDACTED.into())
+            );
             self.state.settings = settings.clone();
             self.state.details = details;
             self.send(NodeEvent::StateUpdate(self.state.clone()))?;
@@ -269,7 +339,11 @@ impl Node {
     }
 
     fn send(&mut self, message: NodeEvent) -> ActyxOSResult<()> {
-        debug!("Node event {:?}", message);
+        match &message {
+            // the state contains the settings, which have already been logged in redacted form
+            NodeEvent::StateUpdate(_) => debug!("Node event StateUpdate"),
+            NodeEvent::Shutdown(_) => debug!("Node event {:?}", message),
+        }
         for (_, c) in &self.components {
             match c {
                 ComponentChannel::Store(s) => standard_lifecycle!(message, s),
@@ -374,6 +448,22 @@ impl Node {
     }
 }
 
+/// Wait for the answers to the health checks, reporting components that don't answer until
+/// `timeout` has elapsed as unresponsive.
+fn collect_component_status(
+    pending: Vec<(ComponentType, Receiver<ComponentStatus>)>,
+    timeout: Duration,
+) -> BTreeMap<String, ComponentStatus> {
+    let deadline = Instant::now() + timeout;
+    pending
+        .into_iter()
+        .map(|(component, rx)| {
+            let status = rx.recv_deadline(deadline).unwrap_or(ComponentStatus::Unresponsive);
+            (component.to_string(), status)
+        })
+        .collect()
+}
+
 #[derive(Clone)]
 pub(crate) enum ComponentChannel {
     Store(Sender<ComponentRequest<StoreRequest>>),
@@ -415,6 +505,7 @@ mod test {
             components::Component,
             node_settings::{EventRouting, Route, Settings},
         },
+        settings::REDACTED,
         util::formats::NodeName,
     };
     use anyhow::Result;
@@ -425,15 +516,13 @@ mod test {
     use tempfile::TempDir;
     use tokio::sync::oneshot::channel;
 
-    const NODE_SETTINGS_SCHEMA: &[u8] = include_bytes!("../../resources/json-schema/node-settings.schema.json");
-
     #[tokio::test]
     async fn should_handle_settings_requests() {
         let (_runtime_tx, runtime_rx) = crossbeam::channel::bounded(8);
         let temp_dir = TempDir::new().unwrap();
         let runtime = Host::new(temp_dir.path().to_path_buf()).unwrap();
         let mut node = Node::new(runtime_rx, vec![], runtime).unwrap();
-        let schema = serde_json::from_slice(NODE_SETTINGS_SCHEMA).unwrap();
+        let schema = Settings::schema().unwrap();
         let scope = system_scope();
         let json = json!(
           {
@@ -442,6 +531,8 @@ mod test {
               "initialPeers": [ "/ip4/127.0.0.1/tcp/4001/p2p/QmaAxuktPMR3ESHe9Pru8kzzzSGvsUie7UFJPfCWqTzzzz" ],
               "announceAddresses": [],
               "topic": "My Topic",
+              "previousTopics": [],
+              "previousTopicsGracePeriod": 604800,
               "blockGcInterval": 300,
               "blockCacheSize": 1073741824,
               "blockCacheCount": 131072,
@@ -451,14 +542,37 @@ mod test {
               "mdns": true,
               "branchCacheSize": 67108864,
               "gossipInterval": 10,
+              "gossipMaxInterval": 60,
+              "fastPathBatch": 50,
               "detectionCyclesLowLatency": 2,
-              "detectionCyclesHighLatency": 5
+              "detectionCyclesHighLatency": 5,
+              "peerStaleAfter": 600,
+              "quarantineThreshold": 20,
+              "quarantineWindow": 60,
+              "quarantineDuration": 600,
+              "maxLamportJump": 1099511627776,
+              "lazyStreamLoading": false,
+              "knownPeersMaxAge": 604800,
+              "prometheus": false,
+              "decisionLog": false,
+              "maintenance": {
+                "windows": [],
+                "outsideWindows": {
+                  "compaction": "reduced",
+                  "pruning": "pause"
+                }
+              }
             },
             "admin": {
               "displayName": "My Node",
               "authorizedUsers": [],
+              "readSecrets": [],
               "logLevels": {
                 "node": "WARN"
+              },
+              "logBuffer": {
+                "records": 10000,
+                "maxMessageLength": 4096
               }
             },
             "licensing": {
@@ -470,6 +584,10 @@ mod test {
             "api": {
               "events": {
                 "readOnly": false,
+                "queryTimeout": 300,
+                "maxQueryTimeout": 3600,
+                "adminQueriesUnlimited": false,
+                "payloadSchemas": {},
                 "_internal": {
                   "allow_publish": true,
                   "topic": "actyxos-demo"
@@ -513,6 +631,7 @@ mod test {
                 json: json.clone(),
                 response,
                 ignore_errors: false,
+                redact: false,
             });
 
             assert_eq!(json, rx.await.unwrap().unwrap());
@@ -526,6 +645,7 @@ mod test {
                 scope: "com.actyx/admin/displayName".parse().unwrap(),
                 no_defaults: false,
                 response,
+                redact: false,
             });
             assert_eq!("My Node", rx.await.unwrap().unwrap());
         }
@@ -537,6 +657,7 @@ mod test {
                 json: changed.clone(),
                 response,
                 ignore_errors: false,
+                redact: false,
             });
 
             assert_eq!(rx.await.unwrap().unwrap(), changed);
@@ -549,6 +670,7 @@ mod test {
                 json: invalid,
                 response,
                 ignore_errors: false, // <=========
+                redact: false,
             });
             assert_eq!(
                 rx.await.unwrap(),
@@ -565,6 +687,7 @@ mod test {
                 json: invalid,
                 response,
                 ignore_errors: true, // <=========
+                redact: false,
             });
             assert_eq!(
                 rx.await.unwrap(),
@@ -588,6 +711,7 @@ mod test {
                 json,
                 response,
                 ignore_errors: false,
+                redact: false,
             });
             assert_eq!(
                 rx.await.unwrap(),
@@ -603,7 +727,7 @@ mod test {
         let temp_dir = TempDir::new().unwrap();
         let runtime = Host::new(temp_dir.path().to_path_buf()).unwrap();
         let mut node = Node::new(runtime_rx, vec![], runtime).unwrap();
-        let schema = serde_json::from_slice(NODE_SETTINGS_SCHEMA).unwrap();
+        let schema = Settings::schema().unwrap();
         {
             let (response, rx) = channel();
             node.handle_settings_request(SettingsRequest::SetSchema {
@@ -637,6 +761,7 @@ mod test {
             json: json.clone(),
             response,
             ignore_errors: false,
+            redact: false,
         });
         assert_eq!(json, rx.await.unwrap().unwrap());
         let expected_event_routing = EventRouting {
@@ -664,6 +789,81 @@ mod test {
         assert_eq!(node.state.settings.event_routing, expected_event_routing);
     }
 
+    #[tokio::test]
+    async fn should_redact_settings() {
+        let (_runtime_tx, runtime_rx) = crossbeam::channel::bounded(8);
+        let temp_dir = TempDir::new().unwrap();
+        let runtime = Host::new(temp_dir.path().to_path_buf()).unwrap();
+        let mut node = Node::new(runtime_rx, vec![], runtime).unwrap();
+        let schema = Settings::schema().unwrap();
+        {
+            let (response, rx) = channel();
+            node.handle_settings_request(SettingsRequest::SetSchema {
+                scope: system_scope(),
+                json: schema,
+                response,
+            });
+            rx.await.unwrap().unwrap();
+        }
+        let swarm_key = "MDAwMDAwMDAxMTExMTExMTIyMjIyMjIyMzMzMzMzMzM=";
+        let licensing = json!({ "node": "development", "apps": { "com.example.sample": "testing" } });
+        let redacted_licensing = json!({ "node": REDACTED, "apps": { "com.example.sample": REDACTED } });
+        let set = |node: &mut Node, scope: &str, json: serde_json::Value, redact: bool| {
+            let (response, rx) = channel();
+            node.handle_settings_request(SettingsRequest::SetSettings {
+                scope: scope.parse().unwrap(),
+                json,
+                response,
+                ignore_errors: false,
+                redact,
+            });
+            rx
+        };
+        let get = |node: &mut Node, scope: &str, redact: bool| {
+            let (response, rx) = channel();
+            node.handle_settings_request(SettingsRequest::GetSettings {
+                scope: scope.parse().unwrap(),
+                no_defaults: false,
+                response,
+                redact,
+            });
+            rx
+        };
+
+        let rx = set(&mut node, "com.actyx/swarm/swarmKey", json!(swarm_key), true);
+        assert_eq!(rx.await.unwrap().unwrap(), json!(REDACTED));
+        let rx = set(&mut node, "com.actyx/licensing", licensing.clone(), true);
+        assert_eq!(rx.await.unwrap().unwrap(), redacted_licensing);
+        let rx = set(
+            &mut node,
+            "com.actyx/eventRouting/streams",
+            json!({ "logs": { "maxEvents": 1 } }),
+            true,
+        );
+        assert_eq!(rx.await.unwrap().unwrap(), json!({ "logs": { "maxEvents": 1 } }));
+
+        let rx = get(&mut node, "com.actyx", true);
+        let settings = rx.await.unwrap().unwrap();
+        assert_eq!(settings["swarm"]["swarmKey"], json!(REDACTED));
+        assert_eq!(settings["swarm"]["topic"], json!("default-topic"));
+        assert_eq!(settings["licensing"], redacted_licensing);
+        assert_eq!(settings["admin"]["displayName"], json!("Default Node"));
+        assert_eq!(
+            settings["eventRouting"]["streams"],
+            json!({ "logs": { "maxEvents": 1 } })
+        );
+        let rx = get(&mut node, ".", true);
+        assert_eq!(rx.await.unwrap().unwrap(), json!({ "com.actyx": settings }));
+        let rx = get(&mut node, "com.actyx/swarm/swarmKey", true);
+        assert_eq!(rx.await.unwrap().unwrap(), json!(REDACTED));
+
+        // callers allowed to read secrets get the real values
+        let rx = get(&mut node, "com.actyx/swarm/swarmKey", false);
+        assert_eq!(rx.await.unwrap().unwrap(), json!(swarm_key));
+        let rx = get(&mut node, "com.actyx/licensing", false);
+        assert_eq!(rx.await.unwrap().unwrap(), licensing);
+    }
+
     struct DummyComponent {
         node_rx: Receiver<ComponentRequest<()>>,
     }
@@ -691,6 +891,44 @@ mod test {
         }
     }
 
+    #[test]
+    fn database_from_future_is_reported() {
+        let err = NodeError::from(anyhow::Error::from(WrongVersionFuture(CURRENT_VERSION + 1)));
+        assert!(
+            matches!(err, NodeError::DatabaseFromFuture { found, supported } if found == CURRENT_VERSION + 1 && supported == CURRENT_VERSION),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn unresponsive_components_in_status() {
+        let (running_tx, running_rx) = bounded(1);
+        let (_stuck_tx, stuck_rx) = bounded(1);
+        let (gone_tx, gone_rx) = bounded::<ComponentStatus>(1);
+        running_tx.send(ComponentStatus::Running).unwrap();
+        drop(gone_tx);
+
+        let start = Instant::now();
+        let status = collect_component_status(
+            vec![
+                ("running".into(), running_rx),
+                ("stuck".into(), stuck_rx),
+                ("gone".into(), gone_rx),
+            ],
+            Duration::from_millis(100),
+        );
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(
+            status,
+            BTreeMap::from([
+                ("gone".to_owned(), ComponentStatus::Unresponsive),
+                ("running".to_owned(), ComponentStatus::Running),
+                ("stuck".to_owned(), ComponentStatus::Unresponsive),
+            ])
+        );
+    }
+
     #[test]
     fn handle_component_lifecycle() -> anyhow::Result<()> {
         // Bootstrap
@@ -806,6 +1044,7 @@ mod test {
                 json: json.clone(),
                 scope: system_scope(),
                 response: req_tx,
+                redact: false,
             }))
             .unwrap();
         assert_eq!(block_on(req_rx).unwrap().unwrap(), json);
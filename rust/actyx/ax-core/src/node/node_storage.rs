@@ -36,7 +36,7 @@ pub struct WrongVersionV0;
     fmt = "Attempting to start AX v2 with a data directory from a future version (schema ID is {})",
     _0
 )]
-pub struct WrongVersionFuture(u32);
+pub struct WrongVersionFuture(pub u32);
 impl std::error::Error for WrongVersionFuture {}
 
 pub const CURRENT_VERSION: u32 = 3;
@@ -69,18 +69,6 @@ impl NodeStorage {
         })
     }
 
-    pub fn migrate(conn: &mut Connection, version: u32) -> anyhow::Result<()> {
-        match version {
-            2 => Ok(conn.execute_batch(
-                "DROP TABLE IF EXISTS meta;
-                    DROP TABLE IF EXISTS streams;
-                    UPDATE node SET value = 3 WHERE name = 'database_version';",
-            )?),
-            CURRENT_VERSION => Ok(()),
-            _ => unreachable!(),
-        }
-    }
-
     fn initialize_db(conn: &mut Connection) -> anyhow::Result<()> {
         match conn.query_row(
             "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'node'",
@@ -17,12 +17,16 @@ pub enum SettingsRequest {
         scope: crate::settings::Scope,
         no_defaults: bool,
         response: Sender<SettingsResponse<serde_json::Value>>,
+        /// Replace sensitive values in the response, see [`crate::settings::redact`]
+        redact: bool,
     },
     SetSettings {
         scope: crate::settings::Scope,
         json: serde_json::Value,
         response: Sender<SettingsResponse<serde_json::Value>>,
         ignore_errors: bool,
+        /// Replace sensitive values in the response, see [`crate::settings::redact`]
+        redact: bool,
     },
     UnsetSettings {
         scope: crate::settings::Scope,
@@ -1,11 +1,12 @@
 use crate::{
     authority::Authority,
     crypto::PublicKey,
-    libp2p_streaming_response::{RequestReceived, Response, StreamingResponse, StreamingResponseConfig},
+    libp2p_streaming_response::{Response, StreamingResponse, StreamingResponseConfig, StreamingResponseEvent},
     private_key::AxPrivateKey,
-    swarm::transport::build_transport,
+    swarm::transport::{build_transport, TcpSocketConfig},
     util::{
         formats::{
+            admin_protocol::AX_AGENT_PREFIX,
             banyan_protocol::{BanyanProtocol, BanyanProtocolName, BanyanRequest, BanyanResponse},
             events_protocol::{EventsProtocol, EventsRequest, EventsResponse},
             ActyxOSCode, ActyxOSError, ActyxOSResult, ActyxOSResultExt, AdminProtocol, AdminRequest, AdminResponse,
@@ -15,7 +16,7 @@ use crate::{
 };
 use anyhow::anyhow;
 use ax_types::{
-    service::{Diagnostic, EventResponse, PublishResponse},
+    service::{Diagnostic, EventResponse, PublishResponse, QueryChecksum, QueryProgress, QueryTimeout},
     NodeId, Payload,
 };
 use derive_more::From;
@@ -60,8 +61,8 @@ struct Behaviour {
 #[derive(Debug, From)]
 #[allow(clippy::large_enum_variant)]
 enum OutEvent {
-    Admin(RequestReceived<AdminProtocol>),
-    Events(RequestReceived<EventsProtocol>),
+    Admin(StreamingResponseEvent<AdminProtocol>),
+    Events(StreamingResponseEvent<EventsProtocol>),
     Banyan(RequestResponseEvent<BanyanRequest, BanyanResponse>),
     Ping(ping::Event),
     Identify(identify::Event),
@@ -96,7 +97,7 @@ pub async fn mk_swarm(key: AxPrivateKey) -> ActyxOSResult<(impl Future<Output =
     let key_pair = key.to_libp2p_pair();
     let public_key = key_pair.public();
     let local_peer_id = public_key.to_peer_id();
-    let transport = build_transport(key_pair, None, Duration::from_secs(20))
+    let transport = build_transport(key_pair, None, Duration::from_secs(20), TcpSocketConfig::default())
         .await
         .ax_err_ctx(ActyxOSCode::ERR_INTERNAL_ERROR, "cannot build network transport")?;
     let behaviour = Behaviour {
@@ -117,7 +118,9 @@ pub async fn mk_swarm(key: AxPrivateKey) -> ActyxOSResult<(impl Future<Output =
         ),
         ping: ping::Behaviour::new(ping::Config::new()),
         identify: identify::Behaviour::new(
-            identify::Config::new("Actyx".to_owned(), public_key).with_initial_delay(Duration::ZERO),
+            identify::Config::new("Actyx".to_owned(), public_key)
+                .with_agent_version(format!("{}{}", AX_AGENT_PREFIX, NodeVersion::get()))
+                .with_initial_delay(Duration::ZERO),
         ),
     };
     let mut swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
@@ -427,6 +430,12 @@ fn forward_stream<T: Send + 'static, U: Send + 'static>(
                         return;
                     }
                     Response::Finished => return,
+                    Response::Cancelled(reason) => {
+                        if let Err(e) = tx.feed(Err(ActyxOSCode::ERR_IO.with_message(reason.to_string()))).await {
+                            tracing::error!("cannot transfer cancellation {}: {}", reason, e);
+                        }
+                        return;
+                    }
                 };
             }
             tracing::error!("response stream ended abruptly");
@@ -503,6 +512,9 @@ pub enum EventDiagnostic {
     Event(EventResponse<Payload>),
     AntiEvent(EventResponse<Payload>),
     Diagnostic(Diagnostic),
+    Progress(QueryProgress),
+    Checksum(QueryChecksum),
+    Timeout(QueryTimeout),
 }
 
 pub async fn request_events(
@@ -520,6 +532,9 @@ pub async fn request_events(
                 ready(Some(Err(ActyxOSCode::ERR_INVALID_INPUT.with_message(message))))
             }
             Ok(EventsResponse::Diagnostic(d)) => ready(Some(Ok(EventDiagnostic::Diagnostic(d)))),
+            Ok(EventsResponse::Progress(p)) => ready(Some(Ok(EventDiagnostic::Progress(p)))),
+            Ok(EventsResponse::Checksum(c)) => ready(Some(Ok(EventDiagnostic::Checksum(c)))),
+            Ok(EventsResponse::Timeout(t)) => ready(Some(Ok(EventDiagnostic::Timeout(t)))),
             Ok(EventsResponse::OffsetMap { offsets }) => {
                 tracing::info!("received OffsetMap covering {} events", offsets.size());
                 ready(None)
@@ -40,6 +40,8 @@ pub enum RuntimeError {
 pub enum RuntimeFailure {
     #[display(fmt = "anti-input cannot be processed in saturated LIMIT")]
     AntiInputInLimit,
+    #[display(fmt = "anti-input cannot be processed after OFFSET has been passed")]
+    AntiInputInOffset,
     #[display(fmt = "anti-input cannot be processed in LAST()")]
     AntiInputInLast,
     #[display(fmt = "anti-input cannot be processed in FIRST()")]
@@ -73,6 +73,8 @@ features! {
     // unclear: metadata for results, interaction with aggregate on subscribe endpoints
     subQuery: Beta [Subscribe SubscribeMonotonic],
     limit: Released [SubscribeMonotonic],
+    // unclear: interaction with anti-inputs on subscribe endpoints
+    offset: Beta [SubscribeMonotonic],
     binding: Released [],
     // unclear: canonical string representation of all value kinds
     interpolation: Beta [],
@@ -188,6 +190,9 @@ fn features_op(feat: &mut Features, op: &Operation) {
         Operation::Limit(_) => {
             feat.add(limit);
         }
+        Operation::Offset(_) => {
+            feat.add(offset);
+        }
         Operation::Binding(_, e) => {
             feat.add(binding);
             features_simple(feat, e);
@@ -344,6 +349,7 @@ mod tests {
             f("FROM 'x' AGGREGATE FROM 'y' LIMIT 1").0,
             btreeset!(aggregate, subQuery, limit)
         );
+        assert_eq!(f("FROM 'x' OFFSET 2 LIMIT 1").0, btreeset!(limit, offset));
     }
 
     #[test]
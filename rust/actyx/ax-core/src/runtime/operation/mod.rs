@@ -17,6 +17,7 @@ pub enum Operation {
     Select(NonEmptyVec<SpreadExpr>),
     Aggregate(SimpleExpr),
     Limit(NonZeroU64),
+    Offset(u64),
     Binding(String, SimpleExpr),
 }
 
@@ -50,6 +51,7 @@ impl Operation {
             Operation::Select(s) => Box::new(Select(s.clone())),
             Operation::Aggregate(a) => aggregate::aggregate(a),
             Operation::Limit(l) => Box::new(Limit((*l).into())),
+            Operation::Offset(o) => Box::new(Offset(*o)),
             Operation::Binding(n, e) => Box::new(Binding(n.clone(), e.clone())),
         }
     }
@@ -62,6 +64,7 @@ impl From<ax_aql::Operation> for Operation {
             ax_aql::Operation::Select(s) => Self::Select(s),
             ax_aql::Operation::Aggregate(a) => Self::Aggregate(a),
             ax_aql::Operation::Limit(l) => Self::Limit(l),
+            ax_aql::Operation::Offset(o) => Self::Offset(o),
             ax_aql::Operation::Binding(n, e) => Self::Binding(n, e),
         }
     }
@@ -183,6 +186,36 @@ impl Processor for Limit {
     }
 }
 
+struct Offset(u64);
+impl Processor for Offset {
+    fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            /*
+             * Anti-flag propagation:
+             *
+             * As long as inputs are being skipped, an anti-input can only refer to a skipped
+             * input, so it is skipped as well and one more input needs to be skipped. Once
+             * inputs are passed on we cannot know whether the corresponding output was emitted
+             * or suppressed earlier, so we stop the query with an error.
+             */
+            let v = cx.remove("_");
+            if self.0 > 0 {
+                match &v {
+                    Ok(v) if v.is_anti() => self.0 += 1,
+                    Ok(_) => self.0 -= 1,
+                    Err(_) => return vec![v],
+                }
+                vec![]
+            } else if v.as_ref().map(|v| v.is_anti()).unwrap_or_default() {
+                vec![Err(RuntimeFailure::AntiInputInOffset.into())]
+            } else {
+                vec![v]
+            }
+        }
+        .boxed()
+    }
+}
+
 struct Binding(String, SimpleExpr);
 impl Processor for Binding {
     fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
@@ -8,7 +8,7 @@ use crate::{
 };
 use ax_aql::{Arr, Galactus, Tactic, TagAtom};
 use ax_types::{service::Order, AppId};
-use futures::{stream, StreamExt};
+use futures::{stream, Stream, StreamExt};
 
 pub struct Pragmas<'a>(Vec<(&'a str, &'a str)>);
 
@@ -23,6 +23,61 @@ impl<'a> Pragmas<'a> {
     }
 }
 
+/// The leading `OFFSET` and `LIMIT` stages of a query, which are applied while reading its source
+/// instead of by the [`Feeder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourceWindow {
+    /// inputs dropped before the first one is passed on
+    pub skip: u64,
+    /// inputs passed on after skipping
+    pub limit: Option<u64>,
+    /// number of leading stages covered by this window
+    stages: usize,
+}
+
+impl SourceWindow {
+    fn offset(&mut self, offset: u64) {
+        self.skip = self.skip.saturating_add(offset);
+        self.limit = self.limit.map(|limit| limit.saturating_sub(offset));
+        self.stages += 1;
+    }
+
+    fn limit(&mut self, limit: u64) {
+        self.limit = Some(self.limit.map_or(limit, |l| l.min(limit)));
+        self.stages += 1;
+    }
+
+    /// The number of events needed from the store, which can then already stop reading.
+    pub fn events(&self) -> Option<u64> {
+        self.limit
+            .map(|limit| if limit == 0 { 0 } else { limit.saturating_add(self.skip) })
+    }
+
+    /// Skip and then take inputs according to this window, errors are passed on without counting.
+    pub fn apply<T, E>(self, inputs: impl Stream<Item = Result<T, E>>) -> impl Stream<Item = Result<T, E>> {
+        stream::unfold(
+            (Box::pin(inputs), self.skip, self.limit),
+            |(mut inputs, mut skip, limit)| async move {
+                if limit == Some(0) {
+                    return None;
+                }
+                loop {
+                    match inputs.next().await? {
+                        Ok(_) if skip > 0 => skip -= 1,
+                        input => {
+                            let limit = match &input {
+                                Ok(_) => limit.map(|limit| limit - 1),
+                                Err(_) => limit,
+                            };
+                            return Some((input, (inputs, skip, limit)));
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Query {
     pub features: Vec<String>,
@@ -55,6 +110,19 @@ impl Query {
         )
     }
 
+    /// The `OFFSET` and `LIMIT` stages the query starts with, see [`make_feeder_after`](Self::make_feeder_after).
+    pub fn source_window(&self) -> SourceWindow {
+        let mut window = SourceWindow::default();
+        for stage in &self.stages {
+            match stage {
+                Operation::Offset(offset) => window.offset(*offset),
+                Operation::Limit(limit) => window.limit(limit.get()),
+                _ => break,
+            }
+        }
+        window
+    }
+
     pub fn enabled_features(&self, pragmas: &Pragmas) -> Vec<String> {
         let mut ret = self.features.clone();
         if let Some(s) = pragmas.pragma("features") {
@@ -65,9 +133,18 @@ impl Query {
 
     /// run a query in the given evaluation context and collect all results
     pub async fn eval(query: &ax_aql::Query<'static>, cx: &Context<'_>) -> Result<Vec<Value>, anyhow::Error> {
-        let mut feeder = Query::feeder_from(&query.ops);
+        let mut window = SourceWindow::default();
+        for op in &query.ops {
+            match op {
+                ax_aql::Operation::Offset(offset) => window.offset(*offset),
+                ax_aql::Operation::Limit(limit) => window.limit(limit.get()),
+                _ => break,
+            }
+        }
+        let mut feeder = Query::feeder_from(&query.ops[window.stages..]);
+        let limit = window.events();
 
-        let (mut stream, cx) = match &query.source {
+        let (stream, cx) = match &query.source {
             ax_aql::Source::Events { from, order } => {
                 let tag_expr = cx.eval_from(from).await?.into_owned();
                 let (stream, cx) = if order.or_else(|| feeder.preferred_order()) == Some(Order::Desc) {
@@ -77,6 +154,7 @@ impl Query {
                             tag_expr,
                             cx.from_offsets_excluding().clone(),
                             cx.to_offsets_including().clone(),
+                            limit,
                         )
                         .await?
                         .stop_on_error();
@@ -89,6 +167,7 @@ impl Query {
                             cx.from_offsets_excluding().clone(),
                             cx.to_offsets_including().clone(),
                             false, // must keep order because some stage may have demanded it
+                            limit,
                         )
                         .await?
                         .stop_on_error();
@@ -107,6 +186,7 @@ impl Query {
                 cx.child(),
             ),
         };
+        let mut stream = window.apply(stream);
 
         let mut results = vec![];
         while let Some(ev) = stream.next().await {
@@ -135,6 +215,15 @@ impl Query {
         Feeder::new(processors)
     }
 
+    /// The feeder for the stages following the `window`, which is to be applied to the inputs.
+    pub fn make_feeder_after(&self, window: &SourceWindow) -> Feeder {
+        let processors = self.stages[window.stages..]
+            .iter()
+            .map(|op| op.make_processor())
+            .collect();
+        Feeder::new(processors)
+    }
+
     pub fn feeder_from(stages: &[ax_aql::Operation]) -> Feeder {
         let processors = stages
             .iter()
@@ -332,6 +421,26 @@ mod tests {
         assert!(!f.is_done());
     }
 
+    #[test]
+    fn source_window() {
+        let window = |q: &str| {
+            let query = Query::from(ax_aql::Query::parse(q).unwrap(), app_id!("com.actyx.test")).0;
+            let window = query.source_window();
+            (
+                window.skip,
+                window.limit,
+                window.events(),
+                query.stages.len() - window.stages,
+            )
+        };
+        assert_eq!(window("FROM 'x' FILTER x LIMIT 2"), (0, None, None, 2));
+        assert_eq!(window("FROM 'x' LIMIT 2 FILTER x"), (0, Some(2), Some(2), 1));
+        assert_eq!(window("FROM 'x' OFFSET 3 FILTER x"), (3, None, None, 1));
+        assert_eq!(window("FROM 'x' OFFSET 3 LIMIT 2"), (3, Some(2), Some(5), 0));
+        assert_eq!(window("FROM 'x' LIMIT 5 OFFSET 3 LIMIT 4"), (3, Some(2), Some(5), 0));
+        assert_eq!(window("FROM 'x' LIMIT 2 OFFSET 3"), (3, Some(0), Some(0), 0));
+    }
+
     #[tokio::test]
     async fn binding() {
         assert_eq!(
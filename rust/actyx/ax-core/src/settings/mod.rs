@@ -2,12 +2,16 @@ mod database;
 mod formats;
 mod json_differ;
 mod json_value;
+mod redaction;
 mod repository;
 mod scope;
 mod validation;
 
 pub use crate::settings::{
     database::{Database, DB_FILENAME},
+    redaction::{
+        mark_sensitive, redact, redact_at, restore_redacted, sensitive, sensitive_values, REDACTED, SENSITIVE,
+    },
     repository::{Error as RepositoryError, Repository},
     scope::{Error as ScopeError, Scope},
     validation::{Error as ValidationError, ValidationErrorDescr, ValidationState, Validator},
@@ -0,0 +1,410 @@
+use crate::settings::Scope;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cell::Cell;
+
+/// Schema keyword marking a settings value as sensitive.
+pub const SENSITIVE: &str = "x-sensitive";
+/// Placeholder replacing redacted settings values.
+pub const REDACTED: &str = "<redacted>";
+
+/// Written in place of sensitive fields while [`mark_sensitive`] probes a settings type
+const PROBE: &str = "x-sensitive-probe";
+
+thread_local! {
+    static PROBING: Cell<bool> = Cell::new(false);
+}
+
+fn probing() -> bool {
+    PROBING.with(Cell::get)
+}
+
+/// Serde helper for sensitive settings fields, `#[serde(with = "crate::settings::sensitive")]`.
+///
+/// The field is (de)serialized as usual, [`mark_sensitive`] adds the [`SENSITIVE`] keyword to its
+/// schema.
+pub mod sensitive {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        if super::probing() {
+            serializer.serialize_str(super::PROBE)
+        } else {
+            value.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
+
+/// Like [`sensitive`], for maps whose values are sensitive while their keys are not.
+pub mod sensitive_values {
+    use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &BTreeMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if super::probing() {
+            // stands for any key, the map may well be empty
+            let mut probe = serializer.serialize_map(Some(1))?;
+            probe.serialize_entry(super::PROBE, super::PROBE)?;
+            probe.end()
+        } else {
+            map.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        BTreeMap::deserialize(deserializer)
+    }
+}
+
+/// Adds the [`SENSITIVE`] keyword to the nodes of `schema` describing the fields of `probe` that
+/// are marked with [`sensitive`] or [`sensitive_values`]. Any value of the settings type will do as
+/// `probe`, the markers are a property of the type.
+///
+/// Fails with the path of a marked field that is not described by the schema.
+pub fn mark_sensitive<T: Serialize>(probe: &T, schema: &mut Value) -> Result<(), String> {
+    PROBING.with(|p| p.set(true));
+    let probe = serde_json::to_value(probe);
+    PROBING.with(|p| p.set(false));
+    let probe = probe.map_err(|e| e.to_string())?;
+
+    let mut paths = vec![];
+    probed_paths(&probe, &mut vec![], &mut paths);
+    for path in paths {
+        let node = schema_pointer(schema, &path)
+            .and_then(|pointer| schema.pointer_mut(&pointer))
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| format!("/{}", path.join("/")))?;
+        node.insert(SENSITIVE.to_owned(), Value::Bool(true));
+    }
+    Ok(())
+}
+
+fn probed_paths(value: &Value, path: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match value {
+        Value::String(s) if s == PROBE => paths.push(path.clone()),
+        Value::Object(members) => {
+            for (name, value) in members {
+                path.push(name.clone());
+                probed_paths(value, path, paths);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The JSON pointer to the schema node describing the settings value at `path`.
+///
+/// `$ref`s are followed to look up the members of an object, but not from the member itself, so
+/// that other values referring to the same definition are not marked.
+fn schema_pointer(schema: &Value, path: &[String]) -> Option<String> {
+    let mut pointer = String::new();
+    for name in path {
+        let mut node_pointer = pointer.clone();
+        let mut member = None;
+        for _ in 0..32 {
+            let node = schema.pointer(&node_pointer)?;
+            if node.get("properties").and_then(|p| p.get(name)).is_some() {
+                let name = name.replace('~', "~0").replace('/', "~1");
+                member = Some(format!("{}/properties/{}", node_pointer, name));
+            } else if node.get("additionalProperties").filter(|a| a.is_object()).is_some() {
+                member = Some(format!("{}/additionalProperties", node_pointer));
+            } else if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+                node_pointer = reference.strip_prefix('#')?.to_owned();
+                continue;
+            }
+            break;
+        }
+        pointer = member?;
+    }
+    Some(pointer)
+}
+
+/// Replaces the [`REDACTED`] placeholders in `settings` with the values at the same place in
+/// `current`, so that redacted settings can be written back without overwriting the secrets.
+///
+/// Fails with the path of a placeholder that does not stand for a current value.
+pub fn restore_redacted(settings: &mut Value, current: Option<&Value>) -> Result<(), String> {
+    restore_redacted0(settings, current, &mut String::new())
+}
+
+fn restore_redacted0(settings: &mut Value, current: Option<&Value>, path: &mut String) -> Result<(), String> {
+    match settings {
+        Value::String(s) if s == REDACTED => {
+            *settings = current.cloned().ok_or_else(|| path.clone())?;
+        }
+        Value::Object(members) => {
+            for (name, value) in members.iter_mut() {
+                let len = path.len();
+                path.push('/');
+                path.push_str(name);
+                restore_redacted0(value, current.and_then(|c| c.get(name.as_str())), path)?;
+                path.truncate(len);
+            }
+        }
+        Value::Array(elements) => {
+            for (idx, value) in elements.iter_mut().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("/{}", idx));
+                restore_redacted0(value, current.and_then(|c| c.get(idx)), path)?;
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces all values in `settings` that are marked as sensitive in `schema` with [`REDACTED`].
+///
+/// A value is sensitive if its schema node (or any schema it refers to via `$ref`, `allOf`,
+/// `anyOf` or `oneOf`) contains `"x-sensitive": true`. Object members that are not described by
+/// the schema — neither listed in `properties` nor covered by an `additionalProperties` schema —
+/// are redacted as well, since nothing is known about their contents. The values of maps whose
+/// keys are only constrained via `propertyNames` are passed through as they are.
+pub fn redact(settings: &Value, schema: &Value) -> Value {
+    redact_at(settings, schema, &Scope::root())
+}
+
+/// Like [`redact`], for `settings` found at `path` below the root of `schema`.
+pub fn redact_at(settings: &Value, schema: &Value, path: &Scope) -> Value {
+    let redactor = Redactor { root: schema };
+    let mut node = Some(schema);
+    for name in path.iter() {
+        node = node.and_then(|n| {
+            let index = || name.parse::<usize>().ok().and_then(|_| redactor.items(n));
+            redactor.member(n, name).or_else(index)
+        });
+    }
+    match node {
+        Some(node) => redactor.redact(node, settings),
+        None => Value::from(REDACTED),
+    }
+}
+
+struct Redactor<'a> {
+    root: &'a Value,
+}
+
+impl<'a> Redactor<'a> {
+    /// Follows `$ref` pointers into the root schema, returning all schema nodes on the way.
+    fn resolve(&self, mut node: &'a Value) -> Vec<&'a Value> {
+        let mut nodes = vec![node];
+        while let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+            match reference.strip_prefix('#').and_then(|ptr| self.root.pointer(ptr)) {
+                Some(target) if nodes.len() < 32 => {
+                    nodes.push(target);
+                    node = target;
+                }
+                _ => break,
+            }
+        }
+        nodes
+    }
+
+    fn is_sensitive(&self, node: &'a Value) -> bool {
+        self.resolve(node).into_iter().any(|n| {
+            n.get(SENSITIVE).and_then(Value::as_bool) == Some(true)
+                || ["allOf", "anyOf", "oneOf"].iter().any(|combinator| {
+                    n.get(combinator)
+                        .and_then(Value::as_array)
+                        .map(|branches| branches.iter().any(|b| self.is_sensitive(b)))
+                        .unwrap_or_default()
+                })
+        })
+    }
+
+    /// The schema describing the member `name` of an object described by `node`.
+    fn member(&self, node: &'a Value, name: &str) -> Option<&'a Value> {
+        const ANY: &Value = &Value::Bool(true);
+        let nodes = self.resolve(node);
+        let property = nodes.iter().find_map(|n| n.get("properties").and_then(|p| p.get(name)));
+        let additional = || {
+            nodes
+                .iter()
+                .find_map(|n| n.get("additionalProperties").filter(|a| a.is_object()))
+        };
+        let named = || nodes.iter().any(|n| n.get("propertyNames").is_some()).then_some(ANY);
+        property.or_else(additional).or_else(named)
+    }
+
+    fn items(&self, node: &'a Value) -> Option<&'a Value> {
+        self.resolve(node)
+            .into_iter()
+            .find_map(|n| n.get("items").filter(|i| i.is_object()))
+    }
+
+    fn redact(&self, node: &'a Value, value: &Value) -> Value {
+        if node == &Value::Bool(true) {
+            return value.clone();
+        }
+        if self.is_sensitive(node) {
+            return Value::from(REDACTED);
+        }
+        match value {
+            Value::Object(members) => Value::Object(
+                members
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = match self.member(node, name) {
+                            Some(member) => self.redact(member, value),
+                            None => Value::from(REDACTED),
+                        };
+                        (name.clone(), value)
+                    })
+                    .collect::<Map<_, _>>(),
+            ),
+            Value::Array(elements) => match self.items(node) {
+                Some(items) => Value::Array(elements.iter().map(|e| self.redact(items, e)).collect()),
+                None => Value::Array(elements.iter().map(|_| Value::from(REDACTED)).collect()),
+            },
+            value => value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "key": { "$ref": "#/definitions/Secret" },
+                "tokens": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string", "x-sensitive": true }
+                },
+                "peers": { "type": "array", "items": { "type": "string" } },
+                "either": { "oneOf": [{ "$ref": "#/definitions/Secret" }, { "const": "none" }] },
+                "open": { "type": "object", "additionalProperties": true },
+                "map": { "type": "object", "propertyNames": { "pattern": "^[a-z]+$" } }
+            },
+            "definitions": {
+                "Secret": { "type": "string", "x-sensitive": true }
+            }
+        })
+    }
+
+    #[test]
+    fn redact_marked_and_unknown() {
+        let settings = json!({
+            "name": "node",
+            "key": "secret",
+            "tokens": { "a": "t1", "b": "t2" },
+            "peers": ["p1", "p2"],
+            "either": "none",
+            "open": { "x": 1 },
+            "map": { "a": { "b": 1 } },
+            "extra": { "y": 2 }
+        });
+        assert_eq!(
+            redact(&settings, &schema()),
+            json!({
+                "name": "node",
+                "key": REDACTED,
+                "tokens": { "a": REDACTED, "b": REDACTED },
+                "peers": ["p1", "p2"],
+                "either": REDACTED,
+                "open": { "x": REDACTED },
+                "map": { "a": { "b": 1 } },
+                "extra": REDACTED
+            })
+        );
+    }
+
+    #[test]
+    fn redact_below_root() {
+        let schema = schema();
+        let at = |path: &str, value: Value| redact_at(&value, &schema, &path.parse().unwrap());
+        assert_eq!(at("key", json!("secret")), json!(REDACTED));
+        assert_eq!(at("tokens", json!({ "a": "t1" })), json!({ "a": REDACTED }));
+        assert_eq!(at("tokens/a", json!("t1")), json!(REDACTED));
+        assert_eq!(at("peers", json!(["p1"])), json!(["p1"]));
+        assert_eq!(at("peers/0", json!("p1")), json!("p1"));
+        assert_eq!(at("extra/y", json!(2)), json!(REDACTED));
+    }
+
+    #[test]
+    fn mark_sensitive_fields() {
+        #[derive(Serialize)]
+        struct Inner {
+            #[serde(with = "crate::settings::sensitive")]
+            key: String,
+        }
+        #[derive(Serialize)]
+        struct Settings {
+            name: String,
+            inner: Inner,
+            #[serde(with = "crate::settings::sensitive_values")]
+            tokens: std::collections::BTreeMap<String, String>,
+        }
+        let settings = Settings {
+            name: "node".into(),
+            inner: Inner { key: "secret".into() },
+            tokens: Default::default(),
+        };
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "inner": { "$ref": "#/definitions/Inner" },
+                "tokens": { "type": "object", "additionalProperties": { "type": "string" } }
+            },
+            "definitions": {
+                "Inner": { "type": "object", "properties": { "key": { "type": "string" } } }
+            }
+        });
+        mark_sensitive(&settings, &mut schema).unwrap();
+        assert_eq!(
+            schema["definitions"]["Inner"]["properties"]["key"][SENSITIVE],
+            json!(true)
+        );
+        assert_eq!(
+            schema["properties"]["tokens"]["additionalProperties"][SENSITIVE],
+            json!(true)
+        );
+        assert_eq!(schema["properties"]["name"].get(SENSITIVE), None);
+        // the values are serialized as usual
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap()["inner"]["key"],
+            json!("secret")
+        );
+
+        let mut schema = json!({ "type": "object", "properties": { "name": { "type": "string" } } });
+        assert_eq!(mark_sensitive(&settings, &mut schema), Err("/inner/key".to_owned()));
+    }
+
+    #[test]
+    fn restore_placeholders() {
+        let current = json!({ "name": "node", "key": "secret", "tokens": ["t1", "t2"] });
+        let mut settings = json!({ "name": "new", "key": REDACTED, "tokens": [REDACTED, "t3"] });
+        restore_redacted(&mut settings, Some(&current)).unwrap();
+        assert_eq!(
+            settings,
+            json!({ "name": "new", "key": "secret", "tokens": ["t1", "t3"] })
+        );
+
+        let mut settings = json!({ "other": REDACTED });
+        assert_eq!(
+            restore_redacted(&mut settings, Some(&current)),
+            Err("/other".to_owned())
+        );
+        assert_eq!(restore_redacted(&mut json!(REDACTED), None), Err(String::new()));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::settings::{database, json_value::JsonValue, Scope, Validator};
+use crate::settings::{
+    database, json_value::JsonValue, redact, redact_at, restore_redacted, Scope, Validator, REDACTED,
+};
 use parking_lot::Mutex;
 use std::sync::Arc;
 use tracing::*;
@@ -23,6 +25,8 @@ pub enum Error {
     NoSettingsAtScope(Scope),
     #[error("Root scope is not allowed.")]
     RootScopeNotAllowed,
+    #[error("Placeholder '<redacted>' at '{0}' does not stand for a current value.")]
+    RedactedPlaceholder(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -47,6 +51,7 @@ pub struct SuccessfulValidation {
 /// On success, the validated object and the schema's scope will be returned.
 fn validate(
     schema_scope: &Scope,
+    schema: &serde_json::Value,
     validator: &Validator,
     scope: &Scope,
     settings: serde_json::Value,
@@ -62,7 +67,10 @@ fn validate(
             .cloned()
             .unwrap() // we successfully did update_at() above so the pointer must be valid
     };
-    debug!("Validating {}", updated_schema_settings_without_defaults);
+    debug!(
+        "Validating {}",
+        redact(&updated_schema_settings_without_defaults, schema)
+    );
     let res = validator
         .validate_with_defaults(Some(&updated_schema_settings_without_defaults), scope)
         .map(|object_with_defaults| SuccessfulValidation {
@@ -102,10 +110,42 @@ fn parent_schema0(tx: &mut database::Transaction, scope: &Scope) -> Result<Optio
 }
 
 /// Creates a validator for the parent scope.
-fn mk_validator(tx: &mut database::Transaction, scope: &Scope) -> Result<(Scope, Validator)> {
+fn mk_validator(tx: &mut database::Transaction, scope: &Scope) -> Result<(Scope, serde_json::Value, Validator)> {
     let (schema_scope, schema) = parent_schema(tx, scope)?;
-    let res = Validator::new(schema)?;
-    Ok((schema_scope, res))
+    let res = Validator::new(schema.clone())?;
+    Ok((schema_scope, schema, res))
+}
+
+/// Redacts `settings` found at `scope` according to the schema responsible for each part of it.
+fn redact_scope(
+    tx: &mut database::Transaction,
+    schema_scopes: &[Scope],
+    scope: &Scope,
+    settings: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let nested = schema_scopes
+        .iter()
+        .any(|s| s.len() > scope.len() && s.starts_with(scope));
+    match settings {
+        serde_json::Value::Object(members) if nested => members
+            .iter()
+            .map(|(name, value)| {
+                let scope = scope.append(&Scope {
+                    tokens: vec![name.clone()],
+                });
+                Ok((name.clone(), redact_scope(tx, schema_scopes, &scope, value)?))
+            })
+            .collect::<Result<serde_json::Map<_, _>>>()
+            .map(serde_json::Value::Object),
+        _ => Ok(match parent_schema0(tx, scope)? {
+            Some((schema_scope, schema)) => redact_at(
+                settings,
+                &schema,
+                &scope.diff(&schema_scope).unwrap_or_else(Scope::root),
+            ),
+            None => serde_json::Value::from(REDACTED),
+        }),
+    }
 }
 
 impl Repository {
@@ -130,6 +170,9 @@ impl Repository {
     ///
     /// If `force` is set then `settings` is returned as is. Otherwise the new settings of the parent
     /// schema are returned.
+    ///
+    /// [`REDACTED`] placeholders keep the current value, so that settings read without permission
+    /// to see secrets can be written back.
     pub fn update_settings(
         &self,
         scope: &Scope,
@@ -142,11 +185,22 @@ impl Repository {
                 .map(parse)
                 .transpose()?
                 .unwrap_or_else(|| serde_json::json!({}));
+            let mut settings = settings;
+            let current = Self::get_schema_settings(tx, Some(&current_settings), scope, false)
+                .ok()
+                .flatten()
+                .and_then(|(schema_scope, current)| match scope.diff(&schema_scope) {
+                    Some(scope_within_schema) => current.pointer(&scope_within_schema.as_json_ptr()).cloned(),
+                    None => Some(current),
+                });
+            restore_redacted(&mut settings, current.as_ref())
+                .map_err(|path| Error::RedactedPlaceholder(format!("{}{}", scope.as_json_ptr(), path)))?;
 
-            let (schema_scope, validator) = mk_validator(tx, scope)?;
+            let (schema_scope, schema, validator) = mk_validator(tx, scope)?;
 
             let validation = validate(
                 &schema_scope,
+                &schema,
                 &validator,
                 scope,
                 settings.clone(),
@@ -160,7 +214,7 @@ impl Repository {
                 }) => {
                     debug!(
                         "Successful validation, new_settings_with_defaults: {}",
-                        new_settings_with_defaults
+                        redact(&new_settings_with_defaults, &schema)
                     );
                     let new_settings = current_settings.update_at(&schema_scope, new_settings_without_defaults)?;
                     tx.set_settings(stringify(&new_settings)?)?;
@@ -178,7 +232,13 @@ impl Repository {
                     let new_settings = current_settings.update_at_force(scope, settings.clone());
                     info!(
                         "Validation failed with error {}. Force is enabled so {} will be set to {}.",
-                        err, scope, new_settings
+                        err,
+                        scope,
+                        redact_at(
+                            &settings,
+                            &schema,
+                            &scope.diff(&schema_scope).unwrap_or_else(Scope::root)
+                        )
                     );
                     tx.set_settings(stringify(&new_settings)?)?;
                     Ok(settings)
@@ -206,7 +266,7 @@ impl Repository {
         scope: &Scope,
         no_defaults: bool,
     ) -> Result<Option<(Scope, serde_json::Value)>> {
-        let (schema_scope, validator) = mk_validator(tx, scope)?;
+        let (schema_scope, _, validator) = mk_validator(tx, scope)?;
         let schema_settings = current_settings.and_then(|c| c.pointer(&schema_scope.as_json_ptr()).cloned());
         let res = if no_defaults {
             schema_settings
@@ -264,6 +324,19 @@ impl Repository {
         })?
     }
 
+    /// Redacts sensitive values from `settings` found at `scope`, according to the installed
+    /// schemas (see [`redact`]). Settings not covered by any schema are redacted entirely.
+    pub fn redact(&self, scope: &Scope, settings: &serde_json::Value) -> Result<serde_json::Value> {
+        self.database.lock().exec(|tx| {
+            let schema_scopes = tx
+                .get_all_schema_scopes()?
+                .into_iter()
+                .map(|s| <Scope as std::convert::TryFrom<String>>::try_from(s).unwrap())
+                .collect::<Vec<Scope>>();
+            redact_scope(tx, &schema_scopes, scope, settings)
+        })?
+    }
+
     /// Deletes a schema for a given `scope`. This will also delete any settings stored for the
     /// same scope in one atomic operation.
     pub fn delete_schema(&self, scope: &Scope) -> Result<()> {
@@ -346,4 +419,89 @@ mod tests {
             json!({ "a": { "b": ["world"] } })
         );
     }
+
+    #[test]
+    fn redact() {
+        let repo = Repository::new_in_memory();
+        let secret = json!({ "type": "string", "x-sensitive": true });
+        repo.set_schema(
+            &"com.actyx".try_into().unwrap(),
+            json!({ "properties": { "name": {}, "key": secret.clone(), "app": {} } }),
+        )
+        .unwrap();
+        repo.set_schema(
+            &"com.actyx/app".try_into().unwrap(),
+            json!({ "properties": { "token": secret, "port": {} } }),
+        )
+        .unwrap();
+        let settings = json!({
+            "com.actyx": { "name": "n", "key": "k", "app": { "token": "t", "port": 1 } },
+            "com.example": { "a": 1 }
+        });
+        assert_eq!(
+            repo.redact(&Scope::root(), &settings).unwrap(),
+            json!({
+                "com.actyx": { "name": "n", "key": REDACTED, "app": { "token": REDACTED, "port": 1 } },
+                "com.example": REDACTED
+            })
+        );
+        assert_eq!(
+            repo.redact(
+                &"com.actyx/app".try_into().unwrap(),
+                &json!({ "token": "t", "port": 1 })
+            )
+            .unwrap(),
+            json!({ "token": REDACTED, "port": 1 })
+        );
+        assert_eq!(
+            repo.redact(&"com.actyx/key".try_into().unwrap(), &json!("k")).unwrap(),
+            json!(REDACTED)
+        );
+        assert_eq!(
+            repo.redact(&"com.actyx/name".try_into().unwrap(), &json!("n")).unwrap(),
+            json!("n")
+        );
+    }
+
+    #[test]
+    fn redacted_round_trip() {
+        let repo = Repository::new_in_memory();
+        let scope: Scope = "com.actyx".try_into().unwrap();
+        repo.set_schema(
+            &scope,
+            json!({ "properties": {
+                "name": { "type": "string" },
+                "key": { "type": "string", "default": "default key", "x-sensitive": true },
+                "other": { "type": "string", "x-sensitive": true }
+            } }),
+        )
+        .unwrap();
+        repo.update_settings(&scope, json!({ "name": "n", "other": "o" }), false)
+            .unwrap();
+        let read = repo.redact(&scope, &repo.get_settings(&scope, false).unwrap()).unwrap();
+        assert_eq!(read, json!({ "name": "n", "key": REDACTED, "other": REDACTED }));
+
+        // placeholders keep the current values, including defaults
+        let mut write = read;
+        write["name"] = json!("m");
+        repo.update_settings(&scope, write, false).unwrap();
+        assert_eq!(
+            repo.get_settings(&scope, false).unwrap(),
+            json!({ "name": "m", "key": "default key", "other": "o" })
+        );
+        repo.update_settings(&"com.actyx/other".try_into().unwrap(), json!(REDACTED), false)
+            .unwrap();
+        assert_eq!(repo.get_settings(&scope, false).unwrap()["other"], json!("o"));
+
+        // without a current value there is nothing to keep
+        repo.set_schema(
+            &scope,
+            json!({ "properties": { "name": { "type": "string" }, "new": { "type": "string" } } }),
+        )
+        .unwrap();
+        assert_eq!(
+            repo.update_settings(&scope, json!({ "name": "n", "new": REDACTED }), false),
+            Err(Error::RedactedPlaceholder("/com.actyx/new".to_owned()))
+        );
+    }
 }
@@ -0,0 +1,47 @@
+//! Stop reading from a tree once enough events have been found, for queries with a `LIMIT`.
+use anyhow::Result;
+use banyan::FilteredChunk;
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+
+/// The number of matching events after which a chunked stream of events ends, see
+/// [`BanyanStore::stream_filtered_chunked_budgeted`](crate::swarm::BanyanStore::stream_filtered_chunked_budgeted).
+///
+/// The budget is checked before requesting the next chunk, so the stream ends with the chunk that
+/// exhausts it, which may contain more events than were left in the budget. Chunks skipped by the
+/// query carry no events and don't count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TakeWhileBudget {
+    events: u64,
+}
+
+impl TakeWhileBudget {
+    pub fn new(events: u64) -> Self {
+        Self { events }
+    }
+
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    pub(crate) fn apply<T, E>(
+        self,
+        chunks: impl Stream<Item = Result<FilteredChunk<T, E>>> + Send + 'static,
+    ) -> BoxStream<'static, Result<FilteredChunk<T, E>>>
+    where
+        T: Send + 'static,
+        E: Send + 'static,
+    {
+        stream::unfold((chunks.boxed(), self.events), |(mut chunks, remaining)| async move {
+            if remaining == 0 {
+                return None;
+            }
+            let chunk = chunks.next().await?;
+            let found = chunk.as_ref().map(|c| c.data.len() as u64).unwrap_or_default();
+            Some((chunk, (chunks, remaining.saturating_sub(found))))
+        })
+        .boxed()
+    }
+}
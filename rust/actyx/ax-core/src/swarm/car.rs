@@ -0,0 +1,250 @@
+//! Export and import of single streams as [CARv1](https://ipld.io/specs/transport/car/carv1/) archives.
+//!
+//! The archive has a single root, a small manifest block naming the stream and the root of its tree
+//! header. It is followed by the header and all blocks of the tree that are present locally, parents
+//! before their children. Leaves that have been pruned are not part of the archive.
+use crate::{
+    swarm::{streams::PublishedTree, AxTreeExt, BanyanStore, Block, Link, StreamAlias},
+    trees::{AxTreeHeader, StoreParams},
+};
+use anyhow::{Context, Result};
+use ax_types::StreamId;
+use banyan::store::ReadOnlyStore;
+use fnv::FnvHashSet;
+use libipld::{cbor::DagCborCodec, codec::Codec, multihash::Code, Cid, DagCbor};
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    io::{self, Read, Write},
+};
+
+/// Upper bound for a single section of the archive: a block plus its CID.
+const MAX_SECTION_SIZE: u64 = <StoreParams as libipld::store::StoreParams>::MAX_BLOCK_SIZE as u64 + 128;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportStats {
+    /// Number of blocks written, including the manifest
+    pub blocks: usize,
+    /// Number of block bytes written, without the framing of the archive
+    pub bytes: u64,
+}
+
+#[derive(Debug, DagCbor)]
+struct CarHeader {
+    roots: Vec<Cid>,
+    version: u64,
+}
+
+#[derive(Debug, DagCbor)]
+struct StreamManifest {
+    stream: StreamId,
+    header: Cid,
+}
+
+fn write_varint(w: &mut impl Write, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Reads an unsigned LEB128 varint, yielding `None` at the end of the input.
+fn read_varint(r: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut n = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        match r.read_exact(&mut byte) {
+            Err(e) if i == 0 && e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            res => res?,
+        }
+        n |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(n));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint too long"))
+}
+
+fn write_section(w: &mut impl Write, cid: &Cid, data: &[u8]) -> io::Result<()> {
+    let cid = cid.to_bytes();
+    write_varint(w, (cid.len() + data.len()) as u64)?;
+    w.write_all(&cid)?;
+    w.write_all(data)
+}
+
+fn read_section(r: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let Some(len) = read_varint(r)? else {
+        return Ok(None);
+    };
+    anyhow::ensure!(len <= MAX_SECTION_SIZE, "CAR section of {} bytes is too large", len);
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf).context("truncated CAR section")?;
+    Ok(Some(buf))
+}
+
+fn read_block(r: &mut impl Read) -> Result<Option<(Cid, Vec<u8>)>> {
+    let Some(section) = read_section(r)? else {
+        return Ok(None);
+    };
+    let mut cursor = io::Cursor::new(section);
+    let cid = Cid::read_bytes(&mut cursor).context("reading CID of CAR section")?;
+    let start = cursor.position() as usize;
+    let mut data = cursor.into_inner();
+    data.drain(..start);
+    Ok(Some((cid, data)))
+}
+
+impl BanyanStore {
+    /// Write the aliased tree of the stream, including its header, as a CARv1 archive.
+    ///
+    /// Only blocks present in the local store are written, so pruned leaves are skipped, but
+    /// any other missing block is an error.
+    pub fn export_stream(&self, stream_id: StreamId, mut writer: impl Write) -> Result<ExportStats> {
+        let root = self
+            .ipfs()
+            .resolve(StreamAlias::from(stream_id))?
+            .with_context(|| format!("no alias for stream {}", stream_id))?;
+        // keep the tree alive even if the stream is pruned or compacted during the export
+        let mut tmp = self.ipfs().create_temp_pin()?;
+        self.ipfs().temp_pin(&mut tmp, &root)?;
+
+        let manifest = StreamManifest {
+            stream: stream_id,
+            header: root,
+        };
+        let manifest = Block::encode(DagCborCodec, Code::Sha2_256, &manifest)?;
+        let header = CarHeader {
+            roots: vec![*manifest.cid()],
+            version: 1,
+        };
+        let header = DagCborCodec.encode(&header)?;
+        write_varint(&mut writer, header.len() as u64)?;
+        writer.write_all(&header)?;
+
+        let mut stats = ExportStats::default();
+        let mut write = |block: &Block| -> Result<()> {
+            write_section(&mut writer, block.cid(), block.data())?;
+            stats.blocks += 1;
+            stats.bytes += block.data().len() as u64;
+            Ok(())
+        };
+        write(&manifest)?;
+        self.walk_blocks(root, write)?;
+        writer.flush()?;
+        tracing::debug!(%stream_id, blocks = stats.blocks, bytes = stats.bytes, "exported stream");
+        Ok(stats)
+    }
+
+    /// Load a stream written by [`export_stream`](Self::export_stream) and make it the current
+    /// state of that stream.
+    ///
+    /// If the stream is already known, the imported tree needs to have a higher lamport than the
+    /// current one, like for trees received from other nodes. Streams of this node cannot be
+    /// imported.
+    pub fn import_stream(&self, mut reader: impl Read) -> Result<StreamId> {
+        let len = read_varint(&mut reader)?.context("empty CAR file")?;
+        anyhow::ensure!(len <= MAX_SECTION_SIZE, "CAR header of {} bytes is too large", len);
+        let mut header = vec![0; len as usize];
+        reader.read_exact(&mut header).context("truncated CAR header")?;
+        let header: CarHeader = DagCborCodec.decode(&header).context("decoding CAR header")?;
+        anyhow::ensure!(header.version == 1, "unsupported CAR version {}", header.version);
+        let [manifest_cid] = header.roots[..] else {
+            anyhow::bail!("expected a single root, found {}", header.roots.len());
+        };
+
+        let (cid, data) = read_block(&mut reader)?.context("missing stream manifest")?;
+        anyhow::ensure!(
+            cid == manifest_cid,
+            "first block {} is not the root {}",
+            cid,
+            manifest_cid
+        );
+        let manifest = Block::new(cid, data)?;
+        let StreamManifest {
+            stream: stream_id,
+            header: root,
+        } = DagCborCodec
+            .decode(manifest.data())
+            .context("decoding stream manifest")?;
+        anyhow::ensure!(!self.is_local(stream_id), "cannot import own stream {}", stream_id);
+
+        let mut tmp = self.ipfs().create_temp_pin()?;
+        self.ipfs().temp_pin(&mut tmp, &root)?;
+        while let Some((cid, data)) = read_block(&mut reader)? {
+            self.ipfs().insert(Block::new(cid, data)?)?;
+        }
+        // make sure that everything but pruned leaves is there
+        self.walk_blocks(root, |_| Ok(()))
+            .context("incomplete stream in CAR file")?;
+
+        let link = Link::try_from(root)?;
+        let header: AxTreeHeader = DagCborCodec
+            .decode(&self.data.forest.store().get(&link)?)
+            .context("decoding tree header")?;
+        let tree = self
+            .data
+            .forest
+            .load_tree(self.data.tree_secrets(stream_id), header.root)?;
+        let offset = tree.offset().context("imported tree is empty")?;
+
+        let stream = self.get_or_create_replicated_stream(stream_id)?;
+        let (validated_header_lamport, validated_header_count) = stream.validated_tree_counters();
+        anyhow::ensure!(
+            header.lamport > validated_header_lamport,
+            "imported lamport {} for stream {} is not newer than {}",
+            header.lamport,
+            stream_id,
+            validated_header_lamport
+        );
+        anyhow::ensure!(
+            tree.count() >= validated_header_count,
+            "imported tree for stream {} has fewer events than the current one",
+            stream_id
+        );
+        self.lock().received_lamport(header.lamport)?;
+        self.data.set_stream_alias(stream_id, Some(&root))?;
+        stream.set_latest(PublishedTree::new(link, header, tree));
+        self.update_highest_seen(stream_id, offset);
+        self.update_present(stream_id, offset);
+        tracing::info!(%stream_id, %offset, "imported stream");
+        Ok(stream_id)
+    }
+
+    /// Visit all locally reachable blocks below `root`, parents before their children.
+    pub(crate) fn walk_blocks(&self, root: Cid, mut f: impl FnMut(&Block) -> Result<()>) -> Result<()> {
+        let mut seen = FnvHashSet::default();
+        let mut queue = VecDeque::from([root]);
+        while let Some(cid) = queue.pop_front() {
+            if !seen.insert(cid) {
+                continue;
+            }
+            let block = self
+                .ipfs()
+                .get(&cid)
+                .with_context(|| format!("missing block {}", cid))?;
+            block.references(&mut queue)?;
+            f(&block)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_roundtrip() {
+        for n in [0, 1, 127, 128, 300, 16_384, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, n).unwrap();
+            assert_eq!(read_varint(&mut &buf[..]).unwrap(), Some(n));
+        }
+        assert_eq!(read_varint(&mut &[][..]).unwrap(), None);
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Up-front checks of a [`SwarmConfig`], so that [`BanyanStore::new`] can report all problems at
+//! once before it starts the ipfs node.
+//!
+//! [`BanyanStore::new`]: crate::swarm::BanyanStore::new
+use crate::swarm::{EphemeralEventsConfig, EventRoutes, SwarmConfig};
+use ax_aql::TagExpr;
+use ipfs_embed::{Multiaddr, PeerId};
+use libp2p::multiaddr::Protocol;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum ConfigError {
+    #[display(fmt = "topic must not be empty")]
+    EmptyTopic,
+    #[display(fmt = "{} must not be zero", _0)]
+    Zero(&'static str),
+    #[display(fmt = "bootstrap address {} must end in /p2p/<peer id>", _0)]
+    MissingPeerId(Multiaddr),
+    #[display(fmt = "invalid peer id in bootstrap address {}", _0)]
+    InvalidPeerId(Multiaddr),
+    /// The swarm transport is set up by ipfs-embed, which only speaks TCP; QUIC addresses would
+    /// only ever fail to connect.
+    #[display(
+        fmt = "QUIC is not supported by the swarm transport, use a TCP address instead of {}",
+        _0
+    )]
+    Quic(Multiaddr),
+    /// Neither a TCP address nor one the transport resolves into TCP addresses, like `/dnsaddr`
+    #[display(fmt = "{} address {} is not a TCP address", kind, addr)]
+    NotTcp { kind: &'static str, addr: Multiaddr },
+    #[display(fmt = "listen addresses {} and {} use the same port", _0, _1)]
+    ConflictingListenAddresses(SocketAddr, SocketAddr),
+    /// Routes only support tags, app ids and `allEvents`
+    #[display(
+        fmt = "event route {} ({}) cannot route by {}, only by tags, appId(…) and allEvents",
+        index,
+        from,
+        atom
+    )]
+    UnsupportedRouteExpression { index: usize, from: TagExpr, atom: TagExpr },
+    #[display(
+        fmt = "event route {} ({}) matches all events like route {} before it, so it never applies",
+        index,
+        from,
+        previous
+    )]
+    DuplicateCatchAll {
+        index: usize,
+        from: TagExpr,
+        previous: usize,
+    },
+    #[display(fmt = "event route {} must not route into the internal stream {}", index, into)]
+    ReservedStream { index: usize, into: String },
+}
+
+impl std::error::Error for ConfigError {}
+
+impl SwarmConfig {
+    /// Check the configuration, returning all problems found.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+        if self.topic.is_empty() {
+            errors.push(ConfigError::EmptyTopic);
+        }
+        if self.block_cache_size == 0 {
+            errors.push(ConfigError::Zero("block cache size"));
+        }
+        if self.block_cache_count == 0 {
+            errors.push(ConfigError::Zero("block cache count"));
+        }
+        if let EphemeralEventsConfig::Enabled { interval, .. } = &self.ephemeral_event_config {
+            if interval.is_zero() {
+                errors.push(ConfigError::Zero("ephemeral events interval"));
+            }
+        }
+
+        for addr in &self.bootstrap_addresses {
+            if let Some(err) = check_transport("bootstrap", addr) {
+                errors.push(err);
+            }
+            match addr.iter().last() {
+                Some(Protocol::P2p(peer_id)) => {
+                    if PeerId::from_multihash(peer_id).is_err() {
+                        errors.push(ConfigError::InvalidPeerId(addr.clone()));
+                    }
+                }
+                _ => errors.push(ConfigError::MissingPeerId(addr.clone())),
+            }
+        }
+        for addr in &self.external_addresses {
+            if let Some(err) = check_transport("external", addr) {
+                errors.push(err);
+            }
+        }
+
+        if let Err(route_errors) = EventRoutes(self.event_routes.clone()).validate() {
+            errors.extend(route_errors);
+        }
+
+        // binding the same port on a specific and the unspecified address of a family fails
+        let mut listen = self.listen_addresses.lock().iter().collect::<Vec<_>>();
+        listen.sort();
+        for (idx, a) in listen.iter().enumerate() {
+            for b in &listen[idx + 1..] {
+                if a.port() != 0
+                    && a.port() == b.port()
+                    && a.is_ipv4() == b.is_ipv4()
+                    && (a.ip().is_unspecified() || b.ip().is_unspecified())
+                {
+                    errors.push(ConfigError::ConflictingListenAddresses(*a, *b));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn check_transport(kind: &'static str, addr: &Multiaddr) -> Option<ConfigError> {
+    if addr.iter().any(|p| matches!(p, Protocol::Quic | Protocol::QuicV1)) {
+        Some(ConfigError::Quic(addr.clone()))
+    } else if !addr
+        .iter()
+        .any(|p| matches!(p, Protocol::Tcp(_) | Protocol::Dnsaddr(_)))
+    {
+        Some(ConfigError::NotTcp {
+            kind,
+            addr: addr.clone(),
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::SocketAddrHelper;
+    use std::time::Duration;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn valid_config() {
+        let peer = PeerId::random();
+        let mut config = SwarmConfig::test("valid");
+        config.bootstrap_addresses = vec![
+            addr(&format!("/ip4/10.0.0.1/tcp/4001/p2p/{}", peer)),
+            addr(&format!("/dns4/example.com/tcp/4001/p2p/{}", peer)),
+            // resolved into TCP addresses via DNS TXT records
+            addr(&format!("/dnsaddr/bootstrap.example.com/p2p/{}", peer)),
+        ];
+        config.external_addresses = vec![addr("/ip4/1.2.3.4/tcp/4001")];
+        *config.listen_addresses.lock() = "0.0.0.0:4001".parse().unwrap();
+        config.listen_addresses.lock().append("[::]:4001".parse().unwrap());
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        let peer = PeerId::random();
+        let mut config = SwarmConfig::test("invalid");
+        config.topic = String::new();
+        config.block_cache_size = 0;
+        config.block_cache_count = 0;
+        config.ephemeral_event_config = EphemeralEventsConfig::Enabled {
+            interval: Duration::ZERO,
+            streams: Default::default(),
+        };
+        config.bootstrap_addresses = vec![
+            addr("/ip4/10.0.0.1/tcp/4001"),
+            addr(&format!("/ip4/10.0.0.1/udp/4001/quic-v1/p2p/{}", peer)),
+            addr(&format!("/ip4/10.0.0.1/tcp/4001/p2p/{}/tcp/1", peer)),
+        ];
+        config.external_addresses = vec![addr("/ip4/1.2.3.4/udp/4001")];
+        let mut listen = SocketAddrHelper::empty();
+        listen.append("0.0.0.0:4001".parse().unwrap());
+        listen.append("127.0.0.1:4001".parse().unwrap());
+        *config.listen_addresses.lock() = listen;
+
+        assert_eq!(
+            config.validate(),
+            Err(vec![
+                ConfigError::EmptyTopic,
+                ConfigError::Zero("block cache size"),
+                ConfigError::Zero("block cache count"),
+                ConfigError::Zero("ephemeral events interval"),
+                ConfigError::MissingPeerId(config.bootstrap_addresses[0].clone()),
+                ConfigError::Quic(config.bootstrap_addresses[1].clone()),
+                ConfigError::MissingPeerId(config.bootstrap_addresses[2].clone()),
+                ConfigError::NotTcp {
+                    kind: "external",
+                    addr: config.external_addresses[0].clone()
+                },
+                ConfigError::ConflictingListenAddresses(
+                    "0.0.0.0:4001".parse().unwrap(),
+                    "127.0.0.1:4001".parse().unwrap()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_event_routes_are_rejected() {
+        use crate::swarm::EventRoute;
+        use std::str::FromStr;
+
+        let route = |from: &str, into: &str| EventRoute::new(TagExpr::from_str(from).unwrap(), into.to_owned());
+        let valid = EventRoutes(vec![
+            route("'a' & appId(com.example)", "a"),
+            route("'b' | 'c'", "bc"),
+            route("allEvents", "default"),
+        ]);
+        assert_eq!(valid.validate(), Ok(()));
+
+        let invalid = EventRoutes(vec![
+            route("'a' & isLocal", "a"),
+            route("allEvents", "everything"),
+            route("'b' | allEvents", "rest"),
+            route("'files'", "files"),
+            route("'discovery'", "discovery"),
+            route("'metrics'", "metrics"),
+        ]);
+        assert_eq!(
+            invalid.validate(),
+            Err(vec![
+                ConfigError::UnsupportedRouteExpression {
+                    index: 0,
+                    from: invalid.0[0].from.clone(),
+                    atom: TagExpr::from_str("isLocal").unwrap(),
+                },
+                ConfigError::DuplicateCatchAll {
+                    index: 2,
+                    from: invalid.0[2].from.clone(),
+                    previous: 1,
+                },
+                ConfigError::ReservedStream {
+                    index: 3,
+                    into: "files".to_owned(),
+                },
+                ConfigError::ReservedStream {
+                    index: 4,
+                    into: "discovery".to_owned(),
+                },
+                ConfigError::ReservedStream {
+                    index: 5,
+                    into: "metrics".to_owned(),
+                },
+            ])
+        );
+
+        // the routes are checked along with the rest of the configuration
+        let mut config = SwarmConfig::test("invalid_routes");
+        config.event_routes = invalid.0;
+        assert_eq!(config.validate().unwrap_err().len(), 5);
+    }
+}
@@ -0,0 +1,505 @@
+//! Connectivity of this node to the swarm, judged by whether it keeps up with the events of others
+//! and whether its own events reach them.
+//!
+//! The [`ConnectivityEngine`] is fed with the [`SwarmOffsets`] of this node, its [`PeerEvent`]s and
+//! the offsets peers announce in their root maps, and reports a [`ConnectivityResponse`] whenever
+//! asked to. [`BanyanStore::connectivity`] wires it up with the store and reports at the cadence of
+//! the [`ConnectivityRequest`].
+use crate::{
+    crypto::node_id_to_peer_id,
+    swarm::{BanyanStore, PeerEvent, RootMap, SwarmOffsets},
+};
+use ax_types::{NodeId, OffsetMap};
+use futures::{
+    channel::mpsc,
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+use ipfs_embed::PeerId;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem::discriminant,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectivityRequest {
+    /// Nodes that must be connected for this node to be fully connected
+    pub special: BTreeSet<NodeId>,
+    /// Cadence of the reports
+    pub report_every_ms: u64,
+    /// Number of reports a change has to persist before it counts: events announced more recently
+    /// are not yet missing, and peers disconnected more recently are still counted as connected
+    pub current_offset_history_delay: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ConnectivityStatus {
+    /// Connected to all special nodes, with no events left to read or send
+    FullyConnected,
+    /// Connected to some peers, but events are missing on either side or special nodes are not
+    /// connected
+    #[serde(rename_all = "camelCase")]
+    PartiallyConnected {
+        /// events known to exist in the swarm that this node does not have yet
+        events_to_read: u64,
+        /// events of this node that are not known to be present on any peer
+        events_to_send: u64,
+        specials_disconnected: Vec<NodeId>,
+    },
+    /// Not connected to any peer
+    #[serde(rename_all = "camelCase")]
+    NotConnected { events_to_read: u64, events_to_send: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectivityResponse {
+    pub status: ConnectivityStatus,
+    /// Time since the status changed to its current kind, disregarding changes in its numbers
+    pub in_current_status_for_ms: u64,
+}
+
+/// An input of the [`ConnectivityEngine`]
+#[derive(Debug, Clone)]
+pub enum ConnectivityInput {
+    Offsets(SwarmOffsets),
+    Peer(PeerEvent),
+    /// Offsets of the streams the peer has, as announced in its root map
+    PeerOffsets(PeerId, OffsetMap),
+}
+
+/// The state of the node as of one report
+#[derive(Debug, Clone, Default)]
+struct Snapshot {
+    /// replication target of the streams of other nodes
+    others_target: OffsetMap,
+    /// present of the own streams
+    own_present: OffsetMap,
+    connected: BTreeSet<PeerId>,
+}
+
+pub struct ConnectivityEngine {
+    node_id: NodeId,
+    special: BTreeMap<PeerId, NodeId>,
+    delay: usize,
+    offsets: SwarmOffsets,
+    connected: BTreeSet<PeerId>,
+    peer_offsets: BTreeMap<PeerId, OffsetMap>,
+    /// snapshots of the last `delay + 1` reports, the oldest first
+    history: VecDeque<Snapshot>,
+    status: Option<(ConnectivityStatus, Instant)>,
+}
+
+impl ConnectivityEngine {
+    pub fn new(node_id: NodeId, request: &ConnectivityRequest) -> Self {
+        Self {
+            node_id,
+            special: request
+                .special
+                .iter()
+                .map(|node_id| (node_id_to_peer_id(*node_id), *node_id))
+                .collect(),
+            delay: request.current_offset_history_delay.into(),
+            offsets: SwarmOffsets::default(),
+            connected: BTreeSet::new(),
+            peer_offsets: BTreeMap::new(),
+            history: VecDeque::new(),
+            status: None,
+        }
+    }
+
+    pub fn handle(&mut self, input: ConnectivityInput) {
+        match input {
+            ConnectivityInput::Offsets(offsets) => self.offsets = offsets,
+            ConnectivityInput::Peer(PeerEvent::Connected { peer, .. }) => {
+                self.connected.insert(peer);
+            }
+            ConnectivityInput::Peer(PeerEvent::Disconnected { peer, .. }) => {
+                self.connected.remove(&peer);
+            }
+            ConnectivityInput::Peer(_) => {}
+            ConnectivityInput::PeerOffsets(peer, offsets) => {
+                self.peer_offsets.insert(peer, offsets);
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot {
+            connected: self.connected.clone(),
+            ..Default::default()
+        };
+        for (stream, offset) in self.offsets.replication_target().stream_iter() {
+            if stream.node_id() != self.node_id {
+                snapshot.others_target.update(stream, offset);
+            }
+        }
+        for (stream, offset) in self.offsets.present().stream_iter() {
+            if stream.node_id() == self.node_id {
+                snapshot.own_present.update(stream, offset);
+            }
+        }
+        snapshot
+    }
+
+    /// The connectivity at `now`, which must not lie before the time of the previous report.
+    ///
+    /// Each report moves the history on, so the outcome depends on how many reports preceded it.
+    pub fn report(&mut self, now: Instant) -> ConnectivityResponse {
+        self.history.push_back(self.snapshot());
+        while self.history.len() > self.delay + 1 {
+            self.history.pop_front();
+        }
+        let delayed = &self.history[0];
+        let connected = self
+            .history
+            .iter()
+            .flat_map(|snapshot| &snapshot.connected)
+            .collect::<BTreeSet<_>>();
+
+        let events_to_read = &delayed.others_target - &self.offsets.present();
+        let sent = self
+            .peer_offsets
+            .values()
+            .fold(OffsetMap::empty(), |sent, offsets| sent.union(offsets));
+        let events_to_send = &delayed.own_present - &sent;
+        let specials_disconnected = self
+            .special
+            .iter()
+            .filter(|(peer, _)| !connected.contains(peer))
+            .map(|(_, node_id)| *node_id)
+            .collect::<Vec<_>>();
+
+        let status = if connected.is_empty() {
+            ConnectivityStatus::NotConnected {
+                events_to_read,
+                events_to_send,
+            }
+        } else if events_to_read == 0 && events_to_send == 0 && specials_disconnected.is_empty() {
+            ConnectivityStatus::FullyConnected
+        } else {
+            ConnectivityStatus::PartiallyConnected {
+                events_to_read,
+                events_to_send,
+                specials_disconnected,
+            }
+        };
+        let since = match &self.status {
+            Some((previous, since)) if discriminant(previous) == discriminant(&status) => *since,
+            _ => now,
+        };
+        self.status = Some((status.clone(), since));
+        ConnectivityResponse {
+            status,
+            in_current_status_for_ms: now.saturating_duration_since(since).as_millis() as u64,
+        }
+    }
+
+    /// Feeds the engine with `inputs` and reports every `interval`, starting one `interval` from
+    /// now. Ends when the inputs end.
+    pub fn run(
+        self,
+        inputs: impl Stream<Item = ConnectivityInput> + Send + Unpin + 'static,
+        interval: Duration,
+    ) -> impl Stream<Item = ConnectivityResponse> + Send + 'static {
+        let ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        stream::unfold(
+            (self, inputs, ticks),
+            |(mut engine, mut inputs, mut ticks)| async move {
+                loop {
+                    tokio::select! {
+                        tick = ticks.tick() => {
+                            let response = engine.report(tick.into_std());
+                            return Some((response, (engine, inputs, ticks)));
+                        }
+                        input = inputs.next() => engine.handle(input?),
+                    }
+                }
+            },
+        )
+    }
+}
+
+/// Subscribers of the offsets that peers announce in their root maps
+#[derive(Default)]
+pub(crate) struct PeerRootOffsets(Mutex<Vec<mpsc::UnboundedSender<(PeerId, OffsetMap)>>>);
+
+impl PeerRootOffsets {
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<(PeerId, OffsetMap)> {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.lock().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, peer: PeerId, root_map: &RootMap) {
+        let mut listeners = self.0.lock();
+        if listeners.is_empty() {
+            return;
+        }
+        let offsets = root_map
+            .entries
+            .keys()
+            .zip(&root_map.offsets)
+            .map(|(stream, (offset, _))| (*stream, *offset))
+            .collect::<OffsetMap>();
+        listeners.retain(|tx| tx.unbounded_send((peer, offsets.clone())).is_ok());
+    }
+}
+
+impl BanyanStore {
+    /// Connectivity of this node to the swarm, reported every `report_every_ms` of the request.
+    ///
+    /// Events of peers that are only partially replicated, see [`ReplicationConfig`], count as
+    /// events to read. Only the offsets from root maps received from now on count towards the
+    /// events sent.
+    ///
+    /// [`ReplicationConfig`]: crate::swarm::ReplicationConfig
+    pub fn connectivity(&self, request: &ConnectivityRequest) -> impl Stream<Item = ConnectivityResponse> + Send {
+        let inputs: Vec<BoxStream<'static, ConnectivityInput>> = vec![
+            self.offsets_stream().map(ConnectivityInput::Offsets).boxed(),
+            self.peer_events().map(ConnectivityInput::Peer).boxed(),
+            self.data
+                .peer_root_offsets
+                .subscribe()
+                .map(|(peer, offsets)| ConnectivityInput::PeerOffsets(peer, offsets))
+                .boxed(),
+        ];
+        ConnectivityEngine::new(self.node_id(), request).run(
+            stream::select_all(inputs),
+            Duration::from_millis(request.report_every_ms.max(1)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{PrivateKey, PublicKey},
+        swarm::VersionedOffsets,
+    };
+    use ax_types::Offset;
+    use ipfs_embed::Multiaddr;
+
+    struct Fixture {
+        engine: ConnectivityEngine,
+        own: NodeId,
+        other: NodeId,
+        special: NodeId,
+        start: Instant,
+        reports: u64,
+    }
+
+    impl Fixture {
+        fn new(delay: u8) -> Self {
+            let node = || NodeId::from(PublicKey::from(PrivateKey::generate()));
+            let (own, other, special) = (node(), node(), node());
+            let request = ConnectivityRequest {
+                special: maplit::btreeset! { special },
+                report_every_ms: 1000,
+                current_offset_history_delay: delay,
+            };
+            Self {
+                engine: ConnectivityEngine::new(own, &request),
+                own,
+                other,
+                special,
+                start: Instant::now(),
+                reports: 0,
+            }
+        }
+
+        fn offsets(&mut self, own: u32, other_present: u32, other_target: u32) {
+            let own_stream = self.own.stream(0.into());
+            let other_stream = self.other.stream(0.into());
+            let present: OffsetMap = [
+                (own_stream, Offset::from(own)),
+                (other_stream, Offset::from(other_present)),
+            ]
+            .into_iter()
+            .collect();
+            let mut target = present.clone();
+            target.update(other_stream, Offset::from(other_target));
+            self.engine.handle(ConnectivityInput::Offsets(SwarmOffsets {
+                present: VersionedOffsets::from(&present),
+                replication_target: VersionedOffsets::from(&target),
+            }));
+        }
+
+        fn connect(&mut self, node_id: NodeId) {
+            self.engine.handle(ConnectivityInput::Peer(PeerEvent::Connected {
+                peer: node_id_to_peer_id(node_id),
+                addr: Multiaddr::empty(),
+            }));
+        }
+
+        fn disconnect(&mut self, node_id: NodeId) {
+            self.engine.handle(ConnectivityInput::Peer(PeerEvent::Disconnected {
+                peer: node_id_to_peer_id(node_id),
+                reason: crate::swarm::DisconnectReason::Closed,
+            }));
+        }
+
+        fn peer_has_own(&mut self, node_id: NodeId, offset: u32) {
+            let offsets = [(self.own.stream(0.into()), Offset::from(offset))]
+                .into_iter()
+                .collect();
+            self.engine
+                .handle(ConnectivityInput::PeerOffsets(node_id_to_peer_id(node_id), offsets));
+        }
+
+        /// Reports one second after the previous report
+        fn report(&mut self) -> ConnectivityResponse {
+            self.reports += 1;
+            self.engine.report(self.start + Duration::from_secs(self.reports))
+        }
+    }
+
+    #[test]
+    fn not_connected() {
+        let mut x = Fixture::new(0);
+        x.offsets(4, 2, 9);
+        assert_eq!(
+            x.report(),
+            ConnectivityResponse {
+                status: ConnectivityStatus::NotConnected {
+                    events_to_read: 7,
+                    events_to_send: 5,
+                },
+                in_current_status_for_ms: 0,
+            }
+        );
+        assert_eq!(x.report().in_current_status_for_ms, 1000);
+    }
+
+    #[test]
+    fn partially_and_fully_connected() {
+        let mut x = Fixture::new(0);
+        x.offsets(4, 9, 9);
+        x.connect(x.other);
+        x.peer_has_own(x.other, 4);
+        assert_eq!(
+            x.report().status,
+            ConnectivityStatus::PartiallyConnected {
+                events_to_read: 0,
+                events_to_send: 0,
+                specials_disconnected: vec![x.special],
+            }
+        );
+
+        x.connect(x.special);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+
+        // the numbers changing does not reset the time in the status
+        x.offsets(6, 9, 10);
+        assert_eq!(
+            x.report(),
+            ConnectivityResponse {
+                status: ConnectivityStatus::PartiallyConnected {
+                    events_to_read: 1,
+                    events_to_send: 2,
+                    specials_disconnected: vec![],
+                },
+                in_current_status_for_ms: 0,
+            }
+        );
+        x.offsets(6, 10, 10);
+        assert_eq!(
+            x.report(),
+            ConnectivityResponse {
+                status: ConnectivityStatus::PartiallyConnected {
+                    events_to_read: 0,
+                    events_to_send: 2,
+                    specials_disconnected: vec![],
+                },
+                in_current_status_for_ms: 1000,
+            }
+        );
+        x.peer_has_own(x.special, 6);
+        assert_eq!(
+            x.report(),
+            ConnectivityResponse {
+                status: ConnectivityStatus::FullyConnected,
+                in_current_status_for_ms: 0,
+            }
+        );
+        assert_eq!(x.report().in_current_status_for_ms, 1000);
+
+        x.disconnect(x.other);
+        x.disconnect(x.special);
+        assert_eq!(
+            x.report().status,
+            ConnectivityStatus::NotConnected {
+                events_to_read: 0,
+                events_to_send: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn changes_count_after_the_history_delay() {
+        let mut x = Fixture::new(2);
+        x.offsets(4, 9, 9);
+        x.connect(x.other);
+        x.connect(x.special);
+        x.peer_has_own(x.other, 4);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+
+        // new events in the swarm and of our own are not missing for two reports
+        x.offsets(5, 9, 12);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+        x.offsets(5, 11, 12);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+        assert_eq!(
+            x.report(),
+            ConnectivityResponse {
+                status: ConnectivityStatus::PartiallyConnected {
+                    events_to_read: 1,
+                    events_to_send: 1,
+                    specials_disconnected: vec![],
+                },
+                in_current_status_for_ms: 0,
+            }
+        );
+        x.offsets(5, 12, 12);
+        x.peer_has_own(x.special, 5);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+
+        // a short disconnection goes unnoticed, a longer one does not
+        x.disconnect(x.special);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+        x.connect(x.special);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+        x.disconnect(x.other);
+        x.disconnect(x.special);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+        assert_eq!(x.report().status, ConnectivityStatus::FullyConnected);
+        assert_eq!(
+            x.report(),
+            ConnectivityResponse {
+                status: ConnectivityStatus::NotConnected {
+                    events_to_read: 0,
+                    events_to_send: 0,
+                },
+                in_current_status_for_ms: 0,
+            }
+        );
+        assert_eq!(x.report().in_current_status_for_ms, 1000);
+
+        // reconnecting is noticed right away
+        x.connect(x.other);
+        assert_eq!(
+            x.report().status,
+            ConnectivityStatus::PartiallyConnected {
+                events_to_read: 0,
+                events_to_send: 0,
+                specials_disconnected: vec![x.special],
+            }
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Append-only log of the decisions taken while replicating streams, to find out after the fact why
+//! two nodes disagree about the head of a stream.
+//!
+//! Every received root update, every start and outcome of syncing a stream, and every new latest
+//! tree of a replicated stream is recorded with a sequence number and a timestamp. The records are
+//! written as consecutive CBOR items to [`SwarmConfig::decision_log_path`] and can be read back with
+//! [`parse_decision_log`].
+//!
+//! Recording never blocks ingestion: records are handed to a writer thread through a bounded
+//! channel, records that do not fit are dropped and counted. Dropped records show up as gaps in the
+//! sequence numbers.
+//!
+//! [`SwarmConfig::decision_log_path`]: super::SwarmConfig::decision_log_path
+use crate::swarm::BanyanStore;
+use anyhow::{Context, Result};
+use ax_types::{LamportTimestamp, Offset, StreamId, Timestamp};
+use crossbeam::channel::{bounded, Receiver, Sender, TrySendError};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Records waiting to be written, beyond this they are dropped
+const CHANNEL_SIZE: usize = 4096;
+
+/// Number of records kept in memory for [`BanyanStore::decision_log_tail`]
+const TAIL_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionRecord {
+    /// consecutive since the store was started
+    pub seq: u64,
+    pub timestamp: Timestamp,
+    pub decision: Decision,
+}
+
+/// Roots are given in their string form, peers by their id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Decision {
+    /// A root was announced for a replicated stream
+    #[serde(rename_all = "camelCase")]
+    RootUpdate {
+        stream: StreamId,
+        root: String,
+        path: String,
+        sender: String,
+        lamport: LamportTimestamp,
+    },
+    /// Syncing the stream to the root has started
+    #[serde(rename_all = "camelCase")]
+    SyncStart { stream: StreamId, root: String },
+    /// Syncing the stream to the root has ended, either with the outcome or with an error
+    #[serde(rename_all = "camelCase")]
+    SyncOutcome {
+        stream: StreamId,
+        root: String,
+        outcome: String,
+    },
+    /// The synced tree has become the latest tree of the stream
+    #[serde(rename_all = "camelCase")]
+    SetLatest {
+        stream: StreamId,
+        root: String,
+        offset: Offset,
+        lamport: LamportTimestamp,
+    },
+}
+
+pub(crate) struct DecisionLog {
+    /// the most recent records, also guards the order of sequence numbers
+    tail: Mutex<VecDeque<DecisionRecord>>,
+    seq: AtomicU64,
+    dropped: AtomicU64,
+    tx: Sender<DecisionRecord>,
+}
+
+impl DecisionLog {
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("unable to open decision log {}", path.display()))?;
+        let (tx, rx) = bounded(CHANNEL_SIZE);
+        std::thread::Builder::new()
+            .name("decision-log".to_owned())
+            .spawn(move || write_records(file, rx))?;
+        Ok(Self {
+            tail: Mutex::new(VecDeque::with_capacity(TAIL_SIZE)),
+            seq: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            tx,
+        })
+    }
+
+    pub fn record(&self, decision: Decision) {
+        let mut tail = self.tail.lock();
+        let record = DecisionRecord {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed),
+            timestamp: Timestamp::now(),
+            decision,
+        };
+        if tail.len() >= TAIL_SIZE {
+            tail.pop_front();
+        }
+        tail.push_back(record.clone());
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(record) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Runs until the [`DecisionLog`] is dropped.
+fn write_records(file: File, rx: Receiver<DecisionRecord>) {
+    let mut writer = BufWriter::new(file);
+    let mut failed = false;
+    for record in rx.iter() {
+        let mut result = serde_cbor::to_writer(&mut writer, &record).map_err(anyhow::Error::from);
+        if result.is_ok() && rx.is_empty() {
+            result = writer.flush().map_err(anyhow::Error::from);
+        }
+        match result {
+            Err(err) if !failed => {
+                tracing::warn!("unable to write decision log: {:#}", err);
+                failed = true;
+            }
+            Err(_) => {}
+            Ok(()) => failed = false,
+        }
+    }
+}
+
+/// Reads the records of a decision log file.
+///
+/// A record cut short at the end of the file, e.g. because the node was killed while writing it,
+/// is ignored.
+pub fn parse_decision_log(reader: impl Read) -> Result<Vec<DecisionRecord>> {
+    let mut records = vec![];
+    for record in serde_cbor::Deserializer::from_reader(reader).into_iter::<DecisionRecord>() {
+        match record {
+            Ok(record) => records.push(record),
+            Err(err) if err.is_eof() => break,
+            Err(err) => return Err(err).context("invalid decision log"),
+        }
+    }
+    Ok(records)
+}
+
+impl BanyanStore {
+    /// The last `n` records of the decision log, oldest first.
+    ///
+    /// Empty unless [`SwarmConfig::decision_log_path`] is set. Only the most recent records are kept
+    /// in memory, use [`parse_decision_log`] on the file for the full log.
+    ///
+    /// [`SwarmConfig::decision_log_path`]: super::SwarmConfig::decision_log_path
+    pub fn decision_log_tail(&self, n: usize) -> Vec<DecisionRecord> {
+        let Some(log) = &self.data.decision_log else {
+            return vec![];
+        };
+        let tail = log.tail.lock();
+        tail.iter().skip(tail.len().saturating_sub(n)).cloned().collect()
+    }
+
+    /// Number of records that could not be written to the decision log because the writer fell
+    /// behind.
+    pub fn decision_log_dropped(&self) -> u64 {
+        self.data
+            .decision_log
+            .as_ref()
+            .map_or(0, |log| log.dropped.load(Ordering::Relaxed))
+    }
+
+    /// Records the decision if the decision log is enabled.
+    pub(crate) fn record_decision(&self, decision: impl FnOnce() -> Decision) {
+        if let Some(log) = &self.data.decision_log {
+            log.record(decision());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swarm::{RootPath, RootSource, SwarmConfig};
+    use acto::ActoRef;
+    use ax_types::{app_id, tags, Payload, StreamNr};
+    use ipfs_embed::PeerId;
+    use libipld::Cid;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn records_sync_decisions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("decisions");
+        let store = BanyanStore::new(
+            SwarmConfig {
+                decision_log_path: Some(path.clone()),
+                ..SwarmConfig::test("decision_log")
+            },
+            ActoRef::blackhole(),
+        )
+        .await?;
+        let other = BanyanStore::test("decision_source").await?;
+        let stream_id = other.node_id().stream(StreamNr::from(1));
+        let peer = PeerId::random();
+
+        let mut offsets = vec![];
+        for _ in 0..3 {
+            let meta = other
+                .append0(
+                    stream_id.stream_nr(),
+                    app_id!("test"),
+                    Timestamp::now(),
+                    vec![(tags!("a"), Payload::null()); 10],
+                )
+                .await?;
+            other.walk_blocks(Cid::from(meta.root()), |block| store.ipfs().insert(block.clone()))?;
+            store.update_root(
+                stream_id,
+                meta.root(),
+                RootSource::new(peer, RootPath::FastPath),
+                meta.min_lamport(),
+            );
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while store.lock().published_tree(stream_id).map(|tree| tree.offset()) != Some(meta.last_offset()) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await?;
+            offsets.push(meta.last_offset());
+        }
+
+        let tail = store.decision_log_tail(usize::MAX);
+        assert!(tail.windows(2).all(|w| w[0].seq + 1 == w[1].seq), "{:?}", tail);
+        let latest = tail
+            .iter()
+            .filter_map(|record| match &record.decision {
+                Decision::SetLatest { stream, offset, .. } if *stream == stream_id => Some(*offset),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(latest, offsets);
+        assert_eq!(store.decision_log_tail(2), tail[tail.len() - 2..]);
+
+        // the file catches up with the records kept in memory
+        let mut parsed = vec![];
+        for _ in 0..100 {
+            parsed = parse_decision_log(File::open(&path)?)?;
+            if parsed.len() >= tail.len() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(parsed[..tail.len()], tail[..]);
+        assert_eq!(store.decision_log_dropped(), 0);
+        Ok(())
+    }
+}
@@ -37,8 +37,12 @@
 //! NATs. When configuring a bootstrap node you are telling the node how to reach another peer,
 //! while when configuring an external address you are telling other peers how to reach you, given
 //! you have a bootstrap node in common.
+//!
+//! Addresses learned via the discovery protocol are also kept in the index store together with the
+//! time they were last seen, so that a restarted node can reach its previous peers even if none of
+//! its bootstrap nodes is up.
 use crate::{
-    swarm::{internal_app_id, BanyanStore},
+    swarm::{internal_app_id, sqlite_index_store::PeerAddressUpdate, BanyanStore, Ipfs, PeerEvent},
     trees::{
         query::{LamportQuery, TagExprQuery, TimeQuery},
         tags::{ScopedTag, ScopedTagSet, TagScope},
@@ -48,7 +52,7 @@ use crate::{
 use anyhow::Result;
 use ax_types::{tag, tags, Payload, Timestamp};
 use fnv::{FnvHashMap, FnvHashSet};
-use futures::stream::{Stream, StreamExt};
+use futures::stream::StreamExt;
 use ipfs_embed::multiaddr;
 use libipld::{
     cbor::DagCborCodec,
@@ -62,6 +66,9 @@ use std::{
 };
 use tokio::time::timeout;
 
+/// Maximum number of discovery events whose addresses are persisted in one transaction
+const PEER_ADDRESS_BATCH: usize = 256;
+
 #[derive(DagCbor, Debug)]
 #[allow(clippy::enum_variant_names)]
 enum Event {
@@ -91,6 +98,17 @@ impl Event {
             Self::ExpiredObservedAddr(peer, _) => &peer.0,
         }
     }
+
+    fn address_update(&self, seen: Timestamp) -> PeerAddressUpdate {
+        match self {
+            Event::NewListenAddr(peer, addr)
+            | Event::NewExternalAddr(peer, addr)
+            | Event::NewObservedAddr(peer, addr) => PeerAddressUpdate::Seen(peer.0, addr.0.clone(), seen),
+            Event::ExpiredListenAddr(peer, addr)
+            | Event::ExpiredExternalAddr(peer, addr)
+            | Event::ExpiredObservedAddr(peer, addr) => PeerAddressUpdate::Expired(peer.0, addr.0.clone()),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
@@ -161,8 +179,8 @@ where
     }
 }
 
-fn decode_event(e: Result<(u64, AxKey, Payload)>, my_peer_id: ipfs_embed::PeerId) -> Option<Event> {
-    let (_off, _key, event) = match e {
+fn decode_event(e: Result<(u64, AxKey, Payload)>, my_peer_id: ipfs_embed::PeerId) -> Option<(Timestamp, Event)> {
+    let (_off, key, event) = match e {
         Ok(event) => event,
         Err(err) => {
             tracing::warn!("store error: {}", err);
@@ -179,10 +197,41 @@ fn decode_event(e: Result<(u64, AxKey, Payload)>, my_peer_id: ipfs_embed::PeerId
     if *event.peer_id() == my_peer_id {
         None
     } else {
-        Some(event)
+        Some((key.time(), event))
+    }
+}
+
+/// Persist what was learned about peer addresses, failures are only logged.
+fn remember_addresses(store: &BanyanStore, updates: &[PeerAddressUpdate]) {
+    if updates.is_empty() {
+        return;
+    }
+    if let Err(err) = store.lock().index_store.update_peer_addresses(updates) {
+        tracing::warn!("error persisting {} peer address updates: {}", updates.len(), err);
     }
 }
 
+/// Add the persisted addresses of previous peers to the address book, dropping those last seen
+/// longer than `max_age` ago.
+pub fn add_known_peers(store: &BanyanStore, max_age: Duration) -> Result<()> {
+    let cutoff = Timestamp::now() - max_age;
+    let addresses = {
+        let mut state = store.lock();
+        state.index_store.forget_peer_addresses(cutoff)?;
+        state.index_store.get_peer_addresses()?
+    };
+    let mut ipfs = store.ipfs().clone();
+    let peer_id = ipfs.local_peer_id();
+    let addresses = addresses
+        .into_iter()
+        .filter(|(peer, ..)| *peer != peer_id)
+        .map(|(peer, addr, _)| (peer, addr))
+        .collect::<Vec<_>>();
+    tracing::info!("adding {} known peer addresses", addresses.len());
+    ipfs.add_addresses(addresses);
+    Ok(())
+}
+
 pub async fn discovery_ingest(store: BanyanStore) {
     let mut tags: ScopedTagSet = tags!("discovery").into();
     tags.insert(ScopedTag::new(TagScope::Internal, tag!("app_id:com.actyx")));
@@ -197,12 +246,14 @@ pub async fn discovery_ingest(store: BanyanStore) {
 
     // first catch up and build a list, we won’t want to spam the address book
     let mut addresses = FnvHashMap::<PeerId, FnvHashSet<Multiaddr>>::default();
+    let mut updates = vec![];
     while let Ok(Some(event)) = timeout(Duration::from_secs(3), stream.next()).await {
-        let event = match decode_event(event, peer_id) {
+        let (seen, event) = match decode_event(event, peer_id) {
             Some(e) => e,
             None => continue,
         };
         tracing::debug!("discovery_ingest (catch-up) {:?}", event);
+        updates.push(event.address_update(seen));
         match event {
             Event::NewListenAddr(peer, addr)
             | Event::NewExternalAddr(peer, addr)
@@ -223,23 +274,30 @@ pub async fn discovery_ingest(store: BanyanStore) {
         }
     }
     ipfs.add_addresses(peer_addresses);
+    remember_addresses(&store, &updates);
 
-    // then switch to live mode
+    // then switch to live mode, persisting the events that arrived together in one go
     tracing::debug!("discovery_ingest switching to live mode");
-    while let Some(event) = stream.next().await {
-        let event = match decode_event(event, peer_id) {
-            Some(e) => e,
-            None => continue,
-        };
-        tracing::debug!("discovery_ingest {:?}", event);
-        match event {
-            Event::NewListenAddr(peer, addr)
-            | Event::NewExternalAddr(peer, addr)
-            | Event::NewObservedAddr(peer, addr) => ipfs.add_address(peer.into(), addr.into()),
-            Event::ExpiredListenAddr(peer, addr)
-            | Event::ExpiredExternalAddr(peer, addr)
-            | Event::ExpiredObservedAddr(peer, addr) => ipfs.remove_address(peer.into(), addr.into()),
+    let mut stream = stream.ready_chunks(PEER_ADDRESS_BATCH);
+    while let Some(events) = stream.next().await {
+        updates.clear();
+        for event in events {
+            let (seen, event) = match decode_event(event, peer_id) {
+                Some(e) => e,
+                None => continue,
+            };
+            tracing::debug!("discovery_ingest {:?}", event);
+            updates.push(event.address_update(seen));
+            match event {
+                Event::NewListenAddr(peer, addr)
+                | Event::NewExternalAddr(peer, addr)
+                | Event::NewObservedAddr(peer, addr) => ipfs.add_address(peer.into(), addr.into()),
+                Event::ExpiredListenAddr(peer, addr)
+                | Event::ExpiredExternalAddr(peer, addr)
+                | Event::ExpiredObservedAddr(peer, addr) => ipfs.remove_address(peer.into(), addr.into()),
+            }
         }
+        remember_addresses(&store, &updates);
     }
 }
 
@@ -265,6 +323,89 @@ impl Drop for Dialer {
     }
 }
 
+/// Budget for the connections of this node.
+///
+/// Discovered peers are not dialed once the budget is used up, and a peer connecting to us is
+/// disconnected again if its connection exceeds the budget. Only the first connection of a peer
+/// that connects to us is checked. Bootstrap peers are always admitted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    pub max_connections: Option<u32>,
+    pub max_connections_per_peer: Option<u32>,
+}
+
+impl ConnectionLimits {
+    /// Whether `peer` may be dialed given the currently established connections, one entry per
+    /// connection.
+    fn may_dial(&self, connections: &[ipfs_embed::PeerId], peer: &ipfs_embed::PeerId) -> bool {
+        let below = |limit: Option<u32>, count: usize| limit.map(|l| count < l as usize).unwrap_or(true);
+        let to_peer = connections.iter().filter(|p| *p == peer).count();
+        below(self.max_connections_per_peer, to_peer) && below(self.max_connections, connections.len())
+    }
+
+    /// Whether the established connections, one entry per connection and including the newly
+    /// established one from `peer`, stay within the limits.
+    fn admits(&self, connections: &[ipfs_embed::PeerId], peer: &ipfs_embed::PeerId) -> bool {
+        let within = |limit: Option<u32>, count: usize| limit.map(|l| count <= l as usize).unwrap_or(true);
+        let to_peer = connections.iter().filter(|p| *p == peer).count();
+        within(self.max_connections_per_peer, to_peer) && within(self.max_connections, connections.len())
+    }
+}
+
+/// Dials peers as long as the [`ConnectionLimits`] permit.
+struct DialBudget {
+    limits: ConnectionLimits,
+    bootstrap: FnvHashSet<ipfs_embed::PeerId>,
+    /// dials that have neither connected nor failed yet
+    pending: FnvHashSet<ipfs_embed::PeerId>,
+}
+
+impl DialBudget {
+    fn permits(&self, ipfs: &Ipfs, peer: &ipfs_embed::PeerId) -> bool {
+        if self.bootstrap.contains(peer) {
+            return true;
+        }
+        let mut connections = ipfs.connections().into_iter().map(|c| c.0).collect::<Vec<_>>();
+        connections.extend(self.pending.iter().copied());
+        self.limits.may_dial(&connections, peer)
+    }
+
+    fn dial(&mut self, ipfs: &mut Ipfs, peer: ipfs_embed::PeerId) {
+        if self.permits(ipfs, &peer) {
+            self.pending.insert(peer);
+            ipfs.dial(peer);
+        } else {
+            tracing::debug!(id = display(&peer), "connection budget exhausted, not dialing");
+        }
+    }
+
+    fn done(&mut self, peer: &ipfs_embed::PeerId) {
+        self.pending.remove(peer);
+    }
+
+    /// Checks a new connection to `peer`, closing it if it was not dialed by us and exceeds the
+    /// budget. Returns whether the connection is kept.
+    fn admit(&mut self, ipfs: &mut Ipfs, peer: &ipfs_embed::PeerId) -> bool {
+        let dialed = self.pending.remove(peer);
+        if dialed || self.bootstrap.contains(peer) {
+            return true;
+        }
+        let mut connections = ipfs.connections().into_iter().map(|c| c.0).collect::<Vec<_>>();
+        connections.extend(self.pending.iter().copied());
+        if self.limits.admits(&connections, peer) {
+            return true;
+        }
+        tracing::debug!(
+            id = display(peer),
+            "connection budget exhausted, closing incoming connection"
+        );
+        // banning closes the connections to the peer, it may connect again later
+        ipfs.ban(*peer);
+        ipfs.unban(*peer);
+        false
+    }
+}
+
 fn is_loopback(addr: &ipfs_embed::Multiaddr) -> bool {
     match addr.iter().next() {
         Some(multiaddr::Protocol::Ip4(a)) => a.is_loopback(),
@@ -275,16 +416,22 @@ fn is_loopback(addr: &ipfs_embed::Multiaddr) -> bool {
 
 pub fn discovery_publish(
     store: BanyanStore,
-    mut stream: impl Stream<Item = ipfs_embed::Event> + Unpin,
     external: FnvHashSet<ipfs_embed::Multiaddr>,
     enable_discovery: bool,
     to_warn: Vec<ipfs_embed::PeerId>,
+    limits: ConnectionLimits,
 ) -> Result<impl Future<Output = ()>> {
+    let mut stream = store.peer_events();
     let mut buffer = vec![];
     let tags = tags!("discovery");
     let mut ipfs = store.ipfs().clone();
     let peer_id: PeerId = ipfs.local_peer_id().into();
     let mut dialers = FnvHashMap::<_, Dialer>::default();
+    let mut budget = DialBudget {
+        limits,
+        bootstrap: to_warn.iter().copied().collect(),
+        pending: Default::default(),
+    };
     let mut to_warn = to_warn
         .into_iter()
         .map(|id| (id, true))
@@ -293,39 +440,39 @@ pub fn discovery_publish(
         while let Some(event) = stream.next().await {
             tracing::trace!("discovery_publish {:?}", event);
             let event = match event {
-                ipfs_embed::Event::NewListenAddr(_, addr) => {
+                PeerEvent::NewListenAddr { addr } => {
                     if !is_loopback(&addr) {
                         Event::NewListenAddr(peer_id, addr.into())
                     } else {
                         continue;
                     }
                 }
-                ipfs_embed::Event::ExpiredListenAddr(_, addr) => {
+                PeerEvent::ExpiredListenAddr { addr } => {
                     if !is_loopback(&addr) {
                         Event::ExpiredListenAddr(peer_id, addr.into())
                     } else {
                         continue;
                     }
                 }
-                ipfs_embed::Event::NewExternalAddr(addr) => {
+                PeerEvent::NewExternalAddr { addr } => {
                     if external.contains(&addr) {
                         Event::NewExternalAddr(peer_id, addr.into())
                     } else {
                         Event::NewObservedAddr(peer_id, addr.into())
                     }
                 }
-                ipfs_embed::Event::ExpiredExternalAddr(addr) => {
+                PeerEvent::ExpiredExternalAddr { addr } => {
                     if external.contains(&addr) {
                         Event::ExpiredExternalAddr(peer_id, addr.into())
                     } else {
                         Event::ExpiredObservedAddr(peer_id, addr.into())
                     }
                 }
-                ipfs_embed::Event::Discovered(peer) => {
-                    ipfs.dial(peer);
+                PeerEvent::Discovered { peer } => {
+                    budget.dial(&mut ipfs, peer);
                     continue;
                 }
-                ipfs_embed::Event::Unreachable(peer) => {
+                PeerEvent::Unreachable { peer } => {
                     if let Some(warn) = to_warn.get_mut(&peer) {
                         if *warn {
                             tracing::warn!(id = display(&peer), "connection failed to initial peer");
@@ -336,11 +483,18 @@ pub fn discovery_publish(
                     } else {
                         tracing::debug!(id = display(&peer), "connection failed");
                     }
+                    budget.done(&peer);
+                    if !budget.permits(&ipfs, &peer) {
+                        // stop retrying until the peer is discovered again
+                        dialers.remove(&peer);
+                        continue;
+                    }
                     let backoff = if let Some(dialer) = dialers.remove(&peer) {
                         dialer.backoff.saturating_mul(2).min(Duration::from_secs(60))
                     } else {
                         Duration::from_secs(1)
                     };
+                    budget.pending.insert(peer);
                     let mut ipfs = ipfs.clone();
                     let task = tokio::spawn(async move {
                         tokio::time::sleep(backoff).await;
@@ -349,7 +503,7 @@ pub fn discovery_publish(
                     dialers.insert(peer, Dialer::new(backoff, task));
                     continue;
                 }
-                ipfs_embed::Event::Connected(peer) => {
+                PeerEvent::Connected { peer, .. } => {
                     if let Some(warn) = to_warn.get_mut(&peer) {
                         tracing::info!(id = display(&peer), "connected to initial peer");
                         *warn = false;
@@ -358,9 +512,15 @@ pub fn discovery_publish(
                     }
                     // dropping the Dialer will kill the task
                     dialers.remove(&peer);
+                    if !budget.admit(&mut ipfs, &peer) {
+                        continue;
+                    }
+                    if let Err(err) = store.lock().index_store.touch_peer(&peer, Timestamp::now()) {
+                        tracing::warn!("error persisting peer address: {}", err);
+                    }
                     continue;
                 }
-                ipfs_embed::Event::Disconnected(peer) => {
+                PeerEvent::Disconnected { peer, .. } => {
                     if let Some(warn) = to_warn.get_mut(&peer) {
                         tracing::info!(id = display(&peer), "disconnected from initial peer");
                         *warn = false;
@@ -368,10 +528,10 @@ pub fn discovery_publish(
                         tracing::debug!(id = display(&peer), "disconnected");
                     }
                     // dialing on disconnected ensures the unreachable event fires.
-                    ipfs.dial(peer);
+                    budget.dial(&mut ipfs, peer);
                     continue;
                 }
-                ipfs_embed::Event::NewInfo(peer) => {
+                PeerEvent::NewInfo { peer } => {
                     if let Some(info) = ipfs.peer_info(&peer) {
                         if let Some(rtt) = info.full_rtt() {
                             if rtt.failures() > 0 {
@@ -395,7 +555,6 @@ pub fn discovery_publish(
                     }
                     continue;
                 }
-                _ => continue,
             };
             if enable_discovery {
                 buffer.clear();
@@ -447,6 +606,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn connection_budget() {
+        let (a, b, c) = (
+            ipfs_embed::PeerId::random(),
+            ipfs_embed::PeerId::random(),
+            ipfs_embed::PeerId::random(),
+        );
+        assert!(ConnectionLimits::default().may_dial(&[a, a, b], &a));
+
+        let limits = ConnectionLimits {
+            max_connections: Some(3),
+            max_connections_per_peer: Some(2),
+        };
+        assert!(limits.may_dial(&[a, b], &a));
+        assert!(!limits.may_dial(&[a, a], &a));
+        assert!(limits.may_dial(&[a, a], &b));
+        assert!(!limits.may_dial(&[a, a, b], &c));
+
+        // incoming connections are already part of the established ones
+        assert!(ConnectionLimits::default().admits(&[a, a, b], &a));
+        assert!(limits.admits(&[a, b, c], &c));
+        assert!(!limits.admits(&[a, a, b, c], &c));
+        assert!(limits.admits(&[a, a, b], &a) && !limits.admits(&[a, a, a], &a));
+    }
+
     fn assert_listen(e: ListenerEvent) {
         if let ListenerEvent::ListenFailed(addr, reason) = e {
             panic!("listen failed for addr {}: {}", addr, reason)
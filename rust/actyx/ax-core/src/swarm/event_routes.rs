@@ -0,0 +1,128 @@
+//! Validation of the event routes, and replacing them on a running store.
+use crate::{
+    swarm::{
+        BanyanStore, ConfigError, EventRoute, RoutingTable, DISCOVERY_STREAM_NAME, FILES_STREAM_NAME,
+        METRICS_STREAM_NAME,
+    },
+    trees::dnf::Dnf,
+};
+use anyhow::Result;
+use ax_aql::{TagAtom, TagExpr};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+/// Event routes in the order they are tried: an event goes into the stream of the first route
+/// matching it, into the default stream if none does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventRoutes(pub Vec<EventRoute>);
+
+impl From<Vec<EventRoute>> for EventRoutes {
+    fn from(routes: Vec<EventRoute>) -> Self {
+        Self(routes)
+    }
+}
+
+impl EventRoutes {
+    /// Check the routes, returning all problems found.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+        let mut catch_all = None;
+        for (index, route) in self.0.iter().enumerate() {
+            let dnf = Dnf::from(&route.from);
+            // the routing table can only evaluate these, anything else would fail on append
+            let unsupported = dnf
+                .0
+                .iter()
+                .flatten()
+                .filter(|atom| !matches!(atom, TagAtom::Tag(_) | TagAtom::AllEvents | TagAtom::AppId(_)))
+                .collect::<BTreeSet<_>>();
+            for atom in unsupported {
+                errors.push(ConfigError::UnsupportedRouteExpression {
+                    index,
+                    from: route.from.clone(),
+                    atom: TagExpr::Atom(atom.clone()),
+                });
+            }
+            let matches_all = dnf
+                .0
+                .iter()
+                .any(|clause| clause.iter().all(|atom| *atom == TagAtom::AllEvents));
+            if matches_all {
+                match catch_all {
+                    Some(previous) => errors.push(ConfigError::DuplicateCatchAll {
+                        index,
+                        from: route.from.clone(),
+                        previous,
+                    }),
+                    None => catch_all = Some(index),
+                }
+            }
+            // the internal streams are written and read by number, other events would garble them
+            if [FILES_STREAM_NAME, DISCOVERY_STREAM_NAME, METRICS_STREAM_NAME].contains(&route.into.as_str()) {
+                errors.push(ConfigError::ReservedStream {
+                    index,
+                    into: route.into.clone(),
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl BanyanStore {
+    pub(crate) fn routing_table(&self) -> Arc<RoutingTable> {
+        self.data.routing_table.lock().clone()
+    }
+
+    /// Replace the event routes, see [`EventRoutes`] for how they apply.
+    ///
+    /// The routes take effect for the next append, each call to [`append`](Self::append) is
+    /// routed entirely by either the previous or the new routes. Streams that are no longer the
+    /// target of a route keep their number.
+    pub async fn update_event_routes(&self, routes: Vec<EventRoute>) -> Result<()> {
+        if let Err(errors) = EventRoutes(routes.clone()).validate() {
+            let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            anyhow::bail!("invalid event routes: {}", errors.join("; "));
+        }
+        let (table, unpublished) = {
+            // the store lock keeps `stream_for_name` from assigning the same numbers meanwhile
+            let store = self.lock();
+            let current = self.routing_table();
+            let names = store.index_store.get_stream_names()?;
+            let mut table = RoutingTable {
+                routes: vec![],
+                stream_mapping: current.stream_mapping.clone(),
+                max_stream_nr: names
+                    .values()
+                    .copied()
+                    .chain(current.max_stream_nr)
+                    .chain(store.local_stream_nrs())
+                    .max(),
+            };
+            let mut unpublished = HashMap::new();
+            for route in routes {
+                if let Some(stream_nr) = table.add_route(route.from, route.into.clone()) {
+                    unpublished.insert(route.into, stream_nr);
+                }
+            }
+            // reserve the new numbers with the current routes until their mappings are published
+            *self.data.routing_table.lock() = Arc::new(RoutingTable {
+                routes: current.routes.clone(),
+                stream_mapping: current.stream_mapping.clone(),
+                max_stream_nr: table.max_stream_nr,
+            });
+            (table, unpublished)
+        };
+        for (name, number) in unpublished {
+            self.append_stream_mapping_event(name, number).await?;
+        }
+        *self.data.routing_table.lock() = Arc::new(table);
+        Ok(())
+    }
+}
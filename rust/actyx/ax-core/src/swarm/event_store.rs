@@ -2,7 +2,7 @@ use std::{cmp::Reverse, convert::TryInto, ops::RangeInclusive};
 
 use crate::{
     ax_futures_util::stream::{AxStreamExt, MergeOrdered},
-    swarm::{selection::StreamEventSelection, BanyanStore, SwarmOffsets},
+    swarm::{selection::StreamEventSelection, BanyanStore, SwarmOffsets, TakeWhileBudget},
     trees::{
         axtrees::AxKey,
         query::{TagExprError, TagExprQuery},
@@ -17,7 +17,7 @@ use banyan::FilteredChunk;
 use futures::{
     future,
     stream::{self, BoxStream},
-    StreamExt, TryStreamExt,
+    Stream, StreamExt, TryStreamExt,
 };
 
 #[derive(Clone, Debug, derive_more::Display, derive_more::Error, derive_more::From)]
@@ -29,6 +29,14 @@ pub enum Error {
 
 pub type PersistenceMeta = (LamportTimestamp, Offset, StreamNr, Timestamp);
 
+/// An item of [`EventStore::subscribe_from_present`]
+#[derive(Debug, Clone)]
+pub enum FromPresent {
+    /// The offsets the subscription starts after, always the first item
+    Present(OffsetMap),
+    Event(Event<Payload>),
+}
+
 /// Wraps a [BanyanStore] and provides functionality for persisting events as well as receiving bounded and
 /// unbounded sets of events for queries across multiple streams with varying order guarantees.
 #[derive(Clone)]
@@ -45,17 +53,24 @@ impl EventStore {
         self.banyan_store.node_id()
     }
 
-    fn forward_stream(&self, selection: StreamEventSelection) -> BoxStream<'static, Event<Payload>> {
+    /// The events of the selection in ascending order, reading no further chunks than needed for
+    /// the first `limit` events.
+    fn forward_stream(
+        &self,
+        selection: StreamEventSelection,
+        limit: Option<u64>,
+    ) -> BoxStream<'static, Event<Payload>> {
         let stream_id = selection.stream_id;
         debug_assert!(self.banyan_store.has_stream(stream_id));
         debug_assert!(selection.from_exclusive < selection.to_inclusive);
         let trees = self.banyan_store.tree_stream(stream_id);
         let range = get_range_inclusive(&selection);
-        self.banyan_store
+        let chunks = self
+            .banyan_store
             .data
             .forest
-            .stream_trees_chunked(selection.tags_query, trees, range, &|_| ())
-            .boxed()
+            .stream_trees_chunked(selection.tags_query, trees, range, &|_| ());
+        budgeted(chunks, limit)
             .map_ok(move |chunk| stream::iter(events_from_chunk(stream_id, chunk)))
             .take_while(|x| future::ready(x.is_ok()))
             .filter_map(|x| future::ready(x.ok()))
@@ -63,17 +78,24 @@ impl EventStore {
             .boxed()
     }
 
-    fn backward_stream(&self, selection: StreamEventSelection) -> BoxStream<'static, Reverse<Event<Payload>>> {
+    /// The events of the selection in descending order, reading no further chunks than needed for
+    /// the first `limit` events.
+    fn backward_stream(
+        &self,
+        selection: StreamEventSelection,
+        limit: Option<u64>,
+    ) -> BoxStream<'static, Reverse<Event<Payload>>> {
         let stream_id = selection.stream_id;
         debug_assert!(selection.from_exclusive < selection.to_inclusive);
         debug_assert!(self.banyan_store.has_stream(stream_id));
         let trees = self.banyan_store.tree_stream(stream_id);
         let range = get_range_inclusive(&selection);
-        self.banyan_store
-            .data
-            .forest
-            .stream_trees_chunked_reverse(selection.tags_query, trees, range, &|_| ())
-            .boxed()
+        let chunks =
+            self.banyan_store
+                .data
+                .forest
+                .stream_trees_chunked_reverse(selection.tags_query, trees, range, &|_| ());
+        budgeted(chunks, limit)
             .map_ok(move |chunk| stream::iter(events_from_chunk_rev(stream_id, chunk)))
             .take_while(|x| future::ready(x.is_ok()))
             .filter_map(|x| future::ready(x.ok()))
@@ -88,7 +110,7 @@ impl EventStore {
         to_offsets_including: OffsetMap,
     ) -> Result<Vec<StreamEventSelection>, Error> {
         let this = self.clone();
-        let present = self.current_offsets().present;
+        let present = self.current_offsets().present();
         if present.union(&to_offsets_including) != present {
             return Err(Error::InvalidUpperBounds);
         }
@@ -106,6 +128,10 @@ impl EventStore {
                 if tags_query.is_empty() {
                     return None;
                 }
+                if !this.banyan_store.data.is_readable(stream_id) {
+                    tracing::warn!(%stream_id, "skipping stream without secret in query");
+                    return None;
+                }
                 Some(StreamEventSelection {
                     stream_id,
                     from_exclusive,
@@ -119,11 +145,15 @@ impl EventStore {
 
     #[cfg(test)]
     fn offsets(&self) -> BoxStream<'static, SwarmOffsets> {
-        self.banyan_store.data.offsets.new_observer().boxed()
+        self.banyan_store.offsets_stream().boxed()
     }
 
     pub fn current_offsets(&self) -> SwarmOffsets {
-        self.banyan_store.data.offsets.get_cloned()
+        self.banyan_store.offsets()
+    }
+
+    pub fn stream_heartbeats(&self) -> BoxStream<'static, (StreamId, LamportTimestamp, Offset)> {
+        self.banyan_store.stream_heartbeats().boxed()
     }
 
     pub async fn persist(&self, app_id: AppId, events: Vec<(TagSet, Payload)>) -> anyhow::Result<Vec<PersistenceMeta>> {
@@ -133,49 +163,68 @@ impl EventStore {
         self.banyan_store.append(app_id, events).await
     }
 
+    /// Replace a [`PayloadRef`](crate::swarm::PayloadRef) by the payload it refers to.
+    pub async fn inline_payload(&self, payload: Payload) -> anyhow::Result<Payload> {
+        self.banyan_store.inline_payload(payload).await
+    }
+
+    /// The matching events between the offsets in ascending order, only the first `limit` ones
+    /// if given.
+    ///
+    /// None of the streams can contribute more than `limit` events to the result, so reading
+    /// stops in each stream once it has produced them.
     pub async fn bounded_forward(
         &self,
         tag_expr: &TagExpr,
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
+        limit: Option<u64>,
     ) -> Result<BoxStream<'static, Event<Payload>>, Error> {
         let this = self.clone();
         let event_streams = self
             .bounded_streams(tag_expr, from_offsets_excluding, to_offsets_including)
             .await?
             .into_iter()
-            .map(|selection| this.forward_stream(selection));
-        Ok(MergeOrdered::new_fixed(event_streams).boxed())
+            .map(|selection| this.forward_stream(selection, limit));
+        Ok(take(MergeOrdered::new_fixed(event_streams), limit))
     }
 
+    /// Like [`bounded_forward`](Self::bounded_forward), but only ordered within each stream.
     pub async fn bounded_forward_per_stream(
         &self,
         tag_expr: &TagExpr,
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
+        limit: Option<u64>,
     ) -> Result<BoxStream<'static, Event<Payload>>, Error> {
         let this = self.clone();
         let event_streams = self
             .bounded_streams(tag_expr, from_offsets_excluding, to_offsets_including)
             .await?
             .into_iter()
-            .map(move |selection| this.forward_stream(selection));
-        Ok(stream::iter(event_streams).merge_unordered().boxed())
+            .map(move |selection| this.forward_stream(selection, limit));
+        Ok(take(stream::iter(event_streams).merge_unordered(), limit))
     }
 
+    /// Like [`bounded_forward`](Self::bounded_forward), but in descending order, i.e. with
+    /// `limit` the latest events.
     pub async fn bounded_backward(
         &self,
         tag_expr: &TagExpr,
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
+        limit: Option<u64>,
     ) -> Result<BoxStream<'static, Event<Payload>>, Error> {
         let this = self.clone();
         let event_streams = self
             .bounded_streams(tag_expr, from_offsets_excluding, to_offsets_including)
             .await?
             .into_iter()
-            .map(move |selection| this.backward_stream(selection));
-        Ok(MergeOrdered::new_fixed(event_streams).map(|reverse| reverse.0).boxed())
+            .map(move |selection| this.backward_stream(selection, limit));
+        Ok(take(
+            MergeOrdered::new_fixed(event_streams).map(|reverse| reverse.0),
+            limit,
+        ))
     }
 
     pub fn unbounded_forward_per_stream(
@@ -183,14 +232,42 @@ impl EventStore {
         tag_expr: &TagExpr,
         from_offsets_excluding: OffsetMap,
     ) -> Result<BoxStream<'static, Event<Payload>>, Error> {
-        let this = self.clone();
         let mk_tags_query = TagExprQuery::from_expr(tag_expr)?;
+        let known_streams = self.banyan_store.stream_known_streams();
+        Ok(self.forward_known_streams(known_streams, mk_tags_query, from_offsets_excluding))
+    }
+
+    /// The matching events from now on, in ascending order per stream, without reading any of the
+    /// events already present.
+    ///
+    /// The first item are the present offsets, taken under the store lock together with the
+    /// subscription to new streams; all events after it are strictly above them. A bounded query up
+    /// to these offsets therefore complements the subscription without duplicates or gaps.
+    pub fn subscribe_from_present(&self, tag_expr: &TagExpr) -> Result<BoxStream<'static, FromPresent>, Error> {
+        let mk_tags_query = TagExprQuery::from_expr(tag_expr)?;
+        let (present, known_streams) = self.banyan_store.stream_known_streams_from_present();
+        let events = self.forward_known_streams(known_streams, mk_tags_query, present.clone());
+        Ok(stream::once(future::ready(FromPresent::Present(present)))
+            .chain(events.map(FromPresent::Event))
+            .boxed())
+    }
+
+    /// The matching events of all streams emitted by `known_streams` above `from_offsets_excluding`.
+    fn forward_known_streams(
+        &self,
+        known_streams: impl Stream<Item = StreamId> + Send + 'static,
+        mk_tags_query: impl Fn(bool, StreamId) -> TagExprQuery + Send + 'static,
+        from_offsets_excluding: OffsetMap,
+    ) -> BoxStream<'static, Event<Payload>> {
+        let this = self.clone();
         let banyan_store = self.banyan_store.clone();
-        Ok(self
-            .banyan_store
-            .stream_known_streams()
+        known_streams
             .boxed()
             .filter_map(move |stream_id| {
+                if !banyan_store.data.is_readable(stream_id) {
+                    tracing::warn!(%stream_id, "skipping stream without secret in subscription");
+                    return future::ready(None);
+                }
                 let local = banyan_store.is_local(stream_id);
                 let tags_query = mk_tags_query(local, stream_id);
                 future::ready(if tags_query.is_empty() {
@@ -204,9 +281,33 @@ impl EventStore {
                     })
                 })
             })
-            .map(move |selection| this.forward_stream(selection))
+            .map(move |selection| this.forward_stream(selection, None))
             .merge_unordered()
-            .boxed())
+            .boxed()
+    }
+}
+
+fn budgeted<T, E>(
+    chunks: impl Stream<Item = anyhow::Result<FilteredChunk<T, E>>> + Send + 'static,
+    limit: Option<u64>,
+) -> BoxStream<'static, anyhow::Result<FilteredChunk<T, E>>>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    match limit {
+        Some(limit) => TakeWhileBudget::new(limit).apply(chunks),
+        None => chunks.boxed(),
+    }
+}
+
+fn take(
+    events: impl Stream<Item = Event<Payload>> + Send + 'static,
+    limit: Option<u64>,
+) -> BoxStream<'static, Event<Payload>> {
+    match limit {
+        Some(limit) => events.take(limit.try_into().unwrap_or(usize::MAX)).boxed(),
+        None => events.boxed(),
     }
 }
 
@@ -259,6 +360,7 @@ mod tests {
     use std::{
         collections::{BTreeMap, BTreeSet},
         str::FromStr,
+        time::Duration,
     };
 
     use crate::ax_futures_util::stream::Drainer;
@@ -365,41 +467,53 @@ mod tests {
             .await
             .unwrap();
 
-        let mut stream = Drainer::new(store.forward_stream(StreamEventSelection {
-            stream_id,
-            from_exclusive: OffsetOrMin::MIN,
-            to_inclusive: OffsetOrMin::ZERO,
-            tags_query: TagExprQuery::all(),
-        }));
+        let mut stream = Drainer::new(store.forward_stream(
+            StreamEventSelection {
+                stream_id,
+                from_exclusive: OffsetOrMin::MIN,
+                to_inclusive: OffsetOrMin::ZERO,
+                tags_query: TagExprQuery::all(),
+            },
+            None,
+        ));
         let res = stream.next().unwrap();
         assert_eq!(res.len(), 1);
         assert_eq!(stream.next(), None);
 
-        let mut stream = Drainer::new(store.forward_stream(StreamEventSelection {
-            stream_id,
-            from_exclusive: OffsetOrMin::MIN,
-            to_inclusive: OffsetOrMin::ZERO,
-            tags_query: TagExprQuery::empty(),
-        }));
+        let mut stream = Drainer::new(store.forward_stream(
+            StreamEventSelection {
+                stream_id,
+                from_exclusive: OffsetOrMin::MIN,
+                to_inclusive: OffsetOrMin::ZERO,
+                tags_query: TagExprQuery::empty(),
+            },
+            None,
+        ));
         assert_eq!(stream.next(), None);
 
-        let mut stream = Drainer::new(store.forward_stream(StreamEventSelection {
-            stream_id,
-            from_exclusive: OffsetOrMin::ZERO + 3,
-            to_inclusive: OffsetOrMin::ZERO + 4,
-            tags_query: TagExprQuery::all(),
-        }));
+        let mut stream = Drainer::new(store.forward_stream(
+            StreamEventSelection {
+                stream_id,
+                from_exclusive: OffsetOrMin::ZERO + 3,
+                to_inclusive: OffsetOrMin::ZERO + 4,
+                tags_query: TagExprQuery::all(),
+            },
+            None,
+        ));
         let res = stream.next().unwrap();
         assert_eq!(res.len(), 1);
         assert_eq!(res[0].meta.app_id, app_id);
         assert_eq!(stream.next(), None); // bounded -> complete
 
-        let mut stream = Drainer::new(store.forward_stream(StreamEventSelection {
-            stream_id,
-            from_exclusive: OffsetOrMin::ZERO,
-            to_inclusive: OffsetOrMin::MAX,
-            tags_query: TagExprQuery::all(),
-        }));
+        let mut stream = Drainer::new(store.forward_stream(
+            StreamEventSelection {
+                stream_id,
+                from_exclusive: OffsetOrMin::ZERO,
+                to_inclusive: OffsetOrMin::MAX,
+                tags_query: TagExprQuery::all(),
+            },
+            None,
+        ));
         let res = stream.next().unwrap();
         assert_eq!(res.len(), 4);
         assert_eq!(stream.next(), Some(vec![])); // unbounded -> keep running
@@ -417,23 +531,29 @@ mod tests {
             .unwrap();
 
         // Skips the default mapping events (0-3)
-        let mut stream = Drainer::new(store.backward_stream(StreamEventSelection {
-            stream_id,
-            from_exclusive: OffsetOrMin::from(3i64),
-            to_inclusive: OffsetOrMin::from(4i64),
-            tags_query: TagExprQuery::all(),
-        }));
+        let mut stream = Drainer::new(store.backward_stream(
+            StreamEventSelection {
+                stream_id,
+                from_exclusive: OffsetOrMin::from(3i64),
+                to_inclusive: OffsetOrMin::from(4i64),
+                tags_query: TagExprQuery::all(),
+            },
+            None,
+        ));
         let res = stream.next().unwrap();
         assert_eq!(res.len(), 1);
         assert_eq!(res[0].0.meta.app_id, app_id);
         assert_eq!(stream.next(), None);
 
-        let mut stream = Drainer::new(store.backward_stream(StreamEventSelection {
-            stream_id,
-            from_exclusive: OffsetOrMin::MIN,
-            to_inclusive: OffsetOrMin::ZERO,
-            tags_query: TagExprQuery::empty(),
-        }));
+        let mut stream = Drainer::new(store.backward_stream(
+            StreamEventSelection {
+                stream_id,
+                from_exclusive: OffsetOrMin::MIN,
+                to_inclusive: OffsetOrMin::ZERO,
+                tags_query: TagExprQuery::empty(),
+            },
+            None,
+        ));
         assert_eq!(stream.next(), None);
     }
 
@@ -484,10 +604,16 @@ mod tests {
                 tag_expr: expr.clone(),
             };
 
-            let forward = store.bounded_forward(expr, from.clone(), to.clone()).await.unwrap();
+            let forward = store
+                .bounded_forward(expr, from.clone(), to.clone(), None)
+                .await
+                .unwrap();
             assert_stream(store.node_id(), forward, selection.clone(), len, Order::Asc, true);
 
-            let backward = store.bounded_backward(expr, from.clone(), to.clone()).await.unwrap();
+            let backward = store
+                .bounded_backward(expr, from.clone(), to.clone(), None)
+                .await
+                .unwrap();
             assert_stream(store.node_id(), backward, selection, len, Order::Desc, true);
         }
 
@@ -500,6 +626,17 @@ mod tests {
         // all
         assert_bounded(&store1, "'test'", None, &max, 6).await;
 
+        // limited, the first and the latest events across both streams
+        let expr = &"'test'".parse::<TagExpr>().unwrap();
+        let forward = |limit| store1.bounded_forward(expr, OffsetMap::default(), offset_map(&max), limit);
+        let all = forward(None).await.unwrap().collect::<Vec<_>>().await;
+        let limited = forward(Some(4)).await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(limited, all[..4]);
+        let backward = |limit| store1.bounded_backward(expr, OffsetMap::default(), offset_map(&max), limit);
+        let all = backward(None).await.unwrap().collect::<Vec<_>>().await;
+        let limited = backward(Some(2)).await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(limited, all[..2]);
+
         // stream1
         assert_bounded(&store1, "isLocal & 'test'", None, &max, 3).await;
         assert_bounded(&store1, "'test'", None, &btreemap! { stream_id1 => 6 }, 3).await;
@@ -531,6 +668,7 @@ mod tests {
                 offset_map(&btreemap! {
                   "Kh8od22U1f.2S7wHoVCnmJaKWX/6.e2dSlEk2K3Jia6-0".parse::<StreamId>().unwrap() => 0
                 }),
+                None,
             )
             .await;
         assert!(matches!(unknown, Err(Error::InvalidUpperBounds)));
@@ -540,6 +678,7 @@ mod tests {
                 &TagExpr::Atom(TagAtom::AllEvents),
                 OffsetMap::default(),
                 offset_map(&btreemap! { stream_id1 => 42 }),
+                None,
             )
             .await;
         assert!(matches!(exceeding_present, Err(Error::InvalidUpperBounds)));
@@ -666,12 +805,15 @@ mod tests {
                 let tags_query = TagExprQuery::new(vec![scoped_tags], LamportQuery::all(), TimeQuery::all());
                 let range = random_range();
                 let actual = store
-                    .forward_stream(StreamEventSelection {
-                        stream_id,
-                        from_exclusive: OffsetOrMin::from(*range.start() as i64 - 1),
-                        to_inclusive: OffsetOrMin::from(*range.end() as i64),
-                        tags_query,
-                    })
+                    .forward_stream(
+                        StreamEventSelection {
+                            stream_id,
+                            from_exclusive: OffsetOrMin::from(*range.start() as i64 - 1),
+                            to_inclusive: OffsetOrMin::from(*range.end() as i64),
+                            tags_query,
+                        },
+                        None,
+                    )
                     .map(|e| e.key.offset)
                     .collect::<Vec<_>>()
                     .await;
@@ -739,7 +881,7 @@ mod tests {
             async move {
                 anyhow::Result::<Vec<String>>::Ok(
                     store
-                        .bounded_forward(&s.parse().unwrap(), OffsetMap::default(), offsets)
+                        .bounded_forward(&s.parse().unwrap(), OffsetMap::default(), offsets, None)
                         .await?
                         .map(|e| e.payload.json_string())
                         .collect()
@@ -816,4 +958,69 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscribe_from_present_during_appends() -> anyhow::Result<()> {
+        let store = mk_store("subscribe_from_present").await;
+        let tag_expr = "'present'".parse::<TagExpr>()?;
+        let appender = {
+            let store = store.clone();
+            tokio::spawn(async move {
+                for i in 0..200 {
+                    let events = vec![(tags!("present"), Payload::null()); 1 + i % 3];
+                    store.persist(app_id(), events).await?;
+                    tokio::task::yield_now().await;
+                }
+                anyhow::Ok(())
+            })
+        };
+        let mut subscriptions = vec![];
+        while !appender.is_finished() {
+            subscriptions.push(store.subscribe_from_present(&tag_expr)?);
+            tokio::task::yield_now().await;
+        }
+        appender.await??;
+        assert!(subscriptions.len() > 1);
+
+        let keys = |events: Vec<Event<Payload>>| events.into_iter().map(|e| e.key).collect::<Vec<_>>();
+        let upper = store.current_offsets().present();
+        let mut all = keys(
+            store
+                .bounded_forward(&tag_expr, OffsetMap::empty(), upper, None)
+                .await?
+                .collect()
+                .await,
+        );
+        all.sort();
+        assert_eq!(all.len(), (0..200).map(|i| 1 + i % 3).sum::<usize>());
+
+        for mut subscription in subscriptions {
+            let present = match subscription.next().await {
+                Some(FromPresent::Present(present)) => present,
+                item => panic!("expected the present offsets first, got {:?}", item),
+            };
+            let mut seen = keys(
+                store
+                    .bounded_forward(&tag_expr, OffsetMap::empty(), present.clone(), None)
+                    .await?
+                    .collect()
+                    .await,
+            );
+            let history = seen.len();
+            let live = (&mut subscription).take(all.len() - history).map(|item| match item {
+                FromPresent::Event(event) => {
+                    assert!(OffsetOrMin::from(event.key.offset) > present.offset(event.key.stream));
+                    event.key
+                }
+                FromPresent::Present(_) => panic!("present offsets after the first item"),
+            });
+            seen.extend(tokio::time::timeout(Duration::from_secs(5), live.collect::<Vec<_>>()).await?);
+            seen.sort();
+            assert_eq!(seen, all, "history of {} events", history);
+            // nothing more than the events up to `upper`
+            let more = tokio::time::timeout(Duration::from_millis(50), subscription.next()).await;
+            assert!(more.is_err(), "unexpected {:?}", more);
+        }
+        Ok(())
+    }
 }
@@ -6,8 +6,8 @@ use crate::{
     trees::query::TagExprError,
 };
 use ax_aql::TagExpr;
-use ax_types::{AppId, Event, OffsetMap, Payload, TagSet};
-use futures::{Future, Stream, StreamExt};
+use ax_types::{AppId, Event, LamportTimestamp, Offset, OffsetMap, Payload, StreamId, TagSet};
+use futures::{stream::BoxStream, Future, Stream, StreamExt};
 use parking_lot::Mutex;
 use std::{
     collections::BTreeMap,
@@ -16,14 +16,16 @@ use std::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::Duration,
 };
 use tokio::{
     runtime::Handle,
     sync::{
         mpsc::{self, error::TrySendError},
-        oneshot,
+        oneshot, Notify,
     },
     task::JoinHandle,
+    time::Instant,
 };
 
 #[derive(Debug, Clone, derive_more::Display, derive_more::Error)]
@@ -32,10 +34,21 @@ pub enum Error {
     Aborted,
     #[display(fmt = "Channel towards event store is overloaded.")]
     Overload,
+    #[display(
+        fmt = "Event store mailbox is full with {} pending requests, request {}.",
+        queue_depth,
+        outcome
+    )]
+    MailboxFull {
+        queue_depth: usize,
+        outcome: MailboxOutcome,
+    },
     #[display(fmt = "Query bounds out of range: upper bound must be within the known present.")]
     InvalidUpperBounds,
     #[display(fmt = "AQL Error: {}", _0)]
     TagExprError(TagExprError),
+    #[display(fmt = "Cannot inline payload: {}", _0)]
+    InlinePayload(#[error(not(source))] String),
 }
 
 impl From<super::event_store::Error> for Error {
@@ -65,14 +78,130 @@ impl<T> From<tokio::sync::mpsc::error::TrySendError<T>> for Error {
     }
 }
 
+/// What an [`EventStoreRef`] does with a request while its mailbox is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MailboxStrategy {
+    /// Reject the request right away
+    #[default]
+    FailFast,
+    /// Wait for a pending request to finish, rejecting the request after `timeout`
+    Block { timeout: Duration },
+    /// Abort the oldest pending query or subscription to make room, e.g. for replacing a
+    /// subscription. Other requests are never aborted, a persist request might already have
+    /// appended its events; without a query or subscription to abort the request is rejected.
+    ShedOldest,
+}
+
+/// How a request fared in a full mailbox, see [`Error::MailboxFull`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum MailboxOutcome {
+    #[display(fmt = "rejected")]
+    Rejected,
+    #[display(fmt = "timed out after {:?}", _0)]
+    TimedOut(Duration),
+    #[display(fmt = "shed for a newer one")]
+    Shed,
+}
+
+/// The requests sent towards the event store whose reply has not arrived yet
+struct Mailbox {
+    capacity: usize,
+    strategy: MailboxStrategy,
+    /// pending requests by ascending id, with the means to shed the sheddable ones
+    pending: Mutex<(u64, BTreeMap<u64, Option<oneshot::Sender<usize>>>)>,
+    released: Notify,
+}
+
+impl Mailbox {
+    async fn admit(self: &Arc<Self>, sheddable: bool) -> Result<(PendingRequest, oneshot::Receiver<usize>), Error> {
+        let deadline = match self.strategy {
+            MailboxStrategy::Block { timeout } => Some((Instant::now() + timeout, timeout)),
+            _ => None,
+        };
+        loop {
+            let released = self.released.notified();
+            {
+                let mut pending = self.pending.lock();
+                let (next_id, requests) = &mut *pending;
+                let queue_depth = requests.len();
+                if queue_depth >= self.capacity {
+                    match self.strategy {
+                        MailboxStrategy::FailFast => {
+                            return Err(Error::MailboxFull {
+                                queue_depth,
+                                outcome: MailboxOutcome::Rejected,
+                            })
+                        }
+                        MailboxStrategy::ShedOldest => {
+                            let oldest = requests.iter().find_map(|(id, shed)| shed.is_some().then_some(*id));
+                            match oldest.and_then(|id| requests.remove(&id)).flatten() {
+                                Some(shed) => {
+                                    let _ = shed.send(queue_depth);
+                                }
+                                None => {
+                                    return Err(Error::MailboxFull {
+                                        queue_depth,
+                                        outcome: MailboxOutcome::Rejected,
+                                    })
+                                }
+                            }
+                        }
+                        MailboxStrategy::Block { .. } => {}
+                    }
+                }
+                if requests.len() < self.capacity {
+                    let id = *next_id;
+                    *next_id += 1;
+                    let (shed, shed_rx) = oneshot::channel();
+                    requests.insert(id, sheddable.then_some(shed));
+                    let request = PendingRequest {
+                        mailbox: self.clone(),
+                        id,
+                    };
+                    return Ok((request, shed_rx));
+                }
+            } // lock is dropped here
+            let Some((deadline, timeout)) = deadline else {
+                // only without any capacity to shed from
+                return Err(Error::MailboxFull {
+                    queue_depth: 0,
+                    outcome: MailboxOutcome::Rejected,
+                });
+            };
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                return Err(Error::MailboxFull {
+                    queue_depth: self.pending.lock().1.len(),
+                    outcome: MailboxOutcome::TimedOut(timeout),
+                });
+            }
+        }
+    }
+}
+
+/// Frees the mailbox slot of a request when dropped
+struct PendingRequest {
+    mailbox: Arc<Mailbox>,
+    id: u64,
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        if self.mailbox.pending.lock().1.remove(&self.id).is_some() {
+            self.mailbox.released.notify_one();
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct EventStoreRef {
     tx: Arc<dyn Fn(EventStoreRequest) -> Result<(), Error> + Send + Sync + 'static>,
+    mailbox: Arc<Mailbox>,
 }
 
 type OneShot<T> = oneshot::Sender<Result<T, Error>>;
 type StreamOf<T> = mpsc::Receiver<Result<T, Error>>;
 type StreamTo<T> = mpsc::Sender<Result<T, Error>>;
+type HeartbeatsOf = BoxStream<'static, (StreamId, LamportTimestamp, Offset)>;
 
 #[derive(Debug, derive_more::Display)]
 pub enum EventStoreRequest {
@@ -84,19 +213,23 @@ pub enum EventStoreRequest {
         events: Vec<(TagSet, Payload)>,
         reply: OneShot<Vec<PersistenceMeta>>,
     },
-    #[display(fmt = "Bounded({}, per_stream={})", tag_expr, per_stream)]
+    #[display(fmt = "Bounded({}, per_stream={}, limit={:?})", tag_expr, per_stream, limit)]
     BoundedForward {
         tag_expr: TagExpr,
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
         per_stream: bool,
+        /// only the first events, see [`EventStore::bounded_forward`]
+        limit: Option<u64>,
         reply: OneShot<StreamOf<Event<Payload>>>,
     },
-    #[display(fmt = "Backward({})", tag_expr)]
+    #[display(fmt = "Backward({}, limit={:?})", tag_expr, limit)]
     BoundedBackward {
         tag_expr: TagExpr,
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
+        /// only the latest events, see [`EventStore::bounded_backward`]
+        limit: Option<u64>,
         reply: OneShot<StreamOf<Event<Payload>>>,
     },
     #[display(fmt = "Unbounded({})", tag_expr)]
@@ -105,25 +238,74 @@ pub enum EventStoreRequest {
         from_offsets_excluding: OffsetMap,
         reply: OneShot<StreamOf<Event<Payload>>>,
     },
+    #[display(fmt = "InlinePayload")]
+    InlinePayload { payload: Payload, reply: OneShot<Payload> },
+    #[display(fmt = "StreamHeartbeats")]
+    StreamHeartbeats { reply: OneShot<HeartbeatsOf> },
 }
 
 use EventStoreRequest::*;
 
+impl EventStoreRequest {
+    /// Queries and subscriptions, which [`MailboxStrategy::ShedOldest`] may abort
+    fn is_sheddable(&self) -> bool {
+        matches!(
+            self,
+            BoundedForward { .. } | BoundedBackward { .. } | UnboundedForward { .. }
+        )
+    }
+}
+
 impl EventStoreRef {
+    /// Send the requests using `f`, without limiting the number of pending requests.
     pub fn new(f: impl Fn(EventStoreRequest) -> Result<(), Error> + Send + Sync + 'static) -> Self {
-        Self { tx: Arc::new(f) }
+        Self::with_mailbox(usize::MAX, MailboxStrategy::FailFast, f)
     }
 
-    pub async fn offsets(&self) -> Result<SwarmOffsets, Error> {
+    /// Send the requests using `f`, with at most `capacity` requests awaiting their reply. Further
+    /// requests are dealt with according to `strategy`.
+    pub fn with_mailbox(
+        capacity: usize,
+        strategy: MailboxStrategy,
+        f: impl Fn(EventStoreRequest) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        let mailbox = Mailbox {
+            capacity,
+            strategy,
+            pending: Default::default(),
+            released: Notify::new(),
+        };
+        Self {
+            tx: Arc::new(f),
+            mailbox: Arc::new(mailbox),
+        }
+    }
+
+    /// The number of requests sent which have not been replied to yet
+    pub fn pending_requests(&self) -> usize {
+        self.mailbox.pending.lock().1.len()
+    }
+
+    async fn request<T>(&self, f: impl FnOnce(OneShot<T>) -> EventStoreRequest) -> Result<T, Error> {
         let (reply, rx) = oneshot::channel();
-        (self.tx)(Offsets { reply })?;
-        rx.await.my_err()?
+        let request = f(reply);
+        let (_pending, shed) = self.mailbox.admit(request.is_sheddable()).await?;
+        (self.tx)(request)?;
+        tokio::select! {
+            result = rx => result.my_err()?,
+            Ok(queue_depth) = shed => Err(Error::MailboxFull {
+                queue_depth,
+                outcome: MailboxOutcome::Shed,
+            }),
+        }
+    }
+
+    pub async fn offsets(&self) -> Result<SwarmOffsets, Error> {
+        self.request(|reply| Offsets { reply }).await
     }
 
     pub async fn persist(&self, app_id: AppId, events: Vec<(TagSet, Payload)>) -> Result<Vec<PersistenceMeta>, Error> {
-        let (reply, rx) = oneshot::channel();
-        (self.tx)(Persist { app_id, events, reply })?;
-        rx.await.my_err()?
+        self.request(|reply| Persist { app_id, events, reply }).await
     }
 
     pub async fn bounded_forward(
@@ -132,16 +314,17 @@ impl EventStoreRef {
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
         per_stream: bool,
+        limit: Option<u64>,
     ) -> Result<mpsc::Receiver<Result<Event<Payload>, Error>>, Error> {
-        let (reply, rx) = oneshot::channel();
-        (self.tx)(BoundedForward {
+        self.request(|reply| BoundedForward {
             tag_expr,
             from_offsets_excluding,
             to_offsets_including,
             per_stream,
+            limit,
             reply,
-        })?;
-        rx.await.my_err()?
+        })
+        .await
     }
 
     pub async fn bounded_backward(
@@ -149,15 +332,16 @@ impl EventStoreRef {
         tag_expr: TagExpr,
         from_offsets_excluding: OffsetMap,
         to_offsets_including: OffsetMap,
+        limit: Option<u64>,
     ) -> Result<mpsc::Receiver<Result<Event<Payload>, Error>>, Error> {
-        let (reply, rx) = oneshot::channel();
-        (self.tx)(BoundedBackward {
+        self.request(|reply| BoundedBackward {
             tag_expr,
             from_offsets_excluding,
             to_offsets_including,
+            limit,
             reply,
-        })?;
-        rx.await.my_err()?
+        })
+        .await
     }
 
     pub async fn unbounded_forward(
@@ -165,13 +349,21 @@ impl EventStoreRef {
         tag_expr: TagExpr,
         from_offsets_excluding: OffsetMap,
     ) -> Result<mpsc::Receiver<Result<Event<Payload>, Error>>, Error> {
-        let (reply, rx) = oneshot::channel();
-        (self.tx)(UnboundedForward {
+        self.request(|reply| UnboundedForward {
             tag_expr,
             from_offsets_excluding,
             reply,
-        })?;
-        rx.await.my_err()?
+        })
+        .await
+    }
+
+    pub async fn inline_payload(&self, payload: Payload) -> Result<Payload, Error> {
+        self.request(|reply| InlinePayload { payload, reply }).await
+    }
+
+    /// See [`BanyanStore::stream_heartbeats`](crate::swarm::BanyanStore::stream_heartbeats)
+    pub async fn stream_heartbeats(&self) -> Result<HeartbeatsOf, Error> {
+        self.request(|reply| StreamHeartbeats { reply }).await
     }
 }
 
@@ -243,18 +435,19 @@ impl EventStoreHandler {
                 from_offsets_excluding,
                 to_offsets_including,
                 per_stream,
+                limit,
                 reply,
             } => {
                 let store = self.store.clone();
                 self.stream(reply, runtime, move || async move {
                     if per_stream {
                         store
-                            .bounded_forward_per_stream(&tag_expr, from_offsets_excluding, to_offsets_including)
+                            .bounded_forward_per_stream(&tag_expr, from_offsets_excluding, to_offsets_including, limit)
                             .await
                             .map(|s| s.boxed())
                     } else {
                         store
-                            .bounded_forward(&tag_expr, from_offsets_excluding, to_offsets_including)
+                            .bounded_forward(&tag_expr, from_offsets_excluding, to_offsets_including, limit)
                             .await
                             .map(|s| s.boxed())
                     }
@@ -264,12 +457,13 @@ impl EventStoreHandler {
                 tag_expr,
                 from_offsets_excluding,
                 to_offsets_including,
+                limit,
                 reply,
             } => {
                 let store = self.store.clone();
                 self.stream(reply, runtime, move || async move {
                     store
-                        .bounded_backward(&tag_expr, from_offsets_excluding, to_offsets_including)
+                        .bounded_backward(&tag_expr, from_offsets_excluding, to_offsets_including, limit)
                         .await
                 });
             }
@@ -283,10 +477,27 @@ impl EventStoreHandler {
                     ready(store.unbounded_forward_per_stream(&tag_expr, from_offsets_excluding))
                 });
             }
+            StreamHeartbeats { reply } => {
+                let _ = reply.send(Ok(self.store.stream_heartbeats()));
+            }
+            InlinePayload { payload, reply } => {
+                let store = self.store.clone();
+                runtime.spawn(async move {
+                    let _ = reply.send(
+                        store
+                            .inline_payload(payload)
+                            .await
+                            .map_err(|e| Error::InlinePayload(format!("{:#}", e))),
+                    );
+                });
+            }
         }
     }
 
-    fn stream<F, Fut, S>(&mut self, reply: OneShot<StreamOf<Event<Payload>>>, runtime: &Handle, f: F)
+    /// Run the stream created by `f` in a task, sending its events to the receiver in `reply`.
+    ///
+    /// The task stops as soon as the receiver is dropped, also while waiting for the next event.
+    pub(crate) fn stream<F, Fut, S>(&mut self, reply: OneShot<StreamOf<Event<Payload>>>, runtime: &Handle, f: F)
     where
         F: FnOnce() -> Fut + Send + 'static,
         Fut: Future<Output = Result<S, super::event_store::Error>> + Send + 'static,
@@ -310,7 +521,18 @@ impl EventStoreHandler {
                     }; // lock is dropped here
                     tracing::trace!("stream {} started {}", id, doit);
                     if doit && reply.send(Ok(rx)).is_ok() {
-                        while let Some(event) = s.next().await {
+                        loop {
+                            let event = tokio::select! {
+                                _ = tx.closed() => {
+                                    // stream recipient has lost interest, stop producing events
+                                    tracing::trace!("stream {} aborted", id);
+                                    break;
+                                }
+                                event = s.next() => match event {
+                                    Some(event) => event,
+                                    None => break,
+                                },
+                            };
                             tracing::trace!("stream {} got {}/{}", id, event.key.lamport, event.key.stream);
                             match tx.try_reserve() {
                                 Ok(sender) => {
@@ -373,6 +595,156 @@ impl Drop for EventStoreHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
+
+    /// An event store reference whose requests are kept but never handled
+    fn stalled_store(
+        capacity: usize,
+        strategy: MailboxStrategy,
+    ) -> (EventStoreRef, Arc<Mutex<Vec<EventStoreRequest>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let store = received.clone();
+        let event_store = EventStoreRef::with_mailbox(capacity, strategy, move |request| {
+            store.lock().push(request);
+            Ok(())
+        });
+        (event_store, received)
+    }
+
+    async fn wait_for_pending(event_store: &EventStoreRef, n: usize) {
+        while event_store.pending_requests() < n {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    async fn wait_for_pending_below(event_store: &EventStoreRef, n: usize) {
+        while event_store.pending_requests() >= n {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn mailbox_fail_fast() {
+        let (event_store, received) = stalled_store(2, MailboxStrategy::FailFast);
+        for _ in 0..2 {
+            let event_store = event_store.clone();
+            tokio::spawn(async move { event_store.offsets().await });
+        }
+        wait_for_pending(&event_store, 2).await;
+
+        let err = event_store.offsets().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MailboxFull {
+                queue_depth: 2,
+                outcome: MailboxOutcome::Rejected
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Event store mailbox is full with 2 pending requests, request rejected."
+        );
+        assert_eq!(received.lock().len(), 2);
+        assert_eq!(event_store.pending_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn mailbox_block() {
+        let timeout = Duration::from_millis(100);
+        let (event_store, received) = stalled_store(1, MailboxStrategy::Block { timeout });
+        let first = tokio::spawn({
+            let event_store = event_store.clone();
+            async move { event_store.offsets().await }
+        });
+        wait_for_pending(&event_store, 1).await;
+
+        let err = event_store.offsets().await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MailboxFull {
+                queue_depth: 1,
+                outcome: MailboxOutcome::TimedOut(t)
+            } if t == timeout
+        ));
+        assert_eq!(received.lock().len(), 1);
+
+        // the waiting request goes out as soon as the pending one is done
+        let second = tokio::spawn({
+            let event_store = event_store.clone();
+            async move { event_store.offsets().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(received.lock().len(), 1);
+        match received.lock().pop() {
+            Some(Offsets { reply }) => reply.send(Ok(SwarmOffsets::default())).unwrap(),
+            request => panic!("unexpected request {:?}", request),
+        }
+        first.await.unwrap().unwrap();
+        while received.lock().is_empty() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(event_store.pending_requests(), 1);
+        second.abort();
+    }
+
+    #[tokio::test]
+    async fn mailbox_shed_oldest() {
+        let (event_store, received) = stalled_store(2, MailboxStrategy::ShedOldest);
+        let subscribe = |event_store: &EventStoreRef| {
+            let event_store = event_store.clone();
+            tokio::spawn(async move {
+                event_store
+                    .unbounded_forward(TagExpr::from_str("'a'").unwrap(), OffsetMap::empty())
+                    .await
+            })
+        };
+        let persist = tokio::spawn({
+            let event_store = event_store.clone();
+            async move { event_store.persist(ax_types::app_id!("test"), vec![]).await }
+        });
+        wait_for_pending(&event_store, 1).await;
+        let first = subscribe(&event_store);
+        wait_for_pending(&event_store, 2).await;
+
+        // the subscription makes room, the older persist request is kept
+        let second = subscribe(&event_store);
+        let err = first.await.unwrap().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MailboxFull {
+                queue_depth: 2,
+                outcome: MailboxOutcome::Shed
+            }
+        ));
+        assert_eq!(event_store.pending_requests(), 2);
+        while received.lock().len() < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(!persist.is_finished());
+
+        // with only unsheddable requests pending, further ones are rejected
+        second.abort();
+        wait_for_pending_below(&event_store, 2).await;
+        let blocker = tokio::spawn({
+            let event_store = event_store.clone();
+            async move { event_store.offsets().await }
+        });
+        wait_for_pending(&event_store, 2).await;
+        let err = event_store
+            .persist(ax_types::app_id!("test"), vec![])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MailboxFull {
+                queue_depth: 2,
+                outcome: MailboxOutcome::Rejected
+            }
+        ));
+        assert!(!persist.is_finished());
+        persist.abort();
+        blocker.abort();
+    }
 
     #[test]
     fn error_string() {
@@ -0,0 +1,181 @@
+//! Names for file roots, recorded as events on the files stream.
+//!
+//! Publishing a name appends an event mapping it to a [`Cid`] and pins the root with an alias
+//! for as long as the name is published from this node. Names published by other nodes are
+//! resolved as well, but their blocks are not pinned here. When several nodes publish the same
+//! name, the event with the highest lamport wins, ties are broken by the stream id.
+//!
+//! The files stream is subject to pruning. Once all events for a name have been pruned from our
+//! own stream the name no longer resolves, so its root is unpinned as well.
+use crate::{
+    swarm::{BanyanStore, Tree, FILES_STREAM_NUMBER},
+    trees::{
+        query::{LamportQuery, TagExprQuery, TimeQuery},
+        tags::ScopedTagSet,
+    },
+};
+use anyhow::{ensure, Result};
+use ax_types::{tag, tags, AppId, LamportTimestamp, OffsetMap, Payload, StreamId, StreamNr, Tag, TagSet, Timestamp};
+use banyan::query::AllQuery;
+use futures::TryStreamExt;
+use libipld::Cid;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FileNameEvent {
+    Published {
+        name: String,
+        // This must not be serialized as a ipld cid!
+        #[serde(with = "crate::util::serde_str")]
+        cid: Cid,
+    },
+    Unpublished {
+        name: String,
+    },
+}
+
+struct FileNameAlias(Vec<u8>);
+impl From<&str> for FileNameAlias {
+    fn from(name: &str) -> Self {
+        Self(format!("file_name:{}", name).into_bytes())
+    }
+}
+impl AsRef<[u8]> for FileNameAlias {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+fn name_tag(name: &str) -> Tag {
+    tag!("files:name:") + name
+}
+
+impl BanyanStore {
+    /// Publish `cid` under `name` on the files stream, replacing a previous publication.
+    ///
+    /// The blocks of `cid` are pinned until the name is unpublished or published with another
+    /// root from this node.
+    pub async fn files_publish(&self, name: &str, cid: Cid, app_id: AppId) -> Result<()> {
+        ensure!(!name.is_empty(), "file name must not be empty");
+        let previous = self.ipfs().resolve(FileNameAlias::from(name))?;
+        self.ipfs().alias(FileNameAlias::from(name), Some(&cid))?;
+        let event = FileNameEvent::Published {
+            name: name.to_owned(),
+            cid,
+        };
+        if let Err(err) = self.append_file_name_event(name, &event, app_id).await {
+            // restore the previous state, nothing has been published
+            self.ipfs().alias(FileNameAlias::from(name), previous.as_ref())?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Remove `name` from the files stream, unpinning its root.
+    ///
+    /// The blocks can then be reclaimed by the next garbage collection unless they are pinned
+    /// otherwise.
+    pub async fn files_unpublish(&self, name: &str, app_id: AppId) -> Result<()> {
+        ensure!(!name.is_empty(), "file name must not be empty");
+        let event = FileNameEvent::Unpublished { name: name.to_owned() };
+        self.append_file_name_event(name, &event, app_id).await?;
+        self.ipfs().alias(FileNameAlias::from(name), None)?;
+        Ok(())
+    }
+
+    /// The root currently published under `name` by any node known to this one.
+    pub async fn files_resolve(&self, name: &str) -> Result<Option<Cid>> {
+        ensure!(!name.is_empty(), "file name must not be empty");
+        let query = TagExprQuery::new(
+            vec![ScopedTagSet::from(std::iter::once(name_tag(name)).collect::<TagSet>())],
+            LamportQuery::all(),
+            TimeQuery::all(),
+        );
+        let files_streams = self
+            .offsets()
+            .present()
+            .stream_iter()
+            .filter(|(stream_id, _)| stream_id.stream_nr() == StreamNr::from(FILES_STREAM_NUMBER))
+            .collect::<Vec<_>>();
+        let mut latest: Option<(LamportTimestamp, StreamId, FileNameEvent)> = None;
+        for (stream_id, offset) in files_streams {
+            let mut upper = OffsetMap::empty();
+            upper.update(stream_id, offset);
+            let events = self
+                .stream_filtered_stream_ordered_bounded(query.clone(), upper)
+                .try_collect::<Vec<_>>()
+                .await?;
+            for (_, key, payload) in events {
+                let Ok(event) = payload.extract::<FileNameEvent>() else {
+                    tracing::debug!(%stream_id, "ignoring malformed file name event");
+                    continue;
+                };
+                let order = (key.lamport(), stream_id);
+                if latest.as_ref().map(|(l, s, _)| (*l, *s) < order).unwrap_or(true) {
+                    latest = Some((order.0, order.1, event));
+                }
+            }
+        }
+        Ok(latest.and_then(|(_, _, event)| match event {
+            FileNameEvent::Published { cid, .. } => Some(cid),
+            FileNameEvent::Unpublished { .. } => None,
+        }))
+    }
+
+    async fn append_file_name_event(&self, name: &str, event: &FileNameEvent, app_id: AppId) -> Result<()> {
+        let tags = tags!("files", "files:name") + name_tag(name);
+        let payload = Payload::compact(event).expect("serialization works");
+        self.append0(
+            FILES_STREAM_NUMBER.into(),
+            app_id,
+            Timestamp::now(),
+            vec![(tags, payload)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Unpin the roots of names whose events are present in `before` but have all been pruned
+    /// from `after`, two versions of our own files stream.
+    ///
+    /// Roots published again in the meantime are left alone.
+    pub(crate) fn release_pruned_file_names(&self, before: &Tree, after: &Tree) -> Result<()> {
+        let mut pruned = self.file_name_roots(before)?;
+        for name in self.file_name_roots(after)?.keys() {
+            pruned.remove(name);
+        }
+        for (name, roots) in pruned {
+            let pinned = self.ipfs().resolve(FileNameAlias::from(name.as_str()))?;
+            if pinned.map(|cid| roots.contains(&cid)).unwrap_or_default() {
+                tracing::debug!(%name, "releasing pruned file name");
+                self.ipfs().alias(FileNameAlias::from(name.as_str()), None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The names with events in `tree`, with the roots published under them
+    fn file_name_roots(&self, tree: &Tree) -> Result<BTreeMap<String, BTreeSet<Cid>>> {
+        let mut names = BTreeMap::<String, BTreeSet<Cid>>::new();
+        for res in self.data.forest.iter_filtered(tree, AllQuery) {
+            let (_, _, payload) = res?;
+            match payload.extract::<FileNameEvent>() {
+                Ok(FileNameEvent::Published { name, cid }) => {
+                    names.entry(name).or_default().insert(cid);
+                }
+                Ok(FileNameEvent::Unpublished { name }) => {
+                    names.entry(name).or_default();
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(names)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_file_name_pinned(&self, name: &str) -> Result<bool> {
+        Ok(self.ipfs().resolve(FileNameAlias::from(name))?.is_some())
+    }
+}
@@ -0,0 +1,456 @@
+//! Deterministic test stores.
+//!
+//! [`BanyanStore::fixture`] seeds a store with a declared dataset and returns it together with a
+//! [`FixtureManifest`] describing exactly what has been written, so that tests across crates can
+//! share datasets and compare failures. The same seed always yields byte-identical trees.
+//!
+//! Enable the `fixture` feature to use this from other crates.
+use crate::{
+    crypto::{KeyPair, PrivateKey},
+    swarm::{BanyanStore, EphemeralEventsConfig, SwarmConfig, DEFAULT_STREAM_NUMBER},
+};
+use acto::ActoRef;
+use anyhow::Result;
+use ax_types::{
+    app_id, AppId, LamportTimestamp, NodeId, Offset, OffsetMap, Payload, StreamId, StreamNr, Tag, TagSet, Timestamp,
+};
+use libipld::Cid;
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use std::{collections::BTreeMap, ops::Range, path::PathBuf, time::Duration};
+
+/// 2020-01-01T00:00:00Z, so that fixtures do not depend on the wall clock
+const DEFAULT_START: Timestamp = Timestamp::new(1_577_836_800_000_000);
+
+/// Tags attached to the generated events.
+#[derive(Debug, Clone)]
+pub enum TagPattern {
+    /// Every event carries the same tags.
+    Fixed(TagSet),
+    /// Events cycle through the given tag sets.
+    Cycle(Vec<TagSet>),
+    /// Every event carries between one and `max` tags drawn from the pool.
+    Sample { pool: Vec<Tag>, max: usize },
+}
+
+impl TagPattern {
+    fn generate(&self, index: u64, rng: &mut impl Rng) -> TagSet {
+        match self {
+            TagPattern::Fixed(tags) => tags.clone(),
+            TagPattern::Cycle(sets) if sets.is_empty() => TagSet::empty(),
+            TagPattern::Cycle(sets) => sets[(index % sets.len() as u64) as usize].clone(),
+            TagPattern::Sample { pool, .. } if pool.is_empty() => TagSet::empty(),
+            TagPattern::Sample { pool, max } => {
+                let n = rng.gen_range(1, (*max).clamp(1, pool.len()) + 1);
+                (0..n).map(|_| pool[rng.gen_range(0, pool.len())].clone()).collect()
+            }
+        }
+    }
+}
+
+/// Payloads of the generated events.
+#[derive(Debug, Clone)]
+pub enum Payloads {
+    /// The index of the event within its stream as JSON number.
+    Counter,
+    /// An object with the event index and `size` random bytes in hex encoding.
+    Random { size: usize },
+    /// A custom generator, called with the event index and the seeded random number generator.
+    Custom(fn(u64, &mut dyn RngCore) -> Payload),
+}
+
+impl Payloads {
+    fn generate(&self, index: u64, rng: &mut StdRng) -> Payload {
+        match self {
+            Payloads::Counter => Payload::compact(&index).expect("number is serializable"),
+            Payloads::Random { size } => {
+                let mut data = vec![0u8; *size];
+                rng.fill_bytes(&mut data);
+                Payload::compact(&serde_json::json!({ "n": index, "data": hex::encode(data) }))
+                    .expect("object is serializable")
+            }
+            Payloads::Custom(f) => f(index, rng),
+        }
+    }
+}
+
+/// Progression of the event timestamps within a stream, relative to the fixture start.
+#[derive(Debug, Clone)]
+pub struct Timestamps {
+    step: Duration,
+    jitter: Duration,
+    out_of_order: Vec<(Range<u64>, Duration)>,
+}
+
+impl Timestamps {
+    /// Events are `step` apart.
+    pub fn step(step: Duration) -> Self {
+        Self {
+            step,
+            jitter: Duration::ZERO,
+            out_of_order: vec![],
+        }
+    }
+
+    /// Delay each event by a random amount of up to `jitter`.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Move the events in the given offset range back in time by `shift`.
+    pub fn out_of_order(mut self, events: Range<u64>, shift: Duration) -> Self {
+        self.out_of_order.push((events, shift));
+        self
+    }
+
+    fn generate(&self, start: Timestamp, index: u64, rng: &mut impl Rng) -> Timestamp {
+        let mut micros = (self.step.as_micros() as u64).saturating_mul(index);
+        let jitter = self.jitter.as_micros() as u64;
+        if jitter > 0 {
+            micros = micros.saturating_add(rng.gen_range(0, jitter + 1));
+        }
+        let shift = self
+            .out_of_order
+            .iter()
+            .filter(|(events, _)| events.contains(&index))
+            .fold(0u64, |sum, (_, shift)| sum.saturating_add(shift.as_micros() as u64));
+        // events shifted before the epoch are clamped to it
+        Timestamp::new(u64::from(start).saturating_add(micros).saturating_sub(shift))
+    }
+}
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Self::step(Duration::from_secs(1))
+    }
+}
+
+/// Declaration of one own stream of the fixture.
+#[derive(Debug, Clone)]
+pub struct StreamFixture {
+    stream_nr: StreamNr,
+    events: u64,
+    app_id: AppId,
+    tags: TagPattern,
+    timestamps: Timestamps,
+    payloads: Payloads,
+}
+
+impl StreamFixture {
+    /// Declare a stream with `events` events.
+    ///
+    /// Stream number 0 is reserved for the stream mappings and cannot be used.
+    pub fn new(stream_nr: impl Into<StreamNr>, events: u64) -> Self {
+        Self {
+            stream_nr: stream_nr.into(),
+            events,
+            app_id: app_id!("fixture"),
+            tags: TagPattern::Fixed(TagSet::empty()),
+            timestamps: Timestamps::default(),
+            payloads: Payloads::Counter,
+        }
+    }
+
+    pub fn app_id(mut self, app_id: AppId) -> Self {
+        self.app_id = app_id;
+        self
+    }
+
+    pub fn tags(mut self, tags: TagPattern) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    pub fn payloads(mut self, payloads: Payloads) -> Self {
+        self.payloads = payloads;
+        self
+    }
+}
+
+/// Builder for a [`BanyanStore`] seeded with a deterministic dataset, see [`BanyanStore::fixture`].
+#[derive(Debug, Clone)]
+pub struct FixtureBuilder {
+    seed: u64,
+    start: Timestamp,
+    pack: bool,
+    db_path: Option<PathBuf>,
+    streams: Vec<StreamFixture>,
+}
+
+impl FixtureBuilder {
+    /// Seed for the node identity, tags, timestamps and payloads (default: 0).
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Timestamp of the first event in each stream (default: 2020-01-01T00:00:00Z).
+    pub fn start(mut self, start: Timestamp) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Pack the trees after writing all events instead of leaving them as appended.
+    pub fn packed(mut self, pack: bool) -> Self {
+        self.pack = pack;
+        self
+    }
+
+    /// Persist the store at the given path instead of keeping it in memory.
+    pub fn db_path(mut self, db_path: impl Into<PathBuf>) -> Self {
+        self.db_path = Some(db_path.into());
+        self
+    }
+
+    pub fn stream(mut self, stream: StreamFixture) -> Self {
+        self.streams.push(stream);
+        self
+    }
+
+    /// Create the store and write the declared events, one stream after the other.
+    pub async fn build(self) -> Result<(BanyanStore, FixtureManifest)> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut secret = [0u8; 32];
+        rng.fill_bytes(&mut secret);
+        let keypair = KeyPair::from(PrivateKey::from_bytes(&secret)?);
+
+        let cfg = SwarmConfig {
+            keypair: Some(keypair),
+            db_path: self.db_path,
+            enable_discovery: false,
+            enable_metrics: false,
+            ephemeral_event_config: EphemeralEventsConfig::Disabled,
+            ..SwarmConfig::test("fixture")
+        };
+        let store = BanyanStore::new(cfg, ActoRef::blackhole()).await?;
+        // these would rewrite the trees at unpredictable times
+        store.abort_task("compaction");
+        store.abort_task("prune_events");
+
+        let mut manifest = FixtureManifest {
+            seed: self.seed,
+            node_id: store.node_id(),
+            streams: BTreeMap::new(),
+        };
+        for spec in self.streams {
+            anyhow::ensure!(
+                spec.stream_nr != StreamNr::from(DEFAULT_STREAM_NUMBER),
+                "stream {} is reserved for the stream mappings",
+                DEFAULT_STREAM_NUMBER
+            );
+            let stream_id = store.node_id().stream(spec.stream_nr);
+            anyhow::ensure!(
+                !manifest.streams.contains_key(&stream_id),
+                "stream {} declared twice",
+                spec.stream_nr
+            );
+
+            let mut events = Vec::with_capacity(spec.events as usize);
+            for index in 0..spec.events {
+                let tags = spec.tags.generate(index, &mut rng);
+                let timestamp = spec.timestamps.generate(self.start, index, &mut rng);
+                let payload = spec.payloads.generate(index, &mut rng);
+                let meta = store
+                    .append0(
+                        spec.stream_nr,
+                        spec.app_id.clone(),
+                        timestamp,
+                        vec![(tags.clone(), payload.clone())],
+                    )
+                    .await?;
+                events.push(FixtureEvent {
+                    offset: meta.min_offset,
+                    lamport: meta.min_lamport,
+                    timestamp,
+                    app_id: spec.app_id.clone(),
+                    tags,
+                    payload,
+                });
+            }
+
+            let stream = store.get_or_create_own_stream(spec.stream_nr)?;
+            let mut guard = stream.lock().await;
+            if self.pack {
+                store.transform_stream(&mut guard, |txn, tree| txn.pack(tree))?;
+            }
+            let root = guard.snapshot().link().map(Cid::from);
+            manifest.streams.insert(
+                stream_id,
+                StreamManifest {
+                    stream_nr: spec.stream_nr,
+                    root,
+                    packed: self.pack,
+                    events,
+                },
+            );
+        }
+        Ok((store, manifest))
+    }
+}
+
+/// Everything a fixture has written, for use in assertions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureManifest {
+    pub seed: u64,
+    pub node_id: NodeId,
+    pub streams: BTreeMap<StreamId, StreamManifest>,
+}
+
+impl FixtureManifest {
+    /// Offsets of the last event in each non-empty fixture stream.
+    pub fn offsets(&self) -> OffsetMap {
+        self.streams
+            .iter()
+            .filter_map(|(stream_id, stream)| Some((*stream_id, stream.events.last()?.offset)))
+            .collect::<BTreeMap<_, _>>()
+            .into()
+    }
+
+    /// All events of all fixture streams, ordered by stream.
+    pub fn events(&self) -> impl Iterator<Item = (StreamId, &FixtureEvent)> + '_ {
+        self.streams
+            .iter()
+            .flat_map(|(stream_id, stream)| stream.events.iter().map(move |event| (*stream_id, event)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamManifest {
+    pub stream_nr: StreamNr,
+    /// Root of the tree after writing (and possibly packing), `None` for an empty stream
+    pub root: Option<Cid>,
+    pub packed: bool,
+    pub events: Vec<FixtureEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureEvent {
+    pub offset: Offset,
+    pub lamport: LamportTimestamp,
+    pub timestamp: Timestamp,
+    pub app_id: AppId,
+    pub tags: TagSet,
+    pub payload: Payload,
+}
+
+impl BanyanStore {
+    /// Start declaring a deterministic test store, see the [`fixture`](crate::swarm::fixture) module.
+    pub fn fixture() -> FixtureBuilder {
+        FixtureBuilder {
+            seed: 0,
+            start: DEFAULT_START,
+            pack: false,
+            db_path: None,
+            streams: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::{tag, tags};
+    use banyan::query::AllQuery;
+
+    fn builder(seed: u64) -> FixtureBuilder {
+        BanyanStore::fixture()
+            .seed(seed)
+            .stream(
+                StreamFixture::new(1, 100)
+                    .tags(TagPattern::Cycle(vec![tags!("a"), tags!("a", "b")]))
+                    .timestamps(Timestamps::step(Duration::from_millis(10)).jitter(Duration::from_millis(5)))
+                    .payloads(Payloads::Random { size: 16 }),
+            )
+            .stream(
+                StreamFixture::new(4, 50)
+                    .app_id(app_id!("other"))
+                    .tags(TagPattern::Sample {
+                        pool: vec![tag!("x"), tag!("y"), tag!("z")],
+                        max: 2,
+                    })
+                    .timestamps(Timestamps::default().out_of_order(10..20, Duration::from_secs(60))),
+            )
+    }
+
+    #[tokio::test]
+    async fn same_seed_same_trees() {
+        let (_store1, manifest1) = builder(42).build().await.unwrap();
+        let (_store2, manifest2) = builder(42).build().await.unwrap();
+        assert_eq!(manifest1, manifest2);
+        assert!(manifest1.streams.values().all(|s| s.root.is_some()));
+
+        let (_store3, manifest3) = builder(43).build().await.unwrap();
+        assert_ne!(manifest1.node_id, manifest3.node_id);
+        assert_ne!(
+            manifest1.events().map(|(_, e)| &e.payload).collect::<Vec<_>>(),
+            manifest3.events().map(|(_, e)| &e.payload).collect::<Vec<_>>()
+        );
+
+        let (_packed1, packed1) = builder(42).packed(true).build().await.unwrap();
+        let (_packed2, packed2) = builder(42).packed(true).build().await.unwrap();
+        assert_eq!(packed1, packed2);
+        assert_ne!(
+            packed1.streams.values().map(|s| s.root).collect::<Vec<_>>(),
+            manifest1.streams.values().map(|s| s.root).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn manifest_matches_store() {
+        let (store, manifest) = builder(7).packed(true).build().await.unwrap();
+        let present = store.data.offsets.project(|x| x.present.clone());
+        let offsets = manifest.offsets();
+        for stream_id in offsets.streams() {
+            assert_eq!(present.offset(stream_id), offsets.offset(stream_id));
+        }
+
+        for (stream_id, stream) in &manifest.streams {
+            assert_eq!(stream.events.len(), if stream.stream_nr == 1.into() { 100 } else { 50 });
+            let tree = store
+                .get_or_create_own_stream(stream.stream_nr)
+                .unwrap()
+                .lock()
+                .await
+                .snapshot();
+            assert_eq!(tree.link().map(Cid::from), stream.root);
+            let actual = store
+                .data
+                .forest
+                .iter_filtered(&tree, AllQuery)
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(actual.len(), stream.events.len());
+            for ((offset, key, payload), expected) in actual.into_iter().zip(&stream.events) {
+                assert_eq!(Offset::try_from(offset).unwrap(), expected.offset);
+                assert_eq!(key.lamport(), expected.lamport);
+                assert_eq!(key.time(), expected.timestamp);
+                assert_eq!(key.app_id().as_ref(), Some(&expected.app_id));
+                assert_eq!(key.into_app_tags(), expected.tags);
+                assert_eq!(payload, expected.payload);
+            }
+        }
+
+        let out_of_order = &manifest.streams[&store.node_id().stream(4.into())].events;
+        assert!(out_of_order[10].timestamp < out_of_order[9].timestamp);
+    }
+
+    #[test]
+    fn shift_before_epoch() {
+        let timestamps = Timestamps::step(Duration::from_secs(1)).out_of_order(0..2, Duration::from_secs(10));
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = Timestamp::new(5_000_000);
+        assert_eq!(timestamps.generate(start, 0, &mut rng), Timestamp::new(0));
+        assert_eq!(timestamps.generate(start, 2, &mut rng), Timestamp::new(7_000_000));
+    }
+
+    #[tokio::test]
+    async fn stream_zero_is_reserved() {
+        let res = BanyanStore::fixture().stream(StreamFixture::new(0, 1)).build().await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Grace period for the roots superseded by moving a stream alias.
+//!
+//! The block garbage collection of ipfs-embed keeps only what is reachable from an alias or a temp
+//! pin. A peer may however still be syncing the previous root of a stream, having learned about it
+//! just before the alias moved on, and would fail with missing blocks. The previous root therefore
+//! stays temp pinned for at least
+//! [`SwarmConfig::gc_grace_period`](crate::swarm::SwarmConfig::gc_grace_period).
+//!
+//! Frequent appends supersede many roots, so the roots of a stream share one temp pin: a pin takes
+//! the roots superseded during one grace period and is released one grace period after that, which
+//! leaves at most two pins per stream.
+use crate::swarm::{BanyanStore, BanyanStoreData, StreamAlias};
+use anyhow::Result;
+use ax_types::{StreamId, Timestamp};
+use ipfs_embed::{Cid, TempPin};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The default grace period is this multiple of the `bitswap_timeout`
+pub(crate) const GC_GRACE_FACTOR: u32 = 4;
+
+/// A root that is no longer aliased for its stream but kept, see [`BanyanStore::gc_grace_roots`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupersededRoot {
+    pub stream_id: StreamId,
+    pub root: Cid,
+    pub superseded_at: Timestamp,
+}
+
+/// The roots of one stream superseded during one grace period, starting at `opened`
+struct Batch {
+    stream_id: StreamId,
+    opened: Instant,
+    roots: Vec<SupersededRoot>,
+    pin: TempPin,
+}
+
+pub(crate) struct GcGrace {
+    period: Duration,
+    /// oldest first
+    batches: Mutex<VecDeque<Batch>>,
+}
+
+impl GcGrace {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            batches: Default::default(),
+        }
+    }
+
+    /// Drops the pins of the batches that no longer take roots and whose roots have all been kept
+    /// for the grace period, returns the number of released roots.
+    fn release_expired(&self, now: Instant) -> usize {
+        let mut batches = self.batches.lock();
+        let mut released = 0;
+        while matches!(batches.front(), Some(batch) if batch.opened + 2 * self.period <= now) {
+            released += batches.pop_front().map_or(0, |batch| batch.roots.len());
+        }
+        released
+    }
+}
+
+impl BanyanStoreData {
+    /// Points the alias of the stream to `root`, keeping the previous root pinned for the grace
+    /// period.
+    pub(crate) fn set_stream_alias(&self, stream_id: StreamId, root: Option<&Cid>) -> Result<()> {
+        let previous = self.ipfs.resolve(StreamAlias::from(stream_id))?;
+        if let Some(previous) = previous.filter(|previous| Some(previous) != root) {
+            if !self.gc_grace.period.is_zero() {
+                let now = Instant::now();
+                let superseded = SupersededRoot {
+                    stream_id,
+                    root: previous,
+                    superseded_at: Timestamp::now(),
+                };
+                let mut batches = self.gc_grace.batches.lock();
+                let open = batches
+                    .iter_mut()
+                    .rev()
+                    .take_while(|batch| batch.opened + self.gc_grace.period > now)
+                    .find(|batch| batch.stream_id == stream_id);
+                // pinned before the alias moves, so the garbage collection cannot fall in between
+                if let Some(batch) = open {
+                    self.ipfs.temp_pin(&mut batch.pin, &previous)?;
+                    batch.roots.push(superseded);
+                } else {
+                    let mut pin = self.ipfs.create_temp_pin()?;
+                    self.ipfs.temp_pin(&mut pin, &previous)?;
+                    batches.push_back(Batch {
+                        stream_id,
+                        opened: now,
+                        roots: vec![superseded],
+                        pin,
+                    });
+                }
+            }
+        }
+        self.ipfs.alias(StreamAlias::from(stream_id), root)?;
+        Ok(())
+    }
+}
+
+impl BanyanStore {
+    /// The superseded roots currently kept from garbage collection, oldest first
+    pub fn gc_grace_roots(&self) -> Vec<SupersededRoot> {
+        let batches = self.data.gc_grace.batches.lock();
+        let mut roots = batches
+            .iter()
+            .flat_map(|batch| batch.roots.iter().cloned())
+            .collect::<Vec<_>>();
+        roots.sort_by_key(|root| root.superseded_at);
+        roots
+    }
+
+    /// Number of temp pins held for superseded roots
+    #[cfg(test)]
+    pub(crate) fn gc_grace_pins(&self) -> usize {
+        self.data.gc_grace.batches.lock().len()
+    }
+}
+
+/// Periodically releases the superseded roots whose grace period has passed.
+pub(crate) async fn release_superseded_roots(store: BanyanStore) {
+    let interval = (store.data.gc_grace.period / 4).clamp(Duration::from_millis(100), Duration::from_secs(10));
+    loop {
+        tokio::time::sleep(interval).await;
+        let released = store.data.gc_grace.release_expired(Instant::now());
+        if released > 0 {
+            tracing::debug!("released {} superseded roots", released);
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use crate::{
-    ax_futures_util::stream::ready_iter,
+    ax_futures_util::stream::{ready_iter, variable::Variable},
+    crypto::peer_id_to_node_id,
     swarm::{
-        gossip_protocol::{GossipMessage, RootMap, RootUpdate},
-        BanyanStore, Ipfs, Link, RootPath, RootSource,
+        gossip_protocol::{GossipMessage, RootMap, RootUpdate, ROOT_MAP_VERSION},
+        heartbeats::own_heartbeats,
+        peer_events::PeerEvent,
+        swarm_metrics::SwarmMetrics,
+        transfer::TransferStats,
+        BanyanStore, Ipfs, Link, RootPath, RootSource, StoreParams,
     },
 };
 use acto::ActoRef;
@@ -13,18 +18,230 @@ use cbor_data::{
     Cbor, CborBuilder,
 };
 use futures::{
-    channel::mpsc::{unbounded, UnboundedSender},
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    future,
     prelude::*,
 };
 use ipfs_embed::{GossipEvent, PeerId};
 use libipld::Cid;
+use parking_lot::Mutex;
 use std::{
     collections::{BTreeMap, BTreeSet},
     convert::TryFrom,
+    sync::Arc,
     time::Duration,
 };
+use tokio::{sync::Notify, time::Instant};
 
-const MAX_BROADCAST_BYTES: usize = 1_000_000;
+/// Blocks sent along with a fast path root update, leaving room for the message envelope
+const MAX_BROADCAST_BYTES: usize = <StoreParams as libipld::store::StoreParams>::MAX_BLOCK_SIZE / 2;
+
+/// Number of peers at which the root map publication jitter reaches its maximum
+const JITTER_FULL_SWARM: usize = 100;
+
+/// Time for a newly connected peer to announce its topic subscriptions before the outbox is replayed
+const OUTBOX_SETTLE: Duration = Duration::from_millis(500);
+
+/// Schedule for publishing the root map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootMapCadence {
+    /// Publish the root map at a fixed interval, regardless of changes.
+    Fixed(Duration),
+    /// Publish the root map `debounce` after one of our own streams changed. While nothing
+    /// changes the interval starts at `min` and doubles up to `max`.
+    Adaptive {
+        debounce: Duration,
+        min: Duration,
+        max: Duration,
+    },
+}
+
+impl RootMapCadence {
+    pub fn adaptive(min: Duration, max: Duration) -> Self {
+        if max <= min {
+            Self::Fixed(min)
+        } else {
+            Self::Adaptive {
+                debounce: Duration::from_millis(500),
+                min,
+                max,
+            }
+        }
+    }
+
+    /// The interval to use right after a publication caused by a change
+    fn initial(&self) -> Duration {
+        match self {
+            Self::Fixed(interval) => *interval,
+            Self::Adaptive { min, .. } => *min,
+        }
+    }
+
+    /// The interval to use after an idle publication following one of length `current`
+    fn back_off(&self, current: Duration) -> Duration {
+        match self {
+            Self::Fixed(interval) => *interval,
+            Self::Adaptive { min, max, .. } => (current * 2).clamp(*min, *max),
+        }
+    }
+}
+
+/// Stretch the interval by a random amount that grows with the swarm size, so that
+/// the root map publications of many nodes don’t synchronize.
+///
+/// `random` is expected to be in the range `[0, 1)`.
+fn with_jitter(interval: Duration, n_peers: usize, random: f64) -> Duration {
+    let spread = n_peers.min(JITTER_FULL_SWARM) as f64 / JITTER_FULL_SWARM as f64 / 2.0;
+    interval + interval.mul_f64(spread * random)
+}
+
+/// A rule of the [`PublishPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishRule {
+    /// The topic name, or its prefix if ending in `*`
+    pub pattern: String,
+    pub allow: bool,
+}
+
+impl PublishRule {
+    pub fn allow(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            allow: true,
+        }
+    }
+
+    pub fn deny(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            allow: false,
+        }
+    }
+
+    fn matches(&self, topic: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) => topic.starts_with(prefix),
+            None => topic == self.pattern,
+        }
+    }
+}
+
+/// Which topics this node may publish and broadcast gossip on, subscribing is not restricted.
+///
+/// The default allows all topics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishPolicy {
+    /// Rules in order of precedence
+    pub rules: Vec<PublishRule>,
+    /// Whether topics not matched by any rule are allowed
+    pub default: bool,
+}
+
+impl Default for PublishPolicy {
+    fn default() -> Self {
+        Self {
+            rules: vec![],
+            default: true,
+        }
+    }
+}
+
+/// Publishing or broadcasting on a topic denied by the [`PublishPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[display(fmt = "publishing on topic `{}` is denied by the publish policy", topic)]
+pub struct PublishDenied {
+    pub topic: String,
+}
+
+impl PublishPolicy {
+    pub fn allows(&self, topic: &str) -> bool {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(topic))
+            .map_or(self.default, |rule| rule.allow)
+    }
+
+    pub fn check(&self, topic: &str) -> Result<(), PublishDenied> {
+        if self.allows(topic) {
+            Ok(())
+        } else {
+            Err(PublishDenied {
+                topic: topic.to_owned(),
+            })
+        }
+    }
+
+    /// Publish via gossipsub, if the policy allows the topic
+    pub(crate) async fn publish(&self, ipfs: &mut Ipfs, topic: &str, blob: Vec<u8>) -> Result<()> {
+        self.check(topic)?;
+        ipfs.publish(topic.to_owned(), blob).await
+    }
+
+    /// Broadcast to all connected peers, if the policy allows the topic
+    pub(crate) async fn broadcast(&self, ipfs: &mut Ipfs, topic: &str, blob: Vec<u8>) -> Result<()> {
+        self.check(topic)?;
+        ipfs.broadcast(topic.to_owned(), blob).await
+    }
+}
+
+/// Traffic received on a topic the swarm was previously known under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviousTopic {
+    pub topic: String,
+    /// Number of gossip messages received on the topic since the node started
+    pub messages: u64,
+    pub last_received: Option<Timestamp>,
+    /// Time until the subscription to the topic ends, zero once it has ended
+    pub remaining: Duration,
+}
+
+#[derive(Debug)]
+struct PreviousTopicState {
+    until: Instant,
+    messages: u64,
+    last_received: Option<Timestamp>,
+}
+
+/// Shared message counters for the receive-only subscriptions to previous topics.
+///
+/// Once no more messages arrive on a previous topic, all nodes have been migrated and the topic
+/// can be dropped from the configuration.
+#[derive(Debug, Clone, Default)]
+pub struct PreviousTopics(Arc<Mutex<BTreeMap<String, PreviousTopicState>>>);
+
+impl PreviousTopics {
+    fn track(&self, topic: &str, grace: Duration) {
+        self.0.lock().insert(
+            topic.to_owned(),
+            PreviousTopicState {
+                until: Instant::now() + grace,
+                messages: 0,
+                last_received: None,
+            },
+        );
+    }
+
+    fn received(&self, topic: &str) {
+        if let Some(state) = self.0.lock().get_mut(topic) {
+            state.messages += 1;
+            state.last_received = Some(Timestamp::now());
+        }
+    }
+
+    pub fn get(&self) -> Vec<PreviousTopic> {
+        let now = Instant::now();
+        self.0
+            .lock()
+            .iter()
+            .map(|(topic, state)| PreviousTopic {
+                topic: topic.clone(),
+                messages: state.messages,
+                last_received: state.last_received,
+                remaining: state.until.saturating_duration_since(now),
+            })
+            .collect()
+    }
+}
 
 /// Update when we have rewritten a tree
 #[derive(Debug)]
@@ -36,28 +253,113 @@ struct PublishUpdate {
     offset: Offset,
 }
 
+impl PublishUpdate {
+    /// Replace this update with a newer one for the same stream, keeping the blocks of both.
+    fn supersede(&mut self, newer: PublishUpdate) {
+        let links = std::mem::take(&mut self.links);
+        *self = newer;
+        self.links.extend(links);
+    }
+}
+
+/// Wait for the next updates to publish, keeping only the latest root per stream.
+///
+/// Once an update arrives, further updates are collected for `batch` so that a burst of appends
+/// is published once per stream.
+async fn next_batch(
+    rx: &mut UnboundedReceiver<PublishUpdate>,
+    batch: Duration,
+) -> Option<BTreeMap<StreamNr, PublishUpdate>> {
+    let mut updates = BTreeMap::<StreamNr, PublishUpdate>::new();
+    let mut add = |update: PublishUpdate| match updates.get_mut(&update.stream) {
+        Some(existing) => existing.supersede(update),
+        None => {
+            updates.insert(update.stream, update);
+        }
+    };
+    ready_iter(rx).await?.for_each(&mut add);
+    if batch > Duration::ZERO {
+        tokio::time::sleep(batch).await;
+        while let Ok(Some(update)) = rx.try_next() {
+            add(update);
+        }
+    }
+    Some(updates)
+}
+
+/// The latest root of an own stream that was published while no peer was connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OutboxEntry {
+    root: Cid,
+    lamport: LamportTimestamp,
+    offset: Offset,
+}
+
+/// Roots of own streams that no peer has seen yet, replayed on the next peer connection.
+///
+/// Only the latest root per stream is kept, so the outbox is bounded by the number of own streams.
+#[derive(Debug, Clone, Default)]
+struct Outbox(Arc<Mutex<BTreeMap<StreamNr, OutboxEntry>>>);
+
+impl Outbox {
+    /// Record the publication of a root, which is pending unless a peer was connected.
+    fn published(&self, stream: StreamNr, entry: OutboxEntry, delivered: bool) {
+        let mut outbox = self.0.lock();
+        if delivered {
+            outbox.remove(&stream);
+        } else {
+            outbox.insert(stream, entry);
+        }
+    }
+
+    fn pending(&self) -> BTreeMap<StreamNr, OutboxEntry> {
+        self.0.lock().clone()
+    }
+
+    /// Remove an entry after it has been replayed, unless a newer root arrived in the meantime.
+    fn replayed(&self, stream: StreamNr, entry: OutboxEntry) {
+        let mut outbox = self.0.lock();
+        if outbox.get(&stream) == Some(&entry) {
+            outbox.remove(&stream);
+        }
+    }
+}
+
 pub struct Gossip {
     tx: UnboundedSender<PublishUpdate>,
     publish_handle: tokio::task::JoinHandle<()>,
+    /// signalled whenever one of our own streams got a new root
+    changed: Arc<Notify>,
+    /// the interval until the next root map publication, as last computed
+    root_map_interval: Variable<Duration>,
+    /// roots published while no peer was connected
+    outbox: Outbox,
+    enable_fast_path: bool,
+    policy: PublishPolicy,
 }
 
 impl Gossip {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         mut ipfs: Ipfs,
         node_id: NodeId,
         topic: String,
         enable_fast_path: bool,
         enable_slow_path: bool,
+        fast_path_batch: Duration,
+        policy: PublishPolicy,
         swarm_observer: ActoRef<(PeerId, GossipMessage)>,
+        transfers: TransferStats,
+        metrics: SwarmMetrics,
     ) -> Self {
         let (tx, mut rx) = unbounded::<PublishUpdate>();
+        let outbox = Outbox::default();
+        let outbox2 = outbox.clone();
+        let policy2 = policy.clone();
         let publish_task = async move {
             let mut cbor_scratch = Vec::new();
 
-            while let Some(updates) = ready_iter(&mut rx).await {
-                // drain the channel and only publish the latest update per stream
-                let updates = updates.map(|up| (up.stream, up)).collect::<BTreeMap<_, _>>();
-
+            while let Some(updates) = next_batch(&mut rx, fast_path_batch).await {
                 for (_, update) in updates {
                     let _s = tracing::trace_span!("publishing", stream = %update.stream);
                     let _s = _s.enter();
@@ -80,6 +382,11 @@ impl Gossip {
                         }
                     }
                     tracing::trace!(bytes = size, blocks = blocks.len());
+                    outbox2.published(
+                        update.stream,
+                        OutboxEntry { root, lamport, offset },
+                        !ipfs.connections().is_empty(),
+                    );
 
                     swarm_observer.send((
                         ipfs.local_peer_id(),
@@ -94,6 +401,7 @@ impl Gossip {
                     ));
 
                     if enable_fast_path {
+                        let n_blocks = blocks.len();
                         let root_update = RootUpdate {
                             stream,
                             root,
@@ -106,8 +414,16 @@ impl Gossip {
                             .write_cbor(CborBuilder::with_scratch_space(&mut cbor_scratch))
                             .into_vec();
                         tracing::trace!("broadcast_blob {} {}", stream, blob.len());
-                        if let Err(err) = ipfs.broadcast(topic.clone(), blob).await {
+                        if let Err(err) = policy2.broadcast(&mut ipfs, &topic, blob).await {
                             tracing::error!("broadcast failed: {}", err);
+                        } else {
+                            metrics.gossip_sent();
+                            if n_blocks > 0 {
+                                // the broadcast goes to all connected peers subscribed to the topic
+                                let peers = ipfs.connections().into_iter().map(|c| c.0).collect::<BTreeSet<_>>();
+                                metrics.sent(peers.len(), n_blocks, size);
+                                transfers.sent(peers, n_blocks, size);
+                            }
                         }
                     }
 
@@ -127,8 +443,10 @@ impl Gossip {
                             .write_cbor(CborBuilder::with_scratch_space(&mut cbor_scratch))
                             .into_vec();
                         tracing::trace!(%stream, %topic, "publish_blob len {}", blob.len());
-                        if let Err(err) = ipfs.publish(topic.clone(), blob).await {
+                        if let Err(err) = policy2.publish(&mut ipfs, &topic, blob).await {
                             tracing::error!(%stream, %topic, "publish failed: {}", err);
+                        } else {
+                            metrics.gossip_sent();
                         }
                     }
                 }
@@ -138,9 +456,24 @@ impl Gossip {
         Self {
             tx,
             publish_handle: tokio::spawn(publish_task),
+            changed: Arc::new(Notify::new()),
+            root_map_interval: Variable::new(Duration::ZERO),
+            outbox,
+            enable_fast_path,
+            policy,
         }
     }
 
+    /// The policy consulted before publishing or broadcasting on any topic
+    pub fn publish_policy(&self) -> &PublishPolicy {
+        &self.policy
+    }
+
+    /// The current interval of the root map publication (zero if not publishing)
+    pub fn root_map_interval(&self) -> Duration {
+        self.root_map_interval.get()
+    }
+
     pub fn publish(
         &self,
         stream: StreamNr,
@@ -156,6 +489,8 @@ impl Gossip {
             lamport,
             offset,
         })?;
+        // the root map is not subject to the batching of the fast path
+        self.changed.notify_one();
         Ok(())
     }
 
@@ -163,14 +498,33 @@ impl Gossip {
         &self,
         store: BanyanStore,
         topic: String,
-        interval: Duration,
         swarm_observer: ActoRef<(PeerId, GossipMessage)>,
     ) -> impl Future<Output = ()> {
         let mut ipfs = store.ipfs().clone();
+        let changed = self.changed.clone();
+        let root_map_interval = self.root_map_interval.clone();
+        let policy = self.policy.clone();
         async move {
             let mut cbor_scratch = Vec::new();
+            let mut interval = store.data.settings.project(|s| s.cadence_root_map).initial();
+            let mut last_published = BTreeMap::new();
             loop {
-                tokio::time::sleep(interval).await;
+                // the cadence may have been changed since the last publication
+                let cadence = store.data.settings.project(|s| s.cadence_root_map);
+                let wait = with_jitter(interval, ipfs.peers().len(), rand::random());
+                root_map_interval.set(wait);
+                match cadence {
+                    RootMapCadence::Fixed(_) => tokio::time::sleep(wait).await,
+                    RootMapCadence::Adaptive { debounce, .. } => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(wait) => {}
+                            _ = changed.notified() => {
+                                // let a burst of appends settle before publishing
+                                tokio::time::sleep(debounce).await;
+                            }
+                        }
+                    }
+                }
                 let _s = tracing::trace_span!("publish_root_map");
                 let _s = _s.enter();
                 let guard = store.lock();
@@ -178,6 +532,14 @@ impl Gossip {
                 let lamport = guard.data.lamport.get();
                 drop(guard);
 
+                let heartbeats = own_heartbeats(store.node_id(), &root_map, &last_published, lamport);
+                interval = if root_map == last_published {
+                    cadence.back_off(interval)
+                } else {
+                    cadence.initial()
+                };
+                last_published = root_map.clone();
+
                 let n_entries = root_map.len();
                 let mut offsets = Vec::with_capacity(n_entries);
                 let entries = root_map
@@ -190,8 +552,10 @@ impl Gossip {
 
                 let time = Timestamp::now();
                 let msg = GossipMessage::RootMap(RootMap {
+                    version: ROOT_MAP_VERSION,
                     entries,
                     offsets,
+                    heartbeats,
                     lamport,
                     time,
                 });
@@ -199,10 +563,66 @@ impl Gossip {
                 let blob = msg
                     .write_cbor(CborBuilder::with_scratch_space(&mut cbor_scratch))
                     .into_vec();
-                if let Err(err) = ipfs.publish(topic.clone(), blob).await {
+                if let Err(err) = policy.publish(&mut ipfs, &topic, blob).await {
                     tracing::error!("publish root map failed: {}", err);
                 } else {
-                    tracing::debug!("published {} entries at lamport {}", n_entries, lamport,);
+                    store.data.swarm_metrics.gossip_sent();
+                    tracing::debug!(
+                        "published {} entries at lamport {}, next in {:?}",
+                        n_entries,
+                        lamport,
+                        interval
+                    );
+                }
+            }
+        }
+    }
+
+    /// Re-publish the roots that were published while no peer was connected whenever a peer
+    /// connects, so that it learns about them without waiting for the next root map.
+    ///
+    /// The updates carry no blocks, the peer decides whether to sync the stream.
+    pub fn replay_outbox(&self, store: BanyanStore, topic: String) -> impl Future<Output = ()> {
+        let mut ipfs = store.ipfs().clone();
+        let outbox = self.outbox.clone();
+        let enable_fast_path = self.enable_fast_path;
+        let policy = self.policy.clone();
+        let node_id = store.node_id();
+        let mut peer_events = store.peer_events();
+        async move {
+            let mut cbor_scratch = Vec::new();
+            while let Some(event) = peer_events.next().await {
+                let PeerEvent::Connected { peer, .. } = event else {
+                    continue;
+                };
+                if outbox.pending().is_empty() {
+                    continue;
+                }
+                tokio::time::sleep(OUTBOX_SETTLE).await;
+                for (stream_nr, entry) in outbox.pending() {
+                    let stream = node_id.stream(stream_nr);
+                    tracing::debug!(%stream, %peer, "replaying root {}", entry.root);
+                    let blob = GossipMessage::RootUpdate(RootUpdate {
+                        stream,
+                        root: entry.root,
+                        blocks: vec![],
+                        lamport: entry.lamport,
+                        time: Timestamp::now(),
+                        offset: Some(entry.offset),
+                    })
+                    .write_cbor(CborBuilder::with_scratch_space(&mut cbor_scratch))
+                    .into_vec();
+                    let result = if enable_fast_path {
+                        policy.broadcast(&mut ipfs, &topic, blob).await
+                    } else {
+                        policy.publish(&mut ipfs, &topic, blob).await
+                    };
+                    if let Err(err) = result {
+                        tracing::warn!(%stream, "replaying root failed: {}", err);
+                    } else {
+                        store.data.swarm_metrics.gossip_sent();
+                        outbox.replayed(stream_nr, entry);
+                    }
                 }
             }
         }
@@ -214,29 +634,107 @@ impl Gossip {
         swarm_observer: ActoRef<(PeerId, GossipMessage)>,
     ) -> Result<impl Future<Output = ()>> {
         let mut ipfs = store.ipfs().clone();
-        let mut subscription = ipfs.subscribe(topic.clone()).await?;
+        let messages = ipfs.subscribe(topic).await?.filter_map(|event| {
+            future::ready(match event {
+                GossipEvent::Message(sender, message) => Some((sender, message)),
+                _ => None,
+            })
+        });
+        Ok(Self::ingest_messages(store, messages, swarm_observer, decode_gossip))
+    }
+
+    /// Receive-only subscription to a topic the swarm was previously known under.
+    ///
+    /// Gossip arriving on `topic` is ingested just like gossip on the current topic, but nothing is
+    /// published there. This lets migrated nodes keep replicating from nodes still configured with
+    /// the previous topic; the other direction stops working as soon as a node is migrated. The
+    /// subscription is dropped after `grace`.
+    pub async fn ingest_previous(
+        store: BanyanStore,
+        topic: String,
+        grace: Duration,
+        swarm_observer: ActoRef<(PeerId, GossipMessage)>,
+    ) -> Result<impl Future<Output = ()>> {
+        let mut ipfs = store.ipfs().clone();
+        let previous = store.previous_topics().clone();
+        previous.track(&topic, grace);
+        let counter = previous.clone();
+        let name = topic.clone();
+        let messages = ipfs.subscribe(topic.clone()).await?.filter_map(move |event| {
+            future::ready(match event {
+                GossipEvent::Message(sender, message) => {
+                    counter.received(&name);
+                    Some((sender, message))
+                }
+                _ => None,
+            })
+        });
         Ok(async move {
-            while let Some(event) = subscription.next().await {
-                let (peer_id, message) = if let GossipEvent::Message(sender, message) = event {
-                    (sender, message)
-                } else {
+            let ingest = Self::ingest_messages(store, messages, swarm_observer, decode_gossip);
+            if tokio::time::timeout(grace, ingest).await.is_ok() {
+                tracing::warn!(%topic, "subscription to previous topic ended early");
+            } else {
+                let messages = previous
+                    .get()
+                    .into_iter()
+                    .find(|t| t.topic == topic)
+                    .map(|t| t.messages)
+                    .unwrap_or_default();
+                tracing::info!(%topic, messages, "grace period for previous topic is over, unsubscribing");
+            }
+            // store tasks are not supposed to end
+            future::pending::<()>().await
+        })
+    }
+
+    /// Decode and process the messages, unless they come from a quarantined peer.
+    ///
+    /// Messages that fail to decode or to pass the configured [`GossipValidationConfig`] count as
+    /// violations towards quarantining their sender.
+    ///
+    /// [`GossipValidationConfig`]: crate::swarm::GossipValidationConfig
+    async fn ingest_messages<M: AsRef<[u8]>>(
+        store: BanyanStore,
+        messages: impl Stream<Item = (PeerId, M)>,
+        swarm_observer: ActoRef<(PeerId, GossipMessage)>,
+        decode: impl Fn(&[u8]) -> Result<GossipMessage, CodecError>,
+    ) {
+        futures::pin_mut!(messages);
+        while let Some((peer_id, message)) = messages.next().await {
+            store.data.swarm_metrics.gossip_received();
+            if store.quarantine().is_quarantined(&peer_id) {
+                tracing::trace!("dropping gossip from quarantined peer {}", peer_id);
+                continue;
+            }
+            let decoded = decode(message.as_ref());
+            if let Ok(message) = &decoded {
+                if let Err(err) = store.data.gossip_validation.check(peer_id, message) {
+                    tracing::debug!("dropping gossip message from {}: {}", peer_id, err);
+                    store
+                        .quarantine()
+                        .record_violation(peer_id, format_args!("invalid gossip message: {}", err));
                     continue;
-                };
-                match Cbor::checked(&message)
-                    .map_err(CodecError::custom)
-                    .and_then(GossipMessage::read_cbor)
-                {
-                    Ok(GossipMessage::RootUpdate(root_update)) => {
-                        swarm_observer.send((peer_id, GossipMessage::RootUpdate(root_update.clone_without_blocks())));
-                        let _s = tracing::trace_span!("root update", root = %root_update.root);
-                        let _s = _s.enter();
-                        tracing::debug!(
-                            "from {} with {} blocks, lamport: {}, offset: {:?}",
-                            root_update.stream,
-                            root_update.blocks.len(),
-                            root_update.lamport,
-                            root_update.offset
-                        );
+                }
+            }
+            match decoded {
+                Ok(GossipMessage::RootUpdate(root_update)) => {
+                    swarm_observer.send((peer_id, GossipMessage::RootUpdate(root_update.clone_without_blocks())));
+                    let _s = tracing::trace_span!("root update", root = %root_update.root);
+                    let _s = _s.enter();
+                    tracing::debug!(
+                        "from {} with {} blocks, lamport: {}, offset: {:?}",
+                        root_update.stream,
+                        root_update.blocks.len(),
+                        root_update.lamport,
+                        root_update.offset
+                    );
+                    // the lamport and offset of untrusted peers are taken from the tree once synced
+                    let path = if store.is_untrusted(&peer_id)
+                        || !store.accept_lamport(peer_id, Some(root_update.stream), root_update.lamport)
+                    {
+                        tracing::debug!("deferring root update from {}", peer_id);
+                        RootPath::Deferred
+                    } else {
                         let mut lock = store.lock();
                         tracing::trace!("got store lock");
                         lock.received_lamport(root_update.lamport)
@@ -251,6 +749,9 @@ impl Gossip {
                         } else {
                             RootPath::FastPath
                         };
+                        let bytes = root_update.blocks.iter().map(|b| b.data().len()).sum();
+                        store.data.transfers.received(peer_id, root_update.blocks.len(), bytes);
+                        store.data.swarm_metrics.received(root_update.blocks.len(), bytes);
                         for block in root_update.blocks {
                             let cid = *block.cid();
                             if let Err(err) = store.ipfs().insert(block) {
@@ -259,41 +760,676 @@ impl Gossip {
                                 tracing::trace!("{} written", display(cid));
                             }
                         }
-                        match Link::try_from(root_update.root) {
-                            Ok(root) => store.update_root(root_update.stream, root, RootSource::new(peer_id, path)),
-                            Err(err) => tracing::error!("failed to parse link {}", err),
-                        }
+                        path
+                    };
+                    let source = RootSource::new(peer_id, path).with_offset(root_update.offset);
+                    match Link::try_from(root_update.root) {
+                        Ok(root) => store.update_root(root_update.stream, root, source, root_update.lamport),
+                        Err(err) => tracing::error!("failed to parse link {}", err),
                     }
-                    Ok(GossipMessage::RootMap(root_map)) => {
-                        swarm_observer.send((peer_id, GossipMessage::RootMap(root_map.clone())));
-                        let _s = tracing::trace_span!("root map", lamport = %root_map.lamport);
-                        let _s = _s.enter();
-                        tracing::debug!("with {} entries, lamport: {}", root_map.entries.len(), root_map.lamport);
+                }
+                Ok(GossipMessage::RootMap(root_map)) => {
+                    swarm_observer.send((peer_id, GossipMessage::RootMap(root_map.clone())));
+                    let _s = tracing::trace_span!("root map", lamport = %root_map.lamport);
+                    let _s = _s.enter();
+                    tracing::debug!("with {} entries, lamport: {}", root_map.entries.len(), root_map.lamport);
+                    let path = if store.is_untrusted(&peer_id) || !store.accept_lamport(peer_id, None, root_map.lamport)
+                    {
+                        tracing::debug!("deferring root map from {}", peer_id);
+                        RootPath::Deferred
+                    } else {
                         store
                             .lock()
                             .received_lamport(root_map.lamport)
                             .expect("unable to update lamport");
-                        for (idx, (stream, root)) in root_map.entries.into_iter().enumerate() {
-                            if let Some((offset, _)) = root_map.offsets.get(idx) {
-                                store.update_highest_seen(stream, *offset);
+                        RootPath::RootMap
+                    };
+                    if path == RootPath::RootMap {
+                        store.data.peer_root_offsets.publish(peer_id, &root_map);
+                    }
+                    for (idx, (stream, root)) in root_map.entries.into_iter().enumerate() {
+                        let (offset, lamport) = match root_map.offsets.get(idx) {
+                            Some((offset, lamport)) => (Some(*offset), *lamport),
+                            None => (None, root_map.lamport),
+                        };
+                        if let (Some(offset), RootPath::RootMap) = (offset, &path) {
+                            store.update_highest_seen(stream, offset);
+                        }
+                        let source = RootSource::new(peer_id, path.clone()).with_offset(offset);
+                        match Link::try_from(root) {
+                            Ok(root) => store.update_root(stream, root, source, lamport),
+                            Err(err) => tracing::error!("failed to parse link {}", err),
+                        }
+                    }
+                    if path == RootPath::RootMap {
+                        // a node only knows when its own streams are idle
+                        let sender = peer_id_to_node_id(peer_id).ok();
+                        for (stream, (offset, lamport)) in root_map.heartbeats {
+                            if Some(stream.node_id()) != sender {
+                                tracing::debug!("dropping heartbeat for stream {} from {}", stream, peer_id);
+                                continue;
                             }
-                            match Link::try_from(root) {
-                                Ok(root) => {
-                                    store.update_root(stream, root, RootSource::new(peer_id, RootPath::RootMap))
-                                }
-                                Err(err) => tracing::error!("failed to parse link {}", err),
+                            // the lamport of the message has been checked, a higher one could be bogus
+                            if lamport <= root_map.lamport {
+                                store.data.stream_heartbeats.publish(stream, lamport, offset);
                             }
                         }
                     }
-                    Err(err) => tracing::debug!("received invalid gossip message; skipping. {}", err),
+                }
+                Err(err) => {
+                    tracing::debug!("received invalid gossip message from {}; skipping. {}", peer_id, err);
+                    store
+                        .quarantine()
+                        .record_violation(peer_id, format_args!("invalid gossip message: {}", err));
                 }
             }
-        })
+        }
     }
 }
 
+fn decode_gossip(message: &[u8]) -> Result<GossipMessage, CodecError> {
+    Cbor::checked(message)
+        .map_err(CodecError::custom)
+        .and_then(GossipMessage::read_cbor)
+}
+
 impl Drop for Gossip {
     fn drop(&mut self) {
         self.publish_handle.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adaptive_cadence_backs_off() {
+        let cadence = RootMapCadence::adaptive(Duration::from_secs(1), Duration::from_secs(10));
+        let mut interval = cadence.initial();
+        let mut seen = vec![interval];
+        for _ in 0..5 {
+            interval = cadence.back_off(interval);
+            seen.push(interval);
+        }
+        let secs = seen.iter().map(|d| d.as_secs()).collect::<Vec<_>>();
+        assert_eq!(secs, vec![1, 2, 4, 8, 10, 10]);
+    }
+
+    #[test]
+    fn outbox_keeps_latest_undelivered_root() {
+        let entry = |n: u64| OutboxEntry {
+            root: Cid::from(Link::new(&n.to_be_bytes())),
+            lamport: LamportTimestamp::from(n),
+            offset: Offset::from(n as u32),
+        };
+        let outbox = Outbox::default();
+        outbox.published(0.into(), entry(1), false);
+        outbox.published(0.into(), entry(2), false);
+        outbox.published(1.into(), entry(3), true);
+        assert_eq!(
+            outbox.pending().into_iter().collect::<Vec<_>>(),
+            vec![(0.into(), entry(2))]
+        );
+
+        // a newer root arriving during the replay stays pending
+        outbox.published(0.into(), entry(4), false);
+        outbox.replayed(0.into(), entry(2));
+        assert_eq!(
+            outbox.pending().into_iter().collect::<Vec<_>>(),
+            vec![(0.into(), entry(4))]
+        );
+        outbox.replayed(0.into(), entry(4));
+        assert!(outbox.pending().is_empty());
+
+        // a delivered root supersedes the pending one
+        outbox.published(1.into(), entry(5), false);
+        outbox.published(1.into(), entry(6), true);
+        assert!(outbox.pending().is_empty());
+    }
+
+    #[test]
+    fn fixed_cadence_override() {
+        let cadence = RootMapCadence::Fixed(Duration::from_secs(10));
+        assert_eq!(cadence.initial(), Duration::from_secs(10));
+        assert_eq!(cadence.back_off(Duration::from_secs(10)), Duration::from_secs(10));
+        // a max interval not above the min interval means no backoff
+        assert_eq!(
+            RootMapCadence::adaptive(Duration::from_secs(10), Duration::from_secs(10)),
+            cadence
+        );
+    }
+
+    #[test]
+    fn jitter_scales_with_swarm_size() {
+        let interval = Duration::from_secs(10);
+        assert_eq!(with_jitter(interval, 0, 0.99), interval);
+        assert_eq!(with_jitter(interval, 50, 0.0), interval);
+        assert_eq!(with_jitter(interval, 50, 0.5), Duration::from_millis(11_250));
+        assert_eq!(with_jitter(interval, 1000, 0.5), Duration::from_millis(12_500));
+        assert!(with_jitter(interval, 1000, 0.999) < Duration::from_secs(15));
+    }
+
+    fn update(root: &[u8], offset: u32) -> PublishUpdate {
+        let root = Link::new(root);
+        PublishUpdate {
+            stream: StreamNr::from(1),
+            root,
+            links: [root].into_iter().collect(),
+            lamport: u64::from(offset).into(),
+            offset: Offset::from(offset),
+        }
+    }
+
+    #[tokio::test]
+    async fn fast_path_batches_updates() {
+        let (tx, mut rx) = unbounded();
+        tx.unbounded_send(update(b"a", 0)).unwrap();
+        let later = tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            later.unbounded_send(update(b"b", 1)).unwrap();
+        });
+        let batch = next_batch(&mut rx, Duration::from_millis(200)).await.unwrap();
+        assert_eq!(batch.len(), 1);
+        let published = &batch[&StreamNr::from(1)];
+        assert_eq!(published.root, Link::new(b"b"));
+        assert_eq!(published.offset, Offset::from(1));
+        assert_eq!(
+            published.links,
+            [Link::new(b"a"), Link::new(b"b")].into_iter().collect()
+        );
+
+        // without batching every update is published as soon as it arrives
+        tx.unbounded_send(update(b"c", 2)).unwrap();
+        let batch = next_batch(&mut rx, Duration::ZERO).await.unwrap();
+        assert_eq!(batch[&StreamNr::from(1)].root, Link::new(b"c"));
+    }
+
+    #[tokio::test]
+    async fn fast_path_batch_does_not_delay_root_map() {
+        let store = BanyanStore::test("batch").await.unwrap();
+        let gossip = Gossip::new(
+            store.ipfs().clone(),
+            store.node_id(),
+            "batch".to_owned(),
+            true,
+            true,
+            Duration::from_secs(3600),
+            PublishPolicy::default(),
+            ActoRef::blackhole(),
+            Default::default(),
+            SwarmMetrics::new().unwrap(),
+        );
+        let published = update(b"a", 0);
+        gossip
+            .publish(
+                published.stream,
+                published.root,
+                published.links,
+                published.lamport,
+                published.offset,
+            )
+            .unwrap();
+        tokio::time::timeout(Duration::from_millis(100), gossip.changed.notified())
+            .await
+            .expect("root map publication was not triggered");
+    }
+
+    #[test]
+    fn publish_policy_rules() {
+        let open = PublishPolicy::default();
+        assert!(open.allows("actyx"));
+        assert!(open.allows("actyx-offsets"));
+
+        let policy = PublishPolicy {
+            rules: vec![PublishRule::allow("actyx-offsets"), PublishRule::deny("actyx*")],
+            default: true,
+        };
+        assert!(policy.allows("actyx-offsets"));
+        assert!(!policy.allows("actyx"));
+        assert!(!policy.allows("actyx-other"));
+        assert!(policy.allows("other"));
+        assert_eq!(
+            policy.check("actyx"),
+            Err(PublishDenied {
+                topic: "actyx".to_owned()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn denied_publish_and_broadcast_fail() {
+        let store = BanyanStore::test("policy").await.unwrap();
+        let mut ipfs = store.ipfs().clone();
+        let policy = PublishPolicy {
+            rules: vec![PublishRule::deny("denied")],
+            default: true,
+        };
+        let denied = |result: Result<()>| {
+            result
+                .unwrap_err()
+                .downcast::<PublishDenied>()
+                .expect("not denied by the policy")
+        };
+        assert_eq!(
+            denied(policy.publish(&mut ipfs, "denied", b"a".to_vec()).await).topic,
+            "denied"
+        );
+        assert_eq!(
+            denied(policy.broadcast(&mut ipfs, "denied", b"a".to_vec()).await).topic,
+            "denied"
+        );
+        // without peers the message goes nowhere, but it is allowed
+        policy.broadcast(&mut ipfs, "allowed", b"a".to_vec()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_gossip_quarantines_sender() {
+        use crate::swarm::QuarantineConfig;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let store = BanyanStore::test("quarantine").await.unwrap();
+        store.quarantine().set_config(QuarantineConfig {
+            threshold: 3,
+            window: Duration::from_secs(60),
+            duration: Duration::from_millis(500),
+        });
+        let decoded = Arc::new(AtomicUsize::new(0));
+        let ingest = |messages: Vec<(PeerId, Vec<u8>)>| {
+            let decoded = decoded.clone();
+            Gossip::ingest_messages(
+                store.clone(),
+                futures::stream::iter(messages),
+                ActoRef::blackhole(),
+                move |bytes| {
+                    decoded.fetch_add(1, Ordering::SeqCst);
+                    decode_gossip(bytes)
+                },
+            )
+        };
+        let (bad, good) = (PeerId::random(), PeerId::random());
+        let garbage = vec![0xff, 0x00, 0x42];
+
+        ingest(vec![(bad, garbage.clone()); 10]).await;
+        // the third failure triggers the quarantine, the rest is dropped before decoding
+        assert_eq!(decoded.load(Ordering::SeqCst), 3);
+        assert!(store.quarantine().is_quarantined(&bad));
+
+        ingest(vec![(good, garbage.clone()); 2]).await;
+        assert_eq!(decoded.load(Ordering::SeqCst), 5);
+        assert!(!store.quarantine().is_quarantined(&good));
+        assert_eq!(
+            store
+                .quarantine()
+                .quarantined()
+                .into_iter()
+                .map(|q| q.peer_id)
+                .collect::<Vec<_>>(),
+            vec![bad]
+        );
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(!store.quarantine().is_quarantined(&bad));
+        ingest(vec![(bad, garbage); 1]).await;
+        assert_eq!(decoded.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn implausible_lamport_is_rejected() {
+        use ax_types::{app_id, tags, Payload};
+
+        let store = BanyanStore::test("lamport").await.unwrap();
+        let peer = PeerId::random();
+        let stream = NodeId::from_bytes(&[7; 32]).unwrap().stream(StreamNr::from(0));
+        let root = store
+            .ipfs()
+            .create_temp_pin()
+            .and_then(|mut tmp| store.add(&mut tmp, &b"root"[..]))
+            .unwrap()
+            .0;
+        let update = RootUpdate {
+            stream,
+            root,
+            blocks: vec![],
+            lamport: (u64::MAX - 5).into(),
+            time: Timestamp::now(),
+            offset: Some(Offset::from(3)),
+        };
+        let before = store.data.lamport.get();
+        Gossip::ingest_messages(
+            store.clone(),
+            futures::stream::iter(vec![(peer, vec![0u8])]),
+            ActoRef::blackhole(),
+            move |_| Ok(GossipMessage::RootUpdate(update.clone_without_blocks())),
+        )
+        .await;
+
+        assert_eq!(store.data.lamport.get(), before);
+        assert!(store.lock().published_tree(stream).is_none());
+        let warnings = store.lamport_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&peer.to_string()), "{:?}", warnings);
+
+        // local appends continue right after the local clock
+        let metas = store
+            .append(app_id!("test"), vec![(tags!("a"), Payload::null())])
+            .await
+            .unwrap();
+        assert!(u64::from(metas[0].0) < 1 << 40, "{:?}", metas);
+    }
+
+    /// Appends to a stream of another store and copies the resulting tree into `store`
+    async fn remote_tree(store: &BanyanStore, lamport: u64) -> (ax_types::StreamId, Link, Offset) {
+        use ax_types::{app_id, tags, Payload};
+
+        let other = BanyanStore::test("remote").await.unwrap();
+        other.lock().received_lamport(lamport.into()).unwrap();
+        let meta = other
+            .append0(
+                StreamNr::from(0),
+                app_id!("test"),
+                Timestamp::now(),
+                vec![(tags!("a"), Payload::null()); 3],
+            )
+            .await
+            .unwrap();
+        other
+            .walk_blocks(Cid::from(meta.root()), |block| store.ipfs().insert(block.clone()))
+            .unwrap();
+        (
+            other.node_id().stream(StreamNr::from(0)),
+            meta.root(),
+            meta.last_offset(),
+        )
+    }
+
+    async fn wait_for_tree(store: &BanyanStore, stream: ax_types::StreamId) -> crate::swarm::streams::PublishedTree {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(tree) = store.lock().published_tree(stream) {
+                    return tree;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("tree was not synced")
+    }
+
+    #[tokio::test]
+    async fn poisoned_lamport_is_deferred_until_the_tree_is_validated() {
+        let store = BanyanStore::test("poisoned").await.unwrap();
+        let peer = PeerId::random();
+        let (stream, root, offset) = remote_tree(&store, 1000).await;
+        let update = RootUpdate {
+            stream,
+            root: root.into(),
+            blocks: vec![],
+            lamport: u64::MAX.into(),
+            time: Timestamp::now(),
+            offset: Some(offset),
+        };
+        Gossip::ingest_messages(
+            store.clone(),
+            futures::stream::iter(vec![(peer, vec![0u8])]),
+            ActoRef::blackhole(),
+            move |_| Ok(GossipMessage::RootUpdate(update.clone_without_blocks())),
+        )
+        .await;
+        assert!(store.is_untrusted(&peer));
+        assert!(u64::from(store.data.lamport.get()) < 1000);
+
+        // the tree is still adopted, with the lamport from its header
+        let tree = wait_for_tree(&store, stream).await;
+        assert!(u64::from(tree.lamport()) >= 1000);
+        assert!(store.data.lamport.get() >= tree.lamport());
+        assert!(u64::from(store.data.lamport.get()) < 1 << 40);
+    }
+
+    #[tokio::test]
+    async fn inconsistent_offset_is_rejected() {
+        let store = BanyanStore::test("offset").await.unwrap();
+        let peer = PeerId::random();
+        let (stream, root, offset) = remote_tree(&store, 0).await;
+        let update = move |offset: Offset| RootUpdate {
+            stream,
+            root: root.into(),
+            blocks: vec![],
+            lamport: 10.into(),
+            time: Timestamp::now(),
+            offset: Some(offset),
+        };
+        let ingest = |update: RootUpdate| {
+            Gossip::ingest_messages(
+                store.clone(),
+                futures::stream::iter(vec![(peer, vec![0u8])]),
+                ActoRef::blackhole(),
+                move |_| Ok(GossipMessage::RootUpdate(update.clone_without_blocks())),
+            )
+        };
+
+        ingest(update(Offset::from(99))).await;
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !store.is_untrusted(&peer) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("inconsistent offset was not detected");
+        assert!(store.lock().published_tree(stream).is_none());
+
+        // a consistent announcement is still taken, but only after validation
+        ingest(update(offset)).await;
+        assert_eq!(wait_for_tree(&store, stream).await.offset(), offset);
+    }
+
+    #[tokio::test]
+    async fn forged_root_update_is_dropped() {
+        use crate::{
+            crypto::{peer_id_to_node_id, KeyPair},
+            swarm::{GossipValidationConfig, QuarantineConfig, SwarmConfig},
+        };
+
+        let store = BanyanStore::new(
+            SwarmConfig {
+                gossip_validation: GossipValidationConfig::strict(),
+                ..SwarmConfig::test("validation")
+            },
+            ActoRef::blackhole(),
+        )
+        .await
+        .unwrap();
+        store.quarantine().set_config(QuarantineConfig {
+            threshold: 1,
+            window: Duration::from_secs(60),
+            duration: Duration::from_secs(60),
+        });
+        let owner: PeerId = KeyPair::generate().into();
+        let forger: PeerId = KeyPair::generate().into();
+        let stream = peer_id_to_node_id(owner).unwrap().stream(StreamNr::from(0));
+        let root = store
+            .ipfs()
+            .create_temp_pin()
+            .and_then(|mut tmp| store.add(&mut tmp, &b"root"[..]))
+            .unwrap()
+            .0;
+        let update = move |lamport: u64| RootUpdate {
+            stream,
+            root,
+            blocks: vec![],
+            lamport: lamport.into(),
+            time: Timestamp::now(),
+            offset: Some(Offset::from(3)),
+        };
+        let ingest = |peer: PeerId, update: RootUpdate| {
+            Gossip::ingest_messages(
+                store.clone(),
+                futures::stream::iter(vec![(peer, vec![0u8])]),
+                ActoRef::blackhole(),
+                move |_| Ok(GossipMessage::RootUpdate(update.clone_without_blocks())),
+            )
+        };
+
+        // a third node announcing the owner's stream
+        ingest(forger, update(1000)).await;
+        assert!(u64::from(store.data.lamport.get()) < 1000);
+        assert!(store.quarantine().is_quarantined(&forger));
+
+        ingest(owner, update(1000)).await;
+        assert!(u64::from(store.data.lamport.get()) >= 1000);
+        assert!(!store.quarantine().is_quarantined(&owner));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idle_stream_heartbeats_advance_watermark() {
+        use crate::{
+            api::EventService,
+            crypto::KeyPair,
+            swarm::event_store_ref::{self, EventStoreHandler, EventStoreRef},
+        };
+        use ax_types::{
+            app_id,
+            service::{SubscribeMonotonicRequest, SubscribeMonotonicResponse},
+            tags, OffsetMap, Payload,
+        };
+
+        let a = BanyanStore::test("heartbeats_a").await.unwrap();
+        let b = BanyanStore::test("heartbeats_b").await.unwrap();
+        b.ipfs()
+            .clone()
+            .add_address(a.ipfs().local_peer_id(), a.ipfs().listeners()[0].clone());
+        let metas = a
+            .append(app_id!("test"), vec![(tags!("a"), Payload::null()); 3])
+            .await
+            .unwrap();
+        let stream = a.node_id().stream(metas[0].2);
+        let mut offsets = b.data.offsets.new_observer();
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while let Some(offsets) = offsets.next().await {
+                if offsets.present.offset(stream) == Offset::from(2).into() {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        let events = {
+            let store = b.clone();
+            let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+            b.spawn_task(
+                "handler".to_owned(),
+                async move {
+                    let mut handler = EventStoreHandler::new(store);
+                    let runtime = tokio::runtime::Handle::current();
+                    while let Some(request) = rx.recv().await {
+                        handler.handle(request, &runtime);
+                    }
+                }
+                .boxed(),
+            );
+            EventStoreRef::new(move |e| tx.try_send(e).map_err(event_store_ref::Error::from))
+        };
+        let service = EventService::new(events, b.node_id());
+        let mut subscription = service
+            .subscribe_monotonic(
+                app_id!("test"),
+                SubscribeMonotonicRequest {
+                    query: "FROM allEvents".to_owned(),
+                    session: "heartbeats".into(),
+                    lower_bound: OffsetMap::empty(),
+                },
+            )
+            .await
+            .unwrap();
+        while !matches!(
+            subscription.next().await.unwrap(),
+            SubscribeMonotonicResponse::Offsets(_)
+        ) {}
+
+        let base = u64::from(b.data.lamport.get());
+        let root_map = |version: u64, lamport: u64, heartbeat: u64| RootMap {
+            version,
+            heartbeats: [(stream, (Offset::from(2), (base + heartbeat).into()))].into(),
+            lamport: (base + lamport).into(),
+            ..Default::default()
+        };
+        let owner = a.ipfs().local_peer_id();
+        let forger: PeerId = KeyPair::generate().into();
+        let messages = vec![
+            (owner, root_map(ROOT_MAP_VERSION, 10, 10)),
+            // above the lamport of its message, so not trustworthy
+            (owner, root_map(ROOT_MAP_VERSION, 15, 16)),
+            // not the node of the stream
+            (forger, root_map(ROOT_MAP_VERSION, 18, 18)),
+            (owner, root_map(ROOT_MAP_VERSION, 20, 20)),
+        ];
+        Gossip::ingest_messages(
+            b.clone(),
+            futures::stream::iter(
+                messages
+                    .into_iter()
+                    .map(|(peer, m)| (peer, m.write_cbor(CborBuilder::default()).into_vec())),
+            ),
+            ActoRef::blackhole(),
+            decode_gossip,
+        )
+        .await;
+
+        // no events flowed, still the subscriber's watermark for the stream moves along
+        let mut watermarks = vec![];
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while watermarks.last() != Some(&(base + 20)) {
+                match subscription.next().await.unwrap() {
+                    SubscribeMonotonicResponse::Watermark {
+                        stream: s,
+                        lamport,
+                        offset,
+                    } => {
+                        assert_eq!((s, offset), (stream, Offset::from(2)));
+                        watermarks.push(u64::from(lamport));
+                    }
+                    x => panic!("unexpected {:?}", x),
+                }
+            }
+        })
+        .await
+        .unwrap();
+        // a's own root maps may pass on lower heartbeats meanwhile
+        assert!(watermarks.contains(&(base + 10)), "{:?}", watermarks);
+        assert!(!watermarks.contains(&(base + 16)), "{:?}", watermarks);
+        assert!(!watermarks.contains(&(base + 18)), "{:?}", watermarks);
+    }
+
+    #[test]
+    fn heartbeats_for_unchanged_own_streams() {
+        let own = NodeId::from_bytes(&[1; 32]).unwrap();
+        let other = NodeId::from_bytes(&[2; 32]).unwrap();
+        let entry = |root: &[u8], offset: u32| {
+            (
+                Cid::from(Link::new(root)),
+                Offset::from(offset),
+                LamportTimestamp::from(1),
+            )
+        };
+        let previous = [
+            (own.stream(0.into()), entry(b"a", 1)),
+            (own.stream(1.into()), entry(b"b", 1)),
+            (other.stream(0.into()), entry(b"c", 1)),
+        ]
+        .into();
+        let root_map = [
+            (own.stream(0.into()), entry(b"a", 1)),
+            (own.stream(1.into()), entry(b"d", 2)),
+            (own.stream(2.into()), entry(b"e", 0)),
+            (other.stream(0.into()), entry(b"c", 1)),
+        ]
+        .into();
+        assert_eq!(
+            own_heartbeats(own, &root_map, &previous, 42.into()),
+            [(own.stream(0.into()), (Offset::from(1), 42.into()))].into()
+        );
+        assert!(own_heartbeats(own, &root_map, &BTreeMap::new(), 42.into()).is_empty());
+    }
+}
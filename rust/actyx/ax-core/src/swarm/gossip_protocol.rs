@@ -235,6 +235,9 @@ impl ReadCbor for AsNumberArray<'static> {
     }
 }
 
+/// The version of the [`RootMap`] written by this node, see there for the differences.
+pub const ROOT_MAP_VERSION: u64 = 2;
+
 /// This struct represents a node's validated trees for a set of streams (incl. its own).
 ///
 /// **Wire format**: This struct is extendable, as it's encoded as a infite length map, and older
@@ -242,27 +245,51 @@ impl ReadCbor for AsNumberArray<'static> {
 /// version of Actyx v2 used a fixed size map, so this particular case needs to be special handled
 /// while decoding updates from older nodes.
 ///
-/// Up to including Actyx v2.3.1 the `offsets` field was not present.
-#[derive(Debug, Eq, PartialEq, Default, Clone)]
+/// Up to including Actyx v2.3.1 the `offsets` field was not present. Version 1, i.e. messages
+/// without `version`, carries no `heartbeats`.
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct RootMap {
+    /// Version of the message format, 1 for messages of nodes not writing it
+    pub version: u64,
     pub entries: BTreeMap<StreamId, Cid>,
-    /// Offset and lamport timestamp of the trees referenced in the `entries` map.
-    /// Could be empty (backwards compatibilty!)
+    /// Offset and lamport timestamp of the last event of the trees referenced in the `entries`
+    /// map, in the same order. Could be empty (backwards compatibilty!)
     pub offsets: Vec<(Offset, LamportTimestamp)>,
+    /// Own streams of the publishing node that did not change since its previous root map.
+    ///
+    /// A heartbeat `(offset, lamport)` states that the stream ends at `offset` and that the next
+    /// event appended to it will have a lamport timestamp greater than `lamport`. Thus a node that
+    /// has all events of the stream up to `offset` knows all of its events up to `lamport`.
+    pub heartbeats: BTreeMap<StreamId, (Offset, LamportTimestamp)>,
     /// Highest lamport timestamp known to the node at time of publishing the message
     pub lamport: LamportTimestamp,
     /// Message creation wallclock
     pub time: Timestamp,
 }
 
+impl Default for RootMap {
+    fn default() -> Self {
+        Self {
+            version: ROOT_MAP_VERSION,
+            entries: Default::default(),
+            offsets: Default::default(),
+            heartbeats: Default::default(),
+            lamport: Default::default(),
+            time: Default::default(),
+        }
+    }
+}
+
 impl WriteCbor for RootMap {
     fn write_cbor<W: cbor_data::Writer>(&self, w: W) -> W::Output {
         w.encode_dict(|w| {
             w.set_max_definite_size(Some(u64::MAX));
             w.with_key("entries", |w| self.entries.write_cbor(w));
+            w.with_key("heartbeats", |w| self.heartbeats.write_cbor(w));
             w.with_key("lamport", |w| self.lamport.write_cbor(w));
             w.with_key("offsets", |w| self.offsets.write_cbor(w));
             w.with_key("time", |w| self.time.write_cbor(w));
+            w.with_key("version", |w| self.version.write_cbor(w));
             w.set_max_definite_size(None);
         })
     }
@@ -283,6 +310,11 @@ impl ReadCbor for RootMap {
             .filter_map(|(k, v)| k.decode().to_str().map(|k| (k, v)))
             .collect::<BTreeMap<_, _>>();
         Ok(Self {
+            version: if let Some(version) = d.get("version") {
+                ReadCbor::read_cbor(version.as_ref())?
+            } else {
+                1
+            },
             entries: ReadCbor::read_cbor(
                 d.get("entries")
                     .ok_or_else(|| CodecError::str("missing field `entries`"))?
@@ -293,6 +325,11 @@ impl ReadCbor for RootMap {
             } else {
                 Default::default()
             },
+            heartbeats: if let Some(heartbeats) = d.get("heartbeats") {
+                ReadCbor::read_cbor(heartbeats.as_ref())?
+            } else {
+                Default::default()
+            },
             lamport: ReadCbor::read_cbor(
                 d.get("lamport")
                     .ok_or_else(|| CodecError::str("missing field `lamport`"))?
@@ -344,9 +381,19 @@ mod tests {
                     (Arbitrary::arbitrary(g), cid)
                 })
                 .collect();
+            let heartbeats = (0..g.size())
+                .map(|_| {
+                    (
+                        Arbitrary::arbitrary(g),
+                        (Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)),
+                    )
+                })
+                .collect();
             Self {
+                version: ROOT_MAP_VERSION,
                 entries,
                 offsets,
+                heartbeats,
                 lamport: Arbitrary::arbitrary(g),
                 time: Arbitrary::arbitrary(g),
             }
@@ -358,6 +405,9 @@ mod tests {
             // go home, clippy, you’re drunk (and should learn about the borrow checker)
             #[allow(clippy::needless_collect)]
             let keys = s2.entries.keys().copied().collect::<Vec<_>>();
+            let s3 = self.clone();
+            #[allow(clippy::needless_collect)]
+            let heartbeats = s3.heartbeats.keys().copied().collect::<Vec<_>>();
             Box::new(
                 (0..self.offsets.len())
                     .map(move |idx| Self {
@@ -373,6 +423,14 @@ mod tests {
                         let mut entries = s2.entries.clone();
                         entries.remove(&k);
                         Self { entries, ..s2.clone() }
+                    }))
+                    .chain(heartbeats.into_iter().map(move |k| {
+                        let mut heartbeats = s3.heartbeats.clone();
+                        heartbeats.remove(&k);
+                        Self {
+                            heartbeats,
+                            ..s3.clone()
+                        }
                     })),
             )
         }
@@ -560,11 +618,41 @@ mod tests {
                     b't', b'i', b'm', b'e',
                 0x00, // unsigned(0)
         ];
-        let root_map = RootMap::default();
+        let root_map = RootMap {
+            version: 1,
+            ..Default::default()
+        };
         let root_map2 = RootMap::read_cbor(Cbor::checked(&cbor).unwrap()).unwrap();
         assert_eq!(root_map, root_map2);
     }
 
+    #[test]
+    fn test_decode_root_map_v1() {
+        #[rustfmt::skip]
+        let cbor = [
+            0xbf, // map(infinite length)
+                0x67, // string(7)
+                    b'e', b'n', b't', b'r', b'i', b'e', b's',
+                0xa0, // map(0)
+                0x67, // string(7)
+                    b'l', b'a', b'm', b'p', b'o', b'r', b't',
+                0x00, // unsigned(0)
+                0x67, // string(7)
+                    b'o', b'f', b'f', b's', b'e', b't', b's',
+                0x80, // array(0)
+                0x64, // string(4)
+                    b't', b'i', b'm', b'e',
+                0x00, // unsigned(0)
+            0xff // break
+        ];
+        let root_map = RootMap {
+            version: 1,
+            ..Default::default()
+        };
+        let root_map2 = RootMap::read_cbor(Cbor::checked(&cbor[..]).unwrap()).unwrap();
+        assert_eq!(root_map2, root_map);
+    }
+
     #[test]
     fn test_decode_root_map() {
         #[rustfmt::skip]
@@ -573,6 +661,9 @@ mod tests {
                 0x67, // string(7)
                     b'e', b'n', b't', b'r', b'i', b'e', b's',
                 0xa0, // map(0)
+                0x6a, // string(10)
+                    b'h', b'e', b'a', b'r', b't', b'b', b'e', b'a', b't', b's',
+                0xa0, // map(0)
                 0x67, // string(7)
                     b'l', b'a', b'm', b'p', b'o', b'r', b't',
                 0x00, // unsigned(0)
@@ -582,6 +673,9 @@ mod tests {
                 0x64, // string(4)
                     b't', b'i', b'm', b'e',
                 0x00, // unsigned(0)
+                0x67, // string(7)
+                    b'v', b'e', b'r', b's', b'i', b'o', b'n',
+                0x02, // unsigned(2)
             0xff // break
         ];
         let root_map = RootMap::default();
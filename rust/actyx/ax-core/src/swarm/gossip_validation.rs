@@ -0,0 +1,78 @@
+//! Checks applied to gossip before it is ingested.
+//!
+//! A pre-shared key only controls who may join the swarm, every member can still gossip root
+//! updates for any stream. With validation enabled, gossipsub only accepts signed messages, which
+//! makes the reported source the node that published the message rather than the peer that
+//! forwarded it, and root updates are only accepted for streams of that node. Root maps
+//! legitimately list the streams of other nodes, only their heartbeats are checked.
+//!
+//! The gossipsub behaviour embedded in the ipfs node does not let the application report
+//! validation results, so messages failing the checks are still forwarded within the mesh.
+//! They are dropped before ingestion and count as a violation towards quarantining the sender.
+use crate::{crypto::peer_id_to_node_id, swarm::GossipMessage};
+use ax_types::StreamId;
+use ipfs_embed::PeerId;
+use libp2p::gossipsub::ValidationMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GossipValidationConfig {
+    /// Run gossipsub in strict validation mode, accepting only signed messages
+    pub strict: bool,
+    /// Drop root updates for streams that do not belong to the node that published them, and
+    /// count root maps with such heartbeats as violations. The heartbeats themselves are dropped
+    /// in any case.
+    pub check_stream_origin: bool,
+}
+
+impl GossipValidationConfig {
+    /// All checks enabled
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            check_stream_origin: true,
+        }
+    }
+
+    pub fn validation_mode(&self) -> ValidationMode {
+        if self.strict {
+            ValidationMode::Strict
+        } else {
+            ValidationMode::Permissive
+        }
+    }
+
+    pub fn check(&self, source: PeerId, message: &GossipMessage) -> Result<(), GossipValidationError> {
+        match message {
+            GossipMessage::RootUpdate(update) if self.check_stream_origin => {
+                let stream = update.stream;
+                match peer_id_to_node_id(source) {
+                    Ok(node_id) if node_id == stream.node_id() => Ok(()),
+                    _ => Err(GossipValidationError::ForeignStream { stream, peer: source }),
+                }
+            }
+            GossipMessage::RootMap(root_map) if self.check_stream_origin => {
+                let node_id = peer_id_to_node_id(source).ok();
+                match root_map
+                    .heartbeats
+                    .keys()
+                    .find(|stream| Some(stream.node_id()) != node_id)
+                {
+                    Some(stream) => Err(GossipValidationError::ForeignHeartbeat {
+                        stream: *stream,
+                        peer: source,
+                    }),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+pub enum GossipValidationError {
+    #[display(fmt = "root update for stream {} published by {}", stream, peer)]
+    ForeignStream { stream: StreamId, peer: PeerId },
+    #[display(fmt = "heartbeat for stream {} published by {}", stream, peer)]
+    ForeignHeartbeat { stream: StreamId, peer: PeerId },
+}
@@ -0,0 +1,64 @@
+//! Heartbeats of idle streams, carried in the [`RootMap`](crate::swarm::RootMap).
+//!
+//! Without new events a stream gives no indication how far its node's lamport clock has advanced,
+//! so a consumer ordering events by lamport cannot tell whether an earlier event of the stream may
+//! still arrive. A node therefore lists those of its own streams that did not change since its
+//! previous root map as heartbeats with its current lamport, and receivers hand them out via
+//! [`BanyanStore::stream_heartbeats`].
+use crate::swarm::BanyanStore;
+use ax_types::{LamportTimestamp, NodeId, Offset, StreamId};
+use futures::{channel::mpsc, Stream};
+use libipld::Cid;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+
+/// The heartbeats to publish: the own streams whose entry in `root_map` did not change since
+/// `previous`, at the current `lamport`.
+///
+/// `root_map` and `lamport` must be taken under the same store lock, so that no append can have
+/// reserved a lamport below `lamport` without its tree being in `root_map`.
+pub(crate) fn own_heartbeats(
+    node_id: NodeId,
+    root_map: &BTreeMap<StreamId, (Cid, Offset, LamportTimestamp)>,
+    previous: &BTreeMap<StreamId, (Cid, Offset, LamportTimestamp)>,
+    lamport: LamportTimestamp,
+) -> BTreeMap<StreamId, (Offset, LamportTimestamp)> {
+    root_map
+        .iter()
+        .filter(|(stream, entry)| stream.node_id() == node_id && previous.get(stream) == Some(entry))
+        .map(|(stream, (_, offset, _))| (*stream, (*offset, lamport)))
+        .collect()
+}
+
+#[derive(Default)]
+pub(crate) struct StreamHeartbeats(Mutex<Vec<mpsc::UnboundedSender<(StreamId, LamportTimestamp, Offset)>>>);
+
+impl StreamHeartbeats {
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<(StreamId, LamportTimestamp, Offset)> {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.lock().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, stream: StreamId, lamport: LamportTimestamp, offset: Offset) {
+        self.0
+            .lock()
+            .retain(|tx| tx.unbounded_send((stream, lamport, offset)).is_ok());
+    }
+}
+
+impl BanyanStore {
+    /// Heartbeats of idle streams of other nodes, as received from now on.
+    ///
+    /// A heartbeat `(stream, lamport, offset)` means that `stream` ends at `offset` and its next
+    /// event will have a lamport greater than `lamport`. Once the events up to `offset` are
+    /// present, consumers ordering by lamport can therefore advance their lower bound for the
+    /// stream to `lamport`, see [`SubscribeMonotonicResponse::Watermark`]. Only heartbeats of
+    /// trusted peers for their own streams with an acceptable lamport are passed on; nodes
+    /// sending version 1 root maps send none.
+    ///
+    /// [`SubscribeMonotonicResponse::Watermark`]: ax_types::service::SubscribeMonotonicResponse::Watermark
+    pub fn stream_heartbeats(&self) -> impl Stream<Item = (StreamId, LamportTimestamp, Offset)> + Send + Unpin {
+        self.data.stream_heartbeats.subscribe()
+    }
+}
@@ -0,0 +1,158 @@
+//! Protection of the lamport clock against implausible values.
+//!
+//! Every node raises its clock to the highest lamport it has seen, so a single peer gossiping a
+//! value close to the end of the range would push all subsequent events of the whole swarm to
+//! the top of the ordering. Received values too far ahead of the local clock are therefore
+//! rejected, and running out of timestamps is reported as an error instead of wrapping around.
+use ax_types::{LamportTimestamp, StreamId};
+use ipfs_embed::PeerId;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// The largest lamport the index store can hold, it is persisted as a signed 64 bit integer.
+pub const MAX_LAMPORT: u64 = i64::MAX as u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LamportConfig {
+    /// Received lamports further ahead of the local clock than this are rejected
+    pub max_jump: u64,
+    /// Local lamports beyond this are reported in the node diagnostics
+    pub high_water: u64,
+    /// How long a peer stays untrusted after announcing an implausible lamport or an offset not
+    /// matching the announced tree
+    pub untrusted_for: Duration,
+}
+
+impl Default for LamportConfig {
+    fn default() -> Self {
+        Self {
+            max_jump: 1 << 40,
+            high_water: MAX_LAMPORT / 2,
+            untrusted_for: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl LamportConfig {
+    pub fn check(&self, local: LamportTimestamp, received: LamportTimestamp) -> Result<(), LamportError> {
+        let limit = u64::from(local).saturating_add(self.max_jump).min(MAX_LAMPORT);
+        if u64::from(received) > limit {
+            Err(LamportError::Implausible { received, local })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_high(&self, local: LamportTimestamp) -> bool {
+        u64::from(local) > self.high_water
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+pub enum LamportError {
+    #[display(
+        fmt = "lamport clock exhausted: cannot reserve {} timestamps after {}",
+        count,
+        current
+    )]
+    Exhausted { current: LamportTimestamp, count: u64 },
+    #[display(
+        fmt = "received lamport {} is implausibly far ahead of the local clock {}",
+        received,
+        local
+    )]
+    Implausible {
+        received: LamportTimestamp,
+        local: LamportTimestamp,
+    },
+}
+
+/// The last rejected lamport per peer, together with the stream it was announced for.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RejectedLamports(BTreeMap<PeerId, (Option<StreamId>, LamportTimestamp)>);
+
+impl RejectedLamports {
+    pub fn record(&mut self, peer: PeerId, stream: Option<StreamId>, lamport: LamportTimestamp) {
+        self.0.insert(peer, (stream, lamport));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &(Option<StreamId>, LamportTimestamp))> {
+        self.0.iter()
+    }
+}
+
+/// Peers whose announcements are only applied once the announced tree has been validated, each
+/// with the end of its probation.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UntrustedPeers(BTreeMap<PeerId, Instant>);
+
+impl UntrustedPeers {
+    /// Distrust `peer` for `duration` from `now`, forgetting the peers whose probation has ended
+    pub fn insert(&mut self, peer: PeerId, now: Instant, duration: Duration) {
+        self.0.retain(|_, until| *until > now);
+        self.0.insert(peer, now + duration);
+    }
+
+    pub fn contains(&mut self, peer: &PeerId, now: Instant) -> bool {
+        match self.0.get(peer) {
+            Some(until) if *until > now => true,
+            Some(_) => {
+                self.0.remove(peer);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let config = LamportConfig {
+            max_jump: 100,
+            ..Default::default()
+        };
+        assert!(config.check(10.into(), 110.into()).is_ok());
+        assert_eq!(
+            config.check(10.into(), 111.into()),
+            Err(LamportError::Implausible {
+                received: 111.into(),
+                local: 10.into()
+            })
+        );
+        // values the index store cannot hold are never credible
+        let config = LamportConfig {
+            max_jump: u64::MAX,
+            ..Default::default()
+        };
+        assert!(config.check(MAX_LAMPORT.into(), MAX_LAMPORT.into()).is_ok());
+        assert!(config.check(10.into(), (u64::MAX - 5).into()).is_err());
+    }
+
+    #[test]
+    fn untrusted_peers_expire() {
+        let mut peers = UntrustedPeers::default();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        peers.insert(a, start, minute);
+        assert!(peers.contains(&a, start + minute / 2));
+        assert!(!peers.contains(&b, start));
+
+        // a repeated offence extends the probation
+        peers.insert(a, start + minute / 2, minute);
+        assert!(peers.contains(&a, start + minute));
+        assert!(!peers.contains(&a, start + 2 * minute));
+        assert!(peers.0.is_empty());
+
+        // expired entries are evicted when others are added
+        peers.insert(a, start, minute);
+        peers.insert(b, start + 2 * minute, minute);
+        assert_eq!(peers.0.keys().collect::<Vec<_>>(), vec![&b]);
+    }
+}
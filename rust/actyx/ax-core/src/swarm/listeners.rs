@@ -0,0 +1,116 @@
+//! Listeners of the swarm, which can be added and removed while the store is running.
+//!
+//! ipfs-embed has no call for closing a listener, it releases a listener once nobody consumes its
+//! event stream anymore. So each listener is owned by a task draining that stream, and aborting the
+//! task closes the listener. The bound addresses are announced to other peers by discovery, which
+//! also retracts them once they expire, so the announced addresses follow the listeners without
+//! further bookkeeping.
+use crate::{swarm::BanyanStore, util::formats::NodeErrorContext};
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use ipfs_embed::{Ipfs, ListenerEvent, Multiaddr};
+use parking_lot::Mutex;
+use tokio::task::JoinHandle;
+
+pub(crate) struct Listener {
+    /// the address that was asked for, possibly with port zero or an unspecified ip
+    requested: Multiaddr,
+    /// the first address reported by the listener
+    bound: Multiaddr,
+    task: JoinHandle<()>,
+}
+
+impl Listener {
+    /// Start listening on `addr`, failing if the address cannot be bound.
+    pub async fn bind(ipfs: &Ipfs, addr: Multiaddr) -> Result<Self> {
+        let mut events = ipfs.clone().listen_on(addr.clone());
+        let bound = match events.next().await {
+            Some(ListenerEvent::NewListenAddr(bound)) => bound,
+            Some(ListenerEvent::ListenFailed(_addr, reason)) => {
+                return Err(anyhow::anyhow!("bind failed: {}", reason)).with_context(|| NodeErrorContext::BindFailed {
+                    addr: addr.clone(),
+                    component: "Swarm".into(),
+                })
+            }
+            e => {
+                return Err(anyhow::anyhow!("got unexpected event {:?}", e)).with_context(|| {
+                    NodeErrorContext::BindFailed {
+                        addr: addr.clone(),
+                        component: "Swarm".into(),
+                    }
+                })
+            }
+        };
+        // we print only the first of the discovered addresses, but the others will also be found
+        tracing::info!(target: "SWARM_SERVICES_BOUND", "Swarm Services bound to {}.", bound);
+
+        // print the remaining listen addresses asynchronously
+        let task = tokio::spawn(async move {
+            while let Some(ev) = events.next().await {
+                match ev {
+                    ListenerEvent::NewListenAddr(bound_addr) => {
+                        tracing::info!(target: "SWARM_SERVICES_BOUND", "Swarm Services bound to {}.", bound_addr)
+                    }
+                    ListenerEvent::ExpiredListenAddr(addr) => {
+                        tracing::info!("Swarm Services no longer listening on {}.", addr)
+                    }
+                    ListenerEvent::ListenFailed(addr, reason) => {
+                        tracing::warn!(%addr, %reason, "got belated listen failure");
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            requested: addr,
+            bound,
+            task,
+        })
+    }
+
+    pub fn bound(&self) -> &Multiaddr {
+        &self.bound
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The listeners of a store, configured ones as well as those added at runtime
+pub(crate) struct Listeners(Mutex<Vec<Listener>>);
+
+impl Listeners {
+    pub fn new(listeners: Vec<Listener>) -> Self {
+        Self(Mutex::new(listeners))
+    }
+}
+
+impl BanyanStore {
+    /// Start listening on an additional address, returning the bound address.
+    ///
+    /// The address may use port zero, the returned address then carries the assigned port.
+    pub async fn listen_on(&self, addr: Multiaddr) -> Result<Multiaddr> {
+        let listener = Listener::bind(self.ipfs(), addr).await?;
+        let bound = listener.bound().clone();
+        self.data.listeners.0.lock().push(listener);
+        Ok(bound)
+    }
+
+    /// Stop listening on an address, given either as it was requested or as it was bound.
+    ///
+    /// The last listener cannot be removed, since the node would become unreachable.
+    pub fn stop_listening_on(&self, addr: Multiaddr) -> Result<()> {
+        let mut listeners = self.data.listeners.0.lock();
+        let Some(idx) = listeners.iter().position(|l| l.requested == addr || l.bound == addr) else {
+            bail!("not listening on {}", addr);
+        };
+        if listeners.len() == 1 {
+            bail!("cannot stop listening on {}, it is the last listener", addr);
+        }
+        let listener = listeners.remove(idx);
+        tracing::info!(requested = %listener.requested, bound = %listener.bound, "closing swarm listener");
+        Ok(())
+    }
+}
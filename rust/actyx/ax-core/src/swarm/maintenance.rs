@@ -0,0 +1,392 @@
+//! Weekly maintenance windows restricting when heavy background work may run at full speed.
+//!
+//! The [`MaintenanceSchedule`] is a shared handle consulted by the store's task loops before
+//! each pass, so replacing its configuration takes effect without restarting the store.
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+
+/// A window opening on the given days (every day if empty) at `start` node-local time and
+/// lasting until the next `end`, which may lie on the following day.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceWindow {
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl MaintenanceWindow {
+    fn length(&self) -> ChronoDuration {
+        let length = self.end - self.start;
+        if length > ChronoDuration::zero() {
+            length
+        } else {
+            length + ChronoDuration::days(1)
+        }
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+/// How a task may run while no maintenance window is open.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum MaintenanceBudget {
+    Full,
+    /// only a small part of the work is done per pass
+    Reduced,
+    /// the task skips its passes
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    Compaction,
+    Pruning,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OutsideWindows {
+    pub compaction: MaintenanceBudget,
+    pub pruning: MaintenanceBudget,
+}
+
+impl Default for OutsideWindows {
+    fn default() -> Self {
+        Self {
+            compaction: MaintenanceBudget::Reduced,
+            pruning: MaintenanceBudget::Pause,
+        }
+    }
+}
+
+/// Without windows all tasks always run with their full budget.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceConfig {
+    pub windows: Vec<MaintenanceWindow>,
+    /// Fixed offset of the node-local time the windows are given in, in minutes east of UTC.
+    ///
+    /// Without it the windows follow the time zone of the node's system, including changes to
+    /// and from daylight saving time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc_offset_minutes: Option<i32>,
+    pub outside_windows: OutsideWindows,
+}
+
+impl MaintenanceConfig {
+    /// Whether `now` lies within a window, with the end of that window if so and the next start.
+    fn state_at(&self, now: DateTime<Utc>) -> MaintenanceState {
+        match self.utc_offset_minutes {
+            Some(minutes) => {
+                let offset = ChronoDuration::minutes(minutes.into());
+                self.state_in(now, |local| {
+                    LocalResult::Single(DateTime::from_naive_utc_and_offset(*local - offset, Utc))
+                })
+            }
+            None => self.state_in(now, |local| {
+                Local.from_local_datetime(local).map(|t| t.with_timezone(&Utc))
+            }),
+        }
+    }
+
+    /// [`state_at`](Self::state_at) with node-local times converted by `to_utc`.
+    ///
+    /// A local time that occurs twice when the clock is turned back stands for its first
+    /// occurrence, one skipped when the clock is turned forward for the time one hour later.
+    fn state_in(
+        &self,
+        now: DateTime<Utc>,
+        to_utc: impl Fn(&NaiveDateTime) -> LocalResult<DateTime<Utc>>,
+    ) -> MaintenanceState {
+        let instant = |local: NaiveDateTime| match to_utc(&local) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => Some(t),
+            LocalResult::None => to_utc(&(local - ChronoDuration::hours(1)))
+                .earliest()
+                .map(|t| t + ChronoDuration::hours(1)),
+        };
+        let mut state = MaintenanceState {
+            windows: !self.windows.is_empty(),
+            window_end: None,
+            next_window: None,
+        };
+        // a window may have opened yesterday and lasts at most a day, all opening within a week;
+        // the local date differs from the UTC date by at most a day
+        let today = now.date_naive();
+        for date in (-2..=8).filter_map(|d| today.checked_add_signed(ChronoDuration::days(d))) {
+            for window in self.windows.iter().filter(|w| w.opens_on(date.weekday())) {
+                let start = date.and_time(window.start);
+                let (Some(start), Some(end)) = (instant(start), instant(start + window.length())) else {
+                    continue;
+                };
+                if start <= now && now < end {
+                    state.window_end = state.window_end.max(Some(end));
+                } else if start > now && state.next_window.map(|n| start < n).unwrap_or(true) {
+                    state.next_window = Some(start);
+                }
+            }
+        }
+        state
+    }
+
+    fn budget_at(&self, task: MaintenanceTask, now: DateTime<Utc>) -> MaintenanceBudget {
+        if self.windows.is_empty() || self.state_at(now).in_window() {
+            return MaintenanceBudget::Full;
+        }
+        match task {
+            MaintenanceTask::Compaction => self.outside_windows.compaction,
+            MaintenanceTask::Pruning => self.outside_windows.pruning,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceState {
+    /// whether any windows are configured at all
+    pub windows: bool,
+    /// end of the currently open window
+    pub window_end: Option<DateTime<Utc>>,
+    pub next_window: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceState {
+    pub fn in_window(&self) -> bool {
+        self.window_end.is_some()
+    }
+}
+
+type Clock = Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>;
+
+/// Shared, live-updatable maintenance configuration.
+#[derive(Clone)]
+pub struct MaintenanceSchedule {
+    config: Arc<Mutex<MaintenanceConfig>>,
+    clock: Clock,
+}
+
+impl MaintenanceSchedule {
+    pub fn new(config: MaintenanceConfig) -> Self {
+        Self {
+            config: Arc::new(Mutex::new(config)),
+            clock: Arc::new(Utc::now),
+        }
+    }
+
+    /// Uses the given clock instead of the system time, mainly for testing.
+    pub fn with_clock(self, clock: impl Fn() -> DateTime<Utc> + Send + Sync + 'static) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    pub fn config(&self) -> MaintenanceConfig {
+        self.config.lock().clone()
+    }
+
+    /// Replaces the configuration, taking effect at the next pass of each task.
+    pub fn set_config(&self, config: MaintenanceConfig) {
+        *self.config.lock() = config;
+    }
+
+    pub fn state(&self) -> MaintenanceState {
+        self.config.lock().state_at((self.clock)())
+    }
+
+    pub fn budget(&self, task: MaintenanceTask) -> MaintenanceBudget {
+        self.config.lock().budget_at(task, (self.clock)())
+    }
+}
+
+impl Default for MaintenanceSchedule {
+    fn default() -> Self {
+        Self::new(MaintenanceConfig::default())
+    }
+}
+
+impl fmt::Debug for MaintenanceSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MaintenanceSchedule")
+            .field(&*self.config.lock())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // 2023-01-02 is a Monday
+        Utc.with_ymd_and_hms(2023, 1, day, hour, minute, 0).unwrap()
+    }
+
+    fn window(days: &[Weekday], start: &str, end: &str) -> MaintenanceWindow {
+        MaintenanceWindow {
+            days: days.to_vec(),
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn windows() {
+        let config = MaintenanceConfig {
+            windows: vec![window(&[Weekday::Mon], "22:00", "02:00"), window(&[], "12:00", "12:30")],
+            utc_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        let state = config.state_at(at(2, 23, 0));
+        assert_eq!(state.window_end, Some(at(3, 2, 0)));
+        assert_eq!(state.next_window, Some(at(3, 12, 0)));
+        // the window opened on Monday is still open on Tuesday
+        assert!(config.state_at(at(3, 1, 59)).in_window());
+        let state = config.state_at(at(3, 2, 0));
+        assert!(!state.in_window());
+        assert_eq!(state.next_window, Some(at(3, 12, 0)));
+        assert_eq!(config.state_at(at(3, 13, 0)).next_window, Some(at(4, 12, 0)));
+        // Sunday night opens no window
+        assert!(!config.state_at(at(8, 23, 0)).in_window());
+        assert_eq!(config.state_at(at(9, 13, 0)).next_window, Some(at(9, 22, 0)));
+    }
+
+    #[test]
+    fn utc_offset() {
+        let config = MaintenanceConfig {
+            windows: vec![window(&[Weekday::Mon], "02:00", "03:00")],
+            utc_offset_minutes: Some(120),
+            ..Default::default()
+        };
+        // 02:30 local time is 00:30 UTC
+        assert!(config.state_at(at(2, 0, 30)).in_window());
+        assert!(!config.state_at(at(2, 2, 30)).in_window());
+        assert_eq!(config.state_at(at(1, 12, 0)).next_window, Some(at(2, 0, 0)));
+    }
+
+    /// Central European time, with daylight saving time from March 26 to October 29, 2023
+    fn cet(local: &NaiveDateTime) -> LocalResult<DateTime<Utc>> {
+        let dst =
+            Utc.with_ymd_and_hms(2023, 3, 26, 1, 0, 0).unwrap()..Utc.with_ymd_and_hms(2023, 10, 29, 1, 0, 0).unwrap();
+        let offset = |t: &DateTime<Utc>| if dst.contains(t) { 2 } else { 1 };
+        let valid = [2, 1]
+            .into_iter()
+            .map(|hours| (hours, Utc.from_utc_datetime(&(*local - ChronoDuration::hours(hours)))))
+            .filter(|(hours, t)| offset(t) == *hours)
+            .map(|(_, t)| t)
+            .collect::<Vec<_>>();
+        match valid[..] {
+            [t] => LocalResult::Single(t),
+            [earlier, later] => LocalResult::Ambiguous(earlier, later),
+            _ => LocalResult::None,
+        }
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, month, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        let config = MaintenanceConfig {
+            windows: vec![window(&[], "01:00", "05:00")],
+            ..Default::default()
+        };
+        // the night the clocks go forward: 01:00 CET to 05:00 CEST is only three hours
+        assert_eq!(
+            config.state_in(utc(3, 26, 2, 59), cet).window_end,
+            Some(utc(3, 26, 3, 0))
+        );
+        assert_eq!(
+            config.state_in(utc(3, 26, 12, 0), cet).next_window,
+            Some(utc(3, 26, 23, 0))
+        );
+        // the night the clocks go back: 01:00 CEST to 05:00 CET is five hours
+        assert_eq!(
+            config.state_in(utc(10, 28, 22, 0), cet).next_window,
+            Some(utc(10, 28, 23, 0))
+        );
+        assert_eq!(
+            config.state_in(utc(10, 28, 23, 0), cet).window_end,
+            Some(utc(10, 29, 4, 0))
+        );
+
+        let config = MaintenanceConfig {
+            windows: vec![window(&[Weekday::Sun], "02:30", "02:45")],
+            ..Default::default()
+        };
+        // 02:30 is skipped when the clocks go forward and stands for 03:30 CEST
+        assert_eq!(
+            config.state_in(utc(3, 26, 0, 0), cet).next_window,
+            Some(utc(3, 26, 1, 30))
+        );
+        assert!(!config.state_in(utc(3, 26, 0, 35), cet).in_window());
+        assert_eq!(
+            config.state_in(utc(3, 26, 1, 35), cet).window_end,
+            Some(utc(3, 26, 1, 45))
+        );
+        // 02:30 occurs twice when the clocks go back, the window opens only at the first one
+        assert_eq!(
+            config.state_in(utc(10, 29, 0, 0), cet).next_window,
+            Some(utc(10, 29, 0, 30))
+        );
+        assert_eq!(
+            config.state_in(utc(10, 29, 0, 35), cet).window_end,
+            Some(utc(10, 29, 0, 45))
+        );
+        assert!(!config.state_in(utc(10, 29, 1, 35), cet).in_window());
+    }
+
+    #[test]
+    fn budgets() {
+        let mut config = MaintenanceConfig {
+            utc_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.budget_at(MaintenanceTask::Pruning, at(2, 12, 0)),
+            MaintenanceBudget::Full
+        );
+        config.windows.push(window(&[], "02:00", "03:00"));
+        assert_eq!(
+            config.budget_at(MaintenanceTask::Pruning, at(2, 12, 0)),
+            MaintenanceBudget::Pause
+        );
+        assert_eq!(
+            config.budget_at(MaintenanceTask::Compaction, at(2, 12, 0)),
+            MaintenanceBudget::Reduced
+        );
+        assert_eq!(
+            config.budget_at(MaintenanceTask::Pruning, at(2, 2, 0)),
+            MaintenanceBudget::Full
+        );
+    }
+
+    #[test]
+    fn serde() {
+        let config: MaintenanceConfig = serde_json::from_value(serde_json::json!({
+            "windows": [{ "days": ["Sat", "Sun"], "start": "01:00", "end": "05:00" }],
+            "utcOffsetMinutes": -300,
+            "outsideWindows": { "compaction": "full", "pruning": "reduced" }
+        }))
+        .unwrap();
+        assert_eq!(
+            config.windows,
+            vec![window(&[Weekday::Sat, Weekday::Sun], "01:00", "05:00")]
+        );
+        assert_eq!(config.outside_windows.compaction, MaintenanceBudget::Full);
+        assert_eq!(
+            serde_json::from_value::<MaintenanceConfig>(serde_json::to_value(&config).unwrap()).unwrap(),
+            config
+        );
+    }
+}
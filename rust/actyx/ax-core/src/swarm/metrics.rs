@@ -1,6 +1,6 @@
-use std::{future::Future, io::Write, time::Duration};
+use std::{future::Future, io::Write};
 
-use crate::swarm::{internal_app_id, BanyanStore};
+use crate::swarm::{internal_app_id, BanyanStore, PeerTransferStats};
 use anyhow::Result;
 use ax_types::{tags, Payload};
 use libipld::{
@@ -9,20 +9,22 @@ use libipld::{
         encode::{write_u64, write_u8},
         DagCborCodec,
     },
-    codec::Encode,
+    codec::{Codec, Encode},
     DagCbor,
 };
 use prometheus::{Encoder, Registry};
 
-pub fn metrics(store: BanyanStore, interval: Duration) -> Result<impl Future<Output = ()>> {
+pub fn metrics(store: BanyanStore) -> Result<impl Future<Output = ()>> {
     let registry = Registry::new();
     store.ipfs().register_metrics(&registry)?;
     let tags = tags!("metrics");
+    let transfer_tags = tags!("metrics", "transfers");
 
     Ok(async move {
         let encoder = CborEncoder::new();
         let mut buffer = vec![];
         loop {
+            let interval = store.data.settings.project(|s| s.metrics_interval);
             tokio::time::sleep(interval).await;
             let mf = registry.gather();
             buffer.clear();
@@ -36,6 +38,28 @@ pub fn metrics(store: BanyanStore, interval: Duration) -> Result<impl Future<Out
             {
                 tracing::warn!("error appending metrics: {}", err);
             }
+
+            let transfers = store
+                .transfer_stats()
+                .into_iter()
+                .map(PeerTransfers::from)
+                .collect::<Vec<_>>();
+            if transfers.is_empty() {
+                continue;
+            }
+            let payload = match DagCborCodec.encode(&transfers) {
+                Ok(bytes) => Payload::from_slice(&bytes),
+                Err(err) => {
+                    tracing::warn!("error encoding transfer stats: {}", err);
+                    continue;
+                }
+            };
+            if let Err(err) = store
+                .append(internal_app_id(), vec![(transfer_tags.clone(), payload)])
+                .await
+            {
+                tracing::warn!("error appending transfer stats: {}", err);
+            }
         }
     })
 }
@@ -128,6 +152,31 @@ pub struct LabelPair {
     pub value: String,
 }
 
+/// Blocks exchanged with one peer since node start, as published with the `transfers` tag
+#[derive(Clone, Debug, DagCbor, PartialEq, Eq)]
+#[ipld(repr = "tuple")]
+pub struct PeerTransfers {
+    pub peer: String,
+    pub blocks_sent: u64,
+    pub blocks_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub timed_out_wants: u64,
+}
+
+impl From<PeerTransferStats> for PeerTransfers {
+    fn from(stats: PeerTransferStats) -> Self {
+        Self {
+            peer: stats.peer.to_string(),
+            blocks_sent: stats.blocks_sent,
+            blocks_received: stats.blocks_received,
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+            timed_out_wants: stats.timed_out_wants,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct CborEncoder {}
 
@@ -219,7 +268,6 @@ fn prometheus_encode<W: Write>(families: &[prometheus::proto::MetricFamily], w:
 #[cfg(test)]
 mod tests {
     use super::*;
-    use libipld::{cbor::DagCborCodec, codec::Codec};
     use prometheus::core::Collector;
 
     #[test]
@@ -11,27 +11,98 @@
 //! inside this you have mutable access to the state - but if you lock again you will deadlock.
 
 pub mod blob_store;
+mod budget;
+mod car;
+mod config_validation;
+mod connectivity;
+mod decision_log;
 mod discovery;
+mod event_routes;
 pub mod event_store;
 pub mod event_store_ref;
+mod files;
+#[cfg(any(test, feature = "fixture"))]
+pub mod fixture;
+mod gc_grace;
 mod gossip;
 mod gossip_protocol;
+mod gossip_validation;
+mod heartbeats;
+mod lamport;
+mod listeners;
+mod maintenance;
 pub mod metrics;
+mod offsets;
+mod offsets_exchange;
+mod pack_requests;
+mod payload_blobs;
+mod peer_events;
+mod progress;
 mod prune;
+mod quarantine;
+mod replication;
+mod runtime_settings;
+mod secrets;
 pub mod selection;
+mod snapshot;
 mod sqlite;
 mod sqlite_index_store;
+mod startup_validation;
+mod stream_names;
 mod streams;
+mod swarm_metrics;
+mod tag_stats;
+mod tiered_store;
+mod tombstone;
+mod transfer;
 pub mod transport;
+mod tree_stats;
+mod unixfs_dir;
+mod unixfs_stat;
 
 #[cfg(test)]
 mod tests;
 
 pub use crate::swarm::{
-    gossip_protocol::{GossipMessage, RootMap, RootUpdate},
+    budget::TakeWhileBudget,
+    car::ExportStats,
+    config_validation::ConfigError,
+    connectivity::{
+        ConnectivityEngine, ConnectivityInput, ConnectivityRequest, ConnectivityResponse, ConnectivityStatus,
+    },
+    decision_log::{parse_decision_log, Decision, DecisionRecord},
+    event_routes::EventRoutes,
+    files::FileNameEvent,
+    gc_grace::SupersededRoot,
+    gossip::{PreviousTopic, PublishDenied, PublishPolicy, PublishRule, RootMapCadence},
+    gossip_protocol::{GossipMessage, RootMap, RootUpdate, ROOT_MAP_VERSION},
+    gossip_validation::{GossipValidationConfig, GossipValidationError},
+    lamport::{LamportConfig, LamportError, MAX_LAMPORT},
+    maintenance::{
+        MaintenanceBudget, MaintenanceConfig, MaintenanceSchedule, MaintenanceState, MaintenanceTask,
+        MaintenanceWindow, OutsideWindows,
+    },
+    offsets::{OffsetsDelta, VersionedOffsets},
+    offsets_exchange::{NodeOffsets, OffsetsComparison, PeerUnreachable},
+    payload_blobs::PayloadRef,
+    peer_events::{DisconnectReason, PeerEvent},
+    progress::{ProgressCadence, ProgressItem},
+    quarantine::{PeerQuarantine, QuarantineConfig, QuarantinedPeer},
+    replication::{ReplicationConfig, ReplicationMode, ReplicationRule, StreamPattern, StreamSelector, TagSelector},
+    runtime_settings::RuntimeSwarmSettings,
+    secrets::SecretProvider,
+    snapshot::StoreSnapshot,
     sqlite::{StorageServiceStore, StorageServiceStoreWrite},
-    sqlite_index_store::DbPath,
+    sqlite_index_store::{DbPath, IndexStoreConfig, Synchronous},
+    startup_validation::{IncompleteStreamError, StartupReport, StreamRecovery, ValidationMode},
     streams::StreamAlias,
+    tag_stats::TagStat,
+    tiered_store::{BlockReader, TieredReadStore},
+    tombstone::{Tombstone, TombstoneEvent},
+    transfer::PeerTransferStats,
+    tree_stats::{StoreStats, TreeStats},
+    unixfs_dir::UnixfsDirAdder,
+    unixfs_stat::FileStat,
 };
 use crate::{
     ax_futures_util::stream::{
@@ -40,22 +111,32 @@ use crate::{
     },
     crypto::KeyPair,
     swarm::{
+        connectivity::PeerRootOffsets,
+        decision_log::DecisionLog,
         event_store::PersistenceMeta,
-        gossip::Gossip,
+        gc_grace::{GcGrace, GC_GRACE_FACTOR},
+        gossip::{Gossip, PreviousTopics},
+        heartbeats::StreamHeartbeats,
+        listeners::{Listener, Listeners},
+        offsets_exchange::OffsetsExchange,
+        pack_requests::PackRequests,
+        peer_events::PeerEvents,
         sqlite::{SqliteStore, SqliteStoreWrite},
         streams::{OwnStream, PublishedTree, ReplicatedStream},
+        swarm_metrics::SwarmMetrics,
+        tag_stats::TagStatsCache,
+        transfer::TransferStats,
     },
     trees::{
         axtrees::{AxKey, AxTrees, Sha256Digest},
         dnf::Dnf,
-        query::TagExprQuery,
+        query::{TagExprQuery, TimeQuery},
         tags::{ScopedTag, ScopedTagSet},
         AxTree, AxTreeHeader,
     },
     util::{
-        formats::NodeErrorContext,
         reentrant_safe_mutex::{ReentrantSafeMutex, ReentrantSafeMutexGuard},
-        to_multiaddr, to_socket_addr, SocketAddrHelper,
+        to_listen_multiaddr, to_socket_addr, SocketAddrHelper,
     },
 };
 use anyhow::{Context, Result};
@@ -63,49 +144,46 @@ use ax_aql::{TagAtom, TagExpr};
 use ax_types::{
     app_id, tag, AppId, LamportTimestamp, NodeId, Offset, OffsetMap, Payload, StreamId, StreamNr, TagSet, Timestamp,
 };
+pub use banyan::{
+    index::IndexRef, store::BlockWriter, Forest as BanyanForest, StreamBuilder, Transaction as BanyanTransaction,
+};
 use banyan::{
     query::Query,
     store::{BranchCache, ReadOnlyStore},
     FilteredChunk, Secrets,
 };
-pub use banyan::{store::BlockWriter, Forest as BanyanForest, StreamBuilder, Transaction as BanyanTransaction};
 use fnv::FnvHashMap;
 use futures::{
     channel::mpsc,
     future::{self, BoxFuture},
-    stream, FutureExt, Stream, StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+    FutureExt, Stream, StreamExt, TryStreamExt,
 };
 use ipfs_embed::{
-    config::BitswapConfig, identity::PublicKey::Ed25519, Cid, Config as IpfsConfig, DnsConfig, ListenerEvent,
-    Multiaddr, NetworkConfig, PeerId, SyncEvent, TempPin,
+    config::BitswapConfig, identity::PublicKey::Ed25519, Cid, Config as IpfsConfig, DnsConfig, Multiaddr,
+    NetworkConfig, PeerId, SyncEvent, TempPin,
 };
 pub use ipfs_embed::{Executor as IpfsEmbedExecutor, StorageConfig, StorageService};
+use lamport::{RejectedLamports, UntrustedPeers};
 pub use libipld::codec::Codec as IpldCodec;
 use libipld::{cbor::DagCborCodec, error::BlockNotFound};
-use libp2p::{
-    dns::ResolverConfig,
-    gossipsub::{GossipsubConfigBuilder, ValidationMode},
-    identify,
-    multiaddr::Protocol,
-    ping,
-};
-use once_cell::sync::Lazy;
+use libp2p::{dns::ResolverConfig, gossipsub::GossipsubConfigBuilder, identify, multiaddr::Protocol, ping};
 use parking_lot::Mutex;
 pub use prune::{RetainConfig, StreamAge, StreamSize};
 use serde::{Deserialize, Serialize};
 use sqlite_index_store::SqliteIndexStore;
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     convert::{TryFrom, TryInto},
     fmt::{Debug, Display},
     io::{BufRead, BufReader, Read},
     num::NonZeroU32,
-    ops::{Deref, DerefMut, RangeInclusive},
+    ops::{Deref, DerefMut, Range, RangeInclusive},
     path::PathBuf,
     process::Command,
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use streams::{OwnStreamGuard, RemoteNodeInner};
 pub use unixfs_v1::{
@@ -124,8 +202,8 @@ use unixfs_v1::{
 pub type TT = AxTrees;
 pub type Key = AxKey;
 pub type Event = Payload;
-pub type Forest = banyan::Forest<TT, SqliteStore>;
-pub type Transaction = banyan::Transaction<TT, SqliteStore, SqliteStoreWrite>;
+pub type Forest = banyan::Forest<TT, TieredReadStore>;
+pub type Transaction = banyan::Transaction<TT, TieredReadStore, SqliteStoreWrite>;
 pub type Tree = banyan::Tree<TT, Event>;
 pub type AxStreamBuilder = banyan::StreamBuilder<TT, Event>;
 pub type Link = Sha256Digest;
@@ -135,8 +213,6 @@ use acto::ActoRef;
 pub type Block = libipld::Block<StoreParams>;
 pub type Ipfs = ipfs_embed::Ipfs<StoreParams>;
 
-const MAX_TREE_LEVEL: i32 = 512;
-
 const DEFAULT_STREAM_NAME: &str = "default";
 const DEFAULT_STREAM_NUMBER: u64 = 0;
 
@@ -158,45 +234,168 @@ fn internal_app_id() -> AppId {
     app_id!("com.actyx")
 }
 
+/// Pruning of ephemeral events, see [`RetainConfig`].
+///
+/// Serialized as `"disabled"` or as an object with `interval` and `streams`. Configurations from
+/// before disabling was explicit used an interval of `u64::MAX` seconds, these are read as
+/// [`Disabled`](Self::Disabled).
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct EphemeralEventsConfig {
-    interval: Duration,
-    streams: BTreeMap<String, RetainConfig>,
+#[serde(from = "EphemeralEventsRepr", into = "EphemeralEventsRepr")]
+pub enum EphemeralEventsConfig {
+    /// Events are never pruned
+    Disabled,
+    /// The given streams are pruned every `interval`
+    Enabled {
+        interval: Duration,
+        streams: BTreeMap<String, RetainConfig>,
+    },
 }
 
 impl EphemeralEventsConfig {
     pub fn new(interval: Duration, streams: BTreeMap<String, RetainConfig>) -> Self {
-        Self { interval, streams }
+        Self::Enabled { interval, streams }
     }
-    pub fn disable() -> Self {
-        Self {
-            streams: BTreeMap::default(),
-            interval: Duration::from_secs(u64::MAX),
+
+    /// The streams to prune, `None` if pruning is disabled
+    pub fn streams_mut(&mut self) -> Option<&mut BTreeMap<String, RetainConfig>> {
+        match self {
+            Self::Disabled => None,
+            Self::Enabled { streams, .. } => Some(streams),
         }
     }
 }
 
 impl Default for EphemeralEventsConfig {
     fn default() -> Self {
-        Self {
-            interval: Duration::from_secs(DEFAULT_PRUNING_INTERVAL),
-            streams: BTreeMap::new(),
-        }
+        Self::from(BTreeMap::new())
     }
 }
 
 impl FromStr for EphemeralEventsConfig {
     type Err = serde_json::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // allow the unquoted form on the command line
+        if s.trim() == "disabled" {
+            return Ok(Self::Disabled);
+        }
         serde_json::from_str(s)
     }
 }
 
 impl From<BTreeMap<String, RetainConfig>> for EphemeralEventsConfig {
     fn from(streams: BTreeMap<String, RetainConfig>) -> Self {
+        Self::new(Duration::from_secs(DEFAULT_PRUNING_INTERVAL), streams)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Disabled {
+    Disabled,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EphemeralEventsRepr {
+    Disabled(Disabled),
+    Enabled {
+        interval: Duration,
+        streams: BTreeMap<String, RetainConfig>,
+    },
+}
+
+impl From<EphemeralEventsRepr> for EphemeralEventsConfig {
+    fn from(repr: EphemeralEventsRepr) -> Self {
+        match repr {
+            EphemeralEventsRepr::Disabled(_) => Self::Disabled,
+            EphemeralEventsRepr::Enabled { interval, .. } if interval.as_secs() == u64::MAX => Self::Disabled,
+            EphemeralEventsRepr::Enabled { interval, streams } => Self::Enabled { interval, streams },
+        }
+    }
+}
+
+impl From<EphemeralEventsConfig> for EphemeralEventsRepr {
+    fn from(config: EphemeralEventsConfig) -> Self {
+        match config {
+            EphemeralEventsConfig::Disabled => Self::Disabled(Disabled::Disabled),
+            EphemeralEventsConfig::Enabled { interval, streams } => Self::Enabled { interval, streams },
+        }
+    }
+}
+
+/// How the local streams are compacted (packed), with overrides for individual streams.
+///
+/// Packing a stream holds its lock for the duration of the write, so streams with frequent small
+/// appends may be better served by only packing once enough unpacked appends have accumulated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionConfig {
+    /// How often the compaction loop visits the local streams
+    pub interval: Duration,
+    /// Only pack streams whose tree level exceeds this; each unpacked append adds a level
+    pub level_threshold: usize,
+    pub streams: BTreeMap<StreamNr, StreamCompaction>,
+}
+
+impl CompactionConfig {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            level_threshold: 0,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// The effective `(interval, level_threshold)` for the stream, `None` if it is not compacted.
+    fn policy(&self, stream_nr: StreamNr) -> Option<(Duration, usize)> {
+        match self.streams.get(&stream_nr) {
+            Some(s) if !s.enabled => None,
+            Some(s) => Some((
+                s.interval.unwrap_or(self.interval),
+                s.level_threshold.unwrap_or(self.level_threshold),
+            )),
+            None => Some((self.interval, self.level_threshold)),
+        }
+    }
+
+    /// Period at which the compaction loop needs to wake up to honour all intervals.
+    fn tick(&self) -> Duration {
+        self.streams
+            .values()
+            .filter(|s| s.enabled)
+            .filter_map(|s| s.interval)
+            .fold(self.interval, Duration::min)
+    }
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+/// Overrides of the [`CompactionConfig`] for a single stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamCompaction {
+    pub enabled: bool,
+    pub interval: Option<Duration>,
+    pub level_threshold: Option<usize>,
+}
+
+impl StreamCompaction {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for StreamCompaction {
+    fn default() -> Self {
         Self {
-            interval: Duration::from_secs(DEFAULT_PRUNING_INTERVAL),
-            streams,
+            enabled: true,
+            interval: None,
+            level_threshold: None,
         }
     }
 }
@@ -204,7 +403,12 @@ impl From<BTreeMap<String, RetainConfig>> for EphemeralEventsConfig {
 #[derive(Clone, Debug)]
 pub struct SwarmConfig {
     pub topic: String,
+    /// Topics the swarm was previously known under, only received from during `previous_topics_grace`
+    pub previous_topics: Vec<String>,
+    pub previous_topics_grace: Duration,
     pub index_store: Option<PathBuf>,
+    /// Lamport reservation and durability of the index store
+    pub index_store_config: IndexStoreConfig,
     pub blob_store: Option<PathBuf>,
     pub keypair: Option<KeyPair>,
     pub psk: Option<[u8; 32]>,
@@ -213,6 +417,9 @@ pub struct SwarmConfig {
     pub block_cache_size: u64,
     pub block_cache_count: u64,
     pub block_gc_interval: Duration,
+    /// How long the previous root of a stream stays pinned after its alias moved on, so that peers
+    /// still syncing it can complete. Defaults to four times the `bitswap_timeout`, zero disables.
+    pub gc_grace_period: Option<Duration>,
     pub external_addresses: Vec<Multiaddr>,
     pub listen_addresses: Arc<Mutex<SocketAddrHelper>>,
     pub bootstrap_addresses: Vec<Multiaddr>,
@@ -224,21 +431,75 @@ pub struct SwarmConfig {
     pub enable_root_map: bool,
     pub enable_discovery: bool,
     pub enable_metrics: bool,
+    /// Expose the [`metrics_registry`](BanyanStore::metrics_registry) in the Prometheus text format
+    /// at `/metrics` on the API port
+    pub enable_prometheus: bool,
+    /// Replicate and serve the streams of other nodes without ever creating or modifying own
+    /// streams: appends fail with [`ReadOnlyError`] and internal tasks do not publish events. The
+    /// node still takes part in gossip and the root map, so it can serve blocks to its peers.
+    pub read_only: bool,
     pub banyan_config: BanyanConfig,
-    pub cadence_root_map: Duration,
-    pub cadence_compact: Duration,
+    pub cadence_root_map: RootMapCadence,
+    /// Root updates of own streams are collected for this long before publishing them on the
+    /// fast path, only the latest root per stream is sent. Zero publishes every update right away.
+    pub cadence_fast_path_batch: Duration,
+    pub compaction: CompactionConfig,
     pub metrics_interval: Duration,
     pub ping_timeout: Duration,
     pub bitswap_timeout: Duration,
     pub branch_cache_size: u64,
     pub event_routes: Vec<EventRoute>,
+    /// Payloads larger than this many bytes are stored as blobs and replaced by a [`PayloadRef`]
+    pub payload_blob_threshold: Option<usize>,
+    /// How long the idempotency tokens of [`BanyanStore::append_idempotent`] are remembered
+    pub append_token_ttl: Duration,
+    /// How long a tombstoned stream is kept and gossiped before its events are purged, see
+    /// [`BanyanStore::tombstone_stream`]
+    pub tombstone_retention: Duration,
+    /// When to put peers sending malformed messages into quarantine
+    pub quarantine: QuarantineConfig,
+    /// Quarantine list, shared with the node API which talks to the same peers
+    pub peer_quarantine: PeerQuarantine,
+    /// Which remote streams to replicate
+    pub replication: ReplicationConfig,
+    /// When compaction and pruning may run at full speed
+    pub maintenance: MaintenanceConfig,
+    /// Schedule consulted by the task loops, can be updated while the store is running
+    pub maintenance_schedule: MaintenanceSchedule,
+    /// Limits for lamports received from other nodes
+    pub lamport: LamportConfig,
+    /// Only read the published headers of known streams on startup, loading a stream when it is
+    /// first accessed
+    pub lazy_stream_loading: bool,
+    /// Peer addresses learned via discovery that were last seen longer ago are not dialed on startup
+    pub known_peers_max_age: Duration,
+    /// Number of connections beyond which discovered peers are not dialed, bootstrap peers excepted
+    pub max_connections: Option<u32>,
+    /// Number of connections to a single peer beyond which it is not dialed again
+    pub max_connections_per_peer: Option<u32>,
+    /// Secrets per stream, `banyan_config.secret` is used for all streams if not set
+    pub secret_provider: Option<Arc<dyn SecretProvider>>,
+    /// Checks gossip has to pass before it is ingested
+    pub gossip_validation: GossipValidationConfig,
+    /// Topics this node may publish and broadcast gossip on
+    pub publish_policy: PublishPolicy,
+    /// Read-only stores consulted in order for blocks missing locally, new blocks are always
+    /// written locally
+    pub cold_tiers: Vec<Arc<dyn BlockReader>>,
+    /// Record received root updates and sync decisions in this file, see [`DecisionRecord`]
+    pub decision_log_path: Option<PathBuf>,
+    /// What to do on startup with streams that are not completely present
+    pub validation_mode: ValidationMode,
 }
 impl SwarmConfig {
     pub fn basic() -> Self {
         Self {
             enable_loopback: false,
             topic: String::from("default"),
+            previous_topics: vec![],
+            previous_topics_grace: Duration::from_secs(7 * 24 * 3600),
             index_store: None,
+            index_store_config: IndexStoreConfig::default(),
             blob_store: None,
             keypair: None,
             psk: None,
@@ -254,17 +515,40 @@ impl SwarmConfig {
             enable_root_map: true,
             enable_discovery: true,
             enable_metrics: true,
+            enable_prometheus: false,
+            read_only: false,
             banyan_config: BanyanConfig::default(),
-            cadence_compact: Duration::from_secs(60),
-            cadence_root_map: Duration::from_secs(10),
+            compaction: CompactionConfig::default(),
+            cadence_root_map: RootMapCadence::adaptive(Duration::from_secs(10), Duration::from_secs(60)),
+            cadence_fast_path_batch: Duration::from_millis(50),
             block_cache_size: 1024 * 1024 * 1024,
             block_cache_count: 1024 * 128,
             block_gc_interval: Duration::from_secs(300),
+            gc_grace_period: None,
             metrics_interval: Duration::from_secs(60 * 30),
             ping_timeout: Duration::from_secs(5),
             bitswap_timeout: Duration::from_secs(15),
             branch_cache_size: 67108864,
             event_routes: Default::default(),
+            payload_blob_threshold: None,
+            append_token_ttl: Duration::from_secs(24 * 60 * 60),
+            tombstone_retention: Duration::from_secs(7 * 24 * 60 * 60),
+            quarantine: QuarantineConfig::default(),
+            peer_quarantine: PeerQuarantine::default(),
+            replication: ReplicationConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            maintenance_schedule: MaintenanceSchedule::default(),
+            lamport: LamportConfig::default(),
+            lazy_stream_loading: false,
+            known_peers_max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            max_connections: None,
+            max_connections_per_peer: None,
+            secret_provider: None,
+            gossip_validation: GossipValidationConfig::default(),
+            publish_policy: PublishPolicy::default(),
+            cold_tiers: vec![],
+            decision_log_path: None,
+            validation_mode: ValidationMode::default(),
         }
     }
 }
@@ -273,6 +557,12 @@ impl SwarmConfig {
 pub struct BanyanConfig {
     pub tree: banyan::Config,
     pub secret: banyan::Secrets,
+    /// Own streams with more leaves appended since they were last packed are packed by the
+    /// compaction loop, regardless of the [`CompactionConfig`]
+    pub max_unpacked_leaves: usize,
+    /// Pack within the append exceeding `max_unpacked_leaves` instead, for deterministic tests.
+    /// Stores without a compaction loop always do this.
+    pub pack_inline: bool,
 }
 impl Default for BanyanConfig {
     fn default() -> Self {
@@ -288,6 +578,8 @@ impl Default for BanyanConfig {
         Self {
             tree,
             secret: banyan::Secrets::default(),
+            max_unpacked_leaves: 512,
+            pack_inline: false,
         }
     }
 }
@@ -323,6 +615,9 @@ impl PartialEq for SwarmConfig {
         let me_listen = self.listen_addresses.lock().clone();
         let they_listen = other.listen_addresses.lock().clone();
         self.topic == other.topic
+            && self.previous_topics == other.previous_topics
+            && self.previous_topics_grace == other.previous_topics_grace
+            && self.index_store_config == other.index_store_config
             && self.keypair == other.keypair
             && self.psk == other.psk
             && self.node_name == other.node_name
@@ -330,6 +625,7 @@ impl PartialEq for SwarmConfig {
             && self.block_cache_size == other.block_cache_size
             && self.block_cache_count == other.block_cache_count
             && self.block_gc_interval == other.block_gc_interval
+            && self.gc_grace_period == other.gc_grace_period
             && self.external_addresses == other.external_addresses
             && me_listen == they_listen
             && self.bootstrap_addresses == other.bootstrap_addresses
@@ -341,13 +637,43 @@ impl PartialEq for SwarmConfig {
             && self.enable_root_map == other.enable_root_map
             && self.enable_discovery == other.enable_discovery
             && self.enable_metrics == other.enable_metrics
+            && self.enable_prometheus == other.enable_prometheus
+            && self.read_only == other.read_only
             && self.cadence_root_map == other.cadence_root_map
-            && self.cadence_compact == other.cadence_compact
+            && self.cadence_fast_path_batch == other.cadence_fast_path_batch
+            && self.compaction == other.compaction
             && self.metrics_interval == other.metrics_interval
             && self.ping_timeout == other.ping_timeout
             && self.bitswap_timeout == other.bitswap_timeout
             && self.branch_cache_size == other.branch_cache_size
             && self.event_routes == other.event_routes
+            && self.payload_blob_threshold == other.payload_blob_threshold
+            && self.append_token_ttl == other.append_token_ttl
+            && self.tombstone_retention == other.tombstone_retention
+            // the shared `peer_quarantine` handle is state, not configuration
+            && self.quarantine == other.quarantine
+            && self.replication == other.replication
+            // likewise the `maintenance_schedule`
+            && self.maintenance == other.maintenance
+            && self.lamport == other.lamport
+            && self.lazy_stream_loading == other.lazy_stream_loading
+            && self.known_peers_max_age == other.known_peers_max_age
+            && self.max_connections == other.max_connections
+            && self.max_connections_per_peer == other.max_connections_per_peer
+            && match (&self.secret_provider, &other.secret_provider) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+            && self.gossip_validation == other.gossip_validation
+            && self.publish_policy == other.publish_policy
+            && self.cold_tiers.len() == other.cold_tiers.len()
+            && self
+                .cold_tiers
+                .iter()
+                .zip(&other.cold_tiers)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+            && self.decision_log_path == other.decision_log_path
+            && self.validation_mode == other.validation_mode
     }
 }
 
@@ -358,49 +684,123 @@ pub struct BanyanStore {
     state: Arc<ReentrantSafeMutex<BanyanStoreState>>,
 }
 
-#[derive(Clone, Debug, Default)]
+/// Cheap to clone also with many streams, since both maps are persistent data structures.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct SwarmOffsets {
-    /// Currently validated OffsetMap
-    present: OffsetMap,
-    /// OffsetMap describing the replication target. Currently this is driven via `highest_seen`,
-    /// but should eventually be fed by the partial replication mechanism.
-    replication_target: OffsetMap,
+    /// Currently validated offsets
+    present: VersionedOffsets,
+    /// Offsets describing the replication target. This is driven via `highest_seen`, which
+    /// includes streams that are only partially replicated or ignored, see [`ReplicationConfig`].
+    replication_target: VersionedOffsets,
 }
 
 impl SwarmOffsets {
     /// Currently validated OffsetMap
     pub fn present(&self) -> OffsetMap {
-        self.present.clone()
+        self.present.to_offset_map()
     }
 
-    /// OffsetMap describing the replication target. Currently this is driven via `highest_seen`,
-    /// but should eventually be fed by the partial replication mechanism.
+    /// OffsetMap describing the replication target. This is driven via `highest_seen`, which
+    /// includes streams that are only partially replicated or ignored, see [`ReplicationConfig`].
     pub fn replication_target(&self) -> OffsetMap {
-        self.replication_target.clone()
+        self.replication_target.to_offset_map()
+    }
+
+    /// Changes to [`present`](Self::present) after the given version of it.
+    pub fn present_delta(&self, since: u64) -> OffsetsDelta {
+        self.present.delta_since(since)
+    }
+
+    /// Changes to [`replication_target`](Self::replication_target) after the given version of it.
+    pub fn replication_target_delta(&self, since: u64) -> OffsetsDelta {
+        self.replication_target.delta_since(since)
+    }
+
+    /// Number of events still to be replicated for each stream of the replication target.
+    pub fn replication_lag(&self) -> BTreeMap<StreamId, u64> {
+        self.replication_target
+            .streams()
+            .map(|stream| {
+                let lag = self.replication_target.offset(stream) - self.present.offset(stream);
+                (stream, u64::try_from(lag).unwrap_or_default())
+            })
+            .collect()
     }
 }
 
+/// Appending to or otherwise modifying own streams of a store configured as
+/// [read-only replica](SwarmConfig::read_only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[display(fmt = "this node is a read-only replica and does not modify its own streams")]
+pub struct ReadOnlyError;
+
+/// Result of appending a batch of events to one of the local streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AppendMeta {
     min_lamport: LamportTimestamp,
     min_offset: Offset,
     timestamp: Timestamp,
+    root: Link,
+    last_offset: Offset,
+}
+
+impl AppendMeta {
+    /// lamport of the first appended event, the following ones are consecutive
+    pub fn min_lamport(&self) -> LamportTimestamp {
+        self.min_lamport
+    }
+
+    /// offset of the first appended event, the following ones are consecutive
+    pub fn min_offset(&self) -> Offset {
+        self.min_offset
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    /// Link to the [`AxTreeHeader`] of the tree published by this append.
+    ///
+    /// This is the root that is gossiped and aliased for the stream, the tree itself is
+    /// referenced by the header.
+    pub fn root(&self) -> Link {
+        self.root
+    }
+
+    /// offset of the last appended event
+    pub fn last_offset(&self) -> Offset {
+        self.last_offset
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
 pub struct RootSource {
     path: RootPath,
     sender: PeerId,
+    /// offset announced together with the root, checked against the synced tree
+    offset: Option<Offset>,
 }
 
 impl RootSource {
     fn new(sender: PeerId, path: RootPath) -> Self {
-        Self { sender, path }
+        Self {
+            sender,
+            path,
+            offset: None,
+        }
+    }
+
+    fn with_offset(self, offset: Option<Offset>) -> Self {
+        Self { offset, ..self }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RootPath {
     // needs to be ordered in ascending priority
+    /// Announced by an untrusted peer, its lamport and offset are only applied once the tree has
+    /// been synced and validated
+    Deferred,
     RootMap,
     SlowPath,
     FastPath,
@@ -409,6 +809,7 @@ pub enum RootPath {
 #[test]
 fn root_path_is_ordered() {
     use RootPath::*;
+    assert!(Deferred < RootMap);
     assert!(RootMap < SlowPath);
     assert!(SlowPath < FastPath);
 
@@ -419,6 +820,8 @@ fn root_path_is_ordered() {
 /// All immutable or internally mutable parts of the banyan store
 struct BanyanStoreData {
     topic: String,
+    /// message counters for the previous topics we still listen to
+    previous_topics: PreviousTopics,
     gossip: Gossip,
     forest: Forest,
     ipfs: Ipfs,
@@ -427,8 +830,85 @@ struct BanyanStoreData {
     offsets: Variable<SwarmOffsets>,
     /// lamport timestamp for publishing to internal streams
     lamport: Observer<LamportTimestamp>,
-    /// Routing table
-    routing_table: Lazy<RoutingTable, Box<dyn FnOnce() -> RoutingTable + Send>>,
+    /// Routing table, replaced as a whole by [`BanyanStore::update_event_routes`]
+    routing_table: Mutex<Arc<RoutingTable>>,
+    /// retentions of the internal streams published on first start, see
+    /// [`BanyanStore::effective_ephemeral_events`]
+    builtin_retentions: Mutex<BTreeMap<String, RetainConfig>>,
+    /// payloads above this size are stored as blobs
+    payload_blob_threshold: Option<usize>,
+    /// how long idempotency tokens of appends are remembered
+    append_token_ttl: Duration,
+    /// how long tombstoned streams are kept before being purged
+    tombstone_retention: Duration,
+    /// for signing tombstones of our own streams
+    keypair: KeyPair,
+    /// peers whose messages are currently ignored
+    quarantine: PeerQuarantine,
+    /// which remote streams to replicate
+    replication: ReplicationConfig,
+    /// when compaction and pruning may run at full speed
+    maintenance: MaintenanceSchedule,
+    /// limits for received lamports
+    lamport_config: LamportConfig,
+    /// checks for received gossip
+    gossip_validation: GossipValidationConfig,
+    /// peers whose lamports were rejected
+    rejected_lamports: Mutex<RejectedLamports>,
+    /// peers that announced implausible lamports or offsets not matching the announced tree
+    untrusted_peers: Mutex<UntrustedPeers>,
+    /// blocks exchanged with each peer
+    transfers: TransferStats,
+    /// counters exposed via [`BanyanStore::metrics_registry`]
+    swarm_metrics: SwarmMetrics,
+    /// replication decisions, if enabled
+    decision_log: Option<DecisionLog>,
+    enable_prometheus: bool,
+    read_only: bool,
+    /// banyan secrets per stream
+    secrets: Arc<dyn SecretProvider>,
+    /// settings of the periodic tasks, may change at runtime
+    settings: Variable<RuntimeSwarmSettings>,
+    /// listeners of the swarm, may change at runtime
+    listeners: Listeners,
+    /// subscribers of [`BanyanStore::peer_events`]
+    peer_events: PeerEvents,
+    /// subscribers of [`BanyanStore::stream_heartbeats`]
+    stream_heartbeats: StreamHeartbeats,
+    /// subscribers of the offsets in the root maps of peers, see [`BanyanStore::connectivity`]
+    peer_root_offsets: PeerRootOffsets,
+    /// requests of [`BanyanStore::compare_offsets`] waiting for an answer
+    offsets_exchange: OffsetsExchange,
+    /// see [`BanyanStore::startup_report`]
+    startup_report: Mutex<StartupReport>,
+    /// superseded roots kept from garbage collection, see [`BanyanStore::gc_grace_roots`]
+    gc_grace: GcGrace,
+    /// see [`BanyanStore::tag_stats`]
+    tag_stats: Mutex<TagStatsCache>,
+    /// own streams to be packed by the compaction loop
+    pack_requests: PackRequests,
+}
+
+impl BanyanStoreData {
+    /// Secrets for loading the trees of a stream. Without its secrets the index of a stream can
+    /// still be read for replication, but not its events.
+    fn tree_secrets(&self, stream_id: StreamId) -> Secrets {
+        self.secrets.secrets(stream_id).unwrap_or_default()
+    }
+
+    /// Whether the events of the stream can be decrypted on this node
+    fn is_readable(&self, stream_id: StreamId) -> bool {
+        self.secrets.secrets(stream_id).is_some()
+    }
+
+    fn ensure_readable(&self, stream_id: StreamId) -> Result<()> {
+        anyhow::ensure!(
+            self.is_readable(stream_id),
+            "no secret available to read the events of stream {}",
+            stream_id
+        );
+        Ok(())
+    }
 }
 
 /// Internal mutable state of the stream manager
@@ -444,6 +924,13 @@ struct BanyanStoreState {
     /// all remote nodes we know of
     remote_nodes: BTreeMap<NodeId, RemoteNodeInner>,
 
+    /// known streams of which only the published tree has been read, see
+    /// [`SwarmConfig::lazy_stream_loading`]
+    dormant_streams: BTreeMap<StreamId, PublishedTree>,
+
+    /// streams that have been tombstoned, including those already purged
+    tombstones: BTreeMap<StreamId, Tombstone>,
+
     /// dispatcher to tell interested parties of newly discovered streams
     known_streams: Vec<mpsc::UnboundedSender<StreamId>>,
 
@@ -498,51 +985,88 @@ impl<'a> BanyanStoreGuard<'a> {
     }
 
     fn local_stream_nrs(&self) -> Vec<StreamNr> {
-        self.own_streams.keys().cloned().collect::<Vec<_>>()
+        let node_id = self.node_id();
+        let dormant = self
+            .dormant_streams
+            .keys()
+            .filter(|stream_id| stream_id.node_id() == node_id)
+            .map(|stream_id| stream_id.stream_nr());
+        self.own_streams.keys().cloned().chain(dormant).collect::<Vec<_>>()
+    }
+
+    /// Reads the tree currently aliased for the stream, without loading more than its root.
+    fn read_published_tree(&self, stream_id: StreamId) -> Result<Option<PublishedTree>> {
+        let root = match self
+            .data
+            .ipfs
+            .resolve(StreamAlias::from(stream_id))
+            .context("no alias for stream id")?
+        {
+            Some(root) => Link::try_from(root).context("wrong link format")?,
+            None => return Ok(None),
+        };
+        let header = self.data.forest.store().get(&root).context("header not found")?;
+        let header: AxTreeHeader = DagCborCodec.decode(&header).context("invalid header")?;
+        let tree = self
+            .data
+            .forest
+            .load_tree(self.data.tree_secrets(stream_id), header.root)
+            .with_context(|| format!("unable to load banyan tree for stream {}", stream_id))?;
+        Ok(Some(PublishedTree::new(root, header, tree)))
     }
 
     fn get_or_create_own_stream(&mut self, stream_nr: StreamNr) -> Result<Arc<OwnStream>> {
         if let Some(result) = self.own_streams.get(&stream_nr).cloned() {
             return Ok(result);
         }
-        tracing::debug!("creating new own stream {}", stream_nr);
         let stream_id = self.node_id().stream(stream_nr);
-        self.index_store
-            .add_stream(stream_id)
-            .context("unable to write stream id")?;
-        let (builder, latest) = if let Some(root) = self
+        anyhow::ensure!(!self.is_purged(stream_id), "stream {} has been purged", stream_id);
+        tracing::debug!("creating new own stream {}", stream_nr);
+        let secrets = self
+            .data
+            .secrets
+            .secrets(stream_id)
+            .with_context(|| format!("no secret available for own stream {}", stream_id))?;
+        let root = self
             .data
             .ipfs
             .resolve(StreamAlias::from(stream_id))
-            .context("no alias for stream id")?
-        {
+            .context("no alias for stream id")?;
+        if root.is_none() && self.data.read_only {
+            return Err(ReadOnlyError.into());
+        }
+        // dormant streams have already been announced as known
+        let dormant = self.dormant_streams.remove(&stream_id).is_some();
+        self.index_store
+            .add_stream(stream_id)
+            .context("unable to write stream id")?;
+        let (builder, latest) = if let Some(root) = root {
             let root = Link::try_from(root).context("wrong link format")?;
             let header = self.data.forest.store().get(&root).context("header not found")?;
             let header: AxTreeHeader = DagCborCodec.decode(&header).context("invalid header")?;
             let builder = self
                 .data
                 .forest
-                .load_stream_builder(
-                    self.banyan_config.secret.clone(),
-                    self.banyan_config.tree.clone(),
-                    header.root,
-                )
+                .load_stream_builder(secrets, self.banyan_config.tree.clone(), header.root)
                 .with_context(|| format!("unable to load banyan tree for stream {}", stream_nr))?;
             let published = PublishedTree::new(root, header, builder.snapshot());
             (builder, Some(published))
         } else {
-            let builder = StreamBuilder::new(self.banyan_config.tree.clone(), self.banyan_config.secret.clone());
+            let builder = StreamBuilder::new(self.banyan_config.tree.clone(), secrets);
             (builder, None)
         };
         let stream = Arc::new(OwnStream::new(stream_nr, builder, latest));
         self.own_streams.insert(stream_nr, stream.clone());
-        tracing::debug!("publish new stream_id {}", stream_id);
-        self.publish_new_stream_id(stream_id);
+        if !dormant {
+            tracing::debug!("publish new stream_id {}", stream_id);
+            self.publish_new_stream_id(stream_id);
+        }
         Ok(stream)
     }
 
     fn get_or_create_replicated_stream(&mut self, stream_id: StreamId) -> Result<Arc<ReplicatedStream>> {
         debug_assert!(!self.is_local(stream_id));
+        anyhow::ensure!(!self.is_purged(stream_id), "stream {} has been purged", stream_id);
         self.index_store
             .add_stream(stream_id)
             .context("unable to write stream id")?;
@@ -551,18 +1075,9 @@ impl<'a> BanyanStoreGuard<'a> {
         if let Some(stream) = self.get_or_create_remote_node(node_id).streams.get(&stream_nr).cloned() {
             return Ok(stream);
         }
-        let state = if let Some(root) = self.data.ipfs.resolve(StreamAlias::from(stream_id)).unwrap() {
-            let root = Link::try_from(root).context("wrong link format")?;
-            let header = self.data.forest.store().get(&root).context("header not found")?;
-            let header: AxTreeHeader = DagCborCodec.decode(&header).context("invalid header")?;
-            let tree = self
-                .data
-                .forest
-                .load_tree(Secrets::default(), header.root)
-                .with_context(|| format!("unable to load banyan tree for stream {}", stream_id))?;
-            Some(PublishedTree::new(root, header, tree))
-        } else {
-            None
+        let (dormant, state) = match self.dormant_streams.remove(&stream_id) {
+            Some(tree) => (true, Some(tree)),
+            None => (false, self.read_published_tree(stream_id)?),
         };
         tracing::debug!("creating new replicated stream {}", stream_id);
         let stream = Arc::new(ReplicatedStream::new(state));
@@ -574,8 +1089,10 @@ impl<'a> BanyanStoreGuard<'a> {
             format!("careful_ingestion({})", stream_id),
             store.careful_ingestion(stream_id, stream.clone()).boxed(),
         );
-        tracing::debug!("publish new stream_id {}", stream_id);
-        self.publish_new_stream_id(stream_id);
+        if !dormant {
+            tracing::debug!("publish new stream_id {}", stream_id);
+            self.publish_new_stream_id(stream_id);
+        }
         Ok(stream)
     }
 
@@ -584,7 +1101,9 @@ impl<'a> BanyanStoreGuard<'a> {
     }
 
     fn has_stream(&self, stream_id: StreamId) -> bool {
-        if self.is_local(stream_id) {
+        if self.dormant_streams.contains_key(&stream_id) {
+            true
+        } else if self.is_local(stream_id) {
             self.own_streams.contains_key(&stream_id.stream_nr())
         } else {
             self.remote_nodes
@@ -596,7 +1115,9 @@ impl<'a> BanyanStoreGuard<'a> {
 
     /// Get the last PublishedTree for a stream_id, only if it already exists
     fn published_tree(&self, stream_id: StreamId) -> Option<PublishedTree> {
-        if self.is_local(stream_id) {
+        if let Some(tree) = self.dormant_streams.get(&stream_id) {
+            Some(tree.clone())
+        } else if self.is_local(stream_id) {
             let stream_nr = stream_id.stream_nr();
             let stream = self.own_streams.get(&stream_nr)?;
             stream.published_tree()
@@ -611,16 +1132,32 @@ impl<'a> BanyanStoreGuard<'a> {
 
     /// Get a stream of trees for a given stream id
     fn tree_stream(&mut self, stream_id: StreamId) -> impl Stream<Item = Tree> {
-        if self.is_local(stream_id) {
-            let stream_nr = stream_id.stream_nr();
-            let stream = self.get_or_create_own_stream(stream_nr).unwrap();
-            stream.tree_stream()
+        if self.is_purged(stream_id) {
+            // the events are gone, so queries end right away instead of waiting for them
+            stream::empty().boxed()
+        } else if self.is_local(stream_id) {
+            match self.get_or_create_own_stream(stream_id.stream_nr()) {
+                Ok(stream) => stream.tree_stream(),
+                // a read-only replica does not create the stream, so there are no events
+                Err(err) if err.is::<ReadOnlyError>() => stream::empty().boxed(),
+                Err(err) => panic!("cannot load own stream {}: {:#}", stream_id, err),
+            }
         } else {
             let stream = self.get_or_create_replicated_stream(stream_id).unwrap();
             stream.tree_stream()
         }
     }
 
+    /// Get the current and all future stream ids
+    fn subscribe_known_streams(&mut self) -> mpsc::UnboundedReceiver<StreamId> {
+        let (s, r) = mpsc::unbounded();
+        for stream_id in self.current_stream_ids() {
+            let _ = s.unbounded_send(stream_id);
+        }
+        self.known_streams.push(s);
+        r
+    }
+
     pub fn publish_new_stream_id(&mut self, stream_id: StreamId) {
         self.known_streams
             .retain(|sender| sender.unbounded_send(stream_id).is_ok())
@@ -635,7 +1172,9 @@ impl<'a> BanyanStoreGuard<'a> {
                 .keys()
                 .map(move |stream_nr| node_id.stream(*stream_nr))
         });
-        own_stream_ids.chain(replicated_stream_ids)
+        own_stream_ids
+            .chain(replicated_stream_ids)
+            .chain(self.dormant_streams.keys().copied())
     }
 
     /// Get a complete root map from both own and replicated streams
@@ -651,7 +1190,11 @@ impl<'a> BanyanStoreGuard<'a> {
                 inner.infos().map(|infos| (stream_id, infos))
             })
         });
-        own.chain(other).collect()
+        let dormant = self
+            .dormant_streams
+            .iter()
+            .map(|(stream_id, tree)| (*stream_id, (Cid::from(tree.root()), tree.offset(), tree.lamport())));
+        own.chain(other).chain(dormant).collect()
     }
 
     pub fn get_or_create_remote_node(&mut self, node_id: NodeId) -> &mut RemoteNodeInner {
@@ -665,13 +1208,21 @@ impl<'a> BanyanStoreGuard<'a> {
     pub fn spawn_task(&mut self, name: String, task: BoxFuture<'static, ()>) {
         tracing::debug!("Spawning task '{}'!", name);
         let name2 = name.clone();
-        let handle =
-            tokio::spawn(task.map(move |_| tracing::error!("Fatal: Task '{}' unexpectedly terminated!", name2)));
+        // dropped when the task ends or is aborted
+        let running = self.data.swarm_metrics.task_started(&name);
+        let handle = tokio::spawn(task.map(move |_| {
+            drop(running);
+            tracing::error!("Fatal: Task '{}' unexpectedly terminated!", name2)
+        }));
         self.tasks.push((name, handle));
     }
 
+    fn has_task(&self, name: &str) -> bool {
+        self.tasks.iter().any(|(label, _)| label == name)
+    }
+
     /// Aborts a task.
-    pub fn abort_task(&mut self, name: &'static str) {
+    pub fn abort_task(&mut self, name: &str) {
         self.tasks.retain(|(label, handle)| {
             if *label == name {
                 handle.abort();
@@ -706,17 +1257,37 @@ impl<'a> BanyanStoreGuard<'a> {
                 present.update(stream_id, tree.offset());
             }
         }
+        // purged streams keep their last offset, so that they are not replicated again
+        for (stream_id, tombstone) in &self.tombstones {
+            present.update(*stream_id, tombstone.offset);
+        }
+        let present = VersionedOffsets::from(&present);
         SwarmOffsets {
             replication_target: present.clone(),
             present,
         }
     }
 
-    fn load_known_streams(&mut self) -> Result<u64> {
+    /// Loads all streams known to the index store. With `lazy` only their published trees are
+    /// read, the streams are loaded when first accessed.
+    fn load_known_streams(&mut self, lazy: bool) -> Result<u64> {
         let known_streams = self.index_store.get_observed_streams()?;
         let mut max_lamport = None;
         let mut local_streams = 0;
         for stream_id in known_streams {
+            if self.has_stream(stream_id) || self.is_purged(stream_id) {
+                continue;
+            }
+            if lazy {
+                if let Some(tree) = self.read_published_tree(stream_id)? {
+                    if self.is_local(stream_id) {
+                        local_streams += 1;
+                    }
+                    max_lamport = max_lamport.max(Some(tree.lamport()));
+                    self.dormant_streams.insert(stream_id, tree);
+                    continue;
+                }
+            }
             // just trigger loading of the stream from the alias
             // NOTE: I can return the number of local streams and then use it to check if I need to write the mappings
             // happens when no mappings are present + the number of streams is bigger than 1
@@ -743,8 +1314,44 @@ impl<'a> BanyanStoreGuard<'a> {
 
 impl BanyanStore {
     /// Creates a new [`BanyanStore`] from a [`SwarmConfig`].
-    pub async fn new(mut cfg: SwarmConfig, swarm_observer: ActoRef<(PeerId, GossipMessage)>) -> Result<Self> {
+    pub async fn new(cfg: SwarmConfig, swarm_observer: ActoRef<(PeerId, GossipMessage)>) -> Result<Self> {
+        Self::create(cfg, swarm_observer, true).await
+    }
+
+    /// Creates a [`BanyanStore`] without any networking, keeping blocks and index in memory.
+    ///
+    /// Appends and queries work as usual, but nothing is replicated: there are no listeners, no
+    /// discovery and no gossip, and none of the maintenance tasks are started. Meant for testing
+    /// code built on top of the store.
+    pub async fn offline(cfg: SwarmConfig) -> Result<Self> {
+        let cfg = SwarmConfig {
+            db_path: None,
+            index_store: None,
+            listen_addresses: Arc::new(Mutex::new(SocketAddrHelper::empty())),
+            bootstrap_addresses: vec![],
+            external_addresses: vec![],
+            enable_loopback: false,
+            enable_fast_path: false,
+            enable_slow_path: false,
+            enable_mdns: false,
+            enable_root_map: false,
+            enable_discovery: false,
+            enable_metrics: false,
+            ..cfg
+        };
+        Self::create(cfg, ActoRef::blackhole(), false).await
+    }
+
+    async fn create(
+        mut cfg: SwarmConfig,
+        swarm_observer: ActoRef<(PeerId, GossipMessage)>,
+        online: bool,
+    ) -> Result<Self> {
         tracing::debug!("client_from_config({:?})", cfg);
+        if let Err(errors) = cfg.validate() {
+            let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            anyhow::bail!("invalid swarm configuration: {}", errors.join("; "));
+        }
         tracing::debug!("Start listening on topic '{}'", &cfg.topic);
 
         let keypair = cfg.keypair.unwrap_or_else(KeyPair::generate);
@@ -755,54 +1362,63 @@ impl BanyanStore {
             .node_name
             .unwrap_or_else(|| names::Generator::with_naming(names::Name::Numbered).next().unwrap());
 
-        let mut ipfs = Ipfs::new(IpfsConfig {
-            network: NetworkConfig {
-                enable_loopback: cfg.enable_loopback,
-                port_reuse: false,
-                keep_alive: true,
-                node_key,
-                node_name: node_name.clone(),
-                psk: cfg.psk,
-                mdns: if cfg.enable_mdns {
-                    Some(Default::default())
-                } else {
-                    None
-                },
-                kad: None,
-                dns: if cfg!(target_os = "android") {
-                    // No official support for DNS on Android.
-                    // see https://github.com/Actyx/Cosmos/issues/6582
-                    Some(DnsConfig::Custom {
-                        config: ResolverConfig::cloudflare(),
-                        opts: Default::default(),
-                    })
-                } else {
-                    Some(DnsConfig::SystemWithFallback {
-                        config: ResolverConfig::cloudflare(),
-                        opts: Default::default(),
-                    })
-                },
-                ping: Some(
-                    ping::Config::new()
-                        .with_interval(Duration::from_secs(20))
-                        .with_timeout(cfg.ping_timeout)
-                        .with_max_failures(NonZeroU32::new(3).unwrap()),
-                ),
-                identify: Some(
-                    identify::Config::new("/actyx/2.0.0".to_string(), Ed25519(public)).with_agent_version(node_name),
-                ),
-                gossipsub: Some(
-                    GossipsubConfigBuilder::default()
-                        .validation_mode(ValidationMode::Permissive)
-                        .build()
-                        .expect("valid gossipsub config"),
-                ),
-                broadcast: Some(Default::default()),
-                bitswap: Some(BitswapConfig {
-                    request_timeout: cfg.bitswap_timeout,
-                    connection_keep_alive: cfg.bitswap_timeout,
-                }),
+        let mut network = NetworkConfig {
+            enable_loopback: cfg.enable_loopback,
+            port_reuse: false,
+            keep_alive: true,
+            node_key,
+            node_name: node_name.clone(),
+            psk: cfg.psk,
+            mdns: if cfg.enable_mdns {
+                Some(Default::default())
+            } else {
+                None
+            },
+            kad: None,
+            dns: if cfg!(target_os = "android") {
+                // No official support for DNS on Android.
+                // see https://github.com/Actyx/Cosmos/issues/6582
+                Some(DnsConfig::Custom {
+                    config: ResolverConfig::cloudflare(),
+                    opts: Default::default(),
+                })
+            } else {
+                Some(DnsConfig::SystemWithFallback {
+                    config: ResolverConfig::cloudflare(),
+                    opts: Default::default(),
+                })
             },
+            ping: Some(
+                ping::Config::new()
+                    .with_interval(Duration::from_secs(20))
+                    .with_timeout(cfg.ping_timeout)
+                    .with_max_failures(NonZeroU32::new(3).unwrap()),
+            ),
+            identify: Some(
+                identify::Config::new("/actyx/2.0.0".to_string(), Ed25519(public)).with_agent_version(node_name),
+            ),
+            gossipsub: Some(
+                GossipsubConfigBuilder::default()
+                    .validation_mode(cfg.gossip_validation.validation_mode())
+                    .build()
+                    .expect("valid gossipsub config"),
+            ),
+            broadcast: Some(Default::default()),
+            bitswap: Some(BitswapConfig {
+                request_timeout: cfg.bitswap_timeout,
+                connection_keep_alive: cfg.bitswap_timeout,
+            }),
+        };
+        if !online {
+            network.dns = None;
+            network.ping = None;
+            network.identify = None;
+            network.gossipsub = None;
+            network.broadcast = None;
+            network.bitswap = None;
+        }
+        let mut ipfs = Ipfs::new(IpfsConfig {
+            network,
             storage: StorageConfig {
                 access_db_path: None, // in memory
                 path: cfg.db_path,
@@ -832,52 +1448,13 @@ impl BanyanStore {
             }
         }
         let listen_addrs = cfg.listen_addresses.lock().iter().collect::<Vec<_>>();
+        let mut listeners = Vec::with_capacity(listen_addrs.len());
         for addr in listen_addrs {
-            let maddr = to_multiaddr(addr);
-            let mut listener = ipfs.listen_on(maddr.clone());
-
-            match listener.next().await {
-                Some(ListenerEvent::NewListenAddr(bound_addr)) => {
-                    // we print only the first of the discovered addresses, but the others will also be found
-                    tracing::info!(target: "SWARM_SERVICES_BOUND", "Swarm Services bound to {}.", bound_addr);
-                    if let Some(bound_addr) = to_socket_addr(bound_addr) {
-                        cfg.listen_addresses.lock().inject_bound_addr(addr, bound_addr);
-                    }
-                }
-                Some(ListenerEvent::ListenFailed(_addr, reason)) => {
-                    return Err(anyhow::anyhow!("bind failed: {}", reason)).with_context(|| {
-                        NodeErrorContext::BindFailed {
-                            addr: maddr.clone(),
-                            component: "Swarm".into(),
-                        }
-                    })
-                }
-                e => {
-                    return Err(anyhow::anyhow!("got unexpected event {:?}", e)).with_context(|| {
-                        NodeErrorContext::BindFailed {
-                            addr: maddr,
-                            component: "Swarm".into(),
-                        }
-                    })
-                }
+            let listener = Listener::bind(&ipfs, to_listen_multiaddr(addr)?).await?;
+            if let Some(bound_addr) = to_socket_addr(listener.bound().clone()) {
+                cfg.listen_addresses.lock().inject_bound_addr(addr, bound_addr);
             }
-
-            // print the remaining listen addresses asynchronously
-            tokio::spawn(async move {
-                while let Some(ev) = listener.next().await {
-                    match ev {
-                        ListenerEvent::NewListenAddr(bound_addr) => {
-                            tracing::info!(target: "SWARM_SERVICES_BOUND", "Swarm Services bound to {}.", bound_addr)
-                        }
-                        ListenerEvent::ExpiredListenAddr(addr) => {
-                            tracing::info!("Swarm Services no longer listening on {}.", addr)
-                        }
-                        ListenerEvent::ListenFailed(addr, reason) => {
-                            tracing::warn!(%addr, %reason, "got belated listen failure");
-                        }
-                    }
-                }
-            });
+            listeners.push(listener);
         }
         let external_addrs = cfg.external_addresses.iter().cloned().collect();
         for addr in cfg.external_addresses {
@@ -902,8 +1479,8 @@ impl BanyanStore {
             }
         }
 
-        let index_store = if let Some(conn) = cfg.index_store {
-            let mut db = SqliteIndexStore::open(DbPath::File(conn))?;
+        let mut index_store = if let Some(conn) = cfg.index_store {
+            let mut db = SqliteIndexStore::open(DbPath::File(conn), cfg.index_store_config)?;
             if db.get_observed_streams()?.is_empty() {
                 // either a new store or migrating from pre-2.9
                 let aliases = ipfs.aliases()?;
@@ -924,45 +1501,91 @@ impl BanyanStore {
             }
             db
         } else {
-            SqliteIndexStore::open(DbPath::Memory)?
+            SqliteIndexStore::open(DbPath::Memory, cfg.index_store_config)?
         };
         let branch_cache = BranchCache::<TT>::new(cfg.branch_cache_size.try_into().unwrap());
-        let forest = Forest::new(SqliteStore::wrap(ipfs.clone()), branch_cache.clone());
+        let forest = Forest::new(
+            TieredReadStore::new(SqliteStore::wrap(ipfs.clone()), cfg.cold_tiers.clone()),
+            branch_cache.clone(),
+        );
+        let transfers = TransferStats::default();
+        let swarm_metrics = SwarmMetrics::new()?;
+        let decision_log = cfg.decision_log_path.as_deref().map(DecisionLog::new).transpose()?;
+        let secrets = cfg
+            .secret_provider
+            .clone()
+            .unwrap_or_else(|| Arc::new(cfg.banyan_config.secret.clone()));
         let gossip = Gossip::new(
             ipfs.clone(),
             node_id,
             cfg.topic.clone(),
             cfg.enable_fast_path,
             cfg.enable_slow_path,
+            cfg.cadence_fast_path_batch,
+            cfg.publish_policy.clone(),
             swarm_observer.clone(),
+            transfers.clone(),
+            swarm_metrics.clone(),
         );
-        let routing_table_writer = Arc::new(Mutex::new(None));
-        let routing_table_reader = routing_table_writer.clone();
+        cfg.peer_quarantine.set_config(cfg.quarantine);
+        cfg.maintenance_schedule.set_config(cfg.maintenance.clone());
+        let tombstones = index_store.get_tombstones()?;
         let banyan = Self {
             data: Arc::new(BanyanStoreData {
                 topic: cfg.topic.clone(),
+                previous_topics: Default::default(),
                 node_id,
                 ipfs,
                 gossip,
                 forest,
                 lamport: index_store.observe_lamport(),
                 offsets: Default::default(),
-                routing_table: Lazy::new(Box::new(move || routing_table_reader.lock().take().unwrap())),
+                routing_table: Default::default(),
+                builtin_retentions: Default::default(),
+                payload_blob_threshold: cfg.payload_blob_threshold,
+                append_token_ttl: cfg.append_token_ttl,
+                tombstone_retention: cfg.tombstone_retention,
+                keypair,
+                quarantine: cfg.peer_quarantine.clone(),
+                replication: cfg.replication.clone(),
+                maintenance: cfg.maintenance_schedule.clone(),
+                lamport_config: cfg.lamport,
+                gossip_validation: cfg.gossip_validation,
+                rejected_lamports: Default::default(),
+                untrusted_peers: Default::default(),
+                transfers,
+                swarm_metrics,
+                decision_log,
+                enable_prometheus: cfg.enable_prometheus,
+                read_only: cfg.read_only,
+                secrets,
+                settings: Variable::new(RuntimeSwarmSettings::from(&cfg)),
+                listeners: Listeners::new(listeners),
+                peer_events: Default::default(),
+                stream_heartbeats: Default::default(),
+                peer_root_offsets: Default::default(),
+                offsets_exchange: Default::default(),
+                startup_report: Default::default(),
+                gc_grace: GcGrace::new(cfg.gc_grace_period.unwrap_or(cfg.bitswap_timeout * GC_GRACE_FACTOR)),
+                tag_stats: Default::default(),
+                pack_requests: Default::default(),
             }),
             state: Arc::new(ReentrantSafeMutex::new(BanyanStoreState {
                 index_store,
                 own_streams: Default::default(),
                 remote_nodes: Default::default(),
+                dormant_streams: Default::default(),
+                tombstones,
                 known_streams: Default::default(),
                 tasks: Default::default(),
                 banyan_config: cfg.banyan_config,
             })),
         };
         tracing::info!("loading event streams");
-        let local_streams = banyan.lock().load_known_streams()?;
+        let local_streams = banyan.lock().load_known_streams(cfg.lazy_stream_loading)?;
         // check that all known streams are indeed completely present
         tracing::info!("validating event streams");
-        banyan.validate_known_streams().await?;
+        banyan.validate_known_streams(cfg.validation_mode).await?;
 
         let routing_table_span = tracing::debug_span!("Initializing routing table.");
         let known_mappings = banyan.get_published_mappings(node_id).await?;
@@ -1011,8 +1634,10 @@ impl BanyanStore {
                         .add_stream(stream_name, Some(stream_nr))
                         .expect("The stream should not have been previously added.");
 
-                    cfg.ephemeral_event_config
-                        .streams
+                    banyan
+                        .data
+                        .builtin_retentions
+                        .lock()
                         .insert(stream_name.to_string(), retain_cfg);
                 }
             }
@@ -1022,6 +1647,10 @@ impl BanyanStore {
             }
         }
 
+        // numbers registered by name are not published as mappings but must not be handed out
+        let names = banyan.lock().index_store.get_stream_names()?;
+        routing_table.max_stream_nr = names.values().copied().chain(routing_table.max_stream_nr).max();
+
         let unpublished_mappings = {
             let mut unpublished_mappings = HashMap::new();
 
@@ -1033,39 +1662,22 @@ impl BanyanStore {
             unpublished_mappings
         };
 
-        cfg.ephemeral_event_config.streams = cfg
-            .ephemeral_event_config
-            .streams
-            .into_iter()
-            .filter(|(stream, _)| {
-                if stream == "default" {
-                    tracing::warn!(
-                        "The \"default\" stream cannot be configured, its retention configuration will be ignored."
-                    );
-                    return false;
-                }
-
-                let is_stream_mapped = routing_table.stream_mapping.get(stream).is_some();
-                if !is_stream_mapped {
-                    tracing::warn!(
-                        "The stream \"{}\" does not have a mapping, its retention configuration will be ignored.",
-                        stream
-                    );
-                }
-                is_stream_mapped
-            })
-            .collect::<_>();
-
         drop(routing_table_span_entered);
         for (name, number) in unpublished_mappings {
             banyan.append_stream_mapping_event(name, number).await?;
         }
         let routing_table_span_entered = routing_table_span.enter();
-        *routing_table_writer.lock() = Some(routing_table);
+        *banyan.data.routing_table.lock() = Arc::new(routing_table);
+        // pruning must only ever see the retentions that survived the routing table
+        let ephemeral_events = banyan.effective_ephemeral_events(cfg.ephemeral_event_config);
+        banyan.data.settings.write().ephemeral_events = ephemeral_events;
 
         tracing::debug!("Finished setting up routing.");
         drop(routing_table_span_entered);
         drop(routing_table_span);
+        if !online {
+            return Ok(banyan);
+        }
 
         tracing::info!("starting maintenance tasks");
         banyan.spawn_task(
@@ -1084,50 +1696,116 @@ impl BanyanStore {
                 .await?
                 .boxed(),
         );
+        let previous_topics = cfg
+            .previous_topics
+            .iter()
+            .filter(|topic| **topic != cfg.topic)
+            .collect::<BTreeSet<_>>();
+        if !cfg.previous_topics_grace.is_zero() {
+            for topic in previous_topics {
+                tracing::info!("also listening on previous topic '{}'", topic);
+                banyan.spawn_task(
+                    format!("gossip_ingest_previous_{}", topic),
+                    Gossip::ingest_previous(
+                        banyan.clone(),
+                        topic.clone(),
+                        cfg.previous_topics_grace,
+                        swarm_observer.clone(),
+                    )
+                    .await?
+                    .boxed(),
+                );
+            }
+        }
+        if !cfg.read_only {
+            banyan.spawn_task(
+                "quarantine_events".to_owned(),
+                quarantine::quarantine_events(banyan.clone()).boxed(),
+            );
+        }
         if cfg.enable_root_map {
             banyan.spawn_task(
                 "gossip_publish_root_map".to_owned(),
                 banyan
                     .data
                     .gossip
-                    .publish_root_map(banyan.clone(), cfg.topic.clone(), cfg.cadence_root_map, swarm_observer)
+                    .publish_root_map(banyan.clone(), cfg.topic.clone(), swarm_observer)
+                    .boxed(),
+            );
+        }
+        if cfg.enable_fast_path || cfg.enable_slow_path {
+            banyan.spawn_task(
+                "gossip_replay_outbox".to_owned(),
+                banyan
+                    .data
+                    .gossip
+                    .replay_outbox(banyan.clone(), cfg.topic.clone())
                     .boxed(),
             );
         }
+        if !cfg.read_only {
+            banyan.spawn_task("compaction".to_owned(), banyan.clone().compaction_loop().boxed());
+        }
         banyan.spawn_task(
-            "compaction".to_owned(),
-            banyan.clone().compaction_loop(cfg.cadence_compact).boxed(),
+            "purge_tombstones".to_owned(),
+            tombstone::purge_tombstones(banyan.clone()).boxed(),
         );
+        if cfg.gc_grace_period != Some(Duration::ZERO) {
+            banyan.spawn_task(
+                "release_superseded_roots".to_owned(),
+                gc_grace::release_superseded_roots(banyan.clone()).boxed(),
+            );
+        }
         if cfg.enable_discovery {
+            discovery::add_known_peers(&banyan, cfg.known_peers_max_age)?;
             banyan.spawn_task(
                 "discovery_ingest".to_owned(),
                 discovery::discovery_ingest(banyan.clone()).boxed(),
             );
         }
-        // if `cfg.enable_discovery` is not set, this function WON'T emit any
-        // events! It's needed in any case for `ipfs-embed` to do its thing.
+        // if `cfg.enable_discovery` is not set or the node is read-only, this function WON'T emit
+        // any events! It's needed in any case for `ipfs-embed` to do its thing.
         banyan.spawn_task(
             "discovery".to_owned(),
             discovery::discovery_publish(
                 banyan.clone(),
-                swarm_events,
                 external_addrs,
-                cfg.enable_discovery,
+                cfg.enable_discovery && !cfg.read_only,
                 peers,
+                discovery::ConnectionLimits {
+                    max_connections: cfg.max_connections,
+                    max_connections_per_peer: cfg.max_connections_per_peer,
+                },
             )?
             .boxed(),
         );
-        if cfg.enable_metrics {
+        // started after discovery has subscribed, so it sees the first listen addresses
+        banyan.spawn_task(
+            "peer_events".to_owned(),
+            peer_events::translate_swarm_events(banyan.clone(), swarm_events).boxed(),
+        );
+        banyan.spawn_task(
+            "offsets_exchange".to_owned(),
+            offsets_exchange::offsets_exchange(banyan.clone()).boxed(),
+        );
+        if !banyan.streams_to_repair().is_empty() {
             banyan.spawn_task(
-                "metrics".to_owned(),
-                metrics::metrics(banyan.clone(), cfg.metrics_interval)?.boxed(),
+                "repair_streams".to_owned(),
+                startup_validation::repair_streams(banyan.clone()).boxed(),
             );
         }
+        if cfg.enable_metrics && !cfg.read_only {
+            banyan.spawn_task("metrics".to_owned(), metrics::metrics(banyan.clone())?.boxed());
+        }
 
-        banyan.spawn_task(
-            "prune_events".to_owned(),
-            prune::prune(banyan.clone(), cfg.ephemeral_event_config).boxed(),
-        );
+        // pruning rewrites own streams, which a read-only replica must not modify
+        let pruning = banyan
+            .data
+            .settings
+            .project(|s| matches!(s.ephemeral_events, EphemeralEventsConfig::Enabled { .. }));
+        if pruning && !cfg.read_only {
+            banyan.spawn_task("prune_events".to_owned(), prune::prune(banyan.clone()).boxed());
+        }
 
         Ok(banyan)
     }
@@ -1136,23 +1814,128 @@ impl BanyanStore {
         self.data.topic.clone()
     }
 
-    /// Loads the default stream, reading all [RouteMappingEvents] from it and returning
-    /// the respective route mapping.
-    async fn get_published_mappings(&self, node_id: NodeId) -> Result<HashMap<String, StreamNr>> {
-        let stream_id = node_id.stream(DEFAULT_STREAM_NUMBER.into());
+    /// Gossip traffic still arriving on the topics the swarm was previously known under
+    pub fn previous_topics(&self) -> &PreviousTopics {
+        &self.data.previous_topics
+    }
 
-        let tag_expr = format!("'{}' & appId({})", EVENT_ROUTING_TAG_NAME, internal_app_id());
-        let tag_expr = TagExpr::from_str(&tag_expr).expect("The tag expression should be valid.");
-        let query_factory = TagExprQuery::from_expr(&tag_expr).expect("The query should be valid.");
-        // The boolean value we call here is irrelevant
-        let query = query_factory(false, stream_id);
+    /// Peers whose messages are currently ignored
+    pub fn quarantine(&self) -> &PeerQuarantine {
+        &self.data.quarantine
+    }
 
-        let published_tree = self.lock().published_tree(stream_id);
-        if let Some(tree) = published_tree {
-            let offset = tree.offset().into();
-            self.stream_filtered_chunked(stream_id, 0..=offset, query)
-                .map_ok(|chunk| {
-                    stream::iter(chunk.data).map(|(_, _, payload)| {
+    /// When heavy background tasks may run at full speed
+    pub fn maintenance(&self) -> &MaintenanceSchedule {
+        &self.data.maintenance
+    }
+
+    /// Blocks and bytes exchanged with each peer since this node was started
+    pub fn transfer_stats(&self) -> Vec<PeerTransferStats> {
+        self.data.transfers.get()
+    }
+
+    /// Checks a lamport received from `peer` before it is fed into the local clock.
+    ///
+    /// Implausible values are not propagated, the peer is flagged in [`lamport_warnings`] and
+    /// charged with a violation towards quarantine instead. The peer is then
+    /// [untrusted](BanyanStore::is_untrusted) for a while.
+    ///
+    /// [`lamport_warnings`]: BanyanStore::lamport_warnings
+    pub(crate) fn accept_lamport(&self, peer: PeerId, stream: Option<StreamId>, lamport: LamportTimestamp) -> bool {
+        let Err(err) = self.data.lamport_config.check(self.data.lamport.get(), lamport) else {
+            return true;
+        };
+        tracing::warn!(peer = %peer, stream = ?stream, "{}", err);
+        self.data.rejected_lamports.lock().record(peer, stream, lamport);
+        self.distrust(peer);
+        self.data.quarantine.record_violation(peer, &err);
+        false
+    }
+
+    /// Charges `peer` with a violation towards quarantine for announcing `root`, whose blocks as
+    /// fetched via bitswap cannot be decoded.
+    fn invalid_synced_block(&self, peer: PeerId, root: Link, err: anyhow::Error) -> anyhow::Error {
+        let err = err.context(format!("invalid blocks synced for {}", root));
+        self.data.quarantine.record_violation(peer, format_args!("{:#}", err));
+        err
+    }
+
+    /// Whether the lamports and offsets announced by `peer` must not be applied before the
+    /// announced tree has been validated.
+    ///
+    /// Roots from untrusted peers are ingested with [`RootPath::Deferred`], so they never displace
+    /// roots announced by other peers. This lasts for [`LamportConfig::untrusted_for`] after the
+    /// last offence.
+    pub(crate) fn is_untrusted(&self, peer: &PeerId) -> bool {
+        self.data.untrusted_peers.lock().contains(peer, Instant::now())
+    }
+
+    fn distrust(&self, peer: PeerId) {
+        let duration = self.data.lamport_config.untrusted_for;
+        self.data.untrusted_peers.lock().insert(peer, Instant::now(), duration);
+    }
+
+    /// Peer addresses learned via discovery, as persisted in the index store
+    pub fn known_peers(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let addresses = match self.lock().index_store.get_peer_addresses() {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                tracing::warn!("error reading known peers: {}", err);
+                return vec![];
+            }
+        };
+        let mut peers = BTreeMap::<PeerId, Vec<Multiaddr>>::new();
+        for (peer, addr, _) in addresses {
+            peers.entry(peer).or_default().push(addr);
+        }
+        peers.into_iter().collect()
+    }
+
+    /// Problems with the lamport clock: local values close to the end of the range and
+    /// implausible values received from other peers
+    pub fn lamport_warnings(&self) -> Vec<String> {
+        let lamport = self.data.lamport.get();
+        let mut warnings = Vec::new();
+        if self.data.lamport_config.is_high(lamport) {
+            warnings.push(format!(
+                "local lamport {} exceeds the high-water mark {} of the range up to {}",
+                lamport, self.data.lamport_config.high_water, MAX_LAMPORT
+            ));
+        }
+        for (peer, (stream, rejected)) in self.data.rejected_lamports.lock().iter() {
+            match stream {
+                Some(stream) => warnings.push(format!(
+                    "rejected lamport {} from peer {} for stream {}",
+                    rejected, peer, stream
+                )),
+                None => warnings.push(format!("rejected lamport {} from peer {}", rejected, peer)),
+            }
+        }
+        warnings
+    }
+
+    /// Effective interval until the next root map publication (zero if the root map is disabled)
+    pub fn root_map_interval(&self) -> Duration {
+        self.data.gossip.root_map_interval()
+    }
+
+    /// Loads the default stream, reading all [RouteMappingEvents] from it and returning
+    /// the respective route mapping.
+    async fn get_published_mappings(&self, node_id: NodeId) -> Result<HashMap<String, StreamNr>> {
+        let stream_id = node_id.stream(DEFAULT_STREAM_NUMBER.into());
+
+        let tag_expr = format!("'{}' & appId({})", EVENT_ROUTING_TAG_NAME, internal_app_id());
+        let tag_expr = TagExpr::from_str(&tag_expr).expect("The tag expression should be valid.");
+        let query_factory = TagExprQuery::from_expr(&tag_expr).expect("The query should be valid.");
+        // The boolean value we call here is irrelevant
+        let query = query_factory(false, stream_id);
+
+        let published_tree = self.lock().published_tree(stream_id);
+        if let Some(tree) = published_tree {
+            let offset = tree.offset().into();
+            self.stream_filtered_chunked(stream_id, 0..=offset, query)
+                .map_ok(|chunk| {
+                    stream::iter(chunk.data).map(|(_, _, payload)| {
                         payload
                             .extract::<EventRouteMappingEvent>()
                             .map(|event| (event.stream_name, event.stream_nr))
@@ -1190,6 +1973,35 @@ impl BanyanStore {
         self.data.node_id
     }
 
+    pub fn topic(&self) -> &str {
+        &self.data.topic
+    }
+
+    /// Runs `f` with the present offsets while holding the store lock, so that no events are
+    /// appended meanwhile.
+    pub fn with_appends_paused<T>(&self, f: impl FnOnce(&OffsetMap) -> T) -> T {
+        let _store = self.lock();
+        f(&self.offsets().present())
+    }
+
+    /// Current snapshot of the validated and targeted offsets of all known streams.
+    pub fn offsets(&self) -> SwarmOffsets {
+        self.data.offsets.get_cloned()
+    }
+
+    /// Emits the current [`SwarmOffsets`] upon first poll and then whenever they change.
+    ///
+    /// Intermediate values may be skipped when the consumer is slower than the updates, but the
+    /// latest value is always delivered.
+    pub fn offsets_stream(&self) -> impl Stream<Item = SwarmOffsets> + Send + 'static {
+        self.data.offsets.new_observer().dedup()
+    }
+
+    /// Number of events still to be replicated for each stream, see [`SwarmOffsets::replication_lag`].
+    pub fn replication_lag(&self) -> BTreeMap<StreamId, u64> {
+        self.data.offsets.project(SwarmOffsets::replication_lag)
+    }
+
     pub fn is_local(&self, stream_id: StreamId) -> bool {
         self.lock().is_local(stream_id)
     }
@@ -1226,6 +2038,8 @@ impl BanyanStore {
             file if file.data.Type == UnixFsType::File => Ok(FileNode::File {
                 name: name.unwrap_or_default(),
                 cid,
+                size: unixfs_stat::content_size(&file),
+                mtime: unixfs_stat::mtime(&file),
             }),
             // Other file types are not supported
             other => {
@@ -1236,6 +2050,8 @@ impl BanyanStore {
 
     /// Resolves a [`Cid`] and a relative path to a unixfs-v1 [`FileNode`] descriptor. Any needed
     /// intermediate blocks are fetched automatically. The actual data is not resolved.
+    ///
+    /// A path that does not exist fails with an [`UnixfsPathError`].
     pub async fn unixfs_resolve_path(&self, cid: Cid, mut path: VecDeque<String>) -> anyhow::Result<FileNode> {
         let mut n = self.unixfs_resolve(cid, None).await?;
         while let Some(segment) = path.pop_front() {
@@ -1244,11 +2060,15 @@ impl BanyanStore {
                     if let Some(x) = children.iter().find(|x| x.name == segment) {
                         n = self.unixfs_resolve(x.cid, Some(segment)).await?;
                     } else {
-                        anyhow::bail!("Path {} not found inside {}", segment, own_cid);
+                        return Err(UnixfsPathError::NotFound {
+                            segment,
+                            parent: own_cid,
+                        }
+                        .into());
                     }
                 }
                 FileNode::File { name, .. } => {
-                    anyhow::bail!("Found file {} while looking for directory {}", name, segment)
+                    return Err(UnixfsPathError::NotADirectory { file: name, segment }.into())
                 }
             }
         }
@@ -1284,29 +2104,48 @@ impl BanyanStore {
     /// Retrieves the contents of a unixfs-v1 File from the store. If the `pre_sync` bool is set,
     /// the cid will be synced at the beginning. If not, blocks will be fetched on demand.
     pub fn cat(&self, cid: Cid, pre_sync: bool) -> impl Stream<Item = anyhow::Result<Vec<u8>>> {
+        self.cat_range(cid, 0..u64::MAX, pre_sync)
+    }
+
+    /// Retrieves the bytes within `range` of a unixfs-v1 File from the store, see [`cat`](Self::cat).
+    ///
+    /// Blocks entirely outside of the range are not fetched, the first and last chunk are trimmed to
+    /// the range boundaries. The stream ends at the end of the file and yields no empty chunks.
+    pub fn cat_range(
+        &self,
+        cid: Cid,
+        range: Range<u64>,
+        pre_sync: bool,
+    ) -> impl Stream<Item = anyhow::Result<Vec<u8>>> {
+        let is_first = !range.is_empty();
         stream::try_unfold(
-            (self.ipfs().clone(), None, true),
-            move |(ipfs, maybe_step, is_first): (Ipfs, Option<FileVisit>, bool)| async move {
-                if is_first {
-                    debug_assert!(maybe_step.is_none());
-                    if pre_sync {
-                        ipfs.sync(&cid, ipfs.peers()).await?;
-                    }
+            (self.ipfs().clone(), None, is_first),
+            move |(ipfs, maybe_step, is_first): (Ipfs, Option<FileVisit>, bool)| {
+                let range = range.clone();
+                async move {
+                    if is_first {
+                        debug_assert!(maybe_step.is_none());
+                        if pre_sync {
+                            ipfs.sync(&cid, ipfs.peers()).await?;
+                        }
 
-                    let block = ipfs.fetch(&cid, ipfs.peers()).await?;
-                    let (content, _, _, step) = IdleFileVisit::default().start(block.data())?;
-                    Ok(Some((content.to_vec(), (ipfs, step, false))))
-                } else if let Some(visit) = maybe_step {
-                    let (cid, _) = visit.pending_links();
-                    let block = ipfs.fetch(cid, ipfs.peers()).await?;
-                    let (content, next_step) = visit.continue_walk(block.data(), &mut None)?;
+                        let block = ipfs.fetch(&cid, ipfs.peers()).await?;
+                        let (content, _, _, step) =
+                            IdleFileVisit::default().with_target_range(range).start(block.data())?;
+                        Ok(Some((content.to_vec(), (ipfs, step, false))))
+                    } else if let Some(visit) = maybe_step {
+                        let (cid, _) = visit.pending_links();
+                        let block = ipfs.fetch(cid, ipfs.peers()).await?;
+                        let (content, next_step) = visit.continue_walk(block.data(), &mut None)?;
 
-                    Ok(Some((content.to_vec(), (ipfs, next_step, false))))
-                } else {
-                    Ok(None)
+                        Ok(Some((content.to_vec(), (ipfs, next_step, false))))
+                    } else {
+                        Ok(None)
+                    }
                 }
             },
         )
+        .try_filter(|chunk| future::ready(!chunk.is_empty()))
     }
 
     /// Adds a binary blob to the store. Requires aliasing and flushing before dropping the
@@ -1347,38 +2186,91 @@ impl BanyanStore {
         }
     }
 
+    /// Adds the given files as a unixfs-v1 directory, returning the root directory and the total
+    /// size of all files. Paths are `/`-separated and relative to the root directory. Requires
+    /// aliasing and flushing before dropping the `TempPin`.
+    ///
+    /// Nothing is pinned in `tmp` if any of the readers fails; use [`UnixfsDirAdder`] directly
+    /// to also add empty directories.
+    pub fn add_dir<R: Read>(
+        &self,
+        tmp: &mut TempPin,
+        files: impl IntoIterator<Item = (String, R)>,
+    ) -> Result<(Cid, u64)> {
+        let mut adder = UnixfsDirAdder::new(self)?;
+        for (path, reader) in files {
+            adder.add_file(&path, reader)?;
+        }
+        adder.finish(tmp)
+    }
+
     /// Append events to a stream, publishing the new data.
+    ///
+    /// Payloads above the configured `payload_blob_threshold` are stored as blobs and the
+    /// events carry a [`PayloadRef`] instead.
     pub async fn append(&self, app_id: AppId, events: Vec<(TagSet, Event)>) -> Result<Vec<PersistenceMeta>> {
+        if self.data.read_only {
+            return Err(ReadOnlyError.into());
+        }
         let timestamp = Timestamp::now();
 
         let mut metas = Vec::with_capacity(events.len());
-        let mut grouped_events: Vec<(StreamNr, Vec<_>)> = vec![];
+        let mut grouped_events: Vec<(StreamNr, Vec<_>, Vec<Cid>)> = vec![];
+        // the whole batch is routed by the same table, even if the routes are updated meanwhile
+        let routing_table = self.routing_table();
+        // protects the blobs until the referencing events have been written
+        let mut tmp = None;
 
         for (tags, payload) in events {
-            let stream_nr = self.data.routing_table.get_matching_stream_nr(&tags, &app_id);
+            let (payload, blob) = match self.data.payload_blob_threshold {
+                Some(threshold) if payload.as_slice().len() > threshold => {
+                    if tmp.is_none() {
+                        tmp = Some(self.ipfs().create_temp_pin()?);
+                    }
+                    let (payload, cid) = self.externalize_payload(tmp.as_mut().unwrap(), &payload)?;
+                    (payload, Some(cid))
+                }
+                _ => (payload, None),
+            };
+            let stream_nr = routing_table.get_matching_stream_nr(&tags, &app_id);
             let last_entry = grouped_events.last_mut();
-            if let Some((last_stream_nr, events)) = last_entry {
+            if let Some((last_stream_nr, events, blobs)) = last_entry {
                 if *last_stream_nr == stream_nr {
                     events.push((tags, payload));
+                    blobs.extend(blob);
                     continue;
                 }
             }
             // NOTE: should this become a performance bottleneck (probably not now, but I haven’t measured)
             // then this can also be done without allocating additional vectors, by unifying both loops
             // (and taking a slice in append0 instead of the Vec)
-            grouped_events.push((stream_nr, vec![(tags, payload)]));
+            grouped_events.push((stream_nr, vec![(tags, payload)], blob.into_iter().collect()));
         }
 
-        for (stream_nr, events) in grouped_events {
+        for (stream_nr, events, blobs) in grouped_events {
             let n_events = events.len();
-            let append_meta = self.append0(stream_nr, app_id.clone(), timestamp, events).await?;
+            // pinned before the events are written, so that no event ever references a blob that
+            // could be collected
+            for (n, cid) in blobs.iter().enumerate() {
+                if let Err(err) = self.pin_payload_blob(cid) {
+                    self.unpin_payload_blobs(&blobs[..n]);
+                    return Err(err);
+                }
+            }
+            let append_meta = match self.append0(stream_nr, app_id.clone(), timestamp, events).await {
+                Ok(append_meta) => append_meta,
+                Err(err) => {
+                    self.unpin_payload_blobs(&blobs);
+                    return Err(err);
+                }
+            };
             metas.extend((0..n_events).map(|n| {
                 let n = n as u64;
                 (
-                    append_meta.min_lamport + n,
-                    append_meta.min_offset.increase(n).unwrap(),
+                    append_meta.min_lamport() + n,
+                    append_meta.min_offset().increase(n).unwrap(),
                     stream_nr,
-                    append_meta.timestamp,
+                    append_meta.timestamp(),
                 )
             }));
         }
@@ -1387,6 +2279,10 @@ impl BanyanStore {
     }
 
     async fn append_stream_mapping_event(&self, name: String, number: StreamNr) -> Result<()> {
+        if self.data.read_only {
+            // the mapping is only kept in the routing table of this node
+            return Ok(());
+        }
         let event = EventRouteMappingEvent {
             stream_name: name,
             stream_nr: number,
@@ -1411,9 +2307,41 @@ impl BanyanStore {
         app_id: AppId,
         timestamp: Timestamp,
         events: Vec<(TagSet, Event)>,
+    ) -> Result<AppendMeta> {
+        self.append_with_token(stream_nr, app_id, timestamp, events, None).await
+    }
+
+    /// Append events to one of the local streams unless a batch with the same `token` has been
+    /// appended within the last `append_token_ttl`.
+    ///
+    /// A retried batch gets the [`AppendMeta`] of the original append, so clients can safely repeat
+    /// an append after a timeout. Tokens are not tied to a stream, a token reused for another
+    /// stream also returns the original result.
+    pub async fn append_idempotent(
+        &self,
+        stream_nr: StreamNr,
+        app_id: AppId,
+        events: Vec<(TagSet, Event)>,
+        token: [u8; 32],
+    ) -> Result<AppendMeta> {
+        self.append_with_token(stream_nr, app_id, Timestamp::now(), events, Some(&token))
+            .await
+    }
+
+    async fn append_with_token(
+        &self,
+        stream_nr: StreamNr,
+        app_id: AppId,
+        timestamp: Timestamp,
+        events: Vec<(TagSet, Event)>,
+        token: Option<&[u8; 32]>,
     ) -> Result<AppendMeta> {
         debug_assert!(!events.is_empty());
+        if self.data.read_only {
+            return Err(ReadOnlyError.into());
+        }
         tracing::debug!("publishing {} events on stream {}", events.len(), stream_nr);
+        self.check_stream_complete(self.node_id().stream(stream_nr))?;
         let stream = self.get_or_create_own_stream(stream_nr)?;
         let mut guard = stream.lock().await;
 
@@ -1424,7 +2352,38 @@ impl BanyanStore {
         // to the streams before we are done, because that might break lamport ordering within
         // the streams.
         let mut store = self.lock();
-        let mut lamports = store.reserve_lamports(events.len())?.peekable();
+        // checking the token under the store lock makes concurrent retries append only once
+        if let Some(token) = token {
+            if let Some(meta) = store.index_store.get_append_token(token, timestamp)? {
+                tracing::debug!("batch already appended at offset {}", meta.min_offset);
+                return Ok(meta);
+            }
+        }
+        let stream_id = self.node_id().stream(stream_nr);
+        anyhow::ensure!(
+            !store.tombstones.contains_key(&stream_id),
+            "stream {} has been tombstoned",
+            stream_id
+        );
+        let meta = self.append_locked(&mut guard, &mut store, app_id, timestamp, events)?;
+        if let Some(token) = token {
+            let expires = timestamp + self.data.append_token_ttl;
+            store.index_store.add_append_token(token, &meta, expires)?;
+        }
+        Ok(meta)
+    }
+
+    /// Append events while holding both the stream and the store lock.
+    fn append_locked(
+        &self,
+        guard: &mut OwnStreamGuard,
+        store: &mut BanyanStoreGuard,
+        app_id: AppId,
+        timestamp: Timestamp,
+        events: Vec<(TagSet, Event)>,
+    ) -> Result<AppendMeta> {
+        let n_events = events.len();
+        let mut lamports = store.reserve_lamports(n_events)?.peekable();
 
         let min_lamport = *lamports.peek().unwrap();
         let app_id_tag = tag!("app_id:") + app_id.as_str();
@@ -1434,32 +2393,54 @@ impl BanyanStore {
             tags.insert(scoped_app_id_tag.clone());
             (AxKey::new(tags, lamport, timestamp), payload)
         });
-        let min_offset = self.transform_stream(&mut guard, |txn, tree| {
+        let config = &store.banyan_config;
+        let unpacked_leaves = guard.unpacked_leaves() + 1 + (n_events - 1) / config.tree.max_leaf_count.max(1);
+        let pack = unpacked_leaves > config.max_unpacked_leaves;
+        let pack_inline = pack && (config.pack_inline || !store.has_task("compaction"));
+        let min_offset = self.transform_stream(guard, |txn, tree| {
             let snapshot = tree.snapshot();
             txn.extend_unpacked(tree, kvs)?;
-            if tree.level() > MAX_TREE_LEVEL {
+            if pack_inline {
                 txn.pack(tree)?;
             }
             Ok(snapshot.offset())
         })?;
+        if pack_inline {
+            guard.set_unpacked_leaves(0);
+        } else {
+            guard.set_unpacked_leaves(unpacked_leaves);
+            if pack {
+                self.data.pack_requests.request(guard.stream_nr());
+            }
+        }
         let min_offset = min_offset.map(|o| o + 1).unwrap_or(Offset::ZERO);
+        // still holding the stream lock, so this is the tree published by the transaction above
+        let (root, last_offset) = guard
+            .latest()
+            .project(|latest| latest.as_ref().map(|tree| (tree.root(), tree.offset())))
+            .context("append did not publish a tree")?;
+        self.data.swarm_metrics.appended(guard.stream_nr(), n_events);
 
         Ok(AppendMeta {
             min_lamport,
             min_offset,
             timestamp,
+            root,
+            last_offset,
         })
     }
 
     /// Returns a [`Stream`] of known [`StreamId`].
     pub fn stream_known_streams(&self) -> impl Stream<Item = StreamId> + Send {
+        self.lock().subscribe_known_streams() // PANIC
+    }
+
+    /// Like [`stream_known_streams`](Self::stream_known_streams), together with the present
+    /// offsets taken under the same store lock, so that no own stream advances in between.
+    pub fn stream_known_streams_from_present(&self) -> (OffsetMap, impl Stream<Item = StreamId> + Send) {
         let mut state = self.lock(); // PANIC
-        let (s, r) = mpsc::unbounded();
-        for stream_id in state.current_stream_ids() {
-            let _ = s.unbounded_send(stream_id);
-        }
-        state.known_streams.push(s);
-        r
+        let present = self.offsets().present();
+        (present, state.subscribe_known_streams())
     }
 
     /// Returns a [`Stream`] of events filtered with a [`Query`].
@@ -1475,15 +2456,181 @@ impl BanyanStore {
             .try_flatten()
     }
 
+    /// Like [`stream_filtered_stream_ordered`](Self::stream_filtered_stream_ordered), but only
+    /// for the streams in `upper` and up to their offset in it. Streams that have not reached their
+    /// bound yet are waited for, the returned stream completes once all of them have.
+    pub fn stream_filtered_stream_ordered_bounded<Q: Query<TT> + Clone + 'static>(
+        &self,
+        query: Q,
+        upper: OffsetMap,
+    ) -> impl Stream<Item = Result<(u64, Key, Event)>> {
+        let this = self.clone();
+        stream::iter(upper.into_inner())
+            .map(move |(stream_id, end)| {
+                let end = u64::from(end);
+                this.stream_filtered_chunked(stream_id, 0..=end, query.clone())
+                    .take_until_condition(move |chunk| {
+                        future::ready(chunk.as_ref().map(|chunk| chunk.range.end > end).unwrap_or(true))
+                    })
+                    .boxed()
+            })
+            .merge_unordered()
+            .map_ok(|chunk| stream::iter(chunk.data).map(Ok))
+            .try_flatten()
+    }
+
+    /// Returns a [`Stream`] of events filtered with a [`Query`], in descending offset order per
+    /// stream.
+    ///
+    /// Streams in `upper` are read from their offset in it, waiting for it to be present, all other
+    /// streams from their present offset when they are first seen. Streams appearing later are
+    /// included as well, so the returned stream does not complete.
+    pub fn stream_filtered_stream_ordered_reverse<Q: Query<TT> + Clone + 'static>(
+        &self,
+        query: Q,
+        upper: OffsetMap,
+    ) -> impl Stream<Item = Result<(u64, Key, Event)>> {
+        let this = self.clone();
+        self.stream_known_streams()
+            .filter_map(move |stream_id| {
+                let end = upper.get(stream_id).or_else(|| this.offsets().present().get(stream_id));
+                future::ready(end.map(|end| this.stream_filtered_chunked_reverse_from(stream_id, end, query.clone())))
+            })
+            .merge_unordered()
+            .map_ok(|chunk| stream::iter(chunk.data.into_iter().rev()).map(Ok))
+            .try_flatten()
+    }
+
+    /// Reads the stream backwards from `end`, using the first tree that contains it.
+    fn stream_filtered_chunked_reverse_from<Q: Query<TT> + Clone + 'static>(
+        &self,
+        stream_id: StreamId,
+        end: Offset,
+        query: Q,
+    ) -> BoxStream<'static, Result<FilteredChunk<(u64, AxKey, Payload), ()>>> {
+        if let Err(err) = self.data.ensure_readable(stream_id) {
+            return stream::once(future::err(err)).boxed();
+        }
+        let end = u64::from(end);
+        let trees = self
+            .tree_stream(stream_id)
+            .filter(move |tree| future::ready(tree.count() > end))
+            .take(1);
+        self.data
+            .forest
+            .stream_trees_chunked_reverse(query, trees, 0..=end, &|_| {})
+            .boxed()
+    }
+
     pub fn stream_filtered_chunked<Q: Query<TT> + Clone + 'static>(
         &self,
         stream_id: StreamId,
         range: RangeInclusive<u64>,
         query: Q,
     ) -> impl Stream<Item = Result<FilteredChunk<(u64, AxKey, Payload), ()>>> {
+        self.stream_filtered_chunked_with(stream_id, range, query, &|_| {})
+    }
+
+    /// Like [`stream_filtered_chunked`](Self::stream_filtered_chunked), but without requesting
+    /// further chunks from the forest once the `budget` is used up.
+    pub fn stream_filtered_chunked_budgeted<Q: Query<TT> + Clone + 'static>(
+        &self,
+        stream_id: StreamId,
+        range: RangeInclusive<u64>,
+        query: Q,
+        budget: Option<TakeWhileBudget>,
+    ) -> BoxStream<'static, Result<FilteredChunk<(u64, AxKey, Payload), ()>>> {
+        let chunks = self.stream_filtered_chunked(stream_id, range, query);
+        match budget {
+            Some(budget) => budget.apply(chunks),
+            None => chunks.boxed(),
+        }
+    }
+
+    /// Like [`stream_filtered_chunked`](Self::stream_filtered_chunked), but with the `extra` of
+    /// each chunk computed by `mk_extra`.
+    ///
+    /// A chunk is either a leaf or a branch skipped by the query, and `mk_extra` gets its index.
+    /// This allows to compute summaries like the number of events or the tags of a chunk from the
+    /// keys without loading the events, and without cloning the keys.
+    pub fn stream_filtered_chunked_with<Q, E, F>(
+        &self,
+        stream_id: StreamId,
+        range: RangeInclusive<u64>,
+        query: Q,
+        mk_extra: &'static F,
+    ) -> impl Stream<Item = Result<FilteredChunk<(u64, AxKey, Payload), E>>>
+    where
+        Q: Query<TT> + Clone + 'static,
+        E: Send + 'static,
+        F: Fn(IndexRef<TT>) -> E + Send + Sync + 'static,
+    {
         tracing::trace!("stream_filtered_chunked {}", stream_id);
+        if let Err(err) = self.data.ensure_readable(stream_id) {
+            return stream::once(future::err(err)).left_stream();
+        }
         let trees = self.tree_stream(stream_id);
-        self.data.forest.stream_trees_chunked(query, trees, range, &|_| {})
+        self.data
+            .forest
+            .stream_trees_chunked(query, trees, range, mk_extra)
+            .right_stream()
+    }
+
+    /// Events of a stream with a timestamp in `from..to` that match `tag_query`.
+    ///
+    /// Timestamps are not monotonic within a stream, so the range cannot be mapped to offsets.
+    /// Instead, branches are skipped when the time range of their summary lies outside of
+    /// `from..to`, and the events in the remaining leaves are checked individually.
+    pub fn stream_filtered_by_time(
+        &self,
+        stream_id: StreamId,
+        from: Timestamp,
+        to: Timestamp,
+        tag_query: TagExprQuery,
+    ) -> impl Stream<Item = Result<(u64, AxKey, Payload)>> {
+        let query = tag_query.and_time(TimeQuery::from(from..to));
+        self.stream_filtered_chunked(stream_id, 0..=u64::MAX, query)
+            .map_ok(|chunk| stream::iter(chunk.data).map(Ok))
+            .try_flatten()
+    }
+
+    /// Ids of all streams known to this store: own, replicated and dormant ones.
+    pub fn current_stream_ids(&self) -> BTreeSet<StreamId> {
+        self.lock().current_stream_ids().collect()
+    }
+
+    /// Root, offset and lamport of the trees of all known streams.
+    pub fn root_map(&self) -> BTreeMap<StreamId, (Cid, Offset, LamportTimestamp)> {
+        self.lock().root_map()
+    }
+
+    /// Captures the published trees of all known streams, see [`StoreSnapshot`].
+    pub fn snapshot(&self) -> StoreSnapshot {
+        let state = self.lock();
+        let trees = state
+            .current_stream_ids()
+            .filter_map(|stream_id| state.published_tree(stream_id).map(|tree| (stream_id, tree)))
+            .collect();
+        StoreSnapshot::new(trees)
+    }
+
+    /// Like [`stream_filtered_chunked`](Self::stream_filtered_chunked), but only reads the tree
+    /// captured in the snapshot and ends after it, without locking the store.
+    pub fn stream_filtered_chunked_snapshot<Q: Query<TT> + Clone + 'static>(
+        &self,
+        snapshot: &StoreSnapshot,
+        stream_id: StreamId,
+        range: RangeInclusive<u64>,
+        query: Q,
+    ) -> impl Stream<Item = Result<FilteredChunk<(u64, AxKey, Payload), ()>>> {
+        if let Err(err) = self.data.ensure_readable(stream_id) {
+            return stream::once(future::err(err)).left_stream();
+        }
+        let trees = stream::iter(snapshot.tree(stream_id).cloned());
+        self.data
+            .forest
+            .stream_trees_chunked(query, trees, range, &|_| {})
+            .right_stream()
     }
 
     pub fn stream_filtered_chunked_reverse<Q: Query<TT> + Clone + 'static>(
@@ -1492,10 +2639,46 @@ impl BanyanStore {
         range: RangeInclusive<u64>,
         query: Q,
     ) -> impl Stream<Item = Result<FilteredChunk<(u64, AxKey, Payload), ()>>> {
+        self.stream_filtered_chunked_reverse_with(stream_id, range, query, &|_| {})
+    }
+
+    /// The reverse of [`stream_filtered_chunked_budgeted`](Self::stream_filtered_chunked_budgeted),
+    /// e.g. for the latest events of a stream.
+    pub fn stream_filtered_chunked_reverse_budgeted<Q: Query<TT> + Clone + 'static>(
+        &self,
+        stream_id: StreamId,
+        range: RangeInclusive<u64>,
+        query: Q,
+        budget: Option<TakeWhileBudget>,
+    ) -> BoxStream<'static, Result<FilteredChunk<(u64, AxKey, Payload), ()>>> {
+        let chunks = self.stream_filtered_chunked_reverse(stream_id, range, query);
+        match budget {
+            Some(budget) => budget.apply(chunks),
+            None => chunks.boxed(),
+        }
+    }
+
+    /// The reverse of [`stream_filtered_chunked_with`](Self::stream_filtered_chunked_with)
+    pub fn stream_filtered_chunked_reverse_with<Q, E, F>(
+        &self,
+        stream_id: StreamId,
+        range: RangeInclusive<u64>,
+        query: Q,
+        mk_extra: &'static F,
+    ) -> impl Stream<Item = Result<FilteredChunk<(u64, AxKey, Payload), E>>>
+    where
+        Q: Query<TT> + Clone + 'static,
+        E: Send + 'static,
+        F: Fn(IndexRef<TT>) -> E + Send + Sync + 'static,
+    {
+        if let Err(err) = self.data.ensure_readable(stream_id) {
+            return stream::once(future::err(err)).left_stream();
+        }
         let trees = self.tree_stream(stream_id);
         self.data
             .forest
-            .stream_trees_chunked_reverse(query, trees, range, &|_| {})
+            .stream_trees_chunked_reverse(query, trees, range, mk_extra)
+            .right_stream()
     }
 
     fn get_or_create_own_stream(&self, stream_nr: StreamNr) -> Result<Arc<OwnStream>> {
@@ -1511,6 +2694,10 @@ impl BanyanStore {
         stream: &mut OwnStreamGuard,
         f: impl FnOnce(&mut Transaction, &mut AxStreamBuilder) -> Result<T> + Send,
     ) -> Result<T> {
+        // the last line of defence, internal tasks are not even started on a read-only replica
+        if self.data.read_only {
+            return Err(ReadOnlyError.into());
+        }
         let writer = self.data.forest.store().write()?;
         let stream_nr = stream.stream_nr();
         let stream_id = self.node_id().stream(stream_nr);
@@ -1537,7 +2724,7 @@ impl BanyanStore {
         let root = txn.writer_mut().put(DagCborCodec.encode(&header)?)?;
         let cid = Cid::from(root);
         // update the permanent alias. If this fails, we will revert the builder.
-        self.ipfs().alias(StreamAlias::from(stream_id), Some(&cid))?;
+        self.data.set_stream_alias(stream_id, Some(&cid))?;
         // this concludes the things we want to fail the transaction
         guard.commit();
         // set the latest
@@ -1555,8 +2742,20 @@ impl BanyanStore {
         Ok(res)
     }
 
-    fn update_root(&self, stream_id: StreamId, root: Link, source: RootSource) {
+    fn update_root(&self, stream_id: StreamId, root: Link, source: RootSource, lamport: LamportTimestamp) {
         if !self.is_local(stream_id) {
+            self.record_decision(|| Decision::RootUpdate {
+                stream: stream_id,
+                root: root.to_string(),
+                path: format!("{:?}", source.path),
+                sender: source.sender.to_string(),
+                lamport,
+            });
+            if self.is_tombstoned(stream_id) {
+                // a tombstone is the last event of a stream, so there is nothing left to sync
+                tracing::trace!("ignoring root {} of tombstoned stream {}", root, stream_id);
+                return;
+            }
             tracing::trace!("update_root {} {}", stream_id, root);
             self.get_or_create_replicated_stream(stream_id)
                 .unwrap()
@@ -1564,35 +2763,165 @@ impl BanyanStore {
         }
     }
 
-    async fn compaction_loop(self, interval: Duration) {
+    /// Replaces the running compaction loop with one using the given configuration.
+    ///
+    /// A read-only replica only takes note of the configuration, it never compacts.
+    pub fn restart_compaction(&self, config: CompactionConfig) {
+        self.abort_task("compaction");
+        self.data.settings.transform_mut(|settings| {
+            settings.compaction = config;
+            true
+        });
+        if !self.data.read_only {
+            self.spawn_task("compaction".to_owned(), self.clone().compaction_loop().boxed());
+        }
+    }
+
+    /// Current settings of the periodic tasks
+    pub fn settings(&self) -> RuntimeSwarmSettings {
+        self.data.settings.get_cloned()
+    }
+
+    /// Change the settings of the periodic tasks without restarting the store.
+    ///
+    /// Fails if any of the intervals is zero. Pruning of ephemeral events is started or stopped
+    /// when it is enabled or disabled, except on a read-only replica where it never runs.
+    pub fn update_settings(&self, mut settings: RuntimeSwarmSettings) -> Result<()> {
+        settings.validate()?;
+        settings.ephemeral_events = self.effective_ephemeral_events(settings.ephemeral_events);
+        let read_only = self.data.read_only;
+        let is_enabled = |s: &RuntimeSwarmSettings| {
+            !read_only && matches!(s.ephemeral_events, EphemeralEventsConfig::Enabled { .. })
+        };
+        let was_pruning = self.data.settings.project(is_enabled);
+        let pruning = is_enabled(&settings);
+        self.data.settings.set(settings);
+        match (was_pruning, pruning) {
+            (false, true) => self.spawn_task("prune_events".to_owned(), prune::prune(self.clone()).boxed()),
+            (true, false) => self.abort_task("prune_events"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The retentions that take effect for the configured ones: the built-in retentions of the
+    /// internal streams are added, while the "default" stream and streams without a mapping in
+    /// the routing table are dropped.
+    fn effective_ephemeral_events(&self, mut config: EphemeralEventsConfig) -> EphemeralEventsConfig {
+        if let Some(streams) = config.streams_mut() {
+            for (name, retain) in self.data.builtin_retentions.lock().iter() {
+                streams.insert(name.clone(), retain.clone());
+            }
+            let routing_table = self.data.routing_table.lock().clone();
+            streams.retain(|stream, _| {
+                if stream == DEFAULT_STREAM_NAME {
+                    tracing::warn!(
+                        "The \"default\" stream cannot be configured, its retention configuration will be ignored."
+                    );
+                    return false;
+                }
+
+                let is_stream_mapped = routing_table.stream_mapping.get(stream).is_some();
+                if !is_stream_mapped {
+                    tracing::warn!(
+                        "The stream \"{}\" does not have a mapping, its retention configuration will be ignored.",
+                        stream
+                    );
+                }
+                is_stream_mapped
+            });
+        }
+        config
+    }
+
+    async fn compaction_loop(self) {
+        let mut last_compacted = BTreeMap::<StreamNr, Instant>::new();
+        let mut settings = self.data.settings.new_observer();
+        let Some(mut config) = settings.next().await.map(|s| s.compaction) else {
+            return;
+        };
         loop {
-            let stream_nrs = self.lock().local_stream_nrs();
+            // outside maintenance windows only the packing requested by appends remains
+            let budget = self.data.maintenance.budget(MaintenanceTask::Compaction);
+            let stream_nrs = match budget {
+                MaintenanceBudget::Pause => vec![],
+                _ => self.lock().local_stream_nrs(),
+            };
             for stream_nr in stream_nrs {
-                tracing::debug!("compacting stream {}", stream_nr);
-                let stream = self.get_or_create_own_stream(stream_nr).unwrap();
-                let mut guard = stream.lock().await;
-                let result = self.transform_stream(&mut guard, |txn, tree| txn.pack(tree));
-                if let Err(err) = result {
-                    tracing::error!("Error compacting stream {}: {}", stream_nr, err);
-                    break;
+                let (interval, level_threshold) = match config.policy(stream_nr) {
+                    Some(policy) => policy,
+                    None => continue,
+                };
+                let now = Instant::now();
+                if matches!(last_compacted.get(&stream_nr), Some(last) if now.duration_since(*last) < interval) {
+                    continue;
+                }
+                last_compacted.insert(stream_nr, now);
+                match self.compact_stream(stream_nr, level_threshold).await {
+                    // with reduced budget pack at most one stream per pass
+                    Ok(true) if budget == MaintenanceBudget::Reduced => break,
+                    Ok(_) => {}
+                    Err(err) => tracing::error!("Error compacting stream {}: {}", stream_nr, err),
                 }
             }
-            tokio::time::sleep(interval).await;
+            tokio::select! {
+                _ = tokio::time::sleep(config.tick()) => {}
+                Some(changed) = settings.next() => config = changed.compaction,
+                stream_nrs = self.data.pack_requests.requested() => {
+                    for stream_nr in stream_nrs {
+                        if let Err(err) = self.compact_stream(stream_nr, 0).await {
+                            tracing::error!("Error packing stream {}: {}", stream_nr, err);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether the stream was packed.
+    async fn compact_stream(&self, stream_nr: StreamNr, level_threshold: usize) -> Result<bool> {
+        let stream = self.get_or_create_own_stream(stream_nr)?;
+        let mut guard = stream.lock().await;
+        let level = guard.snapshot().level().max(0) as usize;
+        if level <= level_threshold {
+            tracing::trace!("not compacting stream {} at level {}", stream_nr, level);
+            return Ok(false);
         }
+        tracing::debug!("compacting stream {} at level {}", stream_nr, level);
+        self.transform_stream(&mut guard, |txn, tree| txn.pack(tree))?;
+        guard.set_unpacked_leaves(0);
+        Ok(true)
     }
 
     /// careful ingestion - basically just call sync_one on each new ingested root
     async fn careful_ingestion(self, stream_id: StreamId, state: Arc<ReplicatedStream>) {
         let state2 = state.clone();
+        let store = self.clone();
         state
             .incoming_root_stream()
             .switch_map(move |(root, source)| {
+                // streams ignored by their ID need not be looked at, for all others we need the
+                // header and possibly the tree root to decide, which is done by sync_one
+                if self.data.replication.mode(stream_id, None) == Some(ReplicationMode::Ignore) {
+                    return future::ready((Ok(SyncOutcome::Ignored), root))
+                        .left_future()
+                        .into_stream();
+                }
                 self.clone()
                     .sync_one(stream_id, root, source)
                     .map(move |res| (res, root))
+                    .right_future()
                     .into_stream()
             })
             .for_each(|(res, root)| {
+                store.record_decision(|| Decision::SyncOutcome {
+                    stream: stream_id,
+                    root: root.to_string(),
+                    outcome: match &res {
+                        Ok(outcome) => format!("{:?}", outcome),
+                        Err(err) => format!("error: {:#}", err),
+                    },
+                });
                 // Must dial down this root’s priority to allow later updates with lower prio.
                 // This crucially depends on the fact that sync_one will eventually return, i.e.
                 // it must not hang indefinitely. It should ideally fail as quickly as possible
@@ -1629,20 +2958,29 @@ impl BanyanStore {
 
         let s = tracing::trace_span!("sync_one", %stream_id, %root);
         let e = s.enter();
+        self.record_decision(|| Decision::SyncStart {
+            stream: stream_id,
+            root: root.to_string(),
+        });
 
         let cid = Cid::from(root);
         let ipfs = &self.data.ipfs;
         let stream = self.get_or_create_replicated_stream(stream_id)?;
         let (validated_header_lamport, validated_header_count) = stream.validated_tree_counters();
+        let tracked_header_lamport = stream.tracked_header_lamport();
+        let mut mode = self.data.replication.mode(stream_id, None);
         // temporarily pin the new root
         tracing::trace!("assigning temp pin to {}", root);
         let mut temp_pin = ipfs.create_temp_pin()?;
         ipfs.temp_pin(&mut temp_pin, &cid)?;
         let peers = ipfs.peers();
+        // blocks are fetched from the announcing peer first, so that the transfer can be accounted
+        // to its connection; if it cannot serve all of them, all peers are asked
+        let mut from = Some(source.sender).filter(|peer| peers.contains(peer));
         // attempt to sync. This may take a while and is likely to be interrupted
-        tracing::trace!("starting to sync from {} peers", peers.len());
+        tracing::trace!("starting to sync from {} peers", from.map_or(peers.len(), |_| 1));
         // create the sync stream, and log progress. Add an additional element.
-        let mut sync = ipfs.sync(&cid, peers).await?;
+        let mut sync = ipfs.sync(&cid, from.map_or(peers, |peer| vec![peer])).await?;
         // during the sync, try to load the tree asap and abort in case it is not good
         let mut header: Option<AxTreeHeader> = None;
         let mut tree: Option<AxTree> = None;
@@ -1656,8 +2994,19 @@ impl BanyanStore {
                 SyncEvent::Progress { missing } => {
                     tracing::trace!("sync_one: {}/{}", n, n + missing);
                     n += 1;
+                    // the size of blocks fetched via bitswap is not reported
+                    if let Some(peer) = from {
+                        self.data.transfers.received(peer, 1, 0);
+                    }
+                    self.data.swarm_metrics.received(1, 0);
                 }
                 SyncEvent::Complete(Err(err)) => {
+                    if let Some(peer) = from.take() {
+                        tracing::debug!(%stream_id, %err, "sync_one from {} failed, asking all peers", peer);
+                        self.data.transfers.timed_out(peer);
+                        sync = ipfs.sync(&cid, ipfs.peers()).await?;
+                        continue;
+                    }
                     tracing::debug!(%stream_id, %err, "sync_one");
                     return Err(err);
                 }
@@ -1666,8 +3015,12 @@ impl BanyanStore {
             if header.is_none() {
                 // try to load the header. It should be one of the first things being synced
                 if let Ok(blob) = self.data.forest.store().get(&root).surface::<BlockNotFound>()? {
-                    let temp: AxTreeHeader = DagCborCodec.decode(&blob)?;
-                    if temp.lamport <= validated_header_lamport {
+                    let temp: AxTreeHeader = DagCborCodec
+                        .decode(&blob)
+                        .map_err(|err| self.invalid_synced_block(source.sender, root, err))?;
+                    if temp.lamport <= validated_header_lamport
+                        || mode == Some(ReplicationMode::HeadersOnly) && temp.lamport <= tracked_header_lamport
+                    {
                         // this is not unexpected and should not be logged as an error
                         return Ok(SyncOutcome::OldHeader);
                     }
@@ -1679,17 +3032,62 @@ impl BanyanStore {
                 if let Ok(temp) = self
                     .data
                     .forest
-                    .load_tree(Secrets::default(), header.root)
-                    .surface::<BlockNotFound>()?
+                    .load_tree(self.data.tree_secrets(stream_id), header.root)
+                    .surface::<BlockNotFound>()
+                    .map_err(|err| self.invalid_synced_block(source.sender, root, err))?
                 {
                     // sanity check: we must never lose events.
                     anyhow::ensure!(temp.count() >= validated_header_count);
+                    let mode = *mode.get_or_insert_with(|| self.data.replication.mode_for_tree(stream_id, &temp));
                     tree = Some(temp);
+                    if mode != ReplicationMode::Full {
+                        // don’t download the rest of the tree
+                        break;
+                    }
                 }
             }
         }
         let header = header.ok_or_else(|| anyhow::anyhow!("header was not loaded during sync"))?;
         let tree = tree.ok_or_else(|| anyhow::anyhow!("tree was not loaded during sync"))?;
+        let offset = tree.offset().unwrap();
+        if let Some(announced) = source.offset.filter(|announced| *announced != offset) {
+            tracing::warn!(
+                peer = %source.sender,
+                %stream_id,
+                "announced offset {} for a tree ending at {}",
+                announced,
+                offset
+            );
+            self.distrust(source.sender);
+            anyhow::bail!(
+                "offset {} announced by {} does not match the tree",
+                announced,
+                source.sender
+            );
+        }
+        if source.path == RootPath::Deferred {
+            // now that the tree is known the lamport of its header can be applied
+            anyhow::ensure!(
+                self.accept_lamport(source.sender, Some(stream_id), header.lamport),
+                "implausible lamport {} in header of {}",
+                header.lamport,
+                root
+            );
+            self.lock().received_lamport(header.lamport)?;
+            self.update_highest_seen(stream_id, offset);
+        }
+        match mode {
+            Some(ReplicationMode::Full) | None => {}
+            Some(ReplicationMode::HeadersOnly) => {
+                tracing::trace!("sync_one tracking header {} => {}", stream_id, offset);
+                stream.track_header(header.lamport);
+                self.update_highest_seen(stream_id, offset);
+                return Ok(SyncOutcome::HeadersOnly);
+            }
+            Some(ReplicationMode::Ignore) => return Ok(SyncOutcome::Ignored),
+        }
+        let tombstone = self.read_tombstone(stream_id, &tree)?;
+        let lamport = header.lamport;
         let state = PublishedTree::new(root, header, tree.clone());
 
         // if we get here, we already know that the new tree is better than its predecessor
@@ -1697,60 +3095,29 @@ impl BanyanStore {
         // once sync is successful, permanently move the alias
         tracing::trace!("updating alias {}", root);
         // assign the new root as validated
-        ipfs.alias(StreamAlias::from(stream_id), Some(&cid))?;
-        let offset = tree.offset().unwrap();
+        self.data.set_stream_alias(stream_id, Some(&cid))?;
         tracing::trace!("sync_one complete {} => {}", stream_id, offset);
         stream.set_latest(state);
+        self.record_decision(|| Decision::SetLatest {
+            stream: stream_id,
+            root: root.to_string(),
+            offset,
+            lamport,
+        });
         // update present.
         self.update_present(stream_id, offset);
+        self.data
+            .swarm_metrics
+            .synced(tree.count().saturating_sub(validated_header_count));
+        if let Some(event) = tombstone {
+            let purge_at = Timestamp::now() + self.data.tombstone_retention;
+            self.lock()
+                .add_tombstone(stream_id, Tombstone::new(event.reason, offset, purge_at))?;
+        }
         // done
         Ok(SyncOutcome::Success)
     }
 
-    /// Validate that all known streams are completely present
-    ///
-    /// We could have a lenient mode where this is just logged, or a recovery mode
-    /// where it tries to acquire the data on startup, but for now this will just
-    /// return an error if anything is missing.
-    #[allow(clippy::needless_collect)]
-    async fn validate_known_streams(&self) -> Result<()> {
-        let state = self.lock();
-        let headers = state
-            .current_stream_ids()
-            .filter_map(|stream_id| state.published_tree(stream_id).map(|p| (stream_id, p.root())))
-            .collect::<Vec<_>>();
-        drop(state);
-        let futures = headers
-            .into_iter()
-            .map(|(stream_id, root)| async move {
-                // sync with 0 peers to just check if we have the data
-                let result = self.data.ipfs.sync(&root.into(), vec![]).await;
-                (stream_id, result)
-            })
-            .collect::<Vec<_>>();
-        let results = futures::future::join_all(futures).await;
-        // log the results
-        let mut errors = Vec::new();
-        for (stream_id, result) in &results {
-            if let Err(cause) = result {
-                errors.push(*stream_id);
-                tracing::error!("incomplete alias for stream id {}: {}", stream_id, cause);
-            } else {
-                tracing::debug!("validated alias for stream_id {}", stream_id);
-            }
-        }
-        // fail the entire method in case there is just one failure
-        let _ = results
-            .into_iter()
-            .map(|(_, r)| r)
-            .collect::<anyhow::Result<Vec<_>>>()
-            .context(format!(
-                "Found {} streams with missing events, giving up.",
-                errors.len()
-            ))?;
-        Ok(())
-    }
-
     fn update_present(&self, stream_id: StreamId, offset: Offset) {
         self.data.offsets.transform_mut(|offsets| {
             offsets
@@ -1785,7 +3152,7 @@ impl BanyanStore {
         self.lock().spawn_task(name, task)
     }
 
-    pub fn abort_task(&self, name: &'static str) {
+    pub fn abort_task(&self, name: &str) {
         self.lock().abort_task(name)
     }
 }
@@ -1794,6 +3161,10 @@ impl BanyanStore {
 enum SyncOutcome {
     OldHeader,
     Success,
+    /// only the header was retrieved, according to the [`ReplicationConfig`]
+    HeadersOnly,
+    /// nothing was retrieved, according to the [`ReplicationConfig`]
+    Ignored,
 }
 
 trait AnyhowResultExt<T>: Sized {
@@ -1835,9 +3206,31 @@ pub struct Child {
     pub name: String,
     #[serde(with = "crate::util::serde_str")]
     pub cid: Cid,
+    /// The `Tsize` recorded in the directory, which is meant to be the DAG size of the child but
+    /// is the content size for files added by this node. Use [`BanyanStore::unixfs_stat`] for
+    /// either size.
     pub size: u64,
 }
 
+/// A path segment that [`BanyanStore::unixfs_resolve_path`] could not resolve
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+pub enum UnixfsPathError {
+    #[display(fmt = "Path {} not found inside {}", segment, parent)]
+    NotFound { segment: String, parent: Cid },
+    #[display(fmt = "Found file {} while looking for directory {}", file, segment)]
+    NotADirectory { file: String, segment: String },
+}
+
+impl UnixfsPathError {
+    /// The path segment that could not be resolved
+    pub fn segment(&self) -> &str {
+        match self {
+            UnixfsPathError::NotFound { segment, .. } => segment,
+            UnixfsPathError::NotADirectory { segment, .. } => segment,
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub enum FileNode {
     Directory {
@@ -1850,6 +3243,10 @@ pub enum FileNode {
         name: String,
         #[serde(with = "crate::util::serde_str")]
         cid: Cid,
+        /// content size in bytes, as recorded in the root block
+        size: u64,
+        /// modification time, if recorded in the root block
+        mtime: Option<Timestamp>,
     },
 }
 
@@ -1989,7 +3386,7 @@ mod test_match_tag_set {
 }
 
 #[derive(Default, Debug)]
-struct RoutingTable {
+pub(crate) struct RoutingTable {
     routes: Vec<(Dnf, StreamNr)>,
     stream_mapping: HashMap<String, StreamNr>,
     max_stream_nr: Option<StreamNr>,
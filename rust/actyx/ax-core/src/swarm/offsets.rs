@@ -0,0 +1,185 @@
+//! Offset bookkeeping for swarms with many streams, see [`VersionedOffsets`].
+use ax_types::{Offset, OffsetMap, OffsetOrMin, StreamId};
+use im::OrdMap;
+use serde::{Deserialize, Serialize};
+
+/// Offsets per stream in a persistent map, so that cloning is O(1) and updating one stream is
+/// O(log n) regardless of the number of streams.
+///
+/// Every update bumps the version, and the entries changed after a given version can be
+/// extracted as an [`OffsetsDelta`] without looking at the unchanged ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionedOffsets {
+    version: u64,
+    /// offset and the version at which it was last changed
+    offsets: OrdMap<StreamId, (Offset, u64)>,
+    /// index from version to the stream changed in it
+    changes: OrdMap<u64, StreamId>,
+}
+
+impl VersionedOffsets {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub fn offset(&self, stream: StreamId) -> OffsetOrMin {
+        self.get(stream).map(OffsetOrMin::from).unwrap_or(OffsetOrMin::MIN)
+    }
+
+    pub fn get(&self, stream: StreamId) -> Option<Offset> {
+        self.offsets.get(&stream).map(|(offset, _)| *offset)
+    }
+
+    pub fn streams(&self) -> impl Iterator<Item = StreamId> + '_ {
+        self.offsets.keys().copied()
+    }
+
+    /// Raises the offset of the stream, returning the previous offset if it was lower; this
+    /// mirrors [`OffsetMap::update`].
+    pub fn update(&mut self, stream: StreamId, offset: Offset) -> Option<OffsetOrMin> {
+        let previous = self.offsets.get(&stream).copied();
+        if previous.map(|(o, _)| offset <= o).unwrap_or(false) {
+            return None;
+        }
+        self.version += 1;
+        if let Some((_, changed)) = previous {
+            self.changes.remove(&changed);
+        }
+        self.offsets.insert(stream, (offset, self.version));
+        self.changes.insert(self.version, stream);
+        Some(previous.map(|(o, _)| o.into()).unwrap_or(OffsetOrMin::MIN))
+    }
+
+    pub fn to_offset_map(&self) -> OffsetMap {
+        self.offsets
+            .iter()
+            .map(|(stream, (offset, _))| (*stream, *offset))
+            .collect()
+    }
+
+    /// The entries changed after the given version.
+    ///
+    /// A version from the future — e.g. held by an observer of a previous incarnation of this map —
+    /// yields all entries, as does `0`.
+    pub fn delta_since(&self, version: u64) -> OffsetsDelta {
+        let since = if version > self.version { 0 } else { version };
+        let offsets = self
+            .changes
+            .range(since + 1..)
+            .filter_map(|(_, stream)| Some((*stream, self.get(*stream)?)))
+            .collect();
+        OffsetsDelta {
+            since,
+            version: self.version,
+            offsets,
+        }
+    }
+}
+
+impl From<&OffsetMap> for VersionedOffsets {
+    fn from(map: &OffsetMap) -> Self {
+        let mut ret = Self::default();
+        for (stream, offset) in map.stream_iter() {
+            ret.update(stream, offset);
+        }
+        ret
+    }
+}
+
+/// The changed entries of a [`VersionedOffsets`] between two versions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetsDelta {
+    /// version the delta applies to, `0` means the delta contains the full map
+    pub since: u64,
+    pub version: u64,
+    pub offsets: OffsetMap,
+}
+
+impl OffsetsDelta {
+    pub fn is_full(&self) -> bool {
+        self.since == 0
+    }
+
+    /// Brings a map reconstructed from previous deltas up to `self.version`.
+    pub fn apply(&self, map: &mut OffsetMap) {
+        if self.is_full() {
+            *map = self.offsets.clone();
+        } else {
+            *map = map.union(&self.offsets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::NodeId;
+
+    fn stream(n: u64) -> StreamId {
+        NodeId::from_bytes(&[(n % 7) as u8 + 1; 32]).unwrap().stream(n.into())
+    }
+
+    #[test]
+    fn update() {
+        let mut offsets = VersionedOffsets::default();
+        assert_eq!(offsets.update(stream(1), Offset::from(3)), Some(OffsetOrMin::MIN));
+        assert_eq!(offsets.update(stream(1), Offset::from(2)), None);
+        assert_eq!(offsets.update(stream(1), Offset::from(3)), None);
+        assert_eq!(offsets.update(stream(1), Offset::from(5)), Some(Offset::from(3).into()));
+        assert_eq!(offsets.version(), 2);
+        assert_eq!(offsets.offset(stream(1)), Offset::from(5).into());
+        assert_eq!(offsets.offset(stream(2)), OffsetOrMin::MIN);
+    }
+
+    #[test]
+    fn deltas_reconstruct_the_map() {
+        let mut offsets = VersionedOffsets::default();
+        let mut reconstructed = OffsetMap::empty();
+        let mut version = 0;
+        for round in 0..20u64 {
+            for n in 0..100u64 {
+                if (n * round) % 3 == 0 {
+                    offsets.update(stream(n), Offset::from((round * n) as u32));
+                }
+            }
+            let delta = offsets.delta_since(version);
+            assert_eq!(delta.since, version);
+            assert!(delta.offsets.size() <= 100);
+            delta.apply(&mut reconstructed);
+            version = delta.version;
+            assert_eq!(reconstructed, offsets.to_offset_map());
+        }
+        // nothing changed, nothing to send
+        assert!(offsets.delta_since(version).offsets.streams().next().is_none());
+    }
+
+    #[test]
+    fn delta_contains_only_changes() {
+        let mut offsets =
+            VersionedOffsets::from(&(0..10_000).map(|n| (stream(n), Offset::from(1))).collect::<OffsetMap>());
+        let version = offsets.version();
+        offsets.update(stream(17), Offset::from(2));
+        offsets.update(stream(42), Offset::from(2));
+        offsets.update(stream(17), Offset::from(3));
+        let delta = offsets.delta_since(version);
+        assert_eq!(
+            delta.offsets,
+            [(stream(17), Offset::from(3)), (stream(42), Offset::from(2))]
+                .into_iter()
+                .collect()
+        );
+        // a version from the future yields the full map
+        let full = offsets.delta_since(version + 100);
+        assert!(full.is_full());
+        assert_eq!(full.offsets, offsets.to_offset_map());
+    }
+}
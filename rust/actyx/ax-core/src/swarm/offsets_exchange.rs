@@ -0,0 +1,313 @@
+//! Comparison of the offsets of this node with those of a single peer, to find out which of the
+//! two is missing events the other one has.
+//!
+//! ipfs-embed offers no request/response protocol to plug into, so only small control messages go
+//! over broadcast on a dedicated topic: a request is addressed to the peer by its id, and everyone
+//! else ignores it. The peer adds its offsets to its block store as a temporarily pinned file and
+//! answers with the root of that file, addressed back to us. The offsets themselves are then
+//! streamed block by block from that peer via bitswap, so their size is not bounded by the
+//! broadcast message size.
+use crate::swarm::BanyanStore;
+use anyhow::Result;
+use ax_types::{OffsetMap, StreamId};
+use futures::{channel::oneshot, future, StreamExt, TryStreamExt};
+use ipfs_embed::{Cid, GossipEvent, PeerId, SyncEvent, TempPin};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// How long to wait for the peer's answer, and then again for fetching its offsets
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Offsets of one node as exchanged for [`BanyanStore::compare_offsets`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeOffsets {
+    /// events validated and available on the node
+    pub present: OffsetMap,
+    /// events the node knows to exist, a stream missing here was never heard of
+    pub replication_target: OffsetMap,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetsComparison {
+    pub local: NodeOffsets,
+    pub remote: NodeOffsets,
+    /// streams with events present only on this node
+    pub only_local: Vec<StreamId>,
+    /// streams with events present only on the peer
+    pub only_remote: Vec<StreamId>,
+    /// number of events this node has beyond the peer, for streams present on both
+    pub local_ahead: BTreeMap<StreamId, u64>,
+    /// number of events the peer has beyond this node, for streams present on both
+    pub remote_ahead: BTreeMap<StreamId, u64>,
+}
+
+impl OffsetsComparison {
+    pub fn new(local: NodeOffsets, remote: NodeOffsets) -> Self {
+        let mut only_local = vec![];
+        let mut only_remote = vec![];
+        let mut local_ahead = BTreeMap::new();
+        let mut remote_ahead = BTreeMap::new();
+        for stream in local.present.union(&remote.present).streams() {
+            match (local.present.get(stream), remote.present.get(stream)) {
+                (Some(_), None) => only_local.push(stream),
+                (None, Some(_)) => only_remote.push(stream),
+                (Some(l), Some(r)) if l > r => {
+                    local_ahead.insert(stream, u64::from(l) - u64::from(r));
+                }
+                (Some(l), Some(r)) if r > l => {
+                    remote_ahead.insert(stream, u64::from(r) - u64::from(l));
+                }
+                _ => {}
+            }
+        }
+        Self {
+            local,
+            remote,
+            only_local,
+            only_remote,
+            local_ahead,
+            remote_ahead,
+        }
+    }
+
+    /// Whether both nodes have exactly the same events
+    pub fn is_equal(&self) -> bool {
+        self.only_local.is_empty()
+            && self.only_remote.is_empty()
+            && self.local_ahead.is_empty()
+            && self.remote_ahead.is_empty()
+    }
+}
+
+/// The peer is not connected or did not answer in time
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[display(fmt = "peer {} {}", peer, reason)]
+pub struct PeerUnreachable {
+    pub peer: PeerId,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ExchangeMessage {
+    Request {
+        id: u64,
+        #[serde(with = "crate::util::serde_str")]
+        to: PeerId,
+    },
+    Response {
+        id: u64,
+        #[serde(with = "crate::util::serde_str")]
+        to: PeerId,
+        /// root of the unixfs file holding the CBOR encoded [`NodeOffsets`] of the sender
+        #[serde(with = "crate::util::serde_str")]
+        root: Cid,
+    },
+}
+
+/// Requests waiting for the answer of a peer
+#[derive(Default)]
+pub(crate) struct OffsetsExchange {
+    next_id: AtomicU64,
+    pending: Mutex<BTreeMap<u64, (PeerId, oneshot::Sender<Cid>)>>,
+}
+
+fn topic(store: &BanyanStore) -> String {
+    format!("{}-offsets", store.data.topic)
+}
+
+impl BanyanStore {
+    fn node_offsets(&self) -> NodeOffsets {
+        let offsets = self.offsets();
+        NodeOffsets {
+            present: offsets.present(),
+            replication_target: offsets.replication_target(),
+        }
+    }
+
+    /// Fetches the offsets published by `peer` under `root`
+    async fn fetch_node_offsets(&self, peer: PeerId, root: Cid) -> Result<NodeOffsets> {
+        let ipfs = self.ipfs();
+        let mut tmp = ipfs.create_temp_pin()?;
+        ipfs.temp_pin(&mut tmp, &root)?;
+        let mut sync = ipfs.sync(&root, vec![peer]).await?;
+        while let Some(event) = sync.next().await {
+            if let SyncEvent::Complete(result) = event {
+                result?;
+            }
+        }
+        let bytes = self.cat(root, false).try_concat().await?;
+        Ok(serde_cbor::from_slice(&bytes)?)
+    }
+
+    /// Adds the offsets of this node to the block store, they stay pinned by the returned pin
+    fn publish_node_offsets(&self) -> Result<(Cid, TempPin)> {
+        let bytes = serde_cbor::to_vec(&self.node_offsets())?;
+        let mut tmp = self.ipfs().create_temp_pin()?;
+        let (root, _) = self.add(&mut tmp, bytes.as_slice())?;
+        Ok((root, tmp))
+    }
+
+    /// Compares the offsets of this node with those of the connected `peer`.
+    ///
+    /// Fails with [`PeerUnreachable`] if the peer is not connected or doesn't answer in time.
+    pub async fn compare_offsets(&self, peer: PeerId) -> Result<OffsetsComparison> {
+        let unreachable = |reason: &str| PeerUnreachable {
+            peer,
+            reason: reason.to_owned(),
+        };
+        if !self.ipfs().connections().iter().any(|(p, ..)| *p == peer) {
+            return Err(unreachable("is not connected").into());
+        }
+        let exchange = &self.data.offsets_exchange;
+        let id = exchange.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        exchange.pending.lock().insert(id, (peer, tx));
+        let local = self.node_offsets();
+        let request = ExchangeMessage::Request { id, to: peer };
+        let sent = self
+            .data
+            .gossip
+            .publish_policy()
+            .broadcast(&mut self.ipfs().clone(), &topic(self), serde_cbor::to_vec(&request)?)
+            .await;
+        let root = match sent {
+            Ok(()) => tokio::time::timeout(EXCHANGE_TIMEOUT, rx)
+                .await
+                .ok()
+                .and_then(Result::ok),
+            Err(err) => {
+                tracing::debug!("cannot send offsets request to {}: {}", peer, err);
+                None
+            }
+        };
+        exchange.pending.lock().remove(&id);
+        let root = root.ok_or_else(|| unreachable("did not answer"))?;
+        let remote = match tokio::time::timeout(EXCHANGE_TIMEOUT, self.fetch_node_offsets(peer, root)).await {
+            Ok(Ok(remote)) => remote,
+            Ok(Err(err)) => {
+                tracing::debug!("cannot fetch offsets {} from {}: {}", root, peer, err);
+                return Err(unreachable("did not provide its offsets").into());
+            }
+            Err(_) => return Err(unreachable("did not provide its offsets in time").into()),
+        };
+        Ok(OffsetsComparison::new(local, remote))
+    }
+}
+
+/// Answers requests of other peers and hands their answers to the waiting requests, runs until the
+/// swarm shuts down.
+pub(crate) async fn offsets_exchange(store: BanyanStore) {
+    let mut ipfs = store.ipfs().clone();
+    let topic = topic(&store);
+    let local_peer_id = ipfs.local_peer_id();
+    let messages = match ipfs.subscribe(topic.clone()).await {
+        Ok(events) => events.filter_map(|event| {
+            future::ready(match event {
+                GossipEvent::Message(sender, message) => Some((sender, message)),
+                _ => None,
+            })
+        }),
+        Err(err) => {
+            tracing::error!("cannot subscribe to {}: {}", topic, err);
+            return;
+        }
+    };
+    futures::pin_mut!(messages);
+    while let Some((sender, message)) = messages.next().await {
+        let message = match serde_cbor::from_slice::<ExchangeMessage>(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::debug!("invalid offsets exchange message from {}: {}", sender, err);
+                continue;
+            }
+        };
+        match message {
+            ExchangeMessage::Request { id, to } if to == local_peer_id => {
+                tracing::debug!("{} requested offsets", sender);
+                let (root, tmp) = match store.publish_node_offsets() {
+                    Ok(published) => published,
+                    Err(err) => {
+                        tracing::warn!("cannot answer offsets request of {}: {}", sender, err);
+                        continue;
+                    }
+                };
+                let response = ExchangeMessage::Response { id, to: sender, root };
+                let sent = match serde_cbor::to_vec(&response) {
+                    Ok(bytes) => {
+                        store
+                            .data
+                            .gossip
+                            .publish_policy()
+                            .broadcast(&mut ipfs, &topic, bytes)
+                            .await
+                    }
+                    Err(err) => Err(err.into()),
+                };
+                match sent {
+                    // keep the offsets around for as long as the requester may still fetch them
+                    Ok(()) => {
+                        tokio::spawn(async move {
+                            tokio::time::sleep(2 * EXCHANGE_TIMEOUT).await;
+                            drop(tmp);
+                        });
+                    }
+                    Err(err) => tracing::warn!("cannot answer offsets request of {}: {}", sender, err),
+                }
+            }
+            ExchangeMessage::Response { id, to, root } if to == local_peer_id => {
+                let mut pending = store.data.offsets_exchange.pending.lock();
+                // only the peer the request was sent to may answer it
+                if matches!(pending.get(&id), Some((peer, _)) if *peer == sender) {
+                    if let Some((_, tx)) = pending.remove(&id) {
+                        tx.send(root).ok();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::{NodeId, Offset};
+
+    fn stream(n: u8) -> StreamId {
+        NodeId::from_bytes(&[n; 32]).unwrap().stream(0.into())
+    }
+
+    fn offsets(entries: &[(u8, u32)]) -> NodeOffsets {
+        let present = entries
+            .iter()
+            .map(|(n, offset)| (stream(*n), Offset::from(*offset)))
+            .collect::<OffsetMap>();
+        NodeOffsets {
+            replication_target: present.clone(),
+            present,
+        }
+    }
+
+    #[test]
+    fn compare() {
+        let comparison = OffsetsComparison::new(
+            offsets(&[(1, 3), (2, 5), (3, 7), (4, 1)]),
+            offsets(&[(2, 5), (3, 9), (4, 0), (5, 2)]),
+        );
+        assert_eq!(comparison.only_local, vec![stream(1)]);
+        assert_eq!(comparison.only_remote, vec![stream(5)]);
+        assert_eq!(comparison.local_ahead, BTreeMap::from([(stream(4), 1)]));
+        assert_eq!(comparison.remote_ahead, BTreeMap::from([(stream(3), 2)]));
+        assert!(!comparison.is_equal());
+
+        assert!(OffsetsComparison::new(offsets(&[(1, 3)]), offsets(&[(1, 3)])).is_equal());
+    }
+}
@@ -0,0 +1,37 @@
+//! Packing of own streams requested by appends, carried out by the compaction loop.
+//!
+//! Appends only add unpacked leaves to a tree. Packing a stream with many of them takes a while,
+//! so instead of doing it inside the append that crosses [`BanyanConfig::max_unpacked_leaves`]
+//! the stream is handed to the compaction loop.
+//!
+//! [`BanyanConfig::max_unpacked_leaves`]: crate::swarm::BanyanConfig::max_unpacked_leaves
+use ax_types::StreamNr;
+use parking_lot::Mutex;
+use std::collections::BTreeSet;
+use tokio::sync::Notify;
+
+#[derive(Default)]
+pub(crate) struct PackRequests {
+    streams: Mutex<BTreeSet<StreamNr>>,
+    notify: Notify,
+}
+
+impl PackRequests {
+    /// Ask the compaction loop to pack the stream, requests for the same stream are merged.
+    pub fn request(&self, stream_nr: StreamNr) {
+        if self.streams.lock().insert(stream_nr) {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Wait until packing of at least one stream has been requested and take all requests.
+    pub async fn requested(&self) -> BTreeSet<StreamNr> {
+        loop {
+            let streams = std::mem::take(&mut *self.streams.lock());
+            if !streams.is_empty() {
+                return streams;
+            }
+            self.notify.notified().await;
+        }
+    }
+}
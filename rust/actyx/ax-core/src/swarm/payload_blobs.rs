@@ -0,0 +1,226 @@
+//! Large event payloads can be moved out of the event trees into unixfs blobs. The event then
+//! carries a compact [`PayloadRef`] instead, which keeps the trees small and queries fast.
+//!
+//! Blobs are pinned by an alias for as long as at least one of our own events references them.
+//! The reference count lives in the index store. It is increased before the referencing event is
+//! written and decreased when events are pruned.
+use crate::swarm::{BanyanStore, Tree};
+use anyhow::{Context, Result};
+use ax_types::Payload;
+use banyan::query::AllQuery;
+use futures::TryStreamExt;
+use ipfs_embed::TempPin;
+use libipld::Cid;
+use serde::{
+    de::{self, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fmt};
+
+/// Externalized payloads are stored in their binary encoding.
+const PAYLOAD_MEDIA_TYPE: &str = "application/cbor";
+
+const BLOB_MARKER: &[u8] = b"ax-payload-blob";
+
+/// Marks a [`PayloadRef`] written by this store.
+///
+/// It is encoded as a CBOR byte string, which payloads submitted as JSON cannot contain, so an app
+/// cannot have its payload taken for a reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlobMarker;
+
+impl Serialize for BlobMarker {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(BLOB_MARKER)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlobMarker {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MarkerVisitor;
+        impl Visitor<'_> for MarkerVisitor {
+            type Value = BlobMarker;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("the payload blob marker bytes")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                if v == BLOB_MARKER {
+                    Ok(BlobMarker)
+                } else {
+                    Err(E::invalid_value(Unexpected::Bytes(v), &self))
+                }
+            }
+        }
+        deserializer.deserialize_bytes(MarkerVisitor)
+    }
+}
+
+/// Compact payload standing in for a payload that has been stored as a unixfs blob.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PayloadRef {
+    /// Root of the unixfs file holding the original payload
+    pub blob_cid: String,
+    /// Size of the original payload in bytes
+    pub size: u64,
+    /// Encoding of the blob contents
+    pub media_type: String,
+    /// Hex-encoded SHA-256 digest of the original payload
+    pub original_hash: String,
+    pub marker: BlobMarker,
+}
+
+impl PayloadRef {
+    /// Recognise a reference payload; any other payload yields `None`.
+    pub fn from_payload(payload: &Payload) -> Option<Self> {
+        let this = payload.extract::<Self>().ok()?;
+        (this.media_type == PAYLOAD_MEDIA_TYPE && this.cid().is_ok()).then_some(this)
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        Payload::compact(self).expect("PayloadRef is always serializable")
+    }
+
+    pub fn cid(&self) -> Result<Cid> {
+        self.blob_cid.parse().context("parsing blob CID")
+    }
+}
+
+struct PayloadBlobAlias(Vec<u8>);
+impl From<&Cid> for PayloadBlobAlias {
+    fn from(cid: &Cid) -> Self {
+        Self(format!("payload_blob:{}", cid).into_bytes())
+    }
+}
+impl AsRef<[u8]> for PayloadBlobAlias {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+fn hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+impl BanyanStore {
+    /// Store the payload as a unixfs blob and return the reference to put into the event.
+    ///
+    /// The blob is only protected by `tmp` until [`pin_payload_blob`](Self::pin_payload_blob)
+    /// is called, which must happen before the referencing event is written.
+    pub(crate) fn externalize_payload(&self, tmp: &mut TempPin, payload: &Payload) -> Result<(Payload, Cid)> {
+        let bytes = payload.as_slice();
+        let (cid, size) = self.add(tmp, bytes)?;
+        let reference = PayloadRef {
+            blob_cid: cid.to_string(),
+            size: size as u64,
+            media_type: PAYLOAD_MEDIA_TYPE.to_owned(),
+            original_hash: hash(bytes),
+            marker: BlobMarker,
+        };
+        tracing::debug!(%cid, size, "externalized payload");
+        Ok((reference.to_payload(), cid))
+    }
+
+    /// Record a new event referencing the blob, pinning it when it is first referenced.
+    ///
+    /// If the event is not written after all, the reference is taken back with
+    /// [`unpin_payload_blobs`](Self::unpin_payload_blobs).
+    pub(crate) fn pin_payload_blob(&self, cid: &Cid) -> Result<()> {
+        let refs = self.lock().index_store.add_payload_blob_ref(cid)?;
+        if refs == 1 {
+            self.ipfs().alias(PayloadBlobAlias::from(cid), Some(cid))?;
+        }
+        Ok(())
+    }
+
+    /// Take back the references recorded by [`pin_payload_blob`](Self::pin_payload_blob) for
+    /// events that have not been written after all.
+    pub(crate) fn unpin_payload_blobs(&self, cids: &[Cid]) {
+        for cid in cids {
+            let res = self.lock().index_store.remove_payload_blob_ref(cid);
+            let res = match res {
+                Ok(Some(0)) => self.ipfs().alias(PayloadBlobAlias::from(cid), None),
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = res {
+                tracing::warn!(%cid, "cannot unpin payload blob: {:#}", err);
+            }
+        }
+    }
+
+    /// Release the blobs referenced from events in `before` that are no longer present in `after`,
+    /// unpinning those that are not referenced by any event anymore.
+    ///
+    /// Without a `payload_blob_threshold` this node has no blobs to release and the trees are not
+    /// looked at.
+    pub(crate) fn release_payload_blobs(&self, before: &Tree, after: &Tree) -> Result<()> {
+        if self.data.payload_blob_threshold.is_none() {
+            return Ok(());
+        }
+        let mut released = self.payload_refs(before)?;
+        for (cid, n) in self.payload_refs(after)? {
+            if let Some(count) = released.get_mut(&cid) {
+                *count = count.saturating_sub(n);
+            }
+        }
+        let mut state = self.lock();
+        for (cid, n) in released {
+            for _ in 0..n {
+                match state.index_store.remove_payload_blob_ref(&cid)? {
+                    Some(0) => {
+                        tracing::debug!(%cid, "releasing payload blob");
+                        self.ipfs().alias(PayloadBlobAlias::from(&cid), None)?;
+                    }
+                    Some(_) => {}
+                    // not externalized by us, e.g. an event published as reference by an app
+                    None => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_payload_blob_pinned(&self, cid: &Cid) -> Result<bool> {
+        Ok(self.ipfs().resolve(PayloadBlobAlias::from(cid))?.is_some())
+    }
+
+    fn payload_refs(&self, tree: &Tree) -> Result<BTreeMap<Cid, u64>> {
+        let mut refs = BTreeMap::new();
+        for res in self.data.forest.iter_filtered(tree, AllQuery) {
+            let (_, _, payload) = res?;
+            if let Some(cid) = PayloadRef::from_payload(&payload).and_then(|r| r.cid().ok()) {
+                *refs.entry(cid).or_default() += 1;
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Replace a [`PayloadRef`] by the original payload, fetching the blob from the swarm if
+    /// needed. Other payloads are returned unchanged.
+    pub async fn inline_payload(&self, payload: Payload) -> Result<Payload> {
+        let Some(reference) = PayloadRef::from_payload(&payload) else {
+            return Ok(payload);
+        };
+        let bytes = self
+            .cat(reference.cid()?, false)
+            .try_fold(
+                Vec::with_capacity(reference.size as usize),
+                |mut acc, chunk| async move {
+                    acc.extend_from_slice(&chunk);
+                    Ok(acc)
+                },
+            )
+            .await?;
+        anyhow::ensure!(
+            bytes.len() as u64 == reference.size && hash(&bytes) == reference.original_hash,
+            "payload blob {} does not match its reference",
+            reference.blob_cid
+        );
+        Ok(Payload::from_slice(&bytes))
+    }
+}
@@ -0,0 +1,144 @@
+//! Connectivity of the swarm as a stream of typed events.
+//!
+//! ipfs-embed hands out its swarm events to a single consumer, and reports a new connection
+//! sometimes as `Connected` and sometimes only as `ConnectionEstablished`. The events are
+//! translated here into [`PeerEvent`]s, with exactly one `Connected` and one `Disconnected` per
+//! peer connectivity change, and fanned out to any number of subscribers. The currently connected
+//! peers are tracked so that subscribers joining late first receive a `Connected` event for each
+//! of them.
+use crate::swarm::BanyanStore;
+use futures::{channel::mpsc, Stream, StreamExt};
+use ipfs_embed::{Multiaddr, PeerId};
+use parking_lot::Mutex;
+use std::collections::{btree_map::Entry, BTreeMap};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent {
+    /// The first connection to the peer was established, via `addr`
+    Connected { peer: PeerId, addr: Multiaddr },
+    /// The last connection to the peer was closed
+    Disconnected { peer: PeerId, reason: DisconnectReason },
+    /// Listening for incoming connections on a new address
+    NewListenAddr { addr: Multiaddr },
+    /// No longer listening for incoming connections on the address
+    ExpiredListenAddr { addr: Multiaddr },
+    /// An address of the peer was learned, e.g. via mdns
+    Discovered { peer: PeerId },
+    /// Dialing the peer failed
+    Unreachable { peer: PeerId },
+    /// An external address was configured or reported by a peer
+    NewExternalAddr { addr: Multiaddr },
+    /// An external address was removed
+    ExpiredExternalAddr { addr: Multiaddr },
+    /// New ping or identify information is available for the peer
+    NewInfo { peer: PeerId },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The peer stopped answering pings
+    PingFailure,
+    /// The connection was closed by either side
+    Closed,
+}
+
+#[derive(Default)]
+pub(crate) struct PeerEvents(Mutex<PeerEventsInner>);
+
+#[derive(Default)]
+struct PeerEventsInner {
+    connected: BTreeMap<PeerId, Multiaddr>,
+    listeners: Vec<mpsc::UnboundedSender<PeerEvent>>,
+}
+
+impl PeerEvents {
+    fn subscribe(&self) -> mpsc::UnboundedReceiver<PeerEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        // snapshot and registration under the same lock, so no change falls in between
+        let mut inner = self.0.lock();
+        for (peer, addr) in &inner.connected {
+            let _ = tx.unbounded_send(PeerEvent::Connected {
+                peer: *peer,
+                addr: addr.clone(),
+            });
+        }
+        inner.listeners.push(tx);
+        rx
+    }
+
+    fn publish(&self, event: PeerEvent) {
+        let mut inner = self.0.lock();
+        match &event {
+            PeerEvent::Connected { peer, addr } => match inner.connected.entry(*peer) {
+                Entry::Occupied(_) => return,
+                Entry::Vacant(entry) => {
+                    entry.insert(addr.clone());
+                }
+            },
+            PeerEvent::Disconnected { peer, .. } => {
+                if inner.connected.remove(peer).is_none() {
+                    return;
+                }
+            }
+            _ => {}
+        }
+        inner.listeners.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+impl BanyanStore {
+    /// Connectivity changes of the swarm.
+    ///
+    /// The stream can be subscribed at any time and starts with a `Connected` event for each peer
+    /// that is currently connected.
+    pub fn peer_events(&self) -> impl Stream<Item = PeerEvent> + Send + Unpin {
+        self.data.peer_events.subscribe()
+    }
+}
+
+/// Translates the swarm events of ipfs-embed for [`BanyanStore::peer_events`], runs until the swarm
+/// shuts down.
+pub(crate) async fn translate_swarm_events(
+    store: BanyanStore,
+    mut events: impl Stream<Item = ipfs_embed::Event> + Unpin,
+) {
+    while let Some(event) = events.next().await {
+        tracing::trace!("swarm event {:?}", event);
+        let event = match event {
+            ipfs_embed::Event::ConnectionEstablished(peer, endpoint) => PeerEvent::Connected {
+                peer,
+                addr: endpoint.get_remote_address().clone(),
+            },
+            // only emitted for some connections and without the address, see above
+            ipfs_embed::Event::Connected(peer) => {
+                let connection = store.ipfs().connections().into_iter().find(|(p, ..)| *p == peer);
+                let Some((_, addr, ..)) = connection else {
+                    continue;
+                };
+                PeerEvent::Connected { peer, addr }
+            }
+            ipfs_embed::Event::Disconnected(peer) => {
+                let ping_failed = store
+                    .ipfs()
+                    .peer_info(&peer)
+                    .and_then(|info| info.full_rtt().map(|rtt| rtt.failures() > 0))
+                    .unwrap_or_default();
+                let reason = if ping_failed {
+                    DisconnectReason::PingFailure
+                } else {
+                    DisconnectReason::Closed
+                };
+                PeerEvent::Disconnected { peer, reason }
+            }
+            ipfs_embed::Event::NewListenAddr(_, addr) => PeerEvent::NewListenAddr { addr },
+            ipfs_embed::Event::ExpiredListenAddr(_, addr) => PeerEvent::ExpiredListenAddr { addr },
+            ipfs_embed::Event::Discovered(peer) => PeerEvent::Discovered { peer },
+            ipfs_embed::Event::Unreachable(peer) => PeerEvent::Unreachable { peer },
+            ipfs_embed::Event::NewExternalAddr(addr) => PeerEvent::NewExternalAddr { addr },
+            ipfs_embed::Event::ExpiredExternalAddr(addr) => PeerEvent::ExpiredExternalAddr { addr },
+            ipfs_embed::Event::NewInfo(peer) => PeerEvent::NewInfo { peer },
+            _ => continue,
+        };
+        store.data.peer_events.publish(event);
+    }
+}
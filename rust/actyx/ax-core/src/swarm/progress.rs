@@ -0,0 +1,166 @@
+//! Reading events together with how far the read has come, see
+//! [`BanyanStore::stream_filtered_with_progress`].
+use crate::{
+    ax_futures_util::stream::AxStreamExt,
+    swarm::{BanyanStore, Event, Key, TT},
+};
+use anyhow::Result;
+use ax_types::{Offset, OffsetMap, StreamId};
+use banyan::{query::Query, FilteredChunk};
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    Stream, StreamExt, TryStreamExt,
+};
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+/// An item of [`BanyanStore::stream_filtered_with_progress`]
+#[derive(Debug, Clone)]
+pub enum ProgressItem {
+    Event((u64, Key, Event)),
+    /// All events up to these offsets have been delivered, including those before `lower`
+    Progress(OffsetMap),
+}
+
+/// How often [`ProgressItem::Progress`] is emitted.
+///
+/// Progress can only be reported once all events of a chunk have been delivered, so it is emitted
+/// at the end of the first chunk after `events` events or `interval` since the previous report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressCadence {
+    pub events: usize,
+    pub interval: Duration,
+}
+
+impl Default for ProgressCadence {
+    fn default() -> Self {
+        Self {
+            events: 1000,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+type Chunk = FilteredChunk<(u64, Key, Event), ()>;
+
+struct State {
+    chunks: BoxStream<'static, Result<(StreamId, Offset, Chunk)>>,
+    cadence: ProgressCadence,
+    progress: OffsetMap,
+    pending: VecDeque<(u64, Key, Event)>,
+    events: usize,
+    reported: Instant,
+    done: bool,
+}
+
+impl State {
+    fn report(&mut self) -> ProgressItem {
+        self.events = 0;
+        self.reported = Instant::now();
+        ProgressItem::Progress(self.progress.clone())
+    }
+
+    async fn next(mut self) -> Option<(Result<ProgressItem>, Self)> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                self.events += 1;
+                return Some((Ok(ProgressItem::Event(event)), self));
+            }
+            if self.done {
+                return None;
+            }
+            if self.events > 0
+                && (self.events >= self.cadence.events || self.reported.elapsed() >= self.cadence.interval)
+            {
+                let item = self.report();
+                return Some((Ok(item), self));
+            }
+            match self.chunks.next().await {
+                Some(Ok((stream_id, end, chunk))) => {
+                    // the chunk covers its whole range, including the events not matching the query
+                    let last = chunk
+                        .range
+                        .end
+                        .checked_sub(1)
+                        .and_then(|last| Offset::try_from(last).ok())
+                        .map(|last| last.min(end));
+                    if let Some(last) = last {
+                        self.progress.update(stream_id, last);
+                    }
+                    self.pending.extend(chunk.data);
+                    if self.pending.is_empty() && self.reported.elapsed() >= self.cadence.interval {
+                        let item = self.report();
+                        return Some((Ok(item), self));
+                    }
+                }
+                Some(Err(err)) => return Some((Err(err), self)),
+                None => {
+                    self.done = true;
+                    let item = self.report();
+                    return Some((Ok(item), self));
+                }
+            }
+        }
+    }
+}
+
+impl BanyanStore {
+    /// Returns the events matching `query` of the streams in `upper` between `lower` (exclusive)
+    /// and `upper` (inclusive), interleaved with [`ProgressItem::Progress`] according to `cadence`.
+    ///
+    /// The events of a stream are delivered in ascending offset order. Each progress item covers
+    /// exactly the events delivered before it, so a read interrupted after a progress item can be
+    /// resumed by passing it as `lower`. Streams that have not reached their bound yet are waited
+    /// for; the last item is the progress `upper`.
+    pub fn stream_filtered_with_progress<Q: Query<TT> + Clone + 'static>(
+        &self,
+        query: Q,
+        lower: OffsetMap,
+        upper: OffsetMap,
+        cadence: ProgressCadence,
+    ) -> impl Stream<Item = Result<ProgressItem>> {
+        let mut progress = OffsetMap::empty();
+        let mut ranges = vec![];
+        for (stream_id, end) in upper.stream_iter() {
+            match lower.get(stream_id) {
+                Some(delivered) if delivered >= end => {
+                    progress.update(stream_id, end);
+                }
+                Some(delivered) => {
+                    progress.update(stream_id, delivered);
+                    ranges.push((stream_id, u64::from(delivered) + 1, end));
+                }
+                None => ranges.push((stream_id, 0, end)),
+            }
+        }
+        let this = self.clone();
+        // chunks are merged as a whole and progress is only reported between them, so that it
+        // never covers events that are still pending
+        let chunks = stream::iter(ranges)
+            .map(move |(stream_id, start, end)| {
+                let last = u64::from(end);
+                this.stream_filtered_chunked(stream_id, start..=last, query.clone())
+                    .take_until_condition(move |chunk| {
+                        future::ready(chunk.as_ref().map(|chunk| chunk.range.end > last).unwrap_or(true))
+                    })
+                    .map_ok(move |chunk| (stream_id, end, chunk))
+                    .boxed()
+            })
+            .merge_unordered()
+            .boxed();
+        let state = State {
+            chunks,
+            cadence,
+            progress,
+            pending: VecDeque::new(),
+            events: 0,
+            reported: Instant::now(),
+            done: false,
+        };
+        stream::unfold(state, State::next)
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{
-    swarm::{streams::OwnStreamGuard, BanyanStore, EphemeralEventsConfig, Link},
+    swarm::{
+        streams::OwnStreamGuard, BanyanStore, EphemeralEventsConfig, Link, MaintenanceBudget, MaintenanceTask,
+        FILES_STREAM_NUMBER,
+    },
     trees::{
         axtrees::AxTrees,
         query::{OffsetQuery, TimeQuery},
     },
 };
-use ax_types::{Payload, Timestamp};
+use ax_types::{Payload, StreamNr, Timestamp};
 use banyan::{query::AndQuery, Tree};
 use futures::future::{join_all, FutureExt};
 use lazy_static::lazy_static;
@@ -366,32 +369,55 @@ impl RetainConfig {
     }
 }
 
-fn calculate_emit_from(store: &BanyanStore, tree: Tree<AxTrees, Payload>, size: u64) -> u64 {
+/// Offset from which on the newest events fit into `size` bytes.
+///
+/// Whole leaves are accounted by the value bytes recorded in the index, so only the leaf in which
+/// the budget runs out needs to be loaded; its value bytes are apportioned to its events by
+/// payload size, keeping as many of the newest ones as still fit.
+fn calculate_emit_from(store: &BanyanStore, tree: Tree<AxTrees, Payload>, size: u64) -> anyhow::Result<u64> {
     let iter = store.data.forest.iter_index_reverse(&tree, banyan::query::AllQuery);
     let mut bytes = 0u64;
     let mut current_offset = tree.count();
     for maybe_index in iter {
-        let index = maybe_index.unwrap();
         // If we want to be a bit smarter here, we need to extend
         // `banyan` for a more elaborated traversal API. For now a plain
         // iterator is enough, and will be for a long time.
-        if let banyan::index::Index::Leaf(l) = index {
+        let banyan::index::Index::Leaf(l) = maybe_index? else {
+            continue;
+        };
+        let leaf_start = current_offset - l.keys().count() as u64;
+        if bytes + l.value_bytes <= size {
             // Only the value bytes are taken into account
             bytes += l.value_bytes;
-            current_offset -= l.keys().count() as u64;
-            if bytes >= size {
-                tracing::debug!(
-                    "Hitting size target {} > {}. \
-                            Results in min offset (non-inclusive) {}",
-                    bytes + l.value_bytes,
-                    size,
-                    current_offset
-                );
-                return current_offset;
+            current_offset = leaf_start;
+            continue;
+        }
+        let payload_sizes = store
+            .data
+            .forest
+            .iter_filtered_reverse(&tree, OffsetQuery::from(leaf_start..current_offset))
+            .map(|res| res.map(|(offset, _, payload)| (offset, payload.as_slice().len() as u64)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let total = payload_sizes.iter().map(|(_, len)| len).sum::<u64>().max(1);
+        for (offset, len) in payload_sizes {
+            // rounding up, so that no event is considered free
+            let share =
+                ((u128::from(l.value_bytes) * u128::from(len) + u128::from(total) - 1) / u128::from(total)) as u64;
+            if bytes + share > size {
+                break;
             }
+            bytes += share;
+            current_offset = offset;
         }
+        tracing::debug!(
+            "Hitting size target {} with {} bytes. Results in min offset {}",
+            size,
+            bytes,
+            current_offset
+        );
+        return Ok(current_offset);
     }
-    0
+    Ok(0)
 }
 
 // The timestamp parameter is used has an hack around having to use a fake system clock
@@ -403,6 +429,7 @@ fn prune_stream(
     now: Timestamp,
 ) -> anyhow::Result<Option<Link>> {
     let stream_nr = stream.stream_nr();
+    let before = stream.snapshot();
     store.transform_stream(&mut stream, |transaction, tree| {
         let _span = tracing::debug_span!("prune", stream_nr = u64::from(stream_nr)).entered();
         transaction.pack(tree)?;
@@ -414,9 +441,10 @@ fn prune_stream(
 
         let events_lower_bound = config.max_events.map_or(0, |count| tree.count().saturating_sub(count));
 
-        let size_lower_bound = config
-            .max_size
-            .map_or(0, |size| calculate_emit_from(store, tree.snapshot(), size.into()));
+        let size_lower_bound = match config.max_size {
+            Some(size) => calculate_emit_from(store, tree.snapshot(), size.into())?,
+            None => 0,
+        };
 
         let query = AndQuery(
             time_query,
@@ -426,29 +454,64 @@ fn prune_stream(
         tracing::debug!("Pruning: events on {}; retain {:?}", stream_nr, query);
         transaction.retain(tree, &query)
     })?;
-    Ok(stream.snapshot().link())
+    let after = stream.snapshot();
+    if after.link() != before.link() {
+        store.release_payload_blobs(&before, &after)?;
+        if stream_nr == StreamNr::from(FILES_STREAM_NUMBER) {
+            store.release_pruned_file_names(&before, &after)?;
+        }
+    }
+    Ok(after.link())
 }
 
 /// Prunes all ephemeral events for the streams configured via the respective
-/// [`RetainConfig`] in [`EphemeralEventsConfig`] in parallel. After all streams
-/// have been cleaned, waits for the duration given in
-/// [`EphemeralEventsConfig::interval`].
+/// [`RetainConfig`] of an enabled [`EphemeralEventsConfig`] in parallel. After all
+/// streams have been cleaned, waits for `interval`.
+/// Outside of maintenance windows passes may be skipped or only prune a single stream,
+/// see [`MaintenanceSchedule`](super::MaintenanceSchedule).
 /// Note that any unsealed nodes remain untouched.
-pub(crate) async fn prune(store: BanyanStore, config: EphemeralEventsConfig) {
+/// The configuration is read from the store's current settings before every pass; the task is
+/// only running while pruning is enabled.
+pub(crate) async fn prune(store: BanyanStore) {
+    // position of the stream to prune next with reduced budget
+    let mut next = 0;
     loop {
-        tokio::time::sleep(config.interval).await;
-        let tasks = config.streams.iter().map(|(stream_name, cfg)| {
+        let ephemeral_events = || store.data.settings.project(|s| s.ephemeral_events.clone());
+        let EphemeralEventsConfig::Enabled { interval, .. } = ephemeral_events() else {
+            return future::pending().await;
+        };
+        tokio::time::sleep(interval).await;
+        let EphemeralEventsConfig::Enabled { streams: config, .. } = ephemeral_events() else {
+            return future::pending().await;
+        };
+        let streams = match store.data.maintenance.budget(MaintenanceTask::Pruning) {
+            MaintenanceBudget::Full => config.iter().collect::<Vec<_>>(),
+            MaintenanceBudget::Reduced => {
+                next = (next + 1) % config.len().max(1);
+                config.iter().skip(next).take(1).collect()
+            }
+            MaintenanceBudget::Pause => {
+                tracing::debug!("Pruning paused outside of maintenance windows");
+                continue;
+            }
+        };
+        let tasks = streams.into_iter().map(|(stream_name, cfg)| {
             let store = store.clone();
             tracing::debug!("Checking ephemeral event conditions for {}", stream_name);
 
-            let stream_nr = store.data.routing_table.stream_mapping.get(stream_name).copied();
+            let stream_nr = store.routing_table().stream_mapping.get(stream_name).copied();
 
             let Some(stream_nr) = stream_nr else {
                 return future::ready(()).left_future();
             };
+            // a purged stream cannot be recreated and has nothing left to prune
+            if store.lock().is_purged(store.node_id().stream(stream_nr)) {
+                tracing::debug!("Not pruning purged stream {}", stream_name);
+                return future::ready(()).left_future();
+            }
 
             let fut = async move {
-                let stream = store.get_or_create_own_stream(stream_nr).unwrap();
+                let stream = store.get_or_create_own_stream(stream_nr)?;
                 let guard = stream.lock().await;
                 prune_stream(&store, guard, cfg, Timestamp::now())
             };
@@ -473,7 +536,7 @@ mod test {
     use super::*;
     use crate::{
         ax_futures_util::stream::AxStreamExt,
-        swarm::{BanyanConfig, EventRoute, SwarmConfig},
+        swarm::{BanyanConfig, EphemeralEventsConfig, EventRoute, SwarmConfig},
         trees::query::TagExprQuery,
     };
     use acto::ActoRef;
@@ -481,6 +544,7 @@ mod test {
     use ax_types::{app_id, tags, AppId, Payload, StreamNr};
     use futures::{future, StreamExt, TryStreamExt};
     use itertools::Either;
+    use libipld::Cid;
     use parking_lot::Mutex;
     use std::{collections::BTreeMap, iter::once, sync::Arc};
     use tokio::time::{sleep, timeout};
@@ -491,12 +555,16 @@ mod test {
 
     async fn create_store() -> anyhow::Result<BanyanStore> {
         crate::util::setup_logger();
-        let cfg: SwarmConfig = SwarmConfig {
+        BanyanStore::new(store_config(), ActoRef::blackhole()).await
+    }
+
+    fn store_config() -> SwarmConfig {
+        SwarmConfig {
             node_name: Some("ephemeral".to_owned()),
             topic: "topic".into(),
             enable_mdns: false,
             listen_addresses: Arc::new(Mutex::new("127.0.0.1:0".parse().unwrap())),
-            ephemeral_event_config: EphemeralEventsConfig::disable(),
+            ephemeral_event_config: EphemeralEventsConfig::Disabled,
             banyan_config: BanyanConfig {
                 tree: banyan::Config::debug(),
                 ..Default::default()
@@ -506,8 +574,7 @@ mod test {
                 "test_stream".to_string(),
             )],
             ..SwarmConfig::basic()
-        };
-        BanyanStore::new(cfg, ActoRef::blackhole()).await
+        }
     }
 
     async fn publish_events(event_count: u64) -> anyhow::Result<BanyanStore> {
@@ -571,7 +638,8 @@ mod test {
         test_retain_count(0).await;
     }
 
-    async fn test_retain_size(max_size: u64) {
+    /// Prunes a stream of 1024 events down to `max_size` bytes, returning the retained offsets.
+    async fn test_retain_size(max_size: u64) -> Vec<u64> {
         let upper_bound = 1024;
         let test_stream = StreamNr::from(1);
 
@@ -582,44 +650,247 @@ mod test {
         super::prune_stream(&store, guard, &RetainConfig::size(max_size), Timestamp::now()).unwrap();
 
         let query = OffsetQuery::from(0..);
-        let round_tripped = store
+        let retained = store
             .stream_filtered_chunked(store.node_id().stream(test_stream), 0..=u64::MAX, query)
             .take_until_condition(|x| future::ready(x.as_ref().unwrap().range.end >= upper_bound))
             .collect::<Vec<_>>()
             .await
             .into_iter()
-            .rev()
             .collect::<Result<Vec<_>, _>>()
-            .unwrap();
-        let mut bytes = 0u64;
-        for chunk in round_tripped {
-            tracing::debug!(
-                "-----\nmax_size {}\nbytes {}\nchunk.data.len() {}\n{:?}\n-----",
-                max_size,
-                bytes,
-                chunk.data.len(),
-                chunk
-            );
+            .unwrap()
+            .into_iter()
+            .flat_map(|chunk| chunk.data.into_iter().map(|(offset, _, _)| offset))
+            .collect::<Vec<_>>();
+        tracing::debug!("max_size {} retains {} events", max_size, retained.len());
+        // only the newest events are kept
+        assert!(
+            retained
+                .iter()
+                .copied()
+                .eq(upper_bound - retained.len() as u64..upper_bound),
+            "{:?}",
+            retained
+        );
+        retained
+    }
 
-            if bytes > max_size {
-                assert!(chunk.data.is_empty());
-            } else {
-                bytes += chunk.data.len() as u64 * 4;
-                assert_eq!(chunk.data.len(), chunk.range.count());
-            }
+    #[tokio::test]
+    async fn retain_max_size() {
+        let mut previous = 0;
+        for max_size in [0, 1, 256, 512, 1023, 1024, 1025, u64::MAX] {
+            let retained = test_retain_size(max_size).await.len();
+            assert!(
+                retained >= previous,
+                "{} retains fewer events than a smaller budget",
+                max_size
+            );
+            previous = retained;
         }
+        assert_eq!(test_retain_size(0).await.len(), 0);
+        assert_eq!(test_retain_size(u64::MAX).await.len(), 1024);
     }
 
     #[tokio::test]
-    async fn retain_max_size() {
-        test_retain_size(u64::MAX).await;
-        test_retain_size(1025).await;
-        test_retain_size(1024).await;
-        test_retain_size(1023).await;
-        test_retain_size(512).await;
-        test_retain_size(256).await;
-        test_retain_size(1).await;
-        test_retain_size(0).await;
+    async fn retain_size_splits_leaf() {
+        let store = publish_events(1024).await.unwrap();
+        let stream = store.get_or_create_own_stream(StreamNr::from(1)).unwrap();
+        // packs the tree without dropping anything
+        super::prune_stream(
+            &store,
+            stream.lock().await,
+            &RetainConfig::size(u64::MAX),
+            Timestamp::now(),
+        )
+        .unwrap();
+        let tree = stream.lock().await.snapshot();
+        let newest_leaf = store
+            .data
+            .forest
+            .iter_index_reverse(&tree, banyan::query::AllQuery)
+            .find_map(|index| match index.unwrap() {
+                banyan::index::Index::Leaf(l) => Some(l),
+                _ => None,
+            })
+            .unwrap();
+        let leaf_events = newest_leaf.keys().count();
+        assert!(leaf_events > 1);
+
+        super::prune_stream(
+            &store,
+            stream.lock().await,
+            &RetainConfig::size(newest_leaf.value_bytes / 2),
+            Timestamp::now(),
+        )
+        .unwrap();
+        let tree = stream.lock().await.snapshot();
+        let retained = store
+            .data
+            .forest
+            .iter_filtered(&tree, banyan::query::AllQuery)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .len();
+        assert!(
+            retained > 0 && retained < leaf_events,
+            "{} of {}",
+            retained,
+            leaf_events
+        );
+    }
+
+    #[test]
+    fn ephemeral_events_with_size() {
+        let config: EphemeralEventsConfig =
+            r#"{"interval":{"secs":60,"nanos":0},"streams":{"files":{"maxSize":"1GiB"}}}"#
+                .parse()
+                .unwrap();
+        assert_eq!(
+            config,
+            EphemeralEventsConfig::new(
+                Duration::from_secs(60),
+                BTreeMap::from([(
+                    "files".to_owned(),
+                    RetainConfig {
+                        max_size: Some(StreamSize::GibiBytes(1)),
+                        ..Default::default()
+                    }
+                )])
+            )
+        );
+    }
+
+    #[test]
+    fn ephemeral_events_disabled() {
+        let old = r#"{"interval":{"secs":18446744073709551615,"nanos":0},"streams":{}}"#;
+        assert_eq!(
+            old.parse::<EphemeralEventsConfig>().unwrap(),
+            EphemeralEventsConfig::Disabled
+        );
+        assert_eq!(
+            "disabled".parse::<EphemeralEventsConfig>().unwrap(),
+            EphemeralEventsConfig::Disabled
+        );
+        let json = serde_json::to_string(&EphemeralEventsConfig::Disabled).unwrap();
+        assert_eq!(json, r#""disabled""#);
+        assert_eq!(
+            json.parse::<EphemeralEventsConfig>().unwrap(),
+            EphemeralEventsConfig::Disabled
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_pruning_is_not_scheduled() {
+        let store = BanyanStore::new(
+            SwarmConfig {
+                ephemeral_event_config: EphemeralEventsConfig::Disabled,
+                ..SwarmConfig::test("disabled")
+            },
+            ActoRef::blackhole(),
+        )
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(100)).await;
+        let state = store.lock();
+        assert!(state.tasks.iter().all(|(name, _)| name != "prune_events"));
+        assert!(state.tasks.iter().all(|(_, handle)| !handle.is_finished()));
+    }
+
+    #[tokio::test]
+    async fn purged_streams_are_not_pruned() {
+        let test_stream = StreamNr::from(1);
+        let store = BanyanStore::new(
+            SwarmConfig {
+                ephemeral_event_config: EphemeralEventsConfig::Enabled {
+                    interval: Duration::from_millis(10),
+                    streams: BTreeMap::from([("test_stream".to_owned(), RetainConfig::events(1))]),
+                },
+                ..store_config()
+            },
+            ActoRef::blackhole(),
+        )
+        .await
+        .unwrap();
+        store
+            .append(app_id(), vec![(tags!("test"), Payload::null()); 3])
+            .await
+            .unwrap();
+        let stream_id = store.node_id().stream(test_stream);
+        let tombstone = store.tombstone_stream(stream_id, "gone".into()).await.unwrap();
+        assert_eq!(store.purge_due_tombstones(tombstone.purge_at).unwrap(), 1);
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(!store.has_stream(stream_id));
+        let state = store.lock();
+        assert!(state.tasks.iter().any(|(name, _)| name == "prune_events"));
+        assert!(state.tasks.iter().all(|(_, handle)| !handle.is_finished()));
+    }
+
+    #[tokio::test]
+    async fn prune_releases_payload_blobs() {
+        let test_stream = StreamNr::from(1);
+        crate::util::setup_logger();
+        let cfg = SwarmConfig {
+            payload_blob_threshold: Some(100),
+            ..store_config()
+        };
+        let store = BanyanStore::new(cfg, ActoRef::blackhole()).await.unwrap();
+        let large = |n: u64| Payload::compact(&vec![n; 1024]).unwrap();
+        let cid = |payload: &Payload| {
+            store
+                .ipfs()
+                .create_temp_pin()
+                .and_then(|mut tmp| store.add(&mut tmp, payload.as_slice()))
+                .unwrap()
+                .0
+        };
+        let (first, second) = (large(1), large(2));
+        let (first_cid, second_cid) = (cid(&first), cid(&second));
+        // the same blob referenced twice must stay pinned until both events are gone
+        let events = vec![first.clone(), first, second]
+            .into_iter()
+            .map(|payload| (tags!("test"), payload))
+            .collect();
+        store.append(app_id(), events).await.unwrap();
+        assert!(store.is_payload_blob_pinned(&first_cid).unwrap());
+        assert!(store.is_payload_blob_pinned(&second_cid).unwrap());
+
+        let stream = store.get_or_create_own_stream(test_stream).unwrap();
+        super::prune_stream(&store, stream.lock().await, &RetainConfig::events(1), Timestamp::now()).unwrap();
+        assert!(!store.is_payload_blob_pinned(&first_cid).unwrap());
+        assert!(store.is_payload_blob_pinned(&second_cid).unwrap());
+
+        super::prune_stream(&store, stream.lock().await, &RetainConfig::events(0), Timestamp::now()).unwrap();
+        assert!(!store.is_payload_blob_pinned(&second_cid).unwrap());
+    }
+
+    #[tokio::test]
+    async fn prune_releases_file_names() {
+        crate::util::setup_logger();
+        let cfg = SwarmConfig {
+            block_gc_interval: Duration::from_millis(100),
+            ..store_config()
+        };
+        let store = BanyanStore::new(cfg, ActoRef::blackhole()).await.unwrap();
+        let in_block_store = |cid: Cid| store.ipfs().iter().unwrap().any(|c| c == cid);
+        let mut tmp = store.ipfs().create_temp_pin().unwrap();
+        let (root, _) = store.add(&mut tmp, &vec![7u8; 10_000][..]).unwrap();
+        store.files_publish("index", root, app_id()).await.unwrap();
+        drop(tmp);
+        sleep(Duration::from_millis(500)).await;
+        assert!(in_block_store(root));
+
+        let stream = store.get_or_create_own_stream(FILES_STREAM_NUMBER.into()).unwrap();
+        let now = Timestamp::now() + Duration::from_secs(60 * 60 * 24 * 15);
+        let retain = RetainConfig::age_from_seconds(60 * 60 * 24 * 14);
+        super::prune_stream(&store, stream.lock().await, &retain, now).unwrap();
+        assert_eq!(store.files_resolve("index").await.unwrap(), None);
+        timeout(Duration::from_secs(10), async {
+            while in_block_store(root) {
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap();
     }
 
     /// Publishes `event_count` events, and waits some time between each chunk.
@@ -750,7 +1021,7 @@ mod test {
                     BTreeMap::from([("test_stream".to_string(), RetainConfig::events(1))]),
                 )
             } else {
-                EphemeralEventsConfig::disable()
+                EphemeralEventsConfig::Disabled
             },
             ..SwarmConfig::test(store_name)
         };
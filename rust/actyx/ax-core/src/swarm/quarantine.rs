@@ -0,0 +1,263 @@
+//! Peers that keep sending us garbage — malformed gossip or requests violating our protocols —
+//! are put into quarantine for a while. Their gossip is then dropped before decoding and their
+//! requests are rejected, so that a single broken or incompatible peer cannot keep us busy.
+//!
+//! Bitswap is handled inside ipfs-embed, but blocks are only fetched for roots announced by a
+//! peer; if they cannot be decoded, the announcing peer is charged with a violation.
+//!
+//! Quarantine is strictly per peer and lifted automatically once its duration has elapsed.
+use crate::swarm::{internal_app_id, BanyanStore};
+use ax_types::{tags, Payload};
+use futures::{channel::mpsc, Future, StreamExt};
+use ipfs_embed::PeerId;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuarantineConfig {
+    /// Number of violations within `window` after which a peer is quarantined; zero disables quarantine
+    pub threshold: u32,
+    pub window: Duration,
+    /// How long a peer stays in quarantine
+    pub duration: Duration,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 20,
+            window: Duration::from_secs(60),
+            duration: Duration::from_secs(600),
+        }
+    }
+}
+
+/// A peer currently in quarantine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedPeer {
+    pub peer_id: PeerId,
+    /// The violation that tipped the scale
+    pub reason: String,
+    /// Time until the quarantine is lifted
+    pub remaining: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: QuarantineConfig,
+    /// recent violations per peer, oldest first
+    violations: BTreeMap<PeerId, VecDeque<Instant>>,
+    /// quarantined peers with the end of their quarantine and the reason
+    quarantined: BTreeMap<PeerId, (Instant, String)>,
+    listeners: Vec<mpsc::UnboundedSender<QuarantinedPeer>>,
+}
+
+impl Inner {
+    fn check(&mut self, peer_id: &PeerId, now: Instant) -> bool {
+        match self.quarantined.get(peer_id) {
+            Some((until, _)) if *until > now => true,
+            Some(_) => {
+                tracing::info!(peer = %peer_id, "quarantine expired");
+                self.quarantined.remove(peer_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Forget counters without violations within the window and quarantines that have expired
+    fn evict(&mut self, now: Instant) {
+        let window = self.config.window;
+        self.violations
+            .retain(|_, violations| matches!(violations.back(), Some(t) if now.duration_since(*t) < window));
+        self.quarantined.retain(|_, (until, _)| *until > now);
+    }
+}
+
+/// Shared handle on the quarantine list, see the [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct PeerQuarantine(Arc<Mutex<Inner>>);
+
+impl PeerQuarantine {
+    pub fn new(config: QuarantineConfig) -> Self {
+        let this = Self::default();
+        this.set_config(config);
+        this
+    }
+
+    /// Change the thresholds; already quarantined peers keep their current expiry.
+    pub fn set_config(&self, config: QuarantineConfig) {
+        self.0.lock().config = config;
+    }
+
+    pub fn config(&self) -> QuarantineConfig {
+        self.0.lock().config
+    }
+
+    pub fn is_quarantined(&self, peer_id: &PeerId) -> bool {
+        self.0.lock().check(peer_id, Instant::now())
+    }
+
+    /// Count a violation by the given peer, returns true if the peer is (now) quarantined.
+    pub fn record_violation(&self, peer_id: PeerId, reason: impl Display) -> bool {
+        let now = Instant::now();
+        let mut inner = self.0.lock();
+        if inner.check(&peer_id, now) {
+            return true;
+        }
+        let QuarantineConfig {
+            threshold,
+            window,
+            duration,
+        } = inner.config;
+        inner.evict(now);
+        if threshold == 0 {
+            return false;
+        }
+        let violations = inner.violations.entry(peer_id).or_default();
+        while violations.front().map(|t| now.duration_since(*t) >= window) == Some(true) {
+            violations.pop_front();
+        }
+        violations.push_back(now);
+        if violations.len() < threshold as usize {
+            return false;
+        }
+
+        inner.violations.remove(&peer_id);
+        let reason = reason.to_string();
+        tracing::warn!(peer = %peer_id, %reason, "quarantining peer for {:?}", duration);
+        inner.quarantined.insert(peer_id, (now + duration, reason.clone()));
+        let entry = QuarantinedPeer {
+            peer_id,
+            reason,
+            remaining: duration,
+        };
+        inner
+            .listeners
+            .retain(|listener| listener.unbounded_send(entry.clone()).is_ok());
+        true
+    }
+
+    /// All peers currently in quarantine
+    pub fn quarantined(&self) -> Vec<QuarantinedPeer> {
+        let now = Instant::now();
+        let mut inner = self.0.lock();
+        inner.evict(now);
+        inner
+            .quarantined
+            .iter()
+            .map(|(peer_id, (until, reason))| QuarantinedPeer {
+                peer_id: *peer_id,
+                reason: reason.clone(),
+                remaining: until.duration_since(now),
+            })
+            .collect()
+    }
+
+    /// Get notified whenever a peer is put into quarantine
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<QuarantinedPeer> {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.lock().listeners.push(tx);
+        rx
+    }
+}
+
+/// Payload of the internal event recording that a peer has been quarantined
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantineEvent {
+    pub peer_id: String,
+    pub reason: String,
+    pub duration_secs: u64,
+}
+
+/// Record each newly quarantined peer as an event on the internal stream.
+pub(crate) fn quarantine_events(store: BanyanStore) -> impl Future<Output = ()> {
+    let mut quarantined = store.quarantine().subscribe();
+    let tags = tags!("quarantine");
+    async move {
+        while let Some(entry) = quarantined.next().await {
+            let event = QuarantineEvent {
+                peer_id: entry.peer_id.to_string(),
+                reason: entry.reason,
+                duration_secs: entry.remaining.as_secs(),
+            };
+            let payload = Payload::compact(&event).expect("QuarantineEvent is always serializable");
+            if let Err(err) = store.append(internal_app_id(), vec![(tags.clone(), payload)]).await {
+                tracing::warn!("error appending quarantine event: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> QuarantineConfig {
+        QuarantineConfig {
+            threshold: 3,
+            window: Duration::from_secs(10),
+            duration: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn quarantine_after_threshold() {
+        tokio::time::pause();
+        let quarantine = PeerQuarantine::new(config());
+        let mut events = quarantine.subscribe();
+        let (bad, good) = (PeerId::random(), PeerId::random());
+
+        assert!(!quarantine.record_violation(bad, "garbage"));
+        assert!(!quarantine.record_violation(good, "garbage"));
+        assert!(!quarantine.record_violation(bad, "garbage"));
+        assert!(quarantine.record_violation(bad, "more garbage"));
+
+        assert!(quarantine.is_quarantined(&bad));
+        assert!(!quarantine.is_quarantined(&good));
+        let entry = events.next().await.unwrap();
+        assert_eq!(entry.peer_id, bad);
+        assert_eq!(entry.reason, "more garbage");
+        assert_eq!(quarantine.quarantined(), vec![entry]);
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(!quarantine.is_quarantined(&bad));
+        assert!(quarantine.quarantined().is_empty());
+        // counting starts from scratch
+        assert!(!quarantine.record_violation(bad, "garbage"));
+    }
+
+    #[tokio::test]
+    async fn violations_outside_window_are_forgotten() {
+        tokio::time::pause();
+        let quarantine = PeerQuarantine::new(config());
+        let peer = PeerId::random();
+        for _ in 0..10 {
+            assert!(!quarantine.record_violation(peer, "garbage"));
+            tokio::time::advance(Duration::from_secs(6)).await;
+        }
+        assert!(!quarantine.is_quarantined(&peer));
+
+        // counters of peers that stopped misbehaving are dropped with the next violation
+        let other = PeerId::random();
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(!quarantine.record_violation(other, "garbage"));
+        assert_eq!(quarantine.0.lock().violations.keys().collect::<Vec<_>>(), vec![&other]);
+
+        quarantine.set_config(QuarantineConfig {
+            threshold: 0,
+            ..config()
+        });
+        for _ in 0..10 {
+            assert!(!quarantine.record_violation(peer, "garbage"));
+        }
+    }
+}
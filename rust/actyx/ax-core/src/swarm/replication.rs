@@ -0,0 +1,294 @@
+//! Partial replication: which remote streams a node replicates, and to which extent.
+//!
+//! Every remote stream we learn about is matched against the [`ReplicationRule`]s of the
+//! [`ReplicationConfig`], the first matching rule determines its [`ReplicationMode`]. Rules
+//! selecting streams by their ID are evaluated before a sync is started, rules selecting streams
+//! by tags need the root of the stream’s tree, so for those only the tree header and root node are
+//! downloaded before the decision is taken.
+//!
+//! Independent of the mode, the offsets announced for a stream by our peers are tracked in the
+//! replication target, so that it stays visible how much of a stream we are missing.
+use crate::trees::{
+    axtrees::TagsSummary,
+    query::{TagExprError, TagExprQuery},
+    AxTree,
+};
+use anyhow::{anyhow, Context};
+use ax_aql::TagExpr;
+use ax_types::{NodeId, StreamId, StreamNr};
+use banyan::index::{Index, Summarizable};
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplicationMode {
+    /// Replicate all events of the stream
+    #[default]
+    Full,
+    /// Only download the tree header and root to keep track of the stream’s progress
+    HeadersOnly,
+    /// Do not download anything for the stream
+    Ignore,
+}
+
+/// Selects streams by node ID and stream number.
+///
+/// Written as `<node ID>-<stream nr>` where either part may be replaced by `*`, e.g. `*-1`
+/// selects stream number one of every node. A single `*` selects all streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamPattern {
+    pub node_id: Option<NodeId>,
+    pub stream_nr: Option<StreamNr>,
+}
+
+impl StreamPattern {
+    pub fn matches(&self, stream_id: StreamId) -> bool {
+        self.node_id.map(|n| n == stream_id.node_id()).unwrap_or(true)
+            && self.stream_nr.map(|n| n == stream_id.stream_nr()).unwrap_or(true)
+    }
+}
+
+impl FromStr for StreamPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            return Ok(Self {
+                node_id: None,
+                stream_nr: None,
+            });
+        }
+        let (node_id, stream_nr) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("expected `<node ID>-<stream nr>` in stream pattern `{}`", s))?;
+        let node_id = match node_id {
+            "*" => None,
+            n => Some(n.parse().context("parsing stream pattern node ID")?),
+        };
+        let stream_nr = match stream_nr {
+            "*" => None,
+            n => Some(n.parse::<u64>().context("parsing stream pattern stream nr")?.into()),
+        };
+        Ok(Self { node_id, stream_nr })
+    }
+}
+
+impl Display for StreamPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.node_id, self.stream_nr) {
+            (None, None) => write!(f, "*"),
+            (Some(node_id), None) => write!(f, "{}-*", node_id),
+            (None, Some(stream_nr)) => write!(f, "*-{}", stream_nr),
+            (Some(node_id), Some(stream_nr)) => write!(f, "{}-{}", node_id, stream_nr),
+        }
+    }
+}
+
+/// Selects streams by the tags of the events they contain.
+///
+/// A stream is selected as soon as its events may match the tag expression, i.e. when the union
+/// of the tags in the stream covers one of the alternatives of the expression. Time and lamport
+/// restrictions in the expression are not considered.
+#[derive(Clone)]
+pub struct TagSelector {
+    expr: TagExpr,
+    query: Arc<dyn Fn(StreamId) -> TagExprQuery + Send + Sync>,
+}
+
+impl TagSelector {
+    pub fn new(expr: TagExpr) -> Result<Self, TagExprError> {
+        let query = TagExprQuery::from_expr(&expr)?;
+        // remote streams can never match `isLocal`
+        let query = Arc::new(move |stream_id| query(false, stream_id));
+        Ok(Self { expr, query })
+    }
+
+    pub fn expr(&self) -> &TagExpr {
+        &self.expr
+    }
+
+    pub fn matches(&self, stream_id: StreamId, tags: &TagsSummary) -> bool {
+        match tags {
+            TagsSummary::Unrestricted => true,
+            TagsSummary::Complete(tags) => (self.query)(stream_id)
+                .terms()
+                .any(|term| term.into_iter().all(|tag| tags.as_ref().contains(tag))),
+        }
+    }
+}
+
+impl std::fmt::Debug for TagSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TagSelector").field(&self.expr).finish()
+    }
+}
+
+impl PartialEq for TagSelector {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamSelector {
+    Streams(StreamPattern),
+    Tags(TagSelector),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicationRule {
+    pub select: StreamSelector,
+    pub mode: ReplicationMode,
+}
+
+impl ReplicationRule {
+    pub fn streams(pattern: StreamPattern, mode: ReplicationMode) -> Self {
+        Self {
+            select: StreamSelector::Streams(pattern),
+            mode,
+        }
+    }
+
+    pub fn tags(expr: TagExpr, mode: ReplicationMode) -> Result<Self, TagExprError> {
+        Ok(Self {
+            select: StreamSelector::Tags(TagSelector::new(expr)?),
+            mode,
+        })
+    }
+}
+
+/// Which remote streams to replicate, see the [module docs](self).
+///
+/// The default replicates all streams.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReplicationConfig {
+    /// Rules in order of precedence
+    pub rules: Vec<ReplicationRule>,
+    /// Mode for streams not matched by any rule
+    pub default: ReplicationMode,
+}
+
+impl ReplicationConfig {
+    /// Only replicate streams carrying tags matching the given expression, track the offsets of
+    /// all others.
+    pub fn only_tags(expr: TagExpr) -> Result<Self, TagExprError> {
+        Ok(Self {
+            rules: vec![ReplicationRule::tags(expr, ReplicationMode::Full)?],
+            default: ReplicationMode::Ignore,
+        })
+    }
+
+    /// The replication mode for the given stream.
+    ///
+    /// Without `tags` this returns `None` if the decision depends on a tag rule.
+    pub fn mode(&self, stream_id: StreamId, tags: Option<&TagsSummary>) -> Option<ReplicationMode> {
+        for rule in &self.rules {
+            let matches = match &rule.select {
+                StreamSelector::Streams(pattern) => pattern.matches(stream_id),
+                StreamSelector::Tags(selector) => selector.matches(stream_id, tags?),
+            };
+            if matches {
+                return Some(rule.mode);
+            }
+        }
+        Some(self.default)
+    }
+
+    /// The replication mode for the given stream, using the tags from the root of its tree.
+    pub fn mode_for_tree(&self, stream_id: StreamId, tree: &AxTree) -> ReplicationMode {
+        self.mode(stream_id, Some(&tags_summary(tree)))
+            .expect("tags are given, so all rules can be decided")
+    }
+}
+
+/// The union of all tags in the tree, taken from its root node.
+fn tags_summary(tree: &AxTree) -> TagsSummary {
+    match tree.as_index_ref() {
+        Some(Index::Leaf(leaf)) => leaf.keys.summarize().tags,
+        Some(Index::Branch(branch)) => branch.summaries.summarize().tags,
+        None => TagsSummary::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stags, trees::tags::ScopedTagSet};
+
+    fn stream(node: u8, nr: u64) -> StreamId {
+        NodeId::new([node; 32]).stream(nr.into())
+    }
+
+    fn tags(expr: &str) -> ReplicationRule {
+        ReplicationRule::tags(expr.parse().unwrap(), ReplicationMode::Full).unwrap()
+    }
+
+    #[test]
+    fn stream_pattern() {
+        let node = NodeId::new([1; 32]);
+        let all = "*".parse::<StreamPattern>().unwrap();
+        let of_node = format!("{}-*", node).parse::<StreamPattern>().unwrap();
+        let nr = "*-2".parse::<StreamPattern>().unwrap();
+        let exact = format!("{}-2", node).parse::<StreamPattern>().unwrap();
+
+        assert!(all.matches(stream(1, 1)));
+        assert!(of_node.matches(stream(1, 1)));
+        assert!(!of_node.matches(stream(2, 1)));
+        assert!(nr.matches(stream(2, 2)));
+        assert!(!nr.matches(stream(2, 1)));
+        assert!(exact.matches(stream(1, 2)));
+        assert!(!exact.matches(stream(1, 1)));
+        assert!(!exact.matches(stream(2, 2)));
+
+        for pattern in [all, of_node, nr, exact] {
+            assert_eq!(pattern.to_string().parse::<StreamPattern>().unwrap(), pattern);
+        }
+        assert!("".parse::<StreamPattern>().is_err());
+        assert!("abc-1".parse::<StreamPattern>().is_err());
+        assert!("*-x".parse::<StreamPattern>().is_err());
+    }
+
+    #[test]
+    fn mode_by_tags() {
+        let config = ReplicationConfig::only_tags("'a' & 'b' | 'c'".parse().unwrap()).unwrap();
+        let summary = |tags: ScopedTagSet| TagsSummary::Complete(tags);
+        let mode = |tags: ScopedTagSet| config.mode(stream(1, 1), Some(&summary(tags)));
+
+        assert_eq!(config.mode(stream(1, 1), None), None);
+        assert_eq!(mode(stags!("a", "b", "x")), Some(ReplicationMode::Full));
+        assert_eq!(mode(stags!("c")), Some(ReplicationMode::Full));
+        assert_eq!(mode(stags!("a")), Some(ReplicationMode::Ignore));
+        assert_eq!(mode(ScopedTagSet::empty()), Some(ReplicationMode::Ignore));
+        assert_eq!(
+            config.mode(stream(1, 1), Some(&TagsSummary::Unrestricted)),
+            Some(ReplicationMode::Full)
+        );
+    }
+
+    #[test]
+    fn first_rule_wins() {
+        let node = NodeId::new([1; 32]);
+        let config = ReplicationConfig {
+            rules: vec![
+                ReplicationRule::streams(format!("{}-*", node).parse().unwrap(), ReplicationMode::HeadersOnly),
+                tags("'a'"),
+                ReplicationRule::streams("*-1".parse().unwrap(), ReplicationMode::Ignore),
+            ],
+            default: ReplicationMode::Full,
+        };
+        let a = TagsSummary::Complete(stags!("a"));
+        let b = TagsSummary::Complete(stags!("b"));
+
+        // decided by stream ID alone
+        assert_eq!(config.mode(stream(1, 1), None), Some(ReplicationMode::HeadersOnly));
+        // tag rule comes first and needs the tags
+        assert_eq!(config.mode(stream(2, 1), None), None);
+        assert_eq!(config.mode(stream(2, 1), Some(&a)), Some(ReplicationMode::Full));
+        assert_eq!(config.mode(stream(2, 1), Some(&b)), Some(ReplicationMode::Ignore));
+        assert_eq!(config.mode(stream(2, 2), Some(&b)), Some(ReplicationMode::Full));
+
+        assert_eq!(
+            ReplicationConfig::default().mode(stream(2, 1), None),
+            Some(ReplicationMode::Full)
+        );
+    }
+}
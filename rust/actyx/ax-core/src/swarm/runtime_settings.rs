@@ -0,0 +1,100 @@
+//! Swarm settings that can be changed while the store is running.
+//!
+//! The periodic tasks read their settings from the store whenever they wake up, so a change made
+//! with [`BanyanStore::update_settings`] takes effect after their current sleep at the latest; the
+//! compaction loop wakes up right away.
+//!
+//! [`BanyanStore::update_settings`]: crate::swarm::BanyanStore::update_settings
+use crate::swarm::{CompactionConfig, EphemeralEventsConfig, RootMapCadence, SwarmConfig};
+use anyhow::{ensure, Result};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeSwarmSettings {
+    pub compaction: CompactionConfig,
+    /// Only used if metrics are enabled in the [`SwarmConfig`]
+    pub metrics_interval: Duration,
+    /// Only used if the root map is enabled in the [`SwarmConfig`]
+    pub cadence_root_map: RootMapCadence,
+    pub ephemeral_events: EphemeralEventsConfig,
+}
+
+impl RuntimeSwarmSettings {
+    /// Intervals of zero would make the tasks spin, they are rejected.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.compaction.interval.is_zero(),
+            "compaction interval must not be zero"
+        );
+        for (stream_nr, stream) in &self.compaction.streams {
+            ensure!(
+                !matches!(stream.interval, Some(interval) if interval.is_zero()),
+                "compaction interval of stream {} must not be zero",
+                stream_nr
+            );
+        }
+        ensure!(!self.metrics_interval.is_zero(), "metrics interval must not be zero");
+        let root_map = match self.cadence_root_map {
+            RootMapCadence::Fixed(interval) => interval,
+            RootMapCadence::Adaptive { min, .. } => min,
+        };
+        ensure!(!root_map.is_zero(), "root map interval must not be zero");
+        if let EphemeralEventsConfig::Enabled { interval, .. } = &self.ephemeral_events {
+            ensure!(!interval.is_zero(), "ephemeral events interval must not be zero");
+        }
+        Ok(())
+    }
+}
+
+impl From<&SwarmConfig> for RuntimeSwarmSettings {
+    fn from(cfg: &SwarmConfig) -> Self {
+        Self {
+            compaction: cfg.compaction.clone(),
+            metrics_interval: cfg.metrics_interval,
+            cadence_root_map: cfg.cadence_root_map,
+            ephemeral_events: cfg.ephemeral_event_config.clone(),
+        }
+    }
+}
+
+impl SwarmConfig {
+    /// Replace the settings that [`BanyanStore::update_settings`] can change on a running store
+    ///
+    /// [`BanyanStore::update_settings`]: crate::swarm::BanyanStore::update_settings
+    pub fn set_runtime_settings(&mut self, settings: RuntimeSwarmSettings) {
+        self.compaction = settings.compaction;
+        self.metrics_interval = settings.metrics_interval;
+        self.cadence_root_map = settings.cadence_root_map;
+        self.ephemeral_event_config = settings.ephemeral_events;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_intervals_are_rejected() {
+        let settings = RuntimeSwarmSettings::from(&SwarmConfig::basic());
+        settings.validate().unwrap();
+
+        let mut compaction = settings.clone();
+        compaction.compaction.interval = Duration::ZERO;
+        assert!(compaction.validate().is_err());
+
+        let mut metrics = settings.clone();
+        metrics.metrics_interval = Duration::ZERO;
+        assert!(metrics.validate().is_err());
+
+        let mut root_map = settings.clone();
+        root_map.cadence_root_map = RootMapCadence::Fixed(Duration::ZERO);
+        assert!(root_map.validate().is_err());
+
+        let mut ephemeral = settings;
+        ephemeral.ephemeral_events = EphemeralEventsConfig::Enabled {
+            interval: Duration::ZERO,
+            streams: Default::default(),
+        };
+        assert!(ephemeral.validate().is_err());
+    }
+}
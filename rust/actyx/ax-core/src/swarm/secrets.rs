@@ -0,0 +1,22 @@
+//! Banyan secrets per stream.
+//!
+//! Trees are read and written with the secrets the [`SecretProvider`] returns for their stream.
+//! A node without the secrets of a remote stream still replicates its blocks, using the default
+//! secrets to read the index, but refuses to read its events. This requires such streams to only
+//! use a custom value key, the index key has to stay the default one.
+use ax_types::StreamId;
+use banyan::Secrets;
+use std::fmt::Debug;
+
+/// Looks up the banyan secrets of a stream.
+pub trait SecretProvider: Debug + Send + Sync + 'static {
+    /// Secrets of the given stream, `None` if they are not available on this node.
+    fn secrets(&self, stream_id: StreamId) -> Option<Secrets>;
+}
+
+/// The same secrets for all streams
+impl SecretProvider for Secrets {
+    fn secrets(&self, _stream_id: StreamId) -> Option<Secrets> {
+        Some(self.clone())
+    }
+}
@@ -0,0 +1,39 @@
+//! Immutable view of the store for queries.
+//!
+//! A [`StoreSnapshot`] holds the published trees of all known streams at the time it was taken,
+//! so events can be read from it without ever touching the store’s state again.
+use crate::{swarm::streams::PublishedTree, trees::AxTree};
+use ax_types::{OffsetMap, StreamId};
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Debug, Clone)]
+pub struct StoreSnapshot {
+    trees: Arc<BTreeMap<StreamId, PublishedTree>>,
+    offsets: OffsetMap,
+}
+
+impl StoreSnapshot {
+    pub(crate) fn new(trees: BTreeMap<StreamId, PublishedTree>) -> Self {
+        let mut offsets = OffsetMap::empty();
+        for (stream_id, tree) in &trees {
+            offsets.update(*stream_id, tree.offset());
+        }
+        Self {
+            trees: Arc::new(trees),
+            offsets,
+        }
+    }
+
+    /// Offsets of the captured trees, the upper bound of what can be read from this snapshot.
+    pub fn offsets(&self) -> &OffsetMap {
+        &self.offsets
+    }
+
+    pub fn stream_ids(&self) -> impl Iterator<Item = StreamId> + '_ {
+        self.trees.keys().copied()
+    }
+
+    pub fn tree(&self, stream_id: StreamId) -> Option<&AxTree> {
+        self.trees.get(&stream_id).map(|tree| tree.tree())
+    }
+}
@@ -1,22 +1,97 @@
-use crate::ax_futures_util::stream::variable::{Observer, Variable};
+use crate::{
+    ax_futures_util::stream::variable::{Observer, Variable},
+    swarm::{
+        lamport::{LamportError, MAX_LAMPORT},
+        tombstone::Tombstone,
+        AppendMeta,
+    },
+};
 use anyhow::{Context, Result};
-use ax_types::{LamportTimestamp, StreamId};
+use ax_types::{LamportTimestamp, Offset, StreamId, StreamNr, Timestamp};
+use ipfs_embed::{Multiaddr, PeerId};
+use libipld::Cid;
 use parking_lot::Mutex;
-use rusqlite::{backup, params, Connection, OpenFlags};
-use std::{collections::BTreeSet, convert::TryFrom, path::PathBuf, sync::Arc, time::Duration};
+use rusqlite::{backup, params, Connection, OpenFlags, OptionalExtension};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 use tracing::*;
 
+/// Number of peer addresses kept, the ones seen least recently are dropped first
+const MAX_PEER_ADDRESSES: i64 = 1000;
+
+/// Upper bound for the size of the write-ahead log after a checkpoint
+const JOURNAL_SIZE_LIMIT: i64 = 64 * 1024 * 1024;
+
+/// A change of the known addresses of a peer, see [`SqliteIndexStore::update_peer_addresses`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddressUpdate {
+    /// The address was seen at the given time
+    Seen(PeerId, Multiaddr, Timestamp),
+    /// The address is no longer valid
+    Expired(PeerId, Multiaddr),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DbPath {
     File(PathBuf),
     Memory,
 }
 
+/// Value of `PRAGMA synchronous`, see <https://www.sqlite.org/pragma.html#pragma_synchronous>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    /// Leave syncing to the operating system, an OS crash or power loss may corrupt the database
+    Off,
+    /// Sync at checkpoints only, an OS crash or power loss may roll back the latest transactions
+    Normal,
+    /// Sync on every commit
+    Full,
+    /// Like `Full`, also syncing the directory after unlinking the journal
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexStoreConfig {
+    /// Number of lamports reserved in the database at once, local appends are served from memory
+    /// until the reservation is used up
+    pub lamport_chunk: u64,
+    /// Durability of the index store writes; lamports handed out after the last synced
+    /// reservation may be handed out again after an OS crash or power loss unless this is `Full`
+    pub synchronous: Synchronous,
+}
+
+impl Default for IndexStoreConfig {
+    fn default() -> Self {
+        Self {
+            lamport_chunk: 1000,
+            synchronous: Synchronous::Normal,
+        }
+    }
+}
+
 pub struct SqliteIndexStore {
     conn: Arc<Mutex<Connection>>,
     /// local copy of the lamport timestamp for quick access
-    /// This must be ensured to be always in sync with the db value
+    /// This is never greater than `reserved`
     lamport: Variable<LamportTimestamp>,
+    /// lamport stored in the db, all lamports below it may have been handed out
+    reserved: u64,
+    lamport_chunk: u64,
 }
 
 /// Implementation of IpfsIndexStore for sqlite. Please note that for this implementation
@@ -45,7 +120,7 @@ impl SqliteIndexStore {
     }
 
     // #[instrument(level = "debug")]
-    pub fn open(path: DbPath) -> Result<Self> {
+    pub fn open(path: DbPath, config: IndexStoreConfig) -> Result<Self> {
         debug!("Creating database {:?}", path);
         // These are the same flags that the npm SQLite package has
         let flags =
@@ -54,16 +129,16 @@ impl SqliteIndexStore {
             DbPath::File(path) => Connection::open_with_flags(format!("{}.sqlite", path.display()), flags),
             DbPath::Memory => Connection::open(":memory:"),
         }?;
-        Self::from_conn(Arc::new(Mutex::new(conn)))
+        Self::from_conn(Arc::new(Mutex::new(conn)), config)
     }
 
     /**
      * Initialize the store from a connection. This is used from `open` as well
      * as for testing.
      **/
-    pub fn from_conn(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+    pub fn from_conn(conn: Arc<Mutex<Connection>>, config: IndexStoreConfig) -> Result<Self> {
         let locked = conn.lock();
-        initialize_db(&locked).context("initializing DB")?;
+        initialize_db(&locked, config.synchronous).context("initializing DB")?;
         let lamport = locked
             .query_row("SELECT lamport FROM meta", [], |row| {
                 let lamport: i64 = row.get(0)?;
@@ -77,34 +152,66 @@ impl SqliteIndexStore {
             })
             .context("initializing lamport clock")?;
         drop(locked);
+        // the rest of a reservation made before a restart is skipped, since it is not known which
+        // part of it has been used
         Ok(Self {
             conn,
             lamport: Variable::new(lamport.into()),
+            reserved: lamport,
+            lamport_chunk: config.lamport_chunk.max(1),
         })
     }
 
+    /// Persist `reserved` as the new high-water mark, before any lamport below it is handed out
+    fn reserve(&mut self, reserved: u64) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("UPDATE meta SET lamport = ?")?
+            .execute(params![reserved as i64])?;
+        trace!("reserved lamports up to {}", reserved);
+        self.reserved = reserved;
+        Ok(())
+    }
+
     /// we received a lamport from an external source
+    ///
+    /// This only writes to the db if the local clock moves beyond the current reservation, in which
+    /// case a whole chunk beyond the received lamport is reserved.
     pub fn received_lamport(&mut self, lamport: LamportTimestamp) -> Result<()> {
-        let conn = self.conn.lock();
-        let res: i64 = conn
-            .prepare_cached("UPDATE meta SET lamport = MAX(lamport + 1, ?) RETURNING lamport")?
-            .query_row(params![u64::from(lamport) as i64], |x| x.get(0))?;
-        self.lamport.set(u64::try_from(res).expect("negative lamport").into());
-        drop(conn);
+        let current = u64::from(self.lamport.get());
+        let lamport = current.saturating_add(1).max(lamport.into());
+        if lamport > self.reserved {
+            self.reserve(lamport.saturating_add(self.lamport_chunk).min(MAX_LAMPORT).max(lamport))?;
+        }
+        self.lamport.set(lamport.into());
         Ok(())
     }
 
     /// Increase the lamport by `increment` and return the *initial* value
+    ///
+    /// Fails with [`LamportError::Exhausted`] instead of going beyond [`MAX_LAMPORT`].
     pub fn increase_lamport(&mut self, increment: u64) -> Result<LamportTimestamp> {
-        let conn = self.conn.lock();
-        let res: i64 = conn
-            .prepare_cached("UPDATE meta SET lamport = lamport + ? RETURNING lamport")?
-            .query_row(params![&(increment as i64)], |x| x.get(0))?;
-        let initial = self.lamport.get();
-        self.lamport.set(u64::try_from(res).expect("negative lamport").into());
-        drop(conn);
+        let current = self.lamport.get();
+        if u64::from(current)
+            .checked_add(increment)
+            .map_or(true, |lamport| lamport > MAX_LAMPORT)
+        {
+            return Err(LamportError::Exhausted {
+                current,
+                count: increment,
+            }
+            .into());
+        }
+        let next = u64::from(current) + increment;
+        if next > self.reserved {
+            let reserved = u64::from(current)
+                .saturating_add(self.lamport_chunk)
+                .clamp(next, MAX_LAMPORT);
+            self.reserve(reserved)?;
+        }
+        self.lamport.set(next.into());
         trace!("increased lamport by {}", increment);
-        Ok(initial)
+        Ok(current)
     }
 
     pub fn add_stream(&mut self, stream: StreamId) -> Result<()> {
@@ -142,6 +249,222 @@ impl SqliteIndexStore {
         Ok(set)
     }
 
+    /// Record one more event referencing the given payload blob, returning the new count
+    pub fn add_payload_blob_ref(&mut self, cid: &Cid) -> Result<u64> {
+        let refs: i64 = self
+            .conn
+            .lock()
+            .prepare_cached(
+                "INSERT INTO payload_blobs VALUES (?, 1) \
+                ON CONFLICT(cid) DO UPDATE SET refs = refs + 1 RETURNING refs",
+            )?
+            .query_row(params![cid.to_string()], |x| x.get(0))?;
+        Ok(refs as u64)
+    }
+
+    /// Release one reference to the given payload blob, returning the remaining count
+    ///
+    /// Returns `None` if the blob is not known, i.e. it was not externalized by this node.
+    pub fn remove_payload_blob_ref(&mut self, cid: &Cid) -> Result<Option<u64>> {
+        let conn = self.conn.lock();
+        let refs: Option<i64> = conn
+            .prepare_cached("UPDATE payload_blobs SET refs = refs - 1 WHERE cid = ? RETURNING refs")?
+            .query_row(params![cid.to_string()], |x| x.get(0))
+            .optional()?;
+        if refs == Some(0) {
+            conn.prepare_cached("DELETE FROM payload_blobs WHERE cid = ?")?
+                .execute(params![cid.to_string()])?;
+        }
+        Ok(refs.map(|refs| refs as u64))
+    }
+
+    /// Result of the append that used the given idempotency token, unless it expired before `now`
+    pub fn get_append_token(&mut self, token: &[u8; 32], now: Timestamp) -> Result<Option<AppendMeta>> {
+        let row = self
+            .conn
+            .lock()
+            .prepare_cached(
+                "SELECT min_lamport, min_offset, timestamp, root, last_offset FROM append_tokens \
+                WHERE token = ? AND expires > ?",
+            )?
+            .query_row(params![&token[..], u64::from(now) as i64], |r| {
+                Ok((
+                    r.get::<_, i64>(0)?,
+                    r.get::<_, i64>(1)?,
+                    r.get::<_, i64>(2)?,
+                    r.get::<_, String>(3)?,
+                    r.get::<_, i64>(4)?,
+                ))
+            })
+            .optional()?;
+        let Some((min_lamport, min_offset, timestamp, root, last_offset)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(AppendMeta {
+            min_lamport: LamportTimestamp::from(min_lamport as u64),
+            min_offset: Offset::try_from(min_offset)?,
+            timestamp: Timestamp::from(timestamp as u64),
+            root: root.parse()?,
+            last_offset: Offset::try_from(last_offset)?,
+        }))
+    }
+
+    /// Record the result of an append made with an idempotency token, dropping expired tokens
+    pub fn add_append_token(&mut self, token: &[u8; 32], meta: &AppendMeta, expires: Timestamp) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.prepare_cached("DELETE FROM append_tokens WHERE expires <= ?")?
+            .execute(params![u64::from(meta.timestamp) as i64])?;
+        conn.prepare_cached("INSERT OR REPLACE INTO append_tokens VALUES (?, ?, ?, ?, ?, ?, ?)")?
+            .execute(params![
+                &token[..],
+                u64::from(meta.min_lamport) as i64,
+                u64::from(meta.min_offset) as i64,
+                u64::from(meta.timestamp) as i64,
+                meta.root.to_string(),
+                u64::from(meta.last_offset) as i64,
+                u64::from(expires) as i64,
+            ])?;
+        Ok(())
+    }
+
+    /// Remember that a stream has been tombstoned, keeping the first tombstone seen for it
+    pub fn add_tombstone(&mut self, stream: StreamId, tombstone: &Tombstone) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("INSERT OR IGNORE INTO tombstones VALUES (?, ?, ?, ?, ?)")?
+            .execute(params![
+                &stream,
+                tombstone.reason,
+                u64::from(tombstone.offset) as i64,
+                u64::from(tombstone.purge_at) as i64,
+                tombstone.purged,
+            ])?;
+        Ok(())
+    }
+
+    pub fn set_tombstone_purged(&mut self, stream: StreamId) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("UPDATE tombstones SET purged = 1 WHERE stream = ?")?
+            .execute(params![&stream])?;
+        Ok(())
+    }
+
+    pub fn get_tombstones(&mut self) -> Result<BTreeMap<StreamId, Tombstone>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached("SELECT stream, reason, offset, purge_at, purged FROM tombstones")?;
+        let rows = stmt.query_map([], |r| {
+            Ok((
+                r.get::<_, StreamId>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get::<_, i64>(3)?,
+                r.get::<_, bool>(4)?,
+            ))
+        })?;
+        let mut result = BTreeMap::new();
+        for row in rows {
+            let (stream, reason, offset, purge_at, purged) = row?;
+            let tombstone = Tombstone {
+                reason,
+                offset: Offset::try_from(offset)?,
+                purge_at: Timestamp::from(purge_at as u64),
+                purged,
+            };
+            result.insert(stream, tombstone);
+        }
+        Ok(result)
+    }
+
+    /// Register a name for a local stream, failing if the name or the number is already taken
+    pub fn add_stream_name(&mut self, name: &str, stream_nr: StreamNr) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("INSERT INTO stream_names VALUES (?, ?)")?
+            .execute(params![name, u64::from(stream_nr) as i64])?;
+        Ok(())
+    }
+
+    pub fn get_stream_names(&mut self) -> Result<BTreeMap<String, StreamNr>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached("SELECT name, stream_nr FROM stream_names")?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?;
+        let mut result = BTreeMap::new();
+        for row in rows {
+            let (name, stream_nr) = row?;
+            result.insert(name, StreamNr::from(stream_nr as u64));
+        }
+        Ok(result)
+    }
+
+    /// Apply the changes to the known peer addresses in one transaction, then drop the least
+    /// recently seen addresses beyond [`MAX_PEER_ADDRESSES`]
+    pub fn update_peer_addresses(&mut self, updates: &[PeerAddressUpdate]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        {
+            let mut seen = tx.prepare_cached(
+                "INSERT INTO peer_addresses VALUES (?, ?, ?) \
+                ON CONFLICT(peer, addr) DO UPDATE SET last_seen = MAX(last_seen, excluded.last_seen)",
+            )?;
+            let mut expired = tx.prepare_cached("DELETE FROM peer_addresses WHERE peer = ? AND addr = ?")?;
+            for update in updates {
+                match update {
+                    PeerAddressUpdate::Seen(peer, addr, at) => {
+                        seen.execute(params![peer.to_string(), addr.to_string(), u64::from(*at) as i64])?
+                    }
+                    PeerAddressUpdate::Expired(peer, addr) => {
+                        expired.execute(params![peer.to_string(), addr.to_string()])?
+                    }
+                };
+            }
+        }
+        tx.prepare_cached(
+            "DELETE FROM peer_addresses WHERE rowid NOT IN \
+            (SELECT rowid FROM peer_addresses ORDER BY last_seen DESC LIMIT ?)",
+        )?
+        .execute(params![MAX_PEER_ADDRESSES])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Mark all known addresses of the peer as seen
+    pub fn touch_peer(&mut self, peer: &PeerId, seen: Timestamp) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("UPDATE peer_addresses SET last_seen = MAX(last_seen, ?) WHERE peer = ?")?
+            .execute(params![u64::from(seen) as i64, peer.to_string()])?;
+        Ok(())
+    }
+
+    /// Drop the addresses last seen before `cutoff`
+    pub fn forget_peer_addresses(&mut self, cutoff: Timestamp) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("DELETE FROM peer_addresses WHERE last_seen < ?")?
+            .execute(params![u64::from(cutoff) as i64])?;
+        Ok(())
+    }
+
+    /// Known peer addresses, most recently seen first
+    pub fn get_peer_addresses(&mut self) -> Result<Vec<(PeerId, Multiaddr, Timestamp)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare_cached("SELECT peer, addr, last_seen FROM peer_addresses ORDER BY last_seen DESC, peer, addr")?;
+        let rows = stmt.query_map([], |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, i64>(2)?))
+        })?;
+        let mut result = Vec::new();
+        for row in rows {
+            let (peer, addr, seen) = row?;
+            match (peer.parse(), addr.parse()) {
+                (Ok(peer), Ok(addr)) => result.push((peer, addr, Timestamp::from(seen as u64))),
+                _ => warn!("ignoring invalid peer address {} {}", peer, addr),
+            }
+        }
+        Ok(result)
+    }
+
     pub fn observe_lamport(&self) -> Observer<LamportTimestamp> {
         self.lamport.new_observer()
     }
@@ -153,21 +476,26 @@ impl SqliteIndexStore {
     }
 }
 
-pub fn initialize_db(conn: &Connection) -> Result<()> {
-    // We use TRUNCATE to avoid an explosion of WAL file size (multiple GB were observed)
-    // DELETE (the default) needs one extra directory write op per transaction
+pub fn initialize_db(conn: &Connection, synchronous: Synchronous) -> Result<()> {
+    // WAL needs no sync per transaction with synchronous = NORMAL. The WAL file size exploded to
+    // multiple GB when checkpoints were starved, so it is truncated after each checkpoint.
     // This PRAGMA statement returns the new journal mode, so we need to see if it succeeded
-    conn.query_row("PRAGMA journal_mode = TRUNCATE;", [], |row| {
+    conn.query_row("PRAGMA journal_mode = WAL;", [], |row| {
         let res: String = row.get(0)?;
         match res.as_str() {
-            "truncate" => Ok(()),
+            "wal" => Ok(()),
             "memory" => Ok(()), // There is no config choice for memory databases
             _other => Err(rusqlite::Error::InvalidQuery),
         }
     })
     .context("setting journal_mode")?;
-    // `PRAGMA synchronous = NORMAL;` https://www.sqlite.org/pragma.html#pragma_synchronous
-    conn.execute("PRAGMA synchronous = NORMAL;", [])
+    conn.query_row(
+        &format!("PRAGMA journal_size_limit = {};", JOURNAL_SIZE_LIMIT),
+        [],
+        |_| Ok(()),
+    )
+    .context("setting journal size limit")?;
+    conn.execute(&format!("PRAGMA synchronous = {};", synchronous.as_str()), [])
         .context("setting sync mode")?;
     conn.execute_batch(
         "BEGIN;\n\
@@ -175,6 +503,19 @@ pub fn initialize_db(conn: &Connection) -> Result<()> {
             (stream TEXT UNIQUE);\n\
         CREATE TABLE IF NOT EXISTS meta \
             (lamport INTEGER);\n\
+        CREATE TABLE IF NOT EXISTS payload_blobs \
+            (cid TEXT PRIMARY KEY, refs INTEGER NOT NULL);\n\
+        CREATE TABLE IF NOT EXISTS peer_addresses \
+            (peer TEXT NOT NULL, addr TEXT NOT NULL, last_seen INTEGER NOT NULL, PRIMARY KEY (peer, addr));\n\
+        CREATE TABLE IF NOT EXISTS append_tokens \
+            (token BLOB PRIMARY KEY, min_lamport INTEGER NOT NULL, min_offset INTEGER NOT NULL, \
+            timestamp INTEGER NOT NULL, root TEXT NOT NULL, last_offset INTEGER NOT NULL, \
+            expires INTEGER NOT NULL);\n\
+        CREATE TABLE IF NOT EXISTS tombstones \
+            (stream TEXT PRIMARY KEY, reason TEXT NOT NULL, offset INTEGER NOT NULL, purge_at INTEGER NOT NULL, \
+            purged INTEGER NOT NULL);\n\
+        CREATE TABLE IF NOT EXISTS stream_names \
+            (name TEXT PRIMARY KEY, stream_nr INTEGER NOT NULL UNIQUE);\n\
         COMMIT;",
     )
     .context("creating tables")?;
@@ -187,11 +528,19 @@ mod test {
     use quickcheck::{Arbitrary, Gen};
 
     fn get_shared_memory_index_store(path: &str) -> Result<SqliteIndexStore> {
-        SqliteIndexStore::open(DbPath::File(path.into()))
+        SqliteIndexStore::open(DbPath::File(path.into()), IndexStoreConfig::default())
     }
 
     fn empty_store() -> SqliteIndexStore {
-        SqliteIndexStore::open(DbPath::Memory).unwrap()
+        SqliteIndexStore::open(DbPath::Memory, IndexStoreConfig::default()).unwrap()
+    }
+
+    fn persisted_lamport(store: &SqliteIndexStore) -> u64 {
+        store
+            .conn
+            .lock()
+            .query_row("SELECT lamport FROM meta", [], |row| row.get::<_, i64>(0))
+            .unwrap() as u64
     }
 
     #[test]
@@ -203,6 +552,28 @@ mod test {
         assert_eq!(empty_store.lamport.get(), LamportTimestamp::from(6));
     }
 
+    #[test]
+    fn increase_lamport_should_stop_at_the_end_of_the_range() {
+        let mut store = empty_store();
+        store.received_lamport((MAX_LAMPORT - 5).into()).unwrap();
+        let err = store.increase_lamport(6).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LamportError>(),
+            Some(&LamportError::Exhausted {
+                current: (MAX_LAMPORT - 5).into(),
+                count: 6
+            })
+        );
+        assert!(store.increase_lamport(u64::MAX).is_err());
+        // nothing was reserved by the failed attempts
+        assert_eq!(
+            store.increase_lamport(5).unwrap(),
+            LamportTimestamp::from(MAX_LAMPORT - 5)
+        );
+        assert_eq!(store.lamport.get(), LamportTimestamp::from(MAX_LAMPORT));
+        assert!(store.increase_lamport(1).is_err());
+    }
+
     #[test]
     fn creating_a_new_store_should_grab_lamport_from_the_db() -> Result<()> {
         let dir = tempfile::tempdir()?;
@@ -213,20 +584,156 @@ mod test {
         }
         let other_store = get_shared_memory_index_store(&db)?;
 
-        assert_eq!(store.lamport.get(), other_store.lamport.get());
+        // the other store starts after the reservation of the first one
+        assert_eq!(other_store.lamport.get(), LamportTimestamp::from(1000));
         Ok(())
     }
 
     #[test]
-    fn backup_test() -> Result<()> {
+    fn lamports_are_reserved_in_chunks() -> Result<()> {
+        let config = IndexStoreConfig {
+            lamport_chunk: 10,
+            ..Default::default()
+        };
+        let mut store = SqliteIndexStore::open(DbPath::Memory, config)?;
+        assert_eq!(store.increase_lamport(3)?, LamportTimestamp::from(0));
+        assert_eq!(persisted_lamport(&store), 10);
+        // received lamports within the reservation are not written
+        store.received_lamport(5.into())?;
+        store.received_lamport(2.into())?;
+        assert_eq!(store.lamport(), LamportTimestamp::from(6));
+        assert_eq!(persisted_lamport(&store), 10);
+        // a received lamport beyond the reservation reserves a chunk of headroom
+        store.received_lamport(50.into())?;
+        assert_eq!(store.lamport(), LamportTimestamp::from(50));
+        assert_eq!(persisted_lamport(&store), 60);
+        store.received_lamport(55.into())?;
+        assert_eq!(persisted_lamport(&store), 60);
+
+        assert_eq!(store.increase_lamport(3)?, LamportTimestamp::from(55));
+        assert_eq!(persisted_lamport(&store), 60);
+        assert_eq!(store.increase_lamport(7)?, LamportTimestamp::from(58));
+        assert_eq!(persisted_lamport(&store), 68);
+        // a batch larger than the chunk is reserved as a whole
+        assert_eq!(store.increase_lamport(25)?, LamportTimestamp::from(65));
+        assert_eq!(persisted_lamport(&store), 90);
+        Ok(())
+    }
+
+    #[test]
+    fn received_lamport_near_the_end_of_the_range() -> Result<()> {
         let mut store = empty_store();
-        // write some stuff
+        store.received_lamport((MAX_LAMPORT - 5).into())?;
+        assert_eq!(store.lamport(), LamportTimestamp::from(MAX_LAMPORT - 5));
+        assert_eq!(persisted_lamport(&store), MAX_LAMPORT);
+        Ok(())
+    }
+
+    #[test]
+    fn restart_mid_chunk_does_not_reuse_lamports() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = DbPath::File(dir.path().join("db"));
+        let config = IndexStoreConfig {
+            lamport_chunk: 100,
+            synchronous: Synchronous::Full,
+        };
+        let mut handed_out = vec![];
+        let mut store = SqliteIndexStore::open(db.clone(), config)?;
+        for _ in 0..30 {
+            handed_out.push(store.increase_lamport(2)?);
+        }
+        store.received_lamport(150.into())?;
+        for _ in 0..5 {
+            handed_out.push(store.increase_lamport(1)?);
+        }
+        // unclean shutdown, nothing is written on drop
+        std::mem::forget(store);
+
+        let mut store = SqliteIndexStore::open(db, config)?;
+        let last = *handed_out.last().unwrap();
+        assert!(store.lamport() > last);
+        for _ in 0..10 {
+            handed_out.push(store.increase_lamport(3)?);
+        }
+        assert!(handed_out.windows(2).all(|w| w[0] < w[1]), "{:?}", handed_out);
+        assert!(persisted_lamport(&store) >= u64::from(store.lamport()));
+        Ok(())
+    }
+
+    #[test]
+    fn backup_test() -> Result<()> {
+        let config = IndexStoreConfig {
+            lamport_chunk: 300,
+            ..Default::default()
+        };
+        let mut store = SqliteIndexStore::open(DbPath::Memory, config)?;
+        // write some stuff, ending in the middle of the fourth chunk
         for _ in 0..1000 {
             store.increase_lamport(1)?;
         }
+        assert_eq!(persisted_lamport(&store), 1200);
         let backed_up = store.backup(DbPath::Memory).unwrap();
-        let backed_up_store = SqliteIndexStore::from_conn(Arc::new(Mutex::new(backed_up))).unwrap();
-        assert_eq!(backed_up_store.lamport(), store.lamport());
+        let backed_up_store = SqliteIndexStore::from_conn(Arc::new(Mutex::new(backed_up)), config).unwrap();
+        // the backup continues after the reservation, never reusing a lamport
+        assert_eq!(backed_up_store.lamport(), LamportTimestamp::from(1200));
+        assert!(backed_up_store.lamport() >= store.lamport());
+        Ok(())
+    }
+
+    #[test]
+    fn payload_blob_refcount() -> Result<()> {
+        let mut s = empty_store();
+        let cid = Cid::from(crate::trees::axtrees::Sha256Digest::new(b"blob"));
+        assert_eq!(s.remove_payload_blob_ref(&cid)?, None);
+        assert_eq!(s.add_payload_blob_ref(&cid)?, 1);
+        assert_eq!(s.add_payload_blob_ref(&cid)?, 2);
+        assert_eq!(s.remove_payload_blob_ref(&cid)?, Some(1));
+        assert_eq!(s.remove_payload_blob_ref(&cid)?, Some(0));
+        // fully released blobs are forgotten
+        assert_eq!(s.remove_payload_blob_ref(&cid)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn peer_addresses() -> Result<()> {
+        use PeerAddressUpdate::*;
+        let mut s = empty_store();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let addr = |port: u16| format!("/ip4/10.0.0.1/tcp/{}", port).parse::<Multiaddr>().unwrap();
+        s.update_peer_addresses(&[
+            Seen(a, addr(1), Timestamp::from(10)),
+            Seen(a, addr(2), Timestamp::from(20)),
+            Seen(b, addr(3), Timestamp::from(30)),
+            // an older sighting does not move last_seen back
+            Seen(b, addr(3), Timestamp::from(5)),
+        ])?;
+        assert_eq!(
+            s.get_peer_addresses()?,
+            vec![
+                (b, addr(3), Timestamp::from(30)),
+                (a, addr(2), Timestamp::from(20)),
+                (a, addr(1), Timestamp::from(10)),
+            ]
+        );
+
+        s.touch_peer(&a, Timestamp::from(40))?;
+        s.update_peer_addresses(&[Expired(b, addr(3)), Seen(b, addr(4), Timestamp::from(1))])?;
+        assert_eq!(s.get_peer_addresses()?.len(), 3);
+        // stale addresses are forgotten
+        s.forget_peer_addresses(Timestamp::from(10))?;
+        assert_eq!(
+            s.get_peer_addresses()?,
+            vec![(a, addr(1), Timestamp::from(40)), (a, addr(2), Timestamp::from(40))]
+        );
+
+        // the least recently seen addresses beyond the cap are dropped right away
+        let updates = (0..MAX_PEER_ADDRESSES as u16)
+            .map(|port| Seen(b, addr(1000 + port), Timestamp::from(100 + port as u64)))
+            .collect::<Vec<_>>();
+        s.update_peer_addresses(&updates)?;
+        let kept = s.get_peer_addresses()?;
+        assert_eq!(kept.len(), MAX_PEER_ADDRESSES as usize);
+        assert!(kept.iter().all(|(peer, ..)| *peer == b));
         Ok(())
     }
 
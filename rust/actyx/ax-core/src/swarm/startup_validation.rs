@@ -0,0 +1,225 @@
+//! Check on startup that the trees of all known streams are completely present in the block store,
+//! and what to do with the streams whose tree is not, see [`ValidationMode`].
+use crate::swarm::{BanyanStore, BanyanStoreGuard, Link, PeerEvent, StreamAlias};
+use anyhow::{Context, Result};
+use ax_types::{Offset, StreamId};
+use futures::{future, StreamExt};
+use ipfs_embed::{PeerId, SyncEvent};
+use std::collections::BTreeMap;
+
+/// What to do on startup with streams whose tree is not completely present
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Refuse to start
+    #[default]
+    Strict,
+    /// Start anyway.
+    ///
+    /// The incomplete tree of a replicated stream is dropped and synced again from its peers.
+    /// Earlier roots of a stream are not kept, so an own stream has no complete ancestor to fall
+    /// back to and starting it over would fork it: its tree is kept and repaired as in
+    /// [`Repair`](Self::Repair), refusing appends until then.
+    Lenient,
+    /// Keep the incomplete tree and fetch the missing blocks from each peer that connects, until
+    /// the tree is complete. Reading the missing events fails until then, appending to an
+    /// incomplete own stream fails with [`IncompleteStreamError`].
+    Repair,
+}
+
+/// Outcome of the startup validation, see [`BanyanStore::startup_report`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StartupReport {
+    pub mode: ValidationMode,
+    /// streams whose tree was not completely present, with what has been done about it
+    pub incomplete_streams: BTreeMap<StreamId, StreamRecovery>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamRecovery {
+    /// The alias was removed, the events up to `offset` are gone
+    Unaliased { offset: Offset, error: String },
+    /// The alias was kept, the missing blocks are fetched from peers
+    RepairScheduled { offset: Offset, error: String },
+    /// The missing blocks have been fetched from peers
+    Repaired { offset: Offset },
+}
+
+/// Appending to an own stream whose tree was found incomplete on startup and has not been
+/// repaired yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[display(
+    fmt = "stream {} is missing blocks and cannot be appended to until it is repaired",
+    stream_id
+)]
+pub struct IncompleteStreamError {
+    pub stream_id: StreamId,
+}
+
+impl<'a> BanyanStoreGuard<'a> {
+    /// Drop the replicated stream from memory and remove its alias, see [`ValidationMode::Lenient`]
+    fn unalias_stream(&mut self, stream_id: StreamId) -> Result<()> {
+        debug_assert!(!self.is_local(stream_id), "own streams are repaired, never unaliased");
+        if let Some(node) = self.remote_nodes.get_mut(&stream_id.node_id()) {
+            // recreated from the next root update of the stream
+            node.streams.remove(&stream_id.stream_nr());
+            self.abort_task(&format!("careful_ingestion({})", stream_id));
+        }
+        self.dormant_streams.remove(&stream_id);
+        self.data.ipfs.alias(StreamAlias::from(stream_id), None)?;
+        Ok(())
+    }
+}
+
+impl BanyanStore {
+    /// The streams found incomplete on startup and what has been done about them
+    pub fn startup_report(&self) -> StartupReport {
+        self.data.startup_report.lock().clone()
+    }
+
+    /// Fetch the blocks of the tree below `root` missing locally from `peers`. Without peers this
+    /// only checks that all blocks are present.
+    async fn sync_tree(&self, root: Link, peers: Vec<PeerId>) -> Result<()> {
+        let mut sync = self.data.ipfs.sync(&root.into(), peers).await?;
+        while let Some(event) = sync.next().await {
+            if let SyncEvent::Complete(result) = event {
+                return result;
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate that all known streams are completely present, dealing with the incomplete ones
+    /// according to `mode`.
+    pub(crate) async fn validate_known_streams(&self, mode: ValidationMode) -> Result<()> {
+        let state = self.lock();
+        let trees = state
+            .current_stream_ids()
+            .filter_map(|stream_id| {
+                state
+                    .published_tree(stream_id)
+                    .map(|p| (stream_id, p.offset(), p.root()))
+            })
+            .collect::<Vec<_>>();
+        drop(state);
+        let results = future::join_all(trees.into_iter().map(|(stream_id, offset, root)| async move {
+            let result = self.sync_tree(root, vec![]).await;
+            (stream_id, offset, result)
+        }))
+        .await;
+        let mut incomplete = BTreeMap::new();
+        for (stream_id, offset, result) in results {
+            match result {
+                Ok(()) => tracing::debug!("validated alias for stream_id {}", stream_id),
+                Err(cause) => {
+                    tracing::error!("incomplete alias for stream id {}: {}", stream_id, cause);
+                    incomplete.insert(stream_id, (offset, cause));
+                }
+            }
+        }
+        let mut report = StartupReport {
+            mode,
+            incomplete_streams: BTreeMap::new(),
+        };
+        match mode {
+            _ if incomplete.is_empty() => {}
+            ValidationMode::Strict => {
+                let count = incomplete.len();
+                let (_, (_, cause)) = incomplete.into_iter().next().unwrap();
+                return Err(cause).context(format!("Found {} streams with missing events, giving up.", count));
+            }
+            ValidationMode::Lenient => {
+                let mut state = self.lock();
+                for (stream_id, (offset, cause)) in incomplete {
+                    if state.is_local(stream_id) {
+                        tracing::warn!("fetching missing events of own stream {} from peers", stream_id);
+                        let recovery = StreamRecovery::RepairScheduled {
+                            offset,
+                            error: cause.to_string(),
+                        };
+                        report.incomplete_streams.insert(stream_id, recovery);
+                        continue;
+                    }
+                    state.unalias_stream(stream_id)?;
+                    tracing::warn!(
+                        "dropped stream {} with missing events up to offset {}",
+                        stream_id,
+                        offset
+                    );
+                    let recovery = StreamRecovery::Unaliased {
+                        offset,
+                        error: cause.to_string(),
+                    };
+                    report.incomplete_streams.insert(stream_id, recovery);
+                }
+                state.data.offsets.set(state.compute_swarm_offsets());
+            }
+            ValidationMode::Repair => {
+                for (stream_id, (offset, cause)) in incomplete {
+                    tracing::warn!("fetching missing events of stream {} from peers", stream_id);
+                    let recovery = StreamRecovery::RepairScheduled {
+                        offset,
+                        error: cause.to_string(),
+                    };
+                    report.incomplete_streams.insert(stream_id, recovery);
+                }
+            }
+        }
+        *self.data.startup_report.lock() = report;
+        Ok(())
+    }
+
+    /// Fail with [`IncompleteStreamError`] if `stream_id` is still waiting for its missing blocks
+    pub(crate) fn check_stream_complete(&self, stream_id: StreamId) -> Result<()> {
+        match self.data.startup_report.lock().incomplete_streams.get(&stream_id) {
+            Some(StreamRecovery::RepairScheduled { .. }) => Err(IncompleteStreamError { stream_id }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Streams of the startup report still waiting for their missing blocks
+    pub(crate) fn streams_to_repair(&self) -> Vec<(StreamId, Offset)> {
+        self.data
+            .startup_report
+            .lock()
+            .incomplete_streams
+            .iter()
+            .filter_map(|(stream_id, recovery)| match recovery {
+                StreamRecovery::RepairScheduled { offset, .. } => Some((*stream_id, *offset)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Fetches the missing blocks of the streams found incomplete on startup and kept for repair, see
+/// [`StreamRecovery::RepairScheduled`], retrying whenever a peer connects. Runs until the swarm shuts down.
+pub(crate) async fn repair_streams(store: BanyanStore) {
+    let mut peer_events = store.peer_events();
+    while let Some(event) = peer_events.next().await {
+        let PeerEvent::Connected { peer, .. } = event else {
+            continue;
+        };
+        for (stream_id, offset) in store.streams_to_repair() {
+            let Some(root) = store.lock().published_tree(stream_id).map(|tree| tree.root()) else {
+                continue;
+            };
+            tracing::debug!(
+                "fetching missing events of stream {} after {} connected",
+                stream_id,
+                peer
+            );
+            match store.sync_tree(root, store.ipfs().peers()).await {
+                Ok(()) => {
+                    tracing::info!("repaired stream {} up to offset {}", stream_id, offset);
+                    store
+                        .data
+                        .startup_report
+                        .lock()
+                        .incomplete_streams
+                        .insert(stream_id, StreamRecovery::Repaired { offset });
+                }
+                Err(err) => tracing::debug!("cannot repair stream {} yet: {}", stream_id, err),
+            }
+        }
+    }
+}
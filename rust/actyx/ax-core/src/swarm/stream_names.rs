@@ -0,0 +1,59 @@
+//! Registry of named local streams, so applications can have dedicated streams without
+//! hard-coding stream numbers.
+use crate::swarm::{BanyanStore, FILES_STREAM_NUMBER};
+use anyhow::{ensure, Result};
+use ax_types::StreamNr;
+use std::collections::BTreeMap;
+
+/// Stream numbers up to this one are used internally and never assigned to a name
+const MAX_RESERVED_STREAM_NUMBER: u64 = FILES_STREAM_NUMBER;
+
+impl BanyanStore {
+    /// The number of the local stream registered under `name`, registering the next free number
+    /// on first use.
+    ///
+    /// Streams mapped by event routes are found under their route target name. The names of the
+    /// internal streams (`default`, `discovery`, `metrics` and `files`) cannot be used.
+    pub fn stream_for_name(&self, name: &str) -> Result<StreamNr> {
+        ensure!(!name.is_empty(), "stream name must not be empty");
+        // the store lock makes concurrent first lookups assign only one number
+        let mut store = self.lock();
+        let names = store.index_store.get_stream_names()?;
+        if let Some(stream_nr) = names.get(name) {
+            return Ok(*stream_nr);
+        }
+        let routing_table = self.routing_table();
+        if let Some(stream_nr) = routing_table.stream_mapping.get(name) {
+            ensure!(
+                u64::from(*stream_nr) > MAX_RESERVED_STREAM_NUMBER,
+                "stream name '{}' is reserved",
+                name
+            );
+            return Ok(*stream_nr);
+        }
+        // stream numbers are never reused, not even the ones of purged streams
+        let tombstoned = store
+            .tombstones
+            .keys()
+            .filter(|stream_id| store.is_local(**stream_id))
+            .map(|stream_id| stream_id.stream_nr());
+        let stream_nr = names
+            .values()
+            .copied()
+            .chain(routing_table.max_stream_nr)
+            .chain(store.local_stream_nrs())
+            .chain(tombstoned)
+            .map(u64::from)
+            .fold(MAX_RESERVED_STREAM_NUMBER, u64::max)
+            + 1;
+        let stream_nr = StreamNr::from(stream_nr);
+        store.index_store.add_stream_name(name, stream_nr)?;
+        tracing::info!("registered stream name '{}' as stream {}", name, stream_nr);
+        Ok(stream_nr)
+    }
+
+    /// All names registered with [`stream_for_name`](Self::stream_for_name)
+    pub fn stream_names(&self) -> Result<BTreeMap<String, StreamNr>> {
+        self.lock().index_store.get_stream_names()
+    }
+}
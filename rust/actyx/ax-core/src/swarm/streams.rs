@@ -13,7 +13,10 @@ use futures::{
 use std::{
     convert::{TryFrom, TryInto},
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 const PREFIX: u8 = b'S';
@@ -69,6 +72,9 @@ pub struct OwnStream {
     builder: tokio::sync::Mutex<AxStreamBuilder>,
     /// the latest published tree
     latest: Variable<Option<PublishedTree>>,
+    /// leaves appended since the stream was last packed, not counting those from before the start
+    /// of the node; only changed while holding the builder lock
+    unpacked_leaves: AtomicUsize,
 }
 
 impl OwnStream {
@@ -77,6 +83,7 @@ impl OwnStream {
             stream_nr,
             builder: tokio::sync::Mutex::new(builder),
             latest: Variable::new(latest),
+            unpacked_leaves: AtomicUsize::new(0),
         }
     }
 
@@ -116,6 +123,14 @@ impl<'a> OwnStreamGuard<'a> {
     pub fn stream_nr(&self) -> StreamNr {
         self.0.stream_nr
     }
+
+    pub fn unpacked_leaves(&self) -> usize {
+        self.0.unpacked_leaves.load(Ordering::Relaxed)
+    }
+
+    pub fn set_unpacked_leaves(&self, leaves: usize) {
+        self.0.unpacked_leaves.store(leaves, Ordering::Relaxed)
+    }
 }
 
 impl<'a> Deref for OwnStreamGuard<'a> {
@@ -146,6 +161,8 @@ pub struct ReplicatedStream {
     validated: Variable<Option<PublishedTree>>,
     // stream of incoming roots
     incoming: Variable<Option<(Link, RootSource)>>,
+    // lamport of the latest header seen for a stream that is only replicated partially
+    tracked_header: Variable<LamportTimestamp>,
 }
 
 /// Trees are published including a tree header.
@@ -176,6 +193,10 @@ impl PublishedTree {
     pub fn root(&self) -> Link {
         self.root
     }
+
+    pub fn tree(&self) -> &AxTree {
+        &self.tree
+    }
 }
 
 impl ReplicatedStream {
@@ -183,6 +204,7 @@ impl ReplicatedStream {
         Self {
             validated: Variable::new(state),
             incoming: Variable::default(),
+            tracked_header: Variable::default(),
         }
     }
 
@@ -211,6 +233,24 @@ impl ReplicatedStream {
         })
     }
 
+    /// lamport of the latest header tracked without replicating the tree.
+    /// Will default to 0 if no header has been tracked yet.
+    pub fn tracked_header_lamport(&self) -> LamportTimestamp {
+        self.tracked_header.get()
+    }
+
+    /// remember the lamport of a header whose tree is not replicated
+    pub fn track_header(&self, lamport: LamportTimestamp) {
+        self.tracked_header.transform_mut(|x| {
+            if lamport > *x {
+                *x = lamport;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
     /// set the latest incoming root
     ///
     /// This will trigger validation if the `source` has sufficient priority compared to the current link.
@@ -234,7 +274,7 @@ impl ReplicatedStream {
                     if error {
                         x.take();
                     } else {
-                        s.path = RootPath::RootMap;
+                        s.path = RootPath::Deferred;
                     }
                 }
                 _ => {}
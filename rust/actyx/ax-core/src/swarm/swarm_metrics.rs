@@ -0,0 +1,194 @@
+//! Prometheus metrics of the swarm, see [`BanyanStore::metrics_registry`].
+//!
+//! Counters are updated where things happen and only touch atomics. Values that can be read
+//! cheaply from elsewhere, like the present offsets or the number of peers, are refreshed when the
+//! registry is requested.
+use crate::swarm::{event_store_ref::EventStoreRef, BanyanStore};
+use ax_types::StreamNr;
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+#[derive(Clone)]
+pub(crate) struct SwarmMetrics {
+    registry: Registry,
+    appended_events: IntCounterVec,
+    synced_events: IntCounter,
+    gossip_received: IntCounter,
+    gossip_sent: IntCounter,
+    blocks_received: IntCounter,
+    bytes_received: IntCounter,
+    blocks_sent: IntCounter,
+    bytes_sent: IntCounter,
+    present_offsets: IntGauge,
+    peers: IntGauge,
+    tasks: IntGaugeVec,
+}
+
+impl SwarmMetrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str| -> prometheus::Result<IntCounter> {
+            let counter = IntCounter::new(name, help)?;
+            registry.register(Box::new(counter.clone()))?;
+            Ok(counter)
+        };
+        let gauge = |name: &str, help: &str| -> prometheus::Result<IntGauge> {
+            let gauge = IntGauge::new(name, help)?;
+            registry.register(Box::new(gauge.clone()))?;
+            Ok(gauge)
+        };
+        let appended_events = IntCounterVec::new(
+            Opts::new(
+                "ax_swarm_appended_events_total",
+                "Events appended to the streams of this node",
+            ),
+            &["stream"],
+        )?;
+        registry.register(Box::new(appended_events.clone()))?;
+        let tasks = IntGaugeVec::new(
+            Opts::new("ax_swarm_tasks_running", "Running background tasks of the swarm"),
+            &["task"],
+        )?;
+        registry.register(Box::new(tasks.clone()))?;
+        Ok(Self {
+            appended_events,
+            synced_events: counter(
+                "ax_swarm_synced_events_total",
+                "Events of other nodes ingested by syncing their trees",
+            )?,
+            gossip_received: counter("ax_swarm_gossip_received_total", "Gossip messages received")?,
+            gossip_sent: counter("ax_swarm_gossip_sent_total", "Gossip messages sent")?,
+            blocks_received: counter(
+                "ax_swarm_blocks_received_total",
+                "Blocks received on the fast path or via bitswap",
+            )?,
+            bytes_received: counter(
+                "ax_swarm_block_bytes_received_total",
+                "Size of the blocks received on the fast path, bitswap does not report sizes",
+            )?,
+            blocks_sent: counter(
+                "ax_swarm_blocks_sent_total",
+                "Blocks sent on the fast path, counted once per receiving peer",
+            )?,
+            bytes_sent: counter(
+                "ax_swarm_block_bytes_sent_total",
+                "Size of the blocks sent on the fast path, counted once per receiving peer",
+            )?,
+            present_offsets: gauge(
+                "ax_swarm_present_offsets_sum",
+                "Sum of the present offsets of all streams",
+            )?,
+            peers: gauge("ax_swarm_peers", "Currently connected peers")?,
+            tasks,
+            registry,
+        })
+    }
+
+    pub fn appended(&self, stream_nr: StreamNr, events: usize) {
+        self.appended_events
+            .with_label_values(&[&stream_nr.to_string()])
+            .inc_by(events as u64);
+    }
+
+    pub fn synced(&self, events: u64) {
+        self.synced_events.inc_by(events);
+    }
+
+    pub fn gossip_received(&self) {
+        self.gossip_received.inc();
+    }
+
+    pub fn gossip_sent(&self) {
+        self.gossip_sent.inc();
+    }
+
+    pub fn received(&self, blocks: usize, bytes: usize) {
+        self.blocks_received.inc_by(blocks as u64);
+        self.bytes_received.inc_by(bytes as u64);
+    }
+
+    pub fn sent(&self, peers: usize, blocks: usize, bytes: usize) {
+        self.blocks_sent.inc_by((peers * blocks) as u64);
+        self.bytes_sent.inc_by((peers * bytes) as u64);
+    }
+
+    /// Counts the task as running until the returned guard is dropped.
+    ///
+    /// Tasks per stream are counted under their common name, e.g. `careful_ingestion`.
+    pub fn task_started(&self, name: &str) -> RunningTask {
+        let name = name.split('(').next().unwrap_or_default();
+        let gauge = self.tasks.with_label_values(&[name]);
+        gauge.inc();
+        RunningTask(gauge)
+    }
+}
+
+pub(crate) struct RunningTask(IntGauge);
+
+impl Drop for RunningTask {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// The requests of an [`EventStoreRef`] awaiting their reply, read when the registry is gathered
+struct PendingRequests {
+    event_store: EventStoreRef,
+    gauge: IntGauge,
+}
+
+impl Collector for PendingRequests {
+    fn desc(&self) -> Vec<&Desc> {
+        self.gauge.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.gauge.set(self.event_store.pending_requests() as i64);
+        self.gauge.collect()
+    }
+}
+
+impl BanyanStore {
+    /// Include the pending requests of the API’s event store mailbox in the
+    /// [`metrics_registry`](BanyanStore::metrics_registry).
+    ///
+    /// Fails if an event store has been registered already.
+    pub fn register_event_store(&self, event_store: EventStoreRef) -> prometheus::Result<()> {
+        let gauge = IntGauge::new(
+            "ax_api_event_store_pending_requests",
+            "Event store requests of the API awaiting their reply",
+        )?;
+        self.data
+            .swarm_metrics
+            .registry
+            .register(Box::new(PendingRequests { event_store, gauge }))
+    }
+
+    /// Prometheus metrics of the swarm, updated as the store runs.
+    pub fn metrics_registry(&self) -> Registry {
+        let metrics = &self.data.swarm_metrics;
+        let present = self.offsets().present();
+        let sum = present.stream_iter().map(|(_, offset)| u64::from(offset)).sum::<u64>();
+        metrics.present_offsets.set(sum as i64);
+        metrics.peers.set(self.ipfs().peers().len() as i64);
+        metrics.registry.clone()
+    }
+
+    /// Whether the metrics are to be exposed over HTTP, see [`SwarmConfig::enable_prometheus`].
+    ///
+    /// [`SwarmConfig::enable_prometheus`]: crate::swarm::SwarmConfig::enable_prometheus
+    pub fn prometheus_enabled(&self) -> bool {
+        self.data.enable_prometheus
+    }
+
+    /// The metrics in the Prometheus text format, together with its content type.
+    pub fn prometheus_text(&self) -> anyhow::Result<(String, Vec<u8>)> {
+        let encoder = TextEncoder::new();
+        let mut buffer = vec![];
+        encoder.encode(&self.metrics_registry().gather(), &mut buffer)?;
+        Ok((encoder.format_type().to_owned(), buffer))
+    }
+}
@@ -0,0 +1,100 @@
+//! Which tags the events of the streams carry, for query planning and diagnostics.
+//!
+//! The numbers are computed from the keys in the index of a published tree, so they include the
+//! events of pruned leaves. They are cached per stream and recomputed once the root of the stream
+//! has changed, e.g. after an append; the cache entry of a purged stream is dropped.
+//!
+//! Computing the numbers walks the whole index of the tree, so this is done on a thread for
+//! blocking work instead of within the calling task.
+use crate::{
+    swarm::{streams::PublishedTree, BanyanStore, Link},
+    trees::tags::ScopedTag,
+};
+use anyhow::Result;
+use ax_types::{LamportTimestamp, StreamId};
+use banyan::{index::Index, query::AllQuery};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Events carrying a tag within one stream.
+///
+/// App ids and other internal tags are reported in the internal scope, see
+/// [`ScopedTag::to_app`] for keeping only the tags given by apps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagStat {
+    pub stream_id: StreamId,
+    pub tag: ScopedTag,
+    /// Number of events carrying the tag
+    pub events: u64,
+    /// Lowest lamport of these events
+    pub first_lamport: LamportTimestamp,
+    /// Highest lamport of these events
+    pub last_lamport: LamportTimestamp,
+}
+
+/// The tag statistics of each stream, with the root they were computed from
+pub(crate) type TagStatsCache = BTreeMap<StreamId, (Link, Arc<Vec<TagStat>>)>;
+
+impl BanyanStore {
+    /// Statistics of the tags of a stream or, without `stream_id`, of all streams, ordered by
+    /// stream and tag. Unknown streams have no tags.
+    pub async fn tag_stats(&self, stream_id: Option<StreamId>) -> Result<Vec<TagStat>> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.tag_stats_blocking(stream_id)).await?
+    }
+
+    fn tag_stats_blocking(&self, stream_id: Option<StreamId>) -> Result<Vec<TagStat>> {
+        let mut trees = {
+            let state = self.lock();
+            match stream_id {
+                Some(stream_id) => state
+                    .published_tree(stream_id)
+                    .map(|tree| (stream_id, tree))
+                    .into_iter()
+                    .collect::<Vec<_>>(),
+                None => state
+                    .current_stream_ids()
+                    .filter_map(|stream_id| state.published_tree(stream_id).map(|tree| (stream_id, tree)))
+                    .collect::<Vec<_>>(),
+            }
+        };
+        trees.sort_by_key(|(stream_id, _)| *stream_id);
+        let mut stats = vec![];
+        for (stream_id, tree) in trees {
+            stats.extend(self.stream_tag_stats(stream_id, &tree)?.iter().cloned());
+        }
+        Ok(stats)
+    }
+
+    fn stream_tag_stats(&self, stream_id: StreamId, tree: &PublishedTree) -> Result<Arc<Vec<TagStat>>> {
+        if let Some((root, stats)) = self.data.tag_stats.lock().get(&stream_id) {
+            if *root == tree.root() {
+                return Ok(stats.clone());
+            }
+        }
+        let mut tags = BTreeMap::<ScopedTag, TagStat>::new();
+        for index in self.data.forest.iter_index(tree.tree(), AllQuery) {
+            let Index::Leaf(leaf) = index? else { continue };
+            for key in leaf.keys() {
+                let lamport = key.lamport();
+                for tag in key.into_tags() {
+                    let stat = tags.entry(tag.clone()).or_insert_with(|| TagStat {
+                        stream_id,
+                        tag,
+                        events: 0,
+                        first_lamport: lamport,
+                        last_lamport: lamport,
+                    });
+                    stat.events += 1;
+                    stat.first_lamport = stat.first_lamport.min(lamport);
+                    stat.last_lamport = stat.last_lamport.max(lamport);
+                }
+            }
+        }
+        let stats = Arc::new(tags.into_values().collect::<Vec<_>>());
+        self.data
+            .tag_stats
+            .lock()
+            .insert(stream_id, (tree.root(), stats.clone()));
+        Ok(stats)
+    }
+}
@@ -2,25 +2,47 @@ use crate::{
     ax_futures_util::stream::{interval, AxStreamExt, Drainer},
     crypto::{KeyPair, KeyStore, PublicKey},
     swarm::{
-        AxTreeExt, BanyanStore, EphemeralEventsConfig, EventRoute, EventRouteMappingEvent, SwarmConfig,
-        DEFAULT_STREAM_NAME, DISCOVERY_STREAM_NAME, FILES_STREAM_NAME, MAX_TREE_LEVEL, METRICS_STREAM_NAME,
+        event_store::EventStore, event_store_ref::EventStoreRef, streams::PublishedTree, AxTreeExt, BanyanStore,
+        CompactionConfig, EphemeralEventsConfig, EventRoute, EventRouteMappingEvent, FileNode, FlatUnixFs,
+        IncompleteStreamError, IndexRef, MaintenanceBudget, MaintenanceConfig, MaintenanceSchedule, MaintenanceWindow,
+        OutsideWindows, PayloadRef, PeerEvent, ProgressCadence, ProgressItem, ReadOnlyError, ReplicationConfig,
+        RetainConfig, SecretProvider, StreamAlias, StreamCompaction, StreamRecovery, SwarmConfig, SwarmOffsets,
+        TagStat, TakeWhileBudget, UnixFsType, UnixfsDirAdder, ValidationMode, DEFAULT_STREAM_NAME,
+        DISCOVERY_STREAM_NAME, FILES_STREAM_NAME, METRICS_STREAM_NAME,
+    },
+    trees::{
+        axtrees::{AxTrees, TagsSummary},
+        query::{OffsetQuery, TagExprQuery},
+        tags::ScopedTag,
+        AxTreeHeader,
     },
-    trees::query::TagExprQuery,
 };
 use acto::ActoRef;
 use anyhow::Result;
 use ax_aql::TagExpr;
-use ax_types::{app_id, tags, AppId, Offset, OffsetMap, Payload, StreamNr, Tag, TagSet};
-use banyan::query::AllQuery;
+use ax_types::{
+    app_id, tag, tags, AppId, NodeId, Offset, OffsetMap, OffsetOrMin, Payload, StreamId, StreamNr, Tag, TagSet,
+    Timestamp,
+};
+use banyan::{
+    chacha20,
+    index::{CompactSeq, Summarizable},
+    query::AllQuery,
+    store::ReadOnlyStore,
+    FilteredChunk, Secrets,
+};
+use chrono::{TimeZone, Utc};
 use futures::{pin_mut, prelude::*, StreamExt};
-use libipld::Cid;
+use libipld::{cbor::DagCborCodec, codec::Codec, Cid};
 use maplit::btreemap;
+use parking_lot::Mutex;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, VecDeque},
     convert::TryFrom,
     fs, io,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
     time::Duration,
 };
 use tokio::runtime::Runtime;
@@ -96,7 +118,7 @@ fn last_item<T: Clone>(drainer: &mut Drainer<T>) -> anyhow::Result<T> {
 
 #[tokio::test]
 async fn should_compact() {
-    // this will take 1010 chunks, so it will hit the MAX_TREE_LEVEL limit once
+    // this will take 1010 chunks, so it will hit the max_unpacked_leaves limit once
     const EVENTS: usize = 10100;
     let stream_nr = StreamNr::from(1);
     let mut config = SwarmConfig::test_with_routing(
@@ -106,7 +128,8 @@ async fn should_compact() {
             "test_stream".to_string(),
         )],
     );
-    config.cadence_compact = Duration::from_secs(100000);
+    config.compaction.interval = Duration::from_secs(100000);
+    config.banyan_config.pack_inline = true;
     let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
 
     // Wait for the first compaction loop to pass.
@@ -155,16 +178,18 @@ async fn should_compact() {
 }
 
 #[tokio::test]
-async fn should_extend_packed_when_hitting_max_tree_depth() {
-    let store = BanyanStore::test_with_routing(
+async fn should_extend_packed_when_hitting_max_unpacked_leaves() {
+    const MAX_UNPACKED_LEAVES: usize = 20;
+    let mut config = SwarmConfig::test_with_routing(
         "compaction_max_tree",
         vec![EventRoute::new(
             TagExpr::from_str("'abc'").unwrap(),
             "test_stream".to_string(),
         )],
-    )
-    .await
-    .unwrap();
+    );
+    config.banyan_config.max_unpacked_leaves = MAX_UNPACKED_LEAVES;
+    config.banyan_config.pack_inline = true;
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
 
     // Wait for the first compaction loop to pass.
     tokio::time::sleep(Duration::from_millis(500)).await;
@@ -173,33 +198,433 @@ async fn should_extend_packed_when_hitting_max_tree_depth() {
     let mut tree_stream = Drainer::new(tree_stream);
     assert_eq!(last_item(&mut tree_stream).unwrap().count(), 0);
 
-    // Append individually to force creation of new branches
-    // -1 because of the `default` mapping event
-    for ev in (0..MAX_TREE_LEVEL).map(|_| (tags!("abc"), Payload::null())) {
+    // Append individually to force creation of new leaves
+    for ev in (0..MAX_UNPACKED_LEAVES).map(|_| (tags!("abc"), Payload::null())) {
         store.append(app_id(), vec![ev]).await.unwrap();
     }
     let tree_after_append = last_item(&mut tree_stream).unwrap();
     assert!(!store.data.forest.is_packed(&tree_after_append).unwrap());
-    assert_eq!(tree_after_append.level(), MAX_TREE_LEVEL);
+    assert_eq!(tree_after_append.level(), MAX_UNPACKED_LEAVES as i32);
     assert_eq!(
         tree_after_append.offset(),
-        Some(Offset::try_from((MAX_TREE_LEVEL - 1) as i64).unwrap())
+        Some(Offset::try_from((MAX_UNPACKED_LEAVES - 1) as i64).unwrap())
     );
 
-    // packing will be triggered when the existing tree's level is MAX_TREE_LEVEL + 1
+    // packing will be triggered by the append exceeding MAX_UNPACKED_LEAVES
     store
         .append(app_id(), vec![(tags!("abc"), Payload::null())])
         .await
         .unwrap();
     let tree_after_pack = last_item(&mut tree_stream).unwrap();
-    // the tree is not packed
     assert!(store.data.forest.is_packed(&tree_after_pack).unwrap());
-    // but the max level remains constant now
-    assert_eq!(tree_after_pack.level(), 3);
+    assert!(tree_after_pack.level() < MAX_UNPACKED_LEAVES as i32);
     assert_eq!(
         tree_after_pack.offset(),
-        Some(Offset::try_from(MAX_TREE_LEVEL as i64).unwrap())
+        Some(Offset::try_from(MAX_UNPACKED_LEAVES as i64).unwrap())
+    );
+}
+
+#[tokio::test]
+async fn append_burst_leaves_packing_to_compaction_loop() {
+    const MAX_UNPACKED_LEAVES: usize = 20;
+    let mut config = SwarmConfig::test_with_routing(
+        "deferred_pack",
+        vec![EventRoute::new(
+            TagExpr::from_str("'abc'").unwrap(),
+            "test_stream".to_string(),
+        )],
+    );
+    config.compaction.interval = Duration::from_secs(100000);
+    config.banyan_config.max_unpacked_leaves = MAX_UNPACKED_LEAVES;
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
+
+    // Wait for the first compaction loop to pass.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let tree_stream = store.get_or_create_own_stream(1.into()).unwrap().tree_stream();
+    let mut tree_stream = Drainer::new(tree_stream);
+    // the last append exceeds the limit, so the stream cannot have been packed before it
+    for i in 0..=MAX_UNPACKED_LEAVES {
+        store
+            .append(app_id(), vec![(tags!("abc"), Payload::null())])
+            .await
+            .unwrap();
+        // the test runtime is single threaded, so this is the tree published by the append
+        let tree = last_item(&mut tree_stream).unwrap();
+        assert!(!store.data.forest.is_packed(&tree).unwrap(), "append {} packed", i);
+    }
+
+    // the compaction loop packs the stream shortly after
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let tree = last_item(&mut tree_stream).unwrap();
+    assert!(store.data.forest.is_packed(&tree).unwrap());
+    assert_eq!(
+        tree.offset(),
+        Some(Offset::try_from(MAX_UNPACKED_LEAVES as i64).unwrap())
+    );
+}
+
+#[tokio::test]
+async fn should_compact_above_level_threshold() {
+    let store = BanyanStore::test_with_routing(
+        "compaction_threshold",
+        vec![
+            EventRoute::new(TagExpr::from_str("'a'").unwrap(), "stream_a".to_string()),
+            EventRoute::new(TagExpr::from_str("'b'").unwrap(), "stream_b".to_string()),
+        ],
+    )
+    .await
+    .unwrap();
+    store.abort_task("compaction");
+
+    // append individually, each adding a tree level
+    for _ in 0..3 {
+        store
+            .append(app_id(), vec![(tags!("a"), Payload::null())])
+            .await
+            .unwrap();
+    }
+    for _ in 0..10 {
+        store
+            .append(app_id(), vec![(tags!("b"), Payload::null())])
+            .await
+            .unwrap();
+    }
+    let tree = |nr: u64| {
+        let stream = store.get_or_create_own_stream(nr.into()).unwrap();
+        last_item(&mut Drainer::new(stream.tree_stream())).unwrap()
+    };
+    assert!(!store.data.forest.is_packed(&tree(1)).unwrap());
+    assert!(!store.data.forest.is_packed(&tree(2)).unwrap());
+
+    let mut config = CompactionConfig::new(Duration::from_millis(10));
+    config.level_threshold = 5;
+    // stream 0 contains the routing events and is excluded to not interfere
+    config.streams.insert(0.into(), StreamCompaction::disabled());
+    store.restart_compaction(config);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    assert!(!store.data.forest.is_packed(&tree(1)).unwrap());
+    assert!(store.data.forest.is_packed(&tree(2)).unwrap());
+    assert_eq!(tree(1).count(), 3);
+    assert_eq!(tree(2).count(), 10);
+}
+
+#[tokio::test]
+async fn compaction_cadence_changes_at_runtime() {
+    let mut config = SwarmConfig::test_with_routing(
+        "compaction_runtime",
+        vec![EventRoute::new(
+            TagExpr::from_str("'a'").unwrap(),
+            "stream_a".to_string(),
+        )],
+    );
+    config.compaction = CompactionConfig::new(Duration::from_secs(3600));
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
+    // let the first compaction pass go by
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    for _ in 0..3 {
+        store
+            .append(app_id(), vec![(tags!("a"), Payload::null())])
+            .await
+            .unwrap();
+    }
+    let tree = || {
+        let stream = store.get_or_create_own_stream(1.into()).unwrap();
+        last_item(&mut Drainer::new(stream.tree_stream())).unwrap()
+    };
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!store.data.forest.is_packed(&tree()).unwrap());
+
+    let mut settings = store.settings();
+    settings.compaction.interval = Duration::from_millis(10);
+    store.update_settings(settings).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(store.data.forest.is_packed(&tree()).unwrap());
+
+    let mut settings = store.settings();
+    settings.compaction.interval = Duration::ZERO;
+    assert!(store.update_settings(settings).is_err());
+    assert_eq!(store.settings().compaction.interval, Duration::from_millis(10));
+}
+
+#[tokio::test]
+async fn offline_stores_are_independent() -> Result<()> {
+    let a = BanyanStore::offline(SwarmConfig::test("offline_a")).await?;
+    let b = BanyanStore::offline(SwarmConfig::test("offline_b")).await?;
+    let metas = a.append(app_id(), vec![(tags!("offline"), Payload::null())]).await?;
+    let (_, offset, stream_nr, _) = metas[0];
+    let stream_id = a.node_id().stream(stream_nr);
+
+    let query = TagExprQuery::from_expr(&"'offline'".parse().unwrap()).unwrap()(true, stream_id);
+    let events = a
+        .stream_filtered_chunked(stream_id, 0..=offset.into(), query)
+        .map_ok(|chunk| chunk.data.len())
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(events.into_iter().sum::<usize>(), 1);
+
+    let known = |store: &BanyanStore| {
+        store
+            .stream_known_streams()
+            .take_until_signaled(tokio::time::sleep(Duration::from_millis(100)))
+            .collect::<Vec<_>>()
+    };
+    let (known_a, known_b) = future::join(known(&a), known(&b)).await;
+    assert!(known_a.contains(&stream_id));
+    assert!(!known_b.is_empty());
+    assert!(known_b.iter().all(|stream| stream.node_id() == b.node_id()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn offline_stores_start_quickly() -> Result<()> {
+    let started = std::time::Instant::now();
+    let stores =
+        future::try_join_all((0..50).map(|i| BanyanStore::offline(SwarmConfig::test(&format!("offline_{}", i)))))
+            .await?;
+    let elapsed = started.elapsed();
+    assert_eq!(stores.len(), 50);
+    assert!(elapsed < Duration::from_secs(1), "took {:?}", elapsed);
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_publish_resolve_unpublish() -> Result<()> {
+    let store = BanyanStore::offline(SwarmConfig {
+        block_gc_interval: Duration::from_millis(100),
+        ..SwarmConfig::test("files")
+    })
+    .await?;
+    // temp pinned until published, collection is running all the time
+    let add = |data: &'static [u8]| -> Result<_> {
+        let mut tmp = store.ipfs().create_temp_pin()?;
+        let (cid, _) = store.add(&mut tmp, data)?;
+        Ok((cid, tmp))
+    };
+    let (first, first_tmp) = add(b"first")?;
+    let (second, second_tmp) = add(b"second")?;
+    let (third, third_tmp) = add(b"third")?;
+    let in_block_store = |cid: Cid| -> Result<bool> { Ok(store.ipfs().iter()?.any(|c| c == cid)) };
+    let collected = |cid: Cid| {
+        tokio::time::timeout(Duration::from_secs(10), async move {
+            while in_block_store(cid)? {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+    };
+    assert_eq!(store.files_resolve("index").await?, None);
+
+    store.files_publish("index", first, app_id()).await?;
+    drop(first_tmp);
+    assert_eq!(store.files_resolve("index").await?, Some(first));
+    assert!(store.is_file_name_pinned("index")?);
+
+    // overwriting releases the previous root
+    store.files_publish("index", second, app_id()).await?;
+    drop(second_tmp);
+    assert_eq!(store.files_resolve("index").await?, Some(second));
+    collected(first).await??;
+    assert!(in_block_store(second)?);
+
+    // other names are not affected
+    store.files_publish("other", third, app_id()).await?;
+    drop(third_tmp);
+    store.files_unpublish("index", app_id()).await?;
+    assert_eq!(store.files_resolve("index").await?, None);
+    assert_eq!(store.files_resolve("other").await?, Some(third));
+    assert!(!store.is_file_name_pinned("index")?);
+
+    // nothing keeps the blocks of the unpublished root from being collected
+    collected(second).await??;
+    assert!(in_block_store(third)?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn listen_addresses_at_runtime() -> Result<()> {
+    crate::util::setup_logger();
+    let a = BanyanStore::test("a").await?;
+    let b = BanyanStore::test("b").await?;
+    let configured = a.ipfs().listeners()[0].clone();
+
+    let added = a.listen_on("/ip4/127.0.0.1/tcp/0".parse()?).await?;
+    assert_ne!(added, configured);
+    b.ipfs().clone().add_address(a.ipfs().local_peer_id(), added.clone());
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !b.ipfs().peers().contains(&a.ipfs().local_peer_id()) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    assert!(a.stop_listening_on("/ip4/127.0.0.1/tcp/1".parse()?).is_err());
+    a.stop_listening_on(added.clone())?;
+    // the configured listener is the last one left
+    assert!(a.stop_listening_on(configured).is_err());
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while a.ipfs().listeners().contains(&added) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    // new connections to the removed address are refused
+    let c = BanyanStore::test("c").await?;
+    let a_id = a.ipfs().local_peer_id();
+    let mut events = c.peer_events();
+    let mut ipfs = c.ipfs().clone();
+    ipfs.add_address(a_id, added.clone());
+    ipfs.dial(a_id);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match events.next().await {
+                Some(PeerEvent::Unreachable { peer }) if peer == a_id => break,
+                Some(PeerEvent::Connected { peer, addr }) if peer == a_id => {
+                    panic!("connected via {} after stopping to listen on {}", addr, added)
+                }
+                _ => {}
+            }
+        }
+    })
+    .await?;
+    assert!(!c.ipfs().peers().contains(&a_id));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn late_peer_event_subscribers_get_connected_peers() -> Result<()> {
+    crate::util::setup_logger();
+    let a = BanyanStore::test("a").await?;
+    let b = BanyanStore::test("b").await?;
+    let a_id = a.ipfs().local_peer_id();
+    let b_id = b.ipfs().local_peer_id();
+    let mut early = a.peer_events();
+    b.ipfs().clone().add_address(a_id, a.ipfs().listeners()[0].clone());
+
+    let connected = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(PeerEvent::Connected { peer, addr }) = early.next().await {
+                if peer == b_id {
+                    break addr;
+                }
+            }
+        }
+    })
+    .await?;
+
+    // subscribing after the fact starts with the connected peers
+    let mut late = a.peer_events();
+    assert_eq!(
+        late.next().await,
+        Some(PeerEvent::Connected {
+            peer: b_id,
+            addr: connected
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn synced_blocks_are_accounted_to_the_announcing_peer() -> Result<()> {
+    // without the fast path all blocks have to be fetched via bitswap
+    let a = BanyanStore::new(
+        SwarmConfig {
+            enable_fast_path: false,
+            ..SwarmConfig::test("a")
+        },
+        ActoRef::blackhole(),
+    )
+    .await?;
+    let b = BanyanStore::test("b").await?;
+    let a_id = a.ipfs().local_peer_id();
+    b.ipfs().clone().add_address(a_id, a.ipfs().listeners()[0].clone());
+
+    let metas = a.append(app_id(), vec![(tags!("abc"), Payload::null()); 10]).await?;
+    let stream = a.node_id().stream(metas[0].2);
+    let mut offsets = b.data.offsets.new_observer();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while let Some(offsets) = offsets.next().await {
+            if offsets.present.offset(stream) == Offset::from(9).into() {
+                break;
+            }
+        }
+    })
+    .await?;
+
+    let stats = b.transfer_stats();
+    assert_eq!(stats.len(), 1, "{:?}", stats);
+    assert_eq!(stats[0].peer, a_id);
+    assert!(stats[0].blocks_received > 0);
+    assert_eq!(stats[0].timed_out_wants, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn compaction_follows_maintenance_windows() {
+    let now = Arc::new(Mutex::new(Utc.with_ymd_and_hms(2023, 1, 2, 12, 0, 0).unwrap()));
+    let clock = now.clone();
+    let mut config = SwarmConfig::test_with_routing(
+        "maintenance",
+        vec![EventRoute::new(TagExpr::from_str("'a'").unwrap(), "a".to_string())],
+    );
+    config.maintenance = MaintenanceConfig {
+        windows: vec![MaintenanceWindow {
+            days: vec![],
+            start: "02:00".parse().unwrap(),
+            end: "03:00".parse().unwrap(),
+        }],
+        outside_windows: OutsideWindows {
+            compaction: MaintenanceBudget::Pause,
+            pruning: MaintenanceBudget::Pause,
+        },
+        ..Default::default()
+    };
+    config.maintenance_schedule = MaintenanceSchedule::default().with_clock(move || *clock.lock());
+    config.compaction.interval = Duration::from_millis(10);
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
+
+    let append = || async {
+        for _ in 0..3 {
+            store
+                .append(app_id(), vec![(tags!("a"), Payload::null())])
+                .await
+                .unwrap();
+        }
+    };
+    let stream = store.get_or_create_own_stream(1.into()).unwrap();
+    let is_packed = || async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let tree = last_item(&mut Drainer::new(stream.tree_stream())).unwrap();
+        store.data.forest.is_packed(&tree).unwrap()
+    };
+
+    // outside of the window nothing happens
+    append().await;
+    assert!(!is_packed().await);
+    assert!(!store.maintenance().state().in_window());
+
+    // inside of it compaction runs
+    *now.lock() = Utc.with_ymd_and_hms(2023, 1, 3, 2, 30, 0).unwrap();
+    assert!(store.maintenance().state().in_window());
+    assert!(is_packed().await);
+
+    // moving the window takes effect at the next pass
+    let mut maintenance = store.maintenance().config();
+    maintenance.windows[0].start = "04:00".parse().unwrap();
+    maintenance.windows[0].end = "05:00".parse().unwrap();
+    store.maintenance().set_config(maintenance);
+    append().await;
+    assert!(!is_packed().await);
+    assert_eq!(
+        store.maintenance().state().next_window,
+        Some(Utc.with_ymd_and_hms(2023, 1, 3, 4, 0, 0).unwrap())
     );
+
+    // as does removing all windows
+    store.maintenance().set_config(MaintenanceConfig::default());
+    assert!(is_packed().await);
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
@@ -207,10 +632,7 @@ async fn must_not_lose_events_through_compaction() -> Result<()> {
     const EVENTS: usize = 1000;
     let store = BanyanStore::test("compaction_max_tree").await?;
     // compact continuously
-    store.spawn_task(
-        "compaction".to_owned(),
-        store.clone().compaction_loop(Duration::from_micros(0)).boxed(),
-    );
+    store.restart_compaction(CompactionConfig::new(Duration::from_micros(0)));
 
     let tags_query =
         TagExprQuery::from_expr(&"'abc'".parse().unwrap()).unwrap()(true, store.node_id().stream(0.into()));
@@ -272,16 +694,66 @@ async fn must_report_proper_initial_offsets() {
         store.append(app_id(), vec![ev]).await.unwrap();
     }
 
-    let present = store.data.offsets.project(|x| x.present.clone());
+    let present = store.data.offsets.project(SwarmOffsets::present);
     assert_eq!(present, expected_present);
     drop(store);
 
     // load non-empty store from disk and check that the offsets are correctly computed
     let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
     let swarm_offsets = store.data.offsets.project(Clone::clone);
-    assert_eq!(swarm_offsets.present, expected_present);
+    assert_eq!(swarm_offsets.present(), expected_present);
     // replication_target should be equal to the present. is nulled in the event service API
-    assert_eq!(swarm_offsets.replication_target, expected_present);
+    assert_eq!(swarm_offsets.replication_target(), expected_present);
+}
+
+#[tokio::test]
+async fn quic_addresses_are_rejected() {
+    let peer = "12D3KooWRKbNGu4Qp1Tr2cWQgBMDLjEE6k1eDgX8cVrxjezhoFyo";
+    let mut config = SwarmConfig::test("quic_bootstrap");
+    config.bootstrap_addresses = vec![format!("/ip4/127.0.0.1/udp/4001/quic-v1/p2p/{}", peer).parse().unwrap()];
+    let err = BanyanStore::new(config, ActoRef::blackhole()).await.err().unwrap();
+    assert!(err.to_string().contains("QUIC is not supported"), "{:#}", err);
+
+    let mut config = SwarmConfig::test("quic_external");
+    config.external_addresses = vec!["/ip4/1.2.3.4/udp/4001/quic".parse().unwrap()];
+    assert!(BanyanStore::new(config, ActoRef::blackhole()).await.is_err());
+}
+
+#[tokio::test]
+async fn offsets_stream_and_replication_lag() {
+    let config = SwarmConfig {
+        enable_loopback: false,
+        ..SwarmConfig::test("offsets_stream")
+    };
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
+    let own = store.node_id().stream(0.into());
+    let remote = NodeId::from_bytes(&[7; 32]).unwrap().stream(0.into());
+
+    // subscribing before the changes, but polling only afterwards must yield the latest value
+    let mut offsets = Drainer::new(store.offsets_stream());
+    store
+        .append(app_id(), vec![(tags!("a"), Payload::null())])
+        .await
+        .unwrap();
+    store.update_highest_seen(remote, Offset::from(4));
+    store.update_present(remote, Offset::from(1));
+    let latest = last_item(&mut offsets).unwrap();
+    assert_eq!(latest, store.offsets());
+    assert_eq!(latest.present().offset(own), Offset::from(0).into());
+    assert_eq!(latest.present().offset(remote), Offset::from(1).into());
+
+    // setting the same value again is not emitted
+    store.data.offsets.set(latest.clone());
+    assert_eq!(offsets.next(), Some(vec![]));
+
+    store.update_highest_seen(remote, Offset::from(6));
+    assert_eq!(
+        last_item(&mut offsets).unwrap().replication_target().offset(remote),
+        Offset::from(6).into()
+    );
+    let lag = store.replication_lag();
+    assert_eq!(lag.get(&own).copied().unwrap_or_default(), 0);
+    assert_eq!(lag.get(&remote), Some(&5));
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
@@ -305,6 +777,260 @@ async fn test_add_cat() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_cat_range() -> Result<()> {
+    use rand::RngCore;
+    crate::util::setup_logger();
+    // the size of the leaf blocks written by `add`
+    const BLOCK: u64 = 256 * 1024;
+    let store = BanyanStore::test("local").await?;
+    let mut data = vec![0; 3 * BLOCK as usize + 1000];
+    rand::thread_rng().fill_bytes(&mut data);
+    let mut tmp = store.ipfs().create_temp_pin()?;
+    let (root, _) = store.add(&mut tmp, &data[..])?;
+    let len = data.len() as u64;
+
+    let chunks = |range: std::ops::Range<u64>| store.cat_range(root, range, false).try_collect::<Vec<_>>();
+    let slice = |from: u64, to: u64| data[from as usize..to as usize].to_vec();
+
+    // spanning exactly one block boundary
+    assert_eq!(
+        chunks(BLOCK - 10..BLOCK + 10).await?,
+        vec![slice(BLOCK - 10, BLOCK), slice(BLOCK, BLOCK + 10)]
+    );
+    // within a single block
+    assert_eq!(chunks(BLOCK + 5..BLOCK + 7).await?, vec![slice(BLOCK + 5, BLOCK + 7)]);
+    // covering everything
+    assert_eq!(chunks(0..u64::MAX).await?.concat(), data);
+    // reaching beyond the end of the file
+    assert_eq!(chunks(len - 100..len + 100).await?, vec![slice(len - 100, len)]);
+    assert!(chunks(len + 1..len + 100).await?.is_empty());
+    // empty range
+    assert!(chunks(BLOCK..BLOCK).await?.is_empty());
+    Ok(())
+}
+
+async fn cat_all(store: &BanyanStore, cid: Cid) -> Result<Vec<u8>> {
+    store
+        .cat(cid, false)
+        .try_fold(Vec::new(), |mut buf, bytes| {
+            buf.extend(bytes);
+            future::ready(Ok(buf))
+        })
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_add_dir() -> Result<()> {
+    crate::util::setup_logger();
+    let store = BanyanStore::test("local").await?;
+    let files = (0..300)
+        .map(|i| {
+            (
+                format!("dir{}/sub{}/file{}.txt", i % 3, i % 7, i),
+                format!("content {}", i),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut tmp = store.ipfs().create_temp_pin()?;
+    let mut adder = UnixfsDirAdder::new(&store)?;
+    for (path, content) in &files {
+        adder.add_file(path, content.as_bytes())?;
+    }
+    adder.add_dir("empty")?;
+    adder.add_dir("dir0/also_empty/")?;
+    let (root, size) = adder.finish(&mut tmp)?;
+    assert_eq!(size, files.iter().map(|(_, c)| c.len() as u64).sum::<u64>());
+
+    let resolve = |path: &str| store.unixfs_resolve_path(root, path.split('/').map(String::from).collect());
+    for (path, content) in &files {
+        match resolve(path).await? {
+            FileNode::File { cid, .. } => assert_eq!(cat_all(&store, cid).await?, content.as_bytes()),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+    for path in ["empty", "dir0/also_empty"] {
+        match resolve(path).await? {
+            FileNode::Directory { children, .. } => assert!(children.is_empty()),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+    match store.unixfs_resolve_path(root, VecDeque::new()).await? {
+        FileNode::Directory { children, .. } => {
+            let names = children.into_iter().map(|c| c.name).collect::<Vec<_>>();
+            assert_eq!(names, vec!["dir0", "dir1", "dir2", "empty"]);
+        }
+        x => panic!("unexpected {:?}", x),
+    }
+    Ok(())
+}
+
+/// Number and total size of the blocks `FileAdder` writes for `content`
+fn file_blocks(content: &[u8]) -> (u64, u64) {
+    let mut adder = unixfs_v1::file::adder::FileAdder::default();
+    let mut blocks = vec![];
+    let mut total = 0;
+    while total < content.len() {
+        let (written, consumed) = adder.push(&content[total..]);
+        blocks.extend(written);
+        total += consumed;
+    }
+    blocks.extend(adder.finish());
+    (
+        blocks.len() as u64,
+        blocks.iter().map(|(_, data)| data.len() as u64).sum(),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_unixfs_stat() -> Result<()> {
+    let store = BanyanStore::test("local").await?;
+    let small = b"hello".to_vec();
+    let big = (0..1_048_577u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let (small_blocks, small_dag) = file_blocks(&small);
+    let (big_blocks, big_dag) = file_blocks(&big);
+    assert_eq!(small_blocks, 1);
+    assert!(big_blocks > 2);
+
+    let mut tmp = store.ipfs().create_temp_pin()?;
+    let mut adder = UnixfsDirAdder::new(&store)?;
+    adder.add_file("a/b/small.txt", &small[..])?;
+    adder.add_file("a/big.bin", &big[..])?;
+    let (root, _) = adder.finish(&mut tmp)?;
+    let resolve = |path: &str| {
+        let path = path.split('/').filter(|s| !s.is_empty()).map(String::from).collect();
+        store.unixfs_resolve_path(root, path)
+    };
+
+    // files
+    let big_cid = match resolve("a/big.bin").await? {
+        FileNode::File { cid, size, .. } => {
+            assert_eq!(size, 1_048_577);
+            cid
+        }
+        x => panic!("unexpected {:?}", x),
+    };
+    let stat = store.unixfs_stat(big_cid).await?;
+    assert_eq!(stat.kind, UnixFsType::File);
+    assert_eq!(stat.content_size, Some(1_048_577));
+    assert_eq!(stat.dag_size, big_dag);
+    // the leaves are not fetched to count them
+    assert_eq!(stat.blocks, None);
+    assert_eq!(stat.mtime, None);
+    let small_cid = match resolve("a/b/small.txt").await? {
+        FileNode::File { cid, .. } => cid,
+        x => panic!("unexpected {:?}", x),
+    };
+    let stat = store.unixfs_stat(small_cid).await?;
+    assert_eq!(stat.content_size, Some(5));
+    assert_eq!(stat.dag_size, small_dag);
+    assert_eq!(stat.blocks, Some(small_blocks));
+
+    // directories record the content size of the files added here
+    let mut dir_blocks = 0;
+    let mut dir_dag = 0;
+    for path in ["", "a", "a/b"] {
+        match resolve(path).await? {
+            FileNode::Directory { own_cid, children, .. } => {
+                dir_blocks += 1;
+                dir_dag += store.ipfs().get(&own_cid)?.data().len() as u64;
+                match path {
+                    "a/b" => assert_eq!(children[0].size, 5),
+                    "a" => assert_eq!(children[1].size, 1_048_577),
+                    _ => {}
+                }
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+    let stat = store.unixfs_stat(root).await?;
+    assert_eq!(stat.kind, UnixFsType::Directory);
+    assert_eq!(stat.content_size, None);
+    assert_eq!(stat.dag_size, dir_dag + small_dag + big_dag);
+    assert_eq!(stat.blocks, None);
+    // a directory of files made of a single block is counted in full
+    let a_b = resolve_cid(&store, root, "a/b").await?;
+    let stat = store.unixfs_stat(a_b).await?;
+    assert_eq!(stat.dag_size, store.ipfs().get(&a_b)?.data().len() as u64 + small_dag);
+    assert_eq!(stat.blocks, Some(1 + small_blocks));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn unixfs_stat_does_not_fetch_contents() -> Result<()> {
+    crate::util::setup_logger();
+    let a = BanyanStore::test("a").await?;
+    let b = BanyanStore::test("b").await?;
+    let big = (0..1_048_577u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let (_, big_dag) = file_blocks(&big);
+    let mut tmp = a.ipfs().create_temp_pin()?;
+    let mut adder = UnixfsDirAdder::new(&a)?;
+    adder.add_file("big.bin", &big[..])?;
+    let (root, _) = adder.finish(&mut tmp)?;
+    let cid = resolve_cid(&a, root, "big.bin").await?;
+    let leaves = FlatUnixFs::try_parse(a.ipfs().get(&cid)?.data())
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .links
+        .iter()
+        .map(|link| Cid::try_from(link.Hash.as_deref().unwrap_or_default()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert!(!leaves.is_empty());
+
+    b.ipfs()
+        .clone()
+        .add_address(a.ipfs().local_peer_id(), a.ipfs().listeners()[0].clone());
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !b.ipfs().peers().contains(&a.ipfs().local_peer_id()) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+    let stat = b.unixfs_stat(cid).await?;
+    assert_eq!(stat.content_size, Some(1_048_577));
+    assert_eq!(stat.dag_size, big_dag);
+    // only the root block was fetched
+    assert!(b.ipfs().get(&cid).is_ok());
+    for leaf in leaves {
+        assert!(b.ipfs().get(&leaf).is_err());
+    }
+    Ok(())
+}
+
+/// The [`Cid`] of `path` below `root`
+async fn resolve_cid(store: &BanyanStore, root: Cid, path: &str) -> Result<Cid> {
+    let path = path.split('/').filter(|s| !s.is_empty()).map(String::from).collect();
+    match store.unixfs_resolve_path(root, path).await? {
+        FileNode::File { cid, .. } => Ok(cid),
+        FileNode::Directory { own_cid, .. } => Ok(own_cid),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_add_dir_failing_reader() -> Result<()> {
+    struct Failing(usize);
+    impl io::Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::new(io::ErrorKind::Other, "broken"));
+            }
+            let n = self.0.min(buf.len());
+            buf[..n].fill(42);
+            self.0 -= n;
+            Ok(n)
+        }
+    }
+
+    let store = BanyanStore::test("local").await?;
+    let mut tmp = store.ipfs().create_temp_pin()?;
+    let files: Vec<(String, Box<dyn io::Read>)> = vec![
+        ("a.txt".into(), Box::new(&b"fine"[..])),
+        ("b/c.txt".into(), Box::new(Failing(1_000_000))),
+    ];
+    let err = store.add_dir(&mut tmp, files).unwrap_err();
+    assert!(format!("{:#}", err).contains("broken"), "{:#}", err);
+    Ok(())
+}
+
 #[test]
 fn test_add_zero_bytes() -> Result<()> {
     let rt = Runtime::new()?;
@@ -404,15 +1130,12 @@ async fn existing_swarm_config() {
         index_store: Some(index),
         db_path: Some(db),
         topic: "test-topic".to_string(),
-        ephemeral_event_config: EphemeralEventsConfig {
-            streams: btreemap! {
-                "stream_1".to_string() => Default::default(),
-                "stream_2".to_string() => Default::default(),
-                // Stream 3 should not be allocated and generate a warning instead
-                "stream_3".to_string() => Default::default(),
-            },
-            ..Default::default()
-        },
+        ephemeral_event_config: EphemeralEventsConfig::from(btreemap! {
+            "stream_1".to_string() => Default::default(),
+            "stream_2".to_string() => Default::default(),
+            // Stream 3 should not be allocated and generate a warning instead
+            "stream_3".to_string() => Default::default(),
+        }),
         event_routes: vec![
             EventRoute::new(TagExpr::from_str("allEvents").unwrap(), "default".to_string()),
             EventRoute::new(TagExpr::from_str("'stream_1'").unwrap(), "stream_1".to_string()),
@@ -774,3 +1497,1335 @@ async fn existing_swarm_config_existing_streams() {
         assert_eq!(expected_other_mappings[i], round_tripped[i]);
     }
 }
+
+fn payload_blob_config(node_name: &str) -> SwarmConfig {
+    SwarmConfig {
+        payload_blob_threshold: Some(1024),
+        ..SwarmConfig::test(node_name)
+    }
+}
+
+async fn own_payloads(store: &BanyanStore, stream_nr: StreamNr, count: u64) -> Vec<Payload> {
+    store
+        .stream_filtered_chunked(store.node_id().stream(stream_nr), 0..=u64::MAX, AllQuery)
+        .take_until_condition(|x| future::ready(x.as_ref().unwrap().range.end >= count))
+        .map_ok(|chunk| stream::iter(chunk.data.into_iter().map(|(_, _, payload)| Ok(payload))))
+        .try_flatten()
+        .try_collect()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn large_payloads_are_stored_as_blobs() -> Result<()> {
+    crate::util::setup_logger();
+    let store = BanyanStore::new(payload_blob_config("blobs"), ActoRef::blackhole()).await?;
+    let large = Payload::compact(&"x".repeat(4096))?;
+    let small = Payload::compact(&"y")?;
+    let metas = store
+        .append(app_id(), vec![(tags!("a"), large.clone()), (tags!("a"), small.clone())])
+        .await?;
+
+    let payloads = own_payloads(&store, metas[0].2, 2).await;
+    let reference = PayloadRef::from_payload(&payloads[0]).expect("large payload must be a reference");
+    assert_eq!(reference.size, large.as_slice().len() as u64);
+    assert!(payloads[0].as_slice().len() < 1024);
+    assert_eq!(payloads[1], small);
+
+    assert_eq!(store.inline_payload(payloads[0].clone()).await?, large);
+    assert_eq!(store.inline_payload(small.clone()).await?, small);
+    assert!(store.is_payload_blob_pinned(&reference.cid()?)?);
+
+    // the same fields submitted by an app do not make a reference
+    let forged = Payload::from_json_value(payloads[0].json_value()).unwrap();
+    assert_eq!(PayloadRef::from_payload(&forged), None);
+    assert_eq!(store.inline_payload(forged.clone()).await?, forged);
+    Ok(())
+}
+
+#[tokio::test]
+async fn payload_blobs_of_failed_appends_are_unpinned() -> Result<()> {
+    crate::util::setup_logger();
+    let store = BanyanStore::new(payload_blob_config("blobs"), ActoRef::blackhole()).await?;
+    let stream_nr = store
+        .append(app_id(), vec![(tags!("a"), Payload::compact(&"y")?)])
+        .await?[0]
+        .2;
+    store
+        .tombstone_stream(store.node_id().stream(stream_nr), "test".into())
+        .await?;
+
+    let large = Payload::compact(&"x".repeat(4096))?;
+    let cid = {
+        let mut tmp = store.ipfs().create_temp_pin()?;
+        store.add(&mut tmp, large.as_slice())?.0
+    };
+    assert!(store.append(app_id(), vec![(tags!("a"), large)]).await.is_err());
+    assert!(!store.is_payload_blob_pinned(&cid)?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn append_returns_published_root() -> Result<()> {
+    let store = BanyanStore::test("append_root").await?;
+    let stream_nr = StreamNr::from(0);
+    let events = |n: u64| {
+        (0..n)
+            .map(|i| (tags!("a"), Payload::compact(&i).unwrap()))
+            .collect::<Vec<_>>()
+    };
+    store.append0(stream_nr, app_id(), Timestamp::now(), events(3)).await?;
+    let meta = store.append0(stream_nr, app_id(), Timestamp::now(), events(4)).await?;
+    assert_eq!(meta.last_offset(), meta.min_offset().increase(3).unwrap());
+
+    let header = store.data.forest.store().get(&meta.root())?;
+    let header: AxTreeHeader = DagCborCodec.decode(&header)?;
+    let tree = store.data.forest.load_tree(Secrets::default(), header.root)?;
+    assert_eq!(tree.count(), u64::from(meta.last_offset()) + 1);
+    let appended = store
+        .data
+        .forest
+        .iter_filtered(&tree, AllQuery)
+        .filter_map(|item| {
+            let (offset, key, payload) = item.unwrap();
+            (offset >= u64::from(meta.min_offset())).then_some((key.lamport(), payload))
+        })
+        .collect::<Vec<_>>();
+    let expected = events(4)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, payload))| (meta.min_lamport() + i as u64, payload))
+        .collect::<Vec<_>>();
+    assert_eq!(appended, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn append_idempotent_deduplicates_retries() -> Result<()> {
+    let store = BanyanStore::test("append_idempotent").await?;
+    let stream_nr = StreamNr::from(0);
+    let stream_id = store.node_id().stream(stream_nr);
+    let events = |n: u64| {
+        (0..n)
+            .map(|i| (tags!("a"), Payload::compact(&i).unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let token = [1u8; 32];
+
+    let meta = store.append_idempotent(stream_nr, app_id(), events(3), token).await?;
+    let retry = store.append_idempotent(stream_nr, app_id(), events(3), token).await?;
+    assert_eq!(retry, meta);
+    assert_eq!(store.tree_stats(stream_id)?.events, 3);
+
+    // concurrent retries of a new batch append it once
+    let (a, b) = future::join(
+        store.append_idempotent(stream_nr, app_id(), events(2), [2u8; 32]),
+        store.append_idempotent(stream_nr, app_id(), events(2), [2u8; 32]),
+    )
+    .await;
+    assert_eq!(a?, b?);
+    assert_eq!(store.tree_stats(stream_id)?.events, 5);
+
+    // appends without a token are not affected
+    store.append0(stream_nr, app_id(), Timestamp::now(), events(1)).await?;
+    assert_eq!(store.tree_stats(stream_id)?.events, 6);
+    Ok(())
+}
+
+#[tokio::test]
+async fn append_idempotent_appends_again_after_expiry() -> Result<()> {
+    let cfg = SwarmConfig {
+        append_token_ttl: Duration::ZERO,
+        ..SwarmConfig::test("append_token_expiry")
+    };
+    let store = BanyanStore::new(cfg, ActoRef::blackhole()).await?;
+    let stream_nr = StreamNr::from(0);
+    let events = vec![(tags!("a"), Payload::compact(&0)?)];
+    let token = [3u8; 32];
+
+    let first = store
+        .append_idempotent(stream_nr, app_id(), events.clone(), token)
+        .await?;
+    let second = store.append_idempotent(stream_nr, app_id(), events, token).await?;
+    assert_eq!(second.min_offset(), first.last_offset().succ());
+    assert!(second.min_lamport() > first.min_lamport());
+    assert_eq!(store.tree_stats(store.node_id().stream(stream_nr))?.events, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn tombstoned_stream_is_purged_after_retention() -> Result<()> {
+    let store = BanyanStore::test("tombstone").await?;
+    let stream_nr = StreamNr::from(5);
+    let stream_id = store.node_id().stream(stream_nr);
+    let events = vec![(tags!("a"), Payload::null()); 3];
+    store
+        .append0(stream_nr, app_id(), Timestamp::now(), events.clone())
+        .await?;
+
+    let tombstone = store.tombstone_stream(stream_id, "decommissioned".into()).await?;
+    assert_eq!(tombstone.offset, Offset::from(3));
+    let err = store
+        .append0(stream_nr, app_id(), Timestamp::now(), events.clone())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("tombstoned"), "{}", err);
+    assert!(store.tombstone_stream(stream_id, "again".into()).await.is_err());
+
+    // until the retention has passed the tombstone can be replicated by other nodes
+    let tree = store.lock().published_tree(stream_id).unwrap();
+    let event = store.read_tombstone(stream_id, tree.tree())?.unwrap();
+    assert_eq!(event.reason, "decommissioned");
+    assert!(event.verify());
+    assert!(!store.tag_stats(Some(stream_id)).await?.is_empty());
+    assert!(store.data.tag_stats.lock().contains_key(&stream_id));
+
+    assert_eq!(store.purge_due_tombstones(tombstone.purge_at)?, 1);
+    assert!(store.tombstones()[&stream_id].purged);
+    assert!(!store.has_stream(stream_id));
+    assert!(!store.data.tag_stats.lock().contains_key(&stream_id));
+    assert!(store.ipfs().resolve(StreamAlias::from(stream_id))?.is_none());
+    assert_eq!(
+        store.offsets().present().offset(stream_id),
+        OffsetOrMin::from(Offset::from(3))
+    );
+    assert!(store
+        .append0(stream_nr, app_id(), Timestamp::now(), events)
+        .await
+        .is_err());
+
+    // a restart does not bring the stream back
+    let mut state = store.lock();
+    state.load_known_streams(false)?;
+    assert!(!state.has_stream(stream_id));
+    Ok(())
+}
+
+#[tokio::test]
+async fn lazy_stream_loading() -> Result<()> {
+    let store = BanyanStore::test("lazy").await?;
+    let stream_nr = StreamNr::from(5);
+    let stream_id = store.node_id().stream(stream_nr);
+    store
+        .append0(
+            stream_nr,
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("a"), Payload::null()); 3],
+        )
+        .await?;
+
+    // emulate a restart by forgetting the stream
+    {
+        let mut state = store.lock();
+        state.own_streams.remove(&stream_nr);
+        state.load_known_streams(true)?;
+        assert!(!state.own_streams.contains_key(&stream_nr));
+        assert!(state.has_stream(stream_id));
+        assert_eq!(state.root_map()[&stream_id].1, Offset::from(2));
+        assert_eq!(
+            state.compute_swarm_offsets().present.offset(stream_id),
+            Offset::from(2).into()
+        );
+        assert!(state.local_stream_nrs().contains(&stream_nr));
+    }
+
+    // the first access loads it
+    let tree = store.tree_stream(stream_id).next().await.unwrap();
+    assert_eq!(tree.count(), 3);
+    let state = store.lock();
+    assert!(state.own_streams.contains_key(&stream_nr));
+    assert!(state.dormant_streams.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn snapshot_does_not_see_later_appends() -> Result<()> {
+    let store = BanyanStore::test("snapshot").await?;
+    let stream_nr = StreamNr::from(5);
+    let stream_id = store.node_id().stream(stream_nr);
+    let append = |n: usize| {
+        store.append0(
+            stream_nr,
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("a"), Payload::null()); n],
+        )
+    };
+    append(3).await?;
+    let snapshot = store.snapshot();
+    append(2).await?;
+
+    assert_eq!(snapshot.offsets().offset(stream_id), Offset::from(2).into());
+    let events = store
+        .stream_filtered_chunked_snapshot(&snapshot, stream_id, 0..=u64::MAX, AllQuery)
+        .map_ok(|chunk| chunk.data.len())
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(events.into_iter().sum::<usize>(), 3);
+
+    // the snapshot is independent of the store
+    let moved = tokio::spawn(async move { snapshot.tree(stream_id).map(|tree| tree.count()) }).await?;
+    assert_eq!(moved, Some(3));
+    assert_eq!(store.snapshot().offsets().offset(stream_id), Offset::from(4).into());
+    Ok(())
+}
+
+/// Exports the stream from `from`, imports it into a fresh store and checks that all its events
+/// are found there.
+async fn export_import_roundtrip(from: &BanyanStore, stream_nr: StreamNr) -> Result<BanyanStore> {
+    let stream_id = from.node_id().stream(stream_nr);
+    let mut car = Vec::new();
+    let stats = from.export_stream(stream_id, &mut car)?;
+    assert!(stats.blocks > 2, "{:?}", stats);
+
+    let to = BanyanStore::test("import").await?;
+    assert_eq!(to.import_stream(&car[..])?, stream_id);
+    let expected = from.lock().published_tree(stream_id).unwrap();
+    let imported = to.lock().published_tree(stream_id).unwrap();
+    assert_eq!(imported.root(), expected.root());
+    assert_eq!(imported.lamport(), expected.lamport());
+    assert_eq!(
+        to.data.offsets.project(SwarmOffsets::present).offset(stream_id),
+        expected.offset().into()
+    );
+    let events = |store: &BanyanStore, tree: &PublishedTree| {
+        store
+            .data
+            .forest
+            .iter_filtered(tree.tree(), AllQuery)
+            .map(|item| item.map(|(offset, _, payload)| (offset, payload)))
+            .collect::<Result<Vec<_>>>()
+    };
+    assert_eq!(events(&to, &imported)?, events(from, &expected)?);
+
+    // importing the same tree again is rejected, it is not newer
+    assert!(to.import_stream(&car[..]).is_err());
+    Ok(to)
+}
+
+#[tokio::test]
+async fn export_import_stream() -> Result<()> {
+    let store = BanyanStore::test("export").await?;
+    let stream_nr = StreamNr::from(3);
+    // many small appends to get a tree with several branches
+    for i in 0..100u64 {
+        let events = (0..10)
+            .map(|j| (tags!("a"), Payload::compact(&(i * 10 + j)).unwrap()))
+            .collect();
+        store.append0(stream_nr, app_id(), Timestamp::now(), events).await?;
+    }
+    export_import_roundtrip(&store, stream_nr).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn export_import_pruned_stream() -> Result<()> {
+    let store = BanyanStore::test("export_pruned").await?;
+    let stream_nr = StreamNr::from(3);
+    for i in 0..100u64 {
+        let events = (0..10)
+            .map(|j| (tags!("a"), Payload::compact(&(i * 10 + j)).unwrap()))
+            .collect();
+        store.append0(stream_nr, app_id(), Timestamp::now(), events).await?;
+    }
+    // drop the leaves of the first half, leaving only their index
+    let stream = store.get_or_create_own_stream(stream_nr)?;
+    let mut guard = stream.lock().await;
+    store.transform_stream(&mut guard, |txn, tree| {
+        txn.pack(tree)?;
+        txn.retain(tree, &OffsetQuery::from(500..))
+    })?;
+    drop(guard);
+
+    let imported = export_import_roundtrip(&store, stream_nr).await?;
+    let stream_id = store.node_id().stream(stream_nr);
+    let tree = imported.lock().published_tree(stream_id).unwrap();
+    assert_eq!(tree.tree().count(), 1000);
+    Ok(())
+}
+
+#[tokio::test]
+async fn tag_stats_count_overlapping_tags() -> Result<()> {
+    let store = BanyanStore::test("tag_stats").await?;
+    let stream_nr = StreamNr::from(3);
+    let stream_id = store.node_id().stream(stream_nr);
+    assert!(store.tag_stats(Some(stream_id)).await?.is_empty());
+
+    let mut lamports = vec![];
+    for tags in [tags!("a", "b"), tags!("b", "c"), tags!("a"), tags!("b")] {
+        let meta = store
+            .append0(stream_nr, app_id(), Timestamp::now(), vec![(tags, Payload::null())])
+            .await?;
+        lamports.push(meta.min_lamport);
+    }
+    let app_tags = |stats: Vec<TagStat>| {
+        stats
+            .into_iter()
+            .filter_map(|stat| {
+                let tag = stat.tag.to_app()?.to_string();
+                Some((tag, stat.events, stat.first_lamport, stat.last_lamport))
+            })
+            .collect::<Vec<_>>()
+    };
+    let stats = store.tag_stats(Some(stream_id)).await?;
+    assert!(stats.iter().all(|stat| stat.stream_id == stream_id));
+    let app_id_tag = ScopedTag::internal(tag!("app_id:") + app_id().as_str());
+    let app_id_stat = stats.iter().find(|stat| stat.tag == app_id_tag).unwrap();
+    assert_eq!(app_id_stat.events, 4);
+    assert_eq!(
+        app_tags(stats),
+        vec![
+            ("a".to_owned(), 2, lamports[0], lamports[2]),
+            ("b".to_owned(), 3, lamports[0], lamports[3]),
+            ("c".to_owned(), 1, lamports[1], lamports[1]),
+        ]
+    );
+
+    // all streams without a stream id
+    let other = store.node_id().stream(StreamNr::from(4));
+    store
+        .append0(
+            4.into(),
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("a"), Payload::null())],
+        )
+        .await?;
+    let all = store.tag_stats(None).await?;
+    assert_eq!(all.iter().filter(|stat| stat.stream_id == stream_id).count(), 4);
+    assert_eq!(app_tags(store.tag_stats(Some(other)).await?).len(), 1);
+    assert!(all.iter().any(|stat| stat.stream_id == other));
+    Ok(())
+}
+
+#[tokio::test]
+async fn tag_stats_are_recomputed_after_append() -> Result<()> {
+    let store = BanyanStore::test("tag_stats").await?;
+    let stream_nr = StreamNr::from(3);
+    let stream_id = store.node_id().stream(stream_nr);
+    let count = |stats: Vec<TagStat>, tag: Tag| {
+        stats
+            .into_iter()
+            .find(|stat| stat.tag == ScopedTag::app(tag.clone()))
+            .map(|stat| stat.events)
+    };
+
+    store
+        .append0(
+            stream_nr,
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("a"), Payload::null())],
+        )
+        .await?;
+    assert_eq!(count(store.tag_stats(Some(stream_id)).await?, tag!("a")), Some(1));
+    // served from the cache: a forged entry for the current root is returned as is
+    let forged = TagStat {
+        stream_id,
+        tag: ScopedTag::app(tag!("forged")),
+        events: 42,
+        first_lamport: 0.into(),
+        last_lamport: 0.into(),
+    };
+    store
+        .data
+        .tag_stats
+        .lock()
+        .get_mut(&stream_id)
+        .expect("stats are cached")
+        .1 = Arc::new(vec![forged.clone()]);
+    assert_eq!(store.tag_stats(Some(stream_id)).await?, vec![forged]);
+
+    store
+        .append0(
+            stream_nr,
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("a"), Payload::null()), (tags!("z"), Payload::null())],
+        )
+        .await?;
+    let stats = store.tag_stats(Some(stream_id)).await?;
+    assert_eq!(count(stats.clone(), tag!("a")), Some(2));
+    assert_eq!(count(stats, tag!("z")), Some(1));
+    Ok(())
+}
+
+#[tokio::test]
+async fn tree_stats_follow_append_compact_prune() -> Result<()> {
+    let store = BanyanStore::test("tree_stats").await?;
+    let stream_nr = StreamNr::from(3);
+    let stream_id = store.node_id().stream(stream_nr);
+    assert!(store.tree_stats(stream_id).is_err());
+    let append = |from: u64| {
+        let store = store.clone();
+        async move {
+            for i in from..from + 50 {
+                let events = (0..10)
+                    .map(|j| (tags!("a"), Payload::compact(&(i * 10 + j)).unwrap()))
+                    .collect();
+                store.append0(stream_nr, app_id(), Timestamp::now(), events).await?;
+            }
+            anyhow::Ok(())
+        }
+    };
+
+    append(0).await?;
+    let first = store.tree_stats(stream_id)?;
+    assert_eq!(first.events, 500);
+    assert_eq!(first.packed_events + first.unpacked_events, 500);
+    assert_eq!(first.pruned_leaves, 0);
+    assert!(first.leaves > 0 && first.bytes > 0, "{:?}", first);
+
+    append(50).await?;
+    let appended = store.tree_stats(stream_id)?;
+    assert_eq!(appended.events, 1000);
+    assert!(appended.leaves >= first.leaves, "{:?}", appended);
+    assert!(appended.bytes > first.bytes, "{:?}", appended);
+    assert!(!appended.packed, "{:?}", appended);
+
+    let stream = store.get_or_create_own_stream(stream_nr)?;
+    let mut guard = stream.lock().await;
+    store.transform_stream(&mut guard, |txn, tree| txn.pack(tree))?;
+    drop(guard);
+    let packed = store.tree_stats(stream_id)?;
+    assert_eq!(packed.events, 1000);
+    assert!(packed.packed, "{:?}", packed);
+    assert!(packed.leaves <= appended.leaves, "{:?}", packed);
+    assert!(packed.unpacked_events <= appended.unpacked_events, "{:?}", packed);
+    assert!(packed.packed_events > 0, "{:?}", packed);
+
+    let mut guard = stream.lock().await;
+    store.transform_stream(&mut guard, |txn, tree| txn.retain(tree, &OffsetQuery::from(500..)))?;
+    drop(guard);
+    let pruned = store.tree_stats(stream_id)?;
+    assert_eq!(pruned.events, 1000);
+    assert_eq!(pruned.leaves, packed.leaves);
+    assert!(pruned.pruned_leaves > 0, "{:?}", pruned);
+    assert!(pruned.blocks < packed.blocks, "{:?}", pruned);
+    assert!(pruned.bytes < packed.bytes, "{:?}", pruned);
+
+    let total = store.store_stats()?;
+    assert!(total.streams >= 1, "{:?}", total);
+    assert!(total.trees.events >= pruned.events, "{:?}", total);
+    assert!(total.trees.pruned_leaves >= pruned.pruned_leaves, "{:?}", total);
+    assert!(total.store_blocks >= total.trees.blocks, "{:?}", total);
+    assert!(total.store_bytes >= total.trees.bytes, "{:?}", total);
+    Ok(())
+}
+
+/// Offsets of the events with the given payload, in the order they were received
+fn offsets_of(events: &[(u64, Payload)], payload: &Payload) -> Vec<u64> {
+    events
+        .iter()
+        .filter(|(_, p)| p == payload)
+        .map(|(offset, _)| *offset)
+        .collect()
+}
+
+#[tokio::test]
+async fn bounded_and_reverse_ordered_queries() -> Result<()> {
+    crate::util::setup_logger();
+    let store = BanyanStore::new(
+        SwarmConfig::test_with_routing(
+            "bounded",
+            vec![EventRoute::new(TagExpr::from_str("'later'")?, "later".to_string())],
+        ),
+        ActoRef::blackhole(),
+    )
+    .await?;
+    let done_payload = Payload::compact(&"done")?;
+    let later_payload = Payload::compact(&"later")?;
+    let done = store
+        .append(app_id(), vec![(tags!("done"), done_payload.clone()); 3])
+        .await?[0]
+        .2;
+    let later = store
+        .append(app_id(), vec![(tags!("later"), later_payload.clone()); 2])
+        .await?[0]
+        .2;
+    let done = store.node_id().stream(done);
+    let later = store.node_id().stream(later);
+    let mut upper = OffsetMap::empty();
+    // already present
+    upper.update(done, Offset::from(1));
+    // only present once more events arrive
+    upper.update(later, Offset::from(3));
+
+    let bounded = store
+        .stream_filtered_stream_ordered_bounded(AllQuery, upper.clone())
+        .map_ok(|(offset, _, payload)| (offset, payload))
+        .try_collect::<Vec<_>>();
+    pin_mut!(bounded);
+    assert!(tokio::time::timeout(Duration::from_millis(500), &mut bounded)
+        .await
+        .is_err());
+    store
+        .append(app_id(), vec![(tags!("later"), later_payload.clone()); 3])
+        .await?;
+    let events = tokio::time::timeout(Duration::from_secs(5), bounded).await??;
+    assert_eq!(offsets_of(&events, &done_payload), vec![0, 1]);
+    assert_eq!(offsets_of(&events, &later_payload), vec![0, 1, 2, 3]);
+
+    let events = store
+        .stream_filtered_stream_ordered_reverse(AllQuery, upper)
+        .map_ok(|(offset, _, payload)| (offset, payload))
+        .try_filter(|(_, payload)| future::ready(*payload == done_payload || *payload == later_payload))
+        .take(6)
+        .try_collect::<Vec<_>>();
+    let events = tokio::time::timeout(Duration::from_secs(5), events).await??;
+    assert_eq!(offsets_of(&events, &done_payload), vec![1, 0]);
+    assert_eq!(offsets_of(&events, &later_payload), vec![3, 2, 1, 0]);
+    Ok(())
+}
+
+/// Secrets for the streams of one node, all other streams are not encrypted
+#[derive(Debug)]
+struct NodeSecrets {
+    node_id: NodeId,
+    secrets: Option<Secrets>,
+}
+
+impl SecretProvider for NodeSecrets {
+    fn secrets(&self, stream_id: StreamId) -> Option<Secrets> {
+        if stream_id.node_id() == self.node_id {
+            self.secrets.clone()
+        } else {
+            Some(Secrets::default())
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn encrypted_streams_are_replicated_without_secret() -> Result<()> {
+    crate::util::setup_logger();
+    let keypair = KeyPair::generate();
+    let node_id = NodeId::from(keypair);
+    let secrets = Secrets::new(Default::default(), chacha20::Key::from([42; 32]));
+    let a = BanyanStore::new(
+        SwarmConfig {
+            keypair: Some(keypair),
+            secret_provider: Some(Arc::new(NodeSecrets {
+                node_id,
+                secrets: Some(secrets),
+            })),
+            ..SwarmConfig::test("a")
+        },
+        ActoRef::blackhole(),
+    )
+    .await?;
+    let b = BanyanStore::new(
+        SwarmConfig {
+            secret_provider: Some(Arc::new(NodeSecrets { node_id, secrets: None })),
+            ..SwarmConfig::test("b")
+        },
+        ActoRef::blackhole(),
+    )
+    .await?;
+    b.ipfs()
+        .clone()
+        .add_address(a.ipfs().local_peer_id(), a.ipfs().listeners()[0].clone());
+
+    let payload = Payload::compact(&"secret")?;
+    let stream_nr = a.append(app_id(), vec![(tags!("a"), payload.clone()); 3]).await?[0].2;
+    let stream_id = a.node_id().stream(stream_nr);
+    assert_eq!(own_payloads(&a, stream_nr, 3).await, vec![payload; 3]);
+
+    let mut offsets = b.data.offsets.new_observer();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while let Some(offsets) = offsets.next().await {
+            if offsets.present.offset(stream_id) == Offset::from(2).into() {
+                break;
+            }
+        }
+    })
+    .await?;
+
+    // all blocks of the stream are there, but its events cannot be read
+    b.export_stream(stream_id, io::sink())?;
+    let err = b
+        .stream_filtered_chunked(stream_id, 0..=u64::MAX, AllQuery)
+        .try_collect::<Vec<_>>()
+        .await
+        .err()
+        .expect("query must fail without secret");
+    assert!(err.to_string().contains("no secret available"), "{}", err);
+
+    // queries skip the stream instead of failing
+    let events = EventStore::new(b.clone())
+        .bounded_forward(
+            &TagExpr::from_str("allEvents")?,
+            OffsetMap::empty(),
+            b.offsets().present(),
+            None,
+        )
+        .await?
+        .collect::<Vec<_>>()
+        .await;
+    assert!(events.iter().all(|ev| ev.key.stream != stream_id));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn payload_blobs_are_fetched_from_peers() -> Result<()> {
+    crate::util::setup_logger();
+    let a = BanyanStore::new(payload_blob_config("a"), ActoRef::blackhole()).await?;
+    let b = BanyanStore::test("b").await?;
+    let large = Payload::compact(&"x".repeat(4096))?;
+    let metas = a.append(app_id(), vec![(tags!("a"), large.clone())]).await?;
+    let reference = own_payloads(&a, metas[0].2, 1).await.remove(0);
+
+    b.ipfs()
+        .clone()
+        .add_address(a.ipfs().local_peer_id(), a.ipfs().listeners()[0].clone());
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !b.ipfs().peers().contains(&a.ipfs().local_peer_id()) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    assert_eq!(b.inline_payload(reference).await?, large);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn partial_replication_by_tags() -> Result<()> {
+    crate::util::setup_logger();
+    let a = BanyanStore::new(
+        SwarmConfig::test_with_routing(
+            "a",
+            vec![EventRoute::new(TagExpr::from_str("'other'")?, "other".to_string())],
+        ),
+        ActoRef::blackhole(),
+    )
+    .await?;
+    let b = BanyanStore::new(
+        SwarmConfig {
+            replication: ReplicationConfig::only_tags(TagExpr::from_str("'keep'")?)?,
+            ..SwarmConfig::test("b")
+        },
+        ActoRef::blackhole(),
+    )
+    .await?;
+    b.ipfs()
+        .clone()
+        .add_address(a.ipfs().local_peer_id(), a.ipfs().listeners()[0].clone());
+
+    let kept = a.append(app_id(), vec![(tags!("keep"), Payload::null()); 3]).await?[0].2;
+    let other = a.append(app_id(), vec![(tags!("other"), Payload::null()); 3]).await?[0].2;
+    let kept = a.node_id().stream(kept);
+    let other = a.node_id().stream(other);
+    assert_ne!(kept, other);
+
+    let mut offsets = b.data.offsets.new_observer();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while let Some(offsets) = offsets.next().await {
+            if offsets.present.offset(kept) == Offset::from(2).into()
+                && offsets.replication_target.offset(other) == Offset::from(2).into()
+            {
+                break;
+            }
+        }
+    })
+    .await?;
+
+    let offsets = b.data.offsets.project(Clone::clone);
+    // offsets of the ignored stream are observed, but its events are not replicated
+    assert_eq!(offsets.present.offset(other), OffsetOrMin::MIN);
+    assert!(b.lock().published_tree(other).is_none());
+    assert!(b.lock().published_tree(kept).is_some());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn topic_migration() -> Result<()> {
+    crate::util::setup_logger();
+    let old = BanyanStore::new(
+        SwarmConfig {
+            topic: "old".into(),
+            ..SwarmConfig::test("old")
+        },
+        ActoRef::blackhole(),
+    )
+    .await?;
+    let migrated = BanyanStore::new(
+        SwarmConfig {
+            topic: "new".into(),
+            previous_topics: vec!["old".into()],
+            ..SwarmConfig::test("migrated")
+        },
+        ActoRef::blackhole(),
+    )
+    .await?;
+    migrated
+        .ipfs()
+        .clone()
+        .add_address(old.ipfs().local_peer_id(), old.ipfs().listeners()[0].clone());
+
+    let from_old = old.append(app_id(), vec![(tags!("a"), Payload::null()); 3]).await?[0].2;
+    let from_old = old.node_id().stream(from_old);
+    let from_migrated = migrated
+        .append(app_id(), vec![(tags!("a"), Payload::null()); 3])
+        .await?[0]
+        .2;
+    let from_migrated = migrated.node_id().stream(from_migrated);
+
+    // the migrated node still receives the gossip published on the previous topic
+    let mut offsets = migrated.data.offsets.new_observer();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while let Some(offsets) = offsets.next().await {
+            if offsets.present.offset(from_old) == Offset::from(2).into() {
+                break;
+            }
+        }
+    })
+    .await?;
+    let previous = migrated.previous_topics().get();
+    assert_eq!(previous.len(), 1);
+    assert_eq!(previous[0].topic, "old");
+    assert!(previous[0].messages > 0);
+    assert!(previous[0].last_received.is_some());
+    assert!(previous[0].remaining > Duration::ZERO);
+
+    // but it only publishes on the new topic, which the old node doesn’t listen to
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let offsets = old.data.offsets.project(Clone::clone);
+    assert_eq!(offsets.present.offset(from_migrated), OffsetOrMin::MIN);
+    assert_eq!(offsets.replication_target.offset(from_migrated), OffsetOrMin::MIN);
+    assert!(old.previous_topics().get().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_only_store_does_not_create_own_streams() -> Result<()> {
+    let config = SwarmConfig {
+        read_only: true,
+        ..SwarmConfig::test("read_only")
+    };
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    // not even the stream mappings are published at startup
+    assert!(store.lock().own_streams.is_empty());
+
+    let events = vec![(tags!("a"), Payload::null())];
+    let err = store.append(app_id!("test"), events.clone()).await.unwrap_err();
+    assert!(err.is::<ReadOnlyError>(), "{}", err);
+    let err = store
+        .append0(StreamNr::from(1), app_id!("test"), Timestamp::now(), events)
+        .await
+        .unwrap_err();
+    assert!(err.is::<ReadOnlyError>(), "{}", err);
+    assert!(store.lock().own_streams.is_empty());
+
+    // changing the settings at runtime does not start tasks that would rewrite own streams
+    store.restart_compaction(CompactionConfig::new(Duration::from_millis(10)));
+    let mut settings = store.settings();
+    settings.ephemeral_events = EphemeralEventsConfig::from(btreemap! {
+        DEFAULT_STREAM_NAME.to_string() => Default::default(),
+    });
+    store.update_settings(settings)?;
+    assert!(!store.lock().has_task("compaction"));
+    assert!(!store.lock().has_task("prune_events"));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn stream_names_are_persisted_and_assigned_once() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config = SwarmConfig {
+        index_store: Some(dir.path().join("index")),
+        db_path: Some(dir.path().join("db")),
+        ..SwarmConfig::test("stream_names")
+    };
+    let store = BanyanStore::new(config.clone(), ActoRef::blackhole()).await?;
+
+    // concurrent first lookups agree on one number
+    let lookups = (0..10)
+        .map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move { store.stream_for_name("sensors") })
+        })
+        .collect::<Vec<_>>();
+    let mut assigned = vec![];
+    for lookup in lookups {
+        assigned.push(lookup.await??);
+    }
+    let sensors = assigned[0];
+    assert!(assigned.iter().all(|nr| *nr == sensors), "{:?}", assigned);
+    assert!(u64::from(sensors) > 3);
+    let alarms = store.stream_for_name("alarms")?;
+    assert_ne!(alarms, sensors);
+    assert!(store.stream_for_name("discovery").is_err());
+    drop(store);
+
+    // routes configured after the names were registered get fresh numbers
+    let routed = SwarmConfig {
+        event_routes: vec![EventRoute::new(
+            TagExpr::from_str("'routed'").unwrap(),
+            "routed".to_string(),
+        )],
+        ..config
+    };
+    let store = BanyanStore::new(routed, ActoRef::blackhole()).await?;
+    assert_eq!(store.stream_for_name("sensors")?, sensors);
+    assert_eq!(
+        store.stream_names()?,
+        btreemap! { "alarms".to_owned() => alarms, "sensors".to_owned() => sensors }
+    );
+    let routed = store.routing_table().stream_mapping["routed"];
+    assert!(routed > sensors.max(alarms), "{} {} {}", routed, sensors, alarms);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn startup_with_missing_blocks() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config = SwarmConfig {
+        index_store: Some(dir.path().join("index")),
+        db_path: Some(dir.path().join("db")),
+        keypair: Some(KeyPair::generate()),
+        ..SwarmConfig::test("missing_blocks")
+    };
+    // not the default stream, which gets the stream mappings appended on startup
+    let stream_nr = StreamNr::from(5);
+    let store = BanyanStore::new(config.clone(), ActoRef::blackhole()).await?;
+    let stream_id = store.node_id().stream(stream_nr);
+    for i in 0..100 {
+        let payload = Payload::from_json_str(&i.to_string()).unwrap();
+        store
+            .append0(stream_nr, app_id(), Timestamp::now(), vec![(tags!("missing"), payload)])
+            .await?;
+    }
+    let tree = store.lock().published_tree(stream_id).unwrap();
+    let tree_root = tree.tree().link().expect("tree is not empty");
+
+    // a peer keeps a complete copy of the stream
+    let peer = BanyanStore::test("missing_blocks_peer").await?;
+    peer.ipfs()
+        .clone()
+        .add_address(store.ipfs().local_peer_id(), store.ipfs().listeners()[0].clone());
+    let mut offsets = peer.data.offsets.new_observer();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while let Some(offsets) = offsets.next().await {
+            if offsets.present.offset(stream_id) == tree.offset().into() {
+                break;
+            }
+        }
+    })
+    .await?;
+    drop(store);
+
+    // delete the children of the root, which is still loaded on startup
+    let conn = rusqlite::Connection::open(dir.path().join("db"))?;
+    let deleted = conn.execute(
+        "DELETE FROM blocks WHERE block_id IN \
+         (SELECT child_id FROM refs WHERE parent_id = (SELECT id FROM cids WHERE cid = ?))",
+        [Cid::from(tree_root).to_bytes()],
+    )?;
+    assert!(deleted > 0);
+    drop(conn);
+
+    assert!(BanyanStore::new(config.clone(), ActoRef::blackhole()).await.is_err());
+
+    // an own stream is never dropped, since starting it over would fork it
+    let lenient = SwarmConfig {
+        validation_mode: ValidationMode::Lenient,
+        ..config.clone()
+    };
+    let store = BanyanStore::new(lenient, ActoRef::blackhole()).await?;
+    let report = store.startup_report();
+    assert_eq!(report.mode, ValidationMode::Lenient);
+    assert_eq!(report.incomplete_streams.keys().collect::<Vec<_>>(), vec![&stream_id]);
+    assert!(matches!(
+        &report.incomplete_streams[&stream_id],
+        StreamRecovery::RepairScheduled { offset, .. } if *offset == tree.offset()
+    ));
+    assert_eq!(
+        store.ipfs().resolve(StreamAlias::from(stream_id))?,
+        Some(Cid::from(tree.root()))
+    );
+    assert_eq!(store.offsets().present().get(stream_id), Some(tree.offset()));
+    let err = store
+        .append0(
+            stream_nr,
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("missing"), Payload::null())],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<IncompleteStreamError>(),
+        Some(&IncompleteStreamError { stream_id })
+    );
+    drop(store);
+
+    // the missing blocks are fetched from the peer once it connects
+    let repair = SwarmConfig {
+        validation_mode: ValidationMode::Repair,
+        ..config.clone()
+    };
+    let store = BanyanStore::new(repair, ActoRef::blackhole()).await?;
+    assert!(matches!(
+        &store.startup_report().incomplete_streams[&stream_id],
+        StreamRecovery::RepairScheduled { offset, .. } if *offset == tree.offset()
+    ));
+    store
+        .ipfs()
+        .clone()
+        .add_address(peer.ipfs().local_peer_id(), peer.ipfs().listeners()[0].clone());
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while !matches!(
+            store.startup_report().incomplete_streams[&stream_id],
+            StreamRecovery::Repaired { .. }
+        ) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+    let events = store
+        .stream_filtered_chunked(stream_id, 0..=u64::MAX, AllQuery)
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(events.iter().map(|chunk| chunk.data.len()).sum::<usize>(), 100);
+    let meta = store
+        .append0(
+            stream_nr,
+            app_id(),
+            Timestamp::now(),
+            vec![(tags!("missing"), Payload::null())],
+        )
+        .await?;
+    assert_eq!(meta.min_offset(), tree.offset().succ());
+    drop(store);
+
+    // and is complete from now on
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    assert!(store.startup_report().incomplete_streams.is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn superseded_roots_are_kept_for_the_grace_period() -> Result<()> {
+    let config = SwarmConfig {
+        gc_grace_period: Some(Duration::from_secs(1)),
+        ..SwarmConfig::test("gc_grace")
+    };
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    let stream_id = store.node_id().stream(0.into());
+    store.append(app_id(), vec![(tags!("a"), Payload::null())]).await?;
+    let first = store.ipfs().resolve(StreamAlias::from(stream_id))?.unwrap();
+    store.append(app_id(), vec![(tags!("a"), Payload::null())]).await?;
+
+    let kept = store.gc_grace_roots();
+    assert!(
+        kept.iter()
+            .any(|root| root.stream_id == stream_id && root.root == first),
+        "{:?}",
+        kept
+    );
+
+    // the roots superseded in quick succession share one pin
+    for _ in 0..20 {
+        store.append(app_id(), vec![(tags!("a"), Payload::null())]).await?;
+    }
+    assert!(store.gc_grace_roots().len() >= 20);
+    assert!(store.gc_grace_pins() <= 2, "{} pins", store.gc_grace_pins());
+
+    // a root is kept for at most two grace periods
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(store.gc_grace_roots(), vec![]);
+    assert_eq!(store.gc_grace_pins(), 0);
+
+    let config = SwarmConfig {
+        gc_grace_period: Some(Duration::ZERO),
+        ..SwarmConfig::test("no_gc_grace")
+    };
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    store.append(app_id(), vec![(tags!("a"), Payload::null())]).await?;
+    store.append(app_id(), vec![(tags!("a"), Payload::null())]).await?;
+    assert_eq!(store.gc_grace_roots(), vec![]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn event_routes_change_at_runtime() -> Result<()> {
+    let store = BanyanStore::test_with_routing(
+        "routes_runtime",
+        vec![
+            EventRoute::new(TagExpr::from_str("'a'")?, "first".to_string()),
+            // never applies, events tagged 'a' are taken by the route before it
+            EventRoute::new(TagExpr::from_str("'a' & 'b'")?, "second".to_string()),
+            EventRoute::new(TagExpr::from_str("'x' | 'y'")?, "before".to_string()),
+        ],
+    )
+    .await?;
+    let stream_nr = |name: &str| store.routing_table().stream_mapping.get(name).copied();
+    let metas = store.append(app_id(), vec![(tags!("a", "b"), Payload::null())]).await?;
+    assert_eq!(Some(metas[0].2), stream_nr("first"));
+
+    let invalid = vec![EventRoute::new(
+        TagExpr::from_str("'x' & isLocal")?,
+        "after".to_string(),
+    )];
+    assert!(store.update_event_routes(invalid).await.is_err());
+    assert_eq!(stream_nr("after"), None);
+
+    let appends = {
+        let store = store.clone();
+        tokio::spawn(async move {
+            let mut batches = vec![];
+            for _ in 0..100 {
+                let events = vec![(tags!("x"), Payload::null()), (tags!("y"), Payload::null())];
+                batches.push(store.append(app_id(), events).await?);
+            }
+            anyhow::Ok(batches)
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let routes = vec![EventRoute::new(TagExpr::from_str("'x' | 'y'")?, "after".to_string())];
+    store.update_event_routes(routes).await?;
+    let batches = appends.await??;
+
+    // each batch went into one stream, never split between the old and the new routes
+    for batch in &batches {
+        let streams = batch.iter().map(|meta| meta.2).collect::<BTreeSet<_>>();
+        assert_eq!(streams.len(), 1, "{:?}", batch);
+    }
+    let after = stream_nr("after");
+    assert!(after.is_some());
+    assert_eq!(Some(batches.last().unwrap()[0].2), after);
+    // the mapping was published before events were routed into the stream
+    let published = store.get_published_mappings(store.node_id()).await?;
+    assert_eq!(published.get("after").copied(), after);
+    // the previous targets keep their numbers, unmatched events go into the default stream
+    assert!(stream_nr("before").is_some());
+    let metas = store.append(app_id(), vec![(tags!("a"), Payload::null())]).await?;
+    assert_eq!(metas[0].2, StreamNr::default());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn chunk_extras_are_computed_from_the_index() -> Result<()> {
+    let store = BanyanStore::test("extras").await?;
+    let tags = [tags!("a"), tags!("a", "b"), tags!("c")];
+    let mut stream_nr = None;
+    for batch in 0..10 {
+        let events = (0..7)
+            .map(|i| (tags[(batch + i) % 3].clone(), Payload::null()))
+            .collect();
+        stream_nr = Some(store.append(app_id(), events).await?[0].2);
+    }
+    let stream_id = store.node_id().stream(stream_nr.unwrap());
+
+    // number of events and distinct tags of each leaf, computed from its keys only
+    let extra = &|index: IndexRef<AxTrees>| match index {
+        IndexRef::Leaf(leaf) => match leaf.keys.summarize().tags {
+            TagsSummary::Complete(tags) => Some((leaf.keys.len(), tags.len())),
+            TagsSummary::Unrestricted => None,
+        },
+        IndexRef::Branch(_) => None,
+    };
+    let chunks = store
+        .stream_filtered_chunked_with(stream_id, 0..=u64::MAX, AllQuery, extra)
+        .take_until_signaled(tokio::time::sleep(Duration::from_secs(1)))
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(chunks.len() > 1, "{} chunks", chunks.len());
+    for chunk in &chunks {
+        let tags = chunk
+            .data
+            .iter()
+            .flat_map(|(_, key, _)| key.tags().as_ref())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(chunk.extra, Some((chunk.data.len(), tags.len())), "{:?}", chunk.range);
+    }
+
+    // the same in reverse, adding up to a full scan
+    let reverse = store
+        .stream_filtered_chunked_reverse_with(stream_id, 0..=u64::MAX, AllQuery, extra)
+        .take_until_signaled(tokio::time::sleep(Duration::from_secs(1)))
+        .try_collect::<Vec<_>>()
+        .await?;
+    let extras = |chunks: &[FilteredChunk<_, Option<(usize, usize)>>]| {
+        chunks.iter().map(|chunk| chunk.extra.unwrap()).collect::<Vec<_>>()
+    };
+    let mut reversed = extras(&reverse);
+    reversed.reverse();
+    assert_eq!(reversed, extras(&chunks));
+    let events = store
+        .stream_filtered_chunked(stream_id, 0..=u64::MAX, AllQuery)
+        .take_until_signaled(tokio::time::sleep(Duration::from_secs(1)))
+        .map_ok(|chunk| chunk.data.len())
+        .try_collect::<Vec<_>>()
+        .await?;
+    let counted = extras(&chunks).iter().map(|(count, _)| count).sum::<usize>();
+    assert_eq!(counted, events.iter().sum::<usize>());
+    Ok(())
+}
+
+#[tokio::test]
+async fn budget_bounds_the_chunks_read() -> Result<()> {
+    let mut config = SwarmConfig::test("budget");
+    config.compaction.interval = Duration::from_secs(100000);
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    let stream_nr = StreamNr::from(1);
+    let stream_id = store.node_id().stream(stream_nr);
+    // without compaction, every append ends up in a leaf of its own
+    for _ in 0..100 {
+        let events = vec![(tags!("a"), Payload::null()); 10];
+        store.append0(stream_nr, app_id(), Timestamp::now(), events).await?;
+    }
+    let all = store
+        .stream_filtered_chunked(stream_id, 0..=u64::MAX, AllQuery)
+        .take_until_signaled(tokio::time::sleep(Duration::from_secs(1)))
+        .map_ok(|chunk| chunk.data.len())
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(all, vec![10; 100]);
+
+    // the streams end on their own once the budget is used up
+    let budget = Some(TakeWhileBudget::new(15));
+    let forward = store
+        .stream_filtered_chunked_budgeted(stream_id, 0..=u64::MAX, AllQuery, budget)
+        .try_collect::<Vec<_>>();
+    let forward = tokio::time::timeout(Duration::from_secs(5), forward).await??;
+    assert_eq!(
+        forward.iter().map(|chunk| chunk.range.clone()).collect::<Vec<_>>(),
+        vec![0..10, 10..20]
+    );
+    let reverse = store
+        .stream_filtered_chunked_reverse_budgeted(stream_id, 0..=u64::MAX, AllQuery, budget)
+        .try_collect::<Vec<_>>();
+    let reverse = tokio::time::timeout(Duration::from_secs(5), reverse).await??;
+    assert_eq!(
+        reverse.iter().map(|chunk| chunk.range.clone()).collect::<Vec<_>>(),
+        vec![990..1000, 980..990]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn resume_from_progress() -> Result<()> {
+    let mut config = SwarmConfig::test("progress");
+    config.compaction.interval = Duration::from_secs(100000);
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    for i in 0..40 {
+        let events = vec![(tags!("a"), Payload::null()); 10];
+        store
+            .append0(StreamNr::from(1 + i % 2), app_id(), Timestamp::now(), events)
+            .await?;
+    }
+    let upper = store.offsets().present();
+    let cadence = ProgressCadence {
+        events: 25,
+        interval: Duration::from_secs(3600),
+    };
+    let read = |lower: OffsetMap| {
+        let items = store
+            .stream_filtered_with_progress(AllQuery, lower, upper.clone(), cadence)
+            .try_collect::<Vec<_>>();
+        async move { anyhow::Ok(tokio::time::timeout(Duration::from_secs(5), items).await??) }
+    };
+    let lamports = |items: &[ProgressItem]| {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                ProgressItem::Event((_, key, _)) => Some(u64::from(key.lamport())),
+                ProgressItem::Progress(_) => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let progress = |item: &ProgressItem| match item {
+        ProgressItem::Progress(progress) => Some(progress.clone()),
+        ProgressItem::Event(_) => None,
+    };
+
+    let full = read(OffsetMap::empty()).await?;
+    assert_eq!(full.last().and_then(progress), Some(upper.clone()));
+    let mut expected = lamports(&full);
+    assert!(expected.len() >= 400);
+    expected.sort_unstable();
+
+    // interrupt the read after the third progress item and resume from there
+    let (idx, checkpoint) = full
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| progress(item).map(|p| (idx, p)))
+        .nth(2)
+        .unwrap();
+    assert!(checkpoint != upper);
+    let resumed = read(checkpoint).await?;
+    assert_eq!(resumed.last().and_then(progress), Some(upper.clone()));
+    let mut combined = lamports(&full[..idx]);
+    combined.extend(lamports(&resumed));
+    combined.sort_unstable();
+    assert_eq!(combined, expected);
+
+    // nothing is left to read beyond the upper bound
+    let done = read(upper.clone()).await?;
+    assert_eq!(done.len(), 1);
+    assert_eq!(progress(&done[0]), Some(upper));
+    Ok(())
+}
+
+#[tokio::test]
+async fn event_store_pending_requests_are_exported() -> Result<()> {
+    let store = BanyanStore::test("pending").await?;
+    // requests are never answered, so they stay pending
+    let events = EventStoreRef::new(|_request| Ok(()));
+    store.register_event_store(events.clone())?;
+    assert!(store.register_event_store(events.clone()).is_err());
+
+    let pending = |store: &BanyanStore| {
+        store
+            .metrics_registry()
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "ax_api_event_store_pending_requests")
+            .map(|family| family.get_metric()[0].get_gauge().get_value())
+    };
+    assert_eq!(pending(&store), Some(0.0));
+
+    let request = tokio::spawn({
+        let events = events.clone();
+        async move { events.offsets().await }
+    });
+    while events.pending_requests() == 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pending(&store), Some(1.0));
+
+    request.abort();
+    let _ = request.await;
+    assert_eq!(pending(&store), Some(0.0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn retentions_are_filtered_by_the_routing_table() -> Result<()> {
+    let retain = RetainConfig::events(10);
+    let mut config = SwarmConfig::test("retentions");
+    config.ephemeral_event_config = EphemeralEventsConfig::from(btreemap! {
+        DEFAULT_STREAM_NAME.to_string() => retain.clone(),
+        "unmapped".to_string() => retain.clone(),
+    });
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await?;
+    let streams = |store: &BanyanStore| match store.settings().ephemeral_events {
+        EphemeralEventsConfig::Enabled { streams, .. } => streams.into_keys().collect::<Vec<_>>(),
+        EphemeralEventsConfig::Disabled => vec![],
+    };
+    // only the built-in retentions of the internal streams remain
+    let builtin = vec![DISCOVERY_STREAM_NAME, FILES_STREAM_NAME, METRICS_STREAM_NAME];
+    assert_eq!(streams(&store), builtin);
+
+    // the same applies to settings changed at runtime
+    let mut settings = store.settings();
+    settings.ephemeral_events = EphemeralEventsConfig::from(btreemap! {
+        DEFAULT_STREAM_NAME.to_string() => retain.clone(),
+        "unmapped".to_string() => retain,
+    });
+    store.update_settings(settings)?;
+    assert_eq!(streams(&store), builtin);
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! Read-only cold tiers behind the local block store.
+//!
+//! Old blocks can be moved to cheaper storage, e.g. an object store bucket or a read-only mounted
+//! archive. Reads that miss the local [`SqliteStore`] are then served from the configured
+//! [`BlockReader`]s, in order. Writes always go to the local store.
+use crate::{
+    swarm::{
+        sqlite::{SqliteStore, SqliteStoreWrite},
+        Link,
+    },
+    trees::axtrees::Sha256Digest,
+};
+use anyhow::Result;
+use banyan::store::ReadOnlyStore;
+use fnv::FnvHashMap;
+use libipld::error::BlockNotFound;
+use parking_lot::Mutex;
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How long a block missing from all tiers is not looked up there again
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Number of missing blocks remembered, the cache is cleared when it is full
+const NEGATIVE_CACHE_SIZE: usize = 65536;
+
+/// A secondary source of blocks, see [`SwarmConfig::cold_tiers`](super::SwarmConfig::cold_tiers).
+pub trait BlockReader: Debug + Send + Sync + 'static {
+    /// The block with the given link, `None` if this tier does not have it.
+    fn get(&self, link: &Link) -> Result<Option<Vec<u8>>>;
+}
+
+/// Block store of the [`Forest`](super::Forest), reading from the local store first and from the
+/// cold tiers if a block is not present locally.
+#[derive(Debug, Clone)]
+pub struct TieredReadStore {
+    local: SqliteStore,
+    tiers: Vec<Arc<dyn BlockReader>>,
+    /// blocks none of the tiers had, and when that was found out
+    missing: Arc<Mutex<FnvHashMap<Link, Instant>>>,
+}
+
+impl TieredReadStore {
+    pub fn new(local: SqliteStore, tiers: Vec<Arc<dyn BlockReader>>) -> Self {
+        Self {
+            local,
+            tiers,
+            missing: Default::default(),
+        }
+    }
+
+    pub fn write(&self) -> Result<SqliteStoreWrite> {
+        self.local.write()
+    }
+
+    fn is_known_missing(&self, link: &Link) -> bool {
+        let mut missing = self.missing.lock();
+        match missing.get(link) {
+            Some(since) if since.elapsed() < NEGATIVE_CACHE_TTL => true,
+            Some(_) => {
+                missing.remove(link);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn set_missing(&self, link: Link) {
+        let mut missing = self.missing.lock();
+        if missing.len() >= NEGATIVE_CACHE_SIZE {
+            missing.retain(|_, since| since.elapsed() < NEGATIVE_CACHE_TTL);
+            if missing.len() >= NEGATIVE_CACHE_SIZE {
+                missing.clear();
+            }
+        }
+        missing.insert(link, Instant::now());
+    }
+
+    /// Look the block up in the cold tiers, `None` if none of them has it.
+    fn get_cold(&self, link: &Link) -> Option<Vec<u8>> {
+        let mut failed = false;
+        for tier in &self.tiers {
+            match tier.get(link) {
+                Ok(Some(data)) if Sha256Digest::new(&data) == *link => return Some(data),
+                Ok(Some(_)) => {
+                    tracing::warn!("cold tier {:?} returned corrupt data for block {}", tier, link);
+                    failed = true;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!("cold tier {:?} failed to read block {}: {:#}", tier, link, err);
+                    failed = true;
+                }
+            }
+        }
+        // a failing tier may have the block after all, so only remember blocks that are missing
+        if !failed {
+            self.set_missing(*link);
+        }
+        None
+    }
+}
+
+impl ReadOnlyStore<Sha256Digest> for TieredReadStore {
+    fn get(&self, link: &Sha256Digest) -> Result<Box<[u8]>> {
+        match self.local.get(link) {
+            Err(err) if !self.tiers.is_empty() && err.is::<BlockNotFound>() => {
+                if self.is_known_missing(link) {
+                    return Err(err);
+                }
+                // keep the original error, callers check for `BlockNotFound`
+                self.get_cold(link).map(Vec::into_boxed_slice).ok_or(err)
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swarm::{BanyanStore, Forest};
+    use ax_types::{app_id, tags, Payload, StreamNr, Timestamp};
+    use banyan::{
+        query::AllQuery,
+        store::{BlockWriter, BranchCache},
+    };
+    use libipld::Cid;
+    use std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    #[derive(Debug, Default)]
+    struct MemTier {
+        blocks: Mutex<BTreeMap<Link, Vec<u8>>>,
+        lookups: AtomicUsize,
+    }
+
+    impl BlockReader for MemTier {
+        fn get(&self, link: &Link) -> Result<Option<Vec<u8>>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(self.blocks.lock().get(link).cloned())
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_fall_back_to_cold_tiers() -> Result<()> {
+        let source = BanyanStore::test("tiered_source").await?;
+        let stream_nr = StreamNr::from(5);
+        let events = vec![(tags!("a"), Payload::null()); 100];
+        source
+            .append0(stream_nr, app_id!("test"), Timestamp::now(), events)
+            .await?;
+        let tree = source
+            .lock()
+            .published_tree(source.node_id().stream(stream_nr))
+            .unwrap();
+
+        // the cold tier has all blocks, every other one has been deleted locally
+        let cold = Arc::new(MemTier::default());
+        let local = BanyanStore::test("tiered_local").await?;
+        let mut writer = local.data.forest.store().write()?;
+        let mut n = 0;
+        source.walk_blocks(Cid::from(tree.root()), |block| {
+            cold.blocks
+                .lock()
+                .insert(Sha256Digest::new(block.data()), block.data().to_vec());
+            if n % 2 == 0 {
+                writer.put(block.data().to_vec())?;
+            }
+            n += 1;
+            Ok(())
+        })?;
+
+        let store = TieredReadStore::new(SqliteStore::wrap(local.ipfs().clone()), vec![cold.clone()]);
+        let forest = Forest::new(store.clone(), BranchCache::new(1000));
+        let read = forest
+            .iter_filtered(tree.tree(), AllQuery)
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(read.len(), 100);
+        assert!(cold.lookups.load(Ordering::SeqCst) > 0);
+
+        // blocks missing everywhere are looked up in the cold tier only once
+        let missing = Sha256Digest::new(b"missing");
+        let lookups = cold.lookups.load(Ordering::SeqCst);
+        for _ in 0..3 {
+            let err = store.get(&missing).unwrap_err();
+            assert!(err.is::<BlockNotFound>(), "{}", err);
+        }
+        assert_eq!(cold.lookups.load(Ordering::SeqCst), lookups + 1);
+        Ok(())
+    }
+}
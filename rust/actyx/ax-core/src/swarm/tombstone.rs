@@ -0,0 +1,215 @@
+//! Tombstones mark streams as deleted, so that the data of a decommissioned node can be purged
+//! from the whole swarm.
+//!
+//! A node tombstones one of its own streams by appending a signed [`TombstoneEvent`] as its last
+//! event. After that no more events can be appended to the stream. Other nodes recognize the
+//! tombstone when they replicate the stream and stop replicating it. Every node keeps the stream
+//! for [`SwarmConfig::tombstone_retention`](super::SwarmConfig::tombstone_retention), so that the
+//! tombstone can still reach peers that are offline for a while, and then purges it: the alias is
+//! removed, so the blocks are collected, and the stream no longer shows up in queries or the root
+//! map. Its last offset stays part of the present offsets, so the stream is not replicated again.
+use crate::{
+    crypto::{KeyPair, PublicKey},
+    swarm::{internal_app_id, BanyanStore, BanyanStoreGuard, Event, ReadOnlyError},
+    trees::{query::OffsetQuery, AxTree},
+};
+use anyhow::{ensure, Context, Result};
+use ax_types::{tags, Offset, StreamId, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+
+/// The last event of a tombstoned stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TombstoneEvent {
+    pub stream_id: StreamId,
+    pub reason: String,
+    /// base64 encoded signature of the node owning the stream
+    pub signature: String,
+}
+
+impl TombstoneEvent {
+    pub fn new(keypair: &KeyPair, stream_id: StreamId, reason: String) -> Self {
+        let signature = base64::encode(keypair.sign(Self::message(stream_id, &reason).as_bytes()));
+        Self {
+            stream_id,
+            reason,
+            signature,
+        }
+    }
+
+    /// Whether the tombstone was signed by the node owning the stream
+    pub fn verify(&self) -> bool {
+        let Ok(signature) = base64::decode(&self.signature) else {
+            return false;
+        };
+        PublicKey::from(self.stream_id.node_id())
+            .verify(Self::message(self.stream_id, &self.reason).as_bytes(), &signature)
+    }
+
+    fn message(stream_id: StreamId, reason: &str) -> String {
+        format!("tombstone {} {}", stream_id, reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    pub reason: String,
+    /// offset of the tombstone event, i.e. the last offset of the stream
+    pub offset: Offset,
+    /// when the events of the stream are purged
+    pub purge_at: Timestamp,
+    /// whether the events have been purged
+    pub purged: bool,
+}
+
+impl Tombstone {
+    pub fn new(reason: String, offset: Offset, purge_at: Timestamp) -> Self {
+        Self {
+            reason,
+            offset,
+            purge_at,
+            purged: false,
+        }
+    }
+}
+
+impl BanyanStore {
+    /// Delete one of the streams of this node.
+    ///
+    /// Appends a signed tombstone as the last event of the stream, further appends fail. The stream
+    /// is purged on all nodes once the tombstone retention has passed.
+    pub async fn tombstone_stream(&self, stream_id: StreamId, reason: String) -> Result<Tombstone> {
+        if self.data.read_only {
+            return Err(ReadOnlyError.into());
+        }
+        ensure!(
+            self.is_local(stream_id),
+            "stream {} belongs to another node and cannot be tombstoned here",
+            stream_id
+        );
+        let stream = self.get_or_create_own_stream(stream_id.stream_nr())?;
+        let mut guard = stream.lock().await;
+        // holding both locks, so no append can sneak in after the tombstone
+        let mut store = self.lock();
+        ensure!(
+            !store.tombstones.contains_key(&stream_id),
+            "stream {} has already been tombstoned",
+            stream_id
+        );
+        let event = TombstoneEvent::new(&self.data.keypair, stream_id, reason.clone());
+        let meta = self.append_locked(
+            &mut guard,
+            &mut store,
+            internal_app_id(),
+            Timestamp::now(),
+            vec![(tags!("tombstone"), Event::compact(&event)?)],
+        )?;
+        let tombstone = Tombstone::new(reason, meta.last_offset, meta.timestamp + self.data.tombstone_retention);
+        store.add_tombstone(stream_id, tombstone.clone())?;
+        Ok(tombstone)
+    }
+
+    /// All tombstoned streams, including the purged ones
+    pub fn tombstones(&self) -> BTreeMap<StreamId, Tombstone> {
+        self.lock().tombstones.clone()
+    }
+
+    pub fn is_tombstoned(&self, stream_id: StreamId) -> bool {
+        self.lock().tombstones.contains_key(&stream_id)
+    }
+
+    /// Purge all tombstoned streams whose retention has passed, returning how many were purged.
+    pub(crate) fn purge_due_tombstones(&self, now: Timestamp) -> Result<usize> {
+        let mut store = self.lock();
+        let due = store
+            .tombstones
+            .iter()
+            .filter(|(_, tombstone)| !tombstone.purged && tombstone.purge_at <= now)
+            .map(|(stream_id, _)| *stream_id)
+            .collect::<Vec<_>>();
+        for stream_id in &due {
+            store.purge_stream(*stream_id)?;
+        }
+        Ok(due.len())
+    }
+
+    /// The verified tombstone at the end of a replicated tree, if any.
+    ///
+    /// Trees of streams this node cannot decrypt are not checked, these streams are kept.
+    pub(crate) fn read_tombstone(&self, stream_id: StreamId, tree: &AxTree) -> Result<Option<TombstoneEvent>> {
+        if !self.data.is_readable(stream_id) || tree.count() == 0 {
+            return Ok(None);
+        }
+        let last = self
+            .data
+            .forest
+            .iter_filtered_reverse(tree, OffsetQuery::from(tree.count() - 1..))
+            .next()
+            .transpose()?;
+        Ok(last
+            .and_then(|(_, _, payload)| payload.extract::<TombstoneEvent>().ok())
+            .filter(|event| event.stream_id == stream_id && event.verify()))
+    }
+}
+
+impl<'a> BanyanStoreGuard<'a> {
+    pub(crate) fn is_purged(&self, stream_id: StreamId) -> bool {
+        self.tombstones
+            .get(&stream_id)
+            .map_or(false, |tombstone| tombstone.purged)
+    }
+
+    pub(crate) fn add_tombstone(&mut self, stream_id: StreamId, tombstone: Tombstone) -> Result<()> {
+        if self.tombstones.contains_key(&stream_id) {
+            return Ok(());
+        }
+        tracing::info!(
+            "stream {} has been tombstoned at offset {}: {}",
+            stream_id,
+            tombstone.offset,
+            tombstone.reason
+        );
+        self.index_store
+            .add_tombstone(stream_id, &tombstone)
+            .context("unable to write tombstone")?;
+        self.tombstones.insert(stream_id, tombstone);
+        Ok(())
+    }
+
+    /// Drop the stream from memory and remove its alias, its blocks are removed by the next
+    /// garbage collection.
+    fn purge_stream(&mut self, stream_id: StreamId) -> Result<()> {
+        tracing::info!("purging tombstoned stream {}", stream_id);
+        if self.is_local(stream_id) {
+            self.own_streams.remove(&stream_id.stream_nr());
+        } else if let Some(node) = self.remote_nodes.get_mut(&stream_id.node_id()) {
+            node.streams.remove(&stream_id.stream_nr());
+            self.abort_task(&format!("careful_ingestion({})", stream_id));
+        }
+        self.dormant_streams.remove(&stream_id);
+        self.data.tag_stats.lock().remove(&stream_id);
+        self.data.set_stream_alias(stream_id, None)?;
+        self.index_store.set_tombstone_purged(stream_id)?;
+        if let Some(tombstone) = self.tombstones.get_mut(&stream_id) {
+            tombstone.purged = true;
+        }
+        Ok(())
+    }
+}
+
+/// Periodically purges the tombstoned streams whose retention has passed.
+pub(crate) async fn purge_tombstones(store: BanyanStore) {
+    let interval = store
+        .data
+        .tombstone_retention
+        .clamp(Duration::from_secs(1), Duration::from_secs(60));
+    loop {
+        tokio::time::sleep(interval).await;
+        match store.purge_due_tombstones(Timestamp::now()) {
+            Ok(0) => {}
+            Ok(n) => tracing::debug!("purged {} tombstoned streams", n),
+            Err(err) => tracing::error!("error purging tombstoned streams: {:#}", err),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Per-peer accounting of the blocks exchanged with other nodes since this node was started.
+//!
+//! Bitswap does not tell us which peer served a block, so the numbers are collected where the
+//! store knows the connection the transfer went over:
+//!
+//! - blocks received on the fast path are counted for the peer the broadcast came from
+//! - a sync first asks only the peer that announced the root, the blocks fetched from it are
+//!   counted for that peer; their size is not known
+//! - if that peer cannot serve all blocks, this counts as a timed-out want of that peer and the
+//!   remaining blocks are fetched from all peers without being accounted to any of them
+//! - blocks broadcast on the fast path are counted as sent to every connected peer
+use fnv::FnvHashMap;
+use ipfs_embed::PeerId;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerTransferStats {
+    pub peer: PeerId,
+    pub blocks_sent: u64,
+    pub blocks_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Number of syncs of roots announced by this peer that did not complete
+    pub timed_out_wants: u64,
+}
+
+impl PeerTransferStats {
+    fn new(peer: PeerId) -> Self {
+        Self {
+            peer,
+            blocks_sent: 0,
+            blocks_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            timed_out_wants: 0,
+        }
+    }
+}
+
+/// Transfer counters keyed by peer, so that they are kept across reconnects.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TransferStats(Arc<Mutex<FnvHashMap<PeerId, PeerTransferStats>>>);
+
+impl TransferStats {
+    fn update(&self, peer: PeerId, f: impl FnOnce(&mut PeerTransferStats)) {
+        f(self
+            .0
+            .lock()
+            .entry(peer)
+            .or_insert_with(|| PeerTransferStats::new(peer)))
+    }
+
+    pub fn sent(&self, peers: impl IntoIterator<Item = PeerId>, blocks: usize, bytes: usize) {
+        let mut stats = self.0.lock();
+        for peer in peers {
+            let stats = stats.entry(peer).or_insert_with(|| PeerTransferStats::new(peer));
+            stats.blocks_sent += blocks as u64;
+            stats.bytes_sent += bytes as u64;
+        }
+    }
+
+    pub fn received(&self, peer: PeerId, blocks: usize, bytes: usize) {
+        self.update(peer, |stats| {
+            stats.blocks_received += blocks as u64;
+            stats.bytes_received += bytes as u64;
+        })
+    }
+
+    pub fn timed_out(&self, peer: PeerId) {
+        self.update(peer, |stats| stats.timed_out_wants += 1)
+    }
+
+    /// Snapshot of the counters of all peers, ordered by peer id
+    pub fn get(&self) -> Vec<PeerTransferStats> {
+        let mut stats = self.0.lock().values().copied().collect::<Vec<_>>();
+        stats.sort_unstable_by_key(|s| s.peer);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_per_peer() {
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let stats = TransferStats::default();
+        stats.received(a, 3, 300);
+        stats.received(a, 1, 50);
+        stats.sent([a, b], 2, 100);
+        stats.timed_out(b);
+
+        let report = stats.get();
+        assert_eq!(report.len(), 2);
+        let get = |peer| *report.iter().find(|s| s.peer == peer).unwrap();
+        assert_eq!(
+            get(a),
+            PeerTransferStats {
+                peer: a,
+                blocks_sent: 2,
+                blocks_received: 4,
+                bytes_sent: 100,
+                bytes_received: 350,
+                timed_out_wants: 0,
+            }
+        );
+        assert_eq!(
+            get(b),
+            PeerTransferStats {
+                peer: b,
+                blocks_sent: 2,
+                blocks_received: 0,
+                bytes_sent: 100,
+                bytes_received: 0,
+                timed_out_wants: 1,
+            }
+        );
+    }
+}
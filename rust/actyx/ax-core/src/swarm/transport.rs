@@ -1,6 +1,12 @@
 use anyhow::Context;
 use libp2p::{
-    core::{either::EitherTransport, muxing::StreamMuxerBox, transport::Boxed, upgrade::Version},
+    core::{
+        either::EitherTransport,
+        muxing::StreamMuxerBox,
+        transport::{map::Map, Boxed},
+        upgrade::Version,
+        ConnectedPoint,
+    },
     dns::{ResolverConfig, TokioDnsConfig},
     identity, noise,
     pnet::{PnetConfig, PreSharedKey},
@@ -8,15 +14,199 @@ use libp2p::{
     yamux::YamuxConfig,
     PeerId, Transport,
 };
-use std::{io, time::Duration};
+use socket2::{SockRef, TcpKeepalive};
+use std::{fmt, io, time::Duration};
+
+/// TCP keepalive parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpKeepaliveConfig {
+    /// Time a connection needs to be idle before the first probe is sent
+    pub idle: Duration,
+    /// Time between probes
+    pub interval: Duration,
+    /// Number of unanswered probes after which the connection is dropped
+    pub count: u32,
+}
+
+/// Socket level tuning applied to both dialed and accepted TCP connections.
+///
+/// Options the current platform does not support are silently skipped, see [`socket_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpSocketConfig {
+    pub keepalive: Option<TcpKeepaliveConfig>,
+    /// Maximum time transmitted data may remain unacknowledged (`TCP_USER_TIMEOUT`, Linux only)
+    pub user_timeout: Option<Duration>,
+}
+
+impl Default for TcpSocketConfig {
+    fn default() -> Self {
+        // detect dead connections within roughly a minute, like the swarm's ping does
+        Self {
+            keepalive: Some(TcpKeepaliveConfig {
+                idle: Duration::from_secs(30),
+                interval: Duration::from_secs(10),
+                count: 3,
+            }),
+            user_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// A socket option as actually set on a given platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketOption {
+    Keepalive {
+        idle: Duration,
+        interval: Option<Duration>,
+        count: Option<u32>,
+    },
+    UserTimeout(Duration),
+}
+
+impl fmt::Display for SocketOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketOption::Keepalive { idle, interval, count } => {
+                write!(f, "keepalive idle={}s", idle.as_secs())?;
+                if let Some(interval) = interval {
+                    write!(f, " interval={}s", interval.as_secs())?;
+                }
+                if let Some(count) = count {
+                    write!(f, " count={}", count)?;
+                }
+                Ok(())
+            }
+            SocketOption::UserTimeout(timeout) => write!(f, "user_timeout={}s", timeout.as_secs()),
+        }
+    }
+}
+
+const KEEPALIVE_INTERVAL_OS: &[&str] = &["linux", "android", "macos", "ios", "freebsd", "netbsd", "windows"];
+const KEEPALIVE_COUNT_OS: &[&str] = &["linux", "android", "macos", "ios", "freebsd", "netbsd"];
+const USER_TIMEOUT_OS: &[&str] = &["linux", "android"];
+
+/// The socket options to set for the given config on the given operating system (as in
+/// [`std::env::consts::OS`]).
+pub fn socket_options(config: &TcpSocketConfig, os: &str) -> Vec<SocketOption> {
+    let mut options = vec![];
+    if let Some(keepalive) = config.keepalive {
+        options.push(SocketOption::Keepalive {
+            idle: keepalive.idle,
+            interval: KEEPALIVE_INTERVAL_OS.contains(&os).then_some(keepalive.interval),
+            count: KEEPALIVE_COUNT_OS.contains(&os).then_some(keepalive.count),
+        });
+    }
+    if let Some(timeout) = config.user_timeout.filter(|_| USER_TIMEOUT_OS.contains(&os)) {
+        options.push(SocketOption::UserTimeout(timeout));
+    }
+    options
+}
+
+/// Sets the probe interval on the platforms listed in [`KEEPALIVE_INTERVAL_OS`]
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    windows
+))]
+fn keepalive_interval(keepalive: TcpKeepalive, interval: Duration) -> TcpKeepalive {
+    keepalive.with_interval(interval)
+}
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    windows
+)))]
+fn keepalive_interval(keepalive: TcpKeepalive, _interval: Duration) -> TcpKeepalive {
+    keepalive
+}
+
+/// Sets the probe count on the platforms listed in [`KEEPALIVE_COUNT_OS`]
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd"
+))]
+fn keepalive_count(keepalive: TcpKeepalive, count: u32) -> TcpKeepalive {
+    keepalive.with_retries(count)
+}
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd"
+)))]
+fn keepalive_count(keepalive: TcpKeepalive, _count: u32) -> TcpKeepalive {
+    keepalive
+}
+
+trait SetSocketOption {
+    fn set(&self, option: SocketOption) -> io::Result<()>;
+}
+
+impl SetSocketOption for SockRef<'_> {
+    fn set(&self, option: SocketOption) -> io::Result<()> {
+        match option {
+            SocketOption::Keepalive { idle, interval, count } => {
+                let mut keepalive = TcpKeepalive::new().with_time(idle);
+                if let Some(interval) = interval {
+                    keepalive = keepalive_interval(keepalive, interval);
+                }
+                if let Some(count) = count {
+                    keepalive = keepalive_count(keepalive, count);
+                }
+                self.set_tcp_keepalive(&keepalive)
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            SocketOption::UserTimeout(timeout) => self.set_tcp_user_timeout(Some(timeout)),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            SocketOption::UserTimeout(_) => Ok(()),
+        }
+    }
+}
+
+fn apply_socket_options(socket: &impl SetSocketOption, options: &[SocketOption]) {
+    for option in options {
+        if let Err(err) = socket.set(*option) {
+            tracing::debug!("cannot set socket option {}: {}", option, err);
+        }
+    }
+}
+
+/// TCP transport applying the given socket options to every connection.
+fn tcp_transport(
+    config: TcpSocketConfig,
+) -> Map<
+    tcp::tokio::Transport,
+    impl FnOnce(tcp::tokio::TcpStream, ConnectedPoint) -> tcp::tokio::TcpStream + Clone + Send + Unpin,
+> {
+    let options = socket_options(&config, std::env::consts::OS);
+    tcp::tokio::Transport::new(tcp::Config::new().nodelay(true)).map(move |stream, _| {
+        apply_socket_options(&SockRef::from(&stream.0), &options);
+        stream
+    })
+}
 
 /// Builds the transport that serves as a common ground for all connections.
 pub async fn build_transport(
     key_pair: identity::Keypair,
     psk: Option<PreSharedKey>,
     upgrade_timeout: Duration,
+    tcp_config: TcpSocketConfig,
 ) -> anyhow::Result<Boxed<(PeerId, StreamMuxerBox)>> {
-    let tcp = tcp::tokio::Transport::new(tcp::Config::new().nodelay(true));
+    let tcp = tcp_transport(tcp_config);
     let base_transport = if cfg!(target_os = "android") {
         // No official support for DNS on Android.
         // see https://github.com/Actyx/Cosmos/issues/6582
@@ -56,3 +246,98 @@ pub async fn build_transport(
         .boxed();
     Ok(transport)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<SocketOption>>);
+
+    impl SetSocketOption for Recorder {
+        fn set(&self, option: SocketOption) -> io::Result<()> {
+            self.0.borrow_mut().push(option);
+            match option {
+                SocketOption::UserTimeout(_) => Err(io::Error::new(io::ErrorKind::Other, "unsupported")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn recorded(os: &str) -> Vec<SocketOption> {
+        let recorder = Recorder::default();
+        apply_socket_options(&recorder, &socket_options(&TcpSocketConfig::default(), os));
+        recorder.0.into_inner()
+    }
+
+    #[test]
+    fn option_mapping() {
+        let keepalive = |interval: Option<u64>, count: Option<u32>| SocketOption::Keepalive {
+            idle: Duration::from_secs(30),
+            interval: interval.map(Duration::from_secs),
+            count,
+        };
+        let user_timeout = SocketOption::UserTimeout(Duration::from_secs(60));
+
+        assert_eq!(recorded("linux"), vec![keepalive(Some(10), Some(3)), user_timeout]);
+        assert_eq!(recorded("android"), vec![keepalive(Some(10), Some(3)), user_timeout]);
+        assert_eq!(recorded("macos"), vec![keepalive(Some(10), Some(3))]);
+        assert_eq!(recorded("windows"), vec![keepalive(Some(10), None)]);
+        assert_eq!(recorded("openbsd"), vec![keepalive(None, None)]);
+
+        let disabled = TcpSocketConfig {
+            keepalive: None,
+            user_timeout: None,
+        };
+        assert_eq!(socket_options(&disabled, "linux"), vec![]);
+
+        assert_eq!(
+            socket_options(&TcpSocketConfig::default(), "linux")
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["keepalive idle=30s interval=10s count=3", "user_timeout=60s"]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn options_applied_to_accepted_sockets() {
+        use futures::future::poll_fn;
+        use libp2p::core::transport::TransportEvent;
+        use std::pin::Pin;
+
+        let config = TcpSocketConfig {
+            keepalive: Some(TcpKeepaliveConfig {
+                idle: Duration::from_secs(42),
+                interval: Duration::from_secs(7),
+                count: 5,
+            }),
+            user_timeout: Some(Duration::from_secs(33)),
+        };
+        let mut transport = tcp_transport(config);
+        transport.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        let addr = match poll_fn(|cx| Pin::new(&mut transport).poll(cx)).await {
+            TransportEvent::NewAddress { listen_addr, .. } => listen_addr,
+            _ => panic!("expected listen address"),
+        };
+        let port = match addr.iter().last() {
+            Some(libp2p::multiaddr::Protocol::Tcp(port)) => port,
+            _ => panic!("unexpected address {}", addr),
+        };
+        let _client = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let stream = loop {
+            if let TransportEvent::Incoming { upgrade, .. } = poll_fn(|cx| Pin::new(&mut transport).poll(cx)).await {
+                break upgrade.await.unwrap();
+            }
+        };
+
+        let socket = SockRef::from(&stream.0);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(42));
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(7));
+        assert_eq!(socket.keepalive_retries().unwrap(), 5);
+        assert_eq!(socket.tcp_user_timeout().unwrap(), Some(Duration::from_secs(33)));
+    }
+}
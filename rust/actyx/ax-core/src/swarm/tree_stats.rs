@@ -0,0 +1,136 @@
+//! Shape and size of the stream trees, for debugging and capacity planning.
+//!
+//! The numbers are computed from a published tree taken out of the store state, so walking a large
+//! tree does not block appends or replication. They describe the tree as it was when taken.
+use crate::swarm::{streams::PublishedTree, BanyanStore};
+use anyhow::{Context, Result};
+use ax_types::StreamId;
+use banyan::{index::Index, query::AllQuery};
+use libipld::Cid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Number of events in the tree, including those in pruned leaves
+    pub events: u64,
+    /// Number of leaves, including pruned ones
+    pub leaves: u64,
+    /// Number of leaves whose events have been pruned, only their index is kept
+    pub pruned_leaves: u64,
+    /// Number of branch nodes
+    pub branches: u64,
+    /// Level of the root node, zero for a single leaf
+    pub level: i32,
+    /// Whether the tree is packed, i.e. compaction would not change it
+    pub packed: bool,
+    /// Number of events in sealed leaves, which compaction leaves alone
+    pub packed_events: u64,
+    /// Number of events in unsealed leaves, which compaction will merge
+    pub unpacked_events: u64,
+    /// Number of blocks present locally, including the tree header
+    pub blocks: u64,
+    /// Size of the blocks present locally, i.e. as stored after compression and encryption
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Number of streams that have a published tree
+    pub streams: u64,
+    /// Sum of the statistics of all stream trees
+    pub trees: TreeStats,
+    /// Number of blocks in the block store, including payload blobs, files and unreferenced blocks
+    pub store_blocks: u64,
+    /// Size of all blocks in the block store
+    pub store_bytes: u64,
+}
+
+impl TreeStats {
+    fn add(&mut self, other: &TreeStats) {
+        self.events += other.events;
+        self.leaves += other.leaves;
+        self.pruned_leaves += other.pruned_leaves;
+        self.branches += other.branches;
+        self.level = self.level.max(other.level);
+        self.packed = self.packed && other.packed;
+        self.packed_events += other.packed_events;
+        self.unpacked_events += other.unpacked_events;
+        self.blocks += other.blocks;
+        self.bytes += other.bytes;
+    }
+}
+
+impl BanyanStore {
+    /// Statistics of the tree of a stream, failing for unknown streams.
+    pub fn tree_stats(&self, stream_id: StreamId) -> Result<TreeStats> {
+        let tree = self
+            .lock()
+            .published_tree(stream_id)
+            .with_context(|| format!("no published tree for stream {}", stream_id))?;
+        self.published_tree_stats(&tree)
+    }
+
+    /// Statistics of all stream trees and of the block store as a whole.
+    ///
+    /// This reads every block in the store, so it is meant for occasional diagnostics only.
+    pub fn store_stats(&self) -> Result<StoreStats> {
+        let trees = {
+            let state = self.lock();
+            state
+                .current_stream_ids()
+                .filter_map(|stream_id| state.published_tree(stream_id))
+                .collect::<Vec<_>>()
+        };
+        let mut stats = StoreStats {
+            trees: TreeStats {
+                packed: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for tree in &trees {
+            stats.streams += 1;
+            stats.trees.add(&self.published_tree_stats(tree)?);
+        }
+        for cid in self.ipfs().iter()? {
+            // blocks may be collected concurrently
+            if let Ok(block) = self.ipfs().get(&cid) {
+                stats.store_blocks += 1;
+                stats.store_bytes += block.data().len() as u64;
+            }
+        }
+        Ok(stats)
+    }
+
+    fn published_tree_stats(&self, tree: &PublishedTree) -> Result<TreeStats> {
+        let forest = &self.data.forest;
+        let mut stats = TreeStats {
+            events: tree.tree().count(),
+            level: tree.tree().level(),
+            packed: forest.is_packed(tree.tree())?,
+            ..Default::default()
+        };
+        for index in forest.iter_index(tree.tree(), AllQuery) {
+            match index? {
+                Index::Leaf(leaf) => {
+                    let events = leaf.keys().count() as u64;
+                    stats.leaves += 1;
+                    if leaf.link.is_none() {
+                        stats.pruned_leaves += 1;
+                    }
+                    if leaf.sealed {
+                        stats.packed_events += events;
+                    } else {
+                        stats.unpacked_events += events;
+                    }
+                }
+                Index::Branch(_) => stats.branches += 1,
+            }
+        }
+        self.walk_blocks(Cid::from(tree.root()), |block| {
+            stats.blocks += 1;
+            stats.bytes += block.data().len() as u64;
+            Ok(())
+        })?;
+        Ok(stats)
+    }
+}
@@ -0,0 +1,81 @@
+//! Adding whole directory trees as unixfs-v1 directories, see [`UnixfsDirAdder`].
+use crate::swarm::{BanyanStore, Block, BufferingTreeBuilder, TreeOptions};
+use anyhow::{Context, Result};
+use ipfs_embed::TempPin;
+use libipld::Cid;
+use std::io::Read;
+use unixfs_v1::Metadata;
+
+/// Builds a unixfs-v1 directory from files added one after the other.
+///
+/// The contents of each file are streamed into the store as they are read. All blocks are
+/// protected by a temp pin owned by the adder, which is only transferred to the caller’s temp pin
+/// once the complete tree has been written. Dropping the adder — e.g. after a reader failed —
+/// releases everything written so far.
+pub struct UnixfsDirAdder<'a> {
+    store: &'a BanyanStore,
+    tmp: TempPin,
+    builder: BufferingTreeBuilder,
+    bytes: u64,
+}
+
+impl<'a> UnixfsDirAdder<'a> {
+    pub fn new(store: &'a BanyanStore) -> Result<Self> {
+        let mut opts = TreeOptions::default();
+        opts.wrap_with_directory();
+        Ok(Self {
+            store,
+            tmp: store.ipfs().create_temp_pin()?,
+            builder: BufferingTreeBuilder::new(opts),
+            bytes: 0,
+        })
+    }
+
+    /// Adds a file at the given `/`-separated path, creating parent directories as needed.
+    ///
+    /// Returns the number of bytes read from `reader`.
+    pub fn add_file(&mut self, path: &str, reader: impl Read) -> Result<u64> {
+        let path = normalize(path);
+        let (cid, size) = self
+            .store
+            .add(&mut self.tmp, reader)
+            .with_context(|| format!("adding file {}", path))?;
+        let size = size as u64;
+        self.builder
+            .put_link(path, cid, size)
+            .with_context(|| format!("adding file {} to directory", path))?;
+        self.bytes += size;
+        Ok(size)
+    }
+
+    /// Adds a directory at the given path, which stays empty unless files are added below it.
+    pub fn add_dir(&mut self, path: &str) -> Result<()> {
+        let path = normalize(path);
+        self.builder
+            .set_metadata(path, Metadata::default())
+            .with_context(|| format!("adding directory {}", path))?;
+        Ok(())
+    }
+
+    /// Writes the directory nodes, returning the root directory and the total size of all files.
+    ///
+    /// The tree is temp pinned in `tmp` and needs to be aliased before dropping it.
+    pub fn finish(mut self, tmp: &mut TempPin) -> Result<(Cid, u64)> {
+        let mut root = None;
+        for node in self.builder.build() {
+            let node = node.context("Constructing a directory node")?;
+            self.store.ipfs().temp_pin(&mut self.tmp, &node.cid)?;
+            self.store
+                .ipfs()
+                .insert(Block::new_unchecked(node.cid, node.block.to_vec()))?;
+            root = Some(node.cid);
+        }
+        let root = root.expect("wrapping directory is always built");
+        self.store.ipfs().temp_pin(tmp, &root)?;
+        Ok((root, self.bytes))
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_matches('/')
+}
@@ -0,0 +1,144 @@
+//! Metadata of unixfs-v1 nodes without reading their contents, see [`BanyanStore::unixfs_stat`].
+//!
+//! There are two sizes to tell apart: the content size is the number of bytes of a file, the DAG
+//! size the number of bytes of all blocks making up a node. The `Tsize` of a directory link is
+//! meant to be the DAG size of the child, but it is only what the writer put there: directories
+//! built by [`UnixfsDirAdder`](crate::swarm::UnixfsDirAdder) record the content size for files and,
+//! for subdirectories, the size of the subdirectory block plus the sizes recorded in it.
+use crate::swarm::{BanyanStore, FlatUnixFs, UnixFsType};
+use anyhow::{Context, Result};
+use ax_types::Timestamp;
+use libipld::Cid;
+
+/// The multicodec of dag-pb blocks, all other blocks are leaves
+const DAG_PB: u64 = 0x70;
+
+/// Metadata of a unixfs-v1 node, see [`BanyanStore::unixfs_stat`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub kind: UnixFsType,
+    /// number of bytes of a file, `None` for all other types
+    pub content_size: Option<u64>,
+    /// number of bytes of all blocks below and including the root block
+    pub dag_size: u64,
+    /// number of blocks below and including the root block, `None` if it cannot be told without
+    /// fetching the contents of a file
+    pub blocks: Option<u64>,
+    /// modification time, if recorded in the unixfs metadata
+    pub mtime: Option<Timestamp>,
+}
+
+/// The content size of a file, taken from the root block alone.
+///
+/// Writers usually record the `filesize`, without it the sizes of the child blocks or the inline
+/// data are used.
+pub(crate) fn content_size(flat: &FlatUnixFs) -> u64 {
+    flat.data.filesize.unwrap_or_else(|| {
+        let inline = flat
+            .data
+            .Data
+            .as_ref()
+            .map(|data| data.len() as u64)
+            .unwrap_or_default();
+        inline.saturating_add(
+            flat.data
+                .blocksizes
+                .iter()
+                .fold(0, |sum, size| sum.saturating_add(*size)),
+        )
+    })
+}
+
+/// The modification time recorded in the unixfs metadata, `None` if it is not representable.
+pub(crate) fn mtime(flat: &FlatUnixFs) -> Option<Timestamp> {
+    let mtime = flat.data.mtime.as_ref()?;
+    let seconds = u64::try_from(mtime.Seconds).ok()?;
+    let nanos = u64::from(mtime.FractionalNanoseconds.unwrap_or_default());
+    let micros = seconds.checked_mul(1_000_000)?.checked_add(nanos / 1_000)?;
+    Some(Timestamp::new(micros))
+}
+
+/// Running totals of [`FileStat::dag_size`] and [`FileStat::blocks`]
+struct DagSize {
+    bytes: u64,
+    blocks: Option<u64>,
+}
+
+impl DagSize {
+    /// Adds a block and the links the sizes can be taken from, the other links are returned to be
+    /// fetched.
+    ///
+    /// Links of a file carry the DAG size of their children as written by
+    /// [`FileAdder`](unixfs_v1::file::adder::FileAdder), so files are sized from their root block.
+    /// Only raw leaves are known to have no children of their own, so the number of blocks below
+    /// other links remains unknown. Directory links and file links without a `Tsize` are walked.
+    fn add(&mut self, len: usize, flat: Option<&FlatUnixFs>) -> Result<Vec<Cid>> {
+        self.bytes = self.bytes.saturating_add(len as u64);
+        self.blocks = self.blocks.map(|blocks| blocks + 1);
+        let flat = match flat {
+            Some(flat) => flat,
+            None => return Ok(vec![]),
+        };
+        let links = links(flat)?;
+        let tsizes = flat.links.iter().map(|link| link.Tsize).collect::<Option<Vec<_>>>();
+        match tsizes {
+            Some(tsizes) if flat.data.Type == UnixFsType::File => {
+                self.bytes = tsizes.into_iter().fold(self.bytes, u64::saturating_add);
+                let leaves = links.iter().all(|cid| cid.codec() != DAG_PB);
+                self.blocks = self.blocks.filter(|_| leaves).map(|blocks| blocks + links.len() as u64);
+                Ok(vec![])
+            }
+            _ => Ok(links),
+        }
+    }
+}
+
+impl BanyanStore {
+    /// Returns the metadata of the unixfs-v1 node at `cid`.
+    ///
+    /// Type, content size and modification time are taken from the root block. The DAG size of a
+    /// file is the size of its root block plus the `Tsize`s of its links, so the contents of a file
+    /// are not fetched unless its links lack the `Tsize`. Directory blocks are always walked, since
+    /// their links may record content sizes, see [`Child::size`](crate::swarm::Child::size).
+    pub async fn unixfs_stat(&self, cid: Cid) -> Result<FileStat> {
+        let peers = self.ipfs().peers();
+        let mut tmp = self.ipfs().create_temp_pin()?;
+        self.ipfs().temp_pin(&mut tmp, &cid)?;
+        let root = self.ipfs().fetch(&cid, peers.clone()).await?;
+        let flat = FlatUnixFs::try_parse(root.data()).map_err(|e| anyhow::anyhow!("Error parsing block: {}", e))?;
+        let kind = flat.data.Type;
+        let content_size = (kind == UnixFsType::File).then(|| content_size(&flat));
+        let mtime = mtime(&flat);
+
+        let mut size = DagSize {
+            bytes: 0,
+            blocks: Some(0),
+        };
+        let mut pending = size.add(root.data().len(), Some(&flat))?;
+        while let Some(cid) = pending.pop() {
+            self.ipfs().temp_pin(&mut tmp, &cid)?;
+            let block = self.ipfs().fetch(&cid, peers.clone()).await?;
+            if cid.codec() == DAG_PB {
+                let flat = FlatUnixFs::try_parse(block.data())
+                    .map_err(|e| anyhow::anyhow!("Error parsing block {}: {}", cid, e))?;
+                pending.extend(size.add(block.data().len(), Some(&flat))?);
+            } else {
+                size.add(block.data().len(), None)?;
+            }
+        }
+        Ok(FileStat {
+            kind,
+            content_size,
+            dag_size: size.bytes,
+            blocks: size.blocks,
+            mtime,
+        })
+    }
+}
+
+fn links(flat: &FlatUnixFs) -> Result<Vec<Cid>> {
+    flat.links
+        .iter()
+        .map(|link| Cid::try_from(link.Hash.as_deref().unwrap_or_default()).context("parsing link"))
+        .collect()
+}
@@ -3,7 +3,7 @@ use std::collections::BTreeSet;
 use ax_aql::TagAtom;
 
 // invariant: none of the sets are ever empty
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Dnf(pub BTreeSet<BTreeSet<ax_aql::TagAtom>>);
 
 impl Dnf {
@@ -284,6 +284,12 @@ impl TagExprQuery {
         Self { tags, lamport, time }
     }
 
+    /// Additionally restrict the query to events with a timestamp in `time`
+    pub fn and_time(mut self, time: TimeQuery) -> Self {
+        self.time &= time;
+        self
+    }
+
     pub fn from_expr(tag_expr: &ax_aql::TagExpr) -> Result<impl Fn(bool, StreamId) -> Self, TagExprError> {
         let dnf = Dnf::from(tag_expr).0;
 
@@ -17,6 +17,7 @@ use banyan::{
 use futures::prelude::*;
 use parking_lot::Mutex;
 use pretty_assertions::assert_eq;
+use quickcheck_macros::quickcheck;
 use serde_json::json;
 use std::sync::Arc;
 
@@ -334,3 +335,34 @@ async fn events_banyan_tree_simple_queries() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// Time ranges must not miss events even if the timestamps within the tree are out of order
+#[quickcheck]
+fn time_query_out_of_order(times: Vec<(u16, bool)>, from: u16, len: u16) -> anyhow::Result<bool> {
+    let events = times
+        .into_iter()
+        .enumerate()
+        .map(|(lamport, (time, a))| {
+            let tags = if a { tags!("a") } else { tags!("b") };
+            let key = AxKey::new(
+                tags.into(),
+                LamportTimestamp::new(lamport as u64),
+                Timestamp::new(time.into()),
+            );
+            (key, Payload::null())
+        })
+        .collect::<Vec<_>>();
+    let mut txn = test_txn();
+    let mut builder = StreamBuilder::debug();
+    txn.extend(&mut builder, events.clone())?;
+    let tree = builder.snapshot();
+
+    let range = Timestamp::new(from.into())..Timestamp::new(u64::from(from) + u64::from(len));
+    let tags = vec![stags! {"a"}];
+    let expected = add_offsets(events)
+        .filter(|(_, key, _)| range.contains(&key.time()) && matches(key, &tags))
+        .collect::<Vec<_>>();
+    let query = TagExprQuery::new(tags, LamportQuery::all(), TimeQuery::all()).and_time(TimeQuery::from(range));
+    let actual = futures::executor::block_on(filter_tree(&txn, &tree, query))?;
+    Ok(actual == expected)
+}
@@ -1,13 +1,21 @@
-use super::ActyxOSResult;
-use crate::util::version::NodeVersion;
-use ax_types::NodeId;
+use super::{ActyxOSResult, LogEntry, LogSeverity};
+use crate::{
+    node::backup::{BackupManifest, BackupProgress},
+    swarm::{DecisionRecord, OffsetsComparison},
+    util::version::NodeVersion,
+};
+use ax_types::{service::PeerStatus, NodeId};
 use chrono::{DateTime, Utc};
+use libp2p::{Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::PathBuf};
 
 #[derive(Clone, Debug)]
 pub struct AdminProtocol();
 
+/// Prefix of the identify agent version announced by `ax`, followed by its [`NodeVersion`]
+pub const AX_AGENT_PREFIX: &str = "ax/";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LogQueryMode {
     All,
@@ -42,6 +50,8 @@ impl crate::libp2p_streaming_response::Codec for AdminProtocol {
 pub enum AdminRequest {
     NodesLs,
     NodesInspect,
+    /// Health of the node's components and replication status of its peers
+    NodesStatus,
     NodesShutdown,
     SettingsGet {
         scope: crate::settings::Scope,
@@ -65,6 +75,39 @@ pub enum AdminRequest {
     TopicDelete {
         name: String,
     },
+    /// Start listening for swarm connections on an additional address, until the node restarts
+    SwarmAddListenAddr(Multiaddr),
+    /// Stop listening for swarm connections on an address, the last one cannot be removed
+    SwarmRemoveListenAddr(Multiaddr),
+    /// The most recent `count` log records kept by the node, optionally only those at `min_level`
+    /// or above and those whose target starts with `target_filter`
+    LogsTail {
+        count: usize,
+        min_level: Option<LogSeverity>,
+        target_filter: Option<String>,
+    },
+    /// Exchange offsets with the connected `peer` and report the streams on which the two nodes
+    /// differ
+    CompareOffsets {
+        #[serde(with = "crate::util::serde_str")]
+        peer: PeerId,
+    },
+    /// The most recent `count` records of the decision log, empty unless the `swarm/decisionLog`
+    /// setting is enabled
+    DecisionLogTail {
+        count: usize,
+    },
+    /// Write a backup of the node to the archive at `path` on the node's host, answered with
+    /// progress updates followed by the manifest of the archive
+    BackupCreate {
+        path: PathBuf,
+    },
+    /// Restore the backup archive at `path` on the node's host into `working_dir`, which must be
+    /// empty. A node can then be started from `working_dir`.
+    BackupRestore {
+        path: PathBuf,
+        working_dir: PathBuf,
+    },
     // Without this, the request isn't processed and the client times out
     #[serde(other)]
     FutureCompat,
@@ -74,6 +117,7 @@ pub enum AdminRequest {
 pub enum AdminResponse {
     NodesLsResponse(NodesLsResponse),
     NodesInspectResponse(NodesInspectResponse),
+    NodesStatusResponse(NodesStatusResponse),
     SettingsGetResponse(serde_json::Value),
     SettingsSetResponse(serde_json::Value),
     SettingsSchemaResponse(serde_json::Value),
@@ -81,6 +125,17 @@ pub enum AdminResponse {
     SettingsUnsetResponse,
     TopicLsResponse(TopicLsResponse),
     TopicDeleteResponse(TopicDeleteResponse),
+    /// The bound address, with the port assigned if port zero was requested
+    SwarmAddListenAddrResponse(Multiaddr),
+    SwarmRemoveListenAddrResponse,
+    /// Oldest first
+    LogsTailResponse(Vec<LogEntry>),
+    CompareOffsetsResponse(OffsetsComparison),
+    /// Oldest first
+    DecisionLogTailResponse(Vec<DecisionRecord>),
+    BackupProgress(BackupProgress),
+    BackupCreateResponse(BackupManifest),
+    BackupRestoreResponse(BackupManifest),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -92,6 +147,29 @@ pub struct NodesLsResponse {
     pub started_unix: i64,
     pub version: NodeVersion,
 }
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodesStatusResponse {
+    /// Status per component, by component name
+    pub components: BTreeMap<String, ComponentStatus>,
+    pub uptime_secs: u64,
+    /// Replication status of the peers as observed via gossip, by node ID
+    pub peers_status: BTreeMap<String, PeerStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "state")]
+pub enum ComponentStatus {
+    Starting,
+    Running,
+    Degraded {
+        reason: String,
+    },
+    Stopped,
+    /// The component did not answer the health check in time
+    Unresponsive,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SetSettingsRequest {
     pub settings: serde_json::Value,
@@ -104,8 +182,29 @@ pub struct NodesInspectResponse {
     pub swarm_addrs: Vec<String>,
     pub announce_addrs: Vec<String>,
     pub admin_addrs: Vec<String>,
+    /// Socket options applied to connections accepted on the admin addresses
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_socket_options: Vec<String>,
     pub connections: Vec<Connection>,
     pub known_peers: Vec<Peer>,
+    /// Effective interval until the next root map publication, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gossip_interval_millis: Option<u64>,
+    /// Peers whose messages are currently ignored for sending malformed data
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quarantined_peers: Vec<QuarantinedPeer>,
+    /// Gossip still arriving on topics the swarm was previously known under
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_topics: Vec<PreviousTopicTraffic>,
+    /// Current state of the maintenance windows, if any are configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<MaintenanceStatus>,
+    /// Local lamport close to the end of its range, or implausible lamports received from peers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lamport_warnings: Vec<String>,
+    /// Ping round-trip times and connection failures of the peers seen recently
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub peer_quality: Vec<PeerConnectionQuality>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -119,6 +218,51 @@ pub struct Connection {
     pub outbound: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerConnectionQuality {
+    pub node_id: String,
+    /// Weighted average of the ping round-trip times in microseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_micros: Option<u64>,
+    pub pings: u64,
+    /// Connections closed because the peer stopped answering pings
+    pub failures: u64,
+    pub last_seen: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedPeer {
+    pub peer_id: String,
+    pub reason: String,
+    /// Seconds until the quarantine is lifted
+    pub remaining_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousTopicTraffic {
+    pub topic: String,
+    /// Gossip messages received on the topic since the node started
+    pub messages: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_received: Option<String>,
+    /// Seconds until the node stops listening on the topic
+    pub remaining_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceStatus {
+    pub in_window: bool,
+    /// When the currently open window closes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_window: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Peer {
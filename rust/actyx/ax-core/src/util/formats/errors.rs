@@ -117,6 +117,7 @@ impl From<RepositoryError> for ActyxOSError {
             RepositoryError::DatabaseError(_) => ActyxOSCode::ERR_IO,
             RepositoryError::UpdateError(_) => ActyxOSCode::ERR_IO,
             RepositoryError::RootScopeNotAllowed => ActyxOSCode::ERR_UNAUTHORIZED,
+            RepositoryError::RedactedPlaceholder(_) => ActyxOSCode::ERR_SETTINGS_INVALID,
         };
         code.with_message(format!("{}", err))
     }
@@ -1,8 +1,8 @@
 use crate::libp2p_streaming_response::Codec;
 use ax_types::{
     service::{
-        Diagnostic, EventResponse, OffsetsResponse, PublishRequest, PublishResponse, QueryRequest,
-        SubscribeMonotonicRequest, SubscribeRequest,
+        Diagnostic, EventResponse, OffsetsResponse, PublishRequest, PublishResponse, QueryChecksum, QueryProgress,
+        QueryRequest, QueryTimeout, SubscribeMonotonicRequest, SubscribeRequest,
     },
     OffsetMap, Payload,
 };
@@ -48,6 +48,9 @@ pub enum EventsResponse {
     },
     Publish(PublishResponse),
     Diagnostic(Diagnostic),
+    Progress(QueryProgress),
+    Checksum(QueryChecksum),
+    Timeout(QueryTimeout),
     #[serde(other)]
     FutureCompat,
 }
@@ -76,7 +79,11 @@ mod tests {
                 lower_bound: None,
                 upper_bound: None,
                 query: "FROM allEvents".parse().unwrap(),
-                order: ax_types::service::Order::Asc
+                order: ax_types::service::Order::Asc,
+                progress: None,
+                inline_blobs: None,
+                checksum: None,
+                timeout_millis: None,
             })),
             r#"{"type":"query","query":"FROM allEvents","lowerBound":null,"upperBound":null,"order":"asc"}"#
         );
@@ -154,6 +161,30 @@ mod tests {
             res(EventsResponse::Publish(PublishResponse { data: vec![] })),
             r#"{"type":"publish","data":[]}"#
         );
+        assert_eq!(
+            res(EventsResponse::Progress(QueryProgress {
+                done: 3,
+                total: 4,
+                positions: OffsetMap::default()
+            })),
+            r#"{"type":"progress","done":3,"total":4,"positions":{}}"#
+        );
+        assert_eq!(
+            res(EventsResponse::Checksum(QueryChecksum {
+                events: 2,
+                sha256: "abcd".to_owned(),
+            })),
+            r#"{"type":"checksum","events":2,"sha256":"abcd"}"#
+        );
+        assert_eq!(
+            res(EventsResponse::Timeout(QueryTimeout {
+                timeout_millis: 1000,
+                progress: None,
+                lower_bound: Some(OffsetMap::default()),
+                upper_bound: None,
+            })),
+            r#"{"type":"timeout","timeoutMillis":1000,"lowerBound":{}}"#
+        );
     }
 
     #[test]
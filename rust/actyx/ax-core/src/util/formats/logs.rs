@@ -1,5 +1,6 @@
+use ax_types::Timestamp;
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
 impl From<&tracing::Level> for LogSeverity {
     fn from(l: &tracing::Level) -> Self {
@@ -66,6 +67,18 @@ impl fmt::Display for LogSeverity {
     }
 }
 
+/// A log record as kept by the node for [`AdminRequest::LogsTail`](super::AdminRequest::LogsTail)
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    pub timestamp: Timestamp,
+    pub level: LogSeverity,
+    pub target: String,
+    /// possibly truncated, see the `admin.logBuffer` settings
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+}
+
 #[test]
 fn levels() {
     assert_eq!(serde_json::to_string(&LogSeverity::Error).unwrap(), "\"ERROR\"");
@@ -7,11 +7,10 @@ use anyhow::bail;
 use multiaddr::{Multiaddr, Protocol};
 use serde::{Deserialize, Deserializer};
 use std::{
-    collections::HashSet,
     convert::TryFrom,
     fmt::{Display, Formatter},
     iter::FromIterator,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs},
     num::NonZeroU16,
     str::FromStr,
 };
@@ -63,26 +62,94 @@ pub fn setup_logger() {
     setup_logger_with_level(0);
 }
 
+/// Order in which [`SocketAddrHelper`] lists the addresses of the two IP families.
+///
+/// The families alternate as recommended for happy eyeballs (RFC 8305), starting with the
+/// preferred one, while the addresses of one family keep the order in which they were resolved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preference {
+    #[default]
+    Ipv6First,
+    Ipv4First,
+}
+
+impl Preference {
+    fn is_preferred(self, addr: &SocketAddr) -> bool {
+        match self {
+            Preference::Ipv6First => addr.is_ipv6(),
+            Preference::Ipv4First => addr.is_ipv4(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SocketAddrHelper {
-    inner: HashSet<SocketAddr>,
+    /// without duplicates, ordered according to `preference`
+    inner: Vec<SocketAddr>,
+    preference: Preference,
 }
 
 impl SocketAddrHelper {
+    fn new(addrs: impl IntoIterator<Item = SocketAddr>, preference: Preference) -> Self {
+        let (mut preferred, mut other) = (vec![], vec![]);
+        for addr in addrs {
+            if preferred.contains(&addr) || other.contains(&addr) {
+                continue;
+            }
+            if preference.is_preferred(&addr) {
+                preferred.push(addr);
+            } else {
+                other.push(addr);
+            }
+        }
+        let mut inner = Vec::with_capacity(preferred.len() + other.len());
+        let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+        loop {
+            match (preferred.next(), other.next()) {
+                (None, None) => break,
+                (first, second) => inner.extend(first.into_iter().chain(second)),
+            }
+        }
+        Self { inner, preference }
+    }
+
     pub fn empty() -> Self {
-        Self { inner: HashSet::new() }
+        Self::new([], Preference::default())
+    }
+
+    /// Resolves `host`, listing the addresses in the given order. IPv6 addresses may carry a
+    /// scope id, given as interface name or index like in `[fe80::1%eth0]:4001`.
+    pub fn resolve_with_preference(host: impl ToSocketAddrs, preference: Preference) -> anyhow::Result<Self> {
+        Ok(Self::new(host.to_socket_addrs()?, preference))
     }
 
     // Parses common multiaddrs and resolves dns4 to ip4 hosts.
-    // Limitations: No nested protocols, only tcp.
+    // Limitations: No nested protocols, only tcp. A leading `/ip6zone/<zone>` sets the scope id of
+    // the following `ip6` address.
     pub fn parse_multiaddr(multiaddr_str: &str) -> anyhow::Result<Self> {
-        let multiaddr: Multiaddr = multiaddr_str.parse()?;
-        SocketAddrHelper::try_from(multiaddr)
+        let (zone, multiaddr) = match multiaddr_str.strip_prefix("/ip6zone/").map(|rest| rest.split_once('/')) {
+            Some(Some((zone, rest))) => (Some(scope_id(zone)?), format!("/{}", rest)),
+            Some(None) => bail!("Invalid multiaddr, ip6zone must be followed by ip6"),
+            None => (None, multiaddr_str.to_owned()),
+        };
+        let multiaddr: Multiaddr = multiaddr.parse()?;
+        let mut ret = SocketAddrHelper::try_from(multiaddr)?;
+        if let Some(scope_id) = zone {
+            for addr in ret.inner.iter_mut() {
+                match addr {
+                    SocketAddr::V6(addr) => addr.set_scope_id(scope_id),
+                    SocketAddr::V4(_) => bail!("Invalid multiaddr, ip6zone must be followed by ip6"),
+                }
+            }
+        }
+        Ok(ret)
     }
 
     pub fn from_host_string(host_string: &str) -> anyhow::Result<Self> {
-        let inner = host_string.to_socket_addrs()?.collect();
-        Ok(Self { inner })
+        if let Some(addr) = parse_scoped(host_string, None)? {
+            return Ok(addr.into());
+        }
+        Self::resolve_with_preference(host_string, Preference::default())
     }
 
     /// Takes an input string, which can either be a host, or a host:port
@@ -91,25 +158,25 @@ impl SocketAddrHelper {
     pub fn from_host(host_string: &str, default_port: NonZeroU16) -> anyhow::Result<Self> {
         if let Ok(addr) = host_string.parse() {
             Ok(addr)
+        } else if let Some(addr) = parse_scoped(host_string, Some(default_port.into()))? {
+            Ok(addr.into())
         } else {
-            Ok(Self {
-                inner: (host_string, default_port.into()).to_socket_addrs()?.collect(),
-            })
+            Self::resolve_with_preference((host_string, default_port.into()), Preference::default())
         }
     }
 
     pub fn from_ip_port(ip: IpAddr, port: u16) -> anyhow::Result<Self> {
-        Ok(Self {
-            inner: (ip, port).to_socket_addrs()?.collect(),
-        })
+        Self::resolve_with_preference((ip, port), Preference::default())
     }
 
     pub fn append(&mut self, other: Self) {
-        self.inner.extend(other.inner);
+        let addrs = self.inner.drain(..).chain(other.inner).collect::<Vec<_>>();
+        *self = Self::new(addrs, self.preference);
     }
 
-    pub fn to_multiaddrs(&self) -> impl Iterator<Item = Multiaddr> {
-        self.inner.clone().into_iter().map(to_multiaddr)
+    /// The addresses to listen on with libp2p, see [`to_listen_multiaddr`]
+    pub fn to_multiaddrs(&self) -> anyhow::Result<Vec<Multiaddr>> {
+        self.inner.iter().copied().map(to_listen_multiaddr).collect()
     }
 
     pub fn unspecified(port: u16) -> anyhow::Result<Self> {
@@ -119,17 +186,23 @@ impl SocketAddrHelper {
         let ipv4 = (Ipv4Addr::UNSPECIFIED, port)
             .to_socket_addrs()
             .expect("IPv4 Any:port should work");
-        let inner = ipv6.chain(ipv4).collect();
-        Ok(Self { inner })
+        Ok(Self::new(ipv6.chain(ipv4), Preference::default()))
     }
 
-    pub fn inject_bound_addr(&mut self, mut listen_addr: SocketAddr, bound_addr: SocketAddr) -> Option<()> {
+    pub fn inject_bound_addr(&mut self, listen_addr: SocketAddr, bound_addr: SocketAddr) -> Option<()> {
         if listen_addr.port() != 0 {
             return None;
         }
-        self.inner.remove(&listen_addr);
-        listen_addr.set_port(bound_addr.port());
-        self.inner.insert(listen_addr);
+        let mut bound = listen_addr;
+        bound.set_port(bound_addr.port());
+        // keeps the position of the listen address
+        let addrs = self
+            .inner
+            .iter()
+            .map(|addr| if *addr == listen_addr { bound } else { *addr })
+            .chain((!self.inner.contains(&listen_addr)).then_some(bound))
+            .collect::<Vec<_>>();
+        *self = Self::new(addrs, self.preference);
         Some(())
     }
 
@@ -138,11 +211,51 @@ impl SocketAddrHelper {
     }
 }
 
+/// Parses an IPv6 address with scope id, which `std` only supports as number, like
+/// `[fe80::1%eth0]:4001`, or `fe80::1%eth0` if there is a `default_port`. Returns `None` for
+/// anything else.
+fn parse_scoped(s: &str, default_port: Option<u16>) -> anyhow::Result<Option<SocketAddr>> {
+    let (host, port) = match s.strip_prefix('[').and_then(|rest| rest.split_once("]:")) {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => (host, port),
+            Err(_) => return Ok(None),
+        },
+        None => match default_port {
+            Some(port) => (s, port),
+            None => return Ok(None),
+        },
+    };
+    let Some((ip, zone)) = host.split_once('%') else {
+        return Ok(None);
+    };
+    let Ok(ip) = ip.parse::<Ipv6Addr>() else {
+        return Ok(None);
+    };
+    Ok(Some(SocketAddrV6::new(ip, port, 0, scope_id(zone)?).into()))
+}
+
+/// The scope id of an IPv6 zone, given as interface index or (on unix only) name
+fn scope_id(zone: &str) -> anyhow::Result<u32> {
+    if let Ok(index) = zone.parse() {
+        return Ok(index);
+    }
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(zone)?;
+        // SAFETY: `name` is a NUL terminated string that outlives the call
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index != 0 {
+            return Ok(index);
+        }
+    }
+    bail!("unknown network interface `{}`", zone)
+}
+
 impl TryFrom<Multiaddr> for SocketAddrHelper {
     type Error = anyhow::Error;
     fn try_from(mut multi_addr: Multiaddr) -> Result<Self, Self::Error> {
         if let Some(Protocol::Tcp(port)) = multi_addr.pop() {
-            let inner: HashSet<SocketAddr> = match multi_addr.pop() {
+            let inner: Vec<SocketAddr> = match multi_addr.pop() {
                 Some(Protocol::Ip4(ip4)) => (ip4, port).to_socket_addrs()?.collect(),
                 Some(Protocol::Dns4(dns4)) => (dns4.to_string(), port).to_socket_addrs()?.collect(),
                 Some(Protocol::Ip6(ip6)) => (ip6, port).to_socket_addrs()?.collect(),
@@ -159,7 +272,7 @@ impl TryFrom<Multiaddr> for SocketAddrHelper {
                 bail!("Nested protocols are not supported");
             }
 
-            Ok(Self { inner })
+            Ok(inner.into_iter().collect())
         } else {
             bail!("Multiaddress must end with tcp")
         }
@@ -168,9 +281,7 @@ impl TryFrom<Multiaddr> for SocketAddrHelper {
 
 impl From<SocketAddr> for SocketAddrHelper {
     fn from(s: SocketAddr) -> Self {
-        let mut inner = HashSet::new();
-        inner.insert(s);
-        Self { inner }
+        Self::new([s], Preference::default())
     }
 }
 
@@ -183,7 +294,7 @@ impl FromStr for SocketAddrHelper {
 
 impl IntoIterator for SocketAddrHelper {
     type Item = SocketAddr;
-    type IntoIter = std::collections::hash_set::IntoIter<Self::Item>;
+    type IntoIter = std::vec::IntoIter<Self::Item>;
     fn into_iter(self) -> Self::IntoIter {
         self.inner.into_iter()
     }
@@ -191,7 +302,7 @@ impl IntoIterator for SocketAddrHelper {
 
 impl<'a> IntoIterator for &'a SocketAddrHelper {
     type Item = &'a SocketAddr;
-    type IntoIter = std::collections::hash_set::Iter<'a, SocketAddr>;
+    type IntoIter = std::slice::Iter<'a, SocketAddr>;
     fn into_iter(self) -> Self::IntoIter {
         self.inner.iter()
     }
@@ -199,9 +310,7 @@ impl<'a> IntoIterator for &'a SocketAddrHelper {
 
 impl FromIterator<SocketAddr> for SocketAddrHelper {
     fn from_iter<T: IntoIterator<Item = SocketAddr>>(iter: T) -> Self {
-        Self {
-            inner: iter.into_iter().collect(),
-        }
+        Self::new(iter, Preference::default())
     }
 }
 
@@ -240,6 +349,10 @@ pub fn to_socket_addr(m: Multiaddr) -> Option<SocketAddr> {
     Some((ip, port).into())
 }
 
+/// Converts to an `ip4` or `ip6` multiaddr.
+///
+/// The scope id of an IPv6 address cannot be expressed as [`Multiaddr`] of this version of the
+/// crate and is dropped, [`to_multiaddr_string`] keeps it.
 pub fn to_multiaddr(socket_addr: SocketAddr) -> Multiaddr {
     let proto_ip = match socket_addr.ip() {
         IpAddr::V4(ip4) => Protocol::Ip4(ip4),
@@ -250,6 +363,31 @@ pub fn to_multiaddr(socket_addr: SocketAddr) -> Multiaddr {
         .with(Protocol::Tcp(socket_addr.port()))
 }
 
+/// Like [`to_multiaddr`], but failing for scoped IPv6 addresses instead of dropping the scope id.
+///
+/// The libp2p TCP transport binds the address without the scope id, which fails for link-local
+/// addresses, so scoped addresses cannot be listened on with libp2p at all.
+pub fn to_listen_multiaddr(socket_addr: SocketAddr) -> anyhow::Result<Multiaddr> {
+    match socket_addr {
+        SocketAddr::V6(addr) if addr.scope_id() != 0 => bail!(
+            "cannot listen on {}, libp2p does not support IPv6 scope ids; listen on [::] instead",
+            to_multiaddr_string(socket_addr)
+        ),
+        _ => Ok(to_multiaddr(socket_addr)),
+    }
+}
+
+/// Like [`to_multiaddr`], but with a leading `/ip6zone/<scope id>` for scoped IPv6 addresses as
+/// understood by [`SocketAddrHelper::parse_multiaddr`].
+pub fn to_multiaddr_string(socket_addr: SocketAddr) -> String {
+    match socket_addr {
+        SocketAddr::V6(addr) if addr.scope_id() != 0 => {
+            format!("/ip6zone/{}{}", addr.scope_id(), to_multiaddr(socket_addr))
+        }
+        _ => to_multiaddr(socket_addr).to_string(),
+    }
+}
+
 pub mod serde_str {
     //! Serializes fields annotated with `#[serde(with = "crate::util::serde_str")]` with their !
     //! `Display` implementation, deserializes fields using `FromStr`.
@@ -283,9 +421,9 @@ mod tests {
     fn should_parse_multiaddr() {
         let str = ("/ip4/127.0.0.1/tcp/5001").to_owned();
         let ret = SocketAddrHelper::from_str(&str).unwrap();
-        let mut inner = HashSet::new();
-        inner.insert("127.0.0.1:5001".parse().unwrap());
-        assert_eq!(ret, SocketAddrHelper { inner });
+        let addr: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        assert_eq!(ret, SocketAddrHelper::from(addr));
+        assert_eq!(ret.to_multiaddrs().unwrap(), vec![str.parse().unwrap()]);
 
         let str = ("/dns4/localhost/tcp/5001").to_owned();
         let _ = SocketAddrHelper::from_str(&str).unwrap();
@@ -304,4 +442,67 @@ mod tests {
             assert!(i.ip().is_unspecified());
         }
     }
+
+    #[test]
+    fn should_parse_link_local() {
+        let scoped = SocketAddrV6::new("fe80::1".parse().unwrap(), 4001, 0, 2);
+        let expected = SocketAddrHelper::from(SocketAddr::V6(scoped));
+        assert_eq!(SocketAddrHelper::from_str("[fe80::1%2]:4001").unwrap(), expected);
+        assert_eq!(
+            SocketAddrHelper::from_host("fe80::1%2", NonZeroU16::new(4001).unwrap()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            SocketAddrHelper::from_str("/ip6zone/2/ip6/fe80::1/tcp/4001").unwrap(),
+            expected
+        );
+        assert_eq!(to_multiaddr_string(scoped.into()), "/ip6zone/2/ip6/fe80::1/tcp/4001");
+        // rather than binding without the scope
+        assert!(expected.to_multiaddrs().is_err());
+        assert!(to_listen_multiaddr(scoped.into()).is_err());
+        // the Display format stays that of `SocketAddr`
+        assert_eq!(expected.to_string(), "[[fe80::1%2]:4001]");
+
+        #[cfg(target_os = "linux")]
+        {
+            let lo = SocketAddrHelper::from_str("[fe80::1%lo]:4001").unwrap();
+            match lo.iter().next() {
+                Some(SocketAddr::V6(addr)) => assert_ne!(addr.scope_id(), 0),
+                addr => panic!("unexpected {:?}", addr),
+            }
+        }
+        assert!(SocketAddrHelper::from_str("[fe80::1%no-such-interface]:4001").is_err());
+        assert!(SocketAddrHelper::from_str("/ip6zone/2/ip4/127.0.0.1/tcp/4001").is_err());
+    }
+
+    #[test]
+    fn should_order_deterministically() {
+        let addrs = ["1.2.3.4:1", "[::1]:1", "1.2.3.4:1", "5.6.7.8:1", "[::2]:1", "9.9.9.9:1"]
+            .iter()
+            .map(|addr| addr.parse::<SocketAddr>().unwrap())
+            .collect::<Vec<_>>();
+        let ordered = |preference| {
+            SocketAddrHelper::resolve_with_preference(&addrs[..], preference)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            ordered(Preference::Ipv6First),
+            "[[::1]:1, 1.2.3.4:1, [::2]:1, 5.6.7.8:1, 9.9.9.9:1]"
+        );
+        assert_eq!(
+            ordered(Preference::Ipv4First),
+            "[1.2.3.4:1, [::1]:1, 5.6.7.8:1, [::2]:1, 9.9.9.9:1]"
+        );
+        assert_eq!(
+            addrs.iter().copied().collect::<SocketAddrHelper>().to_string(),
+            ordered(Preference::Ipv6First)
+        );
+
+        let mut unspecified = SocketAddrHelper::unspecified(0).unwrap();
+        assert_eq!(unspecified.to_string(), "[[::]:0, 0.0.0.0:0]");
+        unspecified.inject_bound_addr("0.0.0.0:0".parse().unwrap(), "0.0.0.0:4001".parse().unwrap());
+        unspecified.append("[::]:0".parse().unwrap());
+        assert_eq!(unspecified.to_string(), "[[::]:0, 0.0.0.0:4001]");
+    }
 }
@@ -15,6 +15,8 @@ fn node_schema_in_sync() {
             announce_addresses: btreeset![],
             swarm_key: "MDAwMDAwMDAxMTExMTExMTIyMjIyMjIyMzMzMzMzMzM=".into(),
             topic: "some topic".into(),
+            previous_topics: vec![],
+            previous_topics_grace_period: 604800,
             block_cache_count: 1024 * 128,
             block_cache_size: 1024 * 1024 * 1024,
             block_gc_interval: 300,
@@ -24,19 +26,40 @@ fn node_schema_in_sync() {
             mdns: true,
             branch_cache_size: 67108864,
             gossip_interval: 10,
+            gossip_max_interval: 60,
+            fast_path_batch: 50,
             detection_cycles_low_latency: 2.0,
             detection_cycles_high_latency: 5.0,
+            peer_stale_after: 600,
+            quarantine_threshold: 20,
+            quarantine_window: 60,
+            quarantine_duration: 600,
+            max_lamport_jump: 1 << 40,
+            lazy_stream_loading: false,
+            known_peers_max_age: 604800,
+            prometheus: false,
+            decision_log: false,
+            max_connections: None,
+            max_connections_per_peer: None,
+            payload_blob_threshold: None,
+            maintenance: Default::default(),
         },
         admin: Admin {
             display_name: "some name".into(),
             log_levels: LogLevels::default(),
+            log_buffer: LogBuffer::default(),
             authorized_users: vec![],
+            read_secrets: vec![],
         },
         licensing: Licensing::default(),
         api: Api {
             events: Events {
                 internal: None,
                 read_only: true,
+                query_timeout: 300,
+                max_query_timeout: 3600,
+                admin_queries_unlimited: false,
+                payload_schemas: Default::default(),
             },
         },
         event_routing: Default::default(),
@@ -53,3 +76,51 @@ fn node_schema_in_sync() {
     repo.update_settings(&scope, serde_json::to_value(&sample_settings).unwrap(), false)
         .unwrap();
 }
+
+#[test]
+fn node_schema_marks_secrets() {
+    use ax_core::settings::{redact, REDACTED};
+    use serde_json::{json, Value};
+
+    fn redacted_paths(value: &Value, redacted: &Value, path: String, paths: &mut Vec<String>) {
+        match (value, redacted) {
+            (Value::Object(value), Value::Object(redacted)) => {
+                for (name, v) in value {
+                    redacted_paths(v, &redacted[name], format!("{}/{}", path, name), paths);
+                }
+            }
+            _ if redacted == &json!(REDACTED) => paths.push(path),
+            _ => assert_eq!(value, redacted, "{}", path),
+        }
+    }
+
+    let current_schema = Settings::schema().unwrap();
+    let repo = Repository::new_in_memory();
+    let scope: Scope = "com.actyx".parse().unwrap();
+    repo.set_schema(&scope, current_schema.clone()).unwrap();
+    repo.update_settings(
+        &"com.actyx/licensing/apps".parse().unwrap(),
+        json!({ "com.example.sample": "testing" }),
+        false,
+    )
+    .unwrap();
+    let settings = repo.get_settings(&scope, false).unwrap();
+    // make sure the settings are complete
+    serde_json::from_value::<Settings>(settings.clone()).unwrap();
+
+    let mut paths = vec![];
+    redacted_paths(
+        &settings,
+        &redact(&settings, &current_schema),
+        String::new(),
+        &mut paths,
+    );
+    assert_eq!(
+        paths,
+        vec![
+            "/licensing/apps/com.example.sample",
+            "/licensing/node",
+            "/swarm/swarmKey"
+        ]
+    );
+}
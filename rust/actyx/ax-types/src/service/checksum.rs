@@ -0,0 +1,192 @@
+//! Checksums over the results of bounded queries, requested with [`QueryRequest::checksum`].
+//!
+//! The checksum allows a client to verify that it received exactly the events the node
+//! delivered. Each delivered event contributes one record, which is the canonical JSON encoding
+//! of the object `{"key":KEY,"payload":PAYLOAD}` followed by a newline (`0x0a`), where
+//!
+//! - `KEY` is the event key `{"lamport":…,"offset":…,"stream":"…"}` for events read from the
+//!   event streams and `null` for results computed by the query itself (e.g. aggregations),
+//! - `PAYLOAD` is the payload as delivered in the response.
+//!
+//! The canonical JSON encoding contains no insignificant whitespace and orders object members
+//! by the UTF-8 bytes of their names, recursively. Strings are written with the minimal escaping
+//! required by RFC 8259 (`\"`, `\\`, `\b`, `\f`, `\n`, `\r`, `\t` and `\u00XX` for other control
+//! characters), integers in plain decimal notation, and floating point numbers in the shortest
+//! representation that round-trips (always containing a `.` or an exponent, e.g. `1.0`).
+//!
+//! The checksum is the lowercase hex encoded SHA-256 digest over the concatenation of the
+//! records of all events, in the order in which they were delivered.
+//!
+//! [`QueryRequest::checksum`]: super::QueryRequest::checksum
+use super::{EventMeta, EventResponse};
+use crate::Payload;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Number and checksum of the events delivered by a bounded query, see the [module docs](self).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryChecksum {
+    /// Number of events covered by the checksum.
+    pub events: u64,
+    /// Hex encoded SHA-256 digest.
+    pub sha256: String,
+}
+
+/// Running checksum over the events of a query response.
+#[derive(Clone, Default)]
+pub struct QueryChecksumBuilder {
+    events: u64,
+    hasher: Sha256,
+}
+
+impl QueryChecksumBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, event: &EventResponse<Payload>) {
+        self.events += 1;
+        self.hasher.update(canonical_record(event));
+    }
+
+    /// Checksum over the events added so far
+    pub fn checksum(&self) -> QueryChecksum {
+        QueryChecksum {
+            events: self.events,
+            sha256: hex::encode(self.hasher.clone().finalize()),
+        }
+    }
+}
+
+/// The record by which the given event contributes to the checksum.
+pub fn canonical_record(event: &EventResponse<Payload>) -> Vec<u8> {
+    let key = match &event.meta {
+        EventMeta::Event { key, .. } => Some(key),
+        EventMeta::Range { .. } | EventMeta::Synthetic => None,
+    };
+    let record = serde_json::json!({
+        "key": key,
+        "payload": event.payload.json_value(),
+    });
+    let mut bytes = vec![];
+    write_canonical(&record, &mut bytes);
+    bytes.push(b'\n');
+    bytes
+}
+
+/// Writes `value` with object members sorted by the UTF-8 bytes of their names, independent of
+/// the member order of the JSON object representation.
+fn write_canonical(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Object(members) => {
+            let mut members = members.iter().collect::<Vec<_>>();
+            members.sort_unstable_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+            out.push(b'{');
+            for (i, (name, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                serde_json::to_writer(&mut *out, name).expect("strings are always serializable");
+                out.push(b':');
+                write_canonical(value, out);
+            }
+            out.push(b'}');
+        }
+        Value::Array(elements) => {
+            out.push(b'[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical(element, out);
+            }
+            out.push(b']');
+        }
+        scalar => serde_json::to_writer(&mut *out, scalar).expect("JSON values are always serializable"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_id, tags, EventKey, LamportTimestamp, Metadata, NodeId, Offset, StreamNr, Timestamp};
+
+    fn event(payload: &str) -> EventResponse<Payload> {
+        EventResponse {
+            meta: EventMeta::Event {
+                key: EventKey {
+                    lamport: LamportTimestamp::new(3),
+                    stream: NodeId::new([0xab; 32]).stream(StreamNr::new(1)),
+                    offset: Offset::from(7),
+                },
+                meta: Metadata {
+                    timestamp: Timestamp::new(1),
+                    tags: tags!("a"),
+                    app_id: app_id!("test"),
+                },
+            },
+            payload: Payload::from_json_str(payload).unwrap(),
+        }
+    }
+
+    #[test]
+    fn record_is_canonical() {
+        let record = canonical_record(&event(r#"{ "b": [1, 2.5, "é\n"], "a": { "z": null, "y": true } }"#));
+        assert_eq!(
+            std::str::from_utf8(&record).unwrap(),
+            format!(
+                "{{\"key\":{{\"lamport\":3,\"offset\":7,\"stream\":\"{}\"}},\
+                 \"payload\":{{\"a\":{{\"y\":true,\"z\":null}},\"b\":[1,2.5,\"é\\n\"]}}}}\n",
+                NodeId::new([0xab; 32]).stream(StreamNr::new(1))
+            )
+        );
+
+        let synthetic = EventResponse {
+            meta: EventMeta::Synthetic,
+            payload: Payload::from_json_str("42").unwrap(),
+        };
+        assert_eq!(canonical_record(&synthetic), b"{\"key\":null,\"payload\":42}\n");
+    }
+
+    #[test]
+    fn members_are_sorted_by_bytes() {
+        let value = serde_json::json!({ "b": 1, "é": { "Z": 3, "a": 2 }, "B": [{ "y": 4, "x": 5 }] });
+        let mut bytes = vec![];
+        write_canonical(&value, &mut bytes);
+        assert_eq!(
+            std::str::from_utf8(&bytes).unwrap(),
+            r#"{"B":[{"x":5,"y":4}],"b":1,"é":{"Z":3,"a":2}}"#
+        );
+    }
+
+    #[test]
+    fn checksum() {
+        let mut builder = QueryChecksumBuilder::new();
+        assert_eq!(
+            builder.checksum(),
+            QueryChecksum {
+                events: 0,
+                sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_owned(),
+            }
+        );
+        builder.add(&event("1"));
+        builder.add(&event("2"));
+        let mut expected = canonical_record(&event("1"));
+        expected.extend(canonical_record(&event("2")));
+        assert_eq!(
+            builder.checksum(),
+            QueryChecksum {
+                events: 2,
+                sha256: hex::encode(Sha256::digest(&expected)),
+            }
+        );
+
+        // the same events in a different order yield a different checksum
+        let mut reordered = QueryChecksumBuilder::new();
+        reordered.add(&event("2"));
+        reordered.add(&event("1"));
+        assert_ne!(reordered.checksum(), builder.checksum());
+    }
+}
@@ -0,0 +1,393 @@
+//! Compact wire encoding of event responses.
+//!
+//! High-frequency subscribers spend a large part of their bandwidth on repeated key metadata,
+//! most of all the stream ID on every event. In compact mode each response stream assigns small
+//! integer IDs to stream IDs on first use, announces them in a stream mapping message before the
+//! first response that references them, and uses short field names. Only the wire shape
+//! changes; [`CompactDecoder`] restores the regular responses.
+//!
+//! The field names are stable and map as follows:
+//!
+//! | compact             | regular                                        |
+//! |---------------------|------------------------------------------------|
+//! | `type: "s"`, `m`    | stream mapping message: integer ID → stream ID |
+//! | `type: "e"`         | `type: "event"`                                |
+//! | `type: "a"`         | `type: "antiEvent"`                            |
+//! | `type: "o"`, `o`    | `type: "offsets"`, `offsets`                   |
+//! | `type: "r"`, `r`    | any other response in its regular encoding     |
+//! | `l`                 | `lamport`                                      |
+//! | `s`                 | `stream` (as integer ID)                       |
+//! | `o`                 | `offset`                                       |
+//! | `t`                 | `timestamp`                                    |
+//! | `g`                 | `tags`                                         |
+//! | `a`                 | `appId`                                        |
+//! | `p`                 | `payload`                                      |
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    event::{EventKey, Metadata},
+    scalars::StreamId,
+    service::{EventMeta, EventResponse, OffsetMapResponse, QueryResponse, SubscribeResponse},
+    tags::TagSet,
+    AppId, LamportTimestamp, Offset, Payload, Timestamp,
+};
+
+/// An event with its stream replaced by the integer ID announced in a stream mapping message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactEvent {
+    #[serde(rename = "l")]
+    pub lamport: LamportTimestamp,
+    #[serde(rename = "s")]
+    pub stream: u32,
+    #[serde(rename = "o")]
+    pub offset: Offset,
+    #[serde(rename = "t")]
+    pub timestamp: Timestamp,
+    #[serde(rename = "g")]
+    pub tags: TagSet,
+    #[serde(rename = "a")]
+    pub app_id: AppId,
+    #[serde(rename = "p")]
+    pub payload: Payload,
+}
+
+/// Compact encoding of a response of type `R`, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CompactResponse<R> {
+    #[serde(rename = "s")]
+    Streams {
+        #[serde(rename = "m")]
+        streams: BTreeMap<u32, StreamId>,
+    },
+    #[serde(rename = "e")]
+    Event(CompactEvent),
+    #[serde(rename = "a")]
+    AntiEvent(CompactEvent),
+    #[serde(rename = "o")]
+    Offsets {
+        #[serde(rename = "o")]
+        offsets: BTreeMap<u32, Offset>,
+    },
+    #[serde(rename = "r")]
+    Regular {
+        #[serde(rename = "r")]
+        response: R,
+    },
+}
+
+/// Decomposition of a response into the parts that have a compact encoding.
+pub enum ResponseParts<R> {
+    Event(EventKey, Metadata, Payload),
+    AntiEvent(EventKey, Metadata, Payload),
+    Offsets(OffsetMapResponse),
+    Regular(R),
+}
+
+/// Responses that can be sent in compact encoding.
+pub trait Compactable: Sized {
+    fn into_parts(self) -> ResponseParts<Self>;
+    fn from_parts(parts: ResponseParts<Self>) -> Self;
+}
+
+fn event_parts<R>(
+    event: EventResponse<Payload>,
+    anti: bool,
+    regular: impl FnOnce(EventResponse<Payload>) -> R,
+) -> ResponseParts<R> {
+    match event {
+        EventResponse {
+            meta: EventMeta::Event { key, meta },
+            payload,
+        } if anti => ResponseParts::AntiEvent(key, meta, payload),
+        EventResponse {
+            meta: EventMeta::Event { key, meta },
+            payload,
+        } => ResponseParts::Event(key, meta, payload),
+        // aggregated or synthetic results have no key to compact
+        event => ResponseParts::Regular(regular(event)),
+    }
+}
+
+fn event_response(key: EventKey, meta: Metadata, payload: Payload) -> EventResponse<Payload> {
+    EventResponse {
+        meta: EventMeta::Event { key, meta },
+        payload,
+    }
+}
+
+impl Compactable for QueryResponse {
+    fn into_parts(self) -> ResponseParts<Self> {
+        match self {
+            QueryResponse::Event(event) => event_parts(event, false, QueryResponse::Event),
+            QueryResponse::Offsets(offsets) => ResponseParts::Offsets(offsets),
+            response => ResponseParts::Regular(response),
+        }
+    }
+
+    fn from_parts(parts: ResponseParts<Self>) -> Self {
+        match parts {
+            ResponseParts::Event(key, meta, payload) => QueryResponse::Event(event_response(key, meta, payload)),
+            ResponseParts::AntiEvent(..) => QueryResponse::FutureCompat,
+            ResponseParts::Offsets(offsets) => QueryResponse::Offsets(offsets),
+            ResponseParts::Regular(response) => response,
+        }
+    }
+}
+
+impl Compactable for SubscribeResponse {
+    fn into_parts(self) -> ResponseParts<Self> {
+        match self {
+            SubscribeResponse::Event(event) => event_parts(event, false, SubscribeResponse::Event),
+            SubscribeResponse::AntiEvent(event) => event_parts(event, true, SubscribeResponse::AntiEvent),
+            SubscribeResponse::Offsets(offsets) => ResponseParts::Offsets(offsets),
+            response => ResponseParts::Regular(response),
+        }
+    }
+
+    fn from_parts(parts: ResponseParts<Self>) -> Self {
+        match parts {
+            ResponseParts::Event(key, meta, payload) => SubscribeResponse::Event(event_response(key, meta, payload)),
+            ResponseParts::AntiEvent(key, meta, payload) => {
+                SubscribeResponse::AntiEvent(event_response(key, meta, payload))
+            }
+            ResponseParts::Offsets(offsets) => SubscribeResponse::Offsets(offsets),
+            ResponseParts::Regular(response) => response,
+        }
+    }
+}
+
+/// Encoder for one response stream, assigning stream IDs on first use.
+#[derive(Debug, Default)]
+pub struct CompactEncoder {
+    ids: BTreeMap<StreamId, u32>,
+}
+
+impl CompactEncoder {
+    /// Encode a response, preceded by a stream mapping message if it references new streams.
+    pub fn encode<R: Compactable>(&mut self, response: R) -> Vec<CompactResponse<R>> {
+        let mut new = BTreeMap::new();
+        let encoded = match response.into_parts() {
+            ResponseParts::Event(key, meta, payload) => {
+                CompactResponse::Event(self.event(key, meta, payload, &mut new))
+            }
+            ResponseParts::AntiEvent(key, meta, payload) => {
+                CompactResponse::AntiEvent(self.event(key, meta, payload, &mut new))
+            }
+            ResponseParts::Offsets(OffsetMapResponse { offsets }) => CompactResponse::Offsets {
+                offsets: offsets
+                    .into_inner()
+                    .into_iter()
+                    .map(|(stream, offset)| (self.id(stream, &mut new), offset))
+                    .collect(),
+            },
+            ResponseParts::Regular(response) => CompactResponse::Regular { response },
+        };
+        if new.is_empty() {
+            vec![encoded]
+        } else {
+            vec![CompactResponse::Streams { streams: new }, encoded]
+        }
+    }
+
+    fn event(
+        &mut self,
+        key: EventKey,
+        meta: Metadata,
+        payload: Payload,
+        new: &mut BTreeMap<u32, StreamId>,
+    ) -> CompactEvent {
+        CompactEvent {
+            lamport: key.lamport,
+            stream: self.id(key.stream, new),
+            offset: key.offset,
+            timestamp: meta.timestamp,
+            tags: meta.tags,
+            app_id: meta.app_id,
+            payload,
+        }
+    }
+
+    fn id(&mut self, stream: StreamId, new: &mut BTreeMap<u32, StreamId>) -> u32 {
+        let next = self.ids.len() as u32;
+        *self.ids.entry(stream).or_insert_with(|| {
+            new.insert(next, stream);
+            next
+        })
+    }
+}
+
+/// Decoder for one compact response stream.
+#[derive(Debug, Default)]
+pub struct CompactDecoder {
+    streams: BTreeMap<u32, StreamId>,
+}
+
+impl CompactDecoder {
+    /// Restore the regular response; stream mapping messages yield `None`.
+    pub fn decode<R: Compactable>(&mut self, response: CompactResponse<R>) -> Result<Option<R>> {
+        let parts = match response {
+            CompactResponse::Streams { streams } => {
+                self.streams.extend(streams);
+                return Ok(None);
+            }
+            CompactResponse::Event(event) => {
+                let (key, meta, payload) = self.event(event)?;
+                ResponseParts::Event(key, meta, payload)
+            }
+            CompactResponse::AntiEvent(event) => {
+                let (key, meta, payload) = self.event(event)?;
+                ResponseParts::AntiEvent(key, meta, payload)
+            }
+            CompactResponse::Offsets { offsets } => ResponseParts::Offsets(OffsetMapResponse {
+                offsets: offsets
+                    .into_iter()
+                    .map(|(id, offset)| Ok((self.stream(id)?, offset)))
+                    .collect::<Result<BTreeMap<_, _>>>()?
+                    .into(),
+            }),
+            CompactResponse::Regular { response } => ResponseParts::Regular(response),
+        };
+        Ok(Some(R::from_parts(parts)))
+    }
+
+    fn event(&self, event: CompactEvent) -> Result<(EventKey, Metadata, Payload)> {
+        let key = EventKey {
+            lamport: event.lamport,
+            stream: self.stream(event.stream)?,
+            offset: event.offset,
+        };
+        let meta = Metadata {
+            timestamp: event.timestamp,
+            tags: event.tags,
+            app_id: event.app_id,
+        };
+        Ok((key, meta, event.payload))
+    }
+
+    fn stream(&self, id: u32) -> Result<StreamId> {
+        self.streams
+            .get(&id)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("stream ID {} has not been announced", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app_id,
+        service::{Diagnostic, QueryProgress},
+        tags, NodeId, OffsetMap,
+    };
+
+    fn event(stream: StreamId, n: u64) -> EventResponse<Payload> {
+        event_response(
+            EventKey {
+                lamport: LamportTimestamp::new(n),
+                stream,
+                offset: Offset::try_from(n).unwrap(),
+            },
+            Metadata {
+                timestamp: Timestamp::new(1_600_000_000_000_000 + n),
+                tags: tags!("sensor", "temperature"),
+                app_id: app_id!("com.example.monitor"),
+            },
+            Payload::compact(&n).unwrap(),
+        )
+    }
+
+    fn streams() -> Vec<StreamId> {
+        (0..3u8)
+            .map(|n| NodeId::new([n; 32]).stream(u64::from(n).into()))
+            .collect()
+    }
+
+    fn round_trip<R: Compactable + Serialize + serde::de::DeserializeOwned + Clone + PartialEq + std::fmt::Debug>(
+        responses: Vec<R>,
+    ) -> (usize, usize) {
+        let mut encoder = CompactEncoder::default();
+        let mut decoder = CompactDecoder::default();
+        let (mut regular_bytes, mut compact_bytes) = (0, 0);
+        let mut decoded = vec![];
+        for response in responses.iter().cloned() {
+            regular_bytes += serde_json::to_string(&response).unwrap().len();
+            for compact in encoder.encode(response) {
+                let json = serde_json::to_string(&compact).unwrap();
+                compact_bytes += json.len();
+                decoded.extend(decoder.decode(serde_json::from_str(&json).unwrap()).unwrap());
+            }
+        }
+        assert_eq!(decoded, responses);
+        (regular_bytes, compact_bytes)
+    }
+
+    #[test]
+    fn query_round_trip() {
+        let streams = streams();
+        let mut responses = (0..300)
+            .map(|n| QueryResponse::Event(event(streams[n as usize % 3], n)))
+            .collect::<Vec<_>>();
+        responses.push(QueryResponse::Event(EventResponse {
+            meta: EventMeta::Synthetic,
+            payload: Payload::null(),
+        }));
+        responses.push(QueryResponse::Diagnostic(Diagnostic::warn("careful".to_owned())));
+        responses.push(QueryResponse::Progress(QueryProgress {
+            done: 1,
+            total: 2,
+            positions: OffsetMap::empty(),
+        }));
+        responses.push(QueryResponse::Offsets(OffsetMapResponse {
+            offsets: OffsetMap::from(
+                streams
+                    .iter()
+                    .map(|s| (*s, Offset::from(299u32)))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+        }));
+
+        let (regular, compact) = round_trip(responses);
+        assert!(compact * 2 < regular, "compact {} regular {}", compact, regular);
+    }
+
+    #[test]
+    fn subscribe_round_trip() {
+        let streams = streams();
+        let responses = vec![
+            SubscribeResponse::Event(event(streams[0], 1)),
+            SubscribeResponse::AntiEvent(event(streams[0], 1)),
+            SubscribeResponse::Event(event(streams[1], 2)),
+            SubscribeResponse::Offsets(OffsetMapResponse {
+                offsets: OffsetMap::from(
+                    streams
+                        .iter()
+                        .map(|s| (*s, Offset::from(2u32)))
+                        .collect::<BTreeMap<_, _>>(),
+                ),
+            }),
+        ];
+        round_trip(responses);
+    }
+
+    #[test]
+    fn streams_are_announced_once() {
+        let streams = streams();
+        let mut encoder = CompactEncoder::default();
+        let first = encoder.encode(QueryResponse::Event(event(streams[0], 0)));
+        assert_eq!(first.len(), 2);
+        assert!(matches!(&first[0], CompactResponse::Streams { streams: m } if m.get(&0) == Some(&streams[0])));
+        assert_eq!(encoder.encode(QueryResponse::Event(event(streams[0], 1))).len(), 1);
+
+        let json = serde_json::to_value(&first[1]).unwrap();
+        assert_eq!(json["type"], "e");
+        assert_eq!(json["s"], 0);
+        assert_eq!(json["g"], serde_json::json!(["sensor", "temperature"]));
+
+        let mut decoder = CompactDecoder::default();
+        assert!(decoder.decode(first[1].clone()).is_err());
+    }
+}
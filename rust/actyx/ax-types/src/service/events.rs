@@ -2,11 +2,12 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, fmt::Display, num::NonZeroU64, ops::AddAssign};
 
+use super::QueryChecksum;
 use crate::{
     app_id,
     event::{Event, EventKey, Metadata},
     scalars::StreamId,
-    tags::TagSet,
+    tags::{Tag, TagSet},
     LamportTimestamp, Offset, OffsetMap, Payload, Timestamp,
 };
 use lazy_static::lazy_static;
@@ -52,6 +53,96 @@ pub struct QueryRequest {
     pub upper_bound: Option<OffsetMap>,
     /// Order in which events should be received.
     pub order: Order,
+    /// Opt-in reporting of query progress, see [`QueryProgress`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<QueryProgressRequest>,
+    /// Deliver payloads that have been stored as blobs instead of their references.
+    ///
+    /// Blobs that cannot be retrieved are delivered as reference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_blobs: Option<bool>,
+    /// Opt-in checksum over the delivered events, sent as [`QueryChecksum`] before the final
+    /// offsets; see [`QueryChecksumBuilder`](super::QueryChecksumBuilder) for how to recompute it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<bool>,
+    /// Execution timeout in milliseconds, replacing the node’s default timeout.
+    ///
+    /// The node caps this at its configured maximum. When the timeout is exceeded, the response
+    /// ends with a [`QueryTimeout`] instead of the final offsets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_millis: Option<u64>,
+}
+
+/// Configuration of the progress messages interleaved into the response of a bounded query.
+///
+/// A progress message is sent whenever at least `interval_millis` milliseconds have passed
+/// or `events` events have been delivered since the previous one, and a final message is
+/// sent once all events within the upper bound have been delivered.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QueryProgressRequest {
+    /// Minimum time between two progress messages, in milliseconds.
+    pub interval_millis: u64,
+    /// Number of delivered events after which a progress message is sent.
+    pub events: u64,
+}
+
+impl Default for QueryProgressRequest {
+    fn default() -> Self {
+        Self {
+            interval_millis: 1000,
+            events: 10_000,
+        }
+    }
+}
+
+/// Progress of a bounded query.
+///
+/// Progress is measured in stream offsets between the lower and upper bounds of the query,
+/// regardless of whether the events at those offsets match the query. The positions are the
+/// offsets reached so far per stream, which are the highest offsets for ascending orders and
+/// the lowest offsets for descending order.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryProgress {
+    /// Number of offsets processed so far.
+    pub done: u64,
+    /// Total number of offsets between the lower and upper bounds.
+    pub total: u64,
+    /// Offsets reached so far per stream.
+    pub positions: OffsetMap,
+}
+
+impl QueryProgress {
+    /// Fraction of the query that is complete, between 0.0 and 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        }
+    }
+}
+
+/// Sent as the last message of a bounded query that exceeded its execution timeout.
+///
+/// For queries reading events the bounds describe the part of the query that was not yet
+/// delivered: running the same query again with these bounds delivers the remaining events,
+/// as long as the query does not aggregate or limit its results.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTimeout {
+    /// The timeout that was exceeded, in milliseconds.
+    pub timeout_millis: u64,
+    /// Progress reached when the query was stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<QueryProgress>,
+    /// Lower bound for resuming the query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lower_bound: Option<OffsetMap>,
+    /// Upper bound for resuming the query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upper_bound: Option<OffsetMap>,
 }
 
 /// Subscription to an unbounded set of events across multiple streams.
@@ -453,6 +544,15 @@ pub enum SubscribeMonotonicResponse {
     TimeTravel { new_start: EventKey },
     #[serde(rename_all = "camelCase")]
     Diagnostic(Diagnostic),
+    /// Sent while `stream` is idle, after its events up to `offset` have been delivered: its
+    /// further events will have a lamport above `lamport`, so the consumer may advance its
+    /// watermark for the stream without having received an event.
+    #[serde(rename_all = "camelCase")]
+    Watermark {
+        stream: StreamId,
+        lamport: LamportTimestamp,
+        offset: Offset,
+    },
     #[serde(other)]
     FutureCompat,
 }
@@ -461,6 +561,9 @@ pub enum SubscribeMonotonicResponse {
 ///
 /// This will currently only be elements of type `Event` but will eventually contain
 /// `Offset`s to communicate progress of events not included in the query.
+///
+/// `Progress` elements are only sent when requested via [`QueryRequest::progress`], the
+/// `Checksum` element only when requested via [`QueryRequest::checksum`].
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub enum QueryResponse {
@@ -470,6 +573,12 @@ pub enum QueryResponse {
     Offsets(OffsetMapResponse),
     #[serde(rename_all = "camelCase")]
     Diagnostic(Diagnostic),
+    #[serde(rename_all = "camelCase")]
+    Progress(QueryProgress),
+    #[serde(rename_all = "camelCase")]
+    Checksum(QueryChecksum),
+    #[serde(rename_all = "camelCase")]
+    Timeout(QueryTimeout),
     #[serde(other)]
     FutureCompat,
 }
@@ -536,6 +645,21 @@ pub struct OffsetsResponse {
     pub to_replicate: BTreeMap<StreamId, NonZeroU64>,
 }
 
+/// JSON schema registered for the payloads of events carrying a given tag
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadSchema {
+    pub tag: Tag,
+    pub schema: serde_json::Value,
+}
+
+/// Response to the schemas request, listing all registered [`PayloadSchema`]s
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadSchemasResponse {
+    pub schemas: Vec<PayloadSchema>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -577,6 +701,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn query_request_progress() {
+        let request = serde_json::from_str::<QueryRequest>(r#"{"query":"FROM allEvents","order":"asc"}"#).unwrap();
+        assert_eq!(request.progress, None);
+        assert!(!serde_json::to_string(&request).unwrap().contains("progress"));
+
+        let request = serde_json::from_str::<QueryRequest>(
+            r#"{"query":"FROM allEvents","order":"asc","progress":{"intervalMillis":500}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.progress,
+            Some(QueryProgressRequest {
+                interval_millis: 500,
+                events: 10_000,
+            })
+        );
+    }
+
     #[test]
     fn event_response_compat() {
         let stream = NodeId::from_bytes(b"abcdefghijklmnopqrstuvwxyz123456")
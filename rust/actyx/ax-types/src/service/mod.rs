@@ -1,7 +1,10 @@
 mod auth;
+mod checksum;
+pub mod compact;
 mod events;
 mod node;
 
 pub use auth::*;
+pub use checksum::*;
 pub use events::*;
 pub use node::*;
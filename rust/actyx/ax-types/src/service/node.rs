@@ -1,4 +1,4 @@
-use crate::NodeId;
+use crate::{NodeId, Timestamp};
 use im::OrdMap;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -22,6 +22,24 @@ pub struct NodeInfoResponse {
 #[serde(rename_all = "camelCase")]
 pub struct SwarmState {
     pub peers_status: OrdMap<NodeId, PeerStatus>,
+    /// Connection quality of the peers seen recently
+    #[serde(default, skip_serializing_if = "OrdMap::is_empty")]
+    pub peers_quality: OrdMap<NodeId, PeerQuality>,
+}
+
+/// Ping round-trip times and connection failures of a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerQuality {
+    /// Exponentially weighted average of the ping round-trip times in microseconds, if any ping
+    /// was answered yet
+    pub rtt_micros: Option<u64>,
+    /// Number of ping results received
+    pub pings: u64,
+    /// Number of connections closed because the peer stopped answering pings
+    pub failures: u64,
+    /// When the last ping result or closed connection was seen
+    pub last_seen: Timestamp,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
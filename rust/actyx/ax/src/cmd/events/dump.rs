@@ -232,6 +232,10 @@ impl AxCliCommand for EventsDump {
                     upper_bound: None,
                     query: opts.query,
                     order: Order::Asc,
+                    progress: None,
+                    inline_blobs: None,
+                    checksum: None,
+                    timeout_millis: None,
                 }),
                 tx,
             ))
@@ -286,6 +290,16 @@ impl AxCliCommand for EventsDump {
                         }
                     }
                     EventsResponse::Diagnostic(d) => diag.log(format!("diagnostic {:?}: {}", d.severity, d.message))?,
+                    // the node's query timeout stopped the query, the dump would silently miss events
+                    EventsResponse::Timeout(t) => {
+                        return Err(ActyxOSError::new(
+                            ActyxOSCode::ERR_IO,
+                            format!(
+                                "query aborted by the node after {}ms with {} events written, the dump is incomplete",
+                                t.timeout_millis, count
+                            ),
+                        ))
+                    }
                     _ => {}
                 }
             }
@@ -7,7 +7,7 @@ use ax_core::{
     runtime::value::Value,
     util::formats::{events_protocol::EventsRequest, ActyxOSCode, ActyxOSResult, ActyxOSResultExt},
 };
-use ax_sdk::types::service::{Order, QueryRequest};
+use ax_sdk::types::service::{Order, QueryChecksumBuilder, QueryProgressRequest, QueryRequest};
 use futures::{future::ready, Stream, StreamExt};
 use std::{fs::File, io::Read};
 
@@ -18,6 +18,15 @@ pub struct QueryOpts {
     console_opt: ConsoleOpt,
     /// event API query (read from file if the argument starts with @)
     query: String,
+    /// report query progress at most every given number of milliseconds
+    #[arg(long, value_name = "MILLIS")]
+    progress: Option<u64>,
+    /// request a checksum over the delivered events and verify it
+    #[arg(long)]
+    checksum: bool,
+    /// abort the query after the given number of milliseconds (capped by the node’s settings)
+    #[arg(long, value_name = "MILLIS")]
+    timeout: Option<u64>,
 }
 
 pub struct EventsQuery;
@@ -49,12 +58,34 @@ impl AxCliCommand for EventsQuery {
                     upper_bound: None,
                     query,
                     order: Order::Asc,
+                    progress: opts.progress.map(|interval_millis| QueryProgressRequest {
+                        interval_millis,
+                        ..Default::default()
+                    }),
+                    inline_blobs: None,
+                    checksum: opts.checksum.then_some(true),
+                    timeout_millis: opts.timeout,
                 }),
             )
             .await?;
 
+            let mut checksum = QueryChecksumBuilder::new();
             while let Some(ev) = stream.next().await {
-                co.yield_(Ok(Some(ev?))).await;
+                let ev = ev?;
+                match &ev {
+                    EventDiagnostic::Event(e) => checksum.add(e),
+                    EventDiagnostic::Checksum(c) if *c != checksum.checksum() => {
+                        return Err(ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!(
+                            "checksum mismatch: node sent {} events with SHA-256 {}, received {} events with SHA-256 {}",
+                            c.events,
+                            c.sha256,
+                            checksum.checksum().events,
+                            checksum.checksum().sha256
+                        )));
+                    }
+                    _ => {}
+                }
+                co.yield_(Ok(Some(ev))).await;
             }
             Ok(None)
         })
@@ -67,6 +98,9 @@ impl AxCliCommand for EventsQuery {
             EventDiagnostic::Event(e) => Value::from(e).to_string(),
             EventDiagnostic::AntiEvent(e) => format!("- {}", Value::from(e)),
             EventDiagnostic::Diagnostic(d) => format!("{:?}: {}", d.severity, d.message),
+            EventDiagnostic::Progress(p) => format!("progress: {:.1}% ({}/{})", p.fraction() * 100.0, p.done, p.total),
+            EventDiagnostic::Checksum(c) => format!("checksum: {} events, SHA-256 {}", c.events, c.sha256),
+            EventDiagnostic::Timeout(t) => format!("timeout: query aborted after {}ms", t.timeout_millis),
         }
     }
 }
@@ -65,6 +65,9 @@ impl AxCliCommand for EventsSubscribe {
             EventDiagnostic::Event(e) => Value::from(e).to_string(),
             EventDiagnostic::AntiEvent(e) => format!("- {}", Value::from(e)),
             EventDiagnostic::Diagnostic(d) => format!("{:?}: {}", d.severity, d.message),
+            EventDiagnostic::Progress(p) => format!("progress: {:.1}% ({}/{})", p.fraction() * 100.0, p.done, p.total),
+            EventDiagnostic::Checksum(c) => format!("checksum: {} events, SHA-256 {}", c.events, c.sha256),
+            EventDiagnostic::Timeout(t) => format!("timeout: query aborted after {}ms", t.timeout_millis),
         }
     }
 }
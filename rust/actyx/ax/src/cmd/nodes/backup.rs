@@ -0,0 +1,123 @@
+use crate::{
+    cmd::{AxCliCommand, ConsoleOpt},
+    gen_stream::GenStream,
+};
+use ax_core::{
+    node::backup::{BackupManifest, BackupProgress, BackupStage},
+    node_connection::Task,
+    util::formats::{ActyxOSError, ActyxOSResult, AdminRequest, AdminResponse},
+};
+use futures::{channel::mpsc, future::ready, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(clap::Parser, Clone, Debug)]
+/// write a backup of the node into an archive on the node's host
+pub struct BackupOpts {
+    #[command(flatten)]
+    console_opt: ConsoleOpt,
+    /// path of the archive to create, on the node's host
+    path: PathBuf,
+}
+
+#[derive(clap::Parser, Clone, Debug)]
+/// restore a backup archive on the node's host into an empty working directory
+pub struct RestoreOpts {
+    #[command(flatten)]
+    console_opt: ConsoleOpt,
+    /// path of the archive to restore, on the node's host
+    path: PathBuf,
+    /// empty directory on the node's host to restore into, a node can be started from it afterwards
+    working_dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BackupOutput {
+    Progress(BackupProgress),
+    Done(BackupManifest),
+}
+
+/// Send `request` and yield the progress updates of the node followed by the manifest
+fn request(console_opt: ConsoleOpt, request: AdminRequest) -> impl Stream<Item = ActyxOSResult<BackupOutput>> + Unpin {
+    GenStream::new(move |co| async move {
+        let (mut conn, peer) = console_opt.connect().await?;
+        let (tx, mut rx) = mpsc::channel(10);
+        conn.feed(Task::Admin(peer, request, tx)).await?;
+        while let Some(response) = rx.next().await {
+            let output = match response? {
+                AdminResponse::BackupProgress(progress) => BackupOutput::Progress(progress),
+                AdminResponse::BackupCreateResponse(manifest) | AdminResponse::BackupRestoreResponse(manifest) => {
+                    BackupOutput::Done(manifest)
+                }
+                x => return Err(ActyxOSError::internal(format!("Unexpected reply: {:?}", x))),
+            };
+            co.yield_(Ok(Some(output))).await;
+        }
+        Ok(None)
+    })
+    .filter_map(|x| ready(x.transpose()))
+}
+
+fn pretty(result: BackupOutput) -> String {
+    match result {
+        BackupOutput::Progress(BackupProgress {
+            stage,
+            path,
+            done,
+            total,
+        }) => {
+            let stage = match stage {
+                BackupStage::Snapshot => "copying",
+                BackupStage::Archive => "archiving",
+                BackupStage::Verify => "verifying",
+                BackupStage::Unpack => "unpacking",
+            };
+            format!("{} {}/{}: {}", stage, done, total, path)
+        }
+        BackupOutput::Done(manifest) => format!(
+            "node {} on topic {}: {} files, {} events",
+            manifest.node_id,
+            manifest.topic,
+            manifest.files.len(),
+            manifest.offsets.size()
+        ),
+    }
+}
+
+pub struct NodesBackup;
+impl AxCliCommand for NodesBackup {
+    type Opt = BackupOpts;
+    type Output = BackupOutput;
+
+    fn run(opts: Self::Opt) -> Box<dyn Stream<Item = ActyxOSResult<Self::Output>> + Unpin> {
+        Box::new(request(
+            opts.console_opt,
+            AdminRequest::BackupCreate { path: opts.path },
+        ))
+    }
+
+    fn pretty(result: Self::Output) -> String {
+        pretty(result)
+    }
+}
+
+pub struct NodesRestore;
+impl AxCliCommand for NodesRestore {
+    type Opt = RestoreOpts;
+    type Output = BackupOutput;
+
+    fn run(opts: Self::Opt) -> Box<dyn Stream<Item = ActyxOSResult<Self::Output>> + Unpin> {
+        Box::new(request(
+            opts.console_opt,
+            AdminRequest::BackupRestore {
+                path: opts.path,
+                working_dir: opts.working_dir,
+            },
+        ))
+    }
+
+    fn pretty(result: Self::Output) -> String {
+        pretty(result)
+    }
+}
@@ -93,6 +93,35 @@ impl AxCliCommand for NodesInspect {
             writeln!(&mut s, "    {}", addr).unwrap();
         }
 
+        if !result.admin_socket_options.is_empty() {
+            writeln!(&mut s, "AdminSocketOptions:").unwrap();
+            for option in &result.admin_socket_options {
+                writeln!(&mut s, "    {}", option).unwrap();
+            }
+        }
+
+        if let Some(millis) = result.gossip_interval_millis {
+            writeln!(&mut s, "GossipInterval: {}ms", millis).unwrap();
+        }
+
+        if let Some(maintenance) = &result.maintenance {
+            match &maintenance.window_end {
+                Some(end) if maintenance.in_window => writeln!(&mut s, "Maintenance: in window until {}", end),
+                _ => writeln!(&mut s, "Maintenance: outside of windows"),
+            }
+            .unwrap();
+            if let Some(next) = &maintenance.next_window {
+                writeln!(&mut s, "NextMaintenanceWindow: {}", next).unwrap();
+            }
+        }
+
+        if !result.lamport_warnings.is_empty() {
+            writeln!(&mut s, "LamportWarnings:").unwrap();
+            for warning in &result.lamport_warnings {
+                writeln!(&mut s, "    {}", warning).unwrap();
+            }
+        }
+
         writeln!(&mut s, "Connections:").unwrap();
         if result.connections.is_empty() {
             writeln!(&mut s, "  none").unwrap();
@@ -119,6 +148,57 @@ impl AxCliCommand for NodesInspect {
             writeln!(&mut s, "{}", table).unwrap();
         }
 
+        if !result.quarantined_peers.is_empty() {
+            writeln!(&mut s, "QuarantinedPeers:").unwrap();
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL_CONDENSED)
+                .set_header(["PEERID", "REMAINING", "REASON"]);
+            for row in &result.quarantined_peers {
+                table.add_row([
+                    Cell::new(&row.peer_id),
+                    Cell::new(format!("{}s", row.remaining_secs)),
+                    Cell::new(&row.reason),
+                ]);
+            }
+            writeln!(&mut s, "{}", table).unwrap();
+        }
+
+        if !result.previous_topics.is_empty() {
+            writeln!(&mut s, "PreviousTopics:").unwrap();
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL_CONDENSED)
+                .set_header(["TOPIC", "MESSAGES", "LAST_RECEIVED", "REMAINING"]);
+            for row in &result.previous_topics {
+                table.add_row([
+                    Cell::new(&row.topic),
+                    Cell::new(row.messages),
+                    Cell::new(row.last_received.as_deref().unwrap_or("never")),
+                    Cell::new(format!("{}s", row.remaining_secs)),
+                ]);
+            }
+            writeln!(&mut s, "{}", table).unwrap();
+        }
+
+        if !result.peer_quality.is_empty() {
+            writeln!(&mut s, "PeerQuality:").unwrap();
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL_CONDENSED)
+                .set_header(["NODEID", "RTT", "PINGS", "FAILURES", "LAST_SEEN"]);
+            for row in &result.peer_quality {
+                table.add_row([
+                    Cell::new(&row.node_id),
+                    Cell::new(row.rtt_micros.map(format_micros).unwrap_or_else(|| "-".to_owned())),
+                    Cell::new(row.pings),
+                    Cell::new(row.failures),
+                    Cell::new(&row.last_seen),
+                ]);
+            }
+            writeln!(&mut s, "{}", table).unwrap();
+        }
+
         let mut failures = Vec::new();
         let mut ping = Table::new();
         ping.load_preset(UTF8_FULL_CONDENSED).set_header([
@@ -201,7 +281,8 @@ impl AxCliCommand for NodesInspect {
     }
 }
 
-fn format_micros(n: u32) -> String {
+fn format_micros(n: impl Into<u64>) -> String {
+    let n = n.into();
     if n >= 10_000 {
         format!("{}ms", (n + 500) / 1000)
     } else {
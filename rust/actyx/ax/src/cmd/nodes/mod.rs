@@ -1,3 +1,4 @@
+mod backup;
 mod inspect;
 mod ls;
 
@@ -13,11 +14,17 @@ pub enum NodesOpts {
     Ls(LsOpts),
     /// Show node details and connections
     Inspect(InspectOpts),
+    /// Write a backup of the node into an archive on its host
+    Backup(backup::BackupOpts),
+    /// Restore a backup archive on the node's host into an empty working directory
+    Restore(backup::RestoreOpts),
 }
 
 pub fn run(opts: NodesOpts, json: bool) -> Box<dyn Future<Output = ()> + Unpin> {
     match opts {
         NodesOpts::Ls(opt) => ls::NodesLs::output(opt, json),
         NodesOpts::Inspect(opt) => inspect::NodesInspect::output(opt, json),
+        NodesOpts::Backup(opt) => backup::NodesBackup::output(opt, json),
+        NodesOpts::Restore(opt) => backup::NodesRestore::output(opt, json),
     }
 }
@@ -61,6 +61,10 @@ pub struct RunOpts {
     #[arg(short, long, hide = true)]
     pub random: bool,
 
+    /// Show what migrating the node database would change, without changing it, and exit.
+    #[arg(long)]
+    pub migration_dry_run: bool,
+
     /// Control whether to use ANSI color sequences in log output.
     #[arg(
         long,
@@ -120,49 +124,12 @@ pub struct BindToOpts {
 impl TryInto<BindTo> for BindToOpts {
     type Error = anyhow::Error;
     fn try_into(self) -> anyhow::Result<BindTo> {
-        let api = fold(
+        let api = PortOrHostPort::fold(
             |port| SocketAddrHelper::from_ip_port(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
             self.bind_api,
         )?;
-        let admin = fold(SocketAddrHelper::unspecified, self.bind_admin)?;
-        let swarm = fold(SocketAddrHelper::unspecified, self.bind_swarm)?;
+        let admin = PortOrHostPort::fold(SocketAddrHelper::unspecified, self.bind_admin)?;
+        let swarm = PortOrHostPort::fold(SocketAddrHelper::unspecified, self.bind_swarm)?;
         Ok(BindTo { admin, swarm, api })
     }
 }
-
-fn fold<const N: u16>(
-    port: impl FnOnce(u16) -> anyhow::Result<SocketAddrHelper>,
-    input: Vec<PortOrHostPort<N>>,
-) -> anyhow::Result<SocketAddrHelper> {
-    if input.is_empty() {
-        anyhow::bail!("no value provided");
-    }
-    let mut found_port = None;
-    let mut host_port: Option<SocketAddrHelper> = None;
-    for i in input.into_iter() {
-        match i {
-            PortOrHostPort::Port(p) => {
-                if found_port.is_some() {
-                    anyhow::bail!("Multiple single port directives not supported");
-                } else if host_port.is_some() {
-                    anyhow::bail!("Both port directive and host:port combination not supported");
-                } else {
-                    found_port.replace(p);
-                }
-            }
-            PortOrHostPort::HostPort(addr) => {
-                if found_port.is_some() {
-                    anyhow::bail!("Both port directive and host:port combination not supported");
-                } else if let Some(x) = host_port.as_mut() {
-                    x.append(addr);
-                } else {
-                    let _ = host_port.replace(addr);
-                }
-            }
-        }
-    }
-    found_port
-        .map(port)
-        .or_else(|| host_port.map(Ok))
-        .expect("Input must not be empty")
-}
@@ -1,6 +1,6 @@
 use crate::cmd::AxCliCommand;
 use ax_core::{
-    certs::{AppDomain, DeveloperCertificateInput, ManifestDeveloperCertificate},
+    certs::{AppDomain, DeveloperCertificate},
     crypto::{PrivateKey, PublicKey},
     util::formats::{ActyxOSCode, ActyxOSResult, ActyxOSResultExt},
 };
@@ -31,10 +31,9 @@ impl AxCliCommand for UsersDevCert {
     fn run(opts: Self::Opt) -> Box<dyn Stream<Item = ActyxOSResult<Self::Output>> + Unpin> {
         Box::new(once(
             async move {
-                let input = DeveloperCertificateInput::new(opts.dev_public_key, opts.app_domains);
-                let dev_cert = ManifestDeveloperCertificate::new(input, opts.ax_secret_key)
+                let dev_cert = DeveloperCertificate::create(opts.dev_public_key, opts.app_domains, opts.ax_secret_key)
                     .ax_err(ActyxOSCode::ERR_INTERNAL_ERROR)?;
-                serde_json::to_string(&dev_cert).ax_err(ActyxOSCode::ERR_INTERNAL_ERROR)
+                serde_json::to_string(&dev_cert.manifest_dev_cert()).ax_err(ActyxOSCode::ERR_INTERNAL_ERROR)
             }
             .boxed(),
         ))
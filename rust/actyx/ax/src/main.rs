@@ -11,7 +11,7 @@ use crate::cmd::{
 };
 use anyhow::{Context, Result};
 use ax_core::{
-    node::{init_shutdown_ceremony, shutdown_ceremony, ApplicationState, BindTo, Runtime},
+    node::{init_shutdown_ceremony, migration, shutdown_ceremony, ApplicationState, BindTo, Runtime},
     util::version::NodeVersion,
     NODE_VERSION,
 };
@@ -141,6 +141,7 @@ pub fn run(
         working_dir,
         bind_options,
         random,
+        migration_dry_run,
         log_color,
         log_json,
     }: RunOpts,
@@ -176,6 +177,28 @@ pub fn run(
     // printed by hand since things can fail before logging is set up and we want the user to know this
     eprintln!("using data directory `{}`", working_dir.display());
 
+    if migration_dry_run {
+        match migration::preview_migration(&working_dir)? {
+            None => println!("no node database found, nothing to migrate"),
+            Some(report) if report.steps.is_empty() => {
+                println!("node database is at version {}, nothing to migrate", report.from)
+            }
+            Some(report) => {
+                println!(
+                    "migrating the node database from version {} to {} would run",
+                    report.from, report.to
+                );
+                for step in report.steps {
+                    println!("  {} -> {}: {}", step.from, step.to, step.description);
+                    for statement in step.statements {
+                        println!("    {} ({} rows)", statement.sql, statement.rows);
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
     // must be done before starting the application
     init_shutdown_ceremony();
 
@@ -0,0 +1,252 @@
+//! The configuration passed to [`axnode_init_with_config`](crate::axnode_init_with_config).
+//!
+//! It is a JSON object, all of whose properties are optional:
+//!
+//! ```json
+//! {
+//!   "bind_admin": "4458",
+//!   "bind_swarm": ["0.0.0.0:4001", "[::]:4001"],
+//!   "bind_api": "localhost",
+//!   "settings": { "admin": { "displayName": "tablet" } }
+//! }
+//! ```
+//!
+//! The bind entries take a string or a list of strings, with the same rules as the `--bind-*`
+//! options of `ax run`. `settings` are the node settings stored for the `com.actyx` scope unless
+//! some have been stored already.
+use anyhow::{Context, Result};
+use ax_core::{
+    node::{initialize_repository, settings::system_scope, BindTo, PortOrHostPort},
+    settings::Scope,
+    util::SocketAddrHelper,
+};
+use serde::Deserialize;
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::Path,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigJson {
+    bind_admin: Option<OneOrMany>,
+    bind_swarm: Option<OneOrMany>,
+    bind_api: Option<OneOrMany>,
+    settings: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn parse<const N: u16>(self, field: &str) -> Result<Vec<PortOrHostPort<N>>> {
+        let values = match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        };
+        values
+            .iter()
+            .map(|value| {
+                value
+                    .parse::<PortOrHostPort<N>>()
+                    .map_err(|e| anyhow::anyhow!("invalid `{}`: {}", field, e))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Config {
+    pub bind_to: BindTo,
+    /// settings for the `com.actyx` scope
+    pub settings: Option<serde_json::Value>,
+}
+
+impl Config {
+    /// Parses the configuration, a missing or blank document means the defaults.
+    pub fn parse(json: Option<&str>) -> Result<Self> {
+        let json = match json {
+            Some(json) if !json.trim().is_empty() => json,
+            _ => return Ok(Self::default()),
+        };
+        let config: ConfigJson = serde_json::from_str(json).context("parsing node configuration")?;
+        let defaults = BindTo::default();
+        let admin = bind(
+            config.bind_admin,
+            "bind_admin",
+            defaults.admin,
+            SocketAddrHelper::unspecified,
+        )?;
+        let swarm = bind(
+            config.bind_swarm,
+            "bind_swarm",
+            defaults.swarm,
+            SocketAddrHelper::unspecified,
+        )?;
+        let api = bind(config.bind_api, "bind_api", defaults.api, |port| {
+            SocketAddrHelper::from_ip_port(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
+        })?;
+        let settings = match config.settings {
+            Some(settings) if !settings.is_object() => anyhow::bail!("invalid `settings`: must be a JSON object"),
+            settings => settings,
+        };
+        Ok(Self {
+            bind_to: BindTo { admin, swarm, api },
+            settings,
+        })
+    }
+
+    /// Stores the settings in the repository of `working_dir` if there are none yet, so that the
+    /// node starts with them. Settings changed later on are kept across restarts.
+    pub fn apply_settings(&self, working_dir: &Path) -> Result<()> {
+        let settings = match &self.settings {
+            Some(settings) => settings,
+            None => return Ok(()),
+        };
+        let repo = initialize_repository(working_dir).context("opening settings repository")?;
+        let stored = repo.get_settings(&Scope::root(), true).ok();
+        let scope = system_scope();
+        if stored.and_then(|s| s.pointer(&scope.as_json_ptr()).cloned()).is_some() {
+            tracing::debug!("settings already stored, ignoring initial settings");
+            return Ok(());
+        }
+        repo.update_settings(&scope, settings.clone(), false)
+            .context("applying initial settings")?;
+        Ok(())
+    }
+}
+
+fn bind<const N: u16>(
+    value: Option<OneOrMany>,
+    field: &str,
+    default: SocketAddrHelper,
+    port: impl FnOnce(u16) -> Result<SocketAddrHelper>,
+) -> Result<SocketAddrHelper> {
+    match value {
+        Some(value) => {
+            PortOrHostPort::<N>::fold(port, value.parse(field)?).with_context(|| format!("invalid `{}`", field))
+        }
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn addrs(helper: &SocketAddrHelper) -> Vec<SocketAddr> {
+        let mut addrs = helper.iter().collect::<Vec<_>>();
+        addrs.sort();
+        addrs
+    }
+
+    fn error(json: &str) -> String {
+        format!("{:#}", Config::parse(Some(json)).unwrap_err())
+    }
+
+    #[test]
+    fn defaults() {
+        let default = BindTo::default();
+        for json in [None, Some(""), Some("  "), Some("{}")] {
+            let config = Config::parse(json).unwrap();
+            assert_eq!(addrs(&config.bind_to.admin), addrs(&default.admin));
+            assert_eq!(addrs(&config.bind_to.swarm), addrs(&default.swarm));
+            assert_eq!(addrs(&config.bind_to.api), addrs(&default.api));
+            assert!(config.settings.is_none());
+        }
+    }
+
+    #[test]
+    fn ports() {
+        let config = Config::parse(Some(r#"{"bind_admin":"1234","bind_swarm":["1235"],"bind_api":"1236"}"#)).unwrap();
+        assert_eq!(
+            addrs(&config.bind_to.admin),
+            addrs(&SocketAddrHelper::unspecified(1234).unwrap())
+        );
+        assert_eq!(
+            addrs(&config.bind_to.swarm),
+            addrs(&SocketAddrHelper::unspecified(1235).unwrap())
+        );
+        assert_eq!(addrs(&config.bind_to.api), vec!["127.0.0.1:1236".parse().unwrap()]);
+    }
+
+    #[test]
+    fn host_ports() {
+        let config = Config::parse(Some(
+            r#"{"bind_admin":["127.0.0.1:1234","/ip4/127.0.0.2/tcp/1234"],"bind_api":"127.0.0.3"}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            addrs(&config.bind_to.admin),
+            vec!["127.0.0.1:1234".parse().unwrap(), "127.0.0.2:1234".parse().unwrap()]
+        );
+        // the default port of the endpoint applies
+        assert_eq!(addrs(&config.bind_to.api), vec!["127.0.0.3:4454".parse().unwrap()]);
+        assert_eq!(
+            addrs(&config.bind_to.swarm),
+            addrs(&SocketAddrHelper::unspecified(4001).unwrap())
+        );
+    }
+
+    #[test]
+    fn settings() {
+        let config = Config::parse(Some(r#"{"settings":{"admin":{"displayName":"tablet"}}}"#)).unwrap();
+        assert_eq!(
+            config.settings,
+            Some(serde_json::json!({"admin": {"displayName": "tablet"}}))
+        );
+        assert_eq!(error(r#"{"settings":42}"#), "invalid `settings`: must be a JSON object");
+    }
+
+    #[test]
+    fn apply_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let display_name = || {
+            initialize_repository(dir.path())
+                .unwrap()
+                .get_settings(&system_scope(), false)
+                .unwrap()["admin"]["displayName"]
+                .clone()
+        };
+        let config = |name: &str| {
+            Config::parse(Some(&format!(
+                r#"{{"settings":{{"admin":{{"displayName":"{}"}}}}}}"#,
+                name
+            )))
+            .unwrap()
+        };
+
+        // nothing is stored without settings, so the initial settings below still apply
+        Config::parse(None).unwrap().apply_settings(dir.path()).unwrap();
+        config("tablet").apply_settings(dir.path()).unwrap();
+        assert_eq!(display_name(), "tablet");
+        // settings stored before, e.g. changed by the user, are kept
+        config("phone").apply_settings(dir.path()).unwrap();
+        assert_eq!(display_name(), "tablet");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(error("[]").starts_with("parsing node configuration: "));
+        assert!(error(r#"{"bind_admin":"#).starts_with("parsing node configuration: "));
+        assert!(error(r#"{"bind_amdin":"1234"}"#).contains("unknown field `bind_amdin`"));
+        assert!(error(r#"{"bind_swarm":42}"#).starts_with("parsing node configuration: "));
+        assert!(
+            error(r#"{"bind_api":"not a host!"}"#).starts_with("invalid `bind_api`: cannot interpret `not a host!`")
+        );
+        assert_eq!(
+            error(r#"{"bind_admin":["1234","1235"]}"#),
+            "invalid `bind_admin`: Multiple single port directives not supported"
+        );
+        assert_eq!(
+            error(r#"{"bind_swarm":["1234","127.0.0.1:1235"]}"#),
+            "invalid `bind_swarm`: Both port directive and host:port combination not supported"
+        );
+        assert_eq!(error(r#"{"bind_api":[]}"#), "invalid `bind_api`: no value provided");
+    }
+}
@@ -1,11 +1,14 @@
 #![deny(clippy::future_not_send)]
 
-use ax_core::node::{spawn_with_name, ApplicationState, BindTo, NodeError, Runtime, ShutdownReason};
+mod config;
+
+use ax_core::node::{spawn_with_name, ApplicationState, NodeError, Runtime, ShutdownReason};
+use config::Config;
 use crossbeam::channel::bounded;
 use ffi_support::{ErrorCode, ExternError, FfiStr};
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
-use std::{convert::TryFrom, os::raw::c_char, sync::Arc};
+use std::{convert::TryFrom, os::raw::c_char, path::Path, sync::Arc};
 
 lazy_static! {
     static ref STATE: Mutex<Option<ApplicationState>> = Mutex::new(None);
@@ -21,16 +24,37 @@ type Callback = unsafe extern "C" fn(i32, *mut c_char) -> ();
 /// be created under `working_dir`.
 /// A callback must be installed, with which messages are conveyed across the FFI
 /// boundary.
+/// Same as [`axnode_init_with_config`] with the default configuration.
 pub extern "C" fn axnode_init(working_dir: FfiStr, callback: Callback, error: &mut ExternError) {
+    // null is a valid `FfiStr`, meaning no config
+    let config_json = unsafe { FfiStr::from_raw(std::ptr::null()) };
+    axnode_init_with_config(working_dir, config_json, callback, error)
+}
+
+#[no_mangle]
+/// Like [`axnode_init`], additionally taking the ports and interfaces to bind to and the initial
+/// node settings as `config_json`, see the `config` module for its format. A null or empty
+/// `config_json` means the defaults.
+pub extern "C" fn axnode_init_with_config(
+    working_dir: FfiStr,
+    config_json: FfiStr,
+    callback: Callback,
+    error: &mut ExternError,
+) {
     ffi_support::call_with_result(error, || {
+        let config = Config::parse(config_json.as_opt_str())
+            .map_err(|e| ExternError::new_error(ErrorCode::new(42), format!("invalid config: {:#}", e)))?;
         callback_holder::set_callback(callback);
         let (ffi_sink, rx) = bounded(32);
         let mut state = STATE.lock();
         if state.is_none() {
+            config
+                .apply_settings(Path::new(working_dir.as_str()))
+                .map_err(|e| ExternError::new_error(ErrorCode::new(42), format!("{:#}", e)))?;
             match ApplicationState::spawn(
                 working_dir.as_str().into(),
                 Runtime::Android { ffi_sink },
-                BindTo::default(),
+                config.bind_to,
                 true,
                 false,
             ) {
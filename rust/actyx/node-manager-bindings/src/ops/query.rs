@@ -35,6 +35,10 @@ async fn do_query(mut tx: Sender<Task>, peer: PeerId, query: String) -> ActyxOSR
             upper_bound: None,
             query,
             order: Order::Asc,
+            progress: None,
+            inline_blobs: None,
+            checksum: None,
+            timeout_millis: None,
         }),
     )
     .await;
@@ -1,13 +1,13 @@
 use anyhow::Result;
 use ax_core::{
     crypto::{KeyPair, PrivateKey},
-    swarm::{BanyanConfig, SwarmConfig},
+    swarm::{BanyanConfig, RootMapCadence, SwarmConfig},
     trees::axtrees::AxKey,
     util::SocketAddrHelper,
 };
 use ax_sdk::{
     aql::Query,
-    types::{Payload, TagSet, Timestamp},
+    types::{LamportTimestamp, Offset, OffsetMap, Payload, StreamId, TagSet, Timestamp},
 };
 use cbor_data::{
     codec::{ReadCbor, WriteCbor},
@@ -15,13 +15,25 @@ use cbor_data::{
 };
 use chrono::{DateTime, Utc};
 use parking_lot::Mutex;
-use std::{borrow::Borrow, convert::TryFrom, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 use structopt::StructOpt;
 
-pub use ax_core::swarm::{EphemeralEventsConfig, EventRoute, GossipMessage, RetainConfig, RootMap, RootUpdate};
+pub use ax_core::swarm::{
+    Decision, DecisionRecord, EphemeralEventsConfig, EventRoute, GossipMessage, NodeOffsets, OffsetsComparison,
+    RetainConfig, RootMap, RootUpdate,
+};
+pub use ipfs_embed::Cid;
 pub use libp2p::{multiaddr, Multiaddr, PeerId};
 
-#[derive(Clone, Debug, StructOpt)]
+#[derive(Clone, Debug, Default, StructOpt)]
 pub struct Config {
     #[structopt(long)]
     pub path: Option<PathBuf>,
@@ -55,6 +67,22 @@ pub struct Config {
     pub max_leaf_count: Option<usize>,
     #[structopt(long)]
     pub event_routes: Vec<EventRoute>,
+    #[structopt(long)]
+    pub root_map_interval_ms: Option<u64>,
+    #[structopt(long)]
+    pub root_map_max_interval_ms: Option<u64>,
+    #[structopt(long)]
+    pub max_connections: Option<u32>,
+    #[structopt(long)]
+    pub tombstone_retention_ms: Option<u64>,
+    #[structopt(long)]
+    pub block_gc_interval_ms: Option<u64>,
+    #[structopt(long)]
+    pub gc_grace_period_ms: Option<u64>,
+    #[structopt(long)]
+    pub read_only: bool,
+    #[structopt(long)]
+    pub decision_log_path: Option<PathBuf>,
 }
 
 impl From<Config> for async_process::Command {
@@ -109,6 +137,30 @@ impl From<Config> for async_process::Command {
         if let Some(x) = config.max_leaf_count {
             cmd.arg("--max-leaf-count").arg(x.to_string());
         }
+        if let Some(x) = config.root_map_interval_ms {
+            cmd.arg("--root-map-interval-ms").arg(x.to_string());
+        }
+        if let Some(x) = config.root_map_max_interval_ms {
+            cmd.arg("--root-map-max-interval-ms").arg(x.to_string());
+        }
+        if let Some(x) = config.max_connections {
+            cmd.arg("--max-connections").arg(x.to_string());
+        }
+        if let Some(x) = config.tombstone_retention_ms {
+            cmd.arg("--tombstone-retention-ms").arg(x.to_string());
+        }
+        if let Some(x) = config.block_gc_interval_ms {
+            cmd.arg("--block-gc-interval-ms").arg(x.to_string());
+        }
+        if let Some(x) = config.gc_grace_period_ms {
+            cmd.arg("--gc-grace-period-ms").arg(x.to_string());
+        }
+        if config.read_only {
+            cmd.arg("--read-only");
+        }
+        if let Some(path) = config.decision_log_path.as_ref() {
+            cmd.arg("--decision-log-path").arg(path);
+        }
         for route in config.event_routes {
             cmd.arg("--event-routes")
                 .arg(format!("[\"{}\", \"{}\"]", route.from, route.into));
@@ -133,7 +185,16 @@ impl From<Config> for SwarmConfig {
                     acc
                 }),
         ));
+        let cadence_root_map = match (config.root_map_interval_ms, config.root_map_max_interval_ms) {
+            (None, None) => SwarmConfig::basic().cadence_root_map,
+            (min, max) => {
+                let min = Duration::from_millis(min.unwrap_or(10_000));
+                let max = max.map(Duration::from_millis).unwrap_or(min);
+                RootMapCadence::adaptive(min, max)
+            }
+        };
         Self {
+            index_store: config.path.as_ref().map(|path| path.with_extension("index")),
             db_path: config.path,
             node_name: config.node_name,
             keypair: Some(keypair(config.keypair)),
@@ -147,9 +208,22 @@ impl From<Config> for SwarmConfig {
             enable_root_map: config.enable_root_map,
             enable_discovery: config.enable_discovery,
             enable_metrics: config.enable_metrics,
-            ephemeral_event_config: config.ephemeral_events.unwrap_or_else(EphemeralEventsConfig::disable),
+            ephemeral_event_config: config.ephemeral_events.unwrap_or(EphemeralEventsConfig::Disabled),
             banyan_config,
             event_routes: config.event_routes,
+            cadence_root_map,
+            max_connections: config.max_connections,
+            tombstone_retention: config
+                .tombstone_retention_ms
+                .map(Duration::from_millis)
+                .unwrap_or_else(|| SwarmConfig::basic().tombstone_retention),
+            block_gc_interval: config
+                .block_gc_interval_ms
+                .map(Duration::from_millis)
+                .unwrap_or_else(|| SwarmConfig::basic().block_gc_interval),
+            gc_grace_period: config.gc_grace_period_ms.map(Duration::from_millis),
+            read_only: config.read_only,
+            decision_log_path: config.decision_log_path,
             ..SwarmConfig::basic()
         }
     }
@@ -165,9 +239,25 @@ pub fn keypair(i: u64) -> KeyPair {
 pub enum Command {
     AddAddress(PeerId, Multiaddr),
     Append(Vec<(TagSet, Payload)>),
+    /// Append to a local stream given by number or by name, see `BanyanStore::stream_for_name`
+    AppendTo(String, Vec<(TagSet, Payload)>),
     SubscribeQuery(Query<'static>),
     ApiPort,
     GossipSubscribe(String),
+    /// Publish a message on a gossipsub topic, encoded like the `<gossip` event
+    GossipPublish(String, GossipMessage),
+    /// Send a message on the broadcast protocol, encoded like the `<gossip` event
+    Broadcast(String, GossipMessage),
+    /// Tombstone one of the streams of this node, giving a reason
+    Tombstone(StreamId, String),
+    Offsets,
+    Streams,
+    RootMap,
+    /// The last records of the decision log
+    DecisionLog(usize),
+    /// Compare the offsets of this node with those of a connected peer
+    CompareOffsets(PeerId),
+    Exit,
 }
 
 impl std::fmt::Display for Command {
@@ -175,9 +265,23 @@ impl std::fmt::Display for Command {
         match self {
             Self::AddAddress(peer, addr) => write!(f, ">add-address {} {}", peer, addr)?,
             Self::Append(events) => write!(f, ">append {}", serde_json::to_string(events).unwrap())?,
+            Self::AppendTo(stream, events) => {
+                write!(f, ">append-to {} {}", stream, serde_json::to_string(events).unwrap())?
+            }
             Self::SubscribeQuery(expr) => write!(f, ">query {}", expr)?,
             Self::ApiPort => write!(f, ">api-port")?,
             Self::GossipSubscribe(topic) => write!(f, ">gossip-subscribe {}", topic)?,
+            Self::GossipPublish(topic, message) => {
+                write!(f, ">gossip-publish {} {}", topic, encode_gossip(message))?
+            }
+            Self::Broadcast(topic, message) => write!(f, ">broadcast {} {}", topic, encode_gossip(message))?,
+            Self::Tombstone(stream, reason) => write!(f, ">tombstone {} {}", stream, reason)?,
+            Self::Offsets => write!(f, ">offsets")?,
+            Self::Streams => write!(f, ">streams")?,
+            Self::RootMap => write!(f, ">root-map")?,
+            Self::DecisionLog(n) => write!(f, ">decision-log {}", n)?,
+            Self::CompareOffsets(peer) => write!(f, ">compare-offsets {}", peer)?,
+            Self::Exit => write!(f, ">exit")?,
         }
         Ok(())
     }
@@ -199,8 +303,31 @@ impl std::str::FromStr for Command {
                 let events = serde_json::from_str(s.split_at(8).1).unwrap();
                 Self::Append(events)
             }
+            Some(">append-to") => {
+                let stream = parts.next().unwrap();
+                let events = serde_json::from_str(s.split_at(12 + stream.len()).1)?;
+                Self::AppendTo(stream.into(), events)
+            }
             Some(">api-port") => Self::ApiPort,
             Some(">gossip-subscribe") => Self::GossipSubscribe(parts.next().unwrap().into()),
+            Some(">gossip-publish") => {
+                let topic = parts.next().unwrap().into();
+                Self::GossipPublish(topic, decode_gossip(parts.next().unwrap())?)
+            }
+            Some(">broadcast") => {
+                let topic = parts.next().unwrap().into();
+                Self::Broadcast(topic, decode_gossip(parts.next().unwrap())?)
+            }
+            Some(">tombstone") => {
+                let stream = parts.next().unwrap().parse()?;
+                Self::Tombstone(stream, parts.collect::<Vec<_>>().join(" "))
+            }
+            Some(">offsets") => Self::Offsets,
+            Some(">streams") => Self::Streams,
+            Some(">root-map") => Self::RootMap,
+            Some(">decision-log") => Self::DecisionLog(parts.next().unwrap().parse()?),
+            Some(">compare-offsets") => Self::CompareOffsets(parts.next().unwrap().parse()?),
+            Some(">exit") => Self::Exit,
             _ => {
                 return Err(anyhow::anyhow!("invalid command '{}'", s));
             }
@@ -223,6 +350,16 @@ pub enum Event {
     Result((u64, AxKey, Payload)),
     ApiPort(Option<u16>),
     GossipEvent(String, PeerId, GossipMessage),
+    /// Present offsets and replication target of the store
+    Offsets { present: OffsetMap, target: OffsetMap },
+    Streams(BTreeSet<StreamId>),
+    RootMap(BTreeMap<StreamId, (Cid, Offset, LamportTimestamp)>),
+    /// An append was rejected, e.g. because the node is a read-only replica
+    AppendFailed(String),
+    DecisionLog(Vec<DecisionRecord>),
+    OffsetsCompared(Box<OffsetsComparison>),
+    /// The peer could not be reached for comparing offsets
+    CompareOffsetsFailed(String),
 }
 
 impl std::fmt::Display for Event {
@@ -269,8 +406,33 @@ impl std::fmt::Display for Event {
                 }
             }
             Self::GossipEvent(topic, sender, message) => {
-                let cbor = message.write_cbor(CborBuilder::default());
-                write!(f, "<gossip {} {} {}", topic, sender, hex::encode(cbor))?;
+                write!(f, "<gossip {} {} {}", topic, sender, encode_gossip(message))?;
+            }
+            Self::Offsets { present, target } => {
+                write!(f, "<offsets {}", serde_json::to_string(&(present, target)).unwrap())?;
+            }
+            Self::Streams(streams) => {
+                write!(f, "<streams {}", serde_json::to_string(streams).unwrap())?;
+            }
+            Self::RootMap(root_map) => {
+                // cids are written in their string form
+                let root_map = root_map
+                    .iter()
+                    .map(|(stream, (cid, offset, lamport))| (stream, (cid.to_string(), offset, lamport)))
+                    .collect::<BTreeMap<_, _>>();
+                write!(f, "<root-map {}", serde_json::to_string(&root_map).unwrap())?;
+            }
+            Self::AppendFailed(reason) => {
+                write!(f, "<append-failed {}", reason)?;
+            }
+            Self::DecisionLog(records) => {
+                write!(f, "<decision-log {}", serde_json::to_string(records).unwrap())?;
+            }
+            Self::OffsetsCompared(comparison) => {
+                write!(f, "<offsets-compared {}", serde_json::to_string(comparison).unwrap())?;
+            }
+            Self::CompareOffsetsFailed(reason) => {
+                write!(f, "<compare-offsets-failed {}", reason)?;
             }
         }
         Ok(())
@@ -311,10 +473,35 @@ impl std::str::FromStr for Event {
             Some("<gossip") => {
                 let topic = parts.next().unwrap().into();
                 let sender = parts.next().unwrap().parse()?;
-                let cbor: Vec<u8> = hex::decode(parts.next().unwrap())?;
-                let message = GossipMessage::read_cbor(Cbor::checked(&cbor[..])?)?;
-                Self::GossipEvent(topic, sender, message)
+                Self::GossipEvent(topic, sender, decode_gossip(parts.next().unwrap())?)
+            }
+            Some("<offsets") => {
+                let json: String = parts.collect();
+                let (present, target) = serde_json::from_str(&json)?;
+                Self::Offsets { present, target }
+            }
+            Some("<streams") => {
+                let json: String = parts.collect();
+                Self::Streams(serde_json::from_str(&json)?)
             }
+            Some("<root-map") => {
+                let json: String = parts.collect();
+                let root_map: BTreeMap<StreamId, (String, Offset, LamportTimestamp)> = serde_json::from_str(&json)?;
+                Self::RootMap(
+                    root_map
+                        .into_iter()
+                        .map(|(stream, (cid, offset, lamport))| Ok((stream, (cid.parse()?, offset, lamport))))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            Some("<append-failed") => Self::AppendFailed(parts.collect::<Vec<_>>().join(" ")),
+            // outcomes may contain spaces
+            Some("<decision-log") => Self::DecisionLog(serde_json::from_str(s.split_at(14).1)?),
+            Some("<offsets-compared") => {
+                let json: String = parts.collect();
+                Self::OffsetsCompared(serde_json::from_str(&json)?)
+            }
+            Some("<compare-offsets-failed") => Self::CompareOffsetsFailed(parts.collect::<Vec<_>>().join(" ")),
             _ => {
                 return Err(anyhow::anyhow!("invalid event '{}'", s));
             }
@@ -322,6 +509,16 @@ impl std::str::FromStr for Event {
     }
 }
 
+/// Hex-encoded CBOR, as sent over the wire
+fn encode_gossip(message: &GossipMessage) -> String {
+    hex::encode(message.write_cbor(CborBuilder::default()))
+}
+
+fn decode_gossip(s: &str) -> Result<GossipMessage> {
+    let cbor: Vec<u8> = hex::decode(s)?;
+    Ok(GossipMessage::read_cbor(Cbor::checked(&cbor[..])?)?)
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct TimedEvent {
     pub event: Event,
@@ -371,13 +568,32 @@ fn target_dir() -> std::path::PathBuf {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ax_sdk::types::tags;
+    use ax_sdk::types::{tags, NodeId};
+
+    fn root_update() -> GossipMessage {
+        let node_id: NodeId = keypair(0).into();
+        GossipMessage::RootUpdate(RootUpdate {
+            stream: node_id.stream(0.into()),
+            root: "bafybeih3rdoefyjmhg2wcu34njtwjc6kz44voehswqpr2dnplqjiv3opzi".parse().unwrap(),
+            blocks: vec![],
+            lamport: LamportTimestamp::new(7),
+            time: Timestamp::new(1),
+            offset: Some(Offset::from(3)),
+        })
+    }
 
     #[test]
     fn test_command() -> Result<()> {
         let command = &[
             Command::Append(vec![(tags!("a", "b"), Payload::from_json_str("{}").unwrap())]),
             Command::SubscribeQuery(Query::parse("FROM 'a' & 'b' | 'c'").unwrap()),
+            Command::Offsets,
+            Command::Streams,
+            Command::RootMap,
+            Command::GossipPublish("swarm-cli".into(), root_update()),
+            Command::Broadcast("swarm-cli".into(), root_update()),
+            Command::Tombstone(NodeId::from(keypair(0)).stream(0.into()), "decommissioned node".into()),
+            Command::Exit,
         ];
         for cmd in command.iter() {
             let cmd2: Command = cmd.to_string().parse()?;
@@ -388,11 +604,26 @@ mod tests {
 
     #[test]
     fn test_event() -> Result<()> {
-        let event = &[Event::Result((
-            0,
-            AxKey::new(tags!().into(), 0, 0),
-            Payload::from_json_str("{}").unwrap(),
-        ))];
+        let node_id: NodeId = keypair(0).into();
+        let stream = node_id.stream(0.into());
+        let present = [(stream, Offset::from(3))].into_iter().collect::<OffsetMap>();
+        let target = [(stream, Offset::from(5))].into_iter().collect::<OffsetMap>();
+        let cid = Cid::try_from("bafybeih3rdoefyjmhg2wcu34njtwjc6kz44voehswqpr2dnplqjiv3opzi")?;
+        let event = &[
+            Event::Result((
+                0,
+                AxKey::new(tags!().into(), 0, 0),
+                Payload::from_json_str("{}").unwrap(),
+            )),
+            Event::Offsets { present, target },
+            Event::Streams([stream].into_iter().collect()),
+            Event::RootMap(
+                [(stream, (cid, Offset::from(3), LamportTimestamp::new(7)))]
+                    .into_iter()
+                    .collect(),
+            ),
+            Event::GossipEvent("swarm-cli".into(), keypair(1).into(), root_update()),
+        ];
         for ev in event.iter() {
             let ev2: Event = ev.to_string().parse()?;
             assert_eq!(ev, &ev2);
@@ -7,15 +7,15 @@ use ax_core::{
     swarm::{
         blob_store::BlobStore,
         event_store_ref::{self, EventStoreHandler, EventStoreRef, EventStoreRequest},
-        BanyanStore, DbPath, GossipMessage, SwarmConfig,
+        BanyanStore, DbPath, GossipMessage, ReadOnlyError, SwarmConfig,
     },
     trees::{query::TagExprQuery, AxKey},
     util::variable::Writer,
 };
-use ax_sdk::types::{app_id, service::SwarmState, AppId, Payload};
+use ax_sdk::types::{app_id, service::SwarmState, AppId, Payload, StreamNr, Timestamp};
 use cbor_data::{
-    codec::{CodecError, ReadCbor},
-    Cbor,
+    codec::{CodecError, ReadCbor, WriteCbor},
+    Cbor, CborBuilder,
 };
 use futures::{stream::StreamExt, FutureExt, TryStreamExt};
 use ipfs_embed::GossipEvent;
@@ -248,8 +248,21 @@ async fn run(mut config: Config) -> Result<()> {
         stdin.read_line(&mut line).await?;
         match line.parse()? {
             Command::AddAddress(peer, addr) => swarm.ipfs().clone().add_address(peer, addr),
-            Command::Append(events) => {
-                swarm.append(app_id(), events).await?;
+            Command::Append(events) => match swarm.append(app_id(), events).await {
+                Ok(_) => {}
+                Err(err) if err.is::<ReadOnlyError>() => println!("{}", Event::AppendFailed(err.to_string())),
+                Err(err) => return Err(err),
+            },
+            Command::AppendTo(stream, events) => {
+                let stream_nr = match stream.parse::<u64>() {
+                    Ok(nr) => StreamNr::from(nr),
+                    Err(_) => swarm.stream_for_name(&stream)?,
+                };
+                match swarm.append0(stream_nr, app_id(), Timestamp::now(), events).await {
+                    Ok(_) => {}
+                    Err(err) if err.is::<ReadOnlyError>() => println!("{}", Event::AppendFailed(err.to_string())),
+                    Err(err) => return Err(err),
+                }
             }
             Command::SubscribeQuery(q) => {
                 let from = match q.source {
@@ -279,6 +292,55 @@ async fn run(mut config: Config) -> Result<()> {
             Command::ApiPort => {
                 println!("{}", Event::ApiPort(config.enable_api.map(|a| a.port())));
             }
+            Command::GossipPublish(topic, message) => {
+                let blob = message.write_cbor(CborBuilder::default()).into_vec();
+                if let Err(err) = swarm.ipfs().clone().publish(topic, blob).await {
+                    tracing::error!("publish failed: {}", err);
+                }
+            }
+            Command::Broadcast(topic, message) => {
+                let blob = message.write_cbor(CborBuilder::default()).into_vec();
+                if let Err(err) = swarm.ipfs().clone().broadcast(topic, blob).await {
+                    tracing::error!("broadcast failed: {}", err);
+                }
+            }
+            Command::Tombstone(stream, reason) => {
+                if let Err(err) = swarm.tombstone_stream(stream, reason).await {
+                    tracing::error!("tombstone failed: {}", err);
+                }
+            }
+            Command::Offsets => {
+                let offsets = swarm.offsets();
+                println!(
+                    "{}",
+                    Event::Offsets {
+                        present: offsets.present(),
+                        target: offsets.replication_target(),
+                    }
+                );
+            }
+            Command::Streams => {
+                println!("{}", Event::Streams(swarm.current_stream_ids()));
+            }
+            Command::RootMap => {
+                println!("{}", Event::RootMap(swarm.root_map()));
+            }
+            Command::DecisionLog(n) => {
+                println!("{}", Event::DecisionLog(swarm.decision_log_tail(n)));
+            }
+            Command::CompareOffsets(peer) => {
+                let swarm = swarm.clone();
+                tokio::spawn(async move {
+                    match swarm.compare_offsets(peer).await {
+                        Ok(comparison) => println!("{}", Event::OffsetsCompared(Box::new(comparison))),
+                        Err(err) => println!("{}", Event::CompareOffsetsFailed(format!("{:#}", err))),
+                    }
+                });
+            }
+            Command::Exit => {
+                tracing::info!("exiting on request");
+                return Ok(());
+            }
             Command::GossipSubscribe(topic) => {
                 let mut stream = swarm.ipfs().clone().subscribe(topic.clone()).await?;
                 tokio::spawn(async move {
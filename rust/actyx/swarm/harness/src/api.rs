@@ -1,15 +1,73 @@
 use crate::{m, pinned_resource::PinnedResource};
-use anyhow::{anyhow, Result};
-use async_std::task::block_on;
+use anyhow::{anyhow, bail, Result};
+use async_std::task::{block_on, sleep};
 use ax_sdk::{
-    types::{AppManifest, NodeId},
-    Ax, AxOpts, Url,
+    types::{
+        service::{EventResponse, OffsetsResponse, PublishEvent, PublishResponse, QueryResponse, SubscribeResponse},
+        AppManifest, NodeId, OffsetMap, Payload,
+    },
+    Ax, AxError, AxOpts, Url,
 };
-use futures::channel::oneshot::Canceled;
+use futures::{channel::oneshot::Canceled, future, Stream, StreamExt};
 use netsim_embed::{Machine, MachineId, Namespace, Netsim};
-use std::{borrow::Borrow, collections::BTreeMap, fmt::Display, future::Future, str::FromStr};
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fmt::{self, Display},
+    future::Future,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 use swarm_cli::{Command, Event};
 
+/// Failure of a call to the HTTP API of a machine
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request got no response, e.g. because the connection was refused
+    Connection(String),
+    /// The node responded with an error status
+    Http { status: u16, error: serde_json::Value },
+    /// Anything else, e.g. a response that could not be read
+    Other(anyhow::Error),
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Connection(cause) => write!(f, "no response from node: {}", cause),
+            ApiError::Http { status, error } => write!(f, "node responded with {}: {}", status, error),
+            ApiError::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(e) = e.chain().find_map(|e| e.downcast_ref::<reqwest::Error>()) {
+            if e.is_connect() || e.is_timeout() {
+                return ApiError::Connection(e.to_string());
+            }
+        }
+        match e.downcast::<AxError>() {
+            // the SDK reports transport errors with this code
+            Ok(e) if e.error_code == 101 => ApiError::Connection(format!("{} while {}", e.error, e.context)),
+            Ok(e) => ApiError::Http {
+                status: e.error_code,
+                error: e.error,
+            },
+            Err(e) => ApiError::Other(e),
+        }
+    }
+}
+
+impl From<Canceled> for ApiError {
+    fn from(_: Canceled) -> Self {
+        ApiError::Other(anyhow!("api client thread has stopped"))
+    }
+}
+
 pub struct Api {
     machines: BTreeMap<MachineId, ApiClient>,
 }
@@ -54,8 +112,35 @@ impl Api {
     {
         f(self.machines[&machine].clone()).await
     }
+
+    /// Poll the offsets of all machines until each of them has at least the `target` offsets.
+    pub async fn wait_for_offsets(&self, target: &OffsetMap, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        for (id, client) in &self.machines {
+            loop {
+                let present = client.offsets().await?.present;
+                if present >= *target {
+                    break;
+                }
+                if Instant::now() > deadline {
+                    bail!(
+                        "{} did not reach the offsets within {:.1}sec, missing {} events",
+                        id,
+                        timeout.as_secs_f64(),
+                        target - &present
+                    );
+                }
+                sleep(Duration::from_millis(500)).await;
+            }
+        }
+        Ok(())
+    }
 }
 
+/// Client for the HTTP API of a machine.
+///
+/// The auth token is obtained with the given app manifest, usually the trial manifest from
+/// [`app_manifest`](crate::util::app_manifest), and renewed whenever the node responds with 401.
 #[derive(Clone)]
 pub struct ApiClient(PinnedResource<Ax>);
 impl ApiClient {
@@ -97,10 +182,74 @@ impl ApiClient {
         Ok(ApiClient::new(origin, app_manifest, namespace))
     }
 
-    pub async fn offsets(&self) -> Result<ax_sdk::types::service::OffsetsResponse> {
+    pub async fn offsets(&self) -> Result<OffsetsResponse> {
         self.0.spawn_mut(|c| block_on(c.offsets())).await.unwrap()
     }
 
+    /// Publish the events, the token is renewed by the client if it has expired.
+    pub async fn publish<E>(&self, events: Vec<E>) -> Result<PublishResponse, ApiError>
+    where
+        E: Into<PublishEvent> + Send + 'static,
+    {
+        Ok(self.execute(|ax| block_on(ax.publish().events(events))).await??)
+    }
+
+    /// Run the query up to the current offsets of the node, returning only the events.
+    pub async fn query(
+        &self,
+        aql: impl Into<String> + Send + 'static,
+    ) -> Result<Vec<EventResponse<Payload>>, ApiError> {
+        self.query_events(aql, None).await
+    }
+
+    /// Run the query up to `upper_bound`, returning only the events.
+    pub async fn query_until(
+        &self,
+        aql: impl Into<String> + Send + 'static,
+        upper_bound: OffsetMap,
+    ) -> Result<Vec<EventResponse<Payload>>, ApiError> {
+        self.query_events(aql, Some(upper_bound)).await
+    }
+
+    async fn query_events(
+        &self,
+        aql: impl Into<String> + Send + 'static,
+        upper_bound: Option<OffsetMap>,
+    ) -> Result<Vec<EventResponse<Payload>>, ApiError> {
+        let responses = self
+            .execute(move |ax| {
+                let query = ax.query(aql);
+                match upper_bound {
+                    Some(upper_bound) => block_on(query.with_upper_bound(upper_bound)),
+                    None => block_on(query),
+                }
+            })
+            .await??;
+        Ok(responses
+            .filter_map(|response| {
+                future::ready(match response {
+                    QueryResponse::Event(event) => Some(event),
+                    _ => None,
+                })
+            })
+            .collect()
+            .await)
+    }
+
+    /// Subscribe to the query, the returned stream yields only the events.
+    pub async fn subscribe(
+        &self,
+        aql: impl Into<String> + Send + 'static,
+    ) -> Result<impl Stream<Item = EventResponse<Payload>> + Send + Unpin, ApiError> {
+        let responses = self.execute(|ax| block_on(ax.subscribe(aql))).await??;
+        Ok(responses.filter_map(|response| {
+            future::ready(match response {
+                SubscribeResponse::Event(event) => Some(event),
+                _ => None,
+            })
+        }))
+    }
+
     pub fn execute<U, F>(&self, f: F) -> impl Future<Output = Result<U, Canceled>>
     where
         U: Send + 'static,
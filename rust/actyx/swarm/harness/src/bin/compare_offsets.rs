@@ -0,0 +1,87 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use anyhow::ensure;
+    use ax_sdk::types::{tags, Offset, Payload};
+    use futures::FutureExt;
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event, PeerId};
+    use swarm_harness::{
+        compare_offsets, fetch_offsets, fully_meshed, wait_for_offset, wait_until, HarnessOpts, MachineExt,
+    };
+
+    swarm_harness::setup_env()?;
+    let mut opts = HarnessOpts::from_args();
+    opts.n_nodes = 2;
+    opts.n_bootstrap = 2;
+    // without any replication path the nodes keep their events to themselves
+    opts.enable_fast_path = false;
+    opts.enable_slow_path = false;
+    opts.enable_root_map = false;
+    swarm_harness::run_netsim(opts, |mut sim| async move {
+        fully_meshed::<Event>(&mut sim, Duration::from_secs(60)).await?;
+        let a = sim.machines()[0].id();
+        let b = sim.machines()[1].id();
+        let stream_a = sim.machine(a).node_id().stream(0.into());
+        let stream_b = sim.machine(b).node_id().stream(0.into());
+        let peer_b = sim.machine(b).peer_id();
+
+        for (machine, events) in [(a, 5), (b, 3)] {
+            sim.machine(machine).send(Command::Append(
+                (0..events)
+                    .map(|i| (tags!("compare"), Payload::from_json_str(&i.to_string()).unwrap()))
+                    .collect(),
+            ));
+        }
+        let timeout = Duration::from_secs(60);
+        // the streams also hold the stream mappings published at startup
+        wait_for_offset(sim.machine(a), stream_a, Offset::from(4).into(), timeout).await?;
+        wait_for_offset(sim.machine(b), stream_b, Offset::from(2).into(), timeout).await?;
+        let (present_a, _) = fetch_offsets(sim.machine(a)).await?;
+        let (present_b, _) = fetch_offsets(sim.machine(b)).await?;
+
+        // the peer may not have seen the subscription to the exchange topic yet
+        let comparison = wait_until(
+            sim.machine(a),
+            timeout,
+            format!("compare offsets with {}", peer_b),
+            move |machine| {
+                async move {
+                    match compare_offsets(machine, peer_b).await {
+                        Ok(comparison) => Ok(Some(comparison)),
+                        Err(err) => {
+                            tracing::info!("retrying: {:#}", err);
+                            Ok(None)
+                        }
+                    }
+                }
+                .boxed()
+            },
+        )
+        .await?;
+        ensure!(comparison.local.present == present_a, "{:?}", comparison);
+        ensure!(comparison.remote.present == present_b, "{:?}", comparison);
+        ensure!(comparison.only_local == vec![stream_a], "{:?}", comparison);
+        ensure!(comparison.only_remote == vec![stream_b], "{:?}", comparison);
+        ensure!(comparison.local_ahead.is_empty() && comparison.remote_ahead.is_empty());
+        // never heard of, as opposed to not yet replicated
+        ensure!(!comparison.remote.replication_target.contains_stream(&stream_a));
+        ensure!(!comparison.local.replication_target.contains_stream(&stream_b));
+
+        // the same seen from the other side
+        let reverse = compare_offsets(sim.machine(b), sim.machine(a).peer_id()).await?;
+        ensure!(reverse.only_local == vec![stream_b], "{:?}", reverse);
+        ensure!(reverse.only_remote == vec![stream_a], "{:?}", reverse);
+
+        let unknown = PeerId::random();
+        ensure!(
+            compare_offsets(sim.machine(a), unknown).await.is_err(),
+            "unknown peer {} answered",
+            unknown
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
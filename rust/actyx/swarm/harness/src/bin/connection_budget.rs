@@ -0,0 +1,77 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use ax_sdk::types::{tags, Payload};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        time::Duration,
+    };
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event};
+    use swarm_harness::{fetch_offsets, wait_for_offset, HarnessOpts, MachineExt};
+
+    const MAX_CONNECTIONS: u32 = 2;
+
+    swarm_harness::setup_env()?;
+    let mut opts = HarnessOpts::from_args();
+    let n_nodes = opts.n_nodes.max(6);
+    opts.n_nodes = n_nodes;
+    opts.n_bootstrap = 1;
+    opts.enable_discovery = true;
+    opts.enable_root_map = true;
+    opts.max_connections = Some(MAX_CONNECTIONS);
+    swarm_harness::run_netsim(opts, |mut sim| async move {
+        let bootstrap = sim.machines()[0].peer_id();
+        for machine in sim.machines_mut() {
+            machine.send(Command::Append(vec![(
+                tags!("test"),
+                Payload::from_json_str(&format!("\"{}\"", machine.peer_id())).unwrap(),
+            )]));
+        }
+        // commands are handled in order, so the offsets include the event appended above
+        let mut targets = BTreeMap::new();
+        for machine in sim.machines_mut() {
+            let stream = machine.node_id().stream(0.into());
+            let (present, _) = fetch_offsets(machine).await?;
+            targets.insert(stream, present.offset(stream));
+        }
+
+        // every node sees the events of all nodes, replicated through the connected subset
+        let mut peers = BTreeMap::new();
+        for machine in sim.machines_mut() {
+            for (stream, offset) in &targets {
+                wait_for_offset(machine, *stream, *offset, Duration::from_secs(120)).await?;
+            }
+            let connected = peers.entry(machine.peer_id()).or_insert_with(BTreeSet::new);
+            for event in machine.drain() {
+                match event {
+                    Event::Connected(peer) => {
+                        connected.insert(peer);
+                    }
+                    Event::Disconnected(peer) => {
+                        connected.remove(&peer);
+                    }
+                    _ => {}
+                }
+            }
+            tracing::info!("{} converged with {} peers", machine.peer_id(), connected.len());
+        }
+
+        // each node dials at most MAX_CONNECTIONS - 1 peers besides the bootstrap node
+        let edges = peers
+            .iter()
+            .filter(|(id, _)| **id != bootstrap)
+            .map(|(_, connected)| connected.iter().filter(|peer| **peer != bootstrap).count())
+            .sum::<usize>();
+        let budget = 2 * (n_nodes - 1) * (MAX_CONNECTIONS as usize - 1);
+        anyhow::ensure!(
+            edges <= budget,
+            "{} connections between non-bootstrap nodes exceed the budget of {}",
+            edges,
+            budget
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
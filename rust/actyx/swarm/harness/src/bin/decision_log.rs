@@ -0,0 +1,91 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use anyhow::ensure;
+    use ax_sdk::types::{tags, Offset, OffsetOrMin, Payload};
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Decision, Event};
+    use swarm_harness::{fetch_decision_log, fetch_offsets, fully_meshed, wait_for_offset, HarnessOpts, MachineExt};
+
+    const BATCHES: u32 = 3;
+    const EVENTS: u32 = 5;
+
+    swarm_harness::setup_env()?;
+    let mut opts = HarnessOpts::from_args();
+    opts.n_nodes = 2;
+    opts.n_bootstrap = 2;
+    opts.enable_fast_path = true;
+    opts.enable_slow_path = true;
+    opts.enable_root_map = true;
+    opts.decision_log = true;
+    swarm_harness::run_netsim(opts, |mut sim| async move {
+        fully_meshed::<Event>(&mut sim, Duration::from_secs(60)).await?;
+        let a = sim.machines()[0].id();
+        let b = sim.machines()[1].id();
+        let stream = sim.machine(a).node_id().stream(0.into());
+
+        // append in batches and observe each of them arriving on the other node
+        let mut observed = vec![];
+        let (present, _) = fetch_offsets(sim.machine(a)).await?;
+        let initial = present.offset(stream);
+        let mut expected = initial;
+        for batch in 0..BATCHES {
+            sim.machine(a).send(Command::Append(
+                (0..EVENTS)
+                    .map(|i| (tags!("decisions"), Payload::from_json_str(&i.to_string()).unwrap()))
+                    .collect(),
+            ));
+            // the stream also holds the stream mappings published at startup
+            expected = expected + EVENTS;
+            wait_for_offset(sim.machine(b), stream, expected, Duration::from_secs(60)).await?;
+            let (present, _) = fetch_offsets(sim.machine(b)).await?;
+            let offset = present.offset(stream);
+            ensure!(
+                offset == expected,
+                "unexpected offset {} of {} after batch {}",
+                offset,
+                stream,
+                batch
+            );
+            observed.push(Offset::from_offset_or_min(expected).unwrap());
+        }
+
+        let records = fetch_decision_log(sim.machine(b), 1000).await?;
+        ensure!(
+            records.windows(2).all(|w| w[0].seq < w[1].seq),
+            "records out of order: {:?}",
+            records
+        );
+        let mut started = vec![];
+        let mut latest = vec![];
+        for record in &records {
+            match &record.decision {
+                Decision::SyncStart { stream: s, root } if *s == stream => started.push(root.clone()),
+                Decision::SetLatest {
+                    stream: s,
+                    root,
+                    offset,
+                    ..
+                } if *s == stream => {
+                    ensure!(started.contains(root), "{} set as latest without syncing it", root);
+                    // the stream mappings published at startup may have arrived before or after
+                    // the offsets were fetched
+                    if OffsetOrMin::from(*offset) > initial {
+                        latest.push(*offset);
+                    }
+                }
+                _ => {}
+            }
+        }
+        ensure!(
+            latest == observed,
+            "recorded offsets {:?} do not match the observed {:?}",
+            latest,
+            observed
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
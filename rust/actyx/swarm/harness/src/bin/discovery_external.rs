@@ -15,22 +15,10 @@ fn main() -> anyhow::Result<()> {
         sim.add_route(net_a, net_b);
         sim.add_route(net_a, net_c);
         let mut cfg = Config {
-            path: None,
-            node_name: None,
-            keypair: 0,
             listen_on: vec!["/ip4/0.0.0.0/tcp/30000".parse().unwrap()],
-            bootstrap: vec![],
-            external: vec![],
-            enable_mdns: false,
-            enable_fast_path: false,
-            enable_slow_path: false,
             enable_root_map: true,
             enable_discovery: true,
-            enable_metrics: false,
-            enable_api: None,
-            ephemeral_events: None,
-            max_leaf_count: None,
-            event_routes: Default::default(),
+            ..Default::default()
         };
         let bootstrap = sim.spawn_machine(cfg.clone().into(), None).await;
         sim.plug(bootstrap, net_a, None).await;
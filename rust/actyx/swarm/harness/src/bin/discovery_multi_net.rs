@@ -20,21 +20,11 @@ fn main() -> anyhow::Result<()> {
         for (i, net) in [net_a, net_b, net_c].iter().enumerate() {
             let cfg = Config {
                 path: Some(temp_dir.path().join(i.to_string())),
-                node_name: None,
                 keypair: i as _,
                 listen_on: vec!["/ip4/0.0.0.0/tcp/30000".parse().unwrap()],
-                bootstrap: vec![],
-                external: vec![],
-                enable_mdns: false,
-                enable_fast_path: false,
-                enable_slow_path: false,
                 enable_root_map: true,
                 enable_discovery: true,
-                enable_metrics: false,
-                enable_api: None,
-                ephemeral_events: None,
-                max_leaf_count: None,
-                event_routes: Default::default(),
+                ..Default::default()
             };
             let machine = sim.spawn_machine(cfg.into(), None).await;
             sim.plug(machine, *net, None).await;
@@ -0,0 +1,84 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use anyhow::ensure;
+    use async_std::task::sleep;
+    use ax_sdk::types::{tags, Offset, Timestamp};
+    use netsim_embed::Netsim;
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event, GossipMessage, RootUpdate};
+    use swarm_harness::{append, fetch_root_map, fully_meshed, wait_for_offset, HarnessOpts, MachineExt};
+
+    const EVENTS: usize = 100;
+
+    fn opts(gc_grace_period_ms: u64) -> HarnessOpts {
+        let mut opts = HarnessOpts::from_args();
+        opts.n_nodes = 2;
+        opts.n_bootstrap = 2;
+        // every block fetched by the lagging peer takes a while
+        opts.delay_ms = opts.delay_ms.max(200);
+        // small leaves for many blocks, of which the superseded root has some of its own
+        opts.max_leaf_count = Some(4);
+        // the superseded root only reaches the other node when sent explicitly below
+        opts.enable_fast_path = false;
+        opts.enable_slow_path = false;
+        opts.enable_root_map = false;
+        opts.block_gc_interval_ms = Some(500);
+        opts.gc_grace_period_ms = Some(gc_grace_period_ms);
+        opts
+    }
+
+    /// Supersede a root on one node and let the other node sync it afterwards, within `timeout`
+    async fn sync_superseded_root(mut sim: Netsim<Command, Event>, timeout: Duration) -> anyhow::Result<()> {
+        fully_meshed::<Event>(&mut sim, Duration::from_secs(60)).await?;
+        let a = sim.machines()[0].id();
+        let b = sim.machines()[1].id();
+        let stream = sim.machine(a).node_id().stream(0.into());
+
+        append(&mut sim, a, tags!("grace"), EVENTS);
+        let append_timeout = Duration::from_secs(30);
+        wait_for_offset(
+            sim.machine(a),
+            stream,
+            Offset::from(EVENTS as u32).into(),
+            append_timeout,
+        )
+        .await?;
+        let (old_root, old_offset, old_lamport) = fetch_root_map(sim.machine(a)).await?[&stream];
+
+        // supersede the root and give the garbage collection of `a` a few rounds
+        append(&mut sim, a, tags!("grace"), EVENTS);
+        wait_for_offset(
+            sim.machine(a),
+            stream,
+            Offset::from(2 * EVENTS as u32).into(),
+            append_timeout,
+        )
+        .await?;
+        sleep(Duration::from_secs(3)).await;
+
+        // `b` only learns of the superseded root now
+        let update = GossipMessage::RootUpdate(RootUpdate {
+            stream,
+            root: old_root,
+            blocks: vec![],
+            lamport: old_lamport,
+            time: Timestamp::now(),
+            offset: Some(old_offset),
+        });
+        sim.machine(a).send(Command::Broadcast("swarm-cli".into(), update));
+        wait_for_offset(sim.machine(b), stream, old_offset.into(), timeout).await
+    }
+
+    swarm_harness::setup_env()?;
+    swarm_harness::run_netsim(opts(120_000), |sim| sync_superseded_root(sim, Duration::from_secs(90)))?;
+    // control run: without the grace period the superseded root is gone before `b` asks for it
+    swarm_harness::run_netsim(opts(0), |sim| async move {
+        let synced = sync_superseded_root(sim, Duration::from_secs(60)).await;
+        ensure!(synced.is_err(), "synced the superseded root without a grace period");
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
@@ -0,0 +1,73 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use ax_sdk::types::{tags, Payload};
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        time::{Duration, Instant},
+    };
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event};
+    use swarm_harness::{api::Api, fully_mesh, heal, m, partition, select_single, util::app_manifest, HarnessOpts};
+
+    const ROOT_MAP_INTERVAL: Duration = Duration::from_secs(120);
+
+    let mut opts = HarnessOpts::from_args();
+    opts.n_nodes = 2;
+    opts.n_bootstrap = 2;
+    opts.enable_api = Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 30001));
+    opts.enable_fast_path = true;
+    opts.enable_slow_path = false;
+    opts.enable_root_map = true;
+    opts.enable_discovery = false;
+    // a fixed cadence long enough that only the outbox can bring the new root across in time
+    opts.root_map_interval_ms = Some(ROOT_MAP_INTERVAL.as_millis() as u64);
+    opts.root_map_max_interval_ms = Some(ROOT_MAP_INTERVAL.as_millis() as u64);
+
+    swarm_harness::setup_env()?;
+    swarm_harness::run_netsim(opts, move |mut sim| async move {
+        fully_mesh(&mut sim, Duration::from_secs(60)).await?;
+
+        let machines = sim.machines().iter().map(|m| m.id()).collect::<Vec<_>>();
+        let (writer, reader) = (machines[0], machines[1]);
+        let reader_id = sim.machine(reader).peer_id();
+        partition(&mut sim, vec![vec![writer], vec![reader]]).await;
+
+        // append only once the writer has noticed that it is alone
+        select_single(
+            sim.machine(writer),
+            Duration::from_secs(60),
+            |ev| m!(ev, Event::Disconnected(peer) if *peer == reader_id => ()),
+        )
+        .await;
+        sim.machine(writer).send(Command::Append(
+            (0..10)
+                .map(|i| (tags!("outbox"), Payload::from_json_str(&i.to_string()).unwrap()))
+                .collect(),
+        ));
+        async_std::task::sleep(Duration::from_secs(2)).await;
+
+        heal(&mut sim).await;
+        let healed = Instant::now();
+        let api = Api::new(&mut sim, app_manifest())?;
+        let target = api
+            .run(writer, |api| async move { Ok(api.offsets().await?.present) })
+            .await?;
+        api.wait_for_offsets(&target, ROOT_MAP_INTERVAL / 4).await?;
+        tracing::info!(
+            "reader converged {:.1}sec after healing",
+            healed.elapsed().as_secs_f64()
+        );
+
+        let events = api
+            .run(reader, |api| async move {
+                Ok(api.query_until("FROM 'outbox'", target).await?)
+            })
+            .await?;
+        assert_eq!(events.len(), 10);
+
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
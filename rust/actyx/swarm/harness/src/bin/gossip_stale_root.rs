@@ -0,0 +1,71 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use anyhow::ensure;
+    use async_std::task::sleep;
+    use ax_sdk::types::{tags, Offset, Timestamp};
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event, GossipMessage, RootUpdate};
+    use swarm_harness::{
+        append, fetch_offsets, fetch_root_map, fully_meshed, wait_for_offset, HarnessOpts, MachineExt,
+    };
+
+    const EVENTS: usize = 10;
+
+    swarm_harness::setup_env()?;
+    let mut opts = HarnessOpts::from_args();
+    opts.n_nodes = 2;
+    opts.n_bootstrap = 2;
+    opts.enable_fast_path = true;
+    opts.enable_slow_path = true;
+    opts.enable_root_map = true;
+    swarm_harness::run_netsim(opts, |mut sim| async move {
+        fully_meshed::<Event>(&mut sim, Duration::from_secs(60)).await?;
+        let a = sim.machines()[0].id();
+        let b = sim.machines()[1].id();
+        let stream = sim.machine(a).node_id().stream(0.into());
+
+        // remember an early state of the stream on `a`
+        append(&mut sim, a, tags!("stale"), EVENTS);
+        let timeout = Duration::from_secs(30);
+        wait_for_offset(sim.machine(a), stream, Offset::from(EVENTS as u32).into(), timeout).await?;
+        let (old_root, old_offset, old_lamport) = fetch_root_map(sim.machine(a)).await?[&stream];
+
+        append(&mut sim, a, tags!("stale"), EVENTS);
+        let (current, _) = fetch_offsets(sim.machine(a)).await?;
+        let current = current.offset(stream);
+        wait_for_offset(sim.machine(b), stream, current, timeout).await?;
+
+        // `a` now gossips the early state again, on both paths
+        let stale = GossipMessage::RootUpdate(RootUpdate {
+            stream,
+            root: old_root,
+            blocks: vec![],
+            lamport: old_lamport,
+            time: Timestamp::now(),
+            offset: Some(old_offset),
+        });
+        let machine = sim.machine(a);
+        machine.send(Command::GossipPublish("swarm-cli".into(), stale.clone()));
+        machine.send(Command::Broadcast("swarm-cli".into(), stale));
+        sleep(Duration::from_secs(5)).await;
+
+        let (present, target) = fetch_offsets(sim.machine(b)).await?;
+        ensure!(
+            present.offset(stream) == current,
+            "present offset of {} regressed to {}",
+            stream,
+            present.offset(stream)
+        );
+        ensure!(
+            target.offset(stream) >= current,
+            "replication target of {} regressed to {}",
+            stream,
+            target.offset(stream)
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
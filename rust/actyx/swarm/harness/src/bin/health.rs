@@ -120,22 +120,13 @@ fn main() {
             let net = sim.spawn_network(range);
             let peer_id = PeerId::from(swarm_cli::keypair(*net_id as _));
             let cfg = Config {
-                path: None,
-                node_name: None,
                 keypair: *net_id as u64,
                 listen_on: vec!["/ip4/0.0.0.0/tcp/3000".parse().unwrap()],
                 bootstrap: bootstrap.clone(),
-                external: vec![],
-                enable_mdns: false,
                 enable_discovery: true,
                 enable_fast_path: true,
-                enable_slow_path: false,
                 enable_root_map: true,
-                enable_metrics: false,
-                enable_api: None,
-                ephemeral_events: None,
-                max_leaf_count: None,
-                event_routes: Default::default(),
+                ..Default::default()
             };
             let machine = sim.spawn_machine(cfg.into(), None).await;
             tracing::info!("{} is {}", machine, peer_id);
@@ -156,22 +147,13 @@ fn main() {
             let node = node + nets.len();
             let peer_id = swarm_cli::keypair(node as u64).into();
             let cfg = Config {
-                path: None,
-                node_name: None,
                 keypair: node as u64,
                 listen_on: vec!["/ip4/0.0.0.0/tcp/0".parse().unwrap()],
                 bootstrap: bootstrap.clone(),
-                external: vec![],
-                enable_mdns: false,
                 enable_discovery: true,
                 enable_fast_path: true,
-                enable_slow_path: false,
                 enable_root_map: true,
-                enable_metrics: false,
-                enable_api: None,
-                ephemeral_events: None,
-                max_leaf_count: None,
-                event_routes: Default::default(),
+                ..Default::default()
             };
             let machine = sim.spawn_machine(cfg.into(), None).await;
             tracing::info!("{} is {}", machine, peer_id);
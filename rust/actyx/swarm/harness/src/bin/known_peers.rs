@@ -0,0 +1,77 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use async_std::future::timeout;
+    use netsim_embed::{Ipv4Range, MachineId, Netsim, NetworkId};
+    use std::{net::Ipv4Addr, path::Path, time::Duration};
+    use swarm_cli::{Command, Config, Event, Multiaddr, PeerId};
+    use swarm_harness::m;
+    use tempdir::TempDir;
+
+    async fn spawn_machine(
+        sim: &mut Netsim<Command, Event>,
+        net: NetworkId,
+        addr: Option<Ipv4Addr>,
+        path: &Path,
+        i: u64,
+        bootstrap: Vec<Multiaddr>,
+    ) -> MachineId {
+        let config = Config {
+            path: Some(path.join(i.to_string())),
+            keypair: i,
+            listen_on: vec!["/ip4/0.0.0.0/tcp/30000".parse().unwrap()],
+            bootstrap,
+            enable_fast_path: true,
+            enable_slow_path: true,
+            enable_root_map: true,
+            enable_discovery: true,
+            ..Default::default()
+        };
+        let machine = sim.spawn_machine(config.into(), None).await;
+        sim.plug(machine, net, addr).await;
+        machine
+    }
+
+    async fn wait_connected(sim: &mut Netsim<Command, Event>, id: MachineId, peer: PeerId) -> anyhow::Result<()> {
+        timeout(
+            Duration::from_secs(60),
+            sim.machine(id)
+                .select(|ev| m!(ev, Event::Connected(p) if *p == peer => ())),
+        )
+        .await?;
+        Ok(())
+    }
+
+    swarm_harness::setup_env()?;
+    let temp_dir = TempDir::new("known_peers")?;
+    async_global_executor::block_on(async move {
+        let mut sim = Netsim::new();
+        let net = sim.spawn_network(Ipv4Range::random_local_subnet());
+        let bootstrap_addr = sim.network_mut(net).unique_addr();
+        let bootstrap_peer: PeerId = swarm_cli::keypair(0).into();
+        let bootstrap: Multiaddr = format!("/ip4/{}/tcp/30000/p2p/{}", bootstrap_addr, bootstrap_peer).parse()?;
+        let peer: PeerId = swarm_cli::keypair(1).into();
+        let other: PeerId = swarm_cli::keypair(2).into();
+
+        let b = spawn_machine(&mut sim, net, Some(bootstrap_addr), temp_dir.path(), 0, vec![]).await;
+        let a = spawn_machine(&mut sim, net, None, temp_dir.path(), 1, vec![bootstrap.clone()]).await;
+        let _ = spawn_machine(&mut sim, net, None, temp_dir.path(), 2, vec![bootstrap]).await;
+
+        // the two nodes only learn about each other through discovery via the bootstrap node
+        wait_connected(&mut sim, a, other).await?;
+        tracing::info!("{} discovered {}", peer, other);
+        // give the address a moment to be persisted
+        async_std::task::sleep(Duration::from_secs(2)).await;
+
+        // take down the bootstrap node and restart the node without any bootstrap address
+        sim.machine(b).send(Command::Exit);
+        sim.machine(a).send(Command::Exit);
+        async_std::task::sleep(Duration::from_secs(2)).await;
+        let a = spawn_machine(&mut sim, net, None, temp_dir.path(), 1, vec![]).await;
+        wait_connected(&mut sim, a, other).await?;
+        tracing::info!("{} reconnected to {} after restart", peer, other);
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
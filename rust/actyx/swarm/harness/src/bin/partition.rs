@@ -0,0 +1,73 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use ax_sdk::types::{tags, OffsetMap, Payload};
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
+    use structopt::StructOpt;
+    use swarm_cli::Command;
+    use swarm_harness::{api::Api, fully_mesh, heal, partition, util::app_manifest, HarnessOpts};
+
+    const N: usize = 10;
+
+    let mut opts = HarnessOpts::from_args();
+    opts.n_nodes = opts.n_nodes.max(2);
+    opts.enable_api = Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 30001));
+    opts.enable_fast_path = true;
+    opts.enable_slow_path = true;
+    opts.enable_root_map = true;
+
+    swarm_harness::setup_env()?;
+    swarm_harness::run_netsim(opts, move |mut sim| async move {
+        fully_mesh(&mut sim, Duration::from_secs(60)).await?;
+
+        let machines = sim.machines().iter().map(|m| m.id()).collect::<Vec<_>>();
+        let (left, right) = machines.split_at(machines.len() / 2);
+        partition(&mut sim, vec![left.to_vec(), right.to_vec()]).await;
+
+        // both sides keep writing while they cannot see each other
+        for side in [left, right] {
+            let machine = sim.machine(side[0]);
+            machine.send(Command::Append(
+                (0..N)
+                    .map(|i| (tags!("partition"), Payload::from_json_str(&i.to_string()).unwrap()))
+                    .collect(),
+            ));
+        }
+        async_std::task::sleep(Duration::from_secs(5)).await;
+
+        heal(&mut sim).await;
+        // the api clients are bound to the addresses of the machines, which changed with healing
+        let api = Api::new(&mut sim, app_manifest())?;
+        let mut target = OffsetMap::empty();
+        for machine in &machines {
+            let present = api
+                .run(*machine, |api| async move { Ok(api.offsets().await?.present) })
+                .await?;
+            target.union_with(&present);
+        }
+        api.wait_for_offsets(&target, Duration::from_secs(60)).await?;
+
+        let mut results = vec![];
+        for machine in &machines {
+            let upper_bound = target.clone();
+            let events = api
+                .run(*machine, |api| async move {
+                    Ok(api.query_until("FROM 'partition'", upper_bound).await?)
+                })
+                .await?;
+            tracing::info!("{} got {} events", machine, events.len());
+            results.push(events);
+        }
+        assert_eq!(results[0].len(), 2 * N);
+        for events in &results[1..] {
+            assert_eq!(events, &results[0]);
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
@@ -1,15 +1,8 @@
 #[cfg(target_os = "linux")]
 fn main() -> anyhow::Result<()> {
     use anyhow::Context;
-    use async_std::{
-        future::timeout,
-        task::{block_on, sleep},
-    };
-    use ax_sdk::types::{
-        service::{PublishEvent, QueryResponse},
-        tags, AppManifest, OffsetMap, Payload,
-    };
-    use futures::{future, StreamExt};
+    use async_std::{future::timeout, task::sleep};
+    use ax_sdk::types::{service::PublishEvent, tags, AppManifest, OffsetMap, Payload};
     use std::{
         net::{IpAddr, Ipv4Addr, SocketAddr},
         time::Duration,
@@ -35,24 +28,21 @@ fn main() -> anyhow::Result<()> {
         for (idx, machine) in sim.machines().iter().enumerate() {
             tracing::error!("{}", idx);
             api.run(machine.id(), |api| async move {
-                api.execute(|ax| {
-                    block_on(ax.publish().events((0..N).map(|i| PublishEvent {
-                        tags: tags!("a", "b"),
-                        payload: Payload::from_json_str(&format!("{}", i)).unwrap(),
-                    })))
-                })
-                .await??;
+                api.publish(
+                    (0..N)
+                        .map(|i| PublishEvent {
+                            tags: tags!("a", "b"),
+                            payload: Payload::from_json_str(&format!("{}", i)).unwrap(),
+                        })
+                        .collect(),
+                )
+                .await?;
 
                 let upper_bound = api.offsets().await?.present;
                 let count = (&upper_bound - &OffsetMap::default()) as usize;
                 assert!(count >= N);
 
-                let result = api
-                    .execute(|ax| block_on(ax.query("FROM allEvents")))
-                    .await??
-                    .filter(|resp| future::ready(matches!(resp, QueryResponse::Event(_))))
-                    .collect::<Vec<_>>()
-                    .await;
+                let result = api.query("FROM allEvents").await?;
 
                 assert_eq!(result.len(), count);
                 Ok(result)
@@ -82,15 +72,9 @@ fn main() -> anyhow::Result<()> {
                         let upper_bound = api.offsets().await?.present;
                         let count = (&upper_bound - &OffsetMap::default()) as usize;
 
-                        let result = timeout(
-                            Duration::from_secs(10),
-                            api.execute(move |ax| block_on(ax.query("FROM allEvents").with_upper_bound(upper_bound)))
-                                .await??
-                                .filter(|resp| future::ready(matches!(resp, QueryResponse::Event(_))))
-                                .collect::<Vec<_>>(),
-                        )
-                        .await
-                        .with_context(|| format!("query for {} timed out", machine.id()))?;
+                        let result = timeout(Duration::from_secs(10), api.query_until("FROM allEvents", upper_bound))
+                            .await
+                            .with_context(|| format!("query for {} timed out", machine.id()))??;
 
                         assert_eq!(result.len(), count);
                         tracing::info!("{} got {} events", machine.id(), count);
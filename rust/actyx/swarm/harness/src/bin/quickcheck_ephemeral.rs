@@ -1,14 +1,13 @@
 #[cfg(target_os = "linux")]
 fn main() {
-    use async_std::task::block_on;
     use ax_sdk::{
         aql::TagExpr,
         types::{
-            service::{EventMeta, EventResponse, PublishEvent, QueryResponse},
+            service::{EventMeta, PublishEvent},
             tags, OffsetMap, Payload,
         },
     };
-    use futures::{future, stream::FuturesUnordered, StreamExt};
+    use futures::{stream::FuturesUnordered, StreamExt};
     use quickcheck::{Arbitrary, Gen, QuickCheck, TestResult};
     use std::{
         fs::File,
@@ -131,6 +130,13 @@ fn main() {
             )),
             // Force single event per leaf
             max_leaf_count: Some(1),
+            root_map_interval_ms: None,
+            root_map_max_interval_ms: None,
+            max_connections: None,
+            tombstone_retention_ms: None,
+            block_gc_interval_ms: None,
+            gc_grace_period_ms: None,
+            decision_log: false,
             event_routes: vec![EventRoute::new(
                 TagExpr::from_str("'test'").unwrap(),
                 "test_stream".to_string(),
@@ -144,7 +150,7 @@ fn main() {
             let mut present = OffsetMap::empty();
             let machine = sim.machines().first().unwrap();
             api.run(machine.id(), move |client| async move {
-                client.execute(|ax| block_on(ax.publish().events(events))).await??;
+                client.publish(events).await?;
                 Ok(())
             })
             .await?;
@@ -155,9 +161,7 @@ fn main() {
             // Publish another event for other peers to ingest the new tree
             let (stream_0, max_offset) = api
                 .run(machine.id(), move |client| async move {
-                    let meta = client
-                        .execute(|ax| block_on(ax.publish().events(make_events(1))))
-                        .await??;
+                    let meta = client.publish(make_events(1)).await?;
                     let stream_0 = client.node_id().await.stream(1.into());
                     Ok((stream_0, meta.data.last().unwrap().offset))
                 })
@@ -188,21 +192,11 @@ fn main() {
                             let present = present.clone();
                             async move {
                                 let round_tripped = client
-                                    .execute(|ax| block_on(ax.query("FROM allEvents").with_upper_bound(present)))
-                                    .await??
-                                    .filter_map(|resp| async move {
-                                        if let QueryResponse::Event(EventResponse {
-                                            meta: EventMeta::Event { key, meta },
-                                            payload,
-                                        }) = resp
-                                        {
-                                            Some((key.stream, (meta.tags, payload)))
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .fold(0usize, |acc, _| future::ready(acc + 1))
-                                    .await;
+                                    .query_until("FROM allEvents", present)
+                                    .await?
+                                    .into_iter()
+                                    .filter(|event| matches!(event.meta, EventMeta::Event { .. }))
+                                    .count();
 
                                 Result::<_, anyhow::Error>::Ok(round_tripped)
                             }
@@ -2,7 +2,6 @@
 fn main() {
     use std::{collections::BTreeMap, str::FromStr, time::Duration};
 
-    use async_std::task::block_on;
     use ax_sdk::{
         aql::{Query, Source, TagAtom, TagExpr},
         types::{Tag, TagSet},
@@ -227,6 +226,13 @@ fn main() {
             enable_api: Some("0.0.0.0:30001".parse().unwrap()),
             ephemeral_events: None,
             max_leaf_count: None,
+            root_map_interval_ms: None,
+            root_map_max_interval_ms: None,
+            max_connections: None,
+            tombstone_retention_ms: None,
+            block_gc_interval_ms: None,
+            gc_grace_period_ms: None,
+            decision_log: false,
             event_routes: Default::default(),
         };
 
@@ -244,7 +250,7 @@ fn main() {
                         let events = to_events(tags);
                         tracing::debug!("Cmd {} / Node {}: Publishing {} events", cmd_id, node, events.len());
                         async move {
-                            client.execute(|ax| block_on(ax.publish().events(events))).await??;
+                            client.publish(events).await?;
                             Result::<_, anyhow::Error>::Ok(())
                         }
                         .boxed()
@@ -262,7 +268,7 @@ fn main() {
                         let client = ApiClient::from_machine(sim.machine(id), app_manifest(), None).unwrap();
                         let query = to_query(tags).to_string();
                         async move {
-                            let mut req = client.execute(|ax| block_on(ax.subscribe(query))).await??;
+                            let mut req = client.subscribe(query).await?;
                             let mut actual = 0;
                             if expected_cnt > 0 {
                                 while tokio::time::timeout(Duration::from_secs(10), req.next())
@@ -3,13 +3,9 @@
 mod quickcheck_stress_single_store {
     use std::{str::FromStr, time::Duration};
 
-    use async_std::task::block_on;
     use ax_sdk::{
         aql::TagExpr,
-        types::{
-            service::{EventMeta, EventResponse, SubscribeResponse},
-            tags, Offset, Payload,
-        },
+        types::{service::EventMeta, tags, Offset, Payload, TagSet},
         Url,
     };
     use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
@@ -65,6 +61,13 @@ mod quickcheck_stress_single_store {
             enable_api: Some("0.0.0.0:30001".parse().unwrap()),
             ephemeral_events: None,
             max_leaf_count: None,
+            root_map_interval_ms: None,
+            root_map_max_interval_ms: None,
+            max_connections: None,
+            tombstone_retention_ms: None,
+            block_gc_interval_ms: None,
+            gc_grace_period_ms: None,
+            decision_log: false,
             event_routes: vec![EventRoute::new(
                 TagExpr::from_str("'my_test'").unwrap(),
                 "test_stream".to_string(),
@@ -115,14 +118,10 @@ mod quickcheck_stress_single_store {
                                 publish_chunks_per_client,
                                 publish_chunk_size,
                             );
-                            let _meta =
-                                client
-                                    .execute(move |ax| {
-                                        block_on(ax.publish().events(
-                                            (0..publish_chunk_size).map(|_| (tags!("my_test"), Payload::null())),
-                                        ))
-                                    })
-                                    .await??;
+                            let events = (0..publish_chunk_size)
+                                .map(|_| (tags!("my_test"), Payload::null()))
+                                .collect();
+                            let _meta = client.publish::<(TagSet, Payload)>(events).await?;
                         }
                         tracing::info!("Client {}/{} done", i + 1, concurrent_publishes);
                         Result::<_, anyhow::Error>::Ok(())
@@ -138,7 +137,7 @@ mod quickcheck_stress_single_store {
                 futs.push(
                     async move {
                         tracing::debug!("subscriber {} starting", id);
-                        let req = client.execute(|ax| block_on(ax.subscribe("FROM 'my_test'"))).await?;
+                        let req = client.subscribe("FROM 'my_test'").await;
                         tracing::debug!(
                             "subscriber {} got {:?}",
                             id,
@@ -147,11 +146,7 @@ mod quickcheck_stress_single_store {
                         let mut req = req?;
                         tracing::info!("subscriber {} started", id);
                         while let Some(x) = tokio::time::timeout(Duration::from_secs(30), req.next()).await? {
-                            if let SubscribeResponse::Event(EventResponse {
-                                meta: EventMeta::Event { key, .. },
-                                ..
-                            }) = x
-                            {
+                            if let EventMeta::Event { key, .. } = x.meta {
                                 tracing::debug!("subscriber {} got offsets {}", id, key.offset);
                                 if key.offset >= max_offset {
                                     tracing::info!("subscriber {} ended", id);
@@ -1,9 +1,9 @@
 #[cfg(target_os = "linux")]
 fn main() {
     use anyhow::Context;
-    use async_std::{future::timeout, task::block_on};
+    use async_std::future::timeout;
     use ax_sdk::types::{
-        service::{EventMeta, EventResponse, QueryResponse},
+        service::{EventMeta, EventResponse},
         tag, OffsetMap, TagSet,
     };
     use futures::{stream::FuturesUnordered, StreamExt};
@@ -39,6 +39,13 @@ fn main() {
             enable_api: Some("0.0.0.0:30001".parse().unwrap()),
             ephemeral_events: None,
             max_leaf_count: None,
+            root_map_interval_ms: None,
+            root_map_max_interval_ms: None,
+            max_connections: None,
+            tombstone_retention_ms: None,
+            block_gc_interval_ms: None,
+            gc_grace_period_ms: None,
+            decision_log: false,
             event_routes: Default::default(),
         };
 
@@ -56,7 +63,7 @@ fn main() {
                     api.run(machine.id(), move |client| async move {
                         let events = to_events(tags);
                         let e = events.clone(); // NOTE: Unsure how to do this better
-                        let meta = client.execute(|ax| block_on(ax.publish().events(e))).await??;
+                        let meta = client.publish(e).await?;
                         let stream_0 = client.node_id().await.stream(0.into());
                         Result::<_, anyhow::Error>::Ok((stream_0, meta.data.last().map(|x| x.offset), events))
                     })
@@ -95,28 +102,23 @@ fn main() {
                     api.run(id, move |client| async move {
                         let round_tripped = timeout(
                             Duration::from_secs(5),
-                            client
-                                .execute(|ax| block_on(ax.query("FROM allEvents").with_upper_bound(upper_bound)))
-                                .await??
-                                .filter_map(|resp| async move {
-                                    match resp {
-                                        QueryResponse::Event(EventResponse {
-                                            meta: EventMeta::Event { key, meta },
-                                            payload,
-                                        }) if !meta.tags.contains(&tag!("files"))
-                                            && !meta.tags.contains(&tag!("event_routing"))
-                                            && !meta.tags.contains(&tag!("discovery")) =>
-                                        {
-                                            Some((key.stream, (meta.tags, payload)))
-                                        }
-                                        _ => None,
-                                    }
-                                })
-                                .collect::<Vec<_>>(),
+                            client.query_until("FROM allEvents", upper_bound),
                         )
                         .await
-                        .with_context(|| format!("query for {} timed out", id))?
+                        .with_context(|| format!("query for {} timed out", id))??
                         .into_iter()
+                        .filter_map(|event| match event {
+                            EventResponse {
+                                meta: EventMeta::Event { key, meta },
+                                payload,
+                            } if !meta.tags.contains(&tag!("files"))
+                                && !meta.tags.contains(&tag!("event_routing"))
+                                && !meta.tags.contains(&tag!("discovery")) =>
+                            {
+                                Some((key.stream, (meta.tags, payload)))
+                            }
+                            _ => None,
+                        })
                         .fold(BTreeMap::default(), |mut acc, (stream, payload)| {
                             acc.entry(stream).or_insert_with(Vec::new).push(payload);
                             acc
@@ -30,18 +30,10 @@ fn main() -> anyhow::Result<()> {
             node_name: Some(name.to_string()),
             keypair: i,
             listen_on: vec!["/ip4/0.0.0.0/tcp/3000".parse().unwrap()],
-            bootstrap: vec![],
-            external: vec![],
-            enable_mdns: false,
             enable_fast_path: !ro,
             enable_slow_path: !ro,
             enable_root_map: !ro,
-            enable_discovery: false,
-            enable_metrics: false,
-            enable_api: None,
-            ephemeral_events: None,
-            max_leaf_count: None,
-            event_routes: Default::default(),
+            ..Default::default()
         };
         let machine = sim.spawn_machine(config.into(), None).await;
         sim.plug(machine, net, None).await;
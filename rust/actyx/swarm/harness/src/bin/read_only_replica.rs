@@ -0,0 +1,103 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use anyhow::ensure;
+    use async_std::future::timeout;
+    use ax_sdk::{
+        aql::Query,
+        types::{tags, Payload},
+    };
+    use netsim_embed::{Ipv4Range, MachineId, Netsim, NetworkId};
+    use std::{path::Path, time::Duration};
+    use swarm_cli::{Command, Config, Event};
+    use swarm_harness::{fetch_streams, m, MachineExt};
+    use tempdir::TempDir;
+
+    const EVENTS: usize = 10;
+
+    async fn spawn_machine(
+        sim: &mut Netsim<Command, Event>,
+        net: NetworkId,
+        path: &Path,
+        i: u64,
+        read_only: bool,
+    ) -> MachineId {
+        let config = Config {
+            path: Some(path.join(i.to_string())),
+            keypair: i,
+            listen_on: vec!["/ip4/0.0.0.0/tcp/30000".parse().unwrap()],
+            enable_fast_path: true,
+            enable_slow_path: true,
+            enable_root_map: true,
+            enable_discovery: true,
+            enable_metrics: true,
+            read_only,
+            ..Default::default()
+        };
+        let machine = sim.spawn_machine(config.into(), None).await;
+        sim.plug(machine, net, None).await;
+        machine
+    }
+
+    swarm_harness::setup_env()?;
+    let temp_dir = TempDir::new("read_only_replica")?;
+    async_global_executor::block_on(async move {
+        let mut sim = Netsim::new();
+        let net = sim.spawn_network(Ipv4Range::random_local_subnet());
+        let writer = spawn_machine(&mut sim, net, temp_dir.path(), 0, false).await;
+        let replica = spawn_machine(&mut sim, net, temp_dir.path(), 1, true).await;
+        swarm_harness::fully_mesh(&mut sim, Duration::from_secs(60)).await?;
+
+        sim.machine(replica)
+            .send(Command::SubscribeQuery(Query::parse("FROM 'replica'").unwrap()));
+        sim.machine(writer).send(Command::Append(
+            (0..EVENTS)
+                .map(|i| (tags!("replica"), Payload::from_json_str(&i.to_string()).unwrap()))
+                .collect(),
+        ));
+
+        // the replica ingests the writer's events and answers the query
+        for i in 0..EVENTS {
+            let payload = timeout(
+                Duration::from_secs(60),
+                sim.machine(replica)
+                    .select(|ev| m!(ev, Event::Result((_, _, payload)) => payload.clone())),
+            )
+            .await?
+            .unwrap();
+            ensure!(payload.json_string() == i.to_string(), "unexpected event {}", payload);
+        }
+
+        // appending locally is rejected
+        sim.machine(replica)
+            .send(Command::Append(vec![(tags!("replica"), Payload::null())]));
+        let reason = timeout(
+            Duration::from_secs(10),
+            sim.machine(replica)
+                .select(|ev| m!(ev, Event::AppendFailed(reason) => reason.clone())),
+        )
+        .await?
+        .unwrap();
+        ensure!(reason.contains("read-only"), "unexpected error: {}", reason);
+
+        // and no own streams were created, neither by the append nor by internal tasks
+        let node_id = sim.machine(replica).node_id();
+        let machine = sim.machine(replica);
+        let streams = fetch_streams(machine).await?;
+        ensure!(
+            streams.iter().all(|stream| stream.node_id() != node_id),
+            "replica created own streams: {:?}",
+            streams
+        );
+        ensure!(
+            streams
+                .iter()
+                .any(|stream| stream.node_id() == sim.machine(writer).node_id()),
+            "replica did not replicate the writer's streams: {:?}",
+            streams
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
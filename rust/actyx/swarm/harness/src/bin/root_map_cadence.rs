@@ -0,0 +1,108 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use async_std::future::timeout;
+    use ax_core::crypto::peer_id_to_node_id;
+    use ax_sdk::{
+        aql::TagExpr,
+        types::{tags, Offset, Payload, StreamNr},
+    };
+    use std::{
+        str::FromStr,
+        time::{Duration, Instant},
+    };
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event, EventRoute, GossipMessage, RootMap};
+    use swarm_harness::{fully_meshed, HarnessOpts, MachineExt};
+
+    const MIN_INTERVAL: Duration = Duration::from_secs(1);
+    const WINDOW: Duration = Duration::from_secs(30);
+
+    swarm_harness::setup_env()?;
+    let mut opts = HarnessOpts::from_args();
+    // only the root map may carry the new stream root to the other nodes
+    opts.enable_fast_path = false;
+    opts.enable_slow_path = false;
+    opts.enable_root_map = true;
+    opts.enable_discovery = false;
+    opts.enable_metrics = false;
+    let n_nodes = opts.n_nodes.max(3);
+    opts.n_bootstrap = n_nodes;
+    opts.n_nodes = n_nodes;
+    opts.root_map_interval_ms = Some(MIN_INTERVAL.as_millis() as u64);
+    opts.root_map_max_interval_ms = Some(16_000);
+    opts.event_routes = vec![EventRoute::new(
+        TagExpr::from_str("'test'").unwrap(),
+        "test_stream".to_string(),
+    )];
+    swarm_harness::run_netsim(opts, |mut sim| async move {
+        fully_meshed::<Event>(&mut sim, Duration::from_secs(60)).await?;
+
+        for machine in sim.machines_mut() {
+            machine.send(Command::GossipSubscribe("swarm-cli".into()));
+        }
+
+        // let the startup activity settle, then count the root maps of the idle swarm
+        let (first, rest) = sim.machines_mut().split_first_mut().unwrap();
+        let settle = Instant::now() + Duration::from_secs(10);
+        while let Ok(ev) = timeout(settle.saturating_duration_since(Instant::now()), first.recv()).await {
+            tracing::debug!("settling: {:?}", ev);
+        }
+        let mut root_maps = 0u64;
+        let end = Instant::now() + WINDOW;
+        while let Ok(ev) = timeout(end.saturating_duration_since(Instant::now()), first.recv()).await {
+            if let Some(Event::GossipEvent(_, _, GossipMessage::RootMap(_))) = ev {
+                root_maps += 1;
+            }
+        }
+        let baseline = (n_nodes as u64 - 1) * (WINDOW.as_secs() / MIN_INTERVAL.as_secs());
+        tracing::info!(
+            "received {} root maps, fixed cadence baseline is {}",
+            root_maps,
+            baseline
+        );
+        anyhow::ensure!(
+            root_maps * 3 < baseline,
+            "idle swarm sent {} root maps, fixed cadence would send {}",
+            root_maps,
+            baseline
+        );
+
+        // a change must be published right away instead of waiting for the backed off interval
+        let stream_id = peer_id_to_node_id(first.peer_id())?.stream(StreamNr::from(1));
+        for machine in rest.iter_mut() {
+            machine.drain();
+        }
+        first.send(Command::Append(vec![(
+            tags!("test"),
+            Payload::from_json_str("\"hello\"").unwrap(),
+        )]));
+        let start = Instant::now();
+        for machine in rest.iter_mut() {
+            loop {
+                let ev = timeout(Duration::from_secs(5), machine.recv()).await?;
+                if let Some(Event::GossipEvent(_, sender, GossipMessage::RootMap(RootMap { entries, offsets, .. }))) =
+                    ev
+                {
+                    if sender != first.peer_id() {
+                        continue;
+                    }
+                    let idx = entries.keys().position(|stream| *stream == stream_id);
+                    if idx.and_then(|idx| offsets.get(idx)).map(|(offset, _)| *offset) == Some(Offset::from(0)) {
+                        break;
+                    }
+                }
+            }
+        }
+        let elapsed = start.elapsed();
+        tracing::info!("change propagated after {:?}", elapsed);
+        anyhow::ensure!(
+            elapsed < Duration::from_millis(1500),
+            "change took {:?} to propagate",
+            elapsed
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
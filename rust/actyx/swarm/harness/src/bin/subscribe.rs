@@ -6,10 +6,10 @@ fn main() -> anyhow::Result<()> {
     };
 
     use anyhow::Context;
-    use async_std::{future::timeout, task::block_on};
+    use async_std::future::timeout;
     use ax_sdk::{
         aql::Query,
-        types::{app_id, tags, AppManifest, Timestamp},
+        types::{app_id, tags, AppManifest, Payload, Timestamp},
     };
     use structopt::StructOpt;
     use swarm_cli::{Command, Event, TimedEvent};
@@ -61,8 +61,7 @@ fn main() -> anyhow::Result<()> {
                 .run(id, |api| async move {
                     let now = Timestamp::now();
                     // The move is necessary to force taking ownership of `n`
-                    api.execute(move |ax| block_on(ax.publish().event(tags!("a"), &n).unwrap()))
-                        .await??;
+                    api.publish(vec![(tags!("a"), Payload::compact(&n)?)]).await?;
                     Ok(now)
                 })
                 .await?;
@@ -0,0 +1,74 @@
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use anyhow::ensure;
+    use ax_sdk::types::{tags, Offset, OffsetOrMin, Payload};
+    use futures::FutureExt;
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use swarm_cli::{Command, Event};
+    use swarm_harness::{
+        fetch_offsets, fetch_root_map, fetch_streams, fully_meshed, wait_for_offset, wait_until, HarnessOpts,
+        MachineExt,
+    };
+
+    const EVENTS: usize = 10;
+
+    swarm_harness::setup_env()?;
+    let mut opts = HarnessOpts::from_args();
+    opts.n_nodes = 2;
+    opts.n_bootstrap = 2;
+    opts.enable_fast_path = true;
+    opts.enable_slow_path = true;
+    opts.enable_root_map = true;
+    opts.tombstone_retention_ms = Some(2000);
+    swarm_harness::run_netsim(opts, |mut sim| async move {
+        fully_meshed::<Event>(&mut sim, Duration::from_secs(60)).await?;
+        let a = sim.machines()[0].id();
+        let b = sim.machines()[1].id();
+        let stream = sim.machine(a).node_id().stream(0.into());
+
+        sim.machine(a).send(Command::Append(
+            (0..EVENTS)
+                .map(|i| (tags!("tombstone"), Payload::from_json_str(&i.to_string()).unwrap()))
+                .collect(),
+        ));
+        sim.machine(a).send(Command::Tombstone(stream, "decommissioned".into()));
+        // the tombstone is the last event
+        let last = OffsetOrMin::from(Offset::from(EVENTS as u32));
+
+        wait_for_offset(sim.machine(b), stream, last, Duration::from_secs(60)).await?;
+        wait_until(
+            sim.machine(b),
+            Duration::from_secs(60),
+            format!("purge {}", stream),
+            move |machine| {
+                async move {
+                    let streams = fetch_streams(machine).await?;
+                    let root_map = fetch_root_map(machine).await?;
+                    Ok((!streams.contains(&stream) && !root_map.contains_key(&stream)).then_some(()))
+                }
+                .boxed()
+            },
+        )
+        .await?;
+
+        let machine = sim.machine(a);
+        ensure!(
+            !fetch_streams(machine).await?.contains(&stream),
+            "{} was not purged on {}",
+            stream,
+            a
+        );
+        let (present, _) = fetch_offsets(machine).await?;
+        ensure!(
+            present.offset(stream) == last,
+            "present offset of {} is {} after purging",
+            stream,
+            present.offset(stream)
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
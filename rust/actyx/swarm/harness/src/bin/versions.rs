@@ -1,14 +1,9 @@
 #[cfg(target_os = "linux")]
 mod versions {
     use anyhow::Context;
-    use async_std::task::block_on;
-    use ax_sdk::types::{
-        service::{EventMeta, EventResponse, QueryResponse},
-        StreamId, TagSet,
-    };
+    use ax_sdk::types::{service::EventMeta, Payload, StreamId, TagSet};
     use escargot::CargoBuild;
     use flate2::read::GzDecoder;
-    use futures::{future::ready, StreamExt};
     use netsim_embed::{Ipv4Range, MachineId, Netsim};
     use std::{
         collections::{HashMap, HashSet},
@@ -164,13 +159,9 @@ mod versions {
         for i in machine_ids {
             let r = api
                 .run(*i, |api| async move {
-                    api.execute(|ax| {
-                        let tags = TagSet::from_iter([tag.parse().expect("A valid tag")]);
-                        let event = serde_json::json!("1");
-                        block_on(ax.publish().event(tags, &event).unwrap())
-                    })
-                    .await
-                    .unwrap()
+                    let tags = TagSet::from_iter([tag.parse().expect("A valid tag")]);
+                    let event = Payload::compact(&serde_json::json!("1"))?;
+                    Ok(api.publish(vec![(tags, event)]).await?)
                 })
                 .await?;
             tracing::info!("{} published: {:?}", i, r);
@@ -180,24 +171,17 @@ mod versions {
             let _: anyhow::Result<()> = async_std::future::timeout(timeout, async {
                 loop {
                     let alive_machines = api
-                        .run(*i, |api| async move {
-                            api.execute(move |ax| block_on(ax.query(format!("FROM '{}'", tag))))
-                                .await
-                                .unwrap()
-                        })
+                        .run(*i, |api| async move { Ok(api.query(format!("FROM '{}'", tag)).await?) })
                         .await?
+                        .into_iter()
                         .filter_map(|r| {
                             tracing::info!("{} query response {:?}", i, r);
-                            match r {
-                                QueryResponse::Event(EventResponse {
-                                    meta: EventMeta::Event { key, .. },
-                                    ..
-                                }) => ready(streams.get(&key.stream)),
-                                _ => ready(None),
+                            match r.meta {
+                                EventMeta::Event { key, .. } => streams.get(&key.stream).copied(),
+                                _ => None,
                             }
                         })
-                        .collect::<HashSet<MachineId>>()
-                        .await;
+                        .collect::<HashSet<MachineId>>();
                     if alive_machines == all {
                         break;
                     }
@@ -4,25 +4,25 @@ mod pinned_resource;
 
 pub mod api;
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use async_std::{future, task};
 use ax_core::swarm::{EphemeralEventsConfig, EventRoute};
-use ax_sdk::types::NodeId;
+use ax_sdk::types::{LamportTimestamp, NodeId, Offset, OffsetMap, OffsetOrMin, Payload, StreamId, TagSet};
 use futures::{
     future::{select, BoxFuture, Either, Future},
     FutureExt,
 };
-use netsim_embed::{DelayBuffer, Ipv4Range, Machine, Netsim};
+use netsim_embed::{DelayBuffer, Ipv4Range, Machine, MachineId, Netsim};
 use std::{
     borrow::Borrow,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
     net::SocketAddr,
     str::FromStr,
     time::{Duration, Instant},
 };
 use structopt::StructOpt;
-use swarm_cli::{multiaddr, Command, Config, Event, Multiaddr, PeerId};
+use swarm_cli::{multiaddr, Cid, Command, Config, DecisionRecord, Event, Multiaddr, OffsetsComparison, PeerId};
 use tempdir::TempDir;
 
 pub mod util;
@@ -67,6 +67,28 @@ pub struct HarnessOpts {
 
     #[structopt(long)]
     pub event_routes: Vec<EventRoute>,
+
+    #[structopt(long)]
+    pub root_map_interval_ms: Option<u64>,
+
+    #[structopt(long)]
+    pub root_map_max_interval_ms: Option<u64>,
+
+    #[structopt(long)]
+    pub max_connections: Option<u32>,
+
+    #[structopt(long)]
+    pub tombstone_retention_ms: Option<u64>,
+
+    #[structopt(long)]
+    pub block_gc_interval_ms: Option<u64>,
+
+    #[structopt(long)]
+    pub gc_grace_period_ms: Option<u64>,
+
+    /// Write a decision log for every node next to its database
+    #[structopt(long)]
+    pub decision_log: bool,
 }
 
 pub trait MachineExt {
@@ -133,11 +155,9 @@ where
         for i in 0..opts.n_nodes {
             let cfg = Config {
                 path: Some(temp_dir.path().join(i.to_string())),
-                node_name: None,
                 keypair: i as _,
                 listen_on: vec!["/ip4/0.0.0.0/tcp/30000".parse().unwrap()],
                 bootstrap: bootstrap.clone(),
-                external: vec![],
                 enable_mdns: opts.enable_mdns,
                 enable_fast_path: opts.enable_fast_path,
                 enable_slow_path: opts.enable_slow_path,
@@ -148,6 +168,16 @@ where
                 ephemeral_events: opts.ephemeral_events.clone(),
                 max_leaf_count: opts.max_leaf_count,
                 event_routes: opts.event_routes.clone(),
+                root_map_interval_ms: opts.root_map_interval_ms,
+                root_map_max_interval_ms: opts.root_map_max_interval_ms,
+                max_connections: opts.max_connections,
+                tombstone_retention_ms: opts.tombstone_retention_ms,
+                block_gc_interval_ms: opts.block_gc_interval_ms,
+                gc_grace_period_ms: opts.gc_grace_period_ms,
+                decision_log_path: opts
+                    .decision_log
+                    .then(|| temp_dir.path().join(format!("{}.decisions", i))),
+                ..Default::default()
             };
             let mut delay = DelayBuffer::new();
             delay.set_delay(Duration::from_millis(opts.delay_ms));
@@ -281,6 +311,127 @@ async fn select_multi_internal<T>(machine: &mut Machine<Command, Event>, things:
     res.into_iter().map(|x| x.unwrap()).collect()
 }
 
+/// The present offsets and the replication target of the machine's store
+pub async fn fetch_offsets<E>(machine: &mut Machine<Command, E>) -> Result<(OffsetMap, OffsetMap)>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    machine.send(Command::Offsets);
+    machine
+        .select(|ev| m!(ev.borrow(), Event::Offsets { present, target } => (present.clone(), target.clone())))
+        .await
+        .ok_or_else(|| anyhow!("machine died"))
+}
+
+/// Poll `check` on the machine every 500ms until it yields a value, fails after `timeout`
+pub async fn wait_until<E, T, F>(
+    machine: &mut Machine<Command, E>,
+    timeout: Duration,
+    what: impl Display,
+    mut check: F,
+) -> Result<T>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+    F: for<'a> FnMut(&'a mut Machine<Command, E>) -> BoxFuture<'a, Result<Option<T>>>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(value) = check(machine).await? {
+            return Ok(value);
+        }
+        ensure!(Instant::now() < deadline, "{} did not {}", machine.id(), what);
+        task::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Wait until the present offset of `stream` on the machine has reached `offset`
+pub async fn wait_for_offset<E>(
+    machine: &mut Machine<Command, E>,
+    stream: StreamId,
+    offset: OffsetOrMin,
+    timeout: Duration,
+) -> Result<()>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    let what = format!("reach {} on {}", offset, stream);
+    wait_until(machine, timeout, what, move |machine| {
+        async move {
+            let (present, _) = fetch_offsets(machine).await?;
+            Ok((present.offset(stream) >= offset).then_some(()))
+        }
+        .boxed()
+    })
+    .await
+}
+
+/// Append `n` events with the payloads `0` to `n - 1` on the machine
+pub fn append<E>(sim: &mut Netsim<Command, E>, machine: MachineId, tags: TagSet, n: usize)
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    sim.machine(machine).send(Command::Append(
+        (0..n)
+            .map(|i| (tags.clone(), Payload::from_json_str(&i.to_string()).unwrap()))
+            .collect(),
+    ));
+}
+
+/// All streams known to the machine's store
+pub async fn fetch_streams<E>(machine: &mut Machine<Command, E>) -> Result<BTreeSet<StreamId>>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    machine.send(Command::Streams);
+    machine
+        .select(|ev| m!(ev.borrow(), Event::Streams(streams) => streams.clone()))
+        .await
+        .ok_or_else(|| anyhow!("machine died"))
+}
+
+/// The last `n` records of the decision log of the machine's store
+pub async fn fetch_decision_log<E>(machine: &mut Machine<Command, E>, n: usize) -> Result<Vec<DecisionRecord>>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    machine.send(Command::DecisionLog(n));
+    machine
+        .select(|ev| m!(ev.borrow(), Event::DecisionLog(records) => records.clone()))
+        .await
+        .ok_or_else(|| anyhow!("machine died"))
+}
+
+/// Compares the offsets of the machine's store with those of the connected `peer`
+pub async fn compare_offsets<E>(machine: &mut Machine<Command, E>, peer: PeerId) -> Result<OffsetsComparison>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    machine.send(Command::CompareOffsets(peer));
+    let result = machine
+        .select(|ev| match ev.borrow() {
+            Event::OffsetsCompared(comparison) => Some(Ok((**comparison).clone())),
+            Event::CompareOffsetsFailed(reason) => Some(Err(reason.clone())),
+            _ => None,
+        })
+        .await
+        .ok_or_else(|| anyhow!("machine died"))?;
+    result.map_err(|reason| anyhow!("comparing offsets with {} failed: {}", peer, reason))
+}
+
+/// The root map of the machine's store
+pub async fn fetch_root_map<E>(
+    machine: &mut Machine<Command, E>,
+) -> Result<BTreeMap<StreamId, (Cid, Offset, LamportTimestamp)>>
+where
+    E: Borrow<Event> + FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    machine.send(Command::RootMap);
+    machine
+        .select(|ev| m!(ev.borrow(), Event::RootMap(root_map) => root_map.clone()))
+        .await
+        .ok_or_else(|| anyhow!("machine died"))
+}
+
 pub async fn fully_mesh(sim: &mut Netsim<Command, Event>, timeout: Duration) -> Result<()> {
     for i in 0..sim.machines().len() {
         let machine = &mut sim.machines_mut()[i];
@@ -360,3 +511,61 @@ where
 
     Ok(())
 }
+
+/// Split the machines into groups that can only reach each other within the group.
+///
+/// netsim_embed cannot filter traffic within a network, so each group is moved into a network of
+/// its own, which changes the addresses of its machines; the new addresses are handed out within
+/// the group with [`Command::AddAddress`]. Machines not listed in any group stay where they are.
+pub async fn partition<E>(sim: &mut Netsim<Command, E>, groups: Vec<Vec<MachineId>>)
+where
+    E: FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    for group in groups {
+        let net = sim.spawn_network(Ipv4Range::random_local_subnet());
+        for machine in &group {
+            sim.plug(*machine, net, None).await;
+        }
+        tracing::warn!("partitioned {:?} into network {:?}", group, sim.network(net).range());
+        exchange_addresses(sim, &group);
+    }
+}
+
+/// Undo all partitions by moving every machine into one new network.
+///
+/// All machines learn each other's new addresses with [`Command::AddAddress`], connections are
+/// then reestablished by the nodes themselves.
+pub async fn heal<E>(sim: &mut Netsim<Command, E>)
+where
+    E: FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    let net = sim.spawn_network(Ipv4Range::random_local_subnet());
+    let machines = sim.machines().iter().map(|m| m.id()).collect::<Vec<_>>();
+    for machine in &machines {
+        sim.plug(*machine, net, None).await;
+    }
+    tracing::warn!("healed all partitions into network {:?}", sim.network(net).range());
+    exchange_addresses(sim, &machines);
+}
+
+fn exchange_addresses<E>(sim: &mut Netsim<Command, E>, machines: &[MachineId])
+where
+    E: FromStr<Err = anyhow::Error> + Display + Send + 'static,
+{
+    let addrs = machines
+        .iter()
+        .map(|id| {
+            let machine = sim.machine(*id);
+            (machine.peer_id(), machine.multiaddr())
+        })
+        .collect::<Vec<_>>();
+    for id in machines {
+        let machine = sim.machine(*id);
+        let own = machine.peer_id();
+        for (peer, addr) in &addrs {
+            if *peer != own {
+                machine.send(Command::AddAddress(*peer, addr.clone()));
+            }
+        }
+    }
+}
@@ -2,9 +2,9 @@ use crate::files::FilesGetResponse;
 use anyhow::Result;
 use ax_types::{
     service::{
-        AuthenticationResponse, OffsetsResponse, Order, PublishEvent, PublishRequest, PublishResponse, QueryRequest,
-        QueryResponse, SessionId, SubscribeMonotonicRequest, SubscribeMonotonicResponse, SubscribeRequest,
-        SubscribeResponse,
+        AuthenticationResponse, OffsetsResponse, Order, PublishEvent, PublishRequest, PublishResponse,
+        QueryProgressRequest, QueryRequest, QueryResponse, SessionId, SubscribeMonotonicRequest,
+        SubscribeMonotonicResponse, SubscribeRequest, SubscribeResponse,
     },
     AppManifest, NodeId, OffsetMap, Payload, TagSet,
 };
@@ -602,6 +602,10 @@ impl<'a> Query<'a> {
                 lower_bound: Some(OffsetMap::empty()),
                 upper_bound: None,
                 order: Order::Asc,
+                progress: None,
+                inline_blobs: None,
+                checksum: None,
+                timeout_millis: None,
             },
         }
     }
@@ -887,6 +891,106 @@ impl<'a> Query<'a> {
         }
         panic!("Calling Query::with_order after polling.")
     }
+
+    /// Request progress messages to be interleaved into the query results.
+    ///
+    /// The node will send [`QueryResponse::Progress`] messages according to the given
+    /// [`QueryProgressRequest`], the last one of which is sent when all events up to the
+    /// upper bound have been delivered.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Query`] will result in a panic.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use ax_sdk::{Ax, AxOpts, types::service::{QueryProgressRequest, QueryResponse}};
+    /// use futures::stream::StreamExt;
+    /// async fn progress_example() {
+    ///     let service = Ax::new(AxOpts::default()).await.unwrap();
+    ///     let mut response = service.query("FROM allEvents")
+    ///         .with_progress(QueryProgressRequest::default())
+    ///         .await
+    ///         .unwrap();
+    ///     while let Some(response) = response.next().await {
+    ///         if let QueryResponse::Progress(progress) = response {
+    ///             println!("{:.1}%", progress.fraction() * 100.0);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn with_progress(mut self, progress: QueryProgressRequest) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.progress = Some(progress);
+            return self;
+        }
+        panic!("Calling Query::with_progress after polling.")
+    }
+
+    /// Deliver payloads that the node stored as blobs instead of their references.
+    pub fn with_inline_blobs(mut self) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.inline_blobs = Some(true);
+            return self;
+        }
+        panic!("Calling Query::with_inline_blobs after polling.")
+    }
+
+    /// Request a checksum over the delivered events.
+    ///
+    /// The node will send a [`QueryResponse::Checksum`] before the final offsets, which can be
+    /// compared against the checksum computed over the received events.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Query`] will result in a panic.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use ax_sdk::{Ax, AxOpts, types::service::{QueryChecksumBuilder, QueryResponse}};
+    /// use futures::stream::StreamExt;
+    /// async fn checksum_example() {
+    ///     let service = Ax::new(AxOpts::default()).await.unwrap();
+    ///     let mut response = service.query("FROM allEvents")
+    ///         .with_checksum()
+    ///         .await
+    ///         .unwrap();
+    ///     let mut checksum = QueryChecksumBuilder::new();
+    ///     while let Some(response) = response.next().await {
+    ///         match response {
+    ///             QueryResponse::Event(event) => checksum.add(&event),
+    ///             QueryResponse::Checksum(expected) => assert_eq!(expected, checksum.checksum()),
+    ///             _ => {}
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn with_checksum(mut self) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.checksum = Some(true);
+            return self;
+        }
+        panic!("Calling Query::with_checksum after polling.")
+    }
+
+    /// Limit the execution time of the query on the node.
+    ///
+    /// This replaces the node’s default timeout, up to the maximum configured on the node. When the
+    /// timeout is exceeded, the response ends with a [`QueryResponse::Timeout`] carrying the bounds
+    /// for resuming the query.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Query`] will result in a panic.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.timeout_millis = Some(timeout.as_millis().try_into().unwrap_or(u64::MAX));
+            return self;
+        }
+        panic!("Calling Query::with_timeout after polling.")
+    }
 }
 
 impl<'a> Future for Query<'a> {
@@ -7,7 +7,7 @@
 mod client;
 pub mod files;
 
-pub use client::{Ax, AxOpts, Publish, Query, Subscribe, SubscribeMonotonic};
+pub use client::{Ax, AxError, AxOpts, Publish, Query, Subscribe, SubscribeMonotonic};
 
 pub use ax_aql as aql;
 pub use ax_types as types;